@@ -0,0 +1,86 @@
+//! Decoding throughput of `parse` and `parse_unchecked`.
+//!
+//! Run with `cargo bench --bench decode`.
+
+use armv6_m_instruction_parser::{
+    disassemble, instructons::Instruction, parse, parse_unchecked, Error,
+};
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+
+/// Typical compiler output, only 16 bit instructions.
+const THUMB16: [u8; 32] = [
+    0x80, 0xb5, // push {r7, lr}
+    0x00, 0xaf, // add r7, sp, #0
+    0x01, 0x20, // movs r0, #1
+    0x0a, 0x68, // ldr r2, [r1, #0]
+    0x52, 0x18, // adds r2, r2, r1
+    0x93, 0x42, // cmp r3, r2
+    0xfa, 0xd1, // bne.n
+    0x0b, 0x60, // str r3, [r1, #0]
+    0x5b, 0x00, // lsls r3, r3, #1
+    0x18, 0x43, // orrs r0, r3
+    0x01, 0x4b, // ldr r3, [pc, #4]
+    0x98, 0x47, // blx r3
+    0x40, 0x1c, // adds r0, r0, #1
+    0x00, 0xbf, // nop
+    0xbd, 0x46, // mov sp, r7
+    0x80, 0xbd, // pop {r7, pc}
+];
+
+/// 16 bit instructions mixed with `bl` and system instructions.
+const MIXED: [u8; 32] = [
+    0x80, 0xb5, // push {r7, lr}
+    0x00, 0xf0, 0x7e, 0xf8, // bl
+    0xef, 0xf3, 0x10, 0x80, // mrs r0, PRIMASK
+    0x72, 0xb6, // cpsid i
+    0x01, 0x20, // movs r0, #1
+    0xbf, 0xf3, 0x5f, 0x8f, // dmb sy
+    0x0a, 0x68, // ldr r2, [r1, #0]
+    0xff, 0xf7, 0xf4, 0xff, // bl
+    0x80, 0xf3, 0x10, 0x88, // msr PRIMASK, r0
+    0x62, 0xb6, // cpsie i
+    0x80, 0xbd, // pop {r7, pc}
+];
+
+/// Repeats a sequence of instructions into an image of about 64 KiB.
+fn image(instructions: &[u8]) -> Vec<u8> {
+    instructions.repeat(64 * 1024 / instructions.len())
+}
+
+/// Decodes every instruction of `image` with `decode`, returning the number decoded.
+fn decode_all(image: &[u8], decode: fn(&[u8]) -> Result<Instruction, Error>) -> usize {
+    let mut offset = 0;
+    let mut decoded = 0;
+    while offset < image.len() {
+        let size = match (image[offset + 1] >> 3) & 0x1f {
+            0b11101..=0b11111 => 4,
+            _ => 2,
+        };
+        decoded += decode(black_box(&image[offset..offset + size])).is_ok() as usize;
+        offset += size;
+    }
+    decoded
+}
+
+fn decode(c: &mut Criterion) {
+    for (name, instructions) in [("thumb16", &THUMB16), ("mixed", &MIXED)] {
+        let image = image(instructions);
+        let mut group = c.benchmark_group(name);
+        group.throughput(Throughput::Bytes(image.len() as u64));
+        group.bench_function("parse", |b| b.iter(|| decode_all(&image, parse)));
+        group.bench_function("parse_unchecked", |b| {
+            b.iter(|| decode_all(&image, parse_unchecked))
+        });
+        group.bench_function("disassemble", |b| {
+            b.iter(|| {
+                disassemble(black_box(&image))
+                    .filter(|(_, r)| r.is_ok())
+                    .count()
+            })
+        });
+        group.finish();
+    }
+}
+
+criterion_group!(benches, decode);
+criterion_main!(benches);
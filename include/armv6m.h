@@ -0,0 +1,211 @@
+#ifndef ARMV6M_H
+#define ARMV6M_H
+
+/* Generated by cbindgen from src/ffi.rs, do not edit. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+#define ARMV6M_NO_REGISTER 0xff
+
+/**
+ * Result of the functions of this module, mirroring [`Error`].
+ */
+typedef enum Armv6mStatus {
+  ARMV6M_STATUS_OK = 0,
+  /**
+   * A pointer argument is null.
+   */
+  ARMV6M_STATUS_NULL_POINTER,
+  ARMV6M_STATUS_INSUFFICIENT_INPUT,
+  ARMV6M_STATUS_MALFORMED32_BIT_INSTRUCTION,
+  ARMV6M_STATUS_INVALID32_BIT_INSTRUCTION,
+  ARMV6M_STATUS_INVALID_OP_CODE,
+  ARMV6M_STATUS_UNPREDICTABLE,
+  ARMV6M_STATUS_INVALID_REGISTER,
+  ARMV6M_STATUS_INVALID_CONDITION,
+  /**
+   * Any other [`Error`], which decoding does not return.
+   */
+  ARMV6M_STATUS_OTHER,
+} Armv6mStatus;
+
+/**
+ * Kind of an [`Armv6mInstruction`], the variants of [`Operation`].
+ */
+typedef enum Armv6mOperation {
+  ARMV6M_OPERATION_ADC_REG,
+  ARMV6M_OPERATION_ADD_IMM,
+  ARMV6M_OPERATION_ADD_REG,
+  ARMV6M_OPERATION_ADD_IMM_SP,
+  ARMV6M_OPERATION_ADD_REG_SP,
+  ARMV6M_OPERATION_ADR,
+  ARMV6M_OPERATION_AND_REG,
+  ARMV6M_OPERATION_ASR_IMM,
+  ARMV6M_OPERATION_ASR_REG,
+  ARMV6M_OPERATION_B,
+  ARMV6M_OPERATION_BIC_REG,
+  ARMV6M_OPERATION_BKPT,
+  ARMV6M_OPERATION_BL,
+  ARMV6M_OPERATION_BLX_REG,
+  ARMV6M_OPERATION_BX,
+  ARMV6M_OPERATION_CMN_REG,
+  ARMV6M_OPERATION_CMP_IMM,
+  ARMV6M_OPERATION_CMP_REG,
+  ARMV6M_OPERATION_CPS,
+  ARMV6M_OPERATION_CPY,
+  ARMV6M_OPERATION_DMB,
+  ARMV6M_OPERATION_DSB,
+  ARMV6M_OPERATION_EOR_REG,
+  ARMV6M_OPERATION_ISB,
+  ARMV6M_OPERATION_LDM,
+  ARMV6M_OPERATION_LDR_IMM,
+  ARMV6M_OPERATION_LDR_LITERAL,
+  ARMV6M_OPERATION_LDR_REG,
+  ARMV6M_OPERATION_LDRB_IMM,
+  ARMV6M_OPERATION_LDRB_REG,
+  ARMV6M_OPERATION_LDRH_IMM,
+  ARMV6M_OPERATION_LDRH_REG,
+  ARMV6M_OPERATION_LDRSB_REG,
+  ARMV6M_OPERATION_LDRSH,
+  ARMV6M_OPERATION_LSL_IMM,
+  ARMV6M_OPERATION_LSL_REG,
+  ARMV6M_OPERATION_LSR_IMM,
+  ARMV6M_OPERATION_LSR_REG,
+  ARMV6M_OPERATION_MOV_IMM,
+  ARMV6M_OPERATION_MOV_REG,
+  ARMV6M_OPERATION_MRS,
+  ARMV6M_OPERATION_MSR_REG,
+  ARMV6M_OPERATION_MUL,
+  ARMV6M_OPERATION_MVN_REG,
+  ARMV6M_OPERATION_NOP,
+  ARMV6M_OPERATION_ORR_REG,
+  ARMV6M_OPERATION_POP,
+  ARMV6M_OPERATION_PUSH,
+  ARMV6M_OPERATION_REV,
+  ARMV6M_OPERATION_REV16,
+  ARMV6M_OPERATION_REVSH,
+  ARMV6M_OPERATION_ROR_REG,
+  ARMV6M_OPERATION_RSB_IMM,
+  ARMV6M_OPERATION_SBC_REG,
+  ARMV6M_OPERATION_SEV,
+  ARMV6M_OPERATION_STM,
+  ARMV6M_OPERATION_STR_IMM,
+  ARMV6M_OPERATION_STR_REG,
+  ARMV6M_OPERATION_STRB_IMM,
+  ARMV6M_OPERATION_STRB_REG,
+  ARMV6M_OPERATION_STRH_IMM,
+  ARMV6M_OPERATION_STRH_REG,
+  ARMV6M_OPERATION_SUB_IMM,
+  ARMV6M_OPERATION_SUB_REG,
+  ARMV6M_OPERATION_SUB_IMM_SP,
+  ARMV6M_OPERATION_SVC,
+  ARMV6M_OPERATION_SXTB,
+  ARMV6M_OPERATION_SXTH,
+  ARMV6M_OPERATION_TST_REG,
+  ARMV6M_OPERATION_UDF,
+  ARMV6M_OPERATION_UXTB,
+  ARMV6M_OPERATION_UXTH,
+  ARMV6M_OPERATION_WFE,
+  ARMV6M_OPERATION_WFI,
+  ARMV6M_OPERATION_YIELD,
+} Armv6mOperation;
+
+/**
+ * Decoded instruction, [`DecodedAt`] with the operands of its [`Operation`] flattened.
+ *
+ * Registers are numbered 0 to 15, with [`ARMV6M_NO_REGISTER`] for those the operation does
+ * not have. Operands named `dn` and `dm` fill both fields.
+ */
+typedef struct Armv6mInstruction {
+  uint32_t address;
+  /**
+   * Size in bytes, 2 or 4.
+   */
+  uint8_t size;
+  /**
+   * Bytes of the instruction, the last two unused for 16 bit instructions.
+   */
+  uint8_t bytes[4];
+  enum Armv6mOperation operation;
+  uint8_t d;
+  uint8_t n;
+  uint8_t m;
+  uint8_t t;
+  /**
+   * Immediate, the option of barriers and 1 for `cpsid`. Shift amounts are 1 to 32,
+   * `lsr` and `asr` by 32 not encoded as 0.
+   */
+  uint32_t imm;
+  /**
+   * Bit n set for register n, for `push`, `pop`, `ldm` and `stm`.
+   */
+  uint16_t reg_list;
+  /**
+   * Condition of `b`, 0 (`eq`) to 13 (`le`) or 14 for none.
+   */
+  uint8_t cond;
+  /**
+   * Special register of `mrs` and `msr`, as encoded in SYSm.
+   */
+  uint8_t sysm;
+  /**
+   * Whether `mov` sets the condition flags.
+   */
+  bool set_flags;
+  /**
+   * Registers read, see [`Operation::registers_read`].
+   */
+  uint16_t registers_read;
+  /**
+   * Registers written, see [`Operation::registers_written`].
+   */
+  uint16_t registers_written;
+} Armv6mInstruction;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Decodes the instruction at the start of the `len` bytes at `bytes`, placed at `address`,
+ * into `out`, which is left untouched unless [`Armv6mStatus::Ok`] is returned.
+ *
+ * # Safety
+ * `bytes` must point to `len` readable bytes and `out` to a writable
+ * [`Armv6mInstruction`].
+ */
+enum Armv6mStatus armv6m_decode(const uint8_t *bytes,
+                                size_t len,
+                                uint32_t address,
+                                struct Armv6mInstruction *out);
+
+/**
+ * Writes the assembly text of `instruction`, with branch targets as addresses, to the
+ * `size` bytes at `buffer` like `snprintf`: truncated to `size - 1` bytes and terminated
+ * by a null byte if `size` is not 0.
+ *
+ * Returns the length of the whole text without the null byte, or 0 if `instruction` is
+ * null or was not returned by [`armv6m_decode`]. Only the address, size, bytes and
+ * operation are read, the operation as an integer checked against the bytes.
+ *
+ * # Safety
+ * `instruction` must point to an [`Armv6mInstruction`] and `buffer` to `size` writable
+ * bytes.
+ */
+size_t armv6m_format(const struct Armv6mInstruction *instruction, char *buffer, size_t size);
+
+/**
+ * Returns a static, null terminated description of the [`Armv6mStatus`] `status`, taken as
+ * an integer since C can pass any.
+ */
+const char *armv6m_status_message(int status);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* ARMV6M_H */
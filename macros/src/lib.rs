@@ -0,0 +1,117 @@
+//! Compile time assembly of ARMv6-M thumb instructions, for test fixtures and small stubs.
+//!
+//! The source is assembled by the assembler of `armv6-m-instruction-parser`, so the same
+//! syntax and directives are accepted.
+//!
+//! The macro is not re-exported by `armv6-m-instruction-parser`, which this crate depends on,
+//! so depend on both, usually on this one as a dev-dependency:
+//! ```toml
+//! [dev-dependencies]
+//! armv6-m-instruction-parser-macros = "0.3.0-rc1"
+//! ```
+
+use armv6_m_instruction_parser::assembler::assemble;
+use proc_macro::{Delimiter, Group, Literal, Punct, Spacing, Span, TokenStream, TokenTree};
+
+/// Assembles a string literal into a `[u8; N]` array at compile time.
+///
+/// Errors in the source and references to labels not defined in it are compile errors.
+///
+/// # Example
+/// ```
+/// use armv6_m_instruction_parser_macros::thumb;
+///
+/// const CODE: [u8; 6] = thumb!("push {r4, lr}; movs r0, #1; pop {r4, pc}");
+/// assert_eq!(CODE, [0x10, 0xb5, 0x01, 0x20, 0x10, 0xbd]);
+/// ```
+///
+/// ```compile_fail
+/// use armv6_m_instruction_parser_macros::thumb;
+///
+/// let code = thumb!("bl elsewhere");
+/// ```
+#[proc_macro]
+pub fn thumb(input: TokenStream) -> TokenStream {
+    let mut tokens = input.into_iter();
+    let (literal, source) = match (tokens.next(), tokens.next()) {
+        (Some(TokenTree::Literal(literal)), None) => match string_value(&literal.to_string()) {
+            Some(source) => (literal, source),
+            None => return compile_error("expected a string literal", literal.span()),
+        },
+        (Some(token), _) => return compile_error("expected a string literal", token.span()),
+        (None, _) => return compile_error("expected a string literal", Span::call_site()),
+    };
+
+    let assembly = match assemble(&source) {
+        Ok(assembly) => assembly,
+        Err(error) => return compile_error(&error.to_string(), literal.span()),
+    };
+    if let Some(relocation) = assembly.relocations.first() {
+        return compile_error(
+            &format!(
+                "line {}: undefined label `{}`",
+                relocation.line, relocation.symbol
+            ),
+            literal.span(),
+        );
+    }
+
+    let mut bytes = TokenStream::new();
+    for byte in assembly.bytes {
+        bytes.extend([
+            TokenTree::Literal(Literal::u8_suffixed(byte)),
+            TokenTree::Punct(Punct::new(',', Spacing::Alone)),
+        ]);
+    }
+    TokenTree::Group(Group::new(Delimiter::Bracket, bytes)).into()
+}
+
+/// Returns the value of a string literal token, or `None` if it is not a string literal.
+fn string_value(token: &str) -> Option<String> {
+    if let Some(raw) = token.strip_prefix('r') {
+        let hashes = raw.len() - raw.trim_start_matches('#').len();
+        let quoted = &raw[hashes..raw.len() - hashes];
+        return Some(quoted.strip_prefix('"')?.strip_suffix('"')?.to_string());
+    }
+
+    let quoted = token.strip_prefix('"')?.strip_suffix('"')?;
+    let mut value = String::with_capacity(quoted.len());
+    let mut chars = quoted.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            value.push(c);
+            continue;
+        }
+        match chars.next()? {
+            'n' => value.push('\n'),
+            't' => value.push('\t'),
+            'r' => value.push('\r'),
+            '0' => value.push('\0'),
+            c @ ('\\' | '"' | '\'') => value.push(c),
+            // Line continuation, skips the newline and leading whitespace.
+            '\n' => {
+                let rest = chars.as_str().trim_start();
+                chars = rest.chars();
+            }
+            _ => return None,
+        }
+    }
+    Some(value)
+}
+
+/// Returns a `compile_error!` invocation with `message` at `span`.
+fn compile_error(message: &str, span: Span) -> TokenStream {
+    let mut message = Literal::string(message);
+    message.set_span(span);
+    let mut arguments = Group::new(Delimiter::Parenthesis, TokenTree::Literal(message).into());
+    arguments.set_span(span);
+    let invocation: TokenStream = "::core::compile_error!".parse().unwrap();
+    invocation
+        .into_iter()
+        .chain([TokenTree::Group(arguments)])
+        .map(|mut token| {
+            token.set_span(span);
+            token
+        })
+        .collect()
+}
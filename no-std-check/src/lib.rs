@@ -0,0 +1,57 @@
+//! Uses the decoding, encoding and patching API without `std` and `alloc`, and the
+//! assembler with only `alloc`.
+
+#![no_std]
+
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
+use armv6_m_instruction_parser::{
+    encoder::encode,
+    instructons::{BranchTarget, Operation},
+    parse_at,
+    patcher::insert_bkpt,
+    registers::Register,
+    Error, ThumbInstructions,
+};
+
+/// Counts the instructions pushing `lr` in an image.
+pub fn count_pushes_of_lr(image: &[u8]) -> usize {
+    image
+        .thumb_instructions()
+        .filter(|(_, instruction)| {
+            matches!(
+                instruction,
+                Ok(instruction) if matches!(
+                    instruction.operation,
+                    Operation::PUSH { reg_list } if reg_list.contains(Register::LR)
+                )
+            )
+        })
+        .count()
+}
+
+/// Returns the destination of the branch at `address`.
+pub fn branch_target(code: &[u8], address: u32) -> Result<BranchTarget, Error> {
+    Ok(parse_at(code, address)?.branch_target())
+}
+
+/// Replaces the instruction at `offset` with a breakpoint and restores it.
+pub fn toggle_breakpoint(code: &mut [u8], offset: usize) -> Result<(), Error> {
+    insert_bkpt(code, offset)?.restore(code)
+}
+
+/// Encodes a `nop`.
+pub fn nop() -> [u8; 2] {
+    let encoding = encode(&Operation::NOP).unwrap();
+    [encoding.as_bytes()[0], encoding.as_bytes()[1]]
+}
+
+/// Assembles a function returning `value`.
+#[cfg(feature = "alloc")]
+pub fn return_constant(value: u32) -> alloc::vec::Vec<u8> {
+    let source = alloc::format!("ldr r0, ={value}\nbx lr");
+    armv6_m_instruction_parser::assembler::assemble(&source)
+        .unwrap()
+        .bytes
+}
@@ -0,0 +1,159 @@
+//! Prediction of unaligned memory accesses, which always fault on ARMv6-M.
+//!
+//! # Example
+//! ```
+//! # use armv6_m_instruction_parser::{alignment::{check_alignment, Alignment, KnownBits}, instructons::Operation, registers::Register};
+//! // ldr r0, [r1, #4] with r1 a byte buffer pointer aligned to 2
+//! let ldr = Operation::LDRImm { imm: 4, n: Register::R1, t: Register::R0 };
+//! let base = KnownBits::aligned(2);
+//! assert_eq!(check_alignment(&ldr, base, KnownBits::UNKNOWN), Some(Alignment::MayBeUnaligned));
+//! assert_eq!(check_alignment(&ldr, KnownBits::exact(0x2000_0006), KnownBits::UNKNOWN), Some(Alignment::Unaligned));
+//! ```
+
+use crate::{
+    instructons::{AccessOffset, AccessSize, Operation},
+    registers::Register,
+};
+
+/// Value of a register with only some of its bits known.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct KnownBits {
+    /// Bits that are known.
+    pub mask: u32,
+    /// Values of the known bits, the others are 0.
+    pub value: u32,
+}
+
+impl KnownBits {
+    /// Nothing is known about the value.
+    pub const UNKNOWN: Self = Self { mask: 0, value: 0 };
+
+    /// The value is known.
+    pub const fn exact(value: u32) -> Self {
+        Self { mask: !0, value }
+    }
+
+    /// The value is a multiple of `bytes`, a power of two.
+    pub const fn aligned(bytes: u32) -> Self {
+        Self {
+            mask: bytes.wrapping_sub(1),
+            value: 0,
+        }
+    }
+}
+
+/// Known bits of the sum, the low bits known in both values.
+impl core::ops::Add for KnownBits {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        let known = self.mask.trailing_ones().min(other.mask.trailing_ones());
+        let mask = match known {
+            32 => !0,
+            known => (1 << known) - 1,
+        };
+        Self {
+            mask,
+            value: self.value.wrapping_add(other.value) & mask,
+        }
+    }
+}
+
+/// Alignment of a memory access found by [`check_alignment`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Alignment {
+    /// Every transfer is aligned to its size.
+    Aligned,
+    /// Some transfer is unaligned and faults.
+    Unaligned,
+    /// Not enough bits of the address are known.
+    MayBeUnaligned,
+}
+
+/// Returns the alignment of the memory access of `operation`, with `base` the value of its
+/// base register and `offset` the value of its offset register, if any. `None` if
+/// `operation` does not access memory.
+///
+/// Literal loads and accesses relative to `sp` are always aligned, as the low bits of `pc`
+/// and `sp` are ignored for them. Byte accesses can never be unaligned.
+pub fn check_alignment(
+    operation: &Operation,
+    base: KnownBits,
+    offset: KnownBits,
+) -> Option<Alignment> {
+    let access = operation.memory_access()?;
+    let base = match access.base {
+        Register::PC | Register::SP => KnownBits::aligned(4),
+        _ => base,
+    };
+    let address = match access.offset {
+        AccessOffset::Immediate(imm) => base + KnownBits::exact(imm),
+        AccessOffset::Register(_) => base + offset,
+        // Every register is a word from the base.
+        AccessOffset::IncrementAfter(_) | AccessOffset::DecrementBefore(_) => base,
+    };
+    let low = match access.size {
+        AccessSize::Byte => return Some(Alignment::Aligned),
+        size => size.bytes() - 1,
+    };
+    // A known bit set is enough, the unknown bits are 0 in `value`.
+    Some(if address.value & low != 0 {
+        Alignment::Unaligned
+    } else if address.mask & low == low {
+        Alignment::Aligned
+    } else {
+        Alignment::MayBeUnaligned
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::registers::RegisterList;
+
+    #[test]
+    fn accesses() {
+        let strh = Operation::STRHReg {
+            m: Register::R2,
+            n: Register::R1,
+            t: Register::R0,
+        };
+        let base = KnownBits::aligned(4);
+        assert_eq!(
+            check_alignment(&strh, base, KnownBits::exact(2)),
+            Some(Alignment::Aligned)
+        );
+        assert_eq!(
+            check_alignment(&strh, base, KnownBits::exact(3)),
+            Some(Alignment::Unaligned)
+        );
+        assert_eq!(
+            check_alignment(&strh, base, KnownBits::UNKNOWN),
+            Some(Alignment::MayBeUnaligned)
+        );
+        // An odd bit known below unknown ones is unaligned whatever the rest.
+        assert_eq!(
+            check_alignment(&strh, KnownBits { mask: 1, value: 1 }, KnownBits::exact(0)),
+            Some(Alignment::Unaligned)
+        );
+        let ldm = Operation::LDM {
+            n: Register::R0,
+            reg_list: RegisterList::from([Register::R1, Register::R2]),
+        };
+        assert_eq!(
+            check_alignment(&ldm, KnownBits::exact(0x2000_0002), KnownBits::UNKNOWN),
+            Some(Alignment::Unaligned)
+        );
+        let push = Operation::PUSH {
+            reg_list: RegisterList::from([Register::LR]),
+        };
+        assert_eq!(
+            check_alignment(&push, KnownBits::UNKNOWN, KnownBits::UNKNOWN),
+            Some(Alignment::Aligned)
+        );
+        assert_eq!(
+            check_alignment(&Operation::NOP, KnownBits::UNKNOWN, KnownBits::UNKNOWN),
+            None
+        );
+    }
+}
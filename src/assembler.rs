@@ -0,0 +1,1689 @@
+//! Parsing of ARMv6-M unified assembler language (UAL) into operations.
+//!
+//! # Example
+//! ```
+//! # use armv6_m_instruction_parser::{instructons::Operation, registers::Register};
+//! let operation: Operation = "adds r0, r1, #4".parse().unwrap();
+//! assert_eq!(
+//!     operation,
+//!     Operation::ADDImm {
+//!         imm: 4,
+//!         n: Register::R1,
+//!         d: Register::R0
+//!     }
+//! );
+//! ```
+//!
+//! Whole programs with labels are assembled with [`assemble`]:
+//! ```
+//! # use armv6_m_instruction_parser::assembler::assemble;
+//! let assembly = assemble(
+//!     "    movs r0, #3
+//!      loop:
+//!          subs r0, #1
+//!          bne loop
+//!          bl hook
+//!          bx lr",
+//! )
+//! .unwrap();
+//! assert_eq!(assembly.relocations[0].symbol, "hook");
+//! let image = assembly.relocate(0x2000_0000, |_| Some(0x2000_0100)).unwrap();
+//! assert_eq!(image.len(), 12);
+//! ```
+//!
+//! The `s` suffix of data processing instructions is accepted but not required, as the
+//! [`Operation`] enum only records flag setting for `mov`. Immediates are checked for
+//! encodability when the operation is encoded, not when it is parsed.
+
+use alloc::{
+    boxed::Box,
+    collections::{BTreeMap, BTreeSet},
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+use core::str::FromStr;
+
+use crate::{
+    conditions::Condition,
+    encoder::encode,
+    instructons::Operation,
+    registers::{Register, RegisterList, SpecialRegister},
+    Error,
+};
+
+/// One operand of an assembly statement.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Operand {
+    /// A register, `!` marks writeback as in `ldm r0!, {r1}`.
+    Register { register: Register, writeback: bool },
+    /// An immediate value, with or without a leading `#`.
+    Immediate(i64),
+    /// A memory operand like `[r0, #4]` or `[r0, r1]`.
+    Memory { base: Register, offset: Offset },
+    /// A register list like `{r4-r7, lr}`.
+    RegisterList(RegisterList),
+    /// A bare identifier, e.g. a special register, a barrier option or a label.
+    Symbol(String),
+    /// A literal pool value as in `ldr r0, =0x1234`.
+    Literal(Box<Operand>),
+}
+
+/// Offset part of a memory operand.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Offset {
+    Immediate(i64),
+    Register(Register),
+}
+
+/// A split up assembly statement, i.e. a mnemonic and its operands.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Statement {
+    pub mnemonic: String,
+    pub operands: Vec<Operand>,
+    /// The mnemonic had a `.n` qualifier, i.e. branches must not be relaxed.
+    pub narrow: bool,
+}
+
+impl FromStr for Statement {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (mnemonic, rest) = match s.find(char::is_whitespace) {
+            Some(index) => (&s[..index], s[index..].trim()),
+            None => (s, ""),
+        };
+        if mnemonic.is_empty() {
+            return Err(Error::InvalidSyntax);
+        }
+        let mut mnemonic = mnemonic.to_ascii_lowercase();
+        // Width qualifiers are accepted, the narrowest encoding is always used.
+        let narrow = mnemonic.ends_with(".n");
+        if narrow || mnemonic.ends_with(".w") {
+            mnemonic.truncate(mnemonic.len() - 2);
+        }
+
+        let operands = split_operands(rest)?
+            .into_iter()
+            .map(parse_operand)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Statement {
+            mnemonic,
+            operands,
+            narrow,
+        })
+    }
+}
+
+/// Splits the operand part of a statement on commas not enclosed in brackets or braces.
+fn split_operands(s: &str) -> Result<Vec<&str>, Error> {
+    let mut operands = vec![];
+    if s.is_empty() {
+        return Ok(operands);
+    }
+    let mut depth = 0;
+    let mut start = 0;
+    for (index, c) in s.char_indices() {
+        match c {
+            '[' | '{' => depth += 1,
+            ']' | '}' => {
+                if depth == 0 {
+                    return Err(Error::InvalidSyntax);
+                }
+                depth -= 1;
+            }
+            ',' if depth == 0 => {
+                operands.push(s[start..index].trim());
+                start = index + 1;
+            }
+            _ => (),
+        }
+    }
+    if depth != 0 {
+        return Err(Error::InvalidSyntax);
+    }
+    operands.push(s[start..].trim());
+    if operands.iter().any(|operand| operand.is_empty()) {
+        return Err(Error::InvalidSyntax);
+    }
+    Ok(operands)
+}
+
+fn parse_operand(s: &str) -> Result<Operand, Error> {
+    if let Some(inner) = s.strip_prefix('[') {
+        let inner = inner.strip_suffix(']').ok_or(Error::InvalidSyntax)?;
+        let parts = split_operands(inner)?;
+        let base = parts
+            .first()
+            .ok_or(Error::InvalidSyntax)?
+            .parse::<Register>()?;
+        let offset = match parts.get(1..) {
+            Some([]) => Offset::Immediate(0),
+            Some([offset]) => match offset.parse::<Register>() {
+                Ok(register) => Offset::Register(register),
+                Err(_) => Offset::Immediate(parse_immediate(offset)?),
+            },
+            _ => return Err(Error::InvalidSyntax),
+        };
+        return Ok(Operand::Memory { base, offset });
+    }
+    if let Some(inner) = s.strip_prefix('{') {
+        let inner = inner.strip_suffix('}').ok_or(Error::InvalidSyntax)?;
+        return Ok(Operand::RegisterList(parse_register_list(inner)?));
+    }
+    if let Some(value) = s.strip_prefix('=') {
+        return Ok(Operand::Literal(Box::new(parse_operand(value.trim())?)));
+    }
+    if let Some(register) = s.strip_suffix('!') {
+        return Ok(Operand::Register {
+            register: register.trim().parse()?,
+            writeback: true,
+        });
+    }
+    let lower = s.to_ascii_lowercase();
+    if lower.starts_with('r') && lower[1..].chars().all(|c| c.is_ascii_digit())
+        || s.parse::<Register>().is_ok()
+    {
+        return Ok(Operand::Register {
+            register: s.parse()?,
+            writeback: false,
+        });
+    }
+    if s.starts_with(|c: char| c == '#' || c == '-' || c == '+' || c.is_ascii_digit()) {
+        return Ok(Operand::Immediate(parse_immediate(s)?));
+    }
+    if s.chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '$')
+    {
+        return Ok(Operand::Symbol(s.to_string()));
+    }
+    Err(Error::InvalidSyntax)
+}
+
+/// Parses a decimal, hexadecimal (`0x`) or binary (`0b`) immediate with an optional `#`.
+pub(crate) fn parse_immediate(s: &str) -> Result<i64, Error> {
+    let s = s.trim();
+    let s = s.strip_prefix('#').unwrap_or(s).trim();
+    let (negative, digits) = match s.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, s.strip_prefix('+').unwrap_or(s)),
+    };
+    let lower = digits.to_ascii_lowercase();
+    let value = if let Some(hex) = lower.strip_prefix("0x") {
+        i64::from_str_radix(hex, 16)
+    } else if let Some(bin) = lower.strip_prefix("0b") {
+        i64::from_str_radix(bin, 2)
+    } else {
+        lower.parse::<i64>()
+    }
+    .map_err(|_| Error::InvalidSyntax)?;
+    Ok(if negative { -value } else { value })
+}
+
+fn parse_register_list(s: &str) -> Result<RegisterList, Error> {
+    let mut registers = RegisterList::new();
+    for item in s.split(',') {
+        let item = item.trim();
+        match item.split_once('-') {
+            Some((first, last)) => {
+                let first = first.trim().parse::<Register>()? as u8;
+                let last = last.trim().parse::<Register>()? as u8;
+                if first > last {
+                    return Err(Error::InvalidSyntax);
+                }
+                for register in first..=last {
+                    registers.insert(register.try_into()?);
+                }
+            }
+            None => registers.insert(item.parse()?),
+        }
+    }
+    Ok(registers)
+}
+
+/// Converts a parsed immediate to the `u32` representation used by [`Operation`].
+fn imm(value: i64) -> Result<u32, Error> {
+    if value < i32::MIN as i64 || value > u32::MAX as i64 {
+        return Err(Error::ImmediateOutOfRange);
+    }
+    Ok(value as u32)
+}
+
+/// Builds an operation from a split up statement.
+pub(crate) fn build_operation(statement: &Statement) -> Result<Operation, Error> {
+    use Operand as O;
+
+    let mnemonic = statement.mnemonic.as_str();
+    let operands: Vec<Operand> = statement
+        .operands
+        .iter()
+        .map(|operand| match operand {
+            // Writeback is implied by the operation and only accepted where it is valid.
+            O::Register {
+                register,
+                writeback: true,
+            } if matches!(
+                mnemonic,
+                "ldm" | "ldmia" | "ldmfd" | "stm" | "stmia" | "stmea"
+            ) =>
+            {
+                O::Register {
+                    register: *register,
+                    writeback: false,
+                }
+            }
+            operand => operand.clone(),
+        })
+        .collect();
+
+    // Shorthand for a plain register operand.
+    macro_rules! r {
+        ($name:pat) => {
+            O::Register {
+                register: $name,
+                writeback: false,
+            }
+        };
+    }
+
+    let operation = match (mnemonic, operands.as_slice()) {
+        // Additions.
+        ("add" | "adds", [r!(d), r!(n), O::Immediate(i)]) => match (*d, *n) {
+            (d, Register::SP) => Operation::ADDImmSP { d, imm: imm(*i)? },
+            (d, Register::PC) => Operation::ADR { d, imm: imm(*i)? },
+            (d, n) => Operation::ADDImm {
+                imm: imm(*i)?,
+                n,
+                d,
+            },
+        },
+        ("add" | "adds", [r!(dn), O::Immediate(i)]) => match *dn {
+            Register::SP => Operation::ADDImmSP {
+                d: Register::SP,
+                imm: imm(*i)?,
+            },
+            dn => Operation::ADDImm {
+                imm: imm(*i)?,
+                n: dn,
+                d: dn,
+            },
+        },
+        ("add" | "adds", [r!(d), r!(n), r!(m)]) => match (*d, *n, *m) {
+            (d, Register::SP, m) => Operation::ADDRegSP { d, m },
+            (d, n, Register::SP) if d == n => Operation::ADDRegSP { d, m: d },
+            // `add` of three registers is only encodable as `adds` unless `d` is `n`.
+            (d, n, m) => Operation::ADDReg {
+                m,
+                n,
+                d,
+                set_flags: mnemonic == "adds" || d != n,
+            },
+        },
+        ("add" | "adds", [r!(dn), r!(m)]) => match (*dn, *m) {
+            (dn, Register::SP) => Operation::ADDRegSP { d: dn, m: dn },
+            (Register::SP, m) => Operation::ADDRegSP { d: Register::SP, m },
+            (dn, m) => Operation::ADDReg {
+                m,
+                n: dn,
+                d: dn,
+                set_flags: mnemonic == "adds",
+            },
+        },
+        ("adc" | "adcs", [r!(dn), r!(m)]) => Operation::ADCReg {
+            m: *m,
+            n: *dn,
+            d: *dn,
+        },
+        ("adc" | "adcs", [r!(d), r!(n), r!(m)]) => {
+            let (m, n) = commutative(*d, *n, *m)?;
+            Operation::ADCReg { m, n, d: *d }
+        }
+        ("adr", [r!(d), O::Immediate(i)]) => Operation::ADR {
+            d: *d,
+            imm: imm(*i)?,
+        },
+
+        // Subtractions.
+        ("sub" | "subs", [r!(d), r!(n), O::Immediate(i)]) => match (*d, *n) {
+            (Register::SP, Register::SP) => Operation::SUBImmSP { imm: imm(*i)? },
+            (d, n) => Operation::SUBImm {
+                imm: imm(*i)?,
+                n,
+                d,
+            },
+        },
+        ("sub" | "subs", [r!(dn), O::Immediate(i)]) => match *dn {
+            Register::SP => Operation::SUBImmSP { imm: imm(*i)? },
+            dn => Operation::SUBImm {
+                imm: imm(*i)?,
+                n: dn,
+                d: dn,
+            },
+        },
+        ("sub" | "subs", [r!(d), r!(n), r!(m)]) => Operation::SUBReg {
+            m: *m,
+            n: *n,
+            d: *d,
+        },
+        ("sub" | "subs", [r!(dn), r!(m)]) => Operation::SUBReg {
+            m: *m,
+            n: *dn,
+            d: *dn,
+        },
+        ("sbc" | "sbcs", [r!(dn), r!(m)]) => Operation::SBCReg { m: *m, dn: *dn },
+        ("sbc" | "sbcs", [r!(d), r!(n), r!(m)]) if d == n => Operation::SBCReg { m: *m, dn: *d },
+        ("rsb" | "rsbs", [r!(d), r!(n), O::Immediate(0)]) | ("neg" | "negs", [r!(d), r!(n)]) => {
+            Operation::RSBImm { n: *n, d: *d }
+        }
+        ("rsb" | "rsbs", [r!(_), r!(_), O::Immediate(_)]) => {
+            return Err(Error::ImmediateOutOfRange)
+        }
+
+        // Multiplication.
+        ("mul" | "muls", [r!(dm), r!(n)]) => Operation::MUL { n: *n, dm: *dm },
+        ("mul" | "muls", [r!(d), r!(n), r!(m)]) => {
+            let (n, _) = commutative(*d, *n, *m)?;
+            Operation::MUL { n, dm: *d }
+        }
+
+        // Logical operations.
+        ("and" | "ands", ..) => {
+            let (dn, m) = dn_m(&operands, true)?;
+            Operation::ANDReg { m, dn }
+        }
+        ("eor" | "eors", ..) => {
+            let (dn, m) = dn_m(&operands, true)?;
+            Operation::EORReg { m, dn }
+        }
+        ("orr" | "orrs", ..) => {
+            let (dn, m) = dn_m(&operands, true)?;
+            Operation::ORRReg { m, dn }
+        }
+        ("bic" | "bics", ..) => {
+            let (dn, m) = dn_m(&operands, false)?;
+            Operation::BICReg { m, dn }
+        }
+        ("mvn" | "mvns", [r!(d), r!(m)]) => Operation::MVNReg { m: *m, d: *d },
+        ("tst", [r!(n), r!(m)]) => Operation::TSTReg { m: *m, n: *n },
+        ("cmp", [r!(n), r!(m)]) => Operation::CMPReg { m: *m, n: *n },
+        ("cmp", [r!(n), O::Immediate(i)]) => Operation::CMPImm {
+            n: *n,
+            imm: imm(*i)?,
+        },
+        ("cmn", [r!(n), r!(m)]) => Operation::CMNReg { m: *m, n: *n },
+
+        // Shifts and rotations.
+        ("lsl" | "lsls", [r!(d), r!(m), O::Immediate(0)])
+        | ("lsl" | "lsls", [r!(d @ m), O::Immediate(0)]) => Operation::MOVReg {
+            m: *m,
+            d: *d,
+            set_flags: true,
+        },
+        ("lsl" | "lsls", [r!(d), r!(m), O::Immediate(i)])
+        | ("lsl" | "lsls", [r!(d @ m), O::Immediate(i)]) => Operation::LSLImm {
+            imm: imm(*i)?,
+            m: *m,
+            d: *d,
+        },
+        ("lsr" | "lsrs", [r!(d), r!(m), O::Immediate(i)])
+        | ("lsr" | "lsrs", [r!(d @ m), O::Immediate(i)]) => Operation::LSRImm {
+            imm: shift_32(*i)?,
+            m: *m,
+            d: *d,
+        },
+        ("asr" | "asrs", [r!(d), r!(m), O::Immediate(i)])
+        | ("asr" | "asrs", [r!(d @ m), O::Immediate(i)]) => Operation::ASRImm {
+            imm: shift_32(*i)?,
+            m: *m,
+            d: *d,
+        },
+        ("lsl" | "lsls", ..) => {
+            let (dn, m) = dn_m(&operands, false)?;
+            Operation::LSLReg { m, dn }
+        }
+        ("lsr" | "lsrs", ..) => {
+            let (dn, m) = dn_m(&operands, false)?;
+            Operation::LSRReg { m, dn }
+        }
+        ("asr" | "asrs", ..) => {
+            let (dn, m) = dn_m(&operands, false)?;
+            Operation::ASRReg { m, dn }
+        }
+        ("ror" | "rors", ..) => {
+            let (dn, m) = dn_m(&operands, false)?;
+            Operation::RORReg { m, dn }
+        }
+
+        // Moves.
+        ("mov" | "movs", [r!(d), O::Immediate(i)]) => Operation::MOVImm {
+            d: *d,
+            imm: imm(*i)?,
+        },
+        ("movs", [r!(d), r!(m)]) => Operation::MOVReg {
+            m: *m,
+            d: *d,
+            set_flags: true,
+        },
+        ("mov" | "cpy", [r!(d), r!(m)]) => Operation::MOVReg {
+            m: *m,
+            d: *d,
+            set_flags: false,
+        },
+
+        // Extend and reverse.
+        ("sxtb", [r!(d), r!(m)]) => Operation::SXTB { m: *m, d: *d },
+        ("sxth", [r!(d), r!(m)]) => Operation::SXTH { m: *m, d: *d },
+        ("uxtb", [r!(d), r!(m)]) => Operation::UXTB { m: *m, d: *d },
+        ("uxth", [r!(d), r!(m)]) => Operation::UXTH { m: *m, d: *d },
+        ("rev", [r!(d), r!(m)]) => Operation::REV { m: *m, d: *d },
+        ("rev16", [r!(d), r!(m)]) => Operation::REV16 { m: *m, d: *d },
+        ("revsh", [r!(d), r!(m)]) => Operation::REVSH { m: *m, d: *d },
+
+        // Loads and stores.
+        ("ldr", [r!(t), O::Memory { base, offset }]) => match (*base, offset) {
+            (Register::PC, Offset::Immediate(i)) => Operation::LDRLiteral {
+                t: *t,
+                imm: imm(*i)?,
+            },
+            (n, Offset::Immediate(i)) => Operation::LDRImm {
+                imm: imm(*i)?,
+                n,
+                t: *t,
+            },
+            (n, Offset::Register(m)) => Operation::LDRReg { m: *m, n, t: *t },
+        },
+        ("str", [r!(t), O::Memory { base, offset }]) => match offset {
+            Offset::Immediate(i) => Operation::STRImm {
+                imm: imm(*i)?,
+                n: *base,
+                t: *t,
+            },
+            Offset::Register(m) => Operation::STRReg {
+                m: *m,
+                n: *base,
+                t: *t,
+            },
+        },
+        ("ldrb", [r!(t), O::Memory { base, offset }]) => match offset {
+            Offset::Immediate(i) => Operation::LDRBImm {
+                imm: imm(*i)?,
+                n: *base,
+                t: *t,
+            },
+            Offset::Register(m) => Operation::LDRBReg {
+                m: *m,
+                n: *base,
+                t: *t,
+            },
+        },
+        ("strb", [r!(t), O::Memory { base, offset }]) => match offset {
+            Offset::Immediate(i) => Operation::STRBImm {
+                imm: imm(*i)?,
+                n: *base,
+                t: *t,
+            },
+            Offset::Register(m) => Operation::STRBReg {
+                m: *m,
+                n: *base,
+                t: *t,
+            },
+        },
+        ("ldrh", [r!(t), O::Memory { base, offset }]) => match offset {
+            Offset::Immediate(i) => Operation::LDRHImm {
+                imm: imm(*i)?,
+                n: *base,
+                t: *t,
+            },
+            Offset::Register(m) => Operation::LDRHReg {
+                m: *m,
+                n: *base,
+                t: *t,
+            },
+        },
+        ("strh", [r!(t), O::Memory { base, offset }]) => match offset {
+            Offset::Immediate(i) => Operation::STRHImm {
+                imm: imm(*i)?,
+                n: *base,
+                t: *t,
+            },
+            Offset::Register(m) => Operation::STRHReg {
+                m: *m,
+                n: *base,
+                t: *t,
+            },
+        },
+        (
+            "ldrsb",
+            [r!(t), O::Memory {
+                base,
+                offset: Offset::Register(m),
+            }],
+        ) => Operation::LDRSBReg {
+            m: *m,
+            n: *base,
+            t: *t,
+        },
+        (
+            "ldrsh",
+            [r!(t), O::Memory {
+                base,
+                offset: Offset::Register(m),
+            }],
+        ) => Operation::LDRSH {
+            m: *m,
+            n: *base,
+            t: *t,
+        },
+        ("ldm" | "ldmia" | "ldmfd", [r!(n), O::RegisterList(reg_list)]) => Operation::LDM {
+            n: *n,
+            reg_list: *reg_list,
+        },
+        ("stm" | "stmia" | "stmea", [r!(n), O::RegisterList(reg_list)]) => Operation::STM {
+            n: *n,
+            reg_list: *reg_list,
+        },
+        ("push", [O::RegisterList(reg_list)]) => Operation::PUSH {
+            reg_list: *reg_list,
+        },
+        ("pop", [O::RegisterList(reg_list)]) => Operation::POP {
+            reg_list: *reg_list,
+        },
+
+        // Branches.
+        ("bl", [O::Immediate(i)]) => Operation::BL { imm: imm(*i)? },
+        ("bx", [r!(m)]) => Operation::BX { m: *m },
+        ("blx", [r!(m)]) => Operation::BLXReg { m: *m },
+        (mnemonic, [O::Immediate(i)]) if branch_condition(mnemonic).is_some() => Operation::B {
+            cond: branch_condition(mnemonic).unwrap(),
+            imm: imm(*i)?,
+        },
+
+        // Exception generating and system instructions.
+        ("svc" | "swi", [O::Immediate(i)]) => Operation::SVC { imm: imm(*i)? },
+        ("bkpt", [O::Immediate(i)]) => Operation::BKPT { imm: imm(*i)? },
+        ("bkpt", []) => Operation::BKPT { imm: 0 },
+        ("udf", [O::Immediate(i)]) => Operation::UDF { imm: imm(*i)? },
+        ("udf", []) => Operation::UDF { imm: 0 },
+        ("cpsid", [O::Symbol(flags)]) if flags.eq_ignore_ascii_case("i") => {
+            Operation::CPS { im: true }
+        }
+        ("cpsie", [O::Symbol(flags)]) if flags.eq_ignore_ascii_case("i") => {
+            Operation::CPS { im: false }
+        }
+        ("mrs", [r!(d), O::Symbol(sysm)]) => Operation::MRS {
+            d: *d,
+            sysm: sysm.parse::<SpecialRegister>()?,
+        },
+        ("msr", [O::Symbol(sysm), r!(n)]) => Operation::MSRReg {
+            n: *n,
+            sysm: sysm.parse::<SpecialRegister>()?,
+        },
+        ("dmb", operands) => Operation::DMB {
+            option: barrier_option(operands)?,
+        },
+        ("dsb", operands) => Operation::DSB {
+            option: barrier_option(operands)?,
+        },
+        ("isb", operands) => Operation::ISB {
+            option: barrier_option(operands)?,
+        },
+        ("nop", []) => Operation::NOP,
+        ("yield", []) => Operation::YIELD,
+        ("wfe", []) => Operation::WFE,
+        ("wfi", []) => Operation::WFI,
+        ("sev", []) => Operation::SEV,
+
+        (mnemonic, _) if is_known_mnemonic(mnemonic) => return Err(Error::InvalidOperands),
+        _ => return Err(Error::UnknownMnemonic),
+    };
+    Ok(operation)
+}
+
+/// Returns the condition of a branch mnemonic like `b`, `beq` or `bhs`.
+pub(crate) fn branch_condition(mnemonic: &str) -> Option<Condition> {
+    match mnemonic {
+        "b" => Some(Condition::None),
+        // `bl` and friends are handled before this is called.
+        _ => mnemonic.strip_prefix('b')?.parse().ok(),
+    }
+}
+
+fn is_known_mnemonic(mnemonic: &str) -> bool {
+    const MNEMONICS: &[&str] = &[
+        "add", "adds", "adc", "adcs", "adr", "sub", "subs", "sbc", "sbcs", "rsb", "rsbs", "neg",
+        "negs", "mul", "muls", "and", "ands", "eor", "eors", "orr", "orrs", "bic", "bics", "mvn",
+        "mvns", "tst", "cmp", "cmn", "lsl", "lsls", "lsr", "lsrs", "asr", "asrs", "ror", "rors",
+        "mov", "movs", "cpy", "sxtb", "sxth", "uxtb", "uxth", "rev", "rev16", "revsh", "ldr",
+        "str", "ldrb", "strb", "ldrh", "strh", "ldrsb", "ldrsh", "ldm", "ldmia", "ldmfd", "stm",
+        "stmia", "stmea", "push", "pop", "bl", "bx", "blx", "svc", "swi", "bkpt", "udf", "cpsid",
+        "cpsie", "mrs", "msr", "dmb", "dsb", "isb", "nop", "yield", "wfe", "wfi", "sev",
+    ];
+    MNEMONICS.contains(&mnemonic) || branch_condition(mnemonic).is_some()
+}
+
+/// Register operands of two operand instructions that also accept a three operand form
+/// where the destination is repeated, e.g. `ands r0, r0, r1`.
+fn dn_m(operands: &[Operand], commutes: bool) -> Result<(Register, Register), Error> {
+    let register = |operand: &Operand| match operand {
+        Operand::Register {
+            register,
+            writeback: false,
+        } => Ok(*register),
+        _ => Err(Error::InvalidOperands),
+    };
+    match operands {
+        [dn, m] => Ok((register(dn)?, register(m)?)),
+        [d, n, m] => {
+            let (d, n, m) = (register(d)?, register(n)?, register(m)?);
+            if d == n {
+                Ok((d, m))
+            } else if commutes && d == m {
+                Ok((d, n))
+            } else {
+                Err(Error::InvalidOperands)
+            }
+        }
+        _ => Err(Error::InvalidOperands),
+    }
+}
+
+/// Orders the sources of a commutative operation so that the first one is the destination.
+/// Returns the (other, destination) pair.
+fn commutative(d: Register, n: Register, m: Register) -> Result<(Register, Register), Error> {
+    if d == n {
+        Ok((m, n))
+    } else if d == m {
+        Ok((n, m))
+    } else {
+        Err(Error::InvalidOperands)
+    }
+}
+
+/// Shift amounts of `lsr` and `asr` are encoded with 32 as 0.
+fn shift_32(value: i64) -> Result<u32, Error> {
+    match value {
+        1..=31 => Ok(value as u32),
+        32 => Ok(0),
+        _ => Err(Error::ImmediateOutOfRange),
+    }
+}
+
+fn barrier_option(operands: &[Operand]) -> Result<u8, Error> {
+    match operands {
+        [] => Ok(0xf),
+        [Operand::Symbol(option)] if option.eq_ignore_ascii_case("sy") => Ok(0xf),
+        [Operand::Immediate(option @ 0..=0xf)] => Ok(*option as u8),
+        [Operand::Immediate(_)] => Err(Error::ImmediateOutOfRange),
+        _ => Err(Error::InvalidOperands),
+    }
+}
+
+/// Error from [`assemble`] together with the line it occurred on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AssemblyError {
+    /// Line number, starting from 1.
+    pub line: usize,
+    pub error: Error,
+}
+
+impl core::fmt::Display for AssemblyError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "line {}: {}", self.line, self.error)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for AssemblyError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+/// Kind of reference patched by a relocation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RelocationKind {
+    /// 16 bit `B`, conditional or unconditional.
+    Branch(Condition),
+    /// 32 bit `BL`.
+    BranchLink,
+    /// Absolute address stored in a `.word` or literal pool.
+    Word,
+}
+
+/// A reference to a symbol that can only be resolved once the image address is known.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Relocation {
+    /// Offset of the branch or word from the start of the image.
+    pub offset: u32,
+    pub symbol: String,
+    pub kind: RelocationKind,
+    /// Source line of the reference.
+    pub line: usize,
+}
+
+/// Position independent machine code produced by [`assemble`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Assembly {
+    /// The image, with relocated branches and words left as zero.
+    pub bytes: Vec<u8>,
+    /// Offsets of all labels defined in the source.
+    pub labels: BTreeMap<String, u32>,
+    /// References that has to be patched once the image address is known.
+    pub relocations: Vec<Relocation>,
+}
+
+impl Assembly {
+    /// Returns the image placed at `base` with all relocations resolved.
+    /// `symbols` returns the address of an external symbol.
+    pub fn relocate(
+        &self,
+        base: u32,
+        symbols: impl Fn(&str) -> Option<u32>,
+    ) -> Result<Vec<u8>, AssemblyError> {
+        let mut bytes = self.bytes.clone();
+        for relocation in &self.relocations {
+            let error = |error| AssemblyError {
+                line: relocation.line,
+                error,
+            };
+            let target = match self.labels.get(&relocation.symbol) {
+                Some(offset) => base.wrapping_add(*offset),
+                None => symbols(&relocation.symbol).ok_or(error(Error::UndefinedLabel))?,
+            };
+            let imm = target.wrapping_sub(base.wrapping_add(relocation.offset + 4));
+            let operation = match relocation.kind {
+                RelocationKind::Branch(cond) => Operation::B { cond, imm },
+                RelocationKind::BranchLink => Operation::BL { imm },
+                RelocationKind::Word => {
+                    let offset = relocation.offset as usize;
+                    bytes[offset..offset + 4].copy_from_slice(&target.to_le_bytes());
+                    continue;
+                }
+            };
+            let encoding = encode(&operation).map_err(error)?;
+            let offset = relocation.offset as usize;
+            bytes[offset..offset + encoding.size()].copy_from_slice(encoding.as_bytes());
+        }
+        Ok(bytes)
+    }
+}
+
+/// One statement of a source text with the labels defined before it.
+struct SourceStatement {
+    line: usize,
+    labels: Vec<String>,
+    statement: Option<Statement>,
+}
+
+/// Splits a source text into statements, removing comments and collecting labels.
+fn parse_source(source: &str) -> Result<Vec<SourceStatement>, AssemblyError> {
+    let mut statements = vec![];
+    for (index, text) in source.lines().enumerate() {
+        let line = index + 1;
+        let text = text.split('@').next().unwrap_or_default();
+        let text = text.split("//").next().unwrap_or_default();
+        for mut text in text.split(';') {
+            let mut labels = vec![];
+            while let Some((label, rest)) = text.split_once(':') {
+                let label = label.trim();
+                if label.is_empty()
+                    || !label
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '$')
+                {
+                    break;
+                }
+                labels.push(label.to_string());
+                text = rest;
+            }
+            let statement = if text.trim().is_empty() {
+                None
+            } else {
+                Some(
+                    text.parse::<Statement>()
+                        .map_err(|error| AssemblyError { line, error })?,
+                )
+            };
+            if statement.is_some() || !labels.is_empty() {
+                statements.push(SourceStatement {
+                    line,
+                    labels,
+                    statement,
+                });
+            }
+        }
+    }
+    Ok(statements)
+}
+
+/// Value of a data directive or a literal.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Value {
+    Constant(u32),
+    /// Address of a label or external symbol.
+    Symbol(String),
+}
+
+impl Value {
+    fn from_operand(operand: &Operand, size: u32) -> Result<Self, Error> {
+        match operand {
+            Operand::Immediate(value) => {
+                let bits = size * 8;
+                if bits < 32 && (*value >= 1 << bits || *value < -(1 << (bits - 1))) {
+                    return Err(Error::ImmediateOutOfRange);
+                }
+                Ok(Value::Constant(imm(*value)?))
+            }
+            Operand::Symbol(symbol) if size == 4 => Ok(Value::Symbol(symbol.clone())),
+            _ => Err(Error::InvalidOperands),
+        }
+    }
+}
+
+/// A statement after the first pass.
+enum Item {
+    /// An instruction, `literal` is the value of a `ldr rX, =value`.
+    Instruction {
+        statement: Statement,
+        literal: Option<Value>,
+    },
+    /// Values of a `.word`, `.hword` or `.byte` directive, `size` bytes each.
+    Data { values: Vec<Value>, size: u32 },
+    /// Alignment to `1 << n` bytes from `.align n`.
+    Align(u32),
+    /// Literal pool placed by `.ltorg`.
+    Pool,
+}
+
+impl Item {
+    /// Returns true if execution never continues to the next item.
+    fn is_barrier(&self) -> bool {
+        let Item::Instruction { statement, .. } = self else {
+            return false;
+        };
+        match (statement.mnemonic.as_str(), statement.operands.as_slice()) {
+            ("b" | "bal" | "bx", _) => true,
+            ("pop", [Operand::RegisterList(reg_list)]) => reg_list.contains(Register::PC),
+            _ => false,
+        }
+    }
+}
+
+/// An item with the labels defined before it.
+struct SourceItem {
+    line: usize,
+    labels: Vec<String>,
+    item: Item,
+}
+
+/// Converts the statements of a source to items.
+fn first_pass(statements: Vec<SourceStatement>) -> Result<Vec<SourceItem>, AssemblyError> {
+    let mut items = vec![];
+    for statement in statements {
+        let line = statement.line;
+        let item = match &statement.statement {
+            None => None,
+            Some(source) => item(source).map_err(|error| AssemblyError { line, error })?,
+        };
+        items.push(SourceItem {
+            line,
+            labels: statement.labels,
+            // Labels before ignored directives belong to the next item.
+            item: item.unwrap_or(Item::Data {
+                values: vec![],
+                size: 1,
+            }),
+        });
+    }
+    Ok(items)
+}
+
+fn item(statement: &Statement) -> Result<Option<Item>, Error> {
+    let data = |size| -> Result<Item, Error> {
+        let values = statement
+            .operands
+            .iter()
+            .map(|operand| Value::from_operand(operand, size))
+            .collect::<Result<_, _>>()?;
+        Ok(Item::Data { values, size })
+    };
+    let item = match (statement.mnemonic.as_str(), statement.operands.as_slice()) {
+        (".word" | ".long" | ".4byte", _) => data(4)?,
+        (".hword" | ".short" | ".2byte", _) => data(2)?,
+        (".byte", _) => data(1)?,
+        (".align" | ".p2align", [Operand::Immediate(n @ 0..=16)]) => Item::Align(*n as u32),
+        (".align" | ".p2align", [Operand::Immediate(_)]) => return Err(Error::ImmediateOutOfRange),
+        (".ltorg" | ".pool", []) => Item::Pool,
+        (".thumb" | ".text" | ".syntax", _) => return Ok(None),
+        (mnemonic, _) if mnemonic.starts_with('.') => return Err(Error::UnknownMnemonic),
+        ("ldr", [Operand::Register { .. }, Operand::Literal(value)]) => Item::Instruction {
+            statement: statement.clone(),
+            literal: Some(Value::from_operand(value, 4)?),
+        },
+        _ => Item::Instruction {
+            statement: statement.clone(),
+            literal: None,
+        },
+    };
+    Ok(Some(item))
+}
+
+/// Decisions made after earlier layouts did not fit.
+#[derive(Default)]
+struct Placement {
+    /// Conditional branches relaxed to 4 bytes.
+    relaxed: BTreeSet<usize>,
+    /// Items a literal pool is placed before, with a branch over the pool if true.
+    pools: BTreeMap<usize, bool>,
+}
+
+/// A placed literal pool.
+struct Pool {
+    /// Offset of the pool, or of the branch over it.
+    offset: u32,
+    branch: bool,
+    values: Vec<Value>,
+}
+
+/// Offsets of every item, label and literal.
+struct Layout {
+    offsets: Vec<u32>,
+    labels: BTreeMap<String, u32>,
+    /// Literal pools by the item they are placed before.
+    pools: BTreeMap<usize, Pool>,
+    /// Offset of the literal of every `ldr rX, =value` item.
+    literals: BTreeMap<usize, u32>,
+}
+
+/// Literals waiting to be placed in a pool.
+#[derive(Default)]
+struct PendingLiterals {
+    values: Vec<Value>,
+    /// Items using the literals and the index of their value.
+    users: Vec<(usize, usize)>,
+}
+
+impl Layout {
+    /// Places the pending literals in a pool before item `index` at `offset`.
+    /// Returns the offset after the pool.
+    fn place_pool(
+        &mut self,
+        index: usize,
+        mut offset: u32,
+        branch: bool,
+        pending: &mut PendingLiterals,
+    ) -> u32 {
+        let pending = core::mem::take(pending);
+        if pending.values.is_empty() {
+            return offset;
+        }
+        let start = offset;
+        if branch {
+            offset += 2;
+        }
+        offset += padding(offset, 4);
+        for (user, slot) in pending.users {
+            self.literals.insert(user, offset + slot as u32 * 4);
+        }
+        self.pools.insert(
+            index,
+            Pool {
+                offset: start,
+                branch,
+                values: pending.values,
+            },
+        );
+        offset + self.pools[&index].values.len() as u32 * 4
+    }
+}
+
+/// Bytes of padding needed to align `offset` to `alignment`.
+fn padding(offset: u32, alignment: u32) -> u32 {
+    offset.wrapping_neg() & (alignment - 1)
+}
+
+/// Lays out all items, the width of every instruction is known without knowing the labels
+/// except for relaxed conditional branches, which take 4 bytes.
+fn layout(items: &[SourceItem], placement: &Placement) -> Result<Layout, AssemblyError> {
+    let mut layout = Layout {
+        offsets: Vec::with_capacity(items.len()),
+        labels: BTreeMap::new(),
+        pools: BTreeMap::new(),
+        literals: BTreeMap::new(),
+    };
+    let mut pending = PendingLiterals::default();
+    let mut offset = 0;
+    for (index, item) in items.iter().enumerate() {
+        let error = |error| AssemblyError {
+            line: item.line,
+            error,
+        };
+        if let Some(branch) = placement.pools.get(&index) {
+            offset = layout.place_pool(index, offset, *branch, &mut pending);
+        }
+        for label in &item.labels {
+            if layout.labels.insert(label.clone(), offset).is_some() {
+                return Err(error(Error::DuplicateLabel));
+            }
+        }
+        layout.offsets.push(offset);
+        offset = match &item.item {
+            Item::Instruction { .. } if placement.relaxed.contains(&index) => offset + 4,
+            Item::Instruction {
+                literal: Some(value),
+                ..
+            } => {
+                let slot = match pending.values.iter().position(|slot| slot == value) {
+                    Some(slot) => slot,
+                    None => {
+                        pending.values.push(value.clone());
+                        pending.values.len() - 1
+                    }
+                };
+                pending.users.push((index, slot));
+                offset + 2
+            }
+            Item::Instruction { statement, .. } => {
+                let (statement, _) =
+                    resolve_symbols(statement, offset, None, |_| SymbolValue::Label(0))
+                        .map_err(error)?;
+                offset
+                    + encode(&build_operation(&statement).map_err(error)?)
+                        .map(|encoding| encoding.size() as u32)
+                        .unwrap_or(2)
+            }
+            Item::Data { values, size } => offset + values.len() as u32 * size,
+            Item::Align(n) => offset + padding(offset, 1 << n),
+            Item::Pool => layout.place_pool(index, offset, false, &mut pending),
+        };
+    }
+    layout.place_pool(items.len(), offset, false, &mut pending);
+    Ok(layout)
+}
+
+/// Returns true if `item` at `offset` is a conditional branch to a label out of range of
+/// the 16 bit encoding.
+fn needs_relaxation(item: &Item, offset: u32, labels: &BTreeMap<String, u32>) -> bool {
+    let Item::Instruction { statement, .. } = item else {
+        return false;
+    };
+    match (
+        branch_condition(&statement.mnemonic),
+        statement.operands.as_slice(),
+    ) {
+        (Some(cond), [Operand::Symbol(symbol)]) if cond != Condition::None && !statement.narrow => {
+            match labels.get(symbol) {
+                Some(target) => {
+                    let imm = *target as i64 - (offset as i64 + 4);
+                    !(-256..=254).contains(&imm)
+                }
+                None => false,
+            }
+        }
+        _ => false,
+    }
+}
+
+/// Finds where to place a literal pool for the `ldr rX, =value` at item `user` whose
+/// literal is out of range. Prefers the last place after a barrier, i.e. where no branch
+/// over the pool is needed. Returns the item to place the pool before and if a branch is
+/// needed.
+fn pool_placement(
+    items: &[SourceItem],
+    layout: &Layout,
+    placement: &Placement,
+    user: usize,
+) -> Option<(usize, bool)> {
+    let pool_size = layout
+        .pools
+        .values()
+        .map(|pool| pool.values.len() as u32 * 4)
+        .max()
+        .unwrap_or(0);
+    let limit = ((layout.offsets[user] + 4) & !0b11) + 1020;
+    let candidates = || {
+        (user + 1..items.len()).filter(|index| {
+            matches!(items[*index].item, Item::Instruction { .. })
+                && !placement.pools.contains_key(index)
+        })
+    };
+    let fits = |index: usize, branch: u32| layout.offsets[index] + branch + 2 + pool_size <= limit;
+    candidates()
+        .rev()
+        .find(|index| items[index - 1].item.is_barrier() && fits(*index, 0))
+        .map(|index| (index, false))
+        .or_else(|| {
+            candidates()
+                .rev()
+                .find(|index| fits(*index, 2))
+                .map(|index| (index, true))
+        })
+}
+
+/// Where a symbol operand of a statement refers to.
+enum SymbolValue {
+    /// Offset of a label defined in the source.
+    Label(u32),
+    /// A symbol that is not defined, branches to it are relocated.
+    External,
+}
+
+/// Replaces label operands with PC relative offsets for a statement at `offset`.
+/// `literal` is the offset of the pool slot of a `ldr rX, =value`.
+/// Branches to undefined symbols are given offset 0 and reported as a relocation.
+fn resolve_symbols(
+    statement: &Statement,
+    offset: u32,
+    literal: Option<u32>,
+    lookup: impl Fn(&str) -> SymbolValue,
+) -> Result<(Statement, Option<Relocation>), Error> {
+    let mut statement = statement.clone();
+    let mut relocation = None;
+    // Target addresses of ADR and LDR literal are relative to the word aligned PC.
+    let aligned_pc = (offset + 4) & !0b11;
+    let mnemonic = statement.mnemonic.as_str();
+    let kind = match mnemonic {
+        "bl" => Some(RelocationKind::BranchLink),
+        _ => branch_condition(mnemonic).map(RelocationKind::Branch),
+    };
+    match (kind, statement.operands.as_mut_slice()) {
+        (Some(kind), [operand @ Operand::Symbol(_)]) => {
+            let Operand::Symbol(symbol) = &operand else {
+                unreachable!()
+            };
+            *operand = match lookup(symbol) {
+                SymbolValue::Label(target) => {
+                    Operand::Immediate(target as i64 - (offset as i64 + 4))
+                }
+                SymbolValue::External => {
+                    relocation = Some(Relocation {
+                        offset,
+                        symbol: symbol.clone(),
+                        kind,
+                        line: 0,
+                    });
+                    Operand::Immediate(0)
+                }
+            };
+        }
+        (None, [Operand::Register { .. }, operand @ Operand::Symbol(_)])
+            if matches!(mnemonic, "adr" | "ldr") =>
+        {
+            let Operand::Symbol(symbol) = &operand else {
+                unreachable!()
+            };
+            let SymbolValue::Label(target) = lookup(symbol) else {
+                return Err(Error::UndefinedLabel);
+            };
+            let imm = target as i64 - aligned_pc as i64;
+            *operand = if mnemonic == "adr" {
+                Operand::Immediate(imm)
+            } else {
+                Operand::Memory {
+                    base: Register::PC,
+                    offset: Offset::Immediate(imm),
+                }
+            };
+        }
+        (None, [Operand::Register { .. }, operand @ Operand::Literal(_)]) if mnemonic == "ldr" => {
+            let target = literal.unwrap_or(aligned_pc);
+            *operand = Operand::Memory {
+                base: Register::PC,
+                offset: Offset::Immediate(target as i64 - aligned_pc as i64),
+            };
+        }
+        _ => (),
+    }
+    Ok((statement, relocation))
+}
+
+/// Assembles a source text with one or more statements per line, separated by `;`.
+///
+/// Labels are defined by `name:` and can be used as the target of branches, `adr` and `ldr`.
+/// Comments start with `@` or `//`. References to labels not defined in the source are
+/// reported as [`Relocation`]s, to be resolved by [`Assembly::relocate`].
+///
+/// The directives `.word`, `.hword` and `.byte` emit data, `.align n` pads with `nop`s
+/// to a multiple of `1 << n` bytes and `.ltorg` places the literal pool holding the values
+/// of `ldr rX, =value` pseudo instructions. Literals after the last `.ltorg` are placed at
+/// the end of the image. If a literal would be out of range of its load, an extra pool is
+/// placed after the last unconditional branch in range, or if there is none, in the middle
+/// of the code with a branch over it.
+///
+/// Conditional branches to labels out of range are relaxed into a branch with the inverted
+/// condition over an unconditional branch, e.g. `beq far` becomes `bne 1f; b far; 1:`.
+/// ARMv6-M has no 32 bit `b.w`, so an unconditional branch out of range is an error.
+/// Branches written with a `.n` qualifier are never relaxed.
+pub fn assemble(source: &str) -> Result<Assembly, AssemblyError> {
+    let items = first_pass(parse_source(source)?)?;
+
+    // Relaxing a branch or adding a literal pool can only move other items further apart,
+    // so iterate until everything is in range.
+    let mut placement = Placement::default();
+    let layout = loop {
+        let layout = layout(&items, &placement)?;
+        let out_of_range: Vec<usize> = items
+            .iter()
+            .zip(&layout.offsets)
+            .enumerate()
+            .filter(|(index, (item, offset))| {
+                !placement.relaxed.contains(index)
+                    && needs_relaxation(&item.item, **offset, &layout.labels)
+            })
+            .map(|(index, _)| index)
+            .collect();
+        let far_literal = layout
+            .literals
+            .iter()
+            .find(|(user, literal)| **literal > ((layout.offsets[**user] + 4) & !0b11) + 1020);
+        if let Some((user, _)) = far_literal {
+            let (index, branch) =
+                pool_placement(&items, &layout, &placement, *user).ok_or(AssemblyError {
+                    line: items[*user].line,
+                    error: Error::ImmediateOutOfRange,
+                })?;
+            placement.pools.insert(index, branch);
+        } else if out_of_range.is_empty() {
+            break layout;
+        }
+        placement.relaxed.extend(out_of_range);
+    };
+
+    let mut assembly = Assembly {
+        bytes: vec![],
+        labels: layout.labels.clone(),
+        relocations: vec![],
+    };
+    for (index, (item, &offset)) in items.iter().zip(&layout.offsets).enumerate() {
+        let mut offset = offset;
+        let error = |error| AssemblyError {
+            line: item.line,
+            error,
+        };
+        if let (Some(pool), false) = (layout.pools.get(&index), matches!(item.item, Item::Pool)) {
+            assembly.push_pool(pool, item.line);
+        }
+        match &item.item {
+            Item::Instruction { statement, .. } => {
+                let mut statement = statement.clone();
+                if placement.relaxed.contains(&index) {
+                    let cond = branch_condition(&statement.mnemonic)
+                        .and_then(Condition::inverted)
+                        .unwrap();
+                    let skip = encode(&Operation::B { cond, imm: 0 }).map_err(error)?;
+                    assembly.bytes.extend_from_slice(skip.as_bytes());
+                    statement.mnemonic = "b".to_string();
+                    offset += 2;
+                }
+                let literal = layout.literals.get(&index).copied();
+                let (resolved, relocation) =
+                    resolve_symbols(&statement, offset, literal, |symbol| {
+                        match assembly.labels.get(symbol) {
+                            Some(target) => SymbolValue::Label(*target),
+                            None => SymbolValue::External,
+                        }
+                    })
+                    .map_err(error)?;
+                let encoding =
+                    encode(&build_operation(&resolved).map_err(error)?).map_err(error)?;
+                assembly.bytes.extend_from_slice(encoding.as_bytes());
+                if let Some(relocation) = relocation {
+                    assembly.relocations.push(Relocation {
+                        line: item.line,
+                        ..relocation
+                    });
+                }
+            }
+            Item::Data { values, size } => {
+                for value in values {
+                    assembly.push_value(value, *size, item.line);
+                }
+            }
+            Item::Align(n) => assembly.pad(padding(offset, 1 << n)),
+            Item::Pool => {
+                if let Some(pool) = layout.pools.get(&index) {
+                    assembly.push_pool(pool, item.line);
+                }
+            }
+        }
+    }
+    if let Some(pool) = layout.pools.get(&items.len()) {
+        let line = items.last().map(|item| item.line).unwrap_or(1);
+        assembly.push_pool(pool, line);
+    }
+    Ok(assembly)
+}
+
+impl Assembly {
+    /// Appends a data value, symbols are relocated.
+    fn push_value(&mut self, value: &Value, size: u32, line: usize) {
+        let value = match value {
+            Value::Constant(value) => *value,
+            Value::Symbol(symbol) => {
+                self.relocations.push(Relocation {
+                    offset: self.bytes.len() as u32,
+                    symbol: symbol.clone(),
+                    kind: RelocationKind::Word,
+                    line,
+                });
+                0
+            }
+        };
+        self.bytes
+            .extend_from_slice(&value.to_le_bytes()[..size as usize]);
+    }
+
+    /// Appends a literal pool, with a branch over it if needed.
+    fn push_pool(&mut self, pool: &Pool, line: usize) {
+        let mut offset = pool.offset;
+        if pool.branch {
+            offset += 2;
+            let end = offset + padding(offset, 4) + pool.values.len() as u32 * 4;
+            let branch = Operation::B {
+                cond: Condition::None,
+                imm: end.wrapping_sub(pool.offset + 4),
+            };
+            self.bytes
+                .extend_from_slice(encode(&branch).unwrap().as_bytes());
+        }
+        self.pad(padding(offset, 4));
+        for value in &pool.values {
+            self.push_value(value, 4, line);
+        }
+    }
+
+    /// Pads with a zero byte to halfword alignment and then with `nop`s.
+    fn pad(&mut self, mut padding: u32) {
+        if padding % 2 == 1 {
+            self.bytes.push(0);
+            padding -= 1;
+        }
+        for _ in 0..padding / 2 {
+            self.bytes.extend_from_slice(&[0x00, 0xbf]);
+        }
+    }
+}
+
+impl FromStr for Operation {
+    type Err = Error;
+
+    /// Parses one instruction written in unified assembler language.
+    /// Branch targets are given as the offset stored in the operation, e.g. `b #-4`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        build_operation(&s.parse()?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parse;
+
+    fn decoded(bytes: &[u8]) -> Operation {
+        parse(bytes).unwrap().operation
+    }
+
+    #[test]
+    fn arithmetic() {
+        assert_eq!("adds r0, r1, #4".parse(), Ok(decoded(&[0x08, 0x1d])));
+        assert_eq!("adds r2, #200".parse(), Ok(decoded(&[0xc8, 0x32])));
+        assert_eq!("add r7, sp, #8".parse(), Ok(decoded(&[0x02, 0xaf])));
+        assert_eq!("sub sp, #16".parse(), Ok(decoded(&[0x84, 0xb0])));
+        assert_eq!("add r8, r1".parse(), Ok(decoded(&[0x88, 0x44])));
+        assert_eq!("add sp, r2".parse(), Ok(decoded(&[0x95, 0x44])));
+        assert_eq!("subs r0, r1, r2".parse(), Ok(decoded(&[0x88, 0x1a])));
+        assert_eq!("rsbs r0, r1, #0".parse(), Ok(decoded(&[0x48, 0x42])));
+        assert_eq!("muls r0, r1, r0".parse(), Ok(decoded(&[0x48, 0x43])));
+        assert_eq!("ANDS R0, R1".parse(), Ok(decoded(&[0x08, 0x40])));
+    }
+
+    #[test]
+    fn commutative_operands() {
+        for text in ["adcs r0, r0, r1", "adcs r0, r1, r0"] {
+            let operation: Operation = text.parse().unwrap();
+            let encoding = encode(&operation).unwrap();
+            assert_eq!(encoding.as_bytes(), [0x48, 0x41], "{text}");
+            assert_eq!(decoded(encoding.as_bytes()), operation, "{text}");
+        }
+        assert!(assemble("adcs r0, r0, r1").is_ok());
+    }
+
+    #[test]
+    fn moves_and_shifts() {
+        assert_eq!("movs r0, #0xff".parse(), Ok(decoded(&[0xff, 0x20])));
+        assert_eq!("movs r0, r1".parse(), Ok(decoded(&[0x08, 0x00])));
+        assert_eq!("mov r8, r1".parse(), Ok(decoded(&[0x88, 0x46])));
+        assert_eq!("lsls r0, r1, #3".parse(), Ok(decoded(&[0xc8, 0x00])));
+        assert_eq!("lsrs r0, r1, #32".parse(), Ok(decoded(&[0x08, 0x08])));
+        assert_eq!("rors r0, r1".parse(), Ok(decoded(&[0xc8, 0x41])));
+    }
+
+    #[test]
+    fn memory() {
+        assert_eq!("ldr r0, [r1, #4]".parse(), Ok(decoded(&[0x48, 0x68])));
+        assert_eq!("ldr r0, [sp, #8]".parse(), Ok(decoded(&[0x02, 0x98])));
+        assert_eq!("ldr r0, [pc, #8]".parse(), Ok(decoded(&[0x02, 0x48])));
+        assert_eq!("strb r0, [r1]".parse(), Ok(decoded(&[0x08, 0x70])));
+        assert_eq!("ldrsh r0, [r1, r2]".parse(), Ok(decoded(&[0x88, 0x5e])));
+        assert_eq!("push {r4-r7, lr}".parse(), Ok(decoded(&[0xf0, 0xb5])));
+        assert_eq!("pop {r4, pc}".parse(), Ok(decoded(&[0x10, 0xbd])));
+        assert_eq!("ldm r0!, {r1, r2}".parse(), Ok(decoded(&[0x06, 0xc8])));
+    }
+
+    #[test]
+    fn branches_and_system() {
+        assert_eq!("b #-4".parse(), Ok(decoded(&[0xfe, 0xe7])));
+        assert_eq!("bne #8".parse(), Ok(decoded(&[0x04, 0xd1])));
+        assert_eq!("bhs.n #8".parse(), Ok(decoded(&[0x04, 0xd2])));
+        assert_eq!("bl #0".parse(), Ok(decoded(&[0x00, 0xf0, 0x00, 0xf8])));
+        assert_eq!("bx lr".parse(), Ok(decoded(&[0x70, 0x47])));
+        assert_eq!("svc #1".parse(), Ok(decoded(&[0x01, 0xdf])));
+        assert_eq!("cpsid i".parse(), Ok(decoded(&[0x72, 0xb6])));
+        assert_eq!("dmb sy".parse(), Ok(decoded(&[0xbf, 0xf3, 0x5f, 0x8f])));
+        assert_eq!(
+            "msr primask, r0".parse(),
+            Ok(decoded(&[0x80, 0xf3, 0x10, 0x88]))
+        );
+        assert_eq!("nop".parse(), Ok(decoded(&[0x00, 0xbf])));
+    }
+
+    #[test]
+    fn two_pass() {
+        let assembly = assemble(
+            "start:  movs r0, #0      @ counter
+                     ldr r1, value
+             loop:   adds r0, #1
+                     cmp r0, r1
+                     blt loop
+                     b end
+                     nop
+                     nop
+             value:  nop
+                     nop
+             end:    bl external",
+        )
+        .unwrap();
+        assert_eq!(
+            assembly.bytes,
+            [
+                0x00, 0x20, 0x03, 0x49, 0x40, 0x1c, 0x88, 0x42, 0xfc, 0xdb, 0x03, 0xe0, 0x00, 0xbf,
+                0x00, 0xbf, 0x00, 0xbf, 0x00, 0xbf, 0x00, 0xf0, 0x00, 0xf8
+            ]
+        );
+        assert_eq!(assembly.labels["end"], 20);
+        assert_eq!(
+            assembly.relocations,
+            [Relocation {
+                offset: 20,
+                symbol: "external".to_string(),
+                kind: RelocationKind::BranchLink,
+                line: 11
+            }]
+        );
+        let image = assembly.relocate(0x100, |_| Some(0x100)).unwrap();
+        assert_eq!(
+            parse(&image[20..]).unwrap().operation,
+            Operation::BL { imm: -24i32 as u32 }
+        );
+    }
+
+    #[test]
+    fn assembly_errors() {
+        assert_eq!(
+            assemble("a: nop\na: nop"),
+            Err(AssemblyError {
+                line: 2,
+                error: Error::DuplicateLabel
+            })
+        );
+        assert_eq!(
+            assemble("ldr r0, missing").map(|_| ()),
+            Err(AssemblyError {
+                line: 1,
+                error: Error::UndefinedLabel
+            })
+        );
+        let far = format!("b far\n{}far: nop", "nop\n".repeat(1100));
+        assert_eq!(
+            assemble(&far).map(|_| ()),
+            Err(AssemblyError {
+                line: 1,
+                error: Error::ImmediateOutOfRange
+            })
+        );
+    }
+
+    #[test]
+    fn directives() {
+        let assembly = assemble(
+            "    .syntax unified
+                 .thumb
+                 ldr r0, =0x12345678
+                 ldr r1, =hook
+                 ldr r2, =0x12345678
+                 bx r1
+                 .ltorg
+             table:
+                 .byte 1, 2, 0xff
+                 .align 2
+                 .hword -1
+                 .word table",
+        )
+        .unwrap();
+        assert_eq!(
+            parse(&assembly.bytes[0..2]).unwrap().operation,
+            Operation::LDRLiteral {
+                t: Register::R0,
+                imm: 4
+            }
+        );
+        assert_eq!(
+            parse(&assembly.bytes[4..6]).unwrap().operation,
+            Operation::LDRLiteral {
+                t: Register::R2,
+                imm: 0
+            }
+        );
+        assert_eq!(&assembly.bytes[8..12], &[0x78, 0x56, 0x34, 0x12]);
+        assert_eq!(assembly.labels["table"], 16);
+        assert_eq!(&assembly.bytes[16..20], &[0x01, 0x02, 0xff, 0x00]);
+        assert_eq!(&assembly.bytes[20..22], &[0xff, 0xff]);
+        let image = assembly
+            .relocate(0x1000, |symbol| (symbol == "hook").then_some(0x2001))
+            .unwrap();
+        assert_eq!(&image[12..16], &[0x01, 0x20, 0x00, 0x00]);
+        assert_eq!(&image[22..26], &[0x10, 0x10, 0x00, 0x00]);
+    }
+
+    #[test]
+    fn branch_relaxation() {
+        let source = format!("beq far\nbne.n near\nnear: {}far: nop", "nop\n".repeat(200));
+        let assembly = assemble(&source).unwrap();
+        assert_eq!(
+            parse(&assembly.bytes[0..2]).unwrap().operation,
+            Operation::B {
+                cond: Condition::NE,
+                imm: 0
+            }
+        );
+        assert_eq!(
+            parse(&assembly.bytes[2..4]).unwrap().operation,
+            Operation::B {
+                cond: Condition::None,
+                imm: 400
+            }
+        );
+        assert_eq!(assembly.labels["far"], 406);
+        let narrow = format!("beq.n far\n{}far: nop", "nop\n".repeat(200));
+        assert_eq!(
+            assemble(&narrow).map(|_| ()),
+            Err(AssemblyError {
+                line: 1,
+                error: Error::ImmediateOutOfRange
+            })
+        );
+    }
+
+    #[test]
+    fn literal_pool_scheduling() {
+        // The pool is placed after the `bx lr`, the last barrier in range of the first load.
+        let source = format!(
+            "ldr r0, =0x11111111\n{}bx lr\n{}bx lr\nldr r1, =0x22222222",
+            "nop\n".repeat(100),
+            "nop\n".repeat(500)
+        );
+        let assembly = assemble(&source).unwrap();
+        assert_eq!(&assembly.bytes[204..208], &[0x11, 0x11, 0x11, 0x11]);
+        assert_eq!(
+            parse(&assembly.bytes[0..2]).unwrap().operation,
+            Operation::LDRLiteral {
+                t: Register::R0,
+                imm: 200
+            }
+        );
+        assert_eq!(assembly.bytes.len(), 1216);
+
+        // Without a barrier in range the pool is branched over.
+        let source = format!("ldr r0, =0x11111111\n{}", "nop\n".repeat(600));
+        let assembly = assemble(&source).unwrap();
+        let branch = assembly
+            .bytes
+            .chunks(2)
+            .position(|halfword| halfword[1] & 0xf8 == 0xe0)
+            .unwrap();
+        let Operation::B { imm, .. } = parse(&assembly.bytes[branch * 2..]).unwrap().operation
+        else {
+            panic!()
+        };
+        let literal = branch * 2 + imm as usize;
+        assert_eq!(
+            &assembly.bytes[literal..literal + 4],
+            &[0x11, 0x11, 0x11, 0x11]
+        );
+        assert_eq!(
+            parse(&assembly.bytes[literal + 4..]).unwrap().operation,
+            Operation::NOP
+        );
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!("foo r0".parse::<Operation>(), Err(Error::UnknownMnemonic));
+        assert_eq!("adds r0".parse::<Operation>(), Err(Error::InvalidOperands));
+        assert_eq!(
+            "ands r0, r1, r2".parse::<Operation>(),
+            Err(Error::InvalidOperands)
+        );
+        assert_eq!(
+            "movs r16, #1".parse::<Operation>(),
+            Err(Error::InvalidRegister)
+        );
+        assert_eq!(
+            "ldr r0, [r1".parse::<Operation>(),
+            Err(Error::InvalidSyntax)
+        );
+        assert_eq!("bxx #2".parse::<Operation>(), Err(Error::UnknownMnemonic));
+    }
+}
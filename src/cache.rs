@@ -0,0 +1,158 @@
+//! Interning of decoded instructions by their encoding, for storing disassemblies of large
+//! images where a few encodings make up most of the code.
+//!
+//! # Example
+//! ```
+//! # use armv6_m_instruction_parser::cache::InstructionCache;
+//! // movs r0, #1; bx lr, repeated
+//! let image: Vec<u8> = [0x01, 0x20, 0x70, 0x47].repeat(1000);
+//! let mut cache = InstructionCache::new();
+//! let listing: Vec<_> = cache.intern_all(&image).collect();
+//! assert_eq!(listing.len(), 2000);
+//! assert_eq!(cache.len(), 2);
+//! let (_, id) = listing[1];
+//! assert!(cache[id.unwrap()].is_16bit());
+//! ```
+
+use std::{collections::HashMap, ops::Index, sync::Arc};
+
+use crate::{instruction_size, instructons::Instruction, parse, Error};
+
+/// Index of an instruction in an [`InstructionCache`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct InstructionId(u32);
+
+impl InstructionId {
+    /// Position of the instruction in the order it was first interned.
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// Cache of decoded instructions keyed by their encoding.
+///
+/// Every encoding is decoded once, later occurrences return the same [`InstructionId`], four
+/// bytes instead of a whole [`Instruction`], or share the same [`Arc`]. Encodings that fail
+/// to decode are not cached.
+#[derive(Debug, Clone, Default)]
+pub struct InstructionCache {
+    instructions: Vec<Arc<Instruction>>,
+    ids: HashMap<u32, InstructionId>,
+}
+
+impl InstructionCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Decodes the instruction at the start of `input`, returning its id.
+    pub fn intern(&mut self, input: &[u8]) -> Result<InstructionId, Error> {
+        let key = match input {
+            [first, second, rest @ ..] => {
+                let halfword = u16::from_le_bytes([*first, *second]) as u32;
+                match rest {
+                    [third, fourth, ..] if instruction_size(halfword as u16) == 4 => {
+                        halfword << 16 | u16::from_le_bytes([*third, *fourth]) as u32
+                    }
+                    _ => halfword,
+                }
+            }
+            _ => return Err(Error::InsufficientInput),
+        };
+        if let Some(id) = self.ids.get(&key) {
+            return Ok(*id);
+        }
+        let instruction = parse(input)?;
+        let id = InstructionId(self.instructions.len() as u32);
+        self.instructions.push(Arc::new(instruction));
+        self.ids.insert(key, id);
+        Ok(id)
+    }
+
+    /// Decodes the instruction at the start of `input`, sharing it with earlier occurrences
+    /// of the same encoding.
+    pub fn decode(&mut self, input: &[u8]) -> Result<Arc<Instruction>, Error> {
+        let id = self.intern(input)?;
+        Ok(Arc::clone(&self.instructions[id.index()]))
+    }
+
+    /// Interns every instruction of `input`, yielding the same offsets as
+    /// [`crate::disassemble`].
+    pub fn intern_all<'a>(
+        &'a mut self,
+        input: &'a [u8],
+    ) -> impl Iterator<Item = (usize, Result<InstructionId, Error>)> + 'a {
+        let mut offset = 0;
+        core::iter::from_fn(move || {
+            let rest = &input[offset..];
+            let size = match rest {
+                [] => return None,
+                [first, second, ..] => {
+                    instruction_size(u16::from_le_bytes([*first, *second])).min(rest.len())
+                }
+                _ => rest.len(),
+            };
+            let start = offset;
+            offset += size;
+            Some((start, self.intern(rest)))
+        })
+    }
+
+    /// Returns the instruction with `id`, `None` if it is from another cache.
+    pub fn get(&self, id: InstructionId) -> Option<&Arc<Instruction>> {
+        self.instructions.get(id.index())
+    }
+
+    /// Number of distinct instructions.
+    pub fn len(&self) -> usize {
+        self.instructions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.instructions.is_empty()
+    }
+}
+
+impl Index<InstructionId> for InstructionCache {
+    type Output = Instruction;
+
+    fn index(&self, id: InstructionId) -> &Instruction {
+        &self.instructions[id.index()]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn interning() {
+        let mut cache = InstructionCache::new();
+        // bl; movs r0, #1; bl; invalid 32 bit
+        let input = [
+            0x00, 0xf0, 0x7e, 0xf8, 0x01, 0x20, 0x00, 0xf0, 0x7e, 0xf8, 0xff, 0xf7, 0x00, 0x00,
+        ];
+        let listing: Vec<_> = cache.intern_all(&input).collect();
+        assert_eq!(listing.len(), 4);
+        assert_eq!(listing[0], (0, Ok(InstructionId(0))));
+        assert_eq!(listing[2], (6, Ok(InstructionId(0))));
+        assert_eq!(listing[3].0, 10);
+        assert!(listing[3].1.is_err());
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache[InstructionId(1)], parse(&input[4..6]).unwrap());
+
+        let first = cache.decode(&input).unwrap();
+        let second = cache.decode(&input[6..]).unwrap();
+        assert!(Arc::ptr_eq(&first, &second));
+        // A 16 bit instruction is keyed by its halfword alone.
+        assert_eq!(
+            cache.intern(&[0x01, 0x20, 0x00, 0xf0]),
+            Ok(InstructionId(1))
+        );
+        assert_eq!(
+            cache.intern(&[0x00, 0xf0]),
+            Err(Error::Malfromed32BitInstruction)
+        );
+        assert_eq!(cache.get(InstructionId(5)), None);
+    }
+}
@@ -0,0 +1,272 @@
+//! Call graphs of the functions of an image.
+//!
+//! # Example
+//! ```
+//! # use armv6_m_instruction_parser::{call_graph::CallGraph, functions::functions, program::Program};
+//! // 0x00: push {r4, lr}; bl 0x08; pop {r4, pc}
+//! // 0x08: movs r0, #1; bx lr
+//! let code = [
+//!     0x10, 0xb5, 0x00, 0xf0, 0x01, 0xf8, 0x10, 0xbd, 0x01, 0x20, 0x70, 0x47,
+//! ];
+//! let program = Program::new(&code, 0);
+//! let graph = CallGraph::new(&program, &functions(&program));
+//! assert_eq!(graph.callees(0x00), [0x08]);
+//! assert_eq!(graph.callers(0x08), [0x00]);
+//! ```
+
+use alloc::{
+    collections::BTreeSet,
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::fmt::Write;
+
+use crate::{
+    functions::FunctionRange,
+    instructons::{BranchTarget, Operation},
+    program::Program,
+    registers::Register,
+    symbols::SymbolTable,
+};
+
+/// Destination of a call.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum CallTarget {
+    /// `bl` to an address.
+    Direct(u32),
+    /// `blx` to the address in a register.
+    Indirect(Register),
+}
+
+/// Call instruction in a [`CallGraph`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Call {
+    /// Address of the call instruction.
+    pub site: u32,
+    /// Start of the function containing the call, `None` if it is outside all functions.
+    pub caller: Option<u32>,
+    pub target: CallTarget,
+}
+
+/// Calls between functions, found from the `bl` and `blx` instructions of a program.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CallGraph {
+    functions: Vec<FunctionRange>,
+    calls: Vec<Call>,
+}
+
+impl CallGraph {
+    /// Builds the call graph of `program` with its `functions`, e.g. from
+    /// [`crate::functions::functions`].
+    pub fn new(program: &Program, functions: &[FunctionRange]) -> Self {
+        let mut functions = functions.to_vec();
+        functions.sort_by_key(|function| function.start);
+        let caller = |site: u32| {
+            let index = functions.partition_point(|function| function.start <= site);
+            let function = functions.get(index.checked_sub(1)?)?;
+            (site < function.end).then_some(function.start)
+        };
+
+        let mut calls = Vec::new();
+        for (site, result) in program {
+            let Ok(instruction) = result else {
+                continue;
+            };
+            let target = match (
+                &instruction.operation,
+                instruction.operation.branch_target(site),
+            ) {
+                (Operation::BL { .. }, BranchTarget::Direct(target)) => CallTarget::Direct(target),
+                (Operation::BLXReg { m }, _) => CallTarget::Indirect(*m),
+                _ => continue,
+            };
+            calls.push(Call {
+                site,
+                caller: caller(site),
+                target,
+            });
+        }
+        Self { functions, calls }
+    }
+
+    /// Functions of the graph in address order.
+    pub fn functions(&self) -> &[FunctionRange] {
+        &self.functions
+    }
+
+    /// All calls in address order.
+    pub fn calls(&self) -> &[Call] {
+        &self.calls
+    }
+
+    /// Returns the addresses called directly from the function starting at `function`, in
+    /// address order.
+    pub fn callees(&self, function: u32) -> Vec<u32> {
+        let callees: BTreeSet<u32> = self
+            .calls
+            .iter()
+            .filter(|call| call.caller == Some(function))
+            .filter_map(|call| match call.target {
+                CallTarget::Direct(target) => Some(target),
+                CallTarget::Indirect(_) => None,
+            })
+            .collect();
+        callees.into_iter().collect()
+    }
+
+    /// Returns the starts of the functions calling `function` directly, in address order.
+    pub fn callers(&self, function: u32) -> Vec<u32> {
+        let callers: BTreeSet<u32> = self
+            .calls
+            .iter()
+            .filter(|call| call.target == CallTarget::Direct(function))
+            .filter_map(|call| call.caller)
+            .collect();
+        callers.into_iter().collect()
+    }
+
+    /// Returns the indirect calls made from the function starting at `function`.
+    pub fn indirect_calls(&self, function: u32) -> impl Iterator<Item = &Call> {
+        self.calls.iter().filter(move |call| {
+            call.caller == Some(function) && matches!(call.target, CallTarget::Indirect(_))
+        })
+    }
+
+    /// Returns the graph in the DOT language of Graphviz.
+    ///
+    /// Nodes are named by address, indirect calls are dashed edges to an `indirect` node
+    /// labeled with the register.
+    pub fn to_dot(&self) -> String {
+        self.dot(|address| format!("{address:#010x}"))
+    }
+
+    /// Returns the graph in the DOT language like [`CallGraph::to_dot`], with the nodes named
+    /// by [`SymbolTable::label`].
+    pub fn to_dot_with_symbols(&self, symbols: &SymbolTable) -> String {
+        self.dot(|address| symbols.label(address))
+    }
+
+    fn dot(&self, name: impl Fn(u32) -> String) -> String {
+        let mut dot = String::from("digraph calls {\n");
+        for function in &self.functions {
+            let _ = writeln!(dot, "    \"{}\";", name(function.start));
+        }
+        let mut edges = BTreeSet::new();
+        for call in &self.calls {
+            let caller = call.caller.map_or("unknown".to_string(), &name);
+            edges.insert(match call.target {
+                CallTarget::Direct(target) => {
+                    format!("    \"{caller}\" -> \"{}\";", name(target))
+                }
+                CallTarget::Indirect(register) => format!(
+                    "    \"{caller}\" -> \"indirect\" [style=dashed, label=\"{}\"];",
+                    register_name(register)
+                ),
+            });
+        }
+        for edge in edges {
+            dot.push_str(&edge);
+            dot.push('\n');
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// Returns the graph as JSON, with the functions and calls and addresses as numbers.
+    ///
+    /// ```json
+    /// {"functions":[{"start":0,"end":8}],"calls":[{"site":2,"caller":0,"target":8},
+    /// {"site":4,"caller":0,"indirect":"r3"}]}
+    /// ```
+    pub fn to_json(&self) -> String {
+        let functions: Vec<String> = self
+            .functions
+            .iter()
+            .map(|function| format!("{{\"start\":{},\"end\":{}}}", function.start, function.end))
+            .collect();
+        let calls: Vec<String> = self
+            .calls
+            .iter()
+            .map(|call| {
+                let caller = call
+                    .caller
+                    .map_or("null".to_string(), |caller| caller.to_string());
+                let target = match call.target {
+                    CallTarget::Direct(target) => format!("\"target\":{target}"),
+                    CallTarget::Indirect(register) => {
+                        format!("\"indirect\":\"{}\"", register_name(register))
+                    }
+                };
+                format!("{{\"site\":{},\"caller\":{caller},{target}}}", call.site)
+            })
+            .collect();
+        format!(
+            "{{\"functions\":[{}],\"calls\":[{}]}}",
+            functions.join(","),
+            calls.join(",")
+        )
+    }
+}
+
+fn register_name(register: Register) -> String {
+    format!("{register:?}").to_lowercase()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{functions::functions, symbols::Symbol};
+
+    #[test]
+    fn calls() {
+        // 0x00: push {r4, lr}; bl 0x0c; blx r3; pop {r4, pc}; nop
+        // 0x0c: push {r4, lr}; bl 0x0c; pop {r4, pc}
+        let code = [
+            0x10, 0xb5, 0x00, 0xf0, 0x03, 0xf8, 0x98, 0x47, 0x10, 0xbd, 0x00, 0xbf, //
+            0x10, 0xb5, 0xff, 0xf7, 0xfd, 0xff, 0x10, 0xbd,
+        ];
+        let program = Program::new(&code, 0);
+        let graph = CallGraph::new(&program, &functions(&program));
+        assert_eq!(graph.functions().len(), 2);
+        assert_eq!(graph.callees(0x00), [0x0c]);
+        assert_eq!(graph.callers(0x0c), [0x00, 0x0c]);
+        let indirect: Vec<_> = graph.indirect_calls(0x00).collect();
+        assert_eq!(
+            indirect,
+            [&Call {
+                site: 0x06,
+                caller: Some(0x00),
+                target: CallTarget::Indirect(Register::R3)
+            }]
+        );
+
+        assert_eq!(
+            graph.to_dot(),
+            "digraph calls {\n    \"0x00000000\";\n    \"0x0000000c\";\n    \
+             \"0x00000000\" -> \"0x0000000c\";\n    \
+             \"0x00000000\" -> \"indirect\" [style=dashed, label=\"r3\"];\n    \
+             \"0x0000000c\" -> \"0x0000000c\";\n}\n"
+        );
+        let symbols = [Symbol {
+            name: "main".into(),
+            address: 0,
+            size: 0x0c,
+        }]
+        .into_iter()
+        .collect();
+        assert!(graph
+            .to_dot_with_symbols(&symbols)
+            .contains("    \"main\" -> \"0x0000000c\";\n"));
+        assert_eq!(
+            graph.to_json(),
+            "{\"functions\":[{\"start\":0,\"end\":10},{\"start\":12,\"end\":20}],\
+             \"calls\":[{\"site\":2,\"caller\":0,\"target\":12},\
+             {\"site\":6,\"caller\":0,\"indirect\":\"r3\"},\
+             {\"site\":14,\"caller\":12,\"target\":12}]}"
+        );
+    }
+}
@@ -0,0 +1,176 @@
+//! Conversions to and from the types of Capstone, and decoding with both to compare them, for
+//! projects moving from Capstone or checking one decoder against the other.
+//!
+//! # Example
+//! ```
+//! # use armv6_m_instruction_parser::{capstone::{compare, thumb}, instructons::Instruction};
+//! let cs = thumb().unwrap();
+//! // movs r0, #1; bl 0x1100; bx lr
+//! let code = [0x01, 0x20, 0x00, 0xf0, 0x7e, 0xf8, 0x70, 0x47];
+//! let insns = cs.disasm_all(&code, 0x1000).unwrap();
+//! let instruction = Instruction::try_from(&insns[1]).unwrap();
+//! assert!(instruction.is_32bit());
+//! assert_eq!(compare(&cs, &code, 0x1000), []);
+//! ```
+
+use ::capstone::{
+    arch::{
+        arm::{ArchExtraMode, ArchMode, ArmReg},
+        BuildsCapstone, BuildsCapstoneExtraMode,
+    },
+    Capstone, CsResult, Insn, RegId,
+};
+
+use crate::{instructons::Instruction, parse, registers::Register, Error};
+
+/// Returns a Capstone instance decoding Thumb for M-profile processors, like this crate.
+///
+/// Capstone decodes the ARMv7-M instructions too, which this crate rejects.
+pub fn thumb() -> CsResult<Capstone> {
+    Capstone::new()
+        .arm()
+        .mode(ArchMode::Thumb)
+        .extra_mode([ArchExtraMode::MClass].into_iter())
+        .build()
+}
+
+impl From<Register> for RegId {
+    fn from(register: Register) -> Self {
+        let id = match register {
+            Register::SP => ArmReg::ARM_REG_SP,
+            Register::LR => ArmReg::ARM_REG_LR,
+            Register::PC => ArmReg::ARM_REG_PC,
+            register => ArmReg::ARM_REG_R0 + register as u32,
+        };
+        RegId(id as u16)
+    }
+}
+
+impl TryFrom<RegId> for Register {
+    type Error = Error;
+
+    /// Converts the core registers, returning [`Error::InvalidRegister`] for others like
+    /// the floating point registers.
+    fn try_from(id: RegId) -> Result<Self, Self::Error> {
+        match id.0 as u32 {
+            ArmReg::ARM_REG_SP => Ok(Register::SP),
+            ArmReg::ARM_REG_LR => Ok(Register::LR),
+            ArmReg::ARM_REG_PC => Ok(Register::PC),
+            id @ ArmReg::ARM_REG_R0..=ArmReg::ARM_REG_R12 => {
+                Register::try_from((id - ArmReg::ARM_REG_R0) as u8)
+            }
+            _ => Err(Error::InvalidRegister),
+        }
+    }
+}
+
+/// Decodes the bytes of an instruction decoded by Capstone.
+impl TryFrom<&Insn<'_>> for Instruction {
+    type Error = Error;
+
+    fn try_from(insn: &Insn<'_>) -> Result<Self, Self::Error> {
+        parse(insn.bytes())
+    }
+}
+
+/// Difference between this crate and Capstone found by [`compare`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Mismatch {
+    /// Instruction Capstone decodes but this crate does not.
+    Rejected {
+        address: u32,
+        error: Error,
+        /// Capstone's text of the instruction, like `ldr.w r0, [r1]`.
+        capstone: String,
+    },
+    /// Instruction this crate decodes but Capstone does not.
+    Accepted {
+        address: u32,
+        instruction: Instruction,
+    },
+    /// Instruction decoded with different sizes in bytes.
+    Size {
+        address: u32,
+        size: u32,
+        capstone: u32,
+    },
+}
+
+/// Decodes `code` placed at `address` with this crate and with `cs`, returning where they
+/// disagree on whether an instruction is valid or on its size.
+///
+/// Decoding continues after the instruction as decoded by this crate, by Capstone if only
+/// it decodes it, or after a halfword if neither does.
+pub fn compare(cs: &Capstone, code: &[u8], address: u32) -> Vec<Mismatch> {
+    let mut mismatches = Vec::new();
+    let mut offset = 0;
+    while offset + 2 <= code.len() {
+        let here = address.wrapping_add(offset as u32);
+        let ours = parse(&code[offset..]);
+        let insns = cs.disasm_count(&code[offset..], here as u64, 1);
+        let theirs = insns.as_ref().ok().and_then(|insns| insns.first());
+        let size = match (ours, theirs) {
+            (Ok(instruction), Some(insn)) => {
+                let capstone = insn.bytes().len() as u32;
+                if instruction.size() != capstone {
+                    mismatches.push(Mismatch::Size {
+                        address: here,
+                        size: instruction.size(),
+                        capstone,
+                    });
+                }
+                instruction.size()
+            }
+            (Err(error), Some(insn)) => {
+                let text = match insn.op_str() {
+                    Some(operands) if !operands.is_empty() => {
+                        format!("{} {operands}", insn.mnemonic().unwrap_or_default())
+                    }
+                    _ => insn.mnemonic().unwrap_or_default().to_string(),
+                };
+                mismatches.push(Mismatch::Rejected {
+                    address: here,
+                    error,
+                    capstone: text,
+                });
+                insn.bytes().len() as u32
+            }
+            (Ok(instruction), None) => {
+                let size = instruction.size();
+                mismatches.push(Mismatch::Accepted {
+                    address: here,
+                    instruction,
+                });
+                size
+            }
+            (Err(_), None) => 2,
+        };
+        offset += size as usize;
+    }
+    mismatches
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn registers_and_mismatches() {
+        for number in 0..16 {
+            let register = Register::try_from(number).unwrap();
+            assert_eq!(Register::try_from(RegId::from(register)), Ok(register));
+        }
+        assert_eq!(
+            Register::try_from(RegId(ArmReg::ARM_REG_S0 as u16)),
+            Err(Error::InvalidRegister)
+        );
+
+        // movs r0, #1; ldr.w r0, [r1]; bx lr
+        let code = [0x01, 0x20, 0xd1, 0xf8, 0x00, 0x00, 0x70, 0x47];
+        let mismatches = compare(&thumb().unwrap(), &code, 0x100);
+        assert!(matches!(
+            mismatches.as_slice(),
+            [Mismatch::Rejected { address: 0x102, capstone, .. }] if capstone == "ldr.w r0, [r1]"
+        ));
+    }
+}
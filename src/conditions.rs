@@ -1,6 +1,9 @@
-use crate::Error;
+use core::str::FromStr;
 
-#[derive(Debug, PartialEq)]
+use crate::{ascii_lowercase, Error};
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(u8)]
 pub enum Condition {
     EQ = 0,
@@ -45,6 +48,69 @@ impl TryFrom<u8> for Condition {
     }
 }
 
+impl Condition {
+    /// Returns the condition that holds when this one does not.
+    /// [`Condition::None`] (always) has no inverse.
+    pub fn inverted(self) -> Option<Condition> {
+        match self {
+            Condition::None => None,
+            // Conditions are paired with their inverse differing in the lowest bit.
+            condition => Some((condition as u8 ^ 0b1).try_into().unwrap()),
+        }
+    }
+}
+
+impl FromStr for Condition {
+    type Err = Error;
+
+    /// Parses a condition suffix such as `eq` or `hs`, ignoring case.
+    /// `al` is parsed as [`Condition::None`].
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut buffer = [0; 8];
+        match ascii_lowercase(s, &mut buffer).ok_or(Error::InvalidCondition)? {
+            "eq" => Ok(Condition::EQ),
+            "ne" => Ok(Condition::NE),
+            "cs" | "hs" => Ok(Condition::CS),
+            "cc" | "lo" => Ok(Condition::CC),
+            "mi" => Ok(Condition::MI),
+            "pl" => Ok(Condition::PL),
+            "vs" => Ok(Condition::VS),
+            "vc" => Ok(Condition::VC),
+            "hi" => Ok(Condition::HI),
+            "ls" => Ok(Condition::LS),
+            "ge" => Ok(Condition::GE),
+            "lt" => Ok(Condition::LT),
+            "gt" => Ok(Condition::GT),
+            "le" => Ok(Condition::LE),
+            "al" => Ok(Condition::None),
+            _ => Err(Error::InvalidCondition),
+        }
+    }
+}
+
+impl core::fmt::Display for Condition {
+    /// Writes the condition suffix, like `eq`, or `al` for [`Condition::None`].
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(match self {
+            Condition::EQ => "eq",
+            Condition::NE => "ne",
+            Condition::CS => "cs",
+            Condition::CC => "cc",
+            Condition::MI => "mi",
+            Condition::PL => "pl",
+            Condition::VS => "vs",
+            Condition::VC => "vc",
+            Condition::HI => "hi",
+            Condition::LS => "ls",
+            Condition::GE => "ge",
+            Condition::LT => "lt",
+            Condition::GT => "gt",
+            Condition::LE => "le",
+            Condition::None => "al",
+        })
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -61,4 +127,20 @@ mod tests {
             Err::<Condition, Error>(Error::InvalidCondition)
         )
     }
+
+    #[test]
+    fn inverted_condition() {
+        assert_eq!(Condition::EQ.inverted(), Some(Condition::NE));
+        assert_eq!(Condition::LT.inverted(), Some(Condition::GE));
+        assert_eq!(Condition::None.inverted(), None);
+    }
+
+    #[test]
+    fn condition_from_str() {
+        assert_eq!("eq".parse(), Ok(Condition::EQ));
+        assert_eq!("HS".parse(), Ok(Condition::CS));
+        assert_eq!("lo".parse(), Ok(Condition::CC));
+        assert_eq!("al".parse(), Ok(Condition::None));
+        assert_eq!("xx".parse::<Condition>(), Err(Error::InvalidCondition));
+    }
 }
@@ -0,0 +1,233 @@
+//! Materialization of 32 bit constants in registers, for generating code.
+//!
+//! # Example
+//! ```
+//! # use armv6_m_instruction_parser::{constants::materialize, registers::Register};
+//! // movs r0, #0xff; lsls r0, r0, #24
+//! let constant = materialize(0xff00_0000, Register::R0, 0x1000).unwrap();
+//! assert_eq!(constant.bytes, [0xff, 0x20, 0x00, 0x06]);
+//! assert_eq!(constant.cycles, 2);
+//! ```
+
+use alloc::{vec, vec::Vec};
+
+use crate::{
+    conditions::Condition, encoder::encode, instructons::Operation, registers::Register, Error,
+};
+
+/// Instructions loading a constant into a register.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Constant {
+    /// The encoded instructions, for a literal load followed by the literal.
+    pub bytes: Vec<u8>,
+    /// Estimated number of cycles to execute the instructions on a Cortex-M0.
+    pub cycles: u32,
+}
+
+/// Cycles of a literal load, 2 for the `ldr` and 3 for the taken branch over the literal.
+const LITERAL_CYCLES: u32 = 5;
+
+/// Longest sequence of single cycle instructions that is faster than a literal load.
+const MAX_SEQUENCE: usize = LITERAL_CYCLES as usize - 1;
+
+/// Last instruction of a sequence computing a constant.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Step {
+    Mov(u32),
+    Shift(u32),
+    Add(u32),
+    Sub(u32),
+    Not,
+    Negate,
+}
+
+/// Returns the fastest instructions that load `value` into the low register `d`, when
+/// placed at `address`.
+///
+/// Constants are built with `movs`, `lsls`, `adds`, `subs`, `mvns` and `rsbs` when that takes
+/// fewer cycles than a literal load, otherwise as `ldr d, [pc, #imm]` followed by a branch over
+/// the literal. Ties are broken by the size of the code. The instruction sequences update the
+/// condition flags, the literal load does not.
+pub fn materialize(value: u32, d: Register, address: u32) -> Result<Constant, Error> {
+    let Some(operations) = instruction_sequence(value, d) else {
+        return literal(value, d, address);
+    };
+    let mut bytes = vec![];
+    for operation in &operations {
+        bytes.extend_from_slice(encode(operation)?.as_bytes());
+    }
+    Ok(Constant {
+        bytes,
+        cycles: operations.len() as u32,
+    })
+}
+
+/// Returns the shortest sequence of single cycle instructions computing `value` in `d`, if
+/// one is faster than a literal load.
+pub(crate) fn instruction_sequence(value: u32, d: Register) -> Option<Vec<Operation>> {
+    let steps = (1..=MAX_SEQUENCE).find_map(|length| {
+        let mut steps = Vec::with_capacity(length);
+        search(value, length, None, &mut steps).then_some(steps)
+    })?;
+    let operations = steps
+        .iter()
+        .rev()
+        .map(|step| match *step {
+            Step::Mov(imm) => Operation::MOVImm { d, imm },
+            Step::Shift(imm) => Operation::LSLImm { imm, m: d, d },
+            Step::Add(imm) => Operation::ADDImm { imm, n: d, d },
+            Step::Sub(imm) => Operation::SUBImm { imm, n: d, d },
+            Step::Not => Operation::MVNReg { m: d, d },
+            Step::Negate => Operation::RSBImm { n: d, d },
+        })
+        .collect();
+    Some(operations)
+}
+
+/// Searches a sequence of exactly `length` instructions computing `value`, pushing the
+/// instructions in reverse order to `steps`. `next` is the instruction after the sequence,
+/// used to skip pointless combinations.
+fn search(value: u32, length: usize, next: Option<Step>, steps: &mut Vec<Step>) -> bool {
+    let mut try_step = |step: Step, previous: u32| {
+        steps.push(step);
+        if search(previous, length - 1, Some(step), steps) {
+            return true;
+        }
+        steps.pop();
+        false
+    };
+
+    if length == 1 {
+        if value <= 0xff {
+            steps.push(Step::Mov(value));
+            return true;
+        }
+        return false;
+    }
+
+    if !matches!(next, Some(Step::Not)) && try_step(Step::Not, !value) {
+        return true;
+    }
+    if !matches!(next, Some(Step::Negate)) && try_step(Step::Negate, value.wrapping_neg()) {
+        return true;
+    }
+    if !matches!(next, Some(Step::Shift(_))) {
+        for shift in (1..=value.trailing_zeros().min(31)).rev() {
+            if try_step(Step::Shift(shift), value >> shift) {
+                return true;
+            }
+        }
+    }
+    if !matches!(next, Some(Step::Add(_) | Step::Sub(_))) {
+        // Only the immediates clearing the low bits or leaving a small value are useful.
+        let mut candidates = [0; 18];
+        for bits in 1..=8 {
+            let low = value & ((1 << bits) - 1);
+            candidates[2 * bits - 2] = low;
+            candidates[2 * bits - 1] = ((1 << bits) - low) & 0xff;
+        }
+        candidates[16] = value.wrapping_sub(0xff);
+        candidates[17] = 0xffu32.wrapping_sub(value);
+        for imm in candidates {
+            if imm == 0 || imm > 0xff {
+                continue;
+            }
+            if try_step(Step::Add(imm), value.wrapping_sub(imm))
+                || try_step(Step::Sub(imm), value.wrapping_add(imm))
+            {
+                return true;
+            }
+        }
+    }
+    false
+}
+
+/// Loads `value` from a literal placed right after the load, with a branch over it.
+fn literal(value: u32, d: Register, address: u32) -> Result<Constant, Error> {
+    let pc = address.wrapping_add(4) & !0b11;
+    let literal = (address.wrapping_add(4) + 3) & !0b11;
+    let padding = literal - address.wrapping_add(4);
+    let load = encode(&Operation::LDRLiteral {
+        t: d,
+        imm: literal - pc,
+    })?;
+    let branch = encode(&Operation::B {
+        cond: Condition::None,
+        imm: padding + 4 - 2,
+    })?;
+
+    let mut bytes = vec![];
+    bytes.extend_from_slice(load.as_bytes());
+    bytes.extend_from_slice(branch.as_bytes());
+    bytes.resize(bytes.len() + padding as usize, 0);
+    bytes.extend_from_slice(&value.to_le_bytes());
+    Ok(Constant {
+        bytes,
+        cycles: LITERAL_CYCLES,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parse;
+
+    /// Executes the instructions of a materialized constant and returns the register value.
+    fn execute(constant: &Constant) -> u32 {
+        let mut value = 0u32;
+        for halfword in constant.bytes.chunks(2) {
+            value = match parse(halfword).unwrap().operation {
+                Operation::MOVImm { imm, .. } => imm,
+                Operation::LSLImm { imm, .. } => value << imm,
+                Operation::ADDImm { imm, .. } => value.wrapping_add(imm),
+                Operation::SUBImm { imm, .. } => value.wrapping_sub(imm),
+                Operation::MVNReg { .. } => !value,
+                Operation::RSBImm { .. } => value.wrapping_neg(),
+                operation => panic!("unexpected {operation:?}"),
+            };
+        }
+        value
+    }
+
+    #[test]
+    fn instruction_sequences() {
+        let expected = [
+            (0, 1),
+            (0xff, 1),
+            (0x100, 2),
+            (0xffff_ffff, 2),
+            (0xffff_ff00, 2),
+            (0x1fe, 2),
+            (0x8000_0000, 2),
+            (0x1234, 3),
+            (0xffff_0000, 3),
+            (0xff_ff00, 4),
+            (0x1_2300, 3),
+            (0x12_3400, 4),
+        ];
+        for (value, cycles) in expected {
+            let constant = materialize(value, Register::R3, 0).unwrap();
+            assert_eq!(constant.cycles, cycles, "{value:#x}");
+            assert_eq!(constant.bytes.len() as u32, cycles * 2, "{value:#x}");
+            assert_eq!(execute(&constant), value, "{value:#x}");
+        }
+    }
+
+    #[test]
+    fn literal_loads() {
+        let aligned = materialize(0x1234_5678, Register::R1, 0x1000).unwrap();
+        assert_eq!(aligned.cycles, 5);
+        assert_eq!(
+            aligned.bytes,
+            [0x00, 0x49, 0x01, 0xe0, 0x78, 0x56, 0x34, 0x12]
+        );
+
+        let unaligned = materialize(0x1234_5678, Register::R1, 0x1002).unwrap();
+        assert_eq!(
+            unaligned.bytes,
+            [0x01, 0x49, 0x02, 0xe0, 0x00, 0x00, 0x78, 0x56, 0x34, 0x12]
+        );
+
+        assert_eq!(materialize(1, Register::R8, 0), Err(Error::InvalidRegister));
+    }
+}
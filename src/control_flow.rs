@@ -0,0 +1,482 @@
+//! Control flow analysis of decoded instructions.
+//!
+//! # Example
+//! ```
+//! # use armv6_m_instruction_parser::{control_flow::leaders, ThumbInstructions};
+//! // cmp r0, #0; beq.n +2; movs r0, #1; bx lr
+//! let code = [0x00, 0x28, 0x00, 0xd0, 0x01, 0x20, 0x70, 0x47];
+//! let leaders = leaders(code.thumb_instructions_at(0x1000));
+//! assert_eq!(leaders.into_iter().collect::<Vec<_>>(), [0x1000, 0x1004, 0x1006]);
+//! ```
+
+use alloc::{collections::BTreeSet, vec, vec::Vec};
+use core::{borrow::Borrow, ops::Range};
+
+use crate::{
+    conditions::Condition,
+    instructons::{AccessSize, BranchTarget, Instruction, Operation},
+    program::Program,
+    registers::Register,
+    Error,
+};
+
+/// Number of instructions before a jump searched for the code reading a jump table.
+const JUMP_TABLE_WINDOW: usize = 8;
+
+/// Returns the addresses starting basic blocks in `instructions`, given in address order
+/// with their addresses like [`crate::ThumbInstructions::thumb_instructions_at`] or
+/// [`Program::iter`] yields them.
+///
+/// Leaders are the first instruction, the instructions after ones writing `pc` other than
+/// calls, after instructions that failed to decode, and the destinations of `b` and `bl`
+/// within the instructions. Calls do not end blocks, as they return to the next instruction.
+pub fn leaders<R>(instructions: impl IntoIterator<Item = (u32, R)>) -> BTreeSet<u32>
+where
+    R: Borrow<Result<Instruction, Error>>,
+{
+    let mut leaders = BTreeSet::new();
+    let mut targets = vec![];
+    let mut range: Option<Range<u32>> = None;
+    let mut next_is_leader = true;
+    for (address, result) in instructions {
+        if next_is_leader {
+            leaders.insert(address);
+        }
+        let Ok(instruction) = result.borrow() else {
+            range.get_or_insert(address..address).end = address.wrapping_add(2);
+            next_is_leader = true;
+            continue;
+        };
+        range.get_or_insert(address..address).end = address.wrapping_add(instruction.size());
+
+        let operation = &instruction.operation;
+        if let BranchTarget::Direct(target) = operation.branch_target(address) {
+            if operation.is_branch() {
+                targets.push(target);
+            }
+        }
+        next_is_leader = operation.modifies_pc() && !operation.is_call();
+    }
+    if let Some(range) = range {
+        leaders.extend(targets.into_iter().filter(|target| range.contains(target)));
+    }
+    leaders
+}
+
+/// Splits a program into basic blocks, returning the address range of every block in
+/// address order.
+///
+/// Blocks start at the [`leaders`] and leave out instructions that failed to decode.
+pub fn basic_blocks(program: &Program) -> Vec<Range<u32>> {
+    split(program, &leaders(program))
+}
+
+fn split(program: &Program, leaders: &BTreeSet<u32>) -> Vec<Range<u32>> {
+    let mut blocks = vec![];
+    let mut current: Option<Range<u32>> = None;
+    for (address, result) in program {
+        let Ok(instruction) = result else {
+            blocks.extend(current.take());
+            continue;
+        };
+        if leaders.contains(&address) {
+            blocks.extend(current.take());
+        }
+        current.get_or_insert(address..address).end = address.wrapping_add(instruction.size());
+    }
+    blocks.extend(current);
+    blocks
+}
+
+/// Basic blocks of a program with the blocks control can continue to after each block.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ControlFlowGraph {
+    blocks: Vec<Range<u32>>,
+    successors: Vec<Vec<u32>>,
+}
+
+impl ControlFlowGraph {
+    /// Builds the control flow graph of `program`, with the destinations of `jump_tables`,
+    /// e.g. from [`jump_tables`], as successors of the jumps through them.
+    ///
+    /// Blocks ending with a return or a jump to a register not through a known table have no
+    /// successors, neither do blocks followed by instructions that failed to decode.
+    pub fn new(program: &Program, jump_tables: &[JumpTable]) -> Self {
+        let mut leaders = leaders(program);
+        for table in jump_tables {
+            if let Some(instruction) = program.at(table.site) {
+                leaders.insert(table.site.wrapping_add(instruction.size()));
+            }
+            leaders.extend(
+                table
+                    .targets
+                    .iter()
+                    .filter(|target| program.at(**target).is_some()),
+            );
+        }
+        let blocks = split(program, &leaders);
+
+        let successors = blocks
+            .iter()
+            .map(|block| {
+                let Some((address, Ok(last))) = program.range(block.clone()).next_back() else {
+                    return vec![];
+                };
+                if let Some(table) = jump_tables.iter().find(|table| table.site == address) {
+                    let targets: BTreeSet<u32> = table.targets.iter().copied().collect();
+                    return targets.into_iter().collect();
+                }
+                let operation = &last.operation;
+                let mut successors = vec![];
+                if let (Operation::B { .. }, BranchTarget::Direct(target)) =
+                    (operation, operation.branch_target(address))
+                {
+                    successors.push(target);
+                }
+                let falls_through = match operation {
+                    Operation::B { cond, .. } => *cond != Condition::None,
+                    operation => !operation.modifies_pc() || operation.is_call(),
+                };
+                if falls_through && !successors.contains(&block.end) {
+                    successors.push(block.end);
+                }
+                successors.retain(|successor| leaders.contains(successor));
+                successors
+            })
+            .collect();
+        Self { blocks, successors }
+    }
+
+    /// Address ranges of the basic blocks in address order.
+    pub fn blocks(&self) -> &[Range<u32>] {
+        &self.blocks
+    }
+
+    /// Returns the index of the block containing `address`.
+    pub fn block_at(&self, address: u32) -> Option<usize> {
+        let index = self
+            .blocks
+            .partition_point(|block| block.start <= address)
+            .checked_sub(1)?;
+        self.blocks[index].contains(&address).then_some(index)
+    }
+
+    /// Returns the starts of the blocks following the block starting at `block`.
+    pub fn successors(&self, block: u32) -> &[u32] {
+        match self
+            .blocks
+            .binary_search_by_key(&block, |block| block.start)
+        {
+            Ok(index) => &self.successors[index],
+            Err(_) => &[],
+        }
+    }
+}
+
+/// Table of destinations of a `switch`, found by [`jump_tables`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct JumpTable {
+    /// Address of the instruction jumping through the table.
+    pub site: u32,
+    /// Address of the first entry.
+    pub table: u32,
+    /// Destinations in the order of the entries.
+    pub targets: Vec<u32>,
+}
+
+/// Finds the jump tables of `program`, disassembled from `image`.
+///
+/// Recognizes tables of addresses loaded with `ldr` and jumped to with `mov pc` or `bx`,
+/// tables of offsets loaded with `ldr`, `ldrh` or `ldrb` and added to `pc`, and the
+/// `__gnu_thumb1_case_*` helpers of GCC called with `bl` and followed by a table of byte or
+/// halfword offsets. The number of entries comes from the `cmp` with `bhi` or `bhs` bounding
+/// the index, tables without one are not found. ARMv7-M `tbb` and `tbh` are not ARMv6-M
+/// instructions and never decode.
+pub fn jump_tables(program: &Program, image: &[u8]) -> Vec<JumpTable> {
+    let mut tables = vec![];
+    for (index, (site, result)) in program.iter().enumerate() {
+        let Ok(instruction) = result else {
+            continue;
+        };
+        let table = match instruction.operation {
+            Operation::MOVReg {
+                m, d: Register::PC, ..
+            } => inline_table(program, image, index, m, false),
+            Operation::BX { m } if m != Register::LR => {
+                inline_table(program, image, index, m, false)
+            }
+            Operation::ADDReg {
+                m,
+                n: Register::PC,
+                d: Register::PC,
+                ..
+            } => inline_table(program, image, index, m, true),
+            Operation::BL { .. } => helper_table(program, image, index, site),
+            _ => None,
+        };
+        tables.extend(table);
+    }
+    tables
+}
+
+/// Returns the instructions before the one at `index` in the same straight line code,
+/// nearest first, stopping at calls and jumps.
+fn preceding(program: &Program, index: usize) -> impl Iterator<Item = (u32, &Operation)> {
+    (0..index)
+        .rev()
+        .take(JUMP_TABLE_WINDOW)
+        .map_while(|index| {
+            let (address, result) = program.get(index)?;
+            Some((address, &result.as_ref().ok()?.operation))
+        })
+        .take_while(|(_, operation)| {
+            matches!(operation, Operation::B { cond, .. } if *cond != Condition::None)
+                || !operation.modifies_pc()
+        })
+}
+
+/// Returns the number of entries from `cmp index, #imm` and `bhi` or `bhs` to the default
+/// case, in the code before the instruction at `index`.
+fn bound(program: &Program, index: usize, register: Register) -> Option<u32> {
+    let mut condition = None;
+    for (_, operation) in preceding(program, index) {
+        match *operation {
+            Operation::B { cond, .. } if condition.is_none() => condition = Some(cond),
+            Operation::CMPImm { n, imm } if n == register => {
+                return match condition? {
+                    Condition::HI => Some(imm + 1),
+                    Condition::CS => Some(imm),
+                    _ => None,
+                };
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Recognizes a jump to `register` loaded from a table, with offsets added to `pc` when
+/// `relative`.
+fn inline_table(
+    program: &Program,
+    image: &[u8],
+    index: usize,
+    register: Register,
+    relative: bool,
+) -> Option<JumpTable> {
+    let (site, _) = program.get(index)?;
+    let mut entry = register;
+    let mut entry_shift = 0;
+    let mut load = None;
+    let mut table = None;
+    let mut scaled_index = None;
+    for (address, operation) in preceding(program, index) {
+        match (load, operation) {
+            (None, &Operation::LSLImm { imm: 1, m, d }) if relative && d == entry => {
+                entry_shift = 1;
+                entry = m;
+            }
+            (None, &Operation::LDRReg { m, n, t }) if t == entry => {
+                load = Some((AccessSize::Word, n, m))
+            }
+            (None, &Operation::LDRHReg { m, n, t }) if relative && t == entry => {
+                load = Some((AccessSize::Halfword, n, m))
+            }
+            (None, &Operation::LDRBReg { m, n, t }) if relative && t == entry => {
+                load = Some((AccessSize::Byte, n, m))
+            }
+            (Some((_, base, _)), operation) if table.is_none() => match *operation {
+                Operation::ADR { d, imm } if d == base => {
+                    table = Some((address.wrapping_add(4) & !0b11).wrapping_add(imm));
+                }
+                Operation::LDRLiteral { t, imm } if t == base => {
+                    let literal = (address.wrapping_add(4) & !0b11).wrapping_add(imm);
+                    table = Some(read(program, image, literal, AccessSize::Word)?);
+                }
+                _ => {}
+            },
+            _ => {}
+        }
+        if let (Some((size, _, offset)), &Operation::LSLImm { imm, m, d }) = (load, operation) {
+            if d == offset && scaled_index.is_none() && 1 << imm == size.bytes() {
+                scaled_index = Some(m);
+            }
+        }
+    }
+    let (size, _, offset) = load?;
+    let index_register = match size {
+        AccessSize::Byte => offset,
+        _ => scaled_index?,
+    };
+    let table = table?;
+    let count = bound(program, index, index_register)?;
+    let targets = (0..count)
+        .map(|entry| {
+            let value = read(
+                program,
+                image,
+                table.wrapping_add(entry * size.bytes()),
+                size,
+            )?;
+            Some(match relative {
+                true => site.wrapping_add(4).wrapping_add(value << entry_shift),
+                false => value & !1,
+            })
+        })
+        .collect::<Option<_>>()?;
+    Some(JumpTable {
+        site,
+        table,
+        targets,
+    })
+}
+
+/// Recognizes a call to a `__gnu_thumb1_case_*` helper of GCC, returning to the entry of
+/// the table after the call selected by `r0`.
+fn helper_table(program: &Program, image: &[u8], index: usize, site: u32) -> Option<JumpTable> {
+    let instruction = program.at(site)?;
+    let BranchTarget::Direct(helper) = instruction.operation.branch_target(site) else {
+        return None;
+    };
+    let start = program.index_of(helper)?;
+    let mut reads_lr = false;
+    let mut entry = None;
+    for index in start..start + JUMP_TABLE_WINDOW {
+        let Some((_, Ok(instruction))) = program.get(index) else {
+            break;
+        };
+        match instruction.operation {
+            Operation::MOVReg {
+                m: Register::LR, ..
+            } => reads_lr = true,
+            Operation::LDRBReg { .. } => entry = Some((AccessSize::Byte, false)),
+            Operation::LDRSBReg { .. } => entry = Some((AccessSize::Byte, true)),
+            Operation::LDRHReg { .. } => entry = Some((AccessSize::Halfword, false)),
+            Operation::LDRSH { .. } => entry = Some((AccessSize::Halfword, true)),
+            Operation::ADDReg {
+                d: Register::LR, ..
+            } if reads_lr => break,
+            _ => continue,
+        }
+    }
+    let (size, signed) = entry.filter(|_| reads_lr)?;
+
+    let table = site.wrapping_add(instruction.size());
+    let count = bound(program, index, Register::R0)?;
+    let targets = (0..count)
+        .map(|entry| {
+            let value = read(
+                program,
+                image,
+                table.wrapping_add(entry * size.bytes()),
+                size,
+            )?;
+            let value = match (signed, size) {
+                (true, AccessSize::Byte) => value as u8 as i8 as u32,
+                (true, _) => value as u16 as i16 as u32,
+                (false, _) => value,
+            };
+            Some(table.wrapping_add(value << 1))
+        })
+        .collect::<Option<_>>()?;
+    Some(JumpTable {
+        site,
+        table,
+        targets,
+    })
+}
+
+/// Reads a little endian value of `size` at `address` of the image of `program`.
+fn read(program: &Program, image: &[u8], address: u32, size: AccessSize) -> Option<u32> {
+    let offset = address.checked_sub(program.base())? as usize;
+    let bytes = image.get(offset..offset + size.bytes() as usize)?;
+    Some(
+        bytes
+            .iter()
+            .rev()
+            .fold(0, |value, byte| value << 8 | *byte as u32),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn blocks() {
+        // 0x00: push {r4, lr}; bl 0x0a; cmp r0, #0; bne.n 0x00
+        // 0x0a: invalid 32 bit; movs r0, #1; pop {r4, pc}
+        let code = [
+            0x10, 0xb5, 0x00, 0xf0, 0x02, 0xf8, 0x00, 0x28, 0xfa, 0xd1, 0xff, 0xf7, 0x00, 0x00,
+            0x01, 0x20, 0x10, 0xbd,
+        ];
+        let program = Program::new(&code, 0);
+        let addresses: Vec<_> = leaders(&program).into_iter().collect();
+        assert_eq!(addresses, [0x00, 0x0a, 0x0e]);
+        assert_eq!(basic_blocks(&program), [0x00..0x0a, 0x0e..0x12]);
+        // Targets outside the instructions are not leaders.
+        assert_eq!(leaders(Program::new(&code[6..10], 6).iter()).len(), 1);
+    }
+
+    #[test]
+    fn inline_jump_table() {
+        // 0x00: cmp r0, #2; bhi.n 0x1e; lsls r0, r0, #2; adr r3, 0x0c; ldr r0, [r3, r0];
+        //       mov pc, r0
+        // 0x0c: .word 0x19, 0x1b, 0x1d
+        // 0x18: movs r0, #1; movs r0, #2; movs r0, #3; bx lr
+        let code = [
+            0x02, 0x28, 0x0c, 0xd8, 0x80, 0x00, 0x01, 0xa3, 0x18, 0x58, 0x87, 0x46, //
+            0x19, 0x00, 0x00, 0x00, 0x1b, 0x00, 0x00, 0x00, 0x1d, 0x00, 0x00, 0x00, //
+            0x01, 0x20, 0x02, 0x20, 0x03, 0x20, 0x70, 0x47,
+        ];
+        let program = Program::new(&code, 0);
+        let tables = jump_tables(&program, &code);
+        assert_eq!(
+            tables,
+            [JumpTable {
+                site: 0x0a,
+                table: 0x0c,
+                targets: vec![0x18, 0x1a, 0x1c]
+            }]
+        );
+
+        let graph = ControlFlowGraph::new(&program, &tables);
+        assert_eq!(graph.successors(0x00), [0x1e, 0x04]);
+        assert_eq!(graph.successors(0x04), [0x18, 0x1a, 0x1c]);
+        assert_eq!(graph.successors(0x18), [0x1a]);
+        assert_eq!(graph.successors(0x1e), []);
+        assert_eq!(graph.block_at(0x08), Some(1));
+        assert_eq!(graph.blocks()[1], 0x04..0x0c);
+        // Without the table the jump has no known successors.
+        assert_eq!(ControlFlowGraph::new(&program, &[]).successors(0x04), []);
+    }
+
+    #[test]
+    fn gnu_case_helper() {
+        // 0x00: cmp r0, #1; bhi.n 0x0a; bl __gnu_thumb1_case_uqi
+        // 0x08: .byte 2, 3
+        // 0x0a: bx lr; movs r0, #1; movs r0, #2; bx lr
+        // 0x12: mov ip, r1; mov r1, lr; lsrs r1, r1, #1; lsls r1, r1, #1; ldrb r1, [r1, r0];
+        //       lsls r1, r1, #1; add lr, r1; mov r1, ip; bx lr
+        let code = [
+            0x01, 0x28, 0x02, 0xd8, 0x00, 0xf0, 0x05, 0xf8, 0x02, 0x03, //
+            0x70, 0x47, 0x01, 0x20, 0x02, 0x20, 0x70, 0x47, //
+            0x8c, 0x46, 0x71, 0x46, 0x49, 0x08, 0x49, 0x00, 0x09, 0x5c, 0x49, 0x00, 0x8e, 0x44,
+            0x61, 0x46, 0x70, 0x47,
+        ];
+        let program = Program::new(&code, 0);
+        let tables = jump_tables(&program, &code);
+        assert_eq!(
+            tables,
+            [JumpTable {
+                site: 0x04,
+                table: 0x08,
+                targets: vec![0x0c, 0x0e]
+            }]
+        );
+        let graph = ControlFlowGraph::new(&program, &tables);
+        assert_eq!(graph.successors(0x04), [0x0c, 0x0e]);
+    }
+}
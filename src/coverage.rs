@@ -0,0 +1,138 @@
+//! Execution coverage of emulated runs, the instructions executed and branches taken, as
+//! feedback for fuzzers without hardware trace.
+//!
+//! # Example
+//! ```
+//! # use armv6_m_instruction_parser::{coverage::Coverage, cpu::CpuState, emulator::Emulator, memory::Ram};
+//! // movs r0, #0; b 0x06; movs r0, #1; bkpt 0
+//! let mut bytes = [0x00, 0x20, 0x00, 0xe0, 0x01, 0x20, 0x00, 0xbe];
+//! let memory = Ram { base: 0, bytes: &mut bytes };
+//! let mut emulator = Emulator::new(CpuState::new(0x2000_0000, 0), memory).with_hooks(Coverage::new());
+//! emulator.run();
+//! let coverage = &emulator.hooks;
+//! assert!(coverage.is_covered(0x02) && !coverage.is_covered(0x04));
+//! assert_eq!(coverage.edges().collect::<Vec<_>>(), [((0x02, 0x06), 1)]);
+//! ```
+
+use alloc::collections::BTreeMap;
+
+use crate::{
+    cpu::CpuState,
+    emulator::{Exceptions, Hooks, Stop},
+    instructons::Instruction,
+    registers::Register,
+};
+
+/// Coverage collected as the [`Hooks`] of an emulator.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Coverage {
+    /// Times the instruction at every address was executed.
+    instructions: BTreeMap<u32, u64>,
+    /// Times every edge from a branch to its target was taken.
+    edges: BTreeMap<(u32, u32), u64>,
+}
+
+impl Coverage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns `true` if the instruction at `address` was executed.
+    pub fn is_covered(&self, address: u32) -> bool {
+        self.instructions.contains_key(&address)
+    }
+
+    /// Returns the addresses of the executed instructions with their hit counts, in address
+    /// order.
+    pub fn instructions(&self) -> impl Iterator<Item = (u32, u64)> + '_ {
+        self.instructions
+            .iter()
+            .map(|(address, hits)| (*address, *hits))
+    }
+
+    /// Returns the edges from an instruction to an instruction other than the next one,
+    /// taken branches and returns, with their hit counts.
+    pub fn edges(&self) -> impl Iterator<Item = ((u32, u32), u64)> + '_ {
+        self.edges.iter().map(|(edge, hits)| (*edge, *hits))
+    }
+
+    /// Adds the coverage of `other` and returns the number of instructions and edges it
+    /// covers that were not covered, for a fuzzer to keep inputs finding new paths.
+    pub fn merge(&mut self, other: &Coverage) -> usize {
+        let mut new = 0;
+        for (address, hits) in other.instructions() {
+            let count = self.instructions.entry(address).or_default();
+            new += (*count == 0) as usize;
+            *count += hits;
+        }
+        for (edge, hits) in other.edges() {
+            let count = self.edges.entry(edge).or_default();
+            new += (*count == 0) as usize;
+            *count += hits;
+        }
+        new
+    }
+
+    pub fn clear(&mut self) {
+        self.instructions.clear();
+        self.edges.clear();
+    }
+}
+
+impl Hooks for Coverage {
+    fn after(
+        &mut self,
+        address: u32,
+        instruction: &Instruction,
+        result: Result<(), Stop>,
+        state: &CpuState,
+        _exceptions: &mut Exceptions,
+    ) {
+        // Faulting instructions and breakpoints are not executed.
+        if !matches!(
+            result,
+            Ok(()) | Err(Stop::SupervisorCall(_) | Stop::Sleep | Stop::ExceptionReturn(_))
+        ) {
+            return;
+        }
+        *self.instructions.entry(address).or_default() += 1;
+        let target = state.get(Register::PC);
+        if result.is_ok() && target != address.wrapping_add(instruction.size()) {
+            *self.edges.entry((address, target)).or_default() += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{emulator::Emulator, memory::Ram};
+
+    #[test]
+    fn loop_coverage() {
+        // movs r0, #3; loop: subs r0, #1; bne loop; udf 0
+        let mut bytes = [0x03, 0x20, 0x01, 0x38, 0xfd, 0xd1, 0x00, 0xde];
+        let memory = Ram {
+            base: 0,
+            bytes: &mut bytes,
+        };
+        let mut emulator =
+            Emulator::new(CpuState::new(0x2000_0000, 0), memory).with_hooks(Coverage::new());
+        for _ in 0..7 {
+            emulator.step().unwrap();
+        }
+        let coverage = emulator.hooks.clone();
+        assert_eq!(
+            coverage.instructions().collect::<alloc::vec::Vec<_>>(),
+            [(0x00, 1), (0x02, 3), (0x04, 3)]
+        );
+        assert_eq!(
+            coverage.edges().collect::<alloc::vec::Vec<_>>(),
+            [((0x04, 0x02), 2)]
+        );
+        let mut total = Coverage::new();
+        assert_eq!(total.merge(&coverage), 4);
+        assert_eq!(total.merge(&coverage), 0);
+        assert_eq!(total.instructions().nth(1), Some((0x02, 6)));
+    }
+}
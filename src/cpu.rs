@@ -0,0 +1,274 @@
+//! State of an ARMv6-M processor, the register file with the banked stack pointers and the
+//! special registers, shared by the interpreter and the analyses of saved states.
+//!
+//! # Example
+//! ```
+//! # use armv6_m_instruction_parser::{cpu::{CpuState, StackPointer}, registers::{Register, SpecialRegister}};
+//! let mut state = CpuState::new(0x2000_1000, 0x0800_0100);
+//! state.set_stack_pointer(StackPointer::Process, 0x2000_0800);
+//! // Thread mode switches to the process stack with CONTROL.SPSEL.
+//! state.write_special(SpecialRegister::CONTROL, 0b10);
+//! assert_eq!(state.active_stack(), StackPointer::Process);
+//! assert_eq!(state.get(Register::SP), 0x2000_0800);
+//! assert_eq!(state.xpsr(), 0x0100_0000);
+//! ```
+
+use crate::{
+    conditions::Condition,
+    registers::{Register, SpecialRegister},
+};
+
+/// Flags of the application program status register.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Apsr {
+    pub n: bool,
+    pub z: bool,
+    pub c: bool,
+    pub v: bool,
+}
+
+impl Apsr {
+    /// Returns `true` if `condition` holds for the flags.
+    pub fn holds(self, condition: Condition) -> bool {
+        match condition {
+            Condition::EQ => self.z,
+            Condition::NE => !self.z,
+            Condition::CS => self.c,
+            Condition::CC => !self.c,
+            Condition::MI => self.n,
+            Condition::PL => !self.n,
+            Condition::VS => self.v,
+            Condition::VC => !self.v,
+            Condition::HI => self.c && !self.z,
+            Condition::LS => !self.c || self.z,
+            Condition::GE => self.n == self.v,
+            Condition::LT => self.n != self.v,
+            Condition::GT => !self.z && self.n == self.v,
+            Condition::LE => self.z || self.n != self.v,
+            Condition::None => true,
+        }
+    }
+
+    /// Returns the flags as bits 31 to 28 of the register.
+    pub fn bits(self) -> u32 {
+        (self.n as u32) << 31
+            | (self.z as u32) << 30
+            | (self.c as u32) << 29
+            | (self.v as u32) << 28
+    }
+
+    /// Returns the flags in bits 31 to 28 of `bits`.
+    pub fn from_bits(bits: u32) -> Self {
+        Self {
+            n: bits & 1 << 31 != 0,
+            z: bits & 1 << 30 != 0,
+            c: bits & 1 << 29 != 0,
+            v: bits & 1 << 28 != 0,
+        }
+    }
+}
+
+/// The CONTROL register.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Control {
+    /// Thread mode is unprivileged, bit 0.
+    pub npriv: bool,
+    /// Thread mode uses the process stack pointer, bit 1.
+    pub spsel: bool,
+}
+
+impl Control {
+    pub fn bits(self) -> u32 {
+        (self.spsel as u32) << 1 | self.npriv as u32
+    }
+
+    pub fn from_bits(bits: u32) -> Self {
+        Self {
+            npriv: bits & 1 != 0,
+            spsel: bits & 0b10 != 0,
+        }
+    }
+}
+
+/// One of the two stack pointers banked as `sp`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StackPointer {
+    /// MSP, used out of reset and always in handler mode.
+    Main,
+    /// PSP, used in thread mode when selected by [`Control::spsel`].
+    Process,
+}
+
+/// State of the processor.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CpuState {
+    /// `r0` to `r12`, `lr` and `pc`, the slot of `sp` is unused.
+    registers: [u32; 16],
+    msp: u32,
+    psp: u32,
+    pub apsr: Apsr,
+    /// Exception number of the exception being handled, the IPSR, 0 in thread mode.
+    pub exception: u32,
+    /// Thumb bit of the EPSR, executing with it clear faults.
+    pub thumb: bool,
+    /// Exceptions with configurable priority are masked.
+    pub primask: bool,
+    pub control: Control,
+}
+
+impl CpuState {
+    /// Returns the state after reset, in privileged thread mode executing from `pc` with
+    /// the main stack pointer `sp` and all other registers zero.
+    pub fn new(sp: u32, pc: u32) -> Self {
+        let mut state = Self {
+            registers: [0; 16],
+            msp: 0,
+            psp: 0,
+            apsr: Apsr::default(),
+            exception: 0,
+            thumb: true,
+            primask: false,
+            control: Control::default(),
+        };
+        state.set(Register::SP, sp);
+        state.set(Register::PC, pc);
+        state
+    }
+
+    /// Returns the value of `register`, the active stack pointer for `sp`, and for `pc` the
+    /// address of the next instruction to execute rather than the value instructions read.
+    pub fn get(&self, register: Register) -> u32 {
+        match register {
+            Register::SP => self.stack_pointer(self.active_stack()),
+            register => self.registers[register as usize],
+        }
+    }
+
+    /// Sets `register` to `value`, with the bits always zero in `sp` and `pc` cleared.
+    pub fn set(&mut self, register: Register, value: u32) {
+        match register {
+            Register::SP => self.set_stack_pointer(self.active_stack(), value),
+            Register::PC => self.registers[Register::PC as usize] = value & !1,
+            register => self.registers[register as usize] = value,
+        }
+    }
+
+    /// Returns `true` in handler mode, when handling an exception.
+    pub fn handler_mode(&self) -> bool {
+        self.exception != 0
+    }
+
+    /// Returns `true` if executing privileged, always in handler mode.
+    pub fn privileged(&self) -> bool {
+        self.handler_mode() || !self.control.npriv
+    }
+
+    /// Returns the stack pointer `sp` currently accesses.
+    pub fn active_stack(&self) -> StackPointer {
+        match !self.handler_mode() && self.control.spsel {
+            true => StackPointer::Process,
+            false => StackPointer::Main,
+        }
+    }
+
+    pub fn stack_pointer(&self, stack: StackPointer) -> u32 {
+        match stack {
+            StackPointer::Main => self.msp,
+            StackPointer::Process => self.psp,
+        }
+    }
+
+    /// Sets the stack pointer `stack` to `value` with bits 1 and 0 cleared.
+    pub fn set_stack_pointer(&mut self, stack: StackPointer, value: u32) {
+        match stack {
+            StackPointer::Main => self.msp = value & !0b11,
+            StackPointer::Process => self.psp = value & !0b11,
+        }
+    }
+
+    /// Returns the xPSR, the APSR, EPSR and IPSR combined.
+    pub fn xpsr(&self) -> u32 {
+        self.apsr.bits() | (self.thumb as u32) << 24 | self.exception & 0x3f
+    }
+
+    /// Sets the APSR, EPSR and IPSR from the xPSR `value`, as exception returns do.
+    pub fn set_xpsr(&mut self, value: u32) {
+        self.apsr = Apsr::from_bits(value);
+        self.thumb = value & 1 << 24 != 0;
+        self.exception = value & 0x3f;
+    }
+
+    /// Returns the value `mrs` reads from `register`, where the EPSR reads as zero.
+    pub fn read_special(&self, register: SpecialRegister) -> u32 {
+        let ipsr = self.exception & 0x3f;
+        match register {
+            SpecialRegister::APSR | SpecialRegister::EAPSR => self.apsr.bits(),
+            SpecialRegister::IAPSR | SpecialRegister::XPSR => self.apsr.bits() | ipsr,
+            SpecialRegister::IPSR | SpecialRegister::IEPSR => ipsr,
+            SpecialRegister::EPSR => 0,
+            SpecialRegister::MSP => self.msp,
+            SpecialRegister::PSP => self.psp,
+            SpecialRegister::PRIMASK => self.primask as u32,
+            SpecialRegister::CONTROL => self.control.bits(),
+        }
+    }
+
+    /// Writes `value` to `register` as `msr` does.
+    ///
+    /// Only the flags of the program status registers are written. Writes to the stack
+    /// pointers, PRIMASK and CONTROL are ignored when unprivileged, and to CONTROL also in
+    /// handler mode.
+    pub fn write_special(&mut self, register: SpecialRegister, value: u32) {
+        let privileged = self.privileged();
+        match register {
+            SpecialRegister::APSR
+            | SpecialRegister::IAPSR
+            | SpecialRegister::EAPSR
+            | SpecialRegister::XPSR => self.apsr = Apsr::from_bits(value),
+            SpecialRegister::IPSR | SpecialRegister::EPSR | SpecialRegister::IEPSR => {}
+            SpecialRegister::MSP if privileged => self.set_stack_pointer(StackPointer::Main, value),
+            SpecialRegister::PSP if privileged => {
+                self.set_stack_pointer(StackPointer::Process, value)
+            }
+            SpecialRegister::PRIMASK if privileged => self.primask = value & 1 != 0,
+            SpecialRegister::CONTROL if privileged && !self.handler_mode() => {
+                self.control = Control::from_bits(value)
+            }
+            SpecialRegister::MSP
+            | SpecialRegister::PSP
+            | SpecialRegister::PRIMASK
+            | SpecialRegister::CONTROL => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn banked_stack_pointers() {
+        let mut state = CpuState::new(0x2000_1003, 0x101);
+        assert_eq!(state.get(Register::SP), 0x2000_1000);
+        assert_eq!(state.get(Register::PC), 0x100);
+        state.write_special(SpecialRegister::PSP, 0x2000_0800);
+        state.write_special(SpecialRegister::CONTROL, 0b11);
+        assert_eq!(state.get(Register::SP), 0x2000_0800);
+        state.set(Register::SP, 0x2000_07f0);
+        assert_eq!(state.read_special(SpecialRegister::PSP), 0x2000_07f0);
+        // Unprivileged now, the writes are ignored.
+        state.write_special(SpecialRegister::CONTROL, 0);
+        state.write_special(SpecialRegister::PRIMASK, 1);
+        assert_eq!((state.control.bits(), state.primask), (0b11, false));
+        // Handler mode always uses the main stack and is privileged.
+        state.set_xpsr(0x6100_000b);
+        assert_eq!(state.get(Register::SP), 0x2000_1000);
+        assert!(state.privileged() && state.apsr.z && state.apsr.c);
+        assert_eq!(state.read_special(SpecialRegister::IPSR), 11);
+        assert_eq!(state.read_special(SpecialRegister::XPSR), 0x6000_000b);
+        assert_eq!(state.xpsr(), 0x6100_000b);
+    }
+}
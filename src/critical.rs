@@ -0,0 +1,199 @@
+//! Critical sections between `cpsid i` and `cpsie i`, with how long interrupts stay
+//! disabled.
+//!
+//! # Example
+//! ```
+//! # use armv6_m_instruction_parser::{control_flow::ControlFlowGraph, critical::critical_sections, functions::FunctionRange, program::Program, timing::CoreModel};
+//! // cpsid i; ldr r1, [r0]; adds r1, #1; str r1, [r0]; cpsie i; bx lr
+//! let code = [
+//!     0x72, 0xb6, 0x01, 0x68, 0x01, 0x31, 0x01, 0x60, 0x62, 0xb6, 0x70, 0x47,
+//! ];
+//! let program = Program::new(&code, 0);
+//! let cfg = ControlFlowGraph::new(&program, &[]);
+//! let function = FunctionRange { start: 0, end: 0x0c, called: false, saves_lr: false };
+//! let sections = critical_sections(&program, &cfg, &function, CoreModel::CortexM0);
+//! assert_eq!(sections[0].enables, [0x08]);
+//! assert_eq!((sections[0].instructions, sections[0].cycles), (4, 6));
+//! assert!(!sections[0].unbalanced);
+//! ```
+
+use alloc::{
+    collections::{BTreeMap, BTreeSet},
+    vec::Vec,
+};
+
+use crate::{
+    control_flow::ControlFlowGraph,
+    functions::FunctionRange,
+    instructons::Operation,
+    program::Program,
+    timing::{cycles, CoreModel},
+};
+
+/// Code run with interrupts disabled, found by [`critical_sections`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CriticalSection {
+    /// Address of the `cpsid i`.
+    pub disable: u32,
+    /// Addresses of the `cpsie i` ending the section on some path, in address order.
+    pub enables: Vec<u32>,
+    /// Most instructions run after the `cpsid i` up to and including the `cpsie i`.
+    pub instructions: u32,
+    /// Most cycles of those instructions, see [`cycles`]. Called functions are not included.
+    pub cycles: u32,
+    /// Some path leaves the function with interrupts disabled.
+    pub unbalanced: bool,
+    /// Some path loops, the lengths count every instruction of the loop once.
+    pub looped: bool,
+}
+
+/// Returns the critical sections started in `function`, in address order.
+///
+/// The paths from every `cpsid i` are followed along the blocks of `cfg` until a `cpsie i`.
+/// Paths returning or branching out of the function before that make the section
+/// unbalanced.
+pub fn critical_sections(
+    program: &Program,
+    cfg: &ControlFlowGraph,
+    function: &FunctionRange,
+    model: CoreModel,
+) -> Vec<CriticalSection> {
+    program
+        .range(function.start..function.end)
+        .filter(|(_, result)| {
+            matches!(
+                result.as_ref().map(|instruction| &instruction.operation),
+                Ok(Operation::CPS { im: true })
+            )
+        })
+        .filter_map(|(disable, result)| {
+            let size = result.as_ref().ok()?.size();
+            let mut walk = Walk {
+                program,
+                cfg,
+                function,
+                model,
+                lengths: BTreeMap::new(),
+                active: BTreeSet::new(),
+                enables: BTreeSet::new(),
+                unbalanced: false,
+                looped: false,
+            };
+            let (instructions, cycles) = walk.from(disable.wrapping_add(size));
+            Some(CriticalSection {
+                disable,
+                enables: walk.enables.into_iter().collect(),
+                instructions,
+                cycles,
+                unbalanced: walk.unbalanced,
+                looped: walk.looped,
+            })
+        })
+        .collect()
+}
+
+/// Search of the longest paths of a critical section.
+struct Walk<'a> {
+    program: &'a Program,
+    cfg: &'a ControlFlowGraph,
+    function: &'a FunctionRange,
+    model: CoreModel,
+    /// Longest instructions and cycles from the addresses walked from.
+    lengths: BTreeMap<u32, (u32, u32)>,
+    /// Addresses on the current path.
+    active: BTreeSet<u32>,
+    enables: BTreeSet<u32>,
+    unbalanced: bool,
+    looped: bool,
+}
+
+impl Walk<'_> {
+    /// Returns the most instructions and cycles from `entry` to the end of the section.
+    fn from(&mut self, entry: u32) -> (u32, u32) {
+        if let Some(length) = self.lengths.get(&entry) {
+            return *length;
+        }
+        if !self.active.insert(entry) {
+            self.looped = true;
+            return (0, 0);
+        }
+        let Some(block) = self
+            .cfg
+            .block_at(entry)
+            .map(|index| &self.cfg.blocks()[index])
+        else {
+            self.unbalanced = true;
+            return (0, 0);
+        };
+        let (mut instructions, mut total) = (0, 0);
+        let mut enabled = false;
+        for (address, result) in self.program.range(entry..block.end) {
+            let Ok(instruction) = result else {
+                break;
+            };
+            instructions += 1;
+            total += cycles(&instruction.operation, self.model).max();
+            if instruction.operation == (Operation::CPS { im: false }) {
+                self.enables.insert(address);
+                enabled = true;
+                break;
+            }
+        }
+        if !enabled {
+            let successors = self.cfg.successors(block.start);
+            if successors.is_empty() {
+                self.unbalanced = true;
+            }
+            let mut longest = (0, 0);
+            for &successor in successors {
+                if !(self.function.start..self.function.end).contains(&successor) {
+                    self.unbalanced = true;
+                    continue;
+                }
+                let (successor_instructions, successor_cycles) = self.from(successor);
+                longest.0 = longest.0.max(successor_instructions);
+                longest.1 = longest.1.max(successor_cycles);
+            }
+            instructions += longest.0;
+            total += longest.1;
+        }
+        self.active.remove(&entry);
+        self.lengths.insert(entry, (instructions, total));
+        (instructions, total)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn unbalanced_paths() {
+        // 0x00: cpsid i; cmp r0, #0; beq.n 0x0a
+        // 0x06: movs r1, #1; cpsie i
+        // 0x0a: bx lr
+        let code = [
+            0x72, 0xb6, 0x00, 0x28, 0x01, 0xd0, 0x01, 0x21, 0x62, 0xb6, 0x70, 0x47,
+        ];
+        let program = Program::new(&code, 0);
+        let cfg = ControlFlowGraph::new(&program, &[]);
+        let function = FunctionRange {
+            start: 0,
+            end: 0x0c,
+            called: false,
+            saves_lr: false,
+        };
+        let sections = critical_sections(&program, &cfg, &function, CoreModel::CortexM0Plus);
+        assert_eq!(
+            sections,
+            [CriticalSection {
+                disable: 0x00,
+                enables: vec![0x08],
+                instructions: 4,
+                cycles: 1 + 2 + 1 + 1,
+                unbalanced: true,
+                looped: false,
+            }]
+        );
+    }
+}
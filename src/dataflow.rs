@@ -0,0 +1,193 @@
+//! Forward dataflow analysis over the control flow graph of a function.
+//!
+//! An [`Analysis`] gives the state at the start of the function, how an instruction changes
+//! it and how states are combined where paths meet, [`solve`] iterates it to a fixpoint.
+//! [`ReachingDefinitions`] is an analysis built on it.
+//!
+//! # Example
+//! ```
+//! # use armv6_m_instruction_parser::{control_flow::ControlFlowGraph, dataflow::{solve, ReachingDefinitions}, functions::FunctionRange, program::Program, registers::Register};
+//! // 0x00: movs r0, #1; cmp r1, #0; beq.n 0x08; movs r0, #2
+//! // 0x08: bx lr
+//! let code = [0x01, 0x20, 0x00, 0x29, 0x00, 0xd0, 0x02, 0x20, 0x70, 0x47];
+//! let program = Program::new(&code, 0);
+//! let cfg = ControlFlowGraph::new(&program, &[]);
+//! let function = FunctionRange { start: 0, end: 0x0a, called: false, saves_lr: false };
+//! let reaching = solve(&ReachingDefinitions, &program, &cfg, &function);
+//! let definitions: Vec<_> = reaching.before(0x08).unwrap().of(Register::R0).collect();
+//! assert_eq!(definitions, [0x00, 0x06]);
+//! ```
+
+use alloc::{
+    collections::{BTreeMap, BTreeSet},
+    vec,
+};
+
+use crate::{
+    control_flow::ControlFlowGraph, functions::FunctionRange, instructons::Instruction,
+    program::Program, registers::Register,
+};
+
+/// Forward dataflow problem solved by [`solve`].
+pub trait Analysis {
+    type State: Clone;
+
+    /// Returns the state at the start of the function.
+    fn entry(&self) -> Self::State;
+
+    /// Combines `other` into `state` where paths meet, returning `true` if `state` changed.
+    ///
+    /// The states must only change a finite number of times for [`solve`] to finish.
+    fn join(&self, state: &mut Self::State, other: &Self::State) -> bool;
+
+    /// Updates `state` with the effect of `instruction` at `address`.
+    fn transfer(&self, state: &mut Self::State, address: u32, instruction: &Instruction);
+}
+
+/// States before the instructions of a function, from [`solve`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Solution<S> {
+    before: BTreeMap<u32, S>,
+}
+
+impl<S> Solution<S> {
+    /// Returns the state before the instruction at `address`, `None` if the instruction is
+    /// not reached from the start of the function.
+    pub fn before(&self, address: u32) -> Option<&S> {
+        self.before.get(&address)
+    }
+
+    /// Returns the addresses of the instructions reached with their states, in address order.
+    pub fn iter(&self) -> impl Iterator<Item = (u32, &S)> {
+        self.before.iter().map(|(address, state)| (*address, state))
+    }
+}
+
+/// Solves `analysis` for `function` in `program`, along the blocks of `cfg` reached from the
+/// start of the function without leaving it.
+pub fn solve<A: Analysis>(
+    analysis: &A,
+    program: &Program,
+    cfg: &ControlFlowGraph,
+    function: &FunctionRange,
+) -> Solution<A::State> {
+    let mut entries = BTreeMap::from([(function.start, analysis.entry())]);
+    let mut before = BTreeMap::new();
+    let mut work = vec![function.start];
+    while let Some(entry) = work.pop() {
+        let Some(block) = cfg.block_at(entry).map(|index| &cfg.blocks()[index]) else {
+            continue;
+        };
+        let mut state = entries[&entry].clone();
+        for (address, result) in program.range(entry..block.end) {
+            let Ok(instruction) = result else {
+                break;
+            };
+            before.insert(address, state.clone());
+            analysis.transfer(&mut state, address, instruction);
+        }
+        for &successor in cfg.successors(block.start) {
+            if !(function.start..function.end).contains(&successor) {
+                continue;
+            }
+            match entries.get_mut(&successor) {
+                Some(known) => {
+                    if analysis.join(known, &state) {
+                        work.push(successor);
+                    }
+                }
+                None => {
+                    entries.insert(successor, state.clone());
+                    work.push(successor);
+                }
+            }
+        }
+    }
+    Solution { before }
+}
+
+/// Reaching definitions of registers, the instructions whose writes may still be the values
+/// of the registers.
+///
+/// Calls define the registers the AAPCS lets the called function change. Registers not
+/// written since the start of the function have no definitions.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ReachingDefinitions;
+
+/// Definitions reaching an instruction, see [`ReachingDefinitions`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Definitions(BTreeSet<(Register, u32)>);
+
+impl Definitions {
+    /// Returns the addresses of the definitions of `register`, in address order.
+    pub fn of(&self, register: Register) -> impl Iterator<Item = u32> + '_ {
+        self.0
+            .range((register, 0)..=(register, u32::MAX))
+            .map(|(_, address)| *address)
+    }
+
+    /// Returns every definition with its register.
+    pub fn iter(&self) -> impl Iterator<Item = (Register, u32)> + '_ {
+        self.0.iter().copied()
+    }
+}
+
+impl Analysis for ReachingDefinitions {
+    type State = Definitions;
+
+    fn entry(&self) -> Definitions {
+        Definitions::default()
+    }
+
+    fn join(&self, state: &mut Definitions, other: &Definitions) -> bool {
+        let length = state.0.len();
+        state.0.extend(other.0.iter().copied());
+        state.0.len() != length
+    }
+
+    fn transfer(&self, state: &mut Definitions, address: u32, instruction: &Instruction) {
+        let mut written = instruction.operation.registers_written();
+        if instruction.operation.is_call() {
+            use Register::*;
+            for register in [R0, R1, R2, R3, R12] {
+                written.insert(register);
+            }
+        }
+        written.remove(Register::PC);
+        state.0.retain(|(register, _)| !written.contains(*register));
+        state
+            .0
+            .extend(written.into_iter().map(|register| (register, address)));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn reaching_definitions() {
+        // 0x00: movs r0, #0
+        // 0x02: adds r0, #1; cmp r0, #9; bls.n 0x02; bl 0x0c
+        // 0x0c: bx lr
+        let code = [
+            0x00, 0x20, 0x01, 0x30, 0x09, 0x28, 0xfc, 0xd9, 0x00, 0xf0, 0x00, 0xf8, 0x70, 0x47,
+        ];
+        let program = Program::new(&code, 0);
+        let cfg = ControlFlowGraph::new(&program, &[]);
+        let function = FunctionRange {
+            start: 0,
+            end: 0x0c,
+            called: false,
+            saves_lr: false,
+        };
+        let reaching = solve(&ReachingDefinitions, &program, &cfg, &function);
+        let loop_head = reaching.before(0x02).unwrap();
+        assert_eq!(loop_head.of(Register::R0).collect::<Vec<_>>(), [0x00, 0x02]);
+        // The called function is outside.
+        assert!(reaching.before(0x0c).is_none());
+        let call = reaching.before(0x08).unwrap();
+        assert_eq!(call.iter().collect::<Vec<_>>(), [(Register::R0, 0x02)]);
+        assert_eq!(reaching.iter().count(), 5);
+    }
+}
@@ -0,0 +1,146 @@
+//! Detection of code not reached from the entry points of an image.
+//!
+//! # Example
+//! ```
+//! # use armv6_m_instruction_parser::{control_flow::ControlFlowGraph, dead_code::unreachable_code, program::Program};
+//! // 0x00: movs r0, #1; bx lr
+//! // 0x04: movs r0, #2; bx lr
+//! let image = [0x01, 0x20, 0x70, 0x47, 0x02, 0x20, 0x70, 0x47];
+//! let program = Program::new(&image, 0);
+//! let cfg = ControlFlowGraph::new(&program, &[]);
+//! assert_eq!(unreachable_code(&program, &image, &cfg, &[0x00]), [0x04..0x08]);
+//! ```
+
+use alloc::{collections::BTreeMap, vec, vec::Vec};
+use core::ops::Range;
+
+use crate::{
+    control_flow::ControlFlowGraph,
+    functions::is_padding,
+    instructons::{BranchTarget, Operation},
+    literals::{literal_loads, literal_pools},
+    program::Program,
+    vector_table::VectorTable,
+};
+
+/// Returns the handlers of the first `entries` entries of the vector table at the start of
+/// `image`, without the initial stack pointer.
+///
+/// Entries without the Thumb bit set, like reserved entries left zero, are skipped, see
+/// [`VectorTable`].
+pub fn vector_table(image: &[u8], entries: usize) -> Vec<u32> {
+    VectorTable::parse(image, entries).map_or(vec![], |table| table.handlers().collect())
+}
+
+/// Returns the address ranges of the instructions of `program` not reached from `entries`,
+/// e.g. from [`vector_table`] and exported symbols, in address order.
+///
+/// Code is reached along the blocks of `cfg`, by direct calls, and through literals holding
+/// the address of an instruction with the Thumb bit set, as those are taken as function
+/// pointers. Literal pools and ranges of only padding are not reported.
+pub fn unreachable_code(
+    program: &Program,
+    image: &[u8],
+    cfg: &ControlFlowGraph,
+    entries: &[u32],
+) -> Vec<Range<u32>> {
+    let loads = literal_loads(program, image);
+    let pools = literal_pools(&loads);
+    let pointers: BTreeMap<u32, u32> = loads
+        .iter()
+        .filter_map(|load| {
+            let value = load.value?;
+            (value & 1 == 1 && program.at(value & !1).is_some()).then_some((load.site, value & !1))
+        })
+        .collect();
+
+    // First address reached of every block reached.
+    let mut reached: BTreeMap<usize, u32> = BTreeMap::new();
+    let mut work = entries.to_vec();
+    while let Some(entry) = work.pop() {
+        let Some(index) = cfg.block_at(entry) else {
+            continue;
+        };
+        if reached.get(&index).is_some_and(|from| *from <= entry) {
+            continue;
+        }
+        reached.insert(index, entry);
+        let block = &cfg.blocks()[index];
+        for (address, result) in program.range(entry..block.end) {
+            let Ok(instruction) = result else {
+                break;
+            };
+            if let (Operation::BL { .. }, BranchTarget::Direct(target)) = (
+                &instruction.operation,
+                instruction.operation.branch_target(address),
+            ) {
+                work.push(target);
+            }
+            work.extend(pointers.get(&address));
+        }
+        work.extend(cfg.successors(block.start));
+    }
+
+    let mut ranges: Vec<(Range<u32>, bool)> = vec![];
+    for (address, result) in program {
+        let is_reached = cfg
+            .block_at(address)
+            .is_some_and(|index| reached.get(&index).is_some_and(|from| *from <= address));
+        if is_reached || pools.iter().any(|pool| pool.contains(&address)) {
+            continue;
+        }
+        let size = result.as_ref().map_or(2, |instruction| instruction.size());
+        let padding = result
+            .as_ref()
+            .is_ok_and(|instruction| is_padding(&instruction.operation));
+        match ranges.last_mut() {
+            Some((range, only_padding)) if range.end == address => {
+                range.end = address.wrapping_add(size);
+                *only_padding &= padding;
+            }
+            _ => ranges.push((address..address.wrapping_add(size), padding)),
+        }
+    }
+    ranges
+        .into_iter()
+        .filter(|(_, only_padding)| !only_padding)
+        .map(|(range, _)| range)
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn vectors() {
+        let image = [
+            0x00, 0x10, 0x00, 0x20, 0x01, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x02,
+            0x00, 0x00, 0x01, 0x03, 0x00, 0x00,
+        ];
+        assert_eq!(vector_table(&image, 4), [0x100, 0x200]);
+    }
+
+    #[test]
+    fn unreachable() {
+        // 0x00: push {r4, lr}; bl 0x0a; pop {r4, pc}; nop
+        // 0x0a: ldr r0, [pc, #4]; bx lr; nop; .word 0x15
+        // 0x14: bx lr
+        // 0x16: movs r0, #1; bx lr
+        let image = [
+            0x10, 0xb5, 0x00, 0xf0, 0x02, 0xf8, 0x10, 0xbd, 0x00, 0xbf, //
+            0x01, 0x48, 0x70, 0x47, 0x00, 0xbf, 0x15, 0x00, 0x00, 0x00, //
+            0x70, 0x47, //
+            0x01, 0x20, 0x70, 0x47,
+        ];
+        let program = Program::new(&image, 0);
+        let cfg = ControlFlowGraph::new(&program, &[]);
+        let dead = unreachable_code(&program, &image, &cfg, &[0x00]);
+        assert_eq!(dead.len(), 1);
+        assert_eq!(dead[0], 0x16..0x1a);
+        assert_eq!(
+            unreachable_code(&program, &image, &cfg, &[0x0a]),
+            [0x00..0x0a, 0x16..0x1a]
+        );
+    }
+}
@@ -0,0 +1,381 @@
+//! Differential testing of the emulator against another processor, a GDB remote target like
+//! QEMU or a Cortex-M0 behind a debug probe, stepping both and comparing the registers after
+//! every instruction to validate the decoder and the semantics.
+//!
+//! # Example
+//! ```no_run
+//! # use armv6_m_instruction_parser::{cpu::CpuState, differential::{compare, GdbRemote}, emulator::Emulator, memory::Flash};
+//! // The image loaded in QEMU, started with `-s -S`.
+//! let image = std::fs::read("firmware.bin")?;
+//! let memory = Flash { base: 0, bytes: &image };
+//! let sp = u32::from_le_bytes(image[0..4].try_into().unwrap());
+//! let pc = u32::from_le_bytes(image[4..8].try_into().unwrap());
+//! let mut emulator = Emulator::new(CpuState::new(sp, pc), memory);
+//! let mut target = GdbRemote::connect("localhost:1234")?;
+//! if let Some(divergence) = compare(&mut emulator, &mut target, 10_000)? {
+//!     println!("{divergence:?}");
+//! }
+//! # Ok::<(), std::io::Error>(())
+//! ```
+
+use std::{
+    io::{self, Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+};
+
+use crate::{
+    cpu::CpuState,
+    emulator::{Emulator, Hooks, Stop},
+    memory::Memory,
+    registers::Register,
+};
+
+/// Bits of the xPSR compared, the flags, the Thumb bit and the exception number.
+const XPSR_MASK: u32 = 0xf100_003f;
+
+/// Registers compared, `r0` to `pc` and the xPSR.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Registers {
+    /// `r0` to `pc`, the active stack pointer as `sp` and the next instruction as `pc`.
+    pub core: [u32; 16],
+    pub xpsr: u32,
+}
+
+impl Registers {
+    pub fn from_state(state: &CpuState) -> Self {
+        let mut core = [0; 16];
+        for (index, value) in core.iter_mut().enumerate() {
+            *value = state.get(Register::try_from(index as u8).unwrap());
+        }
+        Self {
+            core,
+            xpsr: state.xpsr(),
+        }
+    }
+
+    /// Returns the first register differing from `other` with the values of both, `None`
+    /// for the xPSR.
+    fn difference(&self, other: &Registers) -> Option<(Option<Register>, u32, u32)> {
+        let core = self.core.iter().zip(other.core).enumerate();
+        for (index, (value, other)) in core {
+            if *value != other {
+                return Some((Register::try_from(index as u8).ok(), *value, other));
+            }
+        }
+        let (xpsr, other) = (self.xpsr & XPSR_MASK, other.xpsr & XPSR_MASK);
+        (xpsr != other).then_some((None, xpsr, other))
+    }
+}
+
+/// Processor the emulator is compared with.
+pub trait Target {
+    type Error;
+
+    /// Executes one instruction.
+    fn step(&mut self) -> Result<(), Self::Error>;
+
+    fn registers(&mut self) -> Result<Registers, Self::Error>;
+}
+
+/// Another emulator as target, e.g. one with a different configuration.
+impl<M: Memory, H: Hooks> Target for Emulator<M, H> {
+    type Error = Stop;
+
+    fn step(&mut self) -> Result<(), Self::Error> {
+        Emulator::step(self)
+    }
+
+    fn registers(&mut self) -> Result<Registers, Self::Error> {
+        Ok(Registers::from_state(&self.state))
+    }
+}
+
+/// First difference between the emulator and the target found by [`compare`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Divergence {
+    /// Instructions executed before, the instruction diverging is step 0 when first.
+    pub step: u64,
+    /// Address of the instruction diverging.
+    pub address: u32,
+    /// Register differing, `None` for the xPSR.
+    pub register: Option<Register>,
+    pub emulator: u32,
+    pub target: u32,
+}
+
+/// Steps `emulator` and `target`, started in the same state, up to `steps` instructions and
+/// returns the first instruction after which their registers differ.
+///
+/// Stops comparing when the emulator stops, at a breakpoint, sleep or lockup, without
+/// stepping the target.
+pub fn compare<M: Memory, H: Hooks, T: Target>(
+    emulator: &mut Emulator<M, H>,
+    target: &mut T,
+    steps: u64,
+) -> Result<Option<Divergence>, T::Error> {
+    for step in 0..steps {
+        let address = emulator.state.get(Register::PC);
+        if emulator.step().is_err() {
+            break;
+        }
+        target.step()?;
+        let expected = target.registers()?;
+        let actual = Registers::from_state(&emulator.state);
+        if let Some((register, emulator, target)) = actual.difference(&expected) {
+            return Ok(Some(Divergence {
+                step,
+                address,
+                register,
+                emulator,
+                target,
+            }));
+        }
+    }
+    Ok(None)
+}
+
+/// Client of the GDB remote serial protocol, controlling a target stopped in a GDB server.
+#[derive(Debug)]
+pub struct GdbRemote<S> {
+    stream: S,
+    /// Number of the xPSR in the `p` and `P` packets, 25 in the register layout of QEMU and
+    /// GDB without a target description, 16 for OpenOCD.
+    pub xpsr_register: u32,
+}
+
+impl GdbRemote<TcpStream> {
+    /// Connects to the GDB server at `address`, like `localhost:1234` for `qemu -s`.
+    pub fn connect(address: impl ToSocketAddrs) -> io::Result<Self> {
+        let stream = TcpStream::connect(address)?;
+        stream.set_nodelay(true)?;
+        Ok(Self::new(stream))
+    }
+}
+
+impl<S: Read + Write> GdbRemote<S> {
+    pub fn new(stream: S) -> Self {
+        Self {
+            stream,
+            xpsr_register: 25,
+        }
+    }
+
+    /// Sends the packet `data` and returns the reply, replies starting with `E` as errors.
+    pub fn command(&mut self, data: &str) -> io::Result<String> {
+        loop {
+            write!(self.stream, "${data}#{:02x}", checksum(data.as_bytes()))?;
+            self.stream.flush()?;
+            match self.byte()? {
+                b'+' => break,
+                b'-' => continue,
+                byte => return Err(protocol(&format!("expected ack, got {byte:#x}"))),
+            }
+        }
+        let reply = self.receive()?;
+        if reply.len() == 3 && reply.starts_with('E') {
+            return Err(io::Error::other(format!("target error {reply}")));
+        }
+        Ok(reply)
+    }
+
+    pub fn read_register(&mut self, number: u32) -> io::Result<u32> {
+        let reply = self.command(&format!("p{number:x}"))?;
+        let bytes = decode_hex(&reply)?;
+        let bytes: [u8; 4] = bytes
+            .get(..4)
+            .and_then(|bytes| bytes.try_into().ok())
+            .ok_or_else(|| protocol("short register"))?;
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    pub fn write_register(&mut self, number: u32, value: u32) -> io::Result<()> {
+        let value = encode_hex(&value.to_le_bytes());
+        self.expect_ok(&format!("P{number:x}={value}"))
+    }
+
+    pub fn read_memory(&mut self, address: u32, length: u32) -> io::Result<Vec<u8>> {
+        let reply = self.command(&format!("m{address:x},{length:x}"))?;
+        decode_hex(&reply)
+    }
+
+    pub fn write_memory(&mut self, address: u32, bytes: &[u8]) -> io::Result<()> {
+        let data = encode_hex(bytes);
+        self.expect_ok(&format!("M{address:x},{:x}:{data}", bytes.len()))
+    }
+
+    /// Writes the registers of `state` to the target, to start it in the state of the
+    /// emulator.
+    pub fn write_state(&mut self, state: &CpuState) -> io::Result<()> {
+        let registers = Registers::from_state(state);
+        for (number, value) in (0..).zip(registers.core) {
+            self.write_register(number, value)?;
+        }
+        self.write_register(self.xpsr_register, registers.xpsr)
+    }
+
+    fn expect_ok(&mut self, data: &str) -> io::Result<()> {
+        match self.command(data)?.as_str() {
+            "OK" => Ok(()),
+            reply => Err(protocol(&format!("unexpected reply {reply}"))),
+        }
+    }
+
+    fn byte(&mut self) -> io::Result<u8> {
+        let mut byte = [0];
+        self.stream.read_exact(&mut byte)?;
+        Ok(byte[0])
+    }
+
+    /// Receives a packet, acknowledging it, and returns its data run-length decoded.
+    fn receive(&mut self) -> io::Result<String> {
+        while self.byte()? != b'$' {}
+        let mut data = Vec::new();
+        loop {
+            match self.byte()? {
+                b'#' => break,
+                byte => data.push(byte),
+            }
+        }
+        let digits = [self.byte()?, self.byte()?];
+        let expected = decode_hex(&String::from_utf8_lossy(&digits))?;
+        if expected != [checksum(&data)] {
+            self.stream.write_all(b"-")?;
+            return self.receive();
+        }
+        self.stream.write_all(b"+")?;
+        let mut decoded = Vec::new();
+        let mut bytes = data.iter();
+        while let Some(&byte) = bytes.next() {
+            match (byte, decoded.last().copied()) {
+                (b'*', Some(repeated)) => {
+                    let count = bytes.next().ok_or_else(|| protocol("bad run length"))?;
+                    let count = (*count as usize).saturating_sub(29);
+                    decoded.extend(std::iter::repeat_n(repeated, count));
+                }
+                (byte, _) => decoded.push(byte),
+            }
+        }
+        String::from_utf8(decoded).map_err(|_| protocol("packet not ascii"))
+    }
+}
+
+impl<S: Read + Write> Target for GdbRemote<S> {
+    type Error = io::Error;
+
+    fn step(&mut self) -> io::Result<()> {
+        let reply = self.command("s")?;
+        match reply.as_bytes().first() {
+            Some(b'S' | b'T') => Ok(()),
+            _ => Err(protocol(&format!("target did not stop: {reply}"))),
+        }
+    }
+
+    fn registers(&mut self) -> io::Result<Registers> {
+        let mut registers = Registers::default();
+        for (number, value) in (0..).zip(&mut registers.core) {
+            *value = self.read_register(number)?;
+        }
+        registers.xpsr = self.read_register(self.xpsr_register)?;
+        Ok(registers)
+    }
+}
+
+fn protocol(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0, |sum, byte| sum.wrapping_add(*byte))
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+fn decode_hex(hex: &str) -> io::Result<Vec<u8>> {
+    (0..hex.len() / 2 * 2)
+        .step_by(2)
+        .map(|index| {
+            u8::from_str_radix(&hex[index..index + 2], 16).map_err(|_| protocol("bad hex"))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::memory::Ram;
+
+    #[test]
+    fn divergence() {
+        // movs r0, #1; adds r0, #1; adds r0, #1; bkpt 0
+        let code = [0x01, 0x20, 0x01, 0x30, 0x01, 0x30, 0x00, 0xbe];
+        let (mut bytes, mut other) = (code, code);
+        // The target adds 2 in the second add.
+        other[4] = 0x02;
+        let state = CpuState::new(0x2000_0000, 0);
+        let memory = Ram {
+            base: 0,
+            bytes: &mut bytes,
+        };
+        let mut emulator = Emulator::new(state.clone(), memory);
+        let memory = Ram {
+            base: 0,
+            bytes: &mut other,
+        };
+        let mut target = Emulator::new(state, memory);
+        let divergence = Divergence {
+            step: 2,
+            address: 0x04,
+            register: Some(Register::R0),
+            emulator: 3,
+            target: 4,
+        };
+        assert_eq!(
+            compare(&mut emulator, &mut target, 10),
+            Ok(Some(divergence))
+        );
+        target.memory.bytes[4] = 0x01;
+        emulator.state.set(Register::PC, 0);
+        target.state = emulator.state.clone();
+        assert_eq!(compare(&mut emulator, &mut target, 10), Ok(None));
+        assert_eq!(emulator.run(), Stop::Breakpoint(0));
+    }
+
+    /// Stream replying with `input` and recording the packets sent.
+    struct Script {
+        input: io::Cursor<Vec<u8>>,
+        output: Vec<u8>,
+    }
+
+    impl Read for Script {
+        fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+            self.input.read(buffer)
+        }
+    }
+
+    impl Write for Script {
+        fn write(&mut self, buffer: &[u8]) -> io::Result<usize> {
+            self.output.write(buffer)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn remote_protocol() {
+        // Acks and replies, the second run-length encoded as 0x0000_0100, and a stop.
+        let input = b"+$78563412#a4+$00*\"1#dd+$T05#b9+$E01#a6";
+        let mut remote = GdbRemote::new(Script {
+            input: io::Cursor::new(input.to_vec()),
+            output: Vec::new(),
+        });
+        assert_eq!(remote.read_register(0).unwrap(), 0x1234_5678);
+        assert_eq!(remote.read_register(0x19).unwrap(), 0x0100_0000);
+        remote.step().unwrap();
+        assert!(remote.read_memory(0, 4).is_err());
+        assert_eq!(remote.stream.output, b"$p0#a0+$p19#da+$s#73+$m0,4#fd+");
+    }
+}
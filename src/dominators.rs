@@ -0,0 +1,233 @@
+//! Dominator trees and natural loops of control flow graphs.
+//!
+//! # Example
+//! ```
+//! # use armv6_m_instruction_parser::{control_flow::ControlFlowGraph, dominators::{natural_loops, Dominators}, program::Program};
+//! // 0x00: movs r0, #0
+//! // 0x02: adds r0, #1; cmp r0, #9; bls.n 0x02
+//! // 0x08: bx lr
+//! let code = [0x00, 0x20, 0x01, 0x30, 0x09, 0x28, 0xfc, 0xd9, 0x70, 0x47];
+//! let cfg = ControlFlowGraph::new(&Program::new(&code, 0), &[]);
+//! let dominators = Dominators::new(&cfg, 0x00);
+//! assert_eq!(dominators.immediate_dominator(0x08), Some(0x02));
+//! let loops = natural_loops(&cfg, &dominators);
+//! assert_eq!(loops[0].header, 0x02);
+//! assert!(loops[0].is_self_loop());
+//! ```
+
+use alloc::{
+    collections::{BTreeMap, BTreeSet},
+    vec,
+    vec::Vec,
+};
+
+use crate::control_flow::ControlFlowGraph;
+
+/// Immediate dominators of the blocks of a [`ControlFlowGraph`] reached from an entry.
+///
+/// A block dominates another when every path from the entry to the other block goes through
+/// it. Blocks are named by their start address.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dominators {
+    entry: u32,
+    /// Immediate dominator of every block reached, the entry is its own.
+    immediate: BTreeMap<u32, u32>,
+}
+
+impl Dominators {
+    /// Computes the dominators of the blocks of `cfg` reached from the block starting at
+    /// `entry`.
+    pub fn new(cfg: &ControlFlowGraph, entry: u32) -> Self {
+        // Blocks in reverse postorder, with the position of every block in it.
+        let mut postorder = vec![];
+        let mut visited = BTreeSet::from([entry]);
+        let mut stack = vec![(entry, 0)];
+        while let Some((block, next)) = stack.last_mut() {
+            match cfg.successors(*block).get(*next) {
+                Some(&successor) => {
+                    *next += 1;
+                    if visited.insert(successor) {
+                        stack.push((successor, 0));
+                    }
+                }
+                None => {
+                    postorder.push(*block);
+                    stack.pop();
+                }
+            }
+        }
+        let position: BTreeMap<u32, usize> = postorder
+            .iter()
+            .enumerate()
+            .map(|(position, block)| (*block, position))
+            .collect();
+        let predecessors = predecessors(cfg, &postorder);
+
+        // The iterative algorithm of Cooper, Harvey and Kennedy.
+        let mut immediate = BTreeMap::from([(entry, entry)]);
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &block in postorder.iter().rev().skip(1) {
+                let mut dominator = None;
+                for &predecessor in &predecessors[&block] {
+                    if !immediate.contains_key(&predecessor) {
+                        continue;
+                    }
+                    dominator = Some(match dominator {
+                        None => predecessor,
+                        Some(mut other) => {
+                            let mut predecessor = predecessor;
+                            while predecessor != other {
+                                while position[&predecessor] < position[&other] {
+                                    predecessor = immediate[&predecessor];
+                                }
+                                while position[&other] < position[&predecessor] {
+                                    other = immediate[&other];
+                                }
+                            }
+                            other
+                        }
+                    });
+                }
+                if let Some(dominator) = dominator {
+                    if immediate.insert(block, dominator) != Some(dominator) {
+                        changed = true;
+                    }
+                }
+            }
+        }
+        Self { entry, immediate }
+    }
+
+    /// Start of the entry block.
+    pub fn entry(&self) -> u32 {
+        self.entry
+    }
+
+    /// Returns the immediate dominator of `block`, `None` for the entry and blocks not
+    /// reached from it.
+    pub fn immediate_dominator(&self, block: u32) -> Option<u32> {
+        let dominator = *self.immediate.get(&block)?;
+        (block != self.entry).then_some(dominator)
+    }
+
+    /// Returns `true` if `dominator` dominates `block`, every block reached dominates itself.
+    pub fn dominates(&self, dominator: u32, mut block: u32) -> bool {
+        if !self.immediate.contains_key(&block) {
+            return false;
+        }
+        loop {
+            if block == dominator {
+                return true;
+            }
+            match self.immediate_dominator(block) {
+                Some(next) => block = next,
+                None => return false,
+            }
+        }
+    }
+
+    /// Returns `true` if `block` is reached from the entry.
+    pub fn is_reached(&self, block: u32) -> bool {
+        self.immediate.contains_key(&block)
+    }
+}
+
+/// Natural loop of a back edge, an edge to a block dominating its source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Loop {
+    /// Block every iteration starts with.
+    pub header: u32,
+    /// Blocks branching back to the header.
+    pub latches: Vec<u32>,
+    /// Blocks of the loop, including the header.
+    pub blocks: BTreeSet<u32>,
+}
+
+impl Loop {
+    /// Returns `true` if the loop is a single block branching to itself, like a busy-wait
+    /// polling a register.
+    pub fn is_self_loop(&self) -> bool {
+        self.blocks.len() == 1
+    }
+}
+
+/// Returns the natural loops of `cfg` reached from the entry of `dominators`, ordered by
+/// header. Back edges to the same header form one loop.
+pub fn natural_loops(cfg: &ControlFlowGraph, dominators: &Dominators) -> Vec<Loop> {
+    let reached: Vec<u32> = dominators.immediate.keys().copied().collect();
+    let predecessors = predecessors(cfg, &reached);
+    let mut loops: BTreeMap<u32, Loop> = BTreeMap::new();
+    for &latch in &reached {
+        for &header in cfg.successors(latch) {
+            if !dominators.dominates(header, latch) {
+                continue;
+            }
+            let natural = loops.entry(header).or_insert_with(|| Loop {
+                header,
+                latches: vec![],
+                blocks: BTreeSet::from([header]),
+            });
+            natural.latches.push(latch);
+            let mut work = vec![latch];
+            while let Some(block) = work.pop() {
+                if natural.blocks.insert(block) {
+                    work.extend(&predecessors[&block]);
+                }
+            }
+        }
+    }
+    loops.into_values().collect()
+}
+
+/// Returns the predecessors among `blocks` of every block of `blocks`.
+fn predecessors(cfg: &ControlFlowGraph, blocks: &[u32]) -> BTreeMap<u32, Vec<u32>> {
+    let mut predecessors: BTreeMap<u32, Vec<u32>> =
+        blocks.iter().map(|block| (*block, vec![])).collect();
+    for &block in blocks {
+        for successor in cfg.successors(block) {
+            if let Some(list) = predecessors.get_mut(successor) {
+                list.push(block);
+            }
+        }
+    }
+    predecessors
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::program::Program;
+
+    #[test]
+    fn loops() {
+        // 0x00: movs r0, #0
+        // 0x02: cmp r0, #9; bhi.n 0x0c
+        // 0x06: adds r0, #1; b.n 0x02
+        // 0x0a: nop
+        // 0x0c: bx lr
+        let code = [
+            0x00, 0x20, 0x09, 0x28, 0x02, 0xd8, 0x01, 0x30, 0xfb, 0xe7, 0x00, 0xbf, 0x70, 0x47,
+        ];
+        let cfg = ControlFlowGraph::new(&Program::new(&code, 0), &[]);
+        let dominators = Dominators::new(&cfg, 0x00);
+        assert_eq!(dominators.immediate_dominator(0x00), None);
+        assert_eq!(dominators.immediate_dominator(0x02), Some(0x00));
+        assert_eq!(dominators.immediate_dominator(0x06), Some(0x02));
+        assert_eq!(dominators.immediate_dominator(0x0c), Some(0x02));
+        assert!(!dominators.is_reached(0x0a));
+        assert!(dominators.dominates(0x02, 0x0c) && !dominators.dominates(0x06, 0x0c));
+
+        let loops = natural_loops(&cfg, &dominators);
+        assert_eq!(
+            loops,
+            [Loop {
+                header: 0x02,
+                latches: vec![0x06],
+                blocks: BTreeSet::from([0x02, 0x06])
+            }]
+        );
+        assert!(!loops[0].is_self_loop());
+    }
+}
@@ -0,0 +1,264 @@
+//! Source lines of instructions from the DWARF line tables of ELF files, for listings with
+//! the source interleaved like `objdump -S`.
+//!
+//! # Example
+//! ```no_run
+//! # use armv6_m_instruction_parser::{dwarf::{interleave, LineTable, ListingLine, Sources}, elf::ElfImage};
+//! let data = std::fs::read("firmware.elf").unwrap();
+//! let (image, lines) = (ElfImage::parse(&data).unwrap(), LineTable::parse(&data).unwrap());
+//! let mut sources = Sources::new();
+//! for line in interleave(&image, &lines) {
+//!     match line {
+//!         ListingLine::Source(location) => match sources.line(&location) {
+//!             Some(text) => println!("{text}"),
+//!             None => println!("{}:{}", location.file, location.line),
+//!         },
+//!         ListingLine::Instruction(address, instruction) => {
+//!             println!("{address:#010x}: {instruction:?}")
+//!         }
+//!     }
+//! }
+//! ```
+
+use std::{borrow::Cow, collections::HashMap, fs, path::PathBuf};
+
+use gimli::{AttributeValue, Dwarf, EndianSlice, RunTimeEndian, SectionId, Unit};
+use object::{read::elf::ElfFile32, LittleEndian, Object, ObjectSection};
+
+use crate::{
+    elf::{ElfError, ElfImage},
+    instructons::Instruction,
+    Error,
+};
+
+type Slice<'a> = EndianSlice<'a, RunTimeEndian>;
+
+/// Source file and line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SourceLocation<'a> {
+    pub file: &'a str,
+    /// Line numbered from 1, 0 for code not from a line.
+    pub line: u32,
+}
+
+/// Source lines of the addresses of an image, from the `.debug_line` section.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LineTable {
+    /// Paths of the source files.
+    files: Vec<String>,
+    /// Start of every row in address order, with its file and line, `None` for the end of a
+    /// sequence.
+    rows: Vec<(u32, Option<(usize, u32)>)>,
+}
+
+impl LineTable {
+    /// Reads the line tables of the ELF file in `data`. Files without debug information give
+    /// an empty table.
+    pub fn parse(data: &[u8]) -> Result<Self, ElfError> {
+        let file = ElfFile32::<LittleEndian>::parse(data)?;
+        let mut sections = HashMap::new();
+        for id in [
+            SectionId::DebugAbbrev,
+            SectionId::DebugInfo,
+            SectionId::DebugLine,
+            SectionId::DebugLineStr,
+            SectionId::DebugStr,
+            SectionId::DebugStrOffsets,
+        ] {
+            if let Some(section) = file.section_by_name(id.name()) {
+                sections.insert(id, section.uncompressed_data()?);
+            }
+        }
+        Self::from_sections(|id| sections.get(&id).map_or(&[][..], Cow::as_ref))
+    }
+
+    /// Reads the line tables of the sections returned by `section`.
+    fn from_sections<'a>(section: impl Fn(SectionId) -> &'a [u8]) -> Result<Self, ElfError> {
+        let dwarf: Dwarf<Slice> = Dwarf::load(|id| {
+            Ok::<_, gimli::Error>(EndianSlice::new(section(id), RunTimeEndian::Little))
+        })?;
+        let mut table = LineTable::default();
+        let mut files: HashMap<String, usize> = HashMap::new();
+        let mut units = dwarf.units();
+        while let Some(header) = units.next()? {
+            let unit = dwarf.unit(header)?;
+            let Some(program) = unit.line_program.clone() else {
+                continue;
+            };
+            let mut rows = program.rows();
+            while let Some((header, row)) = rows.next_row()? {
+                let address = row.address() as u32;
+                if row.end_sequence() {
+                    table.rows.push((address, None));
+                    continue;
+                }
+                let path = match row.file(header) {
+                    Some(file) => {
+                        let mut path = PathBuf::new();
+                        if let Some(directory) = &unit.comp_dir {
+                            path.push(&*directory.to_string_lossy());
+                        }
+                        if let Some(directory) = file.directory(header) {
+                            path.push(&*string(&dwarf, &unit, directory)?);
+                        }
+                        path.push(&*string(&dwarf, &unit, file.path_name())?);
+                        path.to_string_lossy().into_owned()
+                    }
+                    None => String::new(),
+                };
+                let index = *files.entry(path).or_insert_with_key(|path| {
+                    table.files.push(path.clone());
+                    table.files.len() - 1
+                });
+                let line = row.line().map_or(0, |line| line.get() as u32);
+                table.rows.push((address, Some((index, line))));
+            }
+        }
+        // Sequences ending where another starts end first.
+        table
+            .rows
+            .sort_by_key(|&(address, location)| (address, location.is_some()));
+        Ok(table)
+    }
+
+    /// Returns the source location of the code at `address`.
+    pub fn location(&self, address: u32) -> Option<SourceLocation<'_>> {
+        let index = self.rows.partition_point(|&(start, _)| start <= address);
+        let (file, line) = self.rows.get(index.checked_sub(1)?)?.1?;
+        Some(SourceLocation {
+            file: &self.files[file],
+            line,
+        })
+    }
+
+    /// Paths of the source files.
+    pub fn files(&self) -> &[String] {
+        &self.files
+    }
+}
+
+fn string<'a>(
+    dwarf: &Dwarf<Slice<'a>>,
+    unit: &Unit<Slice<'a>>,
+    value: AttributeValue<Slice<'a>>,
+) -> Result<Cow<'a, str>, gimli::Error> {
+    Ok(dwarf.attr_string(unit, value)?.to_string_lossy())
+}
+
+/// Line of a listing from [`interleave`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ListingLine<'a> {
+    /// Source line of the instructions following it.
+    Source(SourceLocation<'a>),
+    Instruction(u32, Result<Instruction, Error>),
+}
+
+/// Returns the instructions of `image` preceded by their source location wherever it
+/// changes.
+pub fn interleave<'a>(
+    image: &'a ElfImage,
+    lines: &'a LineTable,
+) -> impl Iterator<Item = ListingLine<'a>> + 'a {
+    let mut previous = None;
+    image
+        .instructions()
+        .flat_map(move |(address, instruction)| {
+            let location = lines.location(address);
+            let source = match location {
+                Some(location) if previous != Some(location) => Some(ListingLine::Source(location)),
+                _ => None,
+            };
+            previous = location;
+            source
+                .into_iter()
+                .chain([ListingLine::Instruction(address, instruction)])
+        })
+}
+
+/// Source files read on demand for showing the text of [`SourceLocation`]s.
+#[derive(Debug, Default)]
+pub struct Sources {
+    /// Lines of every file read, `None` if it could not be read.
+    files: HashMap<String, Option<Vec<String>>>,
+}
+
+impl Sources {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the text of the source line at `location`, `None` if the file can not be
+    /// read or is shorter.
+    pub fn line(&mut self, location: &SourceLocation) -> Option<&str> {
+        let lines = self
+            .files
+            .entry(location.file.to_string())
+            .or_insert_with_key(|path| {
+                let text = fs::read(path).ok()?;
+                Some(
+                    String::from_utf8_lossy(&text)
+                        .lines()
+                        .map(str::to_string)
+                        .collect(),
+                )
+            });
+        let index = location.line.checked_sub(1)? as usize;
+        lines.as_ref()?.get(index).map(String::as_str)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn line_table() {
+        // Compile unit in /src with the line program at offset 0.
+        let abbrev = [0x01, 0x11, 0x00, 0x10, 0x17, 0x1b, 0x08, 0x00, 0x00, 0x00];
+        let mut info = vec![17, 0, 0, 0, 4, 0, 0, 0, 0, 0, 4, 0x01, 0, 0, 0, 0];
+        info.extend(b"/src\0");
+        // DWARF 4 line program of main.c: line 10 at 0x1000, line 11 at 0x1002 until 0x1008.
+        let mut line = vec![56, 0, 0, 0, 4, 0, 30, 0, 0, 0, 1, 1, 1, 0xfb, 14, 13];
+        line.extend([0, 1, 1, 1, 1, 0, 0, 0, 1, 0, 0, 1, 0]);
+        line.extend(b"main.c\0\0\0\0\0");
+        line.extend([0x00, 0x05, 0x02, 0x00, 0x10, 0x00, 0x00, 0x03, 0x09, 0x01]);
+        line.extend([0x02, 0x02, 0x03, 0x01, 0x01, 0x02, 0x06, 0x00, 0x01, 0x01]);
+        let table = LineTable::from_sections(|id| match id {
+            SectionId::DebugAbbrev => &abbrev,
+            SectionId::DebugInfo => &info,
+            SectionId::DebugLine => &line,
+            _ => &[],
+        })
+        .unwrap();
+
+        assert_eq!(table.files(), ["/src/main.c"]);
+        let location = |file, line| Some(SourceLocation { file, line });
+        assert_eq!(table.location(0x1000), location("/src/main.c", 10));
+        assert_eq!(table.location(0x1006), location("/src/main.c", 11));
+        assert_eq!(table.location(0x1008), None);
+        assert_eq!(table.location(0x0ffe), None);
+
+        // movs r0, #1; bl; bx lr
+        let image = ElfImage {
+            entry: 0x1000,
+            sections: vec![crate::elf::Section {
+                name: ".text".to_string(),
+                address: 0x1000,
+                load_address: 0x1000,
+                data: vec![0x01, 0x20, 0x00, 0xf0, 0x7e, 0xf8, 0x70, 0x47],
+            }],
+            symbols: vec![],
+            relocations: vec![],
+        };
+        let listing: Vec<_> = interleave(&image, &table)
+            .map(|line| match line {
+                ListingLine::Source(location) => Err(location.line),
+                ListingLine::Instruction(address, _) => Ok(address),
+            })
+            .collect();
+        assert_eq!(
+            listing,
+            [Err(10), Ok(0x1000), Err(11), Ok(0x1002), Ok(0x1006)]
+        );
+    }
+}
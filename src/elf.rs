@@ -0,0 +1,682 @@
+//! Loading of the executable sections and symbols of ELF files.
+//!
+//! # Example
+//! ```no_run
+//! # use armv6_m_instruction_parser::elf::disassemble_elf;
+//! let image = disassemble_elf("firmware.elf").unwrap();
+//! for (address, instruction) in image.instructions() {
+//!     match image.symbol(address) {
+//!         Some(symbol) if symbol.address == address => println!("{}:", symbol.name),
+//!         _ => {}
+//!     }
+//!     println!("{address:#010x}: {instruction:?}");
+//! }
+//! ```
+
+use std::{collections::HashMap, fs, io, path::Path};
+
+use object::{
+    elf::{
+        EM_ARM, ET_REL, PT_LOAD, R_ARM_ABS32, R_ARM_THM_JUMP24, R_ARM_THM_PC11, R_ARM_THM_PC22,
+        R_ARM_THM_PC8, R_ARM_THM_PC9,
+    },
+    read::elf::{ElfFile32, FileHeader, ProgramHeader},
+    LittleEndian, Object, ObjectSection, ObjectSymbol, RelocationFlags, RelocationTarget,
+    SectionKind, SymbolKind, SymbolSection,
+};
+
+use crate::{
+    image::MemoryImage,
+    instructons::{Instruction, Operation},
+    parse,
+    patcher::retarget,
+    symbols::{self, SymbolTable},
+    AddressedDisassembly, Error, ThumbInstructions,
+};
+
+/// Executable section of an ELF file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Section {
+    pub name: String,
+    /// Address the section runs at.
+    pub address: u32,
+    /// Address the section is stored at, different from `address` for code copied to RAM
+    /// at startup.
+    pub load_address: u32,
+    pub data: Vec<u8>,
+}
+
+impl Section {
+    /// Returns the instructions of the section with their addresses.
+    pub fn instructions(&self) -> AddressedDisassembly<'_> {
+        self.data.thumb_instructions_at(self.address)
+    }
+
+    /// Returns whether `address` is in the section.
+    pub fn contains(&self, address: u32) -> bool {
+        address.wrapping_sub(self.address) < self.data.len() as u32
+    }
+}
+
+/// Symbol defined in an ELF file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    pub name: String,
+    /// Address of the symbol, without the Thumb bit for functions.
+    pub address: u32,
+    /// Size in bytes, 0 when unknown.
+    pub size: u32,
+    pub function: bool,
+}
+
+/// Type of a [`Relocation`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RelocationKind {
+    /// `R_ARM_ABS32`, a word holding the address.
+    Abs32,
+    /// `R_ARM_THM_CALL`, a `bl`.
+    ThumbCall,
+    /// `R_ARM_THM_JUMP24`, a `b.w`, not available on ARMv6-M.
+    ThumbJump24,
+    /// `R_ARM_THM_JUMP11`, a `b`.
+    ThumbJump11,
+    /// `R_ARM_THM_JUMP8`, a conditional `b`.
+    ThumbJump8,
+    /// `R_ARM_THM_PC8`, a `ldr` or `adr` relative to `pc`.
+    ThumbPc8,
+    /// Other type, by number.
+    Other(u32),
+}
+
+impl RelocationKind {
+    fn from_type(r_type: u32) -> Self {
+        match r_type {
+            R_ARM_ABS32 => RelocationKind::Abs32,
+            R_ARM_THM_PC22 => RelocationKind::ThumbCall,
+            R_ARM_THM_JUMP24 => RelocationKind::ThumbJump24,
+            R_ARM_THM_PC11 => RelocationKind::ThumbJump11,
+            R_ARM_THM_PC9 => RelocationKind::ThumbJump8,
+            R_ARM_THM_PC8 => RelocationKind::ThumbPc8,
+            r_type => RelocationKind::Other(r_type),
+        }
+    }
+}
+
+/// Relocation of an executable section, the place of a reference to a symbol the linker
+/// fills in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Relocation {
+    /// Address of the place, the instruction or word.
+    pub address: u32,
+    pub kind: RelocationKind,
+    /// Name of the symbol referenced, or of the section for references to a section.
+    pub symbol: String,
+    /// Address of the symbol, with the Thumb bit for functions. `None` for symbols not
+    /// defined in the executable sections, like those of other object files.
+    pub target: Option<u32>,
+    /// Addend of `RELA` relocations, 0 for `REL` relocations holding it in the place.
+    pub addend: i64,
+}
+
+/// Error from loading an ELF file.
+#[derive(Debug)]
+pub enum ElfError {
+    /// Reading the file failed.
+    Io(io::Error),
+    /// The file is not a valid little endian 32 bit ELF file.
+    Parse(object::Error),
+    /// The file is not for an ARM processor.
+    NotArm,
+    /// The DWARF debug information is not valid.
+    #[cfg(feature = "gimli")]
+    Dwarf(gimli::Error),
+}
+
+impl core::fmt::Display for ElfError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            ElfError::Io(error) => write!(f, "reading failed: {error}"),
+            ElfError::Parse(error) => write!(f, "invalid ELF file: {error}"),
+            ElfError::NotArm => f.write_str("ELF file is not for ARM"),
+            #[cfg(feature = "gimli")]
+            ElfError::Dwarf(error) => write!(f, "invalid debug information: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for ElfError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ElfError::Io(error) => Some(error),
+            ElfError::Parse(error) => Some(error),
+            ElfError::NotArm => None,
+            #[cfg(feature = "gimli")]
+            ElfError::Dwarf(error) => Some(error),
+        }
+    }
+}
+
+impl From<io::Error> for ElfError {
+    fn from(error: io::Error) -> Self {
+        ElfError::Io(error)
+    }
+}
+
+impl From<object::Error> for ElfError {
+    fn from(error: object::Error) -> Self {
+        ElfError::Parse(error)
+    }
+}
+
+#[cfg(feature = "gimli")]
+impl From<gimli::Error> for ElfError {
+    fn from(error: gimli::Error) -> Self {
+        ElfError::Dwarf(error)
+    }
+}
+
+/// Executable sections and symbols of an ELF file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ElfImage {
+    /// Entry point, without the Thumb bit.
+    pub entry: u32,
+    /// Executable sections in address order.
+    pub sections: Vec<Section>,
+    /// Symbols of the executable sections in address order, without the `$t` and `$d`
+    /// mapping symbols.
+    pub symbols: Vec<Symbol>,
+    /// Relocations of the executable sections in address order.
+    pub relocations: Vec<Relocation>,
+}
+
+impl ElfImage {
+    /// Parses the ELF file in `data`.
+    ///
+    /// The executable sections of relocatable object files, all at address 0 in the file, are
+    /// placed one after the other from 0. Their `bl`s and words with a relocation against a
+    /// symbol of the executable sections are relocated, so calls between the functions
+    /// of the object file have their targets.
+    pub fn parse(data: &[u8]) -> Result<Self, ElfError> {
+        let file = ElfFile32::<LittleEndian>::parse(data)?;
+        let endian = file.endian();
+        if file.elf_header().e_machine(endian) != EM_ARM {
+            return Err(ElfError::NotArm);
+        }
+        let relocatable = file.elf_header().e_type(endian) == ET_REL;
+        let segments = file.elf_program_headers();
+
+        let mut sections = Vec::new();
+        let mut bases = HashMap::new();
+        let mut next = 0u32;
+        for section in file.sections() {
+            if section.kind() != SectionKind::Text {
+                continue;
+            }
+            let address = match relocatable {
+                true => next.next_multiple_of(section.align().max(1) as u32),
+                false => section.address() as u32,
+            };
+            next = address.wrapping_add(section.size() as u32);
+            bases.insert(section.index(), address);
+            let header = section.elf_section_header();
+            let offset = header.sh_offset.get(endian);
+            // The load address follows from the segment holding the section in the file.
+            let load_address = segments
+                .iter()
+                .filter(|segment| segment.p_type(endian) == PT_LOAD)
+                .find(|segment| {
+                    offset.wrapping_sub(segment.p_offset(endian)) < segment.p_filesz(endian)
+                })
+                .map_or(address, |segment| {
+                    segment
+                        .p_paddr(endian)
+                        .wrapping_add(offset - segment.p_offset(endian))
+                });
+            sections.push(Section {
+                name: section.name()?.to_string(),
+                address,
+                load_address,
+                data: section.data()?.to_vec(),
+            });
+        }
+        sections.sort_by_key(|section| section.address);
+
+        // Symbol values of relocatable object files are offsets into their section.
+        let value = |section: SymbolSection, value: u64| {
+            let SymbolSection::Section(index) = section else {
+                return None;
+            };
+            let base = *bases.get(&index)?;
+            Some((value as u32).wrapping_add(if relocatable { base } else { 0 }))
+        };
+
+        let mut symbols = Vec::new();
+        for symbol in file.symbols() {
+            let Some(address) = value(symbol.section(), symbol.address()) else {
+                continue;
+            };
+            let name = symbol.name()?;
+            if name.is_empty() || name.starts_with('$') || symbol.kind() == SymbolKind::Section {
+                continue;
+            }
+            let function = symbol.kind() == SymbolKind::Text;
+            symbols.push(Symbol {
+                name: name.to_string(),
+                address: address & !(function as u32),
+                size: symbol.size() as u32,
+                function,
+            });
+        }
+        symbols.sort_by_key(|symbol| symbol.address);
+
+        let mut relocations = Vec::new();
+        for section in file.sections() {
+            let Some(&base) = bases.get(&section.index()) else {
+                continue;
+            };
+            for (offset, relocation) in section.relocations() {
+                let (RelocationFlags::Elf { r_type }, RelocationTarget::Symbol(index)) =
+                    (relocation.flags(), relocation.target())
+                else {
+                    continue;
+                };
+                let symbol = file.symbol_by_index(index)?;
+                let name = match (symbol.kind(), symbol.section_index()) {
+                    (SymbolKind::Section, Some(index)) => file.section_by_index(index)?.name()?,
+                    _ => symbol.name()?,
+                };
+                relocations.push(Relocation {
+                    address: base.wrapping_add(offset as u32),
+                    kind: RelocationKind::from_type(r_type),
+                    symbol: name.to_string(),
+                    target: value(symbol.section(), symbol.address()),
+                    addend: relocation.addend(),
+                });
+            }
+        }
+        relocations.sort_by_key(|relocation| relocation.address);
+        if relocatable {
+            for relocation in &relocations {
+                relocate(&mut sections, relocation);
+            }
+        }
+
+        Ok(ElfImage {
+            entry: file.entry() as u32 & !1,
+            sections,
+            symbols,
+            relocations,
+        })
+    }
+
+    /// Returns the relocation of the instruction or word at `address`.
+    pub fn relocation(&self, address: u32) -> Option<&Relocation> {
+        let index = self
+            .relocations
+            .partition_point(|relocation| relocation.address < address);
+        self.relocations
+            .get(index)
+            .filter(|relocation| relocation.address == address)
+    }
+
+    /// Returns the instructions of all sections with their addresses.
+    pub fn instructions(&self) -> impl Iterator<Item = (u32, Result<Instruction, Error>)> + '_ {
+        self.sections.iter().flat_map(Section::instructions)
+    }
+
+    /// Returns the executable sections at the addresses they run at, with the entry point.
+    pub fn memory_image(&self) -> MemoryImage {
+        let mut image = MemoryImage::new();
+        for section in &self.sections {
+            image.insert(section.address, &section.data);
+        }
+        image.entry_points.push(self.entry);
+        image
+    }
+
+    /// Returns the symbols for naming addresses.
+    pub fn symbol_table(&self) -> SymbolTable {
+        self.symbols
+            .iter()
+            .map(|symbol| symbols::Symbol {
+                name: symbol.name.clone(),
+                address: symbol.address,
+                size: symbol.size,
+            })
+            .collect()
+    }
+
+    /// Returns the section containing `address`.
+    pub fn section(&self, address: u32) -> Option<&Section> {
+        self.sections
+            .iter()
+            .find(|section| section.contains(address))
+    }
+
+    /// Returns the symbol containing `address`, the closest one before it for symbols
+    /// without a size.
+    pub fn symbol(&self, address: u32) -> Option<&Symbol> {
+        let index = self
+            .symbols
+            .partition_point(|symbol| symbol.address <= address);
+        let symbol = self.symbols[..index].last()?;
+        match symbol.size {
+            0 => Some(symbol),
+            size => (address - symbol.address < size).then_some(symbol),
+        }
+    }
+
+    /// Returns the function symbols in address order.
+    pub fn functions(&self) -> impl Iterator<Item = &Symbol> {
+        self.symbols.iter().filter(|symbol| symbol.function)
+    }
+
+    /// Returns the function named `name`.
+    pub fn function(&self, name: &str) -> Option<&Symbol> {
+        self.functions().find(|symbol| symbol.name == name)
+    }
+
+    /// Returns the bytes of `function`, up to the next symbol or the end of its section for
+    /// functions without a size.
+    pub fn function_bytes(&self, function: &Symbol) -> Option<&[u8]> {
+        let section = self.section(function.address)?;
+        let start = (function.address - section.address) as usize;
+        let end = match function.size {
+            0 => self
+                .symbols
+                .iter()
+                .find(|symbol| symbol.address > function.address)
+                .map_or(section.data.len(), |symbol| {
+                    ((symbol.address - section.address) as usize).min(section.data.len())
+                }),
+            size => start + size as usize,
+        };
+        section.data.get(start..end)
+    }
+
+    /// Returns the instructions of the function named `name` with their addresses.
+    pub fn disassemble_function(&self, name: &str) -> Option<AddressedDisassembly<'_>> {
+        let function = self.function(name)?;
+        let bytes = self.function_bytes(function)?;
+        Some(bytes.thumb_instructions_at(function.address))
+    }
+}
+
+/// Applies `relocation` to the `bl` or word at its place, if its symbol is defined.
+fn relocate(sections: &mut [Section], relocation: &Relocation) {
+    let Some(target) = relocation.target else {
+        return;
+    };
+    let Some(section) = sections
+        .iter_mut()
+        .find(|section| section.contains(relocation.address))
+    else {
+        return;
+    };
+    let offset = (relocation.address - section.address) as usize;
+    let addend = relocation.addend as u32;
+    match relocation.kind {
+        RelocationKind::Abs32 => {
+            let Some(place) = section.data.get_mut(offset..offset + 4) else {
+                return;
+            };
+            let word = u32::from_le_bytes(place.try_into().unwrap());
+            let word = target.wrapping_add(word).wrapping_add(addend);
+            place.copy_from_slice(&word.to_le_bytes());
+        }
+        RelocationKind::ThumbCall => {
+            let Ok(instruction) = parse(&section.data[offset..]) else {
+                return;
+            };
+            let Operation::BL { imm } = instruction.operation else {
+                return;
+            };
+            // The branch offset of the place is the addend, usually -4 for pc.
+            let destination = target
+                .wrapping_add(imm)
+                .wrapping_add(4)
+                .wrapping_add(addend);
+            if let Ok(encoding) = retarget(&instruction.operation, relocation.address, destination)
+            {
+                section.data[offset..offset + 4].copy_from_slice(encoding.as_bytes());
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Reads the ELF file at `path`.
+pub fn disassemble_elf(path: impl AsRef<Path>) -> Result<ElfImage, ElfError> {
+    ElfImage::parse(&fs::read(path)?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn half(out: &mut Vec<u8>, values: &[u16]) {
+        out.extend(values.iter().flat_map(|value| value.to_le_bytes()));
+    }
+
+    fn word(out: &mut Vec<u8>, values: &[u32]) {
+        out.extend(values.iter().flat_map(|value| value.to_le_bytes()));
+    }
+
+    /// Symbol table of symbols with the name offset, value, size, info and section.
+    fn symtab(symbols: &[(u32, u32, u32, u8, u16)]) -> Vec<u8> {
+        let mut symtab = Vec::new();
+        for &(name, value, size, info, section) in symbols {
+            word(&mut symtab, &[name, value, size]);
+            symtab.extend([info, 0]);
+            half(&mut symtab, &[section]);
+        }
+        symtab
+    }
+
+    /// ELF file of type `kind` with the sections with their name, type, flags, address,
+    /// link, info and entry size, and segments of a section at a load address.
+    fn elf(
+        kind: u16,
+        entry: u32,
+        sections: &[(&str, [u32; 6], Vec<u8>)],
+        segments: &[(usize, u32)],
+    ) -> Vec<u8> {
+        let mut elf = vec![0; (52 + 32 * segments.len()).next_multiple_of(4)];
+        let mut names = b"\0.shstrtab\0".to_vec();
+        let mut headers = vec![[0; 10]];
+        for (name, [kind, flags, address, link, info, entsize], data) in sections {
+            let (offset, size) = (elf.len() as u32, data.len() as u32);
+            headers.push([
+                names.len() as u32,
+                *kind,
+                *flags,
+                *address,
+                offset,
+                size,
+                *link,
+                *info,
+                4,
+                *entsize,
+            ]);
+            names.extend(name.as_bytes());
+            names.push(0);
+            elf.extend(data);
+            elf.resize(elf.len().next_multiple_of(4), 0);
+        }
+        headers.push([1, 3, 0, 0, elf.len() as u32, names.len() as u32, 0, 0, 1, 0]);
+        elf.extend(&names);
+        elf.resize(elf.len().next_multiple_of(4), 0);
+        let shoff = elf.len() as u32;
+        for header in &headers {
+            word(&mut elf, header);
+        }
+
+        let mut header = b"\x7fELF\x01\x01\x01".to_vec();
+        header.resize(16, 0);
+        half(&mut header, &[kind, 40]);
+        let phoff = if segments.is_empty() { 0 } else { 52 };
+        word(&mut header, &[1, entry, phoff, shoff, 0x0500_0000]);
+        let (phnum, shnum) = (segments.len() as u16, headers.len() as u16);
+        half(&mut header, &[52, 32, phnum, 40, shnum, shnum - 1]);
+        for &(section, load_address) in segments {
+            let [_, _, _, address, offset, size, ..] = headers[section];
+            word(
+                &mut header,
+                &[PT_LOAD, offset, address, load_address, size, size, 5, 4],
+            );
+        }
+        elf[..header.len()].copy_from_slice(&header);
+        elf
+    }
+
+    /// Executable with `.text` at 0x1000 and `.ramfunc` at 0x2000_0000 loaded at 0x1008.
+    fn executable() -> Vec<u8> {
+        let code = vec![0x01, 0x20, 0x00, 0xf0, 0x7e, 0xf8, 0x70, 0x47];
+        let symbols = symtab(&[
+            (0, 0, 0, 0x00, 0),
+            (6, 0x1000, 0, 0x00, 1),
+            (1, 0x1001, 8, 0x12, 1),
+            (9, 0x2000_0001, 2, 0x12, 2),
+        ]);
+        elf(
+            2,
+            0x1001,
+            &[
+                (".text", [1, 6, 0x1000, 0, 0, 0], code),
+                (".ramfunc", [1, 6, 0x2000_0000, 0, 0, 0], vec![0x70, 0x47]),
+                (".symtab", [2, 0, 0, 4, 2, 16], symbols),
+                (
+                    ".strtab",
+                    [3, 0, 0, 0, 0, 0],
+                    b"\0main\0$t\0ram_func\0".to_vec(),
+                ),
+            ],
+            &[(1, 0x1000), (2, 0x1008)],
+        )
+    }
+
+    #[test]
+    fn executable_sections() {
+        let image = ElfImage::parse(&executable()).unwrap();
+        assert_eq!(image.entry, 0x1000);
+        let sections: Vec<_> = image
+            .sections
+            .iter()
+            .map(|section| (section.name.as_str(), section.address, section.load_address))
+            .collect();
+        assert_eq!(
+            sections,
+            [(".text", 0x1000, 0x1000), (".ramfunc", 0x2000_0000, 0x1008)]
+        );
+        let addresses: Vec<u32> = image.instructions().map(|(address, _)| address).collect();
+        assert_eq!(addresses, [0x1000, 0x1002, 0x1006, 0x2000_0000]);
+        assert_eq!(
+            image.symbols[0],
+            Symbol {
+                name: "main".to_string(),
+                address: 0x1000,
+                size: 8,
+                function: true,
+            }
+        );
+        assert_eq!(image.symbol(0x1006).unwrap().name, "main");
+        assert_eq!(image.symbol(0x2000_0000).unwrap().name, "ram_func");
+        assert!(image.symbol(0x1008).is_none());
+        assert_eq!(image.section(0x2000_0001).unwrap().name, ".ramfunc");
+        let functions: Vec<&str> = image
+            .functions()
+            .map(|symbol| symbol.name.as_str())
+            .collect();
+        assert_eq!(functions, ["main", "ram_func"]);
+        let main: Vec<u32> = image
+            .disassemble_function("main")
+            .unwrap()
+            .map(|(address, _)| address)
+            .collect();
+        assert_eq!(main, [0x1000, 0x1002, 0x1006]);
+        assert!(image.disassemble_function("HAL_UART_Transmit").is_none());
+        let memory = image.memory_image();
+        assert_eq!(memory.segments().len(), 2);
+        assert_eq!(memory.entries(0), [0x1000]);
+        assert_eq!(image.symbol_table().label(0x1002), "main+0x2");
+    }
+
+    #[test]
+    fn relocatable_object() {
+        // a_func: bl b_func; bl puts
+        // b_func: ldr r0, [pc, #0]; bx lr; .word b_func
+        let relocations = |relocations: &[(u32, u32)]| {
+            let mut rel = Vec::new();
+            for &(offset, info) in relocations {
+                word(&mut rel, &[offset, info]);
+            }
+            rel
+        };
+        let object = elf(
+            1,
+            0,
+            &[
+                (
+                    ".text.a",
+                    [1, 6, 0, 0, 0, 0],
+                    vec![0xff, 0xf7, 0xfe, 0xff, 0xff, 0xf7, 0xfe, 0xff],
+                ),
+                (
+                    ".text.b",
+                    [1, 6, 0, 0, 0, 0],
+                    vec![0x00, 0x48, 0x70, 0x47, 0, 0, 0, 0],
+                ),
+                (
+                    ".rel.text.a",
+                    [9, 0x40, 0, 5, 1, 8],
+                    relocations(&[(0, 2 << 8 | 10), (4, 3 << 8 | 10)]),
+                ),
+                (
+                    ".rel.text.b",
+                    [9, 0x40, 0, 5, 2, 8],
+                    relocations(&[(4, 2 << 8 | 2)]),
+                ),
+                (
+                    ".symtab",
+                    [2, 0, 0, 6, 1, 16],
+                    symtab(&[
+                        (0, 0, 0, 0x00, 0),
+                        (1, 1, 8, 0x12, 1),
+                        (8, 1, 8, 0x12, 2),
+                        (15, 0, 0, 0x10, 0),
+                    ]),
+                ),
+                (
+                    ".strtab",
+                    [3, 0, 0, 0, 0, 0],
+                    b"\0a_func\0b_func\0puts\0".to_vec(),
+                ),
+            ],
+            &[],
+        );
+        let image = ElfImage::parse(&object).unwrap();
+        let functions: Vec<(&str, u32)> = image
+            .functions()
+            .map(|symbol| (symbol.name.as_str(), symbol.address))
+            .collect();
+        assert_eq!(functions, [("a_func", 0), ("b_func", 8)]);
+        assert_eq!(
+            image.relocation(4),
+            Some(&Relocation {
+                address: 4,
+                kind: RelocationKind::ThumbCall,
+                symbol: "puts".to_string(),
+                target: None,
+                addend: 0,
+            })
+        );
+        assert_eq!(image.relocation(0x0c).unwrap().target, Some(9));
+        assert_eq!(image.relocation(2), None);
+        // bl b_func is relocated, bl puts is left as is.
+        assert_eq!(
+            image.sections[0].data,
+            [0x00, 0xf0, 0x02, 0xf8, 0xff, 0xf7, 0xfe, 0xff]
+        );
+        assert_eq!(image.sections[1].data[4..], [9, 0, 0, 0]);
+    }
+}
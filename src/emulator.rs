@@ -0,0 +1,1856 @@
+//! Reference interpreter executing decoded instructions with the semantics of the ARMv6-M
+//! pseudocode, as the execution core of simulators and differential tests.
+//!
+//! Instructions raising an exception stop the interpreter with a [`Stop`], which
+//! [`Exceptions`] takes like the processor would, along with the pending interrupts.
+//!
+//! # Example
+//! ```
+//! # use armv6_m_instruction_parser::{cpu::CpuState, emulator::step, memory::Ram, parse, registers::Register};
+//! // movs r0, #7; adds r0, #1
+//! let mut bytes = [0x07, 0x20, 0x01, 0x30];
+//! let mut memory = Ram { base: 0, bytes: &mut bytes };
+//! let mut state = CpuState::new(0x2000_0000, 0);
+//! for _ in 0..2 {
+//!     let instruction = parse(&memory.bytes[state.get(Register::PC) as usize..]).unwrap();
+//!     step(&mut state, &mut memory, &instruction).unwrap();
+//! }
+//! assert_eq!(state.get(Register::R0), 8);
+//! assert_eq!(state.get(Register::PC), 4);
+//! ```
+
+use core::ops::Range;
+
+use crate::{
+    cpu::{Apsr, CpuState, StackPointer},
+    instruction_size,
+    instructons::{AccessDirection, AccessSize, Instruction, Operation},
+    memory::{execute_never, BusError, Memory},
+    parse_halfwords,
+    registers::Register,
+    systick::SysTick,
+    timing::{Clock, CoreModel},
+};
+
+/// Cause of a HardFault, the only fault exception of ARMv6-M.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Fault {
+    /// `udf`, or an operation that cannot be executed.
+    Undefined,
+    /// Load or store not aligned to its size, at the address.
+    Unaligned(u32),
+    /// Load or store the memory failed.
+    Bus { address: u32, error: BusError },
+    /// Execution with the Thumb bit clear, after a branch to an even address.
+    InvalidState,
+    /// `bkpt` without a debugger to halt.
+    Breakpoint,
+    /// `svc` at a priority SVCall cannot preempt.
+    SupervisorCall,
+    /// Exception return to the `EXC_RETURN` value, invalid or not matching the exception
+    /// returned from.
+    InvalidReturn(u32),
+}
+
+/// Fault taken as a HardFault or locking up the processor, recorded by [`Exceptions`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FaultInfo {
+    pub cause: Fault,
+    /// Address of the instruction faulting, or returned to by the exception faulting on
+    /// entry or return.
+    pub address: u32,
+    /// Exception being handled when faulting, 0 in thread mode.
+    pub exception: u32,
+    /// The fault locked up the processor instead of being taken.
+    pub lockup: bool,
+}
+
+/// Reason the interpreter stopped, see [`step`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Stop {
+    /// The instruction faults, the state is as before it.
+    HardFault(Fault),
+    /// `svc` with its immediate, the state is after it.
+    SupervisorCall(u32),
+    /// `bkpt` with its immediate, halting a debugger, the state is as before it.
+    Breakpoint(u32),
+    /// `wfi` or `wfe`, the state is after it.
+    Sleep,
+    /// Branch to an `EXC_RETURN` value in handler mode, returning from an exception, the
+    /// state is after it but for `pc`.
+    ExceptionReturn(u32),
+    /// Fault while handling or taking a HardFault or NMI, the processor locks up, see
+    /// [`Exceptions::last_fault`].
+    Lockup,
+    /// Breakpoint or watchpoint of the [`Debugger`] of an [`Emulator`].
+    Debug(DebugEvent),
+}
+
+/// Executes `instruction`, the one at the `pc` of `state`, on `state` and `memory`.
+///
+/// `add` with only low registers is executed as `adds`, the form compilers and the encoder
+/// use, as the decoded operation is the same as for `add`. When stopped with the state as
+/// before the instruction, the memory may still have been written by `push` and `stm`.
+pub fn step(
+    state: &mut CpuState,
+    memory: &mut impl Memory,
+    instruction: &Instruction,
+) -> Result<(), Stop> {
+    if !state.thumb {
+        return Err(Stop::HardFault(Fault::InvalidState));
+    }
+    let pc = state.get(Register::PC);
+    let mut execution = Execution {
+        pc,
+        next: state.clone(),
+        memory,
+        exception_return: None,
+    };
+    execution
+        .next
+        .set(Register::PC, pc.wrapping_add(instruction.size()));
+    execution.execute(&instruction.operation)?;
+    let exception_return = execution.exception_return;
+    *state = execution.next;
+    if let Some(exc_return) = exception_return {
+        return Err(Stop::ExceptionReturn(exc_return));
+    }
+    match instruction.operation {
+        Operation::SVC { imm } => Err(Stop::SupervisorCall(imm)),
+        Operation::WFE | Operation::WFI => Err(Stop::Sleep),
+        _ => Ok(()),
+    }
+}
+
+/// Exception number of NMI.
+const NMI: u32 = 2;
+/// Exception number of HardFault.
+const HARD_FAULT: u32 = 3;
+/// Exception number of SVCall.
+const SV_CALL: u32 = 11;
+/// Number of exceptions of ARMv6-M, the system exceptions and 32 interrupts.
+const EXCEPTIONS: u32 = 48;
+/// Execution priority of thread mode, below every configurable priority.
+const THREAD_PRIORITY: i16 = 256;
+/// `EXC_RETURN` values returning to handler mode, thread mode with the main stack and
+/// thread mode with the process stack.
+const RETURN_HANDLER: u32 = 0xffff_fff1;
+const RETURN_THREAD_MAIN: u32 = 0xffff_fff9;
+const RETURN_THREAD_PROCESS: u32 = 0xffff_fffd;
+
+/// Exception state of the processor, with exception entry and return as in the ARMv6-M
+/// pseudocode.
+///
+/// Exceptions are numbered as in the vector table, interrupts from 16. Exceptions are taken
+/// one at a time, without tail-chaining or late arrival, which behave the same but for
+/// timing. Faults while stacking, reading the vector or unstacking escalate to HardFault,
+/// and lock up when taking NMI or HardFault.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Exceptions {
+    /// Address of the vector table, 0 without the optional VTOR.
+    pub vector_table: u32,
+    pending: u64,
+    active: u64,
+    priorities: [u8; EXCEPTIONS as usize],
+    last_fault: Option<FaultInfo>,
+}
+
+impl Exceptions {
+    /// Returns the exception state out of reset, with nothing pending and every
+    /// configurable priority 0.
+    pub fn new(vector_table: u32) -> Self {
+        Self {
+            vector_table,
+            pending: 0,
+            active: 0,
+            priorities: [0; EXCEPTIONS as usize],
+            last_fault: None,
+        }
+    }
+
+    /// Makes exception `number` pending, like an interrupt request or writing ICSR.
+    pub fn set_pending(&mut self, number: u32) {
+        if number < EXCEPTIONS {
+            self.pending |= 1 << number;
+        }
+    }
+
+    pub fn clear_pending(&mut self, number: u32) {
+        if number < EXCEPTIONS {
+            self.pending &= !(1 << number);
+        }
+    }
+
+    pub fn is_pending(&self, number: u32) -> bool {
+        number < EXCEPTIONS && self.pending & 1 << number != 0
+    }
+
+    /// Returns `true` if exception `number` is being handled, possibly preempted.
+    pub fn is_active(&self, number: u32) -> bool {
+        number < EXCEPTIONS && self.active & 1 << number != 0
+    }
+
+    /// Sets the priority of exception `number` if configurable, of which only the top two
+    /// bits are implemented.
+    pub fn set_priority(&mut self, number: u32, priority: u8) {
+        if (4..EXCEPTIONS).contains(&number) {
+            self.priorities[number as usize] = priority & 0xc0;
+        }
+    }
+
+    /// Returns the priority of exception `number`, lower values preempting higher ones. Reset,
+    /// NMI and HardFault have the fixed priorities -3, -2 and -1.
+    pub fn priority(&self, number: u32) -> i16 {
+        match number {
+            1 => -3,
+            NMI => -2,
+            HARD_FAULT => -1,
+            number => self.priorities[number.min(EXCEPTIONS - 1) as usize] as i16,
+        }
+    }
+
+    /// Returns the priority an exception needs to preempt execution in `state`: the highest
+    /// priority of the active exceptions, boosted to 0 by PRIMASK.
+    pub fn execution_priority(&self, state: &CpuState) -> i16 {
+        let active = (0..EXCEPTIONS)
+            .filter(|number| self.is_active(*number))
+            .map(|number| self.priority(number))
+            .min()
+            .unwrap_or(THREAD_PRIORITY);
+        match state.primask {
+            true => active.min(0),
+            false => active,
+        }
+    }
+
+    /// Returns the pending exception taken next in `state`, the one with the highest
+    /// priority and then lowest number, if it preempts.
+    pub fn next(&self, state: &CpuState) -> Option<u32> {
+        let execution = self.execution_priority(state);
+        (0..EXCEPTIONS)
+            .filter(|number| self.is_pending(*number))
+            .min_by_key(|number| self.priority(*number))
+            .filter(|number| self.priority(*number) < execution)
+    }
+
+    /// Returns the last fault taken or locking up.
+    pub fn last_fault(&self) -> Option<FaultInfo> {
+        self.last_fault
+    }
+
+    /// Takes the pending exception that preempts execution in `state`, if any, and returns
+    /// its number.
+    pub fn take(
+        &mut self,
+        state: &mut CpuState,
+        memory: &mut impl Memory,
+    ) -> Result<Option<u32>, Stop> {
+        let Some(number) = self.next(state) else {
+            return Ok(None);
+        };
+        self.enter(state, memory, number)?;
+        Ok(Some(number))
+    }
+
+    /// Handles `stop` of [`step`] like the processor: `svc` and faults are taken as SVCall
+    /// and HardFault, escalating to HardFault and then lockup when they cannot preempt, and
+    /// exception returns return. Other stops are returned.
+    pub fn handle(
+        &mut self,
+        state: &mut CpuState,
+        memory: &mut impl Memory,
+        stop: Stop,
+    ) -> Result<(), Stop> {
+        match stop {
+            Stop::SupervisorCall(_) if self.priority(SV_CALL) < self.execution_priority(state) => {
+                self.enter(state, memory, SV_CALL)
+            }
+            Stop::SupervisorCall(_) => self.fault(state, memory, Fault::SupervisorCall),
+            Stop::HardFault(cause) => self.fault(state, memory, cause),
+            Stop::ExceptionReturn(exc_return) => self.exception_return(state, memory, exc_return),
+            stop => Err(stop),
+        }
+    }
+
+    /// Takes a HardFault for `cause`, locking up in NMI or HardFault.
+    fn fault(
+        &mut self,
+        state: &mut CpuState,
+        memory: &mut impl Memory,
+        cause: Fault,
+    ) -> Result<(), Stop> {
+        let lockup = self.execution_priority(state) < 0;
+        self.record(state, cause, lockup)?;
+        self.enter(state, memory, HARD_FAULT)
+    }
+
+    /// Escalates the fault for `cause` taking exception `number` to HardFault, locking up
+    /// when taking NMI or HardFault.
+    fn entry_fault(
+        &mut self,
+        state: &mut CpuState,
+        memory: &mut impl Memory,
+        number: u32,
+        cause: Fault,
+    ) -> Result<(), Stop> {
+        if self.priority(number) < 0 {
+            return self.record(state, cause, true);
+        }
+        self.fault(state, memory, cause)
+    }
+
+    /// Records the fault for `cause` in `state`, returning the lockup if it locks up.
+    fn record(&mut self, state: &CpuState, cause: Fault, lockup: bool) -> Result<(), Stop> {
+        self.last_fault = Some(FaultInfo {
+            cause,
+            address: state.get(Register::PC),
+            exception: state.exception,
+            lockup,
+        });
+        match lockup {
+            true => Err(Stop::Lockup),
+            false => Ok(()),
+        }
+    }
+
+    /// Takes exception `number`, returning to the `pc` of `state`.
+    fn enter(
+        &mut self,
+        state: &mut CpuState,
+        memory: &mut impl Memory,
+        number: u32,
+    ) -> Result<(), Stop> {
+        use Register::{LR, PC, R0, R1, R12, R2, R3};
+
+        // The frame is aligned to 8 bytes, bit 9 of the stacked xPSR records the padding.
+        let stack = state.active_stack();
+        let sp = state.get(Register::SP);
+        let padding = sp & 0b100;
+        let frame = sp.wrapping_sub(0x20) & !0b100;
+        let words = [
+            state.get(R0),
+            state.get(R1),
+            state.get(R2),
+            state.get(R3),
+            state.get(R12),
+            state.get(LR),
+            state.get(PC),
+            state.xpsr() | padding << 7,
+        ];
+        for (address, word) in (frame..).step_by(4).zip(words) {
+            if let Err(error) = memory.write(address, AccessSize::Word, word) {
+                let cause = Fault::Bus { address, error };
+                return self.entry_fault(state, memory, number, cause);
+            }
+        }
+        let address = self.vector_table.wrapping_add(4 * number);
+        let vector = match memory.read(address, AccessSize::Word) {
+            Ok(vector) => vector,
+            Err(error) => {
+                let cause = Fault::Bus { address, error };
+                return self.entry_fault(state, memory, number, cause);
+            }
+        };
+        state.set_stack_pointer(stack, frame);
+        let exc_return = match (state.handler_mode(), stack) {
+            (true, _) => RETURN_HANDLER,
+            (false, StackPointer::Main) => RETURN_THREAD_MAIN,
+            (false, StackPointer::Process) => RETURN_THREAD_PROCESS,
+        };
+        state.set(LR, exc_return);
+        state.exception = number;
+        state.control.spsel = false;
+        state.thumb = vector & 1 == 1;
+        state.set(PC, vector);
+        self.clear_pending(number);
+        self.active |= 1 << number;
+        Ok(())
+    }
+
+    /// Returns from the exception being handled in `state` to the context `exc_return`
+    /// selects.
+    fn exception_return(
+        &mut self,
+        state: &mut CpuState,
+        memory: &mut impl Memory,
+        exc_return: u32,
+    ) -> Result<(), Stop> {
+        use Register::{LR, PC, R0, R1, R12, R2, R3};
+
+        let (handler_mode, stack) = match exc_return {
+            RETURN_HANDLER => (true, StackPointer::Main),
+            RETURN_THREAD_MAIN => (false, StackPointer::Main),
+            RETURN_THREAD_PROCESS => (false, StackPointer::Process),
+            _ => return self.fault(state, memory, Fault::InvalidReturn(exc_return)),
+        };
+        if !self.is_active(state.exception) {
+            return self.fault(state, memory, Fault::InvalidReturn(exc_return));
+        }
+        let frame = state.stack_pointer(stack);
+        let mut words = [0; 8];
+        for (address, word) in (frame..).step_by(4).zip(&mut words) {
+            *word = match memory.read(address, AccessSize::Word) {
+                Ok(word) => word,
+                Err(error) => return self.fault(state, memory, Fault::Bus { address, error }),
+            };
+        }
+        let [r0, r1, r2, r3, r12, lr, pc, xpsr] = words;
+        self.active &= !(1 << state.exception);
+        state.control.spsel = stack == StackPointer::Process;
+        for (register, value) in [(R0, r0), (R1, r1), (R2, r2), (R3, r3), (R12, r12), (LR, lr)] {
+            state.set(register, value);
+        }
+        state.set(PC, pc);
+        state.set_xpsr(xpsr);
+        state.set_stack_pointer(stack, frame.wrapping_add(0x20) | xpsr >> 7 & 0b100);
+        if handler_mode != state.handler_mode() {
+            return self.fault(state, memory, Fault::InvalidReturn(exc_return));
+        }
+        Ok(())
+    }
+}
+
+/// Callbacks of an [`Emulator`], for tracing, coverage and peripherals raising interrupts.
+///
+/// Every callback does nothing by default. Peripherals with registers are modelled as
+/// [`Memory`] regions of a [`MemoryMap`](crate::memory::MemoryMap) instead.
+pub trait Hooks {
+    /// Called after the instruction at `address` is fetched, with its `encoding`, the first
+    /// halfword in the high bits for 32-bit instructions.
+    fn fetch(&mut self, _address: u32, _encoding: u32) {}
+
+    /// Called before `instruction`, at the `pc` of `state`, is executed.
+    fn before(&mut self, _state: &CpuState, _instruction: &Instruction) {}
+
+    /// Called after `instruction` at `address` is executed, with the result of [`step`],
+    /// before the exceptions it raises are taken. Exceptions made pending in `exceptions`
+    /// are taken before the next instruction.
+    fn after(
+        &mut self,
+        _address: u32,
+        _instruction: &Instruction,
+        _result: Result<(), Stop>,
+        _state: &CpuState,
+        _exceptions: &mut Exceptions,
+    ) {
+    }
+
+    /// Called after every load and store of an instruction, with the value transferred.
+    fn memory_access(
+        &mut self,
+        _address: u32,
+        _size: AccessSize,
+        _direction: AccessDirection,
+        _value: u32,
+    ) {
+    }
+}
+
+/// No callbacks.
+impl Hooks for () {}
+
+/// Both hooks, the first called first.
+impl<A: Hooks, B: Hooks> Hooks for (A, B) {
+    fn fetch(&mut self, address: u32, encoding: u32) {
+        self.0.fetch(address, encoding);
+        self.1.fetch(address, encoding);
+    }
+
+    fn before(&mut self, state: &CpuState, instruction: &Instruction) {
+        self.0.before(state, instruction);
+        self.1.before(state, instruction);
+    }
+
+    fn after(
+        &mut self,
+        address: u32,
+        instruction: &Instruction,
+        result: Result<(), Stop>,
+        state: &CpuState,
+        exceptions: &mut Exceptions,
+    ) {
+        self.0
+            .after(address, instruction, result, state, exceptions);
+        self.1
+            .after(address, instruction, result, state, exceptions);
+    }
+
+    fn memory_access(
+        &mut self,
+        address: u32,
+        size: AccessSize,
+        direction: AccessDirection,
+        value: u32,
+    ) {
+        self.0.memory_access(address, size, direction, value);
+        self.1.memory_access(address, size, direction, value);
+    }
+}
+
+/// Number of address breakpoints of a [`Debugger`].
+pub const BREAKPOINTS: usize = 16;
+/// Number of watchpoints of a [`Debugger`].
+pub const WATCHPOINTS: usize = 8;
+
+/// Accesses a [`Watchpoint`] stops on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum WatchAccess {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+/// Data watchpoint, stopping after an instruction accessing the watched addresses.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Watchpoint {
+    /// Watched addresses, accesses to any of their bytes match.
+    pub range: Range<u32>,
+    pub access: WatchAccess,
+    /// Only accesses transferring this value match.
+    pub value: Option<u32>,
+}
+
+impl Watchpoint {
+    /// Returns `true` if the access matches the watchpoint.
+    pub fn matches(
+        &self,
+        address: u32,
+        size: AccessSize,
+        direction: AccessDirection,
+        value: u32,
+    ) -> bool {
+        let access = matches!(
+            (self.access, direction),
+            (WatchAccess::ReadWrite, _)
+                | (WatchAccess::Read, AccessDirection::Load)
+                | (WatchAccess::Write, AccessDirection::Store)
+        );
+        let end = address.saturating_add(size.bytes());
+        access
+            && address < self.range.end
+            && self.range.start < end
+            && self.value.is_none_or(|expected| expected == value)
+    }
+}
+
+/// Debug event stopping an [`Emulator`], see [`Debugger`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DebugEvent {
+    /// About to execute the instruction at the address of a breakpoint.
+    Breakpoint(u32),
+    /// An instruction accessed memory matching the watchpoint with the index.
+    Watchpoint {
+        index: usize,
+        address: u32,
+        direction: AccessDirection,
+        value: u32,
+    },
+}
+
+/// Breakpoints and watchpoints of an [`Emulator`], like the breakpoint unit and data
+/// watchpoints of a debugger attached to the processor.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Debugger {
+    breakpoints: [Option<u32>; BREAKPOINTS],
+    watchpoints: [Option<Watchpoint>; WATCHPOINTS],
+    /// `bkpt` stops with [`Stop::Breakpoint`] instead of faulting as without a debugger.
+    pub trap_bkpt: bool,
+    /// Address of the breakpoint stopped at, not stopping again when resuming from it.
+    resume: Option<u32>,
+}
+
+impl Default for Debugger {
+    fn default() -> Self {
+        Self {
+            breakpoints: [None; BREAKPOINTS],
+            watchpoints: Default::default(),
+            trap_bkpt: true,
+            resume: None,
+        }
+    }
+}
+
+impl Debugger {
+    /// Returns a debugger without breakpoints or watchpoints, trapping `bkpt`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a breakpoint at `address`, returns `false` if every breakpoint is used.
+    pub fn add_breakpoint(&mut self, address: u32) -> bool {
+        if self.breakpoints.contains(&Some(address)) {
+            return true;
+        }
+        let Some(free) = self.breakpoints.iter_mut().find(|slot| slot.is_none()) else {
+            return false;
+        };
+        *free = Some(address);
+        true
+    }
+
+    /// Removes the breakpoint at `address`, returns `false` if there is none.
+    pub fn remove_breakpoint(&mut self, address: u32) -> bool {
+        let Some(slot) = self
+            .breakpoints
+            .iter_mut()
+            .find(|slot| **slot == Some(address))
+        else {
+            return false;
+        };
+        *slot = None;
+        true
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = u32> + '_ {
+        self.breakpoints.iter().flatten().copied()
+    }
+
+    /// Adds `watchpoint` and returns its index, `None` if every watchpoint is used.
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) -> Option<usize> {
+        let index = self.watchpoints.iter().position(Option::is_none)?;
+        self.watchpoints[index] = Some(watchpoint);
+        Some(index)
+    }
+
+    /// Removes and returns the watchpoint with `index`.
+    pub fn remove_watchpoint(&mut self, index: usize) -> Option<Watchpoint> {
+        self.watchpoints.get_mut(index)?.take()
+    }
+
+    pub fn watchpoint(&self, index: usize) -> Option<&Watchpoint> {
+        self.watchpoints.get(index)?.as_ref()
+    }
+
+    /// Returns the event of the first watchpoint the access matches.
+    fn watch(
+        &self,
+        address: u32,
+        size: AccessSize,
+        direction: AccessDirection,
+        value: u32,
+    ) -> Option<DebugEvent> {
+        let index = self.watchpoints.iter().position(|watchpoint| {
+            watchpoint
+                .as_ref()
+                .is_some_and(|watchpoint| watchpoint.matches(address, size, direction, value))
+        })?;
+        Some(DebugEvent::Watchpoint {
+            index,
+            address,
+            direction,
+            value,
+        })
+    }
+}
+
+/// State of an [`Emulator`] saved by [`Emulator::snapshot`].
+#[derive(Debug, Clone)]
+pub struct Snapshot<M> {
+    pub state: CpuState,
+    pub memory: M,
+    pub exceptions: Exceptions,
+    pub clock: Clock,
+    pub sys_tick: SysTick,
+}
+
+/// Processor with its memory executing instructions one at a time, see [`Emulator::step`].
+#[derive(Debug, Clone)]
+pub struct Emulator<M, H = ()> {
+    pub state: CpuState,
+    pub memory: M,
+    pub exceptions: Exceptions,
+    pub debugger: Debugger,
+    pub clock: Clock,
+    /// SysTick, its registers accessed by instructions in place of `memory`.
+    pub sys_tick: SysTick,
+    pub hooks: H,
+}
+
+impl<M: Memory> Emulator<M> {
+    /// Returns an emulator without hooks, with the vector table at address 0, timed as a
+    /// Cortex-M0 with zero wait state memory.
+    pub fn new(state: CpuState, memory: M) -> Self {
+        Self {
+            state,
+            memory,
+            exceptions: Exceptions::new(0),
+            debugger: Debugger::new(),
+            clock: Clock::new(CoreModel::CortexM0),
+            sys_tick: SysTick::new(),
+            hooks: (),
+        }
+    }
+}
+
+impl<M: Memory, H: Hooks> Emulator<M, H> {
+    /// Returns the emulator with `hooks` called instead.
+    pub fn with_hooks<I: Hooks>(self, hooks: I) -> Emulator<M, I> {
+        Emulator {
+            state: self.state,
+            memory: self.memory,
+            exceptions: self.exceptions,
+            debugger: self.debugger,
+            clock: self.clock,
+            sys_tick: self.sys_tick,
+            hooks,
+        }
+    }
+
+    /// Takes the pending exception preempting execution, if any, then fetches and executes
+    /// the next instruction and takes the exceptions it raises.
+    ///
+    /// Returns the stops [`Exceptions::handle`] does not handle: breakpoints, sleep and
+    /// lockup, and the events of the [`Debugger`]. Stopped at a breakpoint, the next step
+    /// executes the instruction there. Stopped at a watchpoint, the instruction accessing
+    /// memory has been executed.
+    pub fn step(&mut self) -> Result<(), Stop> {
+        if self
+            .exceptions
+            .take(&mut self.state, &mut self.memory)?
+            .is_some()
+        {
+            let cycles = self.clock.exception_entry();
+            self.sys_tick.tick(cycles, &mut self.exceptions);
+        }
+        let address = self.state.get(Register::PC);
+        if self.debugger.resume.take() != Some(address)
+            && self.debugger.breakpoints.contains(&Some(address))
+        {
+            self.debugger.resume = Some(address);
+            return Err(Stop::Debug(DebugEvent::Breakpoint(address)));
+        }
+        let fetched = match self.state.thumb {
+            true => self.fetch(address),
+            false => Err(Fault::InvalidState),
+        };
+        let instruction = match fetched {
+            Ok(instruction) => instruction,
+            Err(fault) => {
+                let stop = Stop::HardFault(fault);
+                return self
+                    .exceptions
+                    .handle(&mut self.state, &mut self.memory, stop);
+            }
+        };
+        self.hooks.before(&self.state, &instruction);
+        let mut memory = Observed {
+            memory: &mut self.memory,
+            hooks: &mut self.hooks,
+            debugger: &self.debugger,
+            clock: &self.clock,
+            sys_tick: &mut self.sys_tick,
+            event: None,
+            accesses: 0,
+        };
+        let mut result = step(&mut self.state, &mut memory, &instruction);
+        let (event, accesses) = (memory.event, memory.accesses);
+        if let (Err(Stop::Breakpoint(_)), false) = (result, self.debugger.trap_bkpt) {
+            result = Err(Stop::HardFault(Fault::Breakpoint));
+        }
+        // Halting at a bkpt does not execute it.
+        if !matches!(result, Err(Stop::Breakpoint(_))) {
+            let next = self.state.get(Register::PC);
+            let cycles = self.clock.execute(address, &instruction, next, accesses);
+            self.sys_tick.tick(cycles, &mut self.exceptions);
+        }
+        self.hooks.after(
+            address,
+            &instruction,
+            result,
+            &self.state,
+            &mut self.exceptions,
+        );
+        if let Err(stop) = result {
+            self.exceptions
+                .handle(&mut self.state, &mut self.memory, stop)?;
+        }
+        match event {
+            Some(event) => Err(Stop::Debug(event)),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns the processor, memory and exception state, cheap to take with a memory like
+    /// `CowRam` sharing the unchanged pages.
+    pub fn snapshot(&self) -> Snapshot<M>
+    where
+        M: Clone,
+    {
+        Snapshot {
+            state: self.state.clone(),
+            memory: self.memory.clone(),
+            exceptions: self.exceptions.clone(),
+            clock: self.clock.clone(),
+            sys_tick: self.sys_tick.clone(),
+        }
+    }
+
+    /// Restores the state of `snapshot`, keeping the debugger and hooks.
+    pub fn restore(&mut self, snapshot: &Snapshot<M>)
+    where
+        M: Clone,
+    {
+        self.state = snapshot.state.clone();
+        self.memory = snapshot.memory.clone();
+        self.exceptions = snapshot.exceptions.clone();
+        self.clock = snapshot.clock.clone();
+        self.sys_tick = snapshot.sys_tick.clone();
+        self.debugger.resume = None;
+    }
+
+    /// Steps until a stop [`step`](Self::step) returns.
+    pub fn run(&mut self) -> Stop {
+        loop {
+            if let Err(stop) = self.step() {
+                return stop;
+            }
+        }
+    }
+
+    /// Fetches and decodes the instruction at `address`, faulting in memory without execute
+    /// permission and in the execute never regions of the memory map.
+    fn fetch(&mut self, address: u32) -> Result<Instruction, Fault> {
+        let mut halfword = |address: u32| {
+            let halfword = self
+                .memory
+                .read_halfword(address)
+                .map_err(|error| Fault::Bus { address, error })?;
+            if execute_never(address) || !self.memory.permissions(address).execute {
+                let error = BusError::Permission;
+                return Err(Fault::Bus { address, error });
+            }
+            Ok(halfword)
+        };
+        let first = halfword(address)?;
+        let (encoding, instruction) = match instruction_size(first) {
+            4 => {
+                let second = halfword(address.wrapping_add(2))?;
+                let encoding = (first as u32) << 16 | second as u32;
+                (encoding, parse_halfwords(&[first, second]))
+            }
+            _ => (first as u32, parse_halfwords(&[first])),
+        };
+        self.hooks.fetch(address, encoding);
+        instruction.map_err(|_| Fault::Undefined)
+    }
+}
+
+/// Memory calling [`Hooks::memory_access`] on every access and checking the watchpoints.
+struct Observed<'a, M, H> {
+    memory: &'a mut M,
+    hooks: &'a mut H,
+    debugger: &'a Debugger,
+    clock: &'a Clock,
+    sys_tick: &'a mut SysTick,
+    /// First watchpoint matched.
+    event: Option<DebugEvent>,
+    /// Accesses of memory with wait states.
+    accesses: u32,
+}
+
+impl<M: Memory, H: Hooks> Observed<'_, M, H> {
+    /// Returns the memory accessed at `address`, SysTick over the memory.
+    fn target(&mut self, address: u32) -> &mut dyn Memory {
+        match self.sys_tick.range().contains(&address) {
+            true => self.sys_tick,
+            false => self.memory,
+        }
+    }
+
+    fn observe(&mut self, address: u32, size: AccessSize, direction: AccessDirection, value: u32) {
+        self.hooks.memory_access(address, size, direction, value);
+        if self.clock.wait_state_memory.contains(&address) {
+            self.accesses += 1;
+        }
+        if self.event.is_none() {
+            self.event = self.debugger.watch(address, size, direction, value);
+        }
+    }
+}
+
+impl<M: Memory, H: Hooks> Memory for Observed<'_, M, H> {
+    fn read(&mut self, address: u32, size: AccessSize) -> Result<u32, BusError> {
+        let value = self.target(address).read(address, size)?;
+        self.observe(address, size, AccessDirection::Load, value);
+        Ok(value)
+    }
+
+    fn write(&mut self, address: u32, size: AccessSize, value: u32) -> Result<(), BusError> {
+        self.target(address).write(address, size, value)?;
+        self.observe(address, size, AccessDirection::Store, value);
+        Ok(())
+    }
+
+    fn range(&self) -> Range<u32> {
+        self.memory.range()
+    }
+}
+
+/// Kind of shift, see [`shift`].
+#[derive(Clone, Copy)]
+enum Shift {
+    Lsl,
+    Lsr,
+    Asr,
+    Ror,
+}
+
+/// Returns `value` shifted by `amount` with the carry out, `carry` when `amount` is zero.
+fn shift(value: u32, kind: Shift, amount: u32, carry: bool) -> (u32, bool) {
+    let bit = |n: u32| value >> n & 1 == 1;
+    match (kind, amount) {
+        (_, 0) => (value, carry),
+        (Shift::Lsl, 1..=31) => (value << amount, bit(32 - amount)),
+        (Shift::Lsl, 32) => (0, bit(0)),
+        (Shift::Lsr, 1..=31) => (value >> amount, bit(amount - 1)),
+        (Shift::Lsr, 32) => (0, bit(31)),
+        (Shift::Lsl | Shift::Lsr, _) => (0, false),
+        (Shift::Asr, 1..=31) => (((value as i32) >> amount) as u32, bit(amount - 1)),
+        (Shift::Asr, _) => (((value as i32) >> 31) as u32, bit(31)),
+        (Shift::Ror, _) => {
+            let result = value.rotate_right(amount % 32);
+            (result, result >> 31 == 1)
+        }
+    }
+}
+
+/// Returns `x + y + carry` with the carry out and the signed overflow.
+fn add_with_carry(x: u32, y: u32, carry: bool) -> (u32, bool, bool) {
+    let unsigned = x as u64 + y as u64 + carry as u64;
+    let signed = x as i32 as i64 + y as i32 as i64 + carry as i64;
+    let result = unsigned as u32;
+    (
+        result,
+        result as u64 != unsigned,
+        result as i32 as i64 != signed,
+    )
+}
+
+/// Execution of one instruction, updating a copy of the state committed when it succeeds.
+struct Execution<'a, M> {
+    /// Address of the instruction.
+    pc: u32,
+    next: CpuState,
+    memory: &'a mut M,
+    /// `EXC_RETURN` value branched to.
+    exception_return: Option<u32>,
+}
+
+impl<M: Memory> Execution<'_, M> {
+    /// Returns `register` as read by the instruction, `pc` reads its address plus 4.
+    fn read(&self, register: Register) -> u32 {
+        match register {
+            Register::PC => self.pc.wrapping_add(4),
+            register => self.next.get(register),
+        }
+    }
+
+    fn write(&mut self, register: Register, value: u32) {
+        self.next.set(register, value);
+    }
+
+    /// Writes `result` and sets the negative and zero flags from it.
+    fn write_nz(&mut self, register: Register, result: u32) {
+        self.write(register, result);
+        self.next.apsr.n = result >> 31 == 1;
+        self.next.apsr.z = result == 0;
+    }
+
+    /// Writes `result` and sets all flags.
+    fn write_nzcv(&mut self, register: Register, (result, carry, overflow): (u32, bool, bool)) {
+        self.write_nz(register, result);
+        self.next.apsr.c = carry;
+        self.next.apsr.v = overflow;
+    }
+
+    /// Sets all flags from `result` without writing it.
+    fn compare(&mut self, (result, carry, overflow): (u32, bool, bool)) {
+        self.next.apsr = Apsr {
+            n: result >> 31 == 1,
+            z: result == 0,
+            c: carry,
+            v: overflow,
+        };
+    }
+
+    /// Shifts `value` into `d` with the flags set.
+    fn write_shifted(&mut self, d: Register, value: u32, kind: Shift, amount: u32) {
+        let (result, carry) = shift(value, kind, amount, self.next.apsr.c);
+        self.write_nz(d, result);
+        self.next.apsr.c = carry;
+    }
+
+    /// Branches to `target` as `bx` and loads of `pc` do.
+    fn branch_exchange(&mut self, target: u32) {
+        if target >> 28 == 0xf && self.next.handler_mode() {
+            self.exception_return = Some(target);
+            return;
+        }
+        self.branch_link_exchange(target)
+    }
+
+    /// Branches to `target` as `blx` does, with the Thumb bit from bit 0 of `target`, so the
+    /// next instruction faults if it is clear.
+    fn branch_link_exchange(&mut self, target: u32) {
+        self.next.thumb = target & 1 == 1;
+        self.write(Register::PC, target);
+    }
+
+    fn load(&mut self, address: u32, size: AccessSize) -> Result<u32, Stop> {
+        if !address.is_multiple_of(size.bytes()) {
+            return Err(Stop::HardFault(Fault::Unaligned(address)));
+        }
+        self.memory
+            .read(address, size)
+            .map_err(|error| Stop::HardFault(Fault::Bus { address, error }))
+    }
+
+    fn store(&mut self, address: u32, size: AccessSize, value: u32) -> Result<(), Stop> {
+        if !address.is_multiple_of(size.bytes()) {
+            return Err(Stop::HardFault(Fault::Unaligned(address)));
+        }
+        self.memory
+            .write(address, size, value)
+            .map_err(|error| Stop::HardFault(Fault::Bus { address, error }))
+    }
+
+    fn execute(&mut self, operation: &Operation) -> Result<(), Stop> {
+        use AccessSize::{Byte, Halfword, Word};
+
+        let carry = self.next.apsr.c;
+        match *operation {
+            Operation::ADCReg { m, n, d } => {
+                self.write_nzcv(d, add_with_carry(self.read(n), self.read(m), carry))
+            }
+            Operation::ADDImm { imm, n, d } => {
+                self.write_nzcv(d, add_with_carry(self.read(n), imm, false))
+            }
+            Operation::ADDReg { m, n, d, set_flags } => {
+                let sum = add_with_carry(self.read(n), self.read(m), false);
+                match set_flags {
+                    true => self.write_nzcv(d, sum),
+                    false => self.write(d, sum.0),
+                }
+            }
+            Operation::ADDImmSP { d, imm } => {
+                self.write(d, self.read(Register::SP).wrapping_add(imm))
+            }
+            Operation::ADDRegSP { d, m } => {
+                self.write(d, self.read(Register::SP).wrapping_add(self.read(m)))
+            }
+            Operation::ADR { d, imm } => {
+                self.write(d, (self.read(Register::PC) & !0b11).wrapping_add(imm))
+            }
+            Operation::ANDReg { m, dn } => self.write_nz(dn, self.read(dn) & self.read(m)),
+            Operation::ASRImm { imm, m, d } => {
+                let amount = if imm == 0 { 32 } else { imm };
+                self.write_shifted(d, self.read(m), Shift::Asr, amount)
+            }
+            Operation::ASRReg { m, dn } => {
+                self.write_shifted(dn, self.read(dn), Shift::Asr, self.read(m) & 0xff)
+            }
+            Operation::B { cond, imm } => {
+                if self.next.apsr.holds(cond) {
+                    self.write(Register::PC, self.read(Register::PC).wrapping_add(imm));
+                }
+            }
+            Operation::BICReg { m, dn } => self.write_nz(dn, self.read(dn) & !self.read(m)),
+            Operation::BKPT { imm } => return Err(Stop::Breakpoint(imm)),
+            Operation::BL { imm } => {
+                let next = self.next.get(Register::PC);
+                self.write(Register::LR, next | 1);
+                self.write(Register::PC, self.read(Register::PC).wrapping_add(imm));
+            }
+            Operation::BLXReg { m } => {
+                let target = self.read(m);
+                let next = self.next.get(Register::PC);
+                self.write(Register::LR, next | 1);
+                self.branch_link_exchange(target);
+            }
+            Operation::BX { m } => self.branch_exchange(self.read(m)),
+            Operation::CMNReg { m, n } => {
+                self.compare(add_with_carry(self.read(n), self.read(m), false))
+            }
+            Operation::CMPImm { n, imm } => self.compare(add_with_carry(self.read(n), !imm, true)),
+            Operation::CMPReg { m, n } => {
+                self.compare(add_with_carry(self.read(n), !self.read(m), true))
+            }
+            Operation::CPS { im } => {
+                if self.next.privileged() {
+                    self.next.primask = im;
+                }
+            }
+            Operation::CPY | Operation::UDF { .. } => {
+                return Err(Stop::HardFault(Fault::Undefined))
+            }
+            Operation::DMB { .. }
+            | Operation::DSB { .. }
+            | Operation::ISB { .. }
+            | Operation::NOP
+            | Operation::SEV
+            | Operation::SVC { .. }
+            | Operation::WFE
+            | Operation::WFI
+            | Operation::YIELD => {}
+            Operation::EORReg { m, dn } => self.write_nz(dn, self.read(dn) ^ self.read(m)),
+            Operation::LDM { n, reg_list } => {
+                let mut address = self.read(n);
+                for register in reg_list {
+                    let value = self.load(address, Word)?;
+                    self.write(register, value);
+                    address = address.wrapping_add(4);
+                }
+                if !reg_list.contains(n) {
+                    self.write(n, address);
+                }
+            }
+            Operation::LDRImm { imm, n, t } => {
+                let value = self.load(self.read(n).wrapping_add(imm), Word)?;
+                self.write(t, value);
+            }
+            Operation::LDRLiteral { t, imm } => {
+                let address = (self.read(Register::PC) & !0b11).wrapping_add(imm);
+                let value = self.load(address, Word)?;
+                self.write(t, value);
+            }
+            Operation::LDRReg { m, n, t } => {
+                let value = self.load(self.read(n).wrapping_add(self.read(m)), Word)?;
+                self.write(t, value);
+            }
+            Operation::LDRBImm { imm, n, t } => {
+                let value = self.load(self.read(n).wrapping_add(imm), Byte)?;
+                self.write(t, value);
+            }
+            Operation::LDRBReg { m, n, t } => {
+                let value = self.load(self.read(n).wrapping_add(self.read(m)), Byte)?;
+                self.write(t, value);
+            }
+            Operation::LDRHImm { imm, n, t } => {
+                let value = self.load(self.read(n).wrapping_add(imm), Halfword)?;
+                self.write(t, value);
+            }
+            Operation::LDRHReg { m, n, t } => {
+                let value = self.load(self.read(n).wrapping_add(self.read(m)), Halfword)?;
+                self.write(t, value);
+            }
+            Operation::LDRSBReg { m, n, t } => {
+                let value = self.load(self.read(n).wrapping_add(self.read(m)), Byte)?;
+                self.write(t, value as u8 as i8 as u32);
+            }
+            Operation::LDRSH { m, n, t } => {
+                let value = self.load(self.read(n).wrapping_add(self.read(m)), Halfword)?;
+                self.write(t, value as u16 as i16 as u32);
+            }
+            Operation::LSLImm { imm, m, d } => self.write_shifted(d, self.read(m), Shift::Lsl, imm),
+            Operation::LSLReg { m, dn } => {
+                self.write_shifted(dn, self.read(dn), Shift::Lsl, self.read(m) & 0xff)
+            }
+            Operation::LSRImm { imm, m, d } => {
+                let amount = if imm == 0 { 32 } else { imm };
+                self.write_shifted(d, self.read(m), Shift::Lsr, amount)
+            }
+            Operation::LSRReg { m, dn } => {
+                self.write_shifted(dn, self.read(dn), Shift::Lsr, self.read(m) & 0xff)
+            }
+            Operation::MOVImm { d, imm } => self.write_nz(d, imm),
+            Operation::MOVReg { m, d, set_flags } => match set_flags {
+                true => self.write_nz(d, self.read(m)),
+                false => self.write(d, self.read(m)),
+            },
+            Operation::MRS { d, sysm } => self.write(d, self.next.read_special(sysm)),
+            Operation::MSRReg { n, sysm } => self.next.write_special(sysm, self.read(n)),
+            Operation::MUL { n, dm } => self.write_nz(dm, self.read(n).wrapping_mul(self.read(dm))),
+            Operation::MVNReg { m, d } => self.write_nz(d, !self.read(m)),
+            Operation::ORRReg { m, dn } => self.write_nz(dn, self.read(dn) | self.read(m)),
+            Operation::POP { reg_list } => {
+                let mut address = self.read(Register::SP);
+                let mut target = None;
+                for register in reg_list {
+                    let value = self.load(address, Word)?;
+                    match register {
+                        Register::PC => target = Some(value),
+                        register => self.write(register, value),
+                    }
+                    address = address.wrapping_add(4);
+                }
+                self.write(Register::SP, address);
+                if let Some(target) = target {
+                    self.branch_exchange(target);
+                }
+            }
+            Operation::PUSH { reg_list } => {
+                let start = self
+                    .read(Register::SP)
+                    .wrapping_sub(4 * reg_list.len() as u32);
+                let mut address = start;
+                for register in reg_list {
+                    self.store(address, Word, self.read(register))?;
+                    address = address.wrapping_add(4);
+                }
+                self.write(Register::SP, start);
+            }
+            Operation::REV { m, d } => self.write(d, self.read(m).swap_bytes()),
+            Operation::REV16 { m, d } => self.write(d, self.read(m).swap_bytes().rotate_right(16)),
+            Operation::REVSH { m, d } => {
+                self.write(d, (self.read(m) as u16).swap_bytes() as i16 as u32)
+            }
+            Operation::RORReg { m, dn } => {
+                self.write_shifted(dn, self.read(dn), Shift::Ror, self.read(m) & 0xff)
+            }
+            Operation::RSBImm { n, d } => {
+                self.write_nzcv(d, add_with_carry(!self.read(n), 0, true))
+            }
+            Operation::SBCReg { m, dn } => {
+                self.write_nzcv(dn, add_with_carry(self.read(dn), !self.read(m), carry))
+            }
+            Operation::STM { n, reg_list } => {
+                let mut address = self.read(n);
+                for register in reg_list {
+                    self.store(address, Word, self.read(register))?;
+                    address = address.wrapping_add(4);
+                }
+                self.write(n, address);
+            }
+            Operation::STRImm { imm, n, t } => {
+                self.store(self.read(n).wrapping_add(imm), Word, self.read(t))?
+            }
+            Operation::STRReg { m, n, t } => {
+                self.store(self.read(n).wrapping_add(self.read(m)), Word, self.read(t))?
+            }
+            Operation::STRBImm { imm, n, t } => {
+                self.store(self.read(n).wrapping_add(imm), Byte, self.read(t))?
+            }
+            Operation::STRBReg { m, n, t } => {
+                self.store(self.read(n).wrapping_add(self.read(m)), Byte, self.read(t))?
+            }
+            Operation::STRHImm { imm, n, t } => {
+                self.store(self.read(n).wrapping_add(imm), Halfword, self.read(t))?
+            }
+            Operation::STRHReg { m, n, t } => self.store(
+                self.read(n).wrapping_add(self.read(m)),
+                Halfword,
+                self.read(t),
+            )?,
+            Operation::SUBImm { imm, n, d } => {
+                self.write_nzcv(d, add_with_carry(self.read(n), !imm, true))
+            }
+            Operation::SUBReg { m, n, d } => {
+                self.write_nzcv(d, add_with_carry(self.read(n), !self.read(m), true))
+            }
+            Operation::SUBImmSP { imm } => {
+                self.write(Register::SP, self.read(Register::SP).wrapping_sub(imm))
+            }
+            Operation::SXTB { m, d } => self.write(d, self.read(m) as u8 as i8 as u32),
+            Operation::SXTH { m, d } => self.write(d, self.read(m) as u16 as i16 as u32),
+            Operation::TSTReg { m, n } => {
+                let result = self.read(n) & self.read(m);
+                self.next.apsr.n = result >> 31 == 1;
+                self.next.apsr.z = result == 0;
+            }
+            Operation::UXTB { m, d } => self.write(d, self.read(m) as u8 as u32),
+            Operation::UXTH { m, d } => self.write(d, self.read(m) as u16 as u32),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        conditions::Condition,
+        memory::{Permissions, Protected, Ram},
+        parse,
+    };
+
+    /// Runs from `pc` until the interpreter stops.
+    fn run(state: &mut CpuState, memory: &mut Ram) -> Stop {
+        loop {
+            let pc = state.get(Register::PC) as usize;
+            let instruction = parse(&memory.bytes[pc..]).unwrap();
+            if let Err(stop) = step(state, memory, &instruction) {
+                return stop;
+            }
+        }
+    }
+
+    #[test]
+    fn execution() {
+        let code: [u16; 15] = [
+            0xb510, // push {r4, lr}
+            0x2000, // movs r0, #0
+            0x210a, // movs r1, #10
+            0x1840, // loop: adds r0, r0, r1
+            0x3901, // subs r1, #1
+            0xd1fc, // bne loop
+            0xf000, // bl double
+            0xf803, 0x2101, // movs r1, #1
+            0x5844, // ldr r4, [r0, r1]
+            0xbd10, // pop {r4, pc}
+            0x0040, // double: lsls r0, r0, #1
+            0x28c8, // cmp r0, #200
+            0x4770, // bx lr
+            0xbe01, // bkpt 0x01
+        ];
+        let mut bytes = [0; 0x100];
+        for (index, halfword) in code.iter().enumerate() {
+            bytes[index * 2..index * 2 + 2].copy_from_slice(&halfword.to_le_bytes());
+        }
+        let mut memory = Ram {
+            base: 0,
+            bytes: &mut bytes,
+        };
+        let mut state = CpuState::new(0x100, 0);
+        state.set(Register::LR, 0x1d);
+        let stop = run(&mut state, &mut memory);
+        // The ldr faults, at an address not aligned, leaving the state before it.
+        assert_eq!(stop, Stop::HardFault(Fault::Unaligned(111)));
+        assert_eq!(state.get(Register::R0), 110);
+        assert_eq!(state.get(Register::LR), 0x11);
+        assert_eq!(state.get(Register::SP), 0xf8);
+        assert_eq!(memory.bytes[0xfc], 0x1d);
+
+        state.set(Register::PC, 0x14);
+        assert_eq!(run(&mut state, &mut memory), Stop::Breakpoint(1));
+        assert_eq!(
+            (state.get(Register::SP), state.get(Register::PC)),
+            (0x100, 0x1c)
+        );
+    }
+
+    /// Executes the operation of the assembly `text` at 0x40 on `state` and `memory`.
+    #[cfg(feature = "alloc")]
+    fn execute(state: &mut CpuState, memory: &mut Ram, text: &str) -> Result<(), Stop> {
+        use crate::{encoder::encode, instructons::InstructionWidth};
+
+        let operation: Operation = text.parse().unwrap();
+        let width = match encode(&operation).unwrap().size() {
+            4 => InstructionWidth::Bit32,
+            _ => InstructionWidth::Bit16,
+        };
+        state.set(Register::PC, 0x40);
+        step(state, memory, &Instruction { width, operation })
+    }
+
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn carry_and_overflow() {
+        let mut bytes = [0; 0x100];
+        let mut memory = Ram {
+            base: 0,
+            bytes: &mut bytes,
+        };
+        let flags = |nzcv: u32| Apsr::from_bits(nzcv << 28);
+        // Operation, r0, r1 and carry before, r0 and flags after.
+        let cases: [(&str, u32, u32, bool, u32, Apsr); 11] = [
+            (
+                "adds r0, r0, r1",
+                0x7fff_ffff,
+                1,
+                true,
+                0x8000_0000,
+                flags(0b1001),
+            ),
+            // `add` of low registers leaves the flags.
+            (
+                "add r0, r1",
+                0x7fff_ffff,
+                1,
+                true,
+                0x8000_0000,
+                flags(0b0010),
+            ),
+            (
+                "adcs r0, r1",
+                0x7fff_ffff,
+                0,
+                true,
+                0x8000_0000,
+                flags(0b1001),
+            ),
+            ("adcs r0, r1", 0xffff_ffff, 0, true, 0, flags(0b0110)),
+            (
+                "adcs r0, r1",
+                0x8000_0000,
+                0x8000_0000,
+                false,
+                0,
+                flags(0b0111),
+            ),
+            ("sbcs r0, r1", 0, 0, false, 0xffff_ffff, flags(0b1000)),
+            (
+                "sbcs r0, r1",
+                0x8000_0000,
+                0,
+                false,
+                0x7fff_ffff,
+                flags(0b0011),
+            ),
+            ("sbcs r0, r1", 5, 3, true, 2, flags(0b0010)),
+            ("rsbs r0, r1, #0", 7, 0, false, 0, flags(0b0110)),
+            ("rsbs r0, r1, #0", 7, 1, true, 0xffff_ffff, flags(0b1000)),
+            (
+                "rsbs r0, r1, #0",
+                7,
+                0x8000_0000,
+                false,
+                0x8000_0000,
+                flags(0b1001),
+            ),
+        ];
+        for (text, r0, r1, carry, result, apsr) in cases {
+            let mut state = CpuState::new(0x100, 0x40);
+            state.set(Register::R0, r0);
+            state.set(Register::R1, r1);
+            state.apsr.c = carry;
+            execute(&mut state, &mut memory, text).unwrap();
+            assert_eq!(state.get(Register::R0), result, "{text} {r0:#x} {r1:#x}");
+            assert_eq!(state.apsr, apsr, "{text} {r0:#x} {r1:#x}");
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn shifts() {
+        let mut bytes = [0; 0x100];
+        let mut memory = Ram {
+            base: 0,
+            bytes: &mut bytes,
+        };
+        // Operation, r0, r1 and carry before, r0 and carry after. Register shifts shift by
+        // the bottom byte of r1.
+        let cases: [(&str, u32, u32, bool, u32, bool); 12] = [
+            ("lsrs r0, r1, #32", 7, 0x8000_0001, false, 0, true),
+            ("lsrs r0, r1, #32", 7, 0x7fff_ffff, true, 0, false),
+            ("asrs r0, r1, #32", 7, 0x8000_0000, false, 0xffff_ffff, true),
+            ("asrs r0, r1, #32", 7, 0x7fff_ffff, true, 0, false),
+            ("lsls r0, r1", 1, 32, false, 0, true),
+            ("lsls r0, r1", 1, 33, true, 0, false),
+            ("lsrs r0, r1", 0x8000_0000, 32, false, 0, true),
+            ("lsrs r0, r1", 0x8000_0000, 255, true, 0, false),
+            ("asrs r0, r1", 0x8000_0000, 40, false, 0xffff_ffff, true),
+            ("asrs r0, r1", 0x4000_0000, 32, true, 0, false),
+            ("rors r0, r1", 0x8000_0001, 32, false, 0x8000_0001, true),
+            ("lsls r0, r1", 5, 0x100, true, 5, true),
+        ];
+        for (text, r0, r1, carry, result, carry_out) in cases {
+            let mut state = CpuState::new(0x100, 0x40);
+            state.set(Register::R0, r0);
+            state.set(Register::R1, r1);
+            state.apsr.c = carry;
+            execute(&mut state, &mut memory, text).unwrap();
+            assert_eq!(state.get(Register::R0), result, "{text} {r0:#x} {r1:#x}");
+            assert_eq!(state.apsr.c, carry_out, "{text} {r0:#x} {r1:#x}");
+            assert_eq!(state.apsr.z, result == 0, "{text} {r0:#x} {r1:#x}");
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn multiple_transfers() {
+        let mut bytes = [0; 0x100];
+        bytes[0x80..0x88].copy_from_slice(&[1, 0, 0, 0, 2, 0, 0, 0]);
+        let mut memory = Ram {
+            base: 0,
+            bytes: &mut bytes,
+        };
+        let mut state = CpuState::new(0x100, 0x40);
+        // The base in the list is loaded, without writeback.
+        state.set(Register::R0, 0x80);
+        execute(&mut state, &mut memory, "ldm r0, {r0, r1}").unwrap();
+        assert_eq!((state.get(Register::R0), state.get(Register::R1)), (1, 2));
+        state.set(Register::R1, 0x80);
+        execute(&mut state, &mut memory, "ldm r1!, {r0, r2}").unwrap();
+        assert_eq!((state.get(Register::R0), state.get(Register::R2)), (1, 2));
+        assert_eq!(state.get(Register::R1), 0x88);
+        // The base stored first is stored as before the instruction.
+        state.set(Register::R0, 0x90);
+        execute(&mut state, &mut memory, "stm r0!, {r0, r1}").unwrap();
+        assert_eq!(memory.read_word(0x90), Ok(0x90));
+        assert_eq!(memory.read_word(0x94), Ok(0x88));
+        assert_eq!(state.get(Register::R0), 0x98);
+    }
+
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn pc_writes() {
+        let mut bytes = [0; 0x100];
+        let mut memory = Ram {
+            base: 0,
+            bytes: &mut bytes,
+        };
+        let mut state = CpuState::new(0x100, 0x40);
+        state.apsr = Apsr::from_bits(0x4000_0000);
+        // The pc reads as the address of the instruction plus 4, bit 0 of the result is
+        // dropped and the flags are kept.
+        state.set(Register::R0, 0x11);
+        execute(&mut state, &mut memory, "add pc, r0").unwrap();
+        assert_eq!(state.get(Register::PC), 0x54);
+        assert!(state.thumb && state.apsr.z);
+        state.set(Register::R1, 0x81);
+        execute(&mut state, &mut memory, "mov pc, r1").unwrap();
+        assert_eq!(state.get(Register::PC), 0x80);
+        assert!(state.thumb);
+        state.set(Register::R8, 0x10);
+        execute(&mut state, &mut memory, "add r8, pc").unwrap();
+        assert_eq!(state.get(Register::R8), 0x54);
+        assert_eq!(state.get(Register::PC), 0x42);
+    }
+
+    #[test]
+    fn exceptions() {
+        let mut bytes = [0; 0x200];
+        let mut halfwords = |address: usize, halfwords: &[u16]| {
+            for (index, halfword) in halfwords.iter().enumerate() {
+                let offset = address + 2 * index;
+                bytes[offset..offset + 2].copy_from_slice(&halfword.to_le_bytes());
+            }
+        };
+        // Vectors of SVCall and SysTick.
+        halfwords(11 * 4, &[0x61]);
+        halfwords(15 * 4, &[0x71]);
+        halfwords(0x40, &[0x2005, 0xdf00, 0xbe00]); // movs r0, #5; svc 0; bkpt 0
+        halfwords(
+            0x60,
+            &[
+                0xf3ef, 0x8209, // mrs r2, psp
+                0x6811, // ldr r1, [r2]
+                0x3101, // adds r1, #1
+                0x6011, // str r1, [r2]
+                0x4770, // bx lr
+            ],
+        );
+        halfwords(0x70, &[0x4770]); // bx lr
+        let mut memory = Ram {
+            base: 0,
+            bytes: &mut bytes,
+        };
+        let mut state = CpuState::new(0x200, 0x40);
+        state.set_stack_pointer(StackPointer::Process, 0x184);
+        state.control.spsel = true;
+        let mut exceptions = Exceptions::new(0);
+        let stop = loop {
+            let pc = state.get(Register::PC) as usize;
+            let instruction = parse(&memory.bytes[pc..]).unwrap();
+            if let Err(stop) = step(&mut state, &mut memory, &instruction) {
+                if let Err(stop) = exceptions.handle(&mut state, &mut memory, stop) {
+                    break stop;
+                }
+            }
+        };
+        // The handler incremented the stacked r0 on the process stack, aligned to 8 bytes.
+        assert_eq!(stop, Stop::Breakpoint(0));
+        assert_eq!(state.get(Register::R0), 6);
+        assert_eq!(memory.bytes[0x160], 6);
+        assert_eq!(state.get(Register::SP), 0x184);
+        assert_eq!(state.stack_pointer(StackPointer::Main), 0x200);
+        assert!(!exceptions.is_active(11) && !state.handler_mode());
+
+        // PRIMASK masks SysTick until cleared.
+        state.primask = true;
+        exceptions.set_pending(15);
+        assert_eq!(exceptions.take(&mut state, &mut memory), Ok(None));
+        state.primask = false;
+        assert_eq!(exceptions.take(&mut state, &mut memory), Ok(Some(15)));
+        assert_eq!(state.get(Register::PC), 0x70);
+        assert_eq!(state.get(Register::LR), 0xffff_fffd);
+        assert_eq!(exceptions.execution_priority(&state), 0);
+        // A fault in HardFault locks up.
+        exceptions.set_pending(3);
+        assert_eq!(exceptions.take(&mut state, &mut memory), Ok(Some(3)));
+        let stop = Stop::HardFault(Fault::Undefined);
+        assert_eq!(
+            exceptions.handle(&mut state, &mut memory, stop),
+            Err(Stop::Lockup)
+        );
+    }
+
+    #[test]
+    fn stack_alignment() {
+        for sp in [0x1f8, 0x1fc] {
+            let mut bytes = [0; 0x200];
+            bytes[15 * 4] = 0x71; // SysTick vector
+            bytes[0x70..0x72].copy_from_slice(&0x4770u16.to_le_bytes()); // bx lr
+            let mut memory = Ram {
+                base: 0,
+                bytes: &mut bytes,
+            };
+            let mut state = CpuState::new(sp, 0x40);
+            state.set(Register::R0, 0x1234);
+            let mut exceptions = Exceptions::new(0);
+            exceptions.set_pending(15);
+            assert_eq!(exceptions.take(&mut state, &mut memory), Ok(Some(15)));
+            // The frame is at 0x1d8 either way, bit 9 of the stacked xPSR records the
+            // padding word.
+            assert_eq!(state.get(Register::SP), 0x1d8);
+            assert_eq!(memory.read_word(0x1d8), Ok(0x1234));
+            assert_eq!(memory.read_word(0x1f0), Ok(0x40));
+            let xpsr = memory.read_word(0x1f4).unwrap();
+            assert_eq!(xpsr & 1 << 9 != 0, sp == 0x1fc, "{sp:#x}");
+
+            state.set(Register::R0, 0);
+            let instruction = parse(&memory.bytes[0x70..]).unwrap();
+            let stop = step(&mut state, &mut memory, &instruction).unwrap_err();
+            assert_eq!(stop, Stop::ExceptionReturn(0xffff_fff9));
+            exceptions.handle(&mut state, &mut memory, stop).unwrap();
+            assert_eq!(state.get(Register::SP), sp);
+            assert_eq!(state.get(Register::PC), 0x40);
+            assert_eq!(state.get(Register::R0), 0x1234);
+            assert!(!state.handler_mode() && !exceptions.is_active(15));
+        }
+    }
+
+    #[derive(Default)]
+    struct Trace {
+        instructions: u32,
+        accesses: [u32; 2],
+        last: Option<(u32, AccessDirection, u32)>,
+    }
+
+    impl Hooks for Trace {
+        fn before(&mut self, _state: &CpuState, _instruction: &Instruction) {
+            self.instructions += 1;
+        }
+
+        fn after(
+            &mut self,
+            address: u32,
+            _instruction: &Instruction,
+            _result: Result<(), Stop>,
+            _state: &CpuState,
+            exceptions: &mut Exceptions,
+        ) {
+            // A timer interrupt raised by the store.
+            if address == 0x42 {
+                exceptions.set_pending(15);
+            }
+        }
+
+        fn memory_access(
+            &mut self,
+            address: u32,
+            _size: AccessSize,
+            direction: AccessDirection,
+            value: u32,
+        ) {
+            self.accesses[direction as usize] += 1;
+            self.last = Some((address, direction, value));
+        }
+    }
+
+    #[test]
+    fn hooks() {
+        let mut bytes = [0; 0x200];
+        let code: [(usize, u16); 6] = [
+            (15 * 4, 0x71), // SysTick vector
+            (0x40, 0x2080), // movs r0, #0x80
+            (0x42, 0x6000), // str r0, [r0]
+            (0x44, 0x6801), // ldr r1, [r0]
+            (0x46, 0xbe01), // bkpt 1
+            (0x70, 0x4770), // bx lr
+        ];
+        for (address, halfword) in code {
+            bytes[address..address + 2].copy_from_slice(&halfword.to_le_bytes());
+        }
+        let memory = Ram {
+            base: 0,
+            bytes: &mut bytes,
+        };
+        let mut emulator =
+            Emulator::new(CpuState::new(0x200, 0x40), memory).with_hooks(Trace::default());
+        assert_eq!(emulator.run(), Stop::Breakpoint(1));
+        assert_eq!(emulator.state.get(Register::R1), 0x80);
+        // The handler ran between the store and the load, stacking is not an access.
+        assert_eq!(emulator.hooks.instructions, 5);
+        assert_eq!(emulator.hooks.accesses, [1, 1]);
+        assert_eq!(
+            emulator.hooks.last,
+            Some((0x80, AccessDirection::Load, 0x80))
+        );
+        assert!(!emulator.exceptions.is_pending(15));
+    }
+
+    #[test]
+    fn debugger() {
+        let mut bytes = [0; 0x100];
+        let code: [(usize, u16); 7] = [
+            (3 * 4, 0x51),  // HardFault vector
+            (0x40, 0x2080), // movs r0, #0x80
+            (0x42, 0x2101), // movs r1, #1
+            (0x44, 0x6001), // str r1, [r0]
+            (0x46, 0x3101), // adds r1, #1
+            (0x48, 0x6001), // str r1, [r0]
+            (0x4a, 0xbe02), // bkpt 2
+        ];
+        for (address, halfword) in code {
+            bytes[address..address + 2].copy_from_slice(&halfword.to_le_bytes());
+        }
+        let memory = Ram {
+            base: 0,
+            bytes: &mut bytes,
+        };
+        let mut emulator = Emulator::new(CpuState::new(0x100, 0x40), memory);
+        assert!(emulator.debugger.add_breakpoint(0x42));
+        let watchpoint = Watchpoint {
+            range: 0x80..0x84,
+            access: WatchAccess::Write,
+            value: Some(2),
+        };
+        assert_eq!(emulator.debugger.add_watchpoint(watchpoint), Some(0));
+        assert_eq!(emulator.run(), Stop::Debug(DebugEvent::Breakpoint(0x42)));
+        assert_eq!(emulator.state.get(Register::PC), 0x42);
+        assert_eq!(
+            emulator.run(),
+            Stop::Debug(DebugEvent::Watchpoint {
+                index: 0,
+                address: 0x80,
+                direction: AccessDirection::Store,
+                value: 2
+            })
+        );
+        assert_eq!(emulator.state.get(Register::PC), 0x4a);
+        assert_eq!(emulator.run(), Stop::Breakpoint(2));
+
+        // Without a debugger bkpt faults.
+        emulator.debugger.trap_bkpt = false;
+        assert!(emulator.debugger.remove_breakpoint(0x42));
+        assert!(emulator.debugger.add_breakpoint(0x50));
+        assert_eq!(emulator.run(), Stop::Debug(DebugEvent::Breakpoint(0x50)));
+        assert!(emulator.exceptions.is_active(3));
+        assert_eq!(emulator.debugger.breakpoints().next(), Some(0x50));
+    }
+
+    #[test]
+    fn faults() {
+        let mut bytes = [0; 0x100];
+        let code: [(usize, u16); 4] = [
+            (3 * 4, 0x61),  // HardFault vector
+            (0x40, 0x4700), // bx r0
+            (0x42, 0xde00), // udf 0
+            (0x60, 0x6811), // ldr r1, [r2]
+        ];
+        for (address, halfword) in code {
+            bytes[address..address + 2].copy_from_slice(&halfword.to_le_bytes());
+        }
+        let memory = Ram {
+            base: 0,
+            bytes: &mut bytes,
+        };
+        let mut emulator = Emulator::new(CpuState::new(0x100, 0x40), memory);
+        emulator.state.set(Register::R0, 0x50);
+        emulator.state.set(Register::R2, 0x1000);
+        // The branch to an even address clears the Thumb bit, the next instruction faults.
+        assert_eq!(emulator.step(), Ok(()));
+        assert!(!emulator.state.thumb);
+        assert_eq!(emulator.step(), Ok(()));
+        let fault = FaultInfo {
+            cause: Fault::InvalidState,
+            address: 0x50,
+            exception: 0,
+            lockup: false,
+        };
+        assert_eq!(emulator.exceptions.last_fault(), Some(fault));
+        assert_eq!(emulator.state.get(Register::PC), 0x60);
+        // The unmapped load in HardFault locks up.
+        assert_eq!(emulator.step(), Err(Stop::Lockup));
+        let fault = FaultInfo {
+            cause: Fault::Bus {
+                address: 0x1000,
+                error: BusError::Unmapped,
+            },
+            address: 0x60,
+            exception: 3,
+            lockup: true,
+        };
+        assert_eq!(emulator.exceptions.last_fault(), Some(fault));
+
+        // Stacking to unmapped memory escalates, locking up taking HardFault.
+        emulator.state = CpuState::new(0x1000, 0x42);
+        emulator.exceptions = Exceptions::new(0);
+        assert_eq!(emulator.step(), Err(Stop::Lockup));
+        let cause = emulator.exceptions.last_fault().unwrap().cause;
+        assert_eq!(
+            cause,
+            Fault::Bus {
+                address: 0xfe0,
+                error: BusError::Unmapped
+            }
+        );
+
+        // Fetching from peripherals, or from memory without execute permission, faults.
+        let regions = [
+            (0x4000_0000, Permissions::RWX),
+            (0x2000_0000, Permissions::RW),
+        ];
+        for (base, permissions) in regions {
+            let mut bytes = [0; 0x100];
+            let memory = Protected {
+                memory: Ram {
+                    base,
+                    bytes: &mut bytes,
+                },
+                permissions,
+            };
+            let mut emulator = Emulator::new(CpuState::new(base + 0x100, base + 0x40), memory);
+            emulator.exceptions.vector_table = base;
+            assert_eq!(emulator.step(), Ok(()));
+            let cause = emulator.exceptions.last_fault().unwrap().cause;
+            let error = BusError::Permission;
+            assert_eq!(
+                cause,
+                Fault::Bus {
+                    address: base + 0x40,
+                    error
+                }
+            );
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn snapshots() {
+        use crate::memory::{CowRam, PAGE_SIZE};
+
+        let mut memory = CowRam::new(0, 4 * PAGE_SIZE as u32);
+        // str r0, [r1]; adds r0, #1; bkpt 0
+        for (address, halfword) in [(0, 0x6008), (2, 0x3001), (4, 0xbe00)] {
+            memory.write_halfword(address, halfword).unwrap();
+        }
+        let mut state = CpuState::new(0x100, 0);
+        state.set(Register::R1, 2 * PAGE_SIZE as u32);
+        let mut emulator = Emulator::new(state, memory);
+        let snapshot = emulator.snapshot();
+        assert_eq!(emulator.run(), Stop::Breakpoint(0));
+        assert_eq!(emulator.memory.read_word(2 * PAGE_SIZE as u32), Ok(0));
+        // Only the page written to was copied.
+        assert_eq!(emulator.memory.pages_copied(&snapshot.memory), 1);
+
+        emulator.restore(&snapshot);
+        emulator.state.set(Register::R0, 7);
+        assert_eq!(emulator.run(), Stop::Breakpoint(0));
+        assert_eq!(emulator.state.get(Register::R0), 8);
+        assert_eq!(emulator.memory.read_word(2 * PAGE_SIZE as u32), Ok(7));
+        assert_eq!(snapshot.state.get(Register::R0), 0);
+    }
+
+    #[test]
+    fn flags() {
+        assert_eq!(
+            add_with_carry(0x7fff_ffff, 1, false),
+            (0x8000_0000, false, true)
+        );
+        assert_eq!(add_with_carry(5, !5, true), (0, true, false));
+        assert_eq!(shift(0x8000_0001, Shift::Lsl, 1, false), (2, true));
+        assert_eq!(shift(0x8000_0000, Shift::Asr, 32, false), (!0, true));
+        assert_eq!(
+            shift(0x0000_0003, Shift::Ror, 1, false),
+            (0x8000_0001, true)
+        );
+        let apsr = Apsr::from_bits(0x6000_0000);
+        assert!(apsr.z && apsr.c && apsr.holds(Condition::LS) && !apsr.holds(Condition::HI));
+    }
+}
@@ -0,0 +1,628 @@
+//! Encoding of operations into their binary representation.
+//!
+//! # Example
+//! ```
+//! # use armv6_m_instruction_parser::{encoder::encode, instructons::Operation};
+//! let encoding = encode(&Operation::NOP).unwrap();
+//! assert_eq!(encoding.as_bytes(), &[0x00, 0xbf]);
+//! ```
+
+#[cfg(feature = "alloc")]
+use crate::constants::instruction_sequence;
+use crate::{
+    conditions::Condition,
+    instructons::{InstructionWidth, Operation},
+    registers::{Register, RegisterList},
+    Error,
+};
+#[cfg(feature = "alloc")]
+use alloc::{vec, vec::Vec};
+
+/// Binary representation of one instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Encoding {
+    bytes: [u8; 4],
+    width: InstructionWidth,
+}
+
+impl Encoding {
+    fn bit16(bits: u16) -> Self {
+        let [b0, b1] = bits.to_le_bytes();
+        Encoding {
+            bytes: [b0, b1, 0, 0],
+            width: InstructionWidth::Bit16,
+        }
+    }
+
+    fn bit32(bits: u32) -> Self {
+        let [b0, b1] = ((bits >> 16) as u16).to_le_bytes();
+        let [b2, b3] = (bits as u16).to_le_bytes();
+        Encoding {
+            bytes: [b0, b1, b2, b3],
+            width: InstructionWidth::Bit32,
+        }
+    }
+
+    /// Width of the encoding.
+    pub fn width(&self) -> InstructionWidth {
+        self.width
+    }
+
+    /// Size of the encoding in bytes.
+    pub fn size(&self) -> usize {
+        match self.width {
+            InstructionWidth::Bit16 => 2,
+            InstructionWidth::Bit32 => 4,
+        }
+    }
+
+    /// The encoding as little endian bytes, in the order they are stored in memory.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..self.size()]
+    }
+}
+
+/// Encodes an operation using its narrowest encoding.
+/// Returns an error if a register or immediate can not be encoded.
+pub fn encode(operation: &Operation) -> Result<Encoding, Error> {
+    use Operation as Op;
+
+    let encoding = match operation {
+        Op::ADCReg { m, n, d } if n == d => dn_m(0b0101, *d, *m)?,
+        Op::ADCReg { .. } => return Err(Error::InvalidOperands),
+        Op::ADDImm { imm, n, d } => {
+            if *imm <= 7 {
+                Encoding::bit16(0x1c00 | (*imm as u16) << 6 | low(*n)? << 3 | low(*d)?)
+            } else if n == d {
+                Encoding::bit16(0x3000 | low(*d)? << 8 | unsigned(*imm, 8, 0)?)
+            } else {
+                return Err(Error::ImmediateOutOfRange);
+            }
+        }
+        Op::ADDReg {
+            m,
+            n,
+            d,
+            set_flags: true,
+        } => Encoding::bit16(0x1800 | low(*m)? << 6 | low(*n)? << 3 | low(*d)?),
+        Op::ADDReg { m, n, d, .. } => {
+            if n == d {
+                if *d == Register::SP || *m == Register::SP {
+                    return Err(Error::InvalidRegister);
+                }
+                if *d == Register::PC && *m == Register::PC {
+                    return Err(Error::Unpredictable);
+                }
+                Encoding::bit16(0x4400 | high(*d) | (*m as u16) << 3)
+            } else {
+                return Err(Error::InvalidOperands);
+            }
+        }
+        Op::ADDImmSP {
+            d: Register::SP,
+            imm,
+        } => Encoding::bit16(0xb000 | unsigned(*imm, 7, 2)?),
+        Op::ADDImmSP { d, imm } => Encoding::bit16(0xa800 | low(*d)? << 8 | unsigned(*imm, 8, 2)?),
+        Op::ADDRegSP { d, m } if d == m => Encoding::bit16(0x4468 | high(*d)),
+        Op::ADDRegSP { d: Register::SP, m } => Encoding::bit16(0x4485 | (*m as u16) << 3),
+        Op::ADDRegSP { .. } => return Err(Error::InvalidOperands),
+        Op::ADR { d, imm } => Encoding::bit16(0xa000 | low(*d)? << 8 | unsigned(*imm, 8, 2)?),
+        Op::ANDReg { m, dn } => dn_m(0b0000, *dn, *m)?,
+        Op::ASRImm { imm, m, d } => {
+            Encoding::bit16(0x1000 | unsigned(*imm, 5, 0)? << 6 | low(*m)? << 3 | low(*d)?)
+        }
+        Op::ASRReg { m, dn } => dn_m(0b0100, *dn, *m)?,
+        Op::B {
+            cond: Condition::None,
+            imm,
+        } => Encoding::bit16(0xe000 | signed(*imm, 12)? as u16 >> 1 & 0x7ff),
+        Op::B { cond, imm } => {
+            Encoding::bit16(0xd000 | (*cond as u16) << 8 | signed(*imm, 9)? as u16 >> 1 & 0xff)
+        }
+        Op::BICReg { m, dn } => dn_m(0b1110, *dn, *m)?,
+        Op::BKPT { imm } => Encoding::bit16(0xbe00 | unsigned(*imm, 8, 0)?),
+        Op::BL { imm } => {
+            let imm = signed(*imm, 25)?;
+            let s = (imm >> 24) & 0x1;
+            let i1 = (imm >> 23) & 0x1;
+            let i2 = (imm >> 22) & 0x1;
+            let j1 = !(i1 ^ s) & 0x1;
+            let j2 = !(i2 ^ s) & 0x1;
+            let imm10 = (imm >> 12) & 0x3ff;
+            let imm11 = (imm >> 1) & 0x7ff;
+            Encoding::bit32(0xf000d000 | s << 26 | imm10 << 16 | j1 << 13 | j2 << 11 | imm11)
+        }
+        Op::BLXReg { m: Register::PC } => return Err(Error::Unpredictable),
+        Op::BLXReg { m } => Encoding::bit16(0x4780 | (*m as u16) << 3),
+        Op::BX { m } => Encoding::bit16(0x4700 | (*m as u16) << 3),
+        Op::CMNReg { m, n } => dn_m(0b1011, *n, *m)?,
+        Op::CMPImm { n, imm } => Encoding::bit16(0x2800 | low(*n)? << 8 | unsigned(*imm, 8, 0)?),
+        Op::CMPReg { m, n } => {
+            if is_low(*m) && is_low(*n) {
+                dn_m(0b1010, *n, *m)?
+            } else if *m == Register::PC || *n == Register::PC {
+                return Err(Error::Unpredictable);
+            } else {
+                Encoding::bit16(0x4500 | high(*n) | (*m as u16) << 3)
+            }
+        }
+        Op::CPS { im } => Encoding::bit16(0xb662 | (*im as u16) << 4),
+        // CPY carries no registers, it is written as a MOV.
+        Op::CPY => return Err(Error::InvalidOperands),
+        Op::DMB { option } => Encoding::bit32(0xf3bf8f50 | unsigned(*option as u32, 4, 0)? as u32),
+        Op::DSB { option } => Encoding::bit32(0xf3bf8f40 | unsigned(*option as u32, 4, 0)? as u32),
+        Op::EORReg { m, dn } => dn_m(0b0001, *dn, *m)?,
+        Op::ISB { option } => Encoding::bit32(0xf3bf8f60 | unsigned(*option as u32, 4, 0)? as u32),
+        Op::LDM { n, reg_list } => {
+            Encoding::bit16(0xc800 | low(*n)? << 8 | register_bits(*reg_list, 0xff)?)
+        }
+        Op::LDRImm {
+            imm,
+            n: Register::SP,
+            t,
+        } => Encoding::bit16(0x9800 | low(*t)? << 8 | unsigned(*imm, 8, 2)?),
+        Op::LDRImm { imm, n, t } => load_store_imm(0x6800, *imm, 2, *n, *t)?,
+        Op::LDRLiteral { t, imm } => {
+            Encoding::bit16(0x4800 | low(*t)? << 8 | unsigned(*imm, 8, 2)?)
+        }
+        Op::LDRReg { m, n, t } => load_store_reg(0b100, *m, *n, *t)?,
+        Op::LDRBImm { imm, n, t } => load_store_imm(0x7800, *imm, 0, *n, *t)?,
+        Op::LDRBReg { m, n, t } => load_store_reg(0b110, *m, *n, *t)?,
+        Op::LDRHImm { imm, n, t } => load_store_imm(0x8800, *imm, 1, *n, *t)?,
+        Op::LDRHReg { m, n, t } => load_store_reg(0b101, *m, *n, *t)?,
+        Op::LDRSBReg { m, n, t } => load_store_reg(0b011, *m, *n, *t)?,
+        Op::LDRSH { m, n, t } => load_store_reg(0b111, *m, *n, *t)?,
+        Op::LSLImm { imm, m, d } => {
+            Encoding::bit16(unsigned(*imm, 5, 0)? << 6 | low(*m)? << 3 | low(*d)?)
+        }
+        Op::LSLReg { m, dn } => dn_m(0b0010, *dn, *m)?,
+        Op::LSRImm { imm, m, d } => {
+            Encoding::bit16(0x0800 | unsigned(*imm, 5, 0)? << 6 | low(*m)? << 3 | low(*d)?)
+        }
+        Op::LSRReg { m, dn } => dn_m(0b0011, *dn, *m)?,
+        Op::MOVImm { d, imm } => Encoding::bit16(0x2000 | low(*d)? << 8 | unsigned(*imm, 8, 0)?),
+        Op::MOVReg {
+            m,
+            d,
+            set_flags: true,
+        } => Encoding::bit16(low(*m)? << 3 | low(*d)?),
+        Op::MOVReg {
+            m,
+            d,
+            set_flags: false,
+        } => Encoding::bit16(0x4600 | high(*d) | (*m as u16) << 3),
+        Op::MRS { d, sysm } => {
+            Encoding::bit32(0xf3ef8000 | (not_sp_pc(*d)? as u32) << 8 | *sysm as u32)
+        }
+        Op::MSRReg { n, sysm } => {
+            Encoding::bit32(0xf3808800 | (not_sp_pc(*n)? as u32) << 16 | *sysm as u32)
+        }
+        Op::MUL { n, dm } => dn_m(0b1101, *dm, *n)?,
+        Op::MVNReg { m, d } => dn_m(0b1111, *d, *m)?,
+        Op::NOP => Encoding::bit16(0xbf00),
+        Op::ORRReg { m, dn } => dn_m(0b1100, *dn, *m)?,
+        Op::POP { reg_list } => Encoding::bit16(0xbc00 | pc_lr_list(*reg_list, Register::PC)?),
+        Op::PUSH { reg_list } => Encoding::bit16(0xb400 | pc_lr_list(*reg_list, Register::LR)?),
+        Op::REV { m, d } => Encoding::bit16(0xba00 | low(*m)? << 3 | low(*d)?),
+        Op::REV16 { m, d } => Encoding::bit16(0xba40 | low(*m)? << 3 | low(*d)?),
+        Op::REVSH { m, d } => Encoding::bit16(0xbac0 | low(*m)? << 3 | low(*d)?),
+        Op::RORReg { m, dn } => dn_m(0b0111, *dn, *m)?,
+        Op::RSBImm { n, d } => dn_m(0b1001, *d, *n)?,
+        Op::SBCReg { m, dn } => dn_m(0b0110, *dn, *m)?,
+        Op::SEV => Encoding::bit16(0xbf40),
+        Op::STM { n, reg_list } => {
+            Encoding::bit16(0xc000 | low(*n)? << 8 | register_bits(*reg_list, 0xff)?)
+        }
+        Op::STRImm {
+            imm,
+            n: Register::SP,
+            t,
+        } => Encoding::bit16(0x9000 | low(*t)? << 8 | unsigned(*imm, 8, 2)?),
+        Op::STRImm { imm, n, t } => load_store_imm(0x6000, *imm, 2, *n, *t)?,
+        Op::STRReg { m, n, t } => load_store_reg(0b000, *m, *n, *t)?,
+        Op::STRBImm { imm, n, t } => load_store_imm(0x7000, *imm, 0, *n, *t)?,
+        Op::STRBReg { m, n, t } => load_store_reg(0b010, *m, *n, *t)?,
+        Op::STRHImm { imm, n, t } => load_store_imm(0x8000, *imm, 1, *n, *t)?,
+        Op::STRHReg { m, n, t } => load_store_reg(0b001, *m, *n, *t)?,
+        Op::SUBImm { imm, n, d } => {
+            if *imm <= 7 {
+                Encoding::bit16(0x1e00 | (*imm as u16) << 6 | low(*n)? << 3 | low(*d)?)
+            } else if n == d {
+                Encoding::bit16(0x3800 | low(*d)? << 8 | unsigned(*imm, 8, 0)?)
+            } else {
+                return Err(Error::ImmediateOutOfRange);
+            }
+        }
+        Op::SUBReg { m, n, d } => {
+            Encoding::bit16(0x1a00 | low(*m)? << 6 | low(*n)? << 3 | low(*d)?)
+        }
+        Op::SUBImmSP { imm } => Encoding::bit16(0xb080 | unsigned(*imm, 7, 2)?),
+        Op::SVC { imm } => Encoding::bit16(0xdf00 | unsigned(*imm, 8, 0)?),
+        Op::SXTB { m, d } => Encoding::bit16(0xb240 | low(*m)? << 3 | low(*d)?),
+        Op::SXTH { m, d } => Encoding::bit16(0xb200 | low(*m)? << 3 | low(*d)?),
+        Op::TSTReg { m, n } => dn_m(0b1000, *n, *m)?,
+        Op::UDF { imm } if *imm <= 0xff => Encoding::bit16(0xde00 | *imm as u16),
+        Op::UDF { imm } => {
+            let imm = unsigned(*imm, 16, 0)? as u32;
+            Encoding::bit32(0xf7f0a000 | (imm >> 12) << 16 | (imm & 0xfff))
+        }
+        Op::UXTB { m, d } => Encoding::bit16(0xb2c0 | low(*m)? << 3 | low(*d)?),
+        Op::UXTH { m, d } => Encoding::bit16(0xb280 | low(*m)? << 3 | low(*d)?),
+        Op::WFE => Encoding::bit16(0xbf20),
+        Op::WFI => Encoding::bit16(0xbf30),
+        Op::YIELD => Encoding::bit16(0xbf10),
+    };
+    Ok(encoding)
+}
+
+#[cfg(feature = "alloc")]
+/// Alternative to an operation that can not be encoded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Suggestion {
+    /// Operations with the same effect that can all be encoded, e.g. two `adds` for an
+    /// immediate too large for one.
+    Split(Vec<Operation>),
+    /// A similar operation that can be encoded but has other side effects, e.g. `bl` for an
+    /// unconditional branch out of range, which also writes `lr`.
+    Alternative(Operation),
+    /// Use low registers, the operation has no encoding for high registers.
+    LowRegisters,
+    /// Load the value with `ldr rX, =value` instead.
+    LiteralLoad,
+    /// Move the immediate to a register and use the register form of the operation.
+    RegisterOperand,
+    /// The immediate must be a multiple of this.
+    Align(u32),
+}
+
+#[cfg(feature = "alloc")]
+/// Most operations a [`Suggestion::Split`] replaces an operation with.
+const MAX_SPLIT: usize = 4;
+
+#[cfg(feature = "alloc")]
+/// Returns a suggestion of what to use instead of `operation` if it can not be encoded.
+/// Returns `None` if it can be encoded or no alternative is known.
+///
+/// # Example
+/// ```
+/// # use armv6_m_instruction_parser::{encoder::{suggest, Suggestion}, instructons::Operation, registers::Register};
+/// let add = Operation::ADDImm { imm: 300, n: Register::R0, d: Register::R0 };
+/// assert_eq!(
+///     suggest(&add),
+///     Some(Suggestion::Split(vec![
+///         Operation::ADDImm { imm: 255, n: Register::R0, d: Register::R0 },
+///         Operation::ADDImm { imm: 45, n: Register::R0, d: Register::R0 },
+///     ]))
+/// );
+/// ```
+pub fn suggest(operation: &Operation) -> Option<Suggestion> {
+    use Operation as Op;
+    let error = encode(operation).err()?;
+    let encodable = |operations: Vec<Operation>| {
+        (operations.len() <= MAX_SPLIT && operations.iter().all(|op| encode(op).is_ok()))
+            .then_some(Suggestion::Split(operations))
+    };
+    let alignment = |imm: u32, alignment: u32| {
+        if !imm.is_multiple_of(alignment) {
+            Suggestion::Align(alignment)
+        } else {
+            Suggestion::RegisterOperand
+        }
+    };
+
+    let suggestion = match (operation, error) {
+        (_, Error::InvalidRegister) => Suggestion::LowRegisters,
+        (_, error) if error != Error::ImmediateOutOfRange => return None,
+        (Op::ADDImm { imm, n, d } | Op::SUBImm { imm, n, d }, _) => {
+            let add = matches!(operation, Op::ADDImm { .. });
+            let split = split_immediate(*imm, *n, *d)
+                .into_iter()
+                .map(|(imm, n)| match add {
+                    true => Op::ADDImm { imm, n, d: *d },
+                    false => Op::SUBImm { imm, n, d: *d },
+                })
+                .collect();
+            encodable(split).unwrap_or(Suggestion::RegisterOperand)
+        }
+        (Op::MOVImm { d, imm }, _) => instruction_sequence(*imm, *d)
+            .and_then(encodable)
+            .unwrap_or(Suggestion::LiteralLoad),
+        (
+            Op::B {
+                cond: Condition::None,
+                imm,
+            },
+            _,
+        ) => Suggestion::Alternative(Op::BL { imm: *imm }),
+        (Op::B { cond, imm }, _) => encodable(vec![
+            Op::B {
+                cond: cond.inverted()?,
+                imm: 0,
+            },
+            Op::B {
+                cond: Condition::None,
+                imm: imm.wrapping_sub(2),
+            },
+        ])?,
+        (Op::CMPImm { .. }, _) => Suggestion::RegisterOperand,
+        (
+            Op::LDRImm { imm, .. }
+            | Op::STRImm { imm, .. }
+            | Op::LDRLiteral { imm, .. }
+            | Op::ADR { imm, .. }
+            | Op::ADDImmSP { imm, .. }
+            | Op::SUBImmSP { imm },
+            _,
+        ) => alignment(*imm, 4),
+        (Op::LDRHImm { imm, .. } | Op::STRHImm { imm, .. }, _) => alignment(*imm, 2),
+        (Op::LDRBImm { .. } | Op::STRBImm { .. }, _) => Suggestion::RegisterOperand,
+        _ => return None,
+    };
+    Some(suggestion)
+}
+
+#[cfg(feature = "alloc")]
+/// Splits an immediate added to `n` and written to `d` into immediates that fit the
+/// encodings, the first one with `n` as source and the rest adding to `d`.
+fn split_immediate(mut imm: u32, n: Register, d: Register) -> Vec<(u32, Register)> {
+    let mut split = vec![];
+    if n != d {
+        let first = imm.min(7);
+        split.push((first, n));
+        imm -= first;
+    }
+    while imm > 0 {
+        let part = imm.min(0xff);
+        split.push((part, d));
+        imm -= part;
+    }
+    split
+}
+
+fn is_low(register: Register) -> bool {
+    (register as u8) < 8
+}
+
+/// Register number of a register that has to be one of R0-R7.
+fn low(register: Register) -> Result<u16, Error> {
+    if is_low(register) {
+        Ok(register as u16)
+    } else {
+        Err(Error::InvalidRegister)
+    }
+}
+
+/// Register fields of the encodings splitting a 4 bit register into bit 7 and bits 2-0.
+fn high(register: Register) -> u16 {
+    let register = register as u16;
+    (register & 0b1000) << 4 | (register & 0b111)
+}
+
+fn not_sp_pc(register: Register) -> Result<Register, Error> {
+    match register {
+        Register::SP | Register::PC => Err(Error::Unpredictable),
+        register => Ok(register),
+    }
+}
+
+/// Checks that `value` is a multiple of `1 << shift` that fits `bits` bits after shifting.
+fn unsigned(value: u32, bits: u32, shift: u32) -> Result<u16, Error> {
+    if value & ((1 << shift) - 1) != 0 || value >> shift >= 1 << bits {
+        return Err(Error::ImmediateOutOfRange);
+    }
+    Ok((value >> shift) as u16)
+}
+
+/// Checks that a sign extended branch offset is even and fits `bits` bits.
+fn signed(value: u32, bits: u32) -> Result<u32, Error> {
+    let offset = value as i32;
+    let limit = 1 << (bits - 1);
+    if offset & 1 != 0 || offset < -limit || offset >= limit {
+        return Err(Error::ImmediateOutOfRange);
+    }
+    Ok(value)
+}
+
+/// Data processing encoding with two low registers.
+fn dn_m(opcode: u16, dn: Register, m: Register) -> Result<Encoding, Error> {
+    Ok(Encoding::bit16(
+        0x4000 | opcode << 6 | low(m)? << 3 | low(dn)?,
+    ))
+}
+
+fn load_store_imm(
+    base: u16,
+    imm: u32,
+    shift: u32,
+    n: Register,
+    t: Register,
+) -> Result<Encoding, Error> {
+    Ok(Encoding::bit16(
+        base | unsigned(imm, 5, shift)? << 6 | low(n)? << 3 | low(t)?,
+    ))
+}
+
+fn load_store_reg(opb: u16, m: Register, n: Register, t: Register) -> Result<Encoding, Error> {
+    Ok(Encoding::bit16(
+        0x5000 | opb << 9 | low(m)? << 6 | low(n)? << 3 | low(t)?,
+    ))
+}
+
+/// Bit array of a register list only containing registers in `allowed`.
+fn register_bits(reg_list: RegisterList, allowed: u16) -> Result<u16, Error> {
+    let bits = reg_list.bits();
+    if bits == 0 || bits & !allowed != 0 {
+        return Err(Error::InvalidRegister);
+    }
+    Ok(bits)
+}
+
+/// Register list of PUSH and POP where `extra` is encoded in bit 8.
+fn pc_lr_list(reg_list: RegisterList, extra: Register) -> Result<u16, Error> {
+    let bits = register_bits(reg_list, 0xff | 1 << extra as u16)?;
+    Ok(bits & 0xff | ((bits >> extra as u16) & 0b1) << 8)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parse;
+
+    fn round_trip(bytes: &[u8]) {
+        let instruction = parse(bytes).unwrap();
+        let encoding = encode(&instruction.operation).unwrap();
+        assert_eq!(encoding.as_bytes(), bytes, "{:?}", instruction.operation);
+        assert_eq!(encoding.width(), instruction.width);
+    }
+
+    #[test]
+    fn encode_16bit() {
+        for bits in [
+            0x1d08u16, 0x32c8, 0xaf02, 0xb084, 0xb004, 0x4488, 0x4495, 0x4468, 0x1a88, 0x4248,
+            0x4348, 0x4008, 0x20ff, 0x0008, 0x4688, 0x00c8, 0x0808, 0x1048, 0x41c8, 0x6848, 0x9802,
+            0x9002, 0x4802, 0x7008, 0x8848, 0x5e88, 0x5688, 0xb5f0, 0xbd10, 0xc806, 0xc006, 0xe7fe,
+            0xd104, 0x4770, 0x4788, 0xdf01, 0xbeab, 0xde00, 0xb672, 0xb662, 0xa001, 0x4540, 0x4288,
+            0x42c8, 0x4208, 0xb208, 0xba48, 0xbac8, 0xbf00, 0xbf10, 0xbf20, 0xbf30, 0xbf40,
+        ] {
+            round_trip(&bits.to_le_bytes());
+        }
+    }
+
+    #[test]
+    fn encode_32bit() {
+        round_trip(&[0x00, 0xf0, 0x00, 0xf8]);
+        round_trip(&[0xff, 0xf7, 0xfe, 0xff]);
+        round_trip(&[0x00, 0xf4, 0x00, 0xd0]);
+        round_trip(&[0xbf, 0xf3, 0x5f, 0x8f]);
+        round_trip(&[0xbf, 0xf3, 0x4f, 0x8f]);
+        round_trip(&[0xbf, 0xf3, 0x6f, 0x8f]);
+        round_trip(&[0x80, 0xf3, 0x10, 0x88]);
+        round_trip(&[0xef, 0xf3, 0x09, 0x80]);
+        round_trip(&[0xf1, 0xf7, 0x34, 0xa2]);
+    }
+
+    #[test]
+    fn encode_errors() {
+        let add = Operation::ADDImm {
+            imm: 300,
+            n: Register::R0,
+            d: Register::R0,
+        };
+        assert_eq!(encode(&add), Err(Error::ImmediateOutOfRange));
+        let ldr = Operation::LDRImm {
+            imm: 2,
+            n: Register::R0,
+            t: Register::R1,
+        };
+        assert_eq!(encode(&ldr), Err(Error::ImmediateOutOfRange));
+        let mov = Operation::MOVImm {
+            d: Register::R8,
+            imm: 1,
+        };
+        assert_eq!(encode(&mov), Err(Error::InvalidRegister));
+        let b = Operation::B {
+            cond: Condition::EQ,
+            imm: 256,
+        };
+        assert_eq!(encode(&b), Err(Error::ImmediateOutOfRange));
+    }
+
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn suggestions() {
+        assert_eq!(suggest(&Operation::NOP), None);
+        let add = Operation::ADDImm {
+            imm: 100,
+            n: Register::R1,
+            d: Register::R0,
+        };
+        assert_eq!(
+            suggest(&add),
+            Some(Suggestion::Split(vec![
+                Operation::ADDImm {
+                    imm: 7,
+                    n: Register::R1,
+                    d: Register::R0
+                },
+                Operation::ADDImm {
+                    imm: 93,
+                    n: Register::R0,
+                    d: Register::R0
+                },
+            ]))
+        );
+        let sub = Operation::SUBImm {
+            imm: 2000,
+            n: Register::R0,
+            d: Register::R0,
+        };
+        assert_eq!(suggest(&sub), Some(Suggestion::RegisterOperand));
+        let mov = Operation::MOVImm {
+            d: Register::R2,
+            imm: 0x1_0000,
+        };
+        assert_eq!(
+            suggest(&mov),
+            Some(Suggestion::Split(vec![
+                Operation::MOVImm {
+                    d: Register::R2,
+                    imm: 1
+                },
+                Operation::LSLImm {
+                    imm: 16,
+                    m: Register::R2,
+                    d: Register::R2
+                },
+            ]))
+        );
+        let mov = Operation::MOVImm {
+            d: Register::R2,
+            imm: 0x1234_5678,
+        };
+        assert_eq!(suggest(&mov), Some(Suggestion::LiteralLoad));
+        let mov = Operation::MOVImm {
+            d: Register::R8,
+            imm: 1,
+        };
+        assert_eq!(suggest(&mov), Some(Suggestion::LowRegisters));
+
+        let beq = Operation::B {
+            cond: Condition::EQ,
+            imm: 1000,
+        };
+        assert_eq!(
+            suggest(&beq),
+            Some(Suggestion::Split(vec![
+                Operation::B {
+                    cond: Condition::NE,
+                    imm: 0
+                },
+                Operation::B {
+                    cond: Condition::None,
+                    imm: 998
+                },
+            ]))
+        );
+        let b = Operation::B {
+            cond: Condition::None,
+            imm: 4096,
+        };
+        assert_eq!(
+            suggest(&b),
+            Some(Suggestion::Alternative(Operation::BL { imm: 4096 }))
+        );
+
+        let ldr = Operation::LDRImm {
+            imm: 6,
+            n: Register::R0,
+            t: Register::R1,
+        };
+        assert_eq!(suggest(&ldr), Some(Suggestion::Align(4)));
+        let ldr = Operation::LDRImm {
+            imm: 128,
+            n: Register::R0,
+            t: Register::R1,
+        };
+        assert_eq!(suggest(&ldr), Some(Suggestion::RegisterOperand));
+    }
+}
@@ -0,0 +1,562 @@
+//! ESIL, the semantic strings of radare2 and rizin, of the decoded instructions, so their
+//! analysis engines can run on the decoding of this crate.
+//!
+//! The strings follow the ARM emission of radare2, flags are set from the `$z`, `$s`, `$c`,
+//! `$b` and `$o` internal flags of the last assignment or comparison, and `pc` is read as
+//! the address of the instruction plus 4, written as a constant.
+//!
+//! # Example
+//! ```
+//! # use armv6_m_instruction_parser::{instructons::DecodedAt, parse};
+//! // adds r0, r1, #4
+//! let instruction = parse(&[0x08, 0x1d]).unwrap();
+//! let decoded = DecodedAt { instruction, address: 0x100 };
+//! assert_eq!(decoded.esil(), "0x4,r1,+,r0,=,$z,zf,:=,31,$s,nf,:=,31,$c,cf,:=,31,$o,vf,:=");
+//! ```
+
+use alloc::{format, string::String, vec::Vec};
+
+use crate::{
+    conditions::Condition,
+    instructons::{DecodedAt, Operation},
+    registers::{Register, RegisterList, SpecialRegister},
+};
+
+/// Flags set from the result of the last assignment.
+const NZ: &str = "$z,zf,:=,31,$s,nf,:=";
+/// Carry and overflow of an addition.
+const CV_ADD: &str = "31,$c,cf,:=,31,$o,vf,:=";
+/// Carry and overflow of a subtraction, the carry the inverted borrow.
+const CV_SUB: &str = "31,$b,!,cf,:=,31,$o,vf,:=";
+
+impl DecodedAt {
+    /// Returns the ESIL of the instruction, empty for hints and barriers.
+    pub fn esil(&self) -> String {
+        let pc = self.address.wrapping_add(4);
+        let r = |register: Register| match register {
+            Register::PC => format!("{pc:#x}"),
+            register => String::from(name(register)),
+        };
+        let list = |reg_list: RegisterList| reg_list.iter().collect::<Vec<_>>();
+
+        match self.instruction.operation {
+            Operation::ADCReg { m, n, d } => {
+                let sum = op(&op(&r(n), "+", &r(m)), "+", "cf");
+                format!("{sum},{},=,{NZ},{CV_ADD}", name(d))
+            }
+            Operation::ADDImm { imm, n, d } => {
+                format!("{},{},=,{NZ},{CV_ADD}", plus(&r(n), imm), name(d))
+            }
+            Operation::ADDReg { m, n, d, set_flags } => {
+                let sum = op(&r(n), "+", &r(m));
+                match set_flags {
+                    true => format!("{sum},{},=,{NZ},{CV_ADD}", name(d)),
+                    false => format!("{sum},{},=", name(d)),
+                }
+            }
+            Operation::ADDImmSP { d, imm } => {
+                format!("{},{},=", op("sp", "+", &hex(imm)), name(d))
+            }
+            Operation::ADDRegSP { d, m } => format!("{},{},=", op("sp", "+", &r(m)), name(d)),
+            Operation::ADR { d, imm } => {
+                format!("{},{},=", hex((pc & !0b11).wrapping_add(imm)), name(d))
+            }
+            Operation::ANDReg { m, dn } => logical(&r(dn), "&", &r(m)),
+            Operation::ASRImm { imm, m, d } => shift_immediate(">>>>", imm, &r(m), name(d)),
+            Operation::ASRReg { m, dn } => shift_register(">>>>", &r(m), &r(dn)),
+            Operation::B { cond, imm } => {
+                let branch = format!("{},pc,=", hex(pc.wrapping_add(imm)));
+                match condition(cond) {
+                    Some(condition) => format!("{condition},?{{,{branch},}}"),
+                    None => branch,
+                }
+            }
+            Operation::BICReg { m, dn } => logical(&r(dn), "&", &not(&r(m))),
+            Operation::BKPT { .. } | Operation::CPY | Operation::UDF { .. } => "TRAP".into(),
+            Operation::BL { imm } => format!(
+                "{},lr,=,{},pc,=",
+                hex(self.next_address() | 1),
+                hex(pc.wrapping_add(imm))
+            ),
+            // The target is read before the link is written, for `blx lr`.
+            Operation::BLXReg { m } => format!(
+                "{},{},lr,=,0xfffffffe,&,pc,=",
+                r(m),
+                hex(self.next_address() | 1)
+            ),
+            Operation::BX { m } => format!("{},pc,=", op(&r(m), "&", "0xfffffffe")),
+            // The flags of `n - -m`, without the carry and overflow of the addition.
+            Operation::CMNReg { m, n } => format!("{},{},==,{NZ}", op("0", "-", &r(m)), r(n)),
+            Operation::CMPImm { n, imm } => format!("{},{},==,{NZ},{CV_SUB}", hex(imm), r(n)),
+            Operation::CMPReg { m, n } => format!("{},{},==,{NZ},{CV_SUB}", r(m), r(n)),
+            Operation::CPS { im } => format!("{},primask,=", im as u32),
+            Operation::DMB { .. }
+            | Operation::DSB { .. }
+            | Operation::ISB { .. }
+            | Operation::NOP
+            | Operation::SEV
+            | Operation::WFE
+            | Operation::WFI
+            | Operation::YIELD => String::new(),
+            Operation::EORReg { m, dn } => logical(&r(dn), "^", &r(m)),
+            Operation::LDM { n, reg_list } => {
+                // Loaded to the stack before assigning, for a base in the list.
+                let registers = list(reg_list);
+                let mut parts: Vec<_> = (0..registers.len() as u32)
+                    .map(|index| format!("{},[4]", plus(&r(n), 4 * index)))
+                    .collect();
+                parts.extend(
+                    registers
+                        .iter()
+                        .rev()
+                        .map(|register| format!("{},=", name(*register))),
+                );
+                if !reg_list.contains(n) {
+                    let end = op(&r(n), "+", &hex(4 * registers.len() as u32));
+                    parts.push(format!("{end},{},=", name(n)));
+                }
+                parts.join(",")
+            }
+            Operation::LDRImm { imm, n, t } => load(&plus(&r(n), imm), 4, name(t)),
+            Operation::LDRLiteral { t, imm } => {
+                load(&hex((pc & !0b11).wrapping_add(imm)), 4, name(t))
+            }
+            Operation::LDRReg { m, n, t } => load(&op(&r(n), "+", &r(m)), 4, name(t)),
+            Operation::LDRBImm { imm, n, t } => load(&plus(&r(n), imm), 1, name(t)),
+            Operation::LDRBReg { m, n, t } => load(&op(&r(n), "+", &r(m)), 1, name(t)),
+            Operation::LDRHImm { imm, n, t } => load(&plus(&r(n), imm), 2, name(t)),
+            Operation::LDRHReg { m, n, t } => load(&op(&r(n), "+", &r(m)), 2, name(t)),
+            Operation::LDRSBReg { m, n, t } => {
+                let value = format!("{},[1]", op(&r(n), "+", &r(m)));
+                format!("{},{},=", sign_extend(&value, 8), name(t))
+            }
+            Operation::LDRSH { m, n, t } => {
+                let value = format!("{},[2]", op(&r(n), "+", &r(m)));
+                format!("{},{},=", sign_extend(&value, 16), name(t))
+            }
+            Operation::LSLImm { imm, m, d } => shift_immediate("<<", imm, &r(m), name(d)),
+            Operation::LSLReg { m, dn } => shift_register("<<", &r(m), &r(dn)),
+            Operation::LSRImm { imm, m, d } => shift_immediate(">>", imm, &r(m), name(d)),
+            Operation::LSRReg { m, dn } => shift_register(">>", &r(m), &r(dn)),
+            Operation::MOVImm { d, imm } => format!("{},{},=,{NZ}", hex(imm), name(d)),
+            Operation::MOVReg { m, d, set_flags } => match set_flags {
+                true => format!("{},{},=,{NZ}", r(m), name(d)),
+                false => format!("{},{},=", r(m), name(d)),
+            },
+            Operation::MRS { d, sysm } => format!("{},{},=", special(sysm), name(d)),
+            Operation::MSRReg { n, sysm } => format!("{},{},=", r(n), special(sysm)),
+            Operation::MUL { n, dm } => {
+                format!("{},{},=,{NZ}", op(&r(n), "*", &r(dm)), name(dm))
+            }
+            Operation::MVNReg { m, d } => format!("{},{},=,{NZ}", not(&r(m)), name(d)),
+            Operation::ORRReg { m, dn } => logical(&r(dn), "|", &r(m)),
+            Operation::POP { reg_list } => {
+                let registers = list(reg_list);
+                let mut parts: Vec<_> = (0..registers.len() as u32)
+                    .map(|index| format!("{},[4]", plus("sp", 4 * index)))
+                    .collect();
+                let end = op("sp", "+", &hex(4 * registers.len() as u32));
+                parts.push(format!("{end},sp,="));
+                parts.extend(registers.iter().rev().map(|register| match register {
+                    Register::PC => String::from("0xfffffffe,&,pc,="),
+                    register => format!("{},=", name(*register)),
+                }));
+                parts.join(",")
+            }
+            Operation::PUSH { reg_list } => {
+                let registers = list(reg_list);
+                let size = 4 * registers.len() as u32;
+                let mut parts: Vec<_> = (0..)
+                    .zip(&registers)
+                    .map(|(index, register)| {
+                        let address = op("sp", "-", &hex(size - 4 * index));
+                        format!("{},{address},=[4]", r(*register))
+                    })
+                    .collect();
+                parts.push(format!("{},sp,=", op("sp", "-", &hex(size))));
+                parts.join(",")
+            }
+            Operation::REV { m, d } => {
+                let m = r(m);
+                let bytes = [
+                    op(&op(&m, ">>", "24"), "&", "0xff"),
+                    op(&op(&m, ">>", "8"), "&", "0xff00"),
+                    op(&op(&m, "<<", "8"), "&", "0xff0000"),
+                    op(&op(&m, "<<", "24"), "&", "0xff000000"),
+                ];
+                let value = bytes.into_iter().reduce(|x, y| op(&x, "|", &y)).unwrap();
+                format!("{value},{},=", name(d))
+            }
+            Operation::REV16 { m, d } => format!("{},{},=", swap_halfword_bytes(&r(m)), name(d)),
+            Operation::REVSH { m, d } => {
+                let m = r(m);
+                let low = op(
+                    &op(&op(&m, ">>", "8"), "&", "0xff"),
+                    "|",
+                    &op(&op(&m, "<<", "8"), "&", "0xff00"),
+                );
+                format!("{},{},=", sign_extend(&low, 16), name(d))
+            }
+            Operation::RORReg { m, dn } => {
+                let (amount, dn) = (op(&r(m), "&", "0xff"), name(dn));
+                format!(
+                    "{},{dn},=,{NZ},{amount},?{{,31,{dn},>>,cf,:=,}}",
+                    op(dn, ">>>", &op(&r(m), "&", "0x1f"))
+                )
+            }
+            Operation::RSBImm { n, d } => {
+                format!("{},{},=,{NZ},{CV_SUB}", op("0", "-", &r(n)), name(d))
+            }
+            Operation::SBCReg { m, dn } => {
+                let subtrahend = op(&r(m), "+", "cf,!");
+                format!(
+                    "{},{},=,{NZ},{CV_SUB}",
+                    op(&r(dn), "-", &subtrahend),
+                    name(dn)
+                )
+            }
+            Operation::STM { n, reg_list } => {
+                let registers = list(reg_list);
+                let mut parts: Vec<_> = (0..)
+                    .zip(&registers)
+                    .map(|(index, register)| {
+                        let address = plus(&r(n), 4 * index);
+                        format!("{},{address},=[4]", r(*register))
+                    })
+                    .collect();
+                let end = op(&r(n), "+", &hex(4 * registers.len() as u32));
+                parts.push(format!("{end},{},=", name(n)));
+                parts.join(",")
+            }
+            Operation::STRImm { imm, n, t } => store(&r(t), &plus(&r(n), imm), 4),
+            Operation::STRReg { m, n, t } => store(&r(t), &op(&r(n), "+", &r(m)), 4),
+            Operation::STRBImm { imm, n, t } => store(&r(t), &plus(&r(n), imm), 1),
+            Operation::STRBReg { m, n, t } => store(&r(t), &op(&r(n), "+", &r(m)), 1),
+            Operation::STRHImm { imm, n, t } => store(&r(t), &plus(&r(n), imm), 2),
+            Operation::STRHReg { m, n, t } => store(&r(t), &op(&r(n), "+", &r(m)), 2),
+            Operation::SUBImm { imm, n, d } => {
+                format!("{},{},=,{NZ},{CV_SUB}", op(&r(n), "-", &hex(imm)), name(d))
+            }
+            Operation::SUBReg { m, n, d } => {
+                format!("{},{},=,{NZ},{CV_SUB}", op(&r(n), "-", &r(m)), name(d))
+            }
+            Operation::SUBImmSP { imm } => format!("{},sp,=", op("sp", "-", &hex(imm))),
+            Operation::SVC { imm } => format!("{},$", hex(imm)),
+            Operation::SXTB { m, d } => format!("{},{},=", sign_extend(&r(m), 8), name(d)),
+            Operation::SXTH { m, d } => format!("{},{},=", sign_extend(&r(m), 16), name(d)),
+            Operation::TSTReg { m, n } => format!("0,{},==,{NZ}", op(&r(n), "&", &r(m))),
+            Operation::UXTB { m, d } => format!("{},{},=", op(&r(m), "&", "0xff"), name(d)),
+            Operation::UXTH { m, d } => format!("{},{},=", op(&r(m), "&", "0xffff"), name(d)),
+        }
+    }
+}
+
+/// Returns the ESIL of `x operator y`, `y,x,operator`.
+fn op(x: &str, operator: &str, y: &str) -> String {
+    format!("{y},{x},{operator}")
+}
+
+/// Returns the ESIL of `x + imm`, `x` for 0.
+fn plus(x: &str, imm: u32) -> String {
+    match imm {
+        0 => String::from(x),
+        imm => op(x, "+", &hex(imm)),
+    }
+}
+
+fn hex(value: u32) -> String {
+    format!("{value:#x}")
+}
+
+fn not(x: &str) -> String {
+    op(x, "^", "0xffffffff")
+}
+
+/// Returns the ESIL of the low `bits` of `x` sign extended, `((x ^ s) - s) & 0xffffffff`
+/// with `s` the sign bit.
+fn sign_extend(x: &str, bits: u32) -> String {
+    let sign = hex(1 << (bits - 1));
+    op(&op(&op(x, "^", &sign), "-", &sign), "&", "0xffffffff")
+}
+
+fn swap_halfword_bytes(x: &str) -> String {
+    op(
+        &op(&op(x, ">>", "8"), "&", "0xff00ff"),
+        "|",
+        &op(&op(x, "<<", "8"), "&", "0xff00ff00"),
+    )
+}
+
+fn logical(dn: &str, operator: &str, m: &str) -> String {
+    format!("{},{dn},=,{NZ}", op(dn, operator, m))
+}
+
+fn load(address: &str, size: u32, t: &str) -> String {
+    format!("{address},[{size}],{t},=")
+}
+
+fn store(t: &str, address: &str, size: u32) -> String {
+    format!("{t},{address},=[{size}]")
+}
+
+/// Shift by an immediate, 0 meaning 32 for right shifts, the carry set before the result
+/// overwrites `m`. `asr` by 32 shifts by 31, which gives the same result.
+fn shift_immediate(operator: &str, imm: u32, m: &str, d: &str) -> String {
+    let amount = match (operator, imm) {
+        ("<<", 0) => return format!("{m},{d},=,{NZ}"),
+        (_, 0) => 32,
+        _ => imm,
+    };
+    let carry_bit = match operator {
+        "<<" => 32 - amount,
+        _ => amount - 1,
+    };
+    let carry = op(&op(m, ">>", &format!("{carry_bit}")), "&", "1");
+    let shift = match operator {
+        ">>>>" => amount.min(31),
+        _ => amount,
+    };
+    let result = op(m, operator, &format!("{shift}"));
+    format!("{carry},cf,:=,{result},{d},=,{NZ}")
+}
+
+/// Shift by the bottom byte of `m`, the carry unchanged when it is 0.
+///
+/// The amount is clamped to keep every shift within 64 bits: `lsl` and `lsr` by 33 or more
+/// give 0 with the carry cleared, as by 33, and `asr` by 32 or more the sign.
+fn shift_register(operator: &str, m: &str, dn: &str) -> String {
+    let amount = op(m, "&", "0xff");
+    // `amount` if below `limit`, `limit - 1` otherwise.
+    let clamp = |limit: u32| {
+        let below = op(&amount, "<", &format!("{limit}"));
+        let at_limit = op(&format!("{}", limit - 1), "*", &format!("{below},!"));
+        op(&op(&amount, "*", &below), "+", &at_limit)
+    };
+    let (carry, result) = match operator {
+        // Bit `32 - amount` of `dn`, read as bit `33 - amount` of `dn << 1` to be 0 for 33.
+        "<<" => (
+            op(
+                &op(&op(dn, "<<", "1"), ">>", &op("33", "-", &clamp(34))),
+                "&",
+                "1",
+            ),
+            op(&op(dn, "<<", &clamp(34)), "&", "0xffffffff"),
+        ),
+        ">>" => (
+            op(&op(dn, ">>", &op(&clamp(34), "-", "1")), "&", "1"),
+            op(dn, ">>", &clamp(34)),
+        ),
+        _ => (
+            op(&op(dn, ">>", &op(&clamp(33), "-", "1")), "&", "1"),
+            op(dn, operator, &clamp(32)),
+        ),
+    };
+    format!("{amount},?{{,{carry},cf,:=,}},{result},{dn},=,{NZ}")
+}
+
+/// Returns the ESIL of `condition` over the flags, `None` for always.
+fn condition(condition: Condition) -> Option<&'static str> {
+    Some(match condition {
+        Condition::EQ => "zf",
+        Condition::NE => "zf,!",
+        Condition::CS => "cf",
+        Condition::CC => "cf,!",
+        Condition::MI => "nf",
+        Condition::PL => "nf,!",
+        Condition::VS => "vf",
+        Condition::VC => "vf,!",
+        Condition::HI => "zf,!,cf,&",
+        Condition::LS => "cf,!,zf,|",
+        Condition::GE => "vf,nf,^,!",
+        Condition::LT => "vf,nf,^",
+        Condition::GT => "zf,vf,nf,^,|,!",
+        Condition::LE => "zf,vf,nf,^,|",
+        Condition::None => return None,
+    })
+}
+
+fn name(register: Register) -> &'static str {
+    [
+        "r0", "r1", "r2", "r3", "r4", "r5", "r6", "r7", "r8", "r9", "r10", "r11", "r12", "sp",
+        "lr", "pc",
+    ][register as usize]
+}
+
+fn special(register: SpecialRegister) -> &'static str {
+    match register {
+        SpecialRegister::APSR => "apsr",
+        SpecialRegister::IAPSR => "iapsr",
+        SpecialRegister::EAPSR => "eapsr",
+        SpecialRegister::XPSR => "xpsr",
+        SpecialRegister::IPSR => "ipsr",
+        SpecialRegister::EPSR => "epsr",
+        SpecialRegister::IEPSR => "iepsr",
+        SpecialRegister::MSP => "msp",
+        SpecialRegister::PSP => "psp",
+        SpecialRegister::PRIMASK => "primask",
+        SpecialRegister::CONTROL => "control",
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        cpu::{Apsr, CpuState},
+        emulator::step,
+        instructons::{Instruction, InstructionWidth},
+        memory::Ram,
+        parse,
+    };
+
+    fn esil(bytes: &[u8], address: u32) -> String {
+        let instruction = parse(bytes).unwrap();
+        DecodedAt {
+            instruction,
+            address,
+        }
+        .esil()
+    }
+
+    #[test]
+    fn emission() {
+        // lsls r0, r1, #2
+        assert_eq!(
+            esil(&[0x88, 0x00], 0),
+            "1,30,r1,>>,&,cf,:=,2,r1,<<,r0,=,$z,zf,:=,31,$s,nf,:="
+        );
+        // ldmia r0!, {r0, r1} loads before assigning, without write back.
+        assert_eq!(esil(&[0x03, 0xc8], 0), "r0,[4],0x4,r0,+,[4],r1,=,r0,=");
+        // pop {r4, pc}
+        assert_eq!(
+            esil(&[0x10, 0xbd], 0),
+            "sp,[4],0x4,sp,+,[4],0x8,sp,+,sp,=,0xfffffffe,&,pc,=,r4,="
+        );
+        // beq at 0x08, ldr r0, [pc, #4] at 0x0a
+        assert_eq!(esil(&[0x02, 0xd0], 0x08), "zf,?{,0x10,pc,=,}");
+        assert_eq!(esil(&[0x01, 0x48], 0x0a), "0x10,[4],r0,=");
+        assert_eq!(esil(&[0x00, 0xbf], 0), "");
+    }
+
+    /// Evaluates `esil` over the low registers and flags of `state` on a stack of 64-bit
+    /// values, panicking on the shifts radare2 leaves undefined.
+    fn evaluate(esil: &str, state: &mut CpuState) {
+        let register = |token: &str| -> Option<Register> {
+            token.strip_prefix('r')?.parse::<u8>().ok()?.try_into().ok()
+        };
+        // The values, with the token read for each to assign to it.
+        let mut stack: Vec<(u64, &str)> = Vec::new();
+        let (mut last, mut skip) = (0u32, 0u32);
+        for token in esil.split(',') {
+            if skip > 0 {
+                skip = skip + (token == "?{") as u32 - (token == "}") as u32;
+                continue;
+            }
+            let mut pop = || stack.pop().unwrap();
+            let value = match token {
+                "zf" => state.apsr.z as u64,
+                "nf" => state.apsr.n as u64,
+                "cf" => state.apsr.c as u64,
+                "vf" => state.apsr.v as u64,
+                "$z" => (last == 0) as u64,
+                "$s" => (last >> pop().0 & 1) as u64,
+                "!" => (pop().0 == 0) as u64,
+                "}" => continue,
+                "?{" => {
+                    skip = (pop().0 == 0) as u32;
+                    continue;
+                }
+                "=" | ":=" => {
+                    let (destination, (value, _)) = (pop().1, pop());
+                    match destination {
+                        "zf" => state.apsr.z = value != 0,
+                        "nf" => state.apsr.n = value != 0,
+                        "cf" => state.apsr.c = value != 0,
+                        destination => {
+                            last = value as u32;
+                            state.set(register(destination).unwrap(), last);
+                        }
+                    }
+                    continue;
+                }
+                "+" | "-" | "*" | "&" | "<" | "<<" | ">>" | ">>>" | ">>>>" => {
+                    let (x, y) = (pop().0, pop().0);
+                    match token {
+                        "+" => x.wrapping_add(y),
+                        "-" => x.wrapping_sub(y),
+                        "*" => x.wrapping_mul(y),
+                        "&" => x & y,
+                        "<" => (x < y) as u64,
+                        "<<" => x.checked_shl(y.try_into().unwrap()).unwrap(),
+                        ">>" => x.checked_shr(y.try_into().unwrap()).unwrap(),
+                        ">>>" => (x as u32).rotate_right(y as u32 % 32) as u64,
+                        _ => {
+                            assert!(y < 32);
+                            (x as u32 as i32 >> y) as u32 as u64
+                        }
+                    }
+                }
+                token => match register(token) {
+                    Some(register) => state.get(register) as u64,
+                    None => match token.strip_prefix("0x") {
+                        Some(hex) => u64::from_str_radix(hex, 16).unwrap(),
+                        None => token.parse().unwrap_or_default(),
+                    },
+                },
+            };
+            stack.push((value, token));
+        }
+        assert!(stack.is_empty());
+    }
+
+    #[test]
+    fn shifts_match_emulator() {
+        let mut bytes = [0; 0x100];
+        let mut memory = Ram {
+            base: 0,
+            bytes: &mut bytes,
+        };
+        let operations = [
+            "lsrs r0, r0, #32",
+            "asrs r0, r0, #32",
+            "lsls r0, r0, #31",
+            "lsls r0, r0, #1",
+            "lsls r0, r1",
+            "lsrs r0, r1",
+            "asrs r0, r1",
+            "rors r0, r1",
+        ];
+        for text in operations {
+            let operation: Operation = text.parse().unwrap();
+            let decoded = DecodedAt {
+                instruction: Instruction {
+                    width: InstructionWidth::Bit16,
+                    operation,
+                },
+                address: 0x40,
+            };
+            let esil = decoded.esil();
+            // The value shifted and the amount of the register shifts.
+            for (r0, r1) in [0x8000_0001, 0x4000_0000, 0xffff_ffff]
+                .into_iter()
+                .flat_map(|r0| [0, 1, 31, 32, 33, 40, 0xff, 0x100, 0x121].map(|r1| (r0, r1)))
+            {
+                for carry in [false, true] {
+                    let mut expected = CpuState::new(0x100, 0);
+                    expected.apsr = Apsr::from_bits((carry as u32) << 29);
+                    expected.set(Register::R0, r0);
+                    expected.set(Register::R1, r1);
+                    let mut actual = expected.clone();
+                    expected.set(Register::PC, 0x40);
+                    step(&mut expected, &mut memory, &decoded.instruction).unwrap();
+                    evaluate(&esil, &mut actual);
+                    let state = |state: &CpuState| {
+                        let apsr = &state.apsr;
+                        (state.get(Register::R0), apsr.n, apsr.z, apsr.c)
+                    };
+                    assert_eq!(state(&actual), state(&expected), "{text}, {r0:#x}, {r1:#x}");
+                }
+            }
+        }
+    }
+}
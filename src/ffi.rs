@@ -0,0 +1,610 @@
+//! C interface to decoding and formatting, for debuggers and RTOS tools written in C or C++.
+//!
+//! Build a static library with `cargo rustc --release --features ffi --crate-type staticlib`
+//! and include `include/armv6m.h`, generated from this module with `cbindgen`. The
+//! functions do not allocate, so a `no_std` static library of the firmware can export them
+//! by depending on the crate with `default-features = false`.
+//!
+//! ```c
+//! Armv6mInstruction instruction;
+//! char text[64];
+//! if (armv6m_decode(bytes, len, 0x1000, &instruction) == ARMV6M_STATUS_OK) {
+//!     armv6m_format(&instruction, text, sizeof text);
+//! }
+//! ```
+//!
+//! # Example
+//! ```
+//! # use armv6_m_instruction_parser::ffi::*;
+//! # use core::mem::MaybeUninit;
+//! let mut instruction = MaybeUninit::uninit();
+//! let status = unsafe { armv6m_decode([0x70, 0x47].as_ptr(), 2, 0x1000, instruction.as_mut_ptr()) };
+//! assert_eq!(status, Armv6mStatus::Ok);
+//! let instruction = unsafe { instruction.assume_init() };
+//! assert_eq!(instruction.operation, Armv6mOperation::BX);
+//! assert_eq!(instruction.m, 14);
+//! ```
+
+use core::{
+    ffi::{c_char, c_int, CStr},
+    fmt::{self, Write},
+    ptr, slice,
+};
+
+use crate::{
+    conditions::Condition,
+    instructons::{DecodedAt, Operation},
+    parse, Error,
+};
+
+/// Register field of an [`Armv6mInstruction`] the operation does not have.
+pub const ARMV6M_NO_REGISTER: u8 = 0xff;
+
+/// Result of the functions of this module, mirroring [`Error`].
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Armv6mStatus {
+    Ok = 0,
+    /// A pointer argument is null.
+    NullPointer,
+    InsufficientInput,
+    Malformed32BitInstruction,
+    Invalid32BitInstruction,
+    InvalidOpCode,
+    Unpredictable,
+    InvalidRegister,
+    InvalidCondition,
+    /// Any other [`Error`], which decoding does not return.
+    Other,
+}
+
+impl From<Error> for Armv6mStatus {
+    fn from(error: Error) -> Self {
+        match error {
+            Error::InsufficientInput => Armv6mStatus::InsufficientInput,
+            Error::Malfromed32BitInstruction => Armv6mStatus::Malformed32BitInstruction,
+            Error::Invalid32BitInstruction => Armv6mStatus::Invalid32BitInstruction,
+            Error::InvalidOpCode => Armv6mStatus::InvalidOpCode,
+            Error::Unpredictable => Armv6mStatus::Unpredictable,
+            Error::InvalidRegister => Armv6mStatus::InvalidRegister,
+            Error::InvalidCondition => Armv6mStatus::InvalidCondition,
+            _ => Armv6mStatus::Other,
+        }
+    }
+}
+
+/// Kind of an [`Armv6mInstruction`], the variants of [`Operation`].
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Armv6mOperation {
+    ADCReg,
+    ADDImm,
+    ADDReg,
+    ADDImmSP,
+    ADDRegSP,
+    ADR,
+    ANDReg,
+    ASRImm,
+    ASRReg,
+    B,
+    BICReg,
+    BKPT,
+    BL,
+    BLXReg,
+    BX,
+    CMNReg,
+    CMPImm,
+    CMPReg,
+    CPS,
+    CPY,
+    DMB,
+    DSB,
+    EORReg,
+    ISB,
+    LDM,
+    LDRImm,
+    LDRLiteral,
+    LDRReg,
+    LDRBImm,
+    LDRBReg,
+    LDRHImm,
+    LDRHReg,
+    LDRSBReg,
+    LDRSH,
+    LSLImm,
+    LSLReg,
+    LSRImm,
+    LSRReg,
+    MOVImm,
+    MOVReg,
+    MRS,
+    MSRReg,
+    MUL,
+    MVNReg,
+    NOP,
+    ORRReg,
+    POP,
+    PUSH,
+    REV,
+    REV16,
+    REVSH,
+    RORReg,
+    RSBImm,
+    SBCReg,
+    SEV,
+    STM,
+    STRImm,
+    STRReg,
+    STRBImm,
+    STRBReg,
+    STRHImm,
+    STRHReg,
+    SUBImm,
+    SUBReg,
+    SUBImmSP,
+    SVC,
+    SXTB,
+    SXTH,
+    TSTReg,
+    UDF,
+    UXTB,
+    UXTH,
+    WFE,
+    WFI,
+    YIELD,
+}
+
+impl From<&Operation> for Armv6mOperation {
+    fn from(operation: &Operation) -> Self {
+        match operation {
+            Operation::ADCReg { .. } => Armv6mOperation::ADCReg,
+            Operation::ADDImm { .. } => Armv6mOperation::ADDImm,
+            Operation::ADDReg { .. } => Armv6mOperation::ADDReg,
+            Operation::ADDImmSP { .. } => Armv6mOperation::ADDImmSP,
+            Operation::ADDRegSP { .. } => Armv6mOperation::ADDRegSP,
+            Operation::ADR { .. } => Armv6mOperation::ADR,
+            Operation::ANDReg { .. } => Armv6mOperation::ANDReg,
+            Operation::ASRImm { .. } => Armv6mOperation::ASRImm,
+            Operation::ASRReg { .. } => Armv6mOperation::ASRReg,
+            Operation::B { .. } => Armv6mOperation::B,
+            Operation::BICReg { .. } => Armv6mOperation::BICReg,
+            Operation::BKPT { .. } => Armv6mOperation::BKPT,
+            Operation::BL { .. } => Armv6mOperation::BL,
+            Operation::BLXReg { .. } => Armv6mOperation::BLXReg,
+            Operation::BX { .. } => Armv6mOperation::BX,
+            Operation::CMNReg { .. } => Armv6mOperation::CMNReg,
+            Operation::CMPImm { .. } => Armv6mOperation::CMPImm,
+            Operation::CMPReg { .. } => Armv6mOperation::CMPReg,
+            Operation::CPS { .. } => Armv6mOperation::CPS,
+            Operation::CPY => Armv6mOperation::CPY,
+            Operation::DMB { .. } => Armv6mOperation::DMB,
+            Operation::DSB { .. } => Armv6mOperation::DSB,
+            Operation::EORReg { .. } => Armv6mOperation::EORReg,
+            Operation::ISB { .. } => Armv6mOperation::ISB,
+            Operation::LDM { .. } => Armv6mOperation::LDM,
+            Operation::LDRImm { .. } => Armv6mOperation::LDRImm,
+            Operation::LDRLiteral { .. } => Armv6mOperation::LDRLiteral,
+            Operation::LDRReg { .. } => Armv6mOperation::LDRReg,
+            Operation::LDRBImm { .. } => Armv6mOperation::LDRBImm,
+            Operation::LDRBReg { .. } => Armv6mOperation::LDRBReg,
+            Operation::LDRHImm { .. } => Armv6mOperation::LDRHImm,
+            Operation::LDRHReg { .. } => Armv6mOperation::LDRHReg,
+            Operation::LDRSBReg { .. } => Armv6mOperation::LDRSBReg,
+            Operation::LDRSH { .. } => Armv6mOperation::LDRSH,
+            Operation::LSLImm { .. } => Armv6mOperation::LSLImm,
+            Operation::LSLReg { .. } => Armv6mOperation::LSLReg,
+            Operation::LSRImm { .. } => Armv6mOperation::LSRImm,
+            Operation::LSRReg { .. } => Armv6mOperation::LSRReg,
+            Operation::MOVImm { .. } => Armv6mOperation::MOVImm,
+            Operation::MOVReg { .. } => Armv6mOperation::MOVReg,
+            Operation::MRS { .. } => Armv6mOperation::MRS,
+            Operation::MSRReg { .. } => Armv6mOperation::MSRReg,
+            Operation::MUL { .. } => Armv6mOperation::MUL,
+            Operation::MVNReg { .. } => Armv6mOperation::MVNReg,
+            Operation::NOP => Armv6mOperation::NOP,
+            Operation::ORRReg { .. } => Armv6mOperation::ORRReg,
+            Operation::POP { .. } => Armv6mOperation::POP,
+            Operation::PUSH { .. } => Armv6mOperation::PUSH,
+            Operation::REV { .. } => Armv6mOperation::REV,
+            Operation::REV16 { .. } => Armv6mOperation::REV16,
+            Operation::REVSH { .. } => Armv6mOperation::REVSH,
+            Operation::RORReg { .. } => Armv6mOperation::RORReg,
+            Operation::RSBImm { .. } => Armv6mOperation::RSBImm,
+            Operation::SBCReg { .. } => Armv6mOperation::SBCReg,
+            Operation::SEV => Armv6mOperation::SEV,
+            Operation::STM { .. } => Armv6mOperation::STM,
+            Operation::STRImm { .. } => Armv6mOperation::STRImm,
+            Operation::STRReg { .. } => Armv6mOperation::STRReg,
+            Operation::STRBImm { .. } => Armv6mOperation::STRBImm,
+            Operation::STRBReg { .. } => Armv6mOperation::STRBReg,
+            Operation::STRHImm { .. } => Armv6mOperation::STRHImm,
+            Operation::STRHReg { .. } => Armv6mOperation::STRHReg,
+            Operation::SUBImm { .. } => Armv6mOperation::SUBImm,
+            Operation::SUBReg { .. } => Armv6mOperation::SUBReg,
+            Operation::SUBImmSP { .. } => Armv6mOperation::SUBImmSP,
+            Operation::SVC { .. } => Armv6mOperation::SVC,
+            Operation::SXTB { .. } => Armv6mOperation::SXTB,
+            Operation::SXTH { .. } => Armv6mOperation::SXTH,
+            Operation::TSTReg { .. } => Armv6mOperation::TSTReg,
+            Operation::UDF { .. } => Armv6mOperation::UDF,
+            Operation::UXTB { .. } => Armv6mOperation::UXTB,
+            Operation::UXTH { .. } => Armv6mOperation::UXTH,
+            Operation::WFE => Armv6mOperation::WFE,
+            Operation::WFI => Armv6mOperation::WFI,
+            Operation::YIELD => Armv6mOperation::YIELD,
+        }
+    }
+}
+
+/// Decoded instruction, [`DecodedAt`] with the operands of its [`Operation`] flattened.
+///
+/// Registers are numbered 0 to 15, with [`ARMV6M_NO_REGISTER`] for those the operation does
+/// not have. Operands named `dn` and `dm` fill both fields.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Armv6mInstruction {
+    pub address: u32,
+    /// Size in bytes, 2 or 4.
+    pub size: u8,
+    /// Bytes of the instruction, the last two unused for 16 bit instructions.
+    pub bytes: [u8; 4],
+    pub operation: Armv6mOperation,
+    pub d: u8,
+    pub n: u8,
+    pub m: u8,
+    pub t: u8,
+    /// Immediate, the option of barriers and 1 for `cpsid`. Shift amounts are 1 to 32,
+    /// `lsr` and `asr` by 32 not encoded as 0.
+    pub imm: u32,
+    /// Bit n set for register n, for `push`, `pop`, `ldm` and `stm`.
+    pub reg_list: u16,
+    /// Condition of `b`, 0 (`eq`) to 13 (`le`) or 14 for none.
+    pub cond: u8,
+    /// Special register of `mrs` and `msr`, as encoded in SYSm.
+    pub sysm: u8,
+    /// Whether `mov` and `add` of registers set the condition flags.
+    pub set_flags: bool,
+    /// Registers read, see [`Operation::registers_read`].
+    pub registers_read: u16,
+    /// Registers written, see [`Operation::registers_written`].
+    pub registers_written: u16,
+}
+
+impl From<&DecodedAt> for Armv6mInstruction {
+    fn from(decoded: &DecodedAt) -> Self {
+        let operation = &decoded.instruction.operation;
+        let mut instruction = Armv6mInstruction {
+            address: decoded.address,
+            size: decoded.size() as u8,
+            bytes: [0; 4],
+            operation: operation.into(),
+            d: ARMV6M_NO_REGISTER,
+            n: ARMV6M_NO_REGISTER,
+            m: ARMV6M_NO_REGISTER,
+            t: ARMV6M_NO_REGISTER,
+            imm: 0,
+            reg_list: 0,
+            cond: Condition::None as u8,
+            sysm: 0,
+            set_flags: false,
+            registers_read: operation.registers_read().bits(),
+            registers_written: operation.registers_written().bits(),
+        };
+        let i = &mut instruction;
+        use Operation as O;
+        match *operation {
+            O::ADCReg { m, n, d } | O::SUBReg { m, n, d } => {
+                (i.m, i.n, i.d) = (m as u8, n as u8, d as u8);
+            }
+            O::ADDReg { m, n, d, set_flags } => {
+                (i.m, i.n, i.d, i.set_flags) = (m as u8, n as u8, d as u8, set_flags)
+            }
+            O::ADDImm { imm, n, d } | O::SUBImm { imm, n, d } => {
+                (i.imm, i.n, i.d) = (imm, n as u8, d as u8);
+            }
+            O::ADDImmSP { d, imm } | O::ADR { d, imm } | O::MOVImm { d, imm } => {
+                (i.d, i.imm) = (d as u8, imm);
+            }
+            O::ADDRegSP { d, m }
+            | O::MVNReg { m, d }
+            | O::REV { m, d }
+            | O::REV16 { m, d }
+            | O::REVSH { m, d }
+            | O::SXTB { m, d }
+            | O::SXTH { m, d }
+            | O::UXTB { m, d }
+            | O::UXTH { m, d } => (i.d, i.m) = (d as u8, m as u8),
+            O::ANDReg { m, dn }
+            | O::ASRReg { m, dn }
+            | O::BICReg { m, dn }
+            | O::EORReg { m, dn }
+            | O::LSLReg { m, dn }
+            | O::LSRReg { m, dn }
+            | O::ORRReg { m, dn }
+            | O::RORReg { m, dn }
+            | O::SBCReg { m, dn } => (i.d, i.n, i.m) = (dn as u8, dn as u8, m as u8),
+            O::ASRImm { imm, m, d } | O::LSRImm { imm, m, d } => {
+                let imm = match imm {
+                    0 => 32,
+                    imm => imm,
+                };
+                (i.imm, i.m, i.d) = (imm, m as u8, d as u8);
+            }
+            O::LSLImm { imm, m, d } => (i.imm, i.m, i.d) = (imm, m as u8, d as u8),
+            O::B { cond, imm } => (i.cond, i.imm) = (cond as u8, imm),
+            O::BKPT { imm }
+            | O::BL { imm }
+            | O::SUBImmSP { imm }
+            | O::SVC { imm }
+            | O::UDF { imm } => i.imm = imm,
+            O::BLXReg { m } | O::BX { m } => i.m = m as u8,
+            O::CMNReg { m, n } | O::CMPReg { m, n } | O::TSTReg { m, n } => {
+                (i.m, i.n) = (m as u8, n as u8);
+            }
+            O::CMPImm { n, imm } => (i.n, i.imm) = (n as u8, imm),
+            O::CPS { im } => i.imm = im as u32,
+            O::DMB { option } | O::DSB { option } | O::ISB { option } => i.imm = option as u32,
+            O::LDM { n, reg_list } | O::STM { n, reg_list } => {
+                (i.n, i.reg_list) = (n as u8, reg_list.bits());
+            }
+            O::POP { reg_list } | O::PUSH { reg_list } => i.reg_list = reg_list.bits(),
+            O::LDRImm { imm, n, t }
+            | O::LDRBImm { imm, n, t }
+            | O::LDRHImm { imm, n, t }
+            | O::STRImm { imm, n, t }
+            | O::STRBImm { imm, n, t }
+            | O::STRHImm { imm, n, t } => (i.imm, i.n, i.t) = (imm, n as u8, t as u8),
+            O::LDRLiteral { t, imm } => (i.t, i.imm) = (t as u8, imm),
+            O::LDRReg { m, n, t }
+            | O::LDRBReg { m, n, t }
+            | O::LDRHReg { m, n, t }
+            | O::LDRSBReg { m, n, t }
+            | O::LDRSH { m, n, t }
+            | O::STRReg { m, n, t }
+            | O::STRBReg { m, n, t }
+            | O::STRHReg { m, n, t } => (i.m, i.n, i.t) = (m as u8, n as u8, t as u8),
+            O::MOVReg { m, d, set_flags } => {
+                (i.m, i.d, i.set_flags) = (m as u8, d as u8, set_flags)
+            }
+            O::MRS { d, sysm } => (i.d, i.sysm) = (d as u8, sysm as u8),
+            O::MSRReg { n, sysm } => (i.n, i.sysm) = (n as u8, sysm as u8),
+            O::MUL { n, dm } => (i.n, i.d, i.m) = (n as u8, dm as u8, dm as u8),
+            O::RSBImm { n, d } => (i.n, i.d) = (n as u8, d as u8),
+            O::CPY | O::NOP | O::SEV | O::WFE | O::WFI | O::YIELD => {}
+        }
+        instruction
+    }
+}
+
+/// Decodes the instruction at the start of the `len` bytes at `bytes`, placed at `address`,
+/// into `out`, which is left untouched unless [`Armv6mStatus::Ok`] is returned.
+///
+/// # Safety
+/// `bytes` must point to `len` readable bytes and `out` to a writable
+/// [`Armv6mInstruction`].
+#[no_mangle]
+pub unsafe extern "C" fn armv6m_decode(
+    bytes: *const u8,
+    len: usize,
+    address: u32,
+    out: *mut Armv6mInstruction,
+) -> Armv6mStatus {
+    if bytes.is_null() || out.is_null() {
+        return Armv6mStatus::NullPointer;
+    }
+    let input = slice::from_raw_parts(bytes, len);
+    let instruction = match parse(input) {
+        Ok(instruction) => instruction,
+        Err(error) => return error.into(),
+    };
+    let size = instruction.size() as usize;
+    let mut decoded = Armv6mInstruction::from(&DecodedAt {
+        instruction,
+        address,
+    });
+    decoded.bytes[..size].copy_from_slice(&input[..size]);
+    out.write(decoded);
+    Armv6mStatus::Ok
+}
+
+/// Writes the assembly text of `instruction`, with branch targets as addresses, to the
+/// `size` bytes at `buffer` like `snprintf`: truncated to `size - 1` bytes and terminated
+/// by a null byte if `size` is not 0.
+///
+/// Returns the length of the whole text without the null byte, or 0 if `instruction` is
+/// null or was not returned by [`armv6m_decode`]. Only the address, size, bytes and
+/// operation are read, the operation as an integer checked against the bytes.
+///
+/// # Safety
+/// `instruction` must point to an [`Armv6mInstruction`] and `buffer` to `size` writable
+/// bytes.
+#[no_mangle]
+pub unsafe extern "C" fn armv6m_format(
+    instruction: *const Armv6mInstruction,
+    buffer: *mut c_char,
+    size: usize,
+) -> usize {
+    if instruction.is_null() {
+        return 0;
+    }
+    // Read without a reference to the instruction, whose enum and bool fields C can set to
+    // values not valid in Rust.
+    let length = ptr::addr_of!((*instruction).size).read();
+    let bytes = ptr::addr_of!((*instruction).bytes).read();
+    let operation = ptr::addr_of!((*instruction).operation)
+        .cast::<c_int>()
+        .read();
+    let Some(bytes) = bytes.get(..length as usize) else {
+        return 0;
+    };
+    let Ok(decoded) = parse(bytes) else {
+        return 0;
+    };
+    if Armv6mOperation::from(&decoded.operation) as c_int != operation {
+        return 0;
+    }
+    let decoded = DecodedAt {
+        instruction: decoded,
+        address: ptr::addr_of!((*instruction).address).read(),
+    };
+    let buffer = match buffer.is_null() {
+        true => &mut [][..],
+        false => slice::from_raw_parts_mut(buffer.cast::<u8>(), size),
+    };
+    let mut writer = Truncating { buffer, len: 0 };
+    // Writing never fails, text beyond the buffer is counted only.
+    let _ = write!(writer, "{decoded}");
+    let Truncating { buffer, len } = writer;
+    if let Some(last) = buffer.len().checked_sub(1) {
+        buffer[len.min(last)] = 0;
+    }
+    len
+}
+
+/// Returns a static, null terminated description of the [`Armv6mStatus`] `status`, taken as
+/// an integer since C can pass any.
+#[no_mangle]
+pub extern "C" fn armv6m_status_message(status: c_int) -> *const c_char {
+    use Armv6mStatus as S;
+
+    let message: &CStr = match status {
+        _ if status == S::Ok as c_int => c"success",
+        _ if status == S::NullPointer as c_int => c"null pointer",
+        _ if status == S::InsufficientInput as c_int => c"input not long enough for an instruction",
+        _ if status == S::Malformed32BitInstruction as c_int => {
+            c"32 bit instruction not long enough"
+        }
+        _ if status == S::Invalid32BitInstruction as c_int => {
+            c"opcode not matching a valid 32 bit instruction"
+        }
+        _ if status == S::InvalidOpCode as c_int => c"invalid opcode",
+        _ if status == S::Unpredictable as c_int => c"instruction has unpredictable behaviour",
+        _ if status == S::InvalidRegister as c_int => c"invalid register",
+        _ if status == S::InvalidCondition as c_int => c"invalid condition code",
+        _ if status == S::Other as c_int => c"error",
+        _ => c"unknown status",
+    };
+    message.as_ptr()
+}
+
+/// Writer filling a buffer, leaving room for a null byte, and counting all bytes written.
+struct Truncating<'a> {
+    buffer: &'a mut [u8],
+    len: usize,
+}
+
+impl Write for Truncating<'_> {
+    fn write_str(&mut self, text: &str) -> fmt::Result {
+        let room = self.buffer.len().saturating_sub(1);
+        if let Some(free) = room.checked_sub(self.len) {
+            let copied = free.min(text.len());
+            self.buffer[self.len..self.len + copied].copy_from_slice(&text.as_bytes()[..copied]);
+        }
+        self.len += text.len();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn decode_and_format() {
+        // bl 0x1100
+        let bytes = [0x00, 0xf0, 0x7e, 0xf8, 0x00];
+        let mut instruction = core::mem::MaybeUninit::uninit();
+        let status = unsafe {
+            armv6m_decode(
+                bytes.as_ptr(),
+                bytes.len(),
+                0x1000,
+                instruction.as_mut_ptr(),
+            )
+        };
+        assert_eq!(status, Armv6mStatus::Ok);
+        let instruction = unsafe { instruction.assume_init() };
+        assert_eq!(instruction.operation, Armv6mOperation::BL);
+        assert_eq!(
+            (instruction.size, instruction.bytes),
+            (4, [0x00, 0xf0, 0x7e, 0xf8])
+        );
+        assert_eq!(instruction.registers_written, 1 << 14 | 1 << 15);
+
+        let mut text = [0x55 as c_char; 8];
+        let len = unsafe { armv6m_format(&instruction, text.as_mut_ptr(), text.len()) };
+        assert_eq!(len, "bl 0x1100".len());
+        let text = unsafe { CStr::from_ptr(text.as_ptr()) };
+        assert_eq!(text, c"bl 0x11");
+        assert_eq!(
+            unsafe { armv6m_format(&instruction, core::ptr::null_mut(), 0) },
+            9
+        );
+
+        let status = unsafe { armv6m_decode(bytes.as_ptr(), 1, 0, core::ptr::null_mut()) };
+        assert_eq!(status, Armv6mStatus::NullPointer);
+        let message = unsafe { CStr::from_ptr(armv6m_status_message(status as c_int)) };
+        assert_eq!(message, c"null pointer");
+        let message = unsafe { CStr::from_ptr(armv6m_status_message(99)) };
+        assert_eq!(message, c"unknown status");
+
+        // An operation not valid in Rust, or not the one of the bytes, as C can write.
+        for operation in [1000, Armv6mOperation::BX as c_int] {
+            let mut tampered = core::mem::MaybeUninit::new(instruction);
+            unsafe {
+                ptr::addr_of_mut!((*tampered.as_mut_ptr()).operation)
+                    .cast::<c_int>()
+                    .write(operation);
+                assert_eq!(
+                    armv6m_format(tampered.as_ptr(), core::ptr::null_mut(), 0),
+                    0
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn fields() {
+        use Armv6mOperation as O;
+
+        /// Operation, d, n, m, imm, reg_list, cond and set_flags.
+        type Fields = (O, u8, u8, u8, u32, u16, u8, bool);
+        const NO: u8 = ARMV6M_NO_REGISTER;
+        let cases: [(&[u8], Fields); 7] = [
+            // lsrs r0, r1, #32
+            (&[0x08, 0x08], (O::LSRImm, 0, NO, 1, 32, 0, 14, false)),
+            // asrs r2, r3, #1
+            (&[0x5a, 0x10], (O::ASRImm, 2, NO, 3, 1, 0, 14, false)),
+            // ldm r0, {r0, r1}
+            (&[0x03, 0xc8], (O::LDM, NO, 0, NO, 0, 0b11, 14, false)),
+            // muls r1, r2, r1
+            (&[0x51, 0x43], (O::MUL, 1, 2, 1, 0, 0, 14, false)),
+            // beq 0x108 at 0x100
+            (&[0x02, 0xd0], (O::B, NO, NO, NO, 4, 0, 0, false)),
+            // mov pc, lr
+            (&[0xf7, 0x46], (O::MOVReg, 15, NO, 14, 0, 0, 14, false)),
+            // add pc, r1
+            (&[0x8f, 0x44], (O::ADDReg, 15, 15, 1, 0, 0, 14, false)),
+        ];
+        for (bytes, expected) in cases {
+            let mut instruction = core::mem::MaybeUninit::uninit();
+            let status = unsafe {
+                armv6m_decode(bytes.as_ptr(), bytes.len(), 0x100, instruction.as_mut_ptr())
+            };
+            assert_eq!(status, Armv6mStatus::Ok);
+            let i = unsafe { instruction.assume_init() };
+            let fields = (
+                i.operation,
+                i.d,
+                i.n,
+                i.m,
+                i.imm,
+                i.reg_list,
+                i.cond,
+                i.set_flags,
+            );
+            assert_eq!(fields, expected, "{bytes:x?}");
+            assert_eq!(i.t, NO);
+        }
+    }
+}
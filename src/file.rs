@@ -0,0 +1,73 @@
+//! Disassembly of files through memory mapping, so files larger than memory do not need to
+//! be read in chunks.
+
+use std::{fs::File, io, ops::Deref, path::Path};
+
+use memmap2::Mmap;
+
+use crate::{AddressedDisassembly, ThumbInstructions};
+
+/// A file mapped into memory, holding an image placed at `base`.
+#[derive(Debug)]
+pub struct MappedImage {
+    map: Mmap,
+    base: u32,
+}
+
+impl MappedImage {
+    /// Returns the instructions of the image with their addresses.
+    pub fn instructions(&self) -> AddressedDisassembly<'_> {
+        self.map.thumb_instructions_at(self.base)
+    }
+
+    /// Address of the start of the image.
+    pub fn base(&self) -> u32 {
+        self.base
+    }
+}
+
+impl Deref for MappedImage {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.map
+    }
+}
+
+/// Maps the file at `path` into memory for disassembly, with the file placed at `base`.
+///
+/// The file must not be modified while it is mapped, as the mapping would change under the
+/// disassembly.
+///
+/// # Example
+/// ```no_run
+/// # use armv6_m_instruction_parser::file::disassemble_file;
+/// let image = disassemble_file("firmware.bin", 0x0800_0000).unwrap();
+/// for (address, instruction) in image.instructions() {
+///     println!("{address:#010x}: {instruction:?}");
+/// }
+/// ```
+pub fn disassemble_file(path: impl AsRef<Path>, base: u32) -> io::Result<MappedImage> {
+    let file = File::open(path)?;
+    // Safety: the file is only read through the mapping, modifying it while mapped is
+    // documented as not allowed.
+    let map = unsafe { Mmap::map(&file)? };
+    Ok(MappedImage { map, base })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn mapped_file() {
+        let path = std::env::temp_dir().join(format!("mapped-{}.bin", std::process::id()));
+        std::fs::write(&path, [0x01, 0x20, 0x00, 0xf0, 0x7e, 0xf8, 0x70, 0x47]).unwrap();
+        let image = disassemble_file(&path, 0x1000).unwrap();
+        let addresses: Vec<u32> = image.instructions().map(|(address, _)| address).collect();
+        assert_eq!(addresses, [0x1000, 0x1002, 0x1006]);
+        assert_eq!(image.len(), 8);
+        drop(image);
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,191 @@
+//! Fingerprints of functions independent of where they are placed and which registers they
+//! were allocated, for matching functions between versions of a firmware.
+//!
+//! # Example
+//! ```
+//! # use armv6_m_instruction_parser::{fingerprint::fingerprint, functions::FunctionRange, program::Program};
+//! // adds r0, r0, r1; bx lr
+//! let old = Program::new(&[0x40, 0x18, 0x70, 0x47], 0x100);
+//! // adds r2, r2, r3; bx lr
+//! let new = Program::new(&[0xd2, 0x18, 0x70, 0x47], 0x200);
+//! let function = |start| FunctionRange { start, end: start + 4, called: false, saves_lr: false };
+//! assert_eq!(fingerprint(&old, &function(0x100)), fingerprint(&new, &function(0x200)));
+//! ```
+
+use alloc::{
+    collections::BTreeMap,
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+
+use crate::{
+    functions::FunctionRange,
+    instructons::{BranchTarget, Operation},
+    program::Program,
+};
+
+/// Hash of the normalized instructions of a function, see [`fingerprint`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Fingerprint(pub u64);
+
+/// Returns the fingerprint of `function` in `program`.
+///
+/// The operations are hashed with `r0` to `r12` renamed in order of first use, `sp`, `lr`
+/// and `pc` kept, branches within the function made relative to its start and the addresses
+/// of calls, branches out of the function, literal loads and `adr` left out.
+/// Fingerprints are stable for a version of this crate, not between versions.
+pub fn fingerprint(program: &Program, function: &FunctionRange) -> Fingerprint {
+    let mut names: BTreeMap<String, usize> = BTreeMap::new();
+    let mut hash = Fnv::new();
+    for (address, result) in program.range(function.start..function.end) {
+        let Ok(instruction) = result else {
+            hash.write("error;");
+            continue;
+        };
+        let operation = normalize(&instruction.operation, address, function);
+        let text = format!("{operation:?};");
+        for token in tokens(&text) {
+            let is_register = token.strip_prefix('R').is_some_and(|number| {
+                !number.is_empty() && number.bytes().all(|b| b.is_ascii_digit())
+            });
+            if is_register {
+                let count = names.len();
+                let name = *names.entry(token.to_string()).or_insert(count);
+                hash.write(&format!("r{name}"));
+            } else {
+                hash.write(token);
+            }
+        }
+    }
+    Fingerprint(hash.0)
+}
+
+/// Returns the fingerprints of `functions` in `program`, by start address.
+pub fn fingerprints(program: &Program, functions: &[FunctionRange]) -> Vec<(u32, Fingerprint)> {
+    functions
+        .iter()
+        .map(|function| (function.start, fingerprint(program, function)))
+        .collect()
+}
+
+/// Pairs the functions of `old` and `new` with the same fingerprint, returning the start
+/// addresses in the old and new version, in order of the old addresses.
+///
+/// Only fingerprints occurring once in both versions are paired, as identical functions,
+/// e.g. empty handlers, cannot be told apart.
+pub fn match_functions(old: &[(u32, Fingerprint)], new: &[(u32, Fingerprint)]) -> Vec<(u32, u32)> {
+    let unique = |functions: &[(u32, Fingerprint)]| {
+        let mut by_fingerprint: BTreeMap<Fingerprint, Option<u32>> = BTreeMap::new();
+        for (start, fingerprint) in functions {
+            by_fingerprint
+                .entry(*fingerprint)
+                .and_modify(|start| *start = None)
+                .or_insert(Some(*start));
+        }
+        by_fingerprint
+    };
+    let new = unique(new);
+    let mut pairs: Vec<(u32, u32)> = unique(old)
+        .into_iter()
+        .filter_map(|(fingerprint, old)| Some((old?, (*new.get(&fingerprint)?)?)))
+        .collect();
+    pairs.sort_unstable();
+    pairs
+}
+
+/// Replaces the addresses in `operation` at `address` that change when code moves.
+fn normalize(operation: &Operation, address: u32, function: &FunctionRange) -> Operation {
+    let mut operation = operation.clone();
+    let target = operation.branch_target(address);
+    match &mut operation {
+        Operation::B { imm, .. } => {
+            *imm = match target {
+                BranchTarget::Direct(target)
+                    if (function.start..function.end).contains(&target) =>
+                {
+                    target - function.start
+                }
+                _ => u32::MAX,
+            };
+        }
+        Operation::BL { imm } | Operation::LDRLiteral { imm, .. } | Operation::ADR { imm, .. } => {
+            *imm = 0
+        }
+        _ => {}
+    }
+    operation
+}
+
+/// Splits `text` into identifiers and numbers, and the characters between them.
+fn tokens(text: &str) -> impl Iterator<Item = &str> {
+    let mut rest = text;
+    core::iter::from_fn(move || {
+        let first = rest.chars().next()?;
+        let length = if first.is_ascii_alphanumeric() {
+            rest.find(|c: char| !c.is_ascii_alphanumeric())
+                .unwrap_or(rest.len())
+        } else {
+            first.len_utf8()
+        };
+        let (token, remaining) = rest.split_at(length);
+        rest = remaining;
+        Some(token)
+    })
+}
+
+/// 64 bit FNV-1a hash, the same on every platform.
+struct Fnv(u64);
+
+impl Fnv {
+    fn new() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+
+    fn write(&mut self, text: &str) {
+        for byte in text.bytes() {
+            self.0 ^= byte as u64;
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn function(start: u32, end: u32) -> FunctionRange {
+        FunctionRange {
+            start,
+            end,
+            called: false,
+            saves_lr: false,
+        }
+    }
+
+    #[test]
+    fn matching() {
+        // 0x1000: push {r4, lr}; bl 0x1040; cmp r0, #0; beq.n 0x100c; movs r4, #1
+        // 0x100c: pop {r4, pc}
+        let old = [
+            0x10, 0xb5, 0x00, 0xf0, 0x1d, 0xf8, 0x00, 0x28, 0x00, 0xd0, 0x01, 0x24, 0x10, 0xbd,
+        ];
+        // The same function calling 0x2080 with r5 instead of r4, and a different one.
+        let new = [
+            0x20, 0xb5, 0x00, 0xf0, 0x3d, 0xf8, 0x00, 0x28, 0x00, 0xd0, 0x01, 0x25, 0x20, 0xbd,
+            0x00, 0x20, 0x70, 0x47,
+        ];
+        let old_program = Program::new(&old, 0x1000);
+        let new_program = Program::new(&new, 0x2000);
+        let old = fingerprints(&old_program, &[function(0x1000, 0x100e)]);
+        let new = fingerprints(
+            &new_program,
+            &[function(0x2000, 0x200e), function(0x200e, 0x2012)],
+        );
+        assert_eq!(old[0].1, new[0].1);
+        assert_ne!(new[0].1, new[1].1);
+        assert_eq!(match_functions(&old, &new), [(0x1000, 0x2000)]);
+        // Duplicates are not paired.
+        assert!(match_functions(&old, &[new[0], (0x3000, new[0].1)]).is_empty());
+    }
+}
@@ -0,0 +1,228 @@
+//! Formatting of instructions as assembly text in the syntax of GNU objdump, which the
+//! [assembler](crate::assembler) parses back.
+//!
+//! Operations format branch targets as offsets, [`DecodedAt`] as addresses.
+//!
+//! # Example
+//! ```
+//! # use armv6_m_instruction_parser::{parse, parse_at};
+//! assert_eq!(parse(&[0x08, 0x1d]).unwrap().to_string(), "adds r0, r1, #4");
+//! assert_eq!(parse(&[0x10, 0xb5]).unwrap().to_string(), "push {r4, lr}");
+//! assert_eq!(parse(&[0xfe, 0xe7]).unwrap().to_string(), "b #-4");
+//! assert_eq!(parse_at(&[0xfe, 0xe7], 0x100).unwrap().to_string(), "b 0x100");
+//! ```
+
+use core::fmt::{self, Display, Formatter};
+
+use crate::{
+    conditions::Condition,
+    instructons::{DecodedAt, Instruction, Operation},
+    registers::Register,
+};
+
+impl Display for Operation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write_operation(f, self, None)
+    }
+}
+
+impl Display for Instruction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        self.operation.fmt(f)
+    }
+}
+
+impl Display for DecodedAt {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write_operation(f, &self.instruction.operation, Some(self.address))
+    }
+}
+
+/// Writes `operation`, with the targets of branches as addresses if its `address` is known.
+fn write_operation(
+    f: &mut Formatter<'_>,
+    operation: &Operation,
+    address: Option<u32>,
+) -> fmt::Result {
+    use Operation as O;
+
+    let target = |imm: u32| Target { imm, address };
+    match *operation {
+        O::ADCReg { m, d, .. } => write!(f, "adcs {d}, {m}"),
+        O::ADDImm { imm, n, d } if d == n => write!(f, "adds {d}, #{imm}"),
+        O::ADDImm { imm, n, d } => write!(f, "adds {d}, {n}, #{imm}"),
+        O::ADDReg {
+            m,
+            n,
+            d,
+            set_flags: true,
+        } => write!(f, "adds {d}, {n}, {m}"),
+        O::ADDReg { m, d, .. } => write!(f, "add {d}, {m}"),
+        O::ADDImmSP {
+            d: Register::SP,
+            imm,
+        } => write!(f, "add sp, #{imm}"),
+        O::ADDImmSP { d, imm } => write!(f, "add {d}, sp, #{imm}"),
+        O::ADDRegSP { d: Register::SP, m } => write!(f, "add sp, {m}"),
+        O::ADDRegSP { d, m } if d == m => write!(f, "add {d}, sp"),
+        O::ADDRegSP { d, m } => write!(f, "add {d}, sp, {m}"),
+        O::ADR { d, imm } => write!(f, "add {d}, pc, #{imm}"),
+        O::ANDReg { m, dn } => write!(f, "ands {dn}, {m}"),
+        O::ASRImm { imm, m, d } => write!(f, "asrs {d}, {m}, #{}", shift_32(imm)),
+        O::ASRReg { m, dn } => write!(f, "asrs {dn}, {m}"),
+        O::B {
+            cond: Condition::None,
+            imm,
+        } => write!(f, "b {}", target(imm)),
+        O::B { cond, imm } => write!(f, "b{cond} {}", target(imm)),
+        O::BICReg { m, dn } => write!(f, "bics {dn}, {m}"),
+        O::BKPT { imm } => write!(f, "bkpt {imm:#06x}"),
+        O::BL { imm } => write!(f, "bl {}", target(imm)),
+        O::BLXReg { m } => write!(f, "blx {m}"),
+        O::BX { m } => write!(f, "bx {m}"),
+        O::CMNReg { m, n } => write!(f, "cmn {n}, {m}"),
+        O::CMPImm { n, imm } => write!(f, "cmp {n}, #{imm}"),
+        O::CMPReg { m, n } => write!(f, "cmp {n}, {m}"),
+        O::CPS { im: true } => f.write_str("cpsid i"),
+        O::CPS { im: false } => f.write_str("cpsie i"),
+        O::CPY => f.write_str("cpy"),
+        O::DMB { option } => write!(f, "dmb {}", Barrier(option)),
+        O::DSB { option } => write!(f, "dsb {}", Barrier(option)),
+        O::EORReg { m, dn } => write!(f, "eors {dn}, {m}"),
+        O::ISB { option } => write!(f, "isb {}", Barrier(option)),
+        // The base register is written back unless loaded.
+        O::LDM { n, reg_list } if reg_list.contains(n) => write!(f, "ldmia {n}, {reg_list}"),
+        O::LDM { n, reg_list } => write!(f, "ldmia {n}!, {reg_list}"),
+        O::LDRImm { imm, n, t } => write!(f, "ldr {t}, [{n}, #{imm}]"),
+        O::LDRLiteral { t, imm } => write!(f, "ldr {t}, [pc, #{imm}]"),
+        O::LDRReg { m, n, t } => write!(f, "ldr {t}, [{n}, {m}]"),
+        O::LDRBImm { imm, n, t } => write!(f, "ldrb {t}, [{n}, #{imm}]"),
+        O::LDRBReg { m, n, t } => write!(f, "ldrb {t}, [{n}, {m}]"),
+        O::LDRHImm { imm, n, t } => write!(f, "ldrh {t}, [{n}, #{imm}]"),
+        O::LDRHReg { m, n, t } => write!(f, "ldrh {t}, [{n}, {m}]"),
+        O::LDRSBReg { m, n, t } => write!(f, "ldrsb {t}, [{n}, {m}]"),
+        O::LDRSH { m, n, t } => write!(f, "ldrsh {t}, [{n}, {m}]"),
+        O::LSLImm { imm, m, d } => write!(f, "lsls {d}, {m}, #{imm}"),
+        O::LSLReg { m, dn } => write!(f, "lsls {dn}, {m}"),
+        O::LSRImm { imm, m, d } => write!(f, "lsrs {d}, {m}, #{}", shift_32(imm)),
+        O::LSRReg { m, dn } => write!(f, "lsrs {dn}, {m}"),
+        O::MOVImm { d, imm } => write!(f, "movs {d}, #{imm}"),
+        O::MOVReg {
+            m,
+            d,
+            set_flags: true,
+        } => write!(f, "movs {d}, {m}"),
+        O::MOVReg { m, d, .. } => write!(f, "mov {d}, {m}"),
+        O::MRS { d, sysm } => write!(f, "mrs {d}, {sysm}"),
+        O::MSRReg { n, sysm } => write!(f, "msr {sysm}, {n}"),
+        O::MUL { n, dm } => write!(f, "muls {dm}, {n}"),
+        O::MVNReg { m, d } => write!(f, "mvns {d}, {m}"),
+        O::NOP => f.write_str("nop"),
+        O::ORRReg { m, dn } => write!(f, "orrs {dn}, {m}"),
+        O::POP { reg_list } => write!(f, "pop {reg_list}"),
+        O::PUSH { reg_list } => write!(f, "push {reg_list}"),
+        O::REV { m, d } => write!(f, "rev {d}, {m}"),
+        O::REV16 { m, d } => write!(f, "rev16 {d}, {m}"),
+        O::REVSH { m, d } => write!(f, "revsh {d}, {m}"),
+        O::RORReg { m, dn } => write!(f, "rors {dn}, {m}"),
+        O::RSBImm { n, d } => write!(f, "negs {d}, {n}"),
+        O::SBCReg { m, dn } => write!(f, "sbcs {dn}, {m}"),
+        O::SEV => f.write_str("sev"),
+        O::STM { n, reg_list } => write!(f, "stmia {n}!, {reg_list}"),
+        O::STRImm { imm, n, t } => write!(f, "str {t}, [{n}, #{imm}]"),
+        O::STRReg { m, n, t } => write!(f, "str {t}, [{n}, {m}]"),
+        O::STRBImm { imm, n, t } => write!(f, "strb {t}, [{n}, #{imm}]"),
+        O::STRBReg { m, n, t } => write!(f, "strb {t}, [{n}, {m}]"),
+        O::STRHImm { imm, n, t } => write!(f, "strh {t}, [{n}, #{imm}]"),
+        O::STRHReg { m, n, t } => write!(f, "strh {t}, [{n}, {m}]"),
+        O::SUBImm { imm, n, d } if d == n => write!(f, "subs {d}, #{imm}"),
+        O::SUBImm { imm, n, d } => write!(f, "subs {d}, {n}, #{imm}"),
+        O::SUBReg { m, n, d } => write!(f, "subs {d}, {n}, {m}"),
+        O::SUBImmSP { imm } => write!(f, "sub sp, #{imm}"),
+        O::SVC { imm } => write!(f, "svc {imm}"),
+        O::SXTB { m, d } => write!(f, "sxtb {d}, {m}"),
+        O::SXTH { m, d } => write!(f, "sxth {d}, {m}"),
+        O::TSTReg { m, n } => write!(f, "tst {n}, {m}"),
+        O::UDF { imm } => write!(f, "udf #{imm}"),
+        O::UXTB { m, d } => write!(f, "uxtb {d}, {m}"),
+        O::UXTH { m, d } => write!(f, "uxth {d}, {m}"),
+        O::WFE => f.write_str("wfe"),
+        O::WFI => f.write_str("wfi"),
+        O::YIELD => f.write_str("yield"),
+    }
+}
+
+/// Shift amounts of `lsr` and `asr` encoded as 0 shift by 32.
+fn shift_32(imm: u32) -> u32 {
+    match imm {
+        0 => 32,
+        imm => imm,
+    }
+}
+
+/// Target of a branch, the address if known or the offset from `pc`.
+struct Target {
+    imm: u32,
+    address: Option<u32>,
+}
+
+impl Display for Target {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.address {
+            Some(address) => write!(f, "{:#x}", address.wrapping_add(4).wrapping_add(self.imm)),
+            None => write!(f, "#{}", self.imm as i32),
+        }
+    }
+}
+
+/// Option of a barrier, `sy` for the full system barrier.
+struct Barrier(u8);
+
+impl Display for Barrier {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            0xf => f.write_str("sy"),
+            option => write!(f, "#{option}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{parse, parse_at};
+
+    #[test]
+    fn objdump_syntax() {
+        let text = |bytes: &[u8]| parse(bytes).unwrap().to_string();
+        assert_eq!(text(&[0x01, 0x30]), "adds r0, #1");
+        assert_eq!(text(&[0x40, 0x18]), "adds r0, r0, r1");
+        assert_eq!(text(&[0x40, 0x44]), "add r0, r8");
+        assert_eq!(text(&[0x68, 0x44]), "add r0, sp");
+        assert_eq!(text(&[0x02, 0xa8]), "add r0, sp, #8");
+        assert_eq!(text(&[0x02, 0xa0]), "add r0, pc, #8");
+        assert_eq!(text(&[0x00, 0x08]), "lsrs r0, r0, #32");
+        assert_eq!(text(&[0x06, 0xc8]), "ldmia r0!, {r1, r2}");
+        assert_eq!(text(&[0x03, 0xc8]), "ldmia r0, {r0, r1}");
+        assert_eq!(text(&[0x48, 0x42]), "negs r0, r1");
+        assert_eq!(text(&[0x08, 0x68]), "ldr r0, [r1, #0]");
+        assert_eq!(text(&[0x80, 0xf3, 0x10, 0x88]), "msr PRIMASK, r0");
+        assert_eq!(text(&[0xbf, 0xf3, 0x5f, 0x8f]), "dmb sy");
+        assert_eq!(text(&[0xab, 0xbe]), "bkpt 0x00ab");
+        let decoded = parse_at(&[0x00, 0xf0, 0x7e, 0xf8], 0x1000).unwrap();
+        assert_eq!(decoded.to_string(), "bl 0x1100");
+        assert_eq!(decoded.instruction.to_string(), "bl #252");
+
+        // The assembler parses the text back.
+        #[cfg(feature = "alloc")]
+        for bytes in [
+            [0x01, 0x30],
+            [0x40, 0x18],
+            [0x00, 0x08],
+            [0x03, 0xc8],
+            [0xfe, 0xe7],
+        ] {
+            let operation = parse(&bytes).unwrap().operation;
+            assert_eq!(operation.to_string().parse(), Ok(operation));
+        }
+    }
+}
@@ -0,0 +1,164 @@
+//! Recognition of the stack frames set up by compiler generated prologues and torn down by
+//! epilogues, for unwinders and stack analysis.
+//!
+//! # Example
+//! ```
+//! # use armv6_m_instruction_parser::{frame::prologue, registers::{Register, RegisterList}};
+//! # use armv6_m_instruction_parser::{instructons::Operation, parse};
+//! // push {r4, r7, lr}; sub sp, #12; add r7, sp, #0; movs r0, #1
+//! let code = [0x90, 0xb5, 0x83, 0xb0, 0x00, 0xaf, 0x01, 0x20];
+//! let operations: Vec<Operation> = code
+//!     .chunks(2)
+//!     .map(|halfword| parse(halfword).unwrap().operation)
+//!     .collect();
+//! let frame = prologue(&operations).unwrap();
+//! assert_eq!(
+//!     frame.saved_regs,
+//!     RegisterList::from([Register::R4, Register::R7, Register::LR])
+//! );
+//! assert_eq!(frame.frame_size, 12);
+//! assert_eq!(frame.frame_pointer, Some(Register::R7));
+//! assert_eq!(frame.prologue_length, 3);
+//! ```
+
+use crate::{
+    instructons::Operation,
+    registers::{Register, RegisterList},
+};
+
+/// Stack frame set up by a prologue.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameInfo {
+    /// Registers saved on the stack, including high registers moved to low registers to be
+    /// pushed.
+    pub saved_regs: RegisterList,
+    /// Bytes allocated with `sub sp` below the saved registers.
+    pub frame_size: u32,
+    /// Register pointing into the frame, set with `add r7, sp, #imm` or `mov r7, sp`.
+    pub frame_pointer: Option<Register>,
+    /// Number of instructions of the prologue.
+    pub prologue_length: usize,
+}
+
+impl FrameInfo {
+    /// Bytes the stack grows by in the prologue.
+    pub fn stack_size(&self) -> u32 {
+        4 * self.saved_regs.len() as u32 + self.frame_size
+    }
+}
+
+/// Recognizes a prologue at the start of `operations`.
+///
+/// A prologue is made of `push`, `sub sp, #imm`, setting up a frame pointer, and saving high
+/// registers with `mov rX, r8` followed by pushing the low register, in any order. Returns
+/// `None` if the operations do not start with a prologue.
+pub fn prologue(operations: &[Operation]) -> Option<FrameInfo> {
+    let mut frame = FrameInfo {
+        saved_regs: RegisterList::new(),
+        frame_size: 0,
+        frame_pointer: None,
+        prologue_length: 0,
+    };
+    // High registers moved to low registers, indexed by the low register.
+    let mut moved = [None; 8];
+    for operation in operations {
+        match *operation {
+            Operation::PUSH { reg_list } => {
+                for register in reg_list {
+                    let saved = moved
+                        .get_mut(register as usize)
+                        .and_then(Option::take)
+                        .unwrap_or(register);
+                    frame.saved_regs.insert(saved);
+                }
+            }
+            Operation::MOVReg { m, d, .. }
+                if (d as u8) < 8 && (Register::R8..=Register::R12).contains(&m) =>
+            {
+                moved[d as usize] = Some(m);
+            }
+            Operation::SUBImmSP { imm } => frame.frame_size += imm,
+            Operation::ADDImmSP { d, .. }
+            | Operation::MOVReg {
+                m: Register::SP, d, ..
+            } if d != Register::SP && frame.frame_pointer.is_none() => {
+                frame.frame_pointer = Some(d);
+            }
+            _ => break,
+        }
+        frame.prologue_length += 1;
+    }
+    (frame.prologue_length > 0 && frame.stack_size() > 0).then_some(frame)
+}
+
+/// Recognizes an epilogue at the end of `operations`, returning the index of its first
+/// instruction.
+///
+/// An epilogue ends with `pop {..., pc}` or `bx`, and can restore `sp` from the frame pointer
+/// or with `add sp, #imm`, pop registers and restore high registers with `mov r8, rX`.
+/// Returns `None` if the operations do not end with a return.
+pub fn epilogue(operations: &[Operation]) -> Option<usize> {
+    let (last, rest) = operations.split_last()?;
+    match last {
+        Operation::POP { reg_list } if reg_list.contains(Register::PC) => {}
+        Operation::BX { .. } => {}
+        _ => return None,
+    }
+    let length = rest
+        .iter()
+        .rev()
+        .take_while(|operation| match **operation {
+            Operation::POP { reg_list } => !reg_list.contains(Register::PC),
+            Operation::MOVReg { m, d, .. } => {
+                d == Register::SP || ((Register::R8..=Register::R12).contains(&d) && (m as u8) < 8)
+            }
+            Operation::ADDImmSP { d, .. } => d == Register::SP,
+            _ => false,
+        })
+        .count();
+    Some(rest.len() - length)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parse;
+
+    fn operations(code: &[u8]) -> Vec<Operation> {
+        code.chunks(2)
+            .map(|halfword| parse(halfword).unwrap().operation)
+            .collect()
+    }
+
+    #[test]
+    fn high_registers() {
+        // push {r4, r5, r6, r7, lr}; mov r7, r9; mov r6, r8; push {r6, r7}; sub sp, #20
+        let code = [
+            0xf0, 0xb5, 0x4f, 0x46, 0x46, 0x46, 0xc0, 0xb4, 0x85, 0xb0, 0x01, 0x20,
+        ];
+        let frame = prologue(&operations(&code)).unwrap();
+        use Register::*;
+        assert_eq!(
+            frame.saved_regs,
+            RegisterList::from([R4, R5, R6, R7, R8, R9, LR])
+        );
+        assert_eq!(frame.frame_size, 20);
+        assert_eq!(frame.frame_pointer, None);
+        assert_eq!(frame.prologue_length, 5);
+        assert_eq!(frame.stack_size(), 48);
+
+        assert_eq!(prologue(&operations(&[0x01, 0x20])), None);
+    }
+
+    #[test]
+    fn epilogues() {
+        // movs r0, #1; add sp, #20; pop {r2, r3}; mov r8, r2; mov r9, r3; pop {r4-r7, pc}
+        let code = [
+            0x01, 0x20, 0x05, 0xb0, 0x0c, 0xbc, 0x90, 0x46, 0x99, 0x46, 0xf0, 0xbd,
+        ];
+        assert_eq!(epilogue(&operations(&code)), Some(1));
+        // movs r0, #1; bx lr
+        assert_eq!(epilogue(&operations(&[0x01, 0x20, 0x70, 0x47])), Some(1));
+        assert_eq!(epilogue(&operations(&[0x01, 0x20])), None);
+    }
+}
@@ -0,0 +1,296 @@
+//! Detection of functions in images without symbols.
+//!
+//! # Example
+//! ```
+//! # use armv6_m_instruction_parser::{functions::functions, program::Program};
+//! // 0x00: push {r4, lr}; bl 0x08; pop {r4, pc}
+//! // 0x08: movs r0, #1; bx lr
+//! let code = [
+//!     0x10, 0xb5, 0x00, 0xf0, 0x01, 0xf8, 0x10, 0xbd, 0x01, 0x20, 0x70, 0x47,
+//! ];
+//! let functions = functions(&Program::new(&code, 0));
+//! assert_eq!(functions.len(), 2);
+//! assert_eq!((functions[1].start, functions[1].end), (0x08, 0x0c));
+//! assert!(functions[1].called);
+//! ```
+
+use alloc::{
+    collections::{BTreeMap, BTreeSet},
+    vec::Vec,
+};
+
+use crate::{
+    conditions::Condition,
+    instructons::{BranchTarget, Operation},
+    program::Program,
+    registers::Register,
+};
+
+/// Address range of a function found by [`functions`], with the evidence for it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FunctionRange {
+    pub start: u32,
+    /// Address after the last instruction leaving the function, so literal pools and
+    /// padding after it are not included.
+    pub end: u32,
+    /// The start is the destination of a `bl`.
+    pub called: bool,
+    /// The function starts with `push {..., lr}`.
+    pub saves_lr: bool,
+}
+
+/// Returns the likely functions of `program` in address order.
+///
+/// Functions start at the destinations of `bl`, and at `push {..., lr}` at the start of the
+/// program, after the end of the previous function with only padding between, or on a word
+/// boundary as compilers align functions. A function ends with the last return, `b` or `bx`
+/// before the next function, or where the next function starts if there is none. Functions
+/// only reached by `blx` or through tables and not saving `lr` are not found.
+pub fn functions(program: &Program) -> Vec<FunctionRange> {
+    let mut called = BTreeSet::new();
+    for (address, result) in program {
+        let Ok(instruction) = result else {
+            continue;
+        };
+        if let (Operation::BL { .. }, BranchTarget::Direct(target)) = (
+            &instruction.operation,
+            instruction.operation.branch_target(address),
+        ) {
+            if program.at(target).is_some() {
+                called.insert(target);
+            }
+        }
+    }
+
+    let mut starts = called.clone();
+    let mut after_end = true;
+    for (address, result) in program {
+        let Ok(instruction) = result else {
+            continue;
+        };
+        let operation = &instruction.operation;
+        if saves_lr(operation) && (after_end || address % 4 == 0) {
+            starts.insert(address);
+        }
+        if !is_padding(operation) {
+            after_end = ends_flow(operation);
+        }
+    }
+
+    let starts: Vec<u32> = starts.into_iter().collect();
+    let program_end = program
+        .iter()
+        .next_back()
+        .map_or(program.base(), |(address, result)| {
+            address.wrapping_add(result.as_ref().map_or(2, |instruction| instruction.size()))
+        });
+    starts
+        .iter()
+        .enumerate()
+        .map(|(index, &start)| {
+            let next = starts.get(index + 1).copied().unwrap_or(program_end);
+            let end = program
+                .range(start..next)
+                .filter_map(|(address, result)| {
+                    let instruction = result.as_ref().ok()?;
+                    ends_flow(&instruction.operation)
+                        .then(|| address.wrapping_add(instruction.size()))
+                })
+                .next_back()
+                .unwrap_or(next);
+            FunctionRange {
+                start,
+                end,
+                called: called.contains(&start),
+                saves_lr: program
+                    .at(start)
+                    .is_some_and(|instruction| saves_lr(&instruction.operation)),
+            }
+        })
+        .collect()
+}
+
+/// Score of the evidence for a [`FunctionStart`] being a vector table entry or exported.
+const ENTRY_WEIGHT: u32 = 16;
+
+/// Score of the evidence for every call to a [`FunctionStart`].
+const CALL_WEIGHT: u32 = 8;
+
+/// Score of the evidence for a [`FunctionStart`] saving `lr`.
+const PROLOGUE_WEIGHT: u32 = 8;
+
+/// Score of the evidence for a [`FunctionStart`] following the end of the code before it.
+const AFTER_END_WEIGHT: u32 = 2;
+
+/// Score of the evidence for a [`FunctionStart`] being word aligned.
+const ALIGNED_WEIGHT: u32 = 1;
+
+/// Probable start of a function found by [`discover_functions`], with the evidence for it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FunctionStart {
+    pub address: u32,
+    /// Sum of the weights of the evidence, higher is more probable.
+    pub score: u32,
+    /// The address is one of the known entry points.
+    pub entry: bool,
+    /// Number of `bl` to the address.
+    pub calls: u32,
+    /// The instruction is `push {..., lr}`.
+    pub saves_lr: bool,
+    /// The instruction follows an unconditional branch or return, with only padding between.
+    pub after_end: bool,
+    /// The address is word aligned, as compilers align functions.
+    pub aligned: bool,
+}
+
+/// Returns the probable function starts of `program`, ranked from the most to the least
+/// probable, see [`discover_functions_from`].
+pub fn discover_functions(program: &Program) -> Vec<FunctionStart> {
+    discover_functions_from(program, &[])
+}
+
+/// Returns the probable function starts of `program` given known `entries`, e.g. from
+/// [`VectorTable::handlers`](crate::vector_table::VectorTable::handlers), ranked from the most to the least
+/// probable, ties in address order.
+///
+/// Unlike [`functions`], every address with some evidence is a candidate: known entries,
+/// targets of `bl`, `push {..., lr}` and instructions following the end of the code before
+/// them. Word alignment only adds to the other evidence. Instructions following the end of
+/// code are weak evidence, as they are also the `else` branches within functions.
+pub fn discover_functions_from(program: &Program, entries: &[u32]) -> Vec<FunctionStart> {
+    let mut starts: BTreeMap<u32, FunctionStart> = BTreeMap::new();
+    for &entry in entries {
+        if program.at(entry & !1).is_some() {
+            candidate(&mut starts, entry & !1).entry = true;
+        }
+    }
+    let mut after_end = true;
+    for (address, result) in program {
+        let Ok(instruction) = result else {
+            continue;
+        };
+        let operation = &instruction.operation;
+        if let (Operation::BL { .. }, BranchTarget::Direct(target)) =
+            (operation, operation.branch_target(address))
+        {
+            if program.at(target).is_some() {
+                candidate(&mut starts, target).calls += 1;
+            }
+        }
+        if saves_lr(operation) {
+            candidate(&mut starts, address).saves_lr = true;
+        }
+        if is_padding(operation) {
+            continue;
+        }
+        if after_end {
+            candidate(&mut starts, address).after_end = true;
+        }
+        after_end = ends_flow(operation);
+    }
+
+    let mut starts: Vec<FunctionStart> = starts
+        .into_values()
+        .map(|mut start| {
+            start.score = start.entry as u32 * ENTRY_WEIGHT
+                + start.calls * CALL_WEIGHT
+                + start.saves_lr as u32 * PROLOGUE_WEIGHT
+                + start.after_end as u32 * AFTER_END_WEIGHT
+                + start.aligned as u32 * ALIGNED_WEIGHT;
+            start
+        })
+        .collect();
+    starts.sort_by_key(|start| core::cmp::Reverse(start.score));
+    starts
+}
+
+/// Returns the candidate at `address` of `starts`, added without evidence if missing.
+fn candidate(starts: &mut BTreeMap<u32, FunctionStart>, address: u32) -> &mut FunctionStart {
+    starts.entry(address).or_insert(FunctionStart {
+        address,
+        score: 0,
+        entry: false,
+        calls: 0,
+        saves_lr: false,
+        after_end: false,
+        aligned: address.is_multiple_of(4),
+    })
+}
+
+fn saves_lr(operation: &Operation) -> bool {
+    matches!(operation, Operation::PUSH { reg_list } if reg_list.contains(Register::LR))
+}
+
+/// Instructions after which execution does not continue with the next instruction.
+pub(crate) fn ends_flow(operation: &Operation) -> bool {
+    match operation {
+        Operation::B { cond, .. } => *cond == Condition::None,
+        Operation::BL { .. } | Operation::BLXReg { .. } => false,
+        operation => operation.modifies_pc(),
+    }
+}
+
+/// Instructions compilers and linkers pad between functions with.
+pub(crate) fn is_padding(operation: &Operation) -> bool {
+    matches!(
+        operation,
+        Operation::NOP
+            | Operation::LSLImm {
+                imm: 0,
+                m: Register::R0,
+                d: Register::R0
+            }
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn boundaries() {
+        let code = [
+            // 0x00: leaf function only found through the call, with a literal pool
+            0x00, 0x48, 0x70, 0x47, 0x78, 0x56, 0x34, 0x12, //
+            // 0x08: push {r7, lr}; bl 0x00; pop {r7, pc}; nop
+            0x80, 0xb5, 0xff, 0xf7, 0xf9, 0xff, 0x80, 0xbd, 0x00, 0xbf, //
+            // 0x12: push {r4, lr}; b.n 0x12
+            0x10, 0xb5, 0xfd, 0xe7,
+        ];
+        let functions = functions(&Program::new(&code, 0));
+        let ranges: Vec<_> = functions.iter().map(|f| (f.start, f.end)).collect();
+        assert_eq!(ranges, [(0x00, 0x04), (0x08, 0x10), (0x12, 0x16)]);
+        assert!(functions[0].called && !functions[0].saves_lr);
+        assert!(!functions[1].called && functions[1].saves_lr);
+        assert!(functions[2].saves_lr);
+    }
+
+    #[test]
+    fn ranked_starts() {
+        // 0x00: push {r4, lr}; bl 0x0a; pop {r4, pc}; nop
+        // 0x0a: cmp r0, #0; beq.n 0x10; bx lr
+        // 0x10: movs r0, #1; bx lr
+        let code = [
+            0x10, 0xb5, 0x00, 0xf0, 0x02, 0xf8, 0x10, 0xbd, 0x00, 0xbf, //
+            0x00, 0x28, 0x00, 0xd0, 0x70, 0x47, 0x01, 0x20, 0x70, 0x47,
+        ];
+        let program = Program::new(&code, 0);
+        let starts: Vec<_> = discover_functions_from(&program, &[0x01])
+            .iter()
+            .map(|start| (start.address, start.score))
+            .collect();
+        assert_eq!(
+            starts,
+            [
+                (
+                    0x00,
+                    ENTRY_WEIGHT + PROLOGUE_WEIGHT + AFTER_END_WEIGHT + ALIGNED_WEIGHT
+                ),
+                (0x0a, CALL_WEIGHT + AFTER_END_WEIGHT),
+                (0x10, AFTER_END_WEIGHT + ALIGNED_WEIGHT),
+            ]
+        );
+    }
+}
@@ -0,0 +1,162 @@
+//! Random operations for fuzzing emulators and assemblers against well-formed instructions:
+//! `Arbitrary` for [`Operation`] with the `arbitrary` feature and proptest strategies with
+//! the `proptest` feature.
+//!
+//! Operations are generated by filling the operand bits of an encoding of a random
+//! instruction class and decoding it, so every operation generated decodes from and
+//! [encodes](crate::encoder::encode) to the same bits, and all classes are about equally
+//! likely, including the rare 32 bit instructions. All operations but `cpy`, which has no
+//! encoding, are generated.
+//!
+//! # Example
+//! ```
+//! # use armv6_m_instruction_parser::{encoder::encode, generate::from_bits};
+//! // Class 0 is `lsls`, its bits are imm5, Rm and Rd.
+//! let operation = from_bits(0, 0b00001_001_010).unwrap();
+//! assert_eq!(encode(&operation).unwrap().as_bytes(), [0x4a, 0x00]);
+//! ```
+
+use crate::{encoder::encode, instructons::Operation, parse};
+
+/// Instruction classes, the fixed bits of their encodings and the bits filled randomly,
+/// with the first halfword of 32 bit encodings in the upper 16 bits.
+const CLASSES: [(u32, u32); 41] = [
+    (0x0000, 0x07ff),           // lsls imm
+    (0x0800, 0x07ff),           // lsrs imm
+    (0x1000, 0x07ff),           // asrs imm
+    (0x1800, 0x01ff),           // adds reg
+    (0x1a00, 0x01ff),           // subs reg
+    (0x1c00, 0x01ff),           // adds imm3
+    (0x1e00, 0x01ff),           // subs imm3
+    (0x2000, 0x07ff),           // movs imm
+    (0x2800, 0x07ff),           // cmp imm
+    (0x3000, 0x07ff),           // adds imm8
+    (0x3800, 0x07ff),           // subs imm8
+    (0x4000, 0x03ff),           // data processing
+    (0x4400, 0x00ff),           // add high
+    (0x4500, 0x00ff),           // cmp high
+    (0x4600, 0x00ff),           // mov high
+    (0x4700, 0x0078),           // bx
+    (0x4780, 0x0078),           // blx
+    (0x4800, 0x07ff),           // ldr literal
+    (0x5000, 0x0fff),           // load and store reg
+    (0x6000, 0x0fff),           // str and ldr imm
+    (0x7000, 0x0fff),           // strb and ldrb imm
+    (0x8000, 0x0fff),           // strh and ldrh imm
+    (0x9000, 0x0fff),           // str and ldr sp
+    (0xa000, 0x0fff),           // adr and add sp
+    (0xb000, 0x00ff),           // add and sub sp
+    (0xb200, 0x00ff),           // extend
+    (0xb400, 0x01ff),           // push
+    (0xb662, 0x0010),           // cps
+    (0xba00, 0x00ff),           // rev
+    (0xbc00, 0x01ff),           // pop
+    (0xbe00, 0x00ff),           // bkpt
+    (0xbf00, 0x0070),           // hints
+    (0xc000, 0x07ff),           // stm
+    (0xc800, 0x07ff),           // ldm
+    (0xd000, 0x0fff),           // b cond, udf and svc
+    (0xe000, 0x07ff),           // b
+    (0xf000_d000, 0x07ff_2fff), // bl
+    (0xf380_8800, 0x000f_001f), // msr
+    (0xf3ef_8000, 0x0000_0f1f), // mrs
+    (0xf3bf_8f40, 0x0000_003f), // barriers
+    (0xf7f0_a000, 0x000f_0fff), // udf.w
+];
+
+/// Number of instruction classes, the values of `class` of [`from_bits`].
+pub const CLASS_COUNT: usize = CLASSES.len();
+
+/// Returns the operation of instruction class `class`, modulo [`CLASS_COUNT`], with its
+/// operand bits taken from `bits`, or `None` if the encoding is not valid or does not
+/// encode back to the same operation, like `push` of no registers.
+pub fn from_bits(class: usize, bits: u32) -> Option<Operation> {
+    let (fixed, variable) = CLASSES[class % CLASS_COUNT];
+    let encoding = fixed | bits & variable;
+    let mut bytes = [0; 4];
+    let size = match encoding > 0xffff {
+        true => {
+            bytes[..2].copy_from_slice(&((encoding >> 16) as u16).to_le_bytes());
+            bytes[2..].copy_from_slice(&(encoding as u16).to_le_bytes());
+            4
+        }
+        false => {
+            bytes[..2].copy_from_slice(&(encoding as u16).to_le_bytes());
+            2
+        }
+    };
+    let operation = parse(&bytes[..size]).ok()?.operation;
+    (encode(&operation).ok()?.as_bytes() == &bytes[..size]).then_some(operation)
+}
+
+/// Operand bits tried for a class before falling back to another.
+#[cfg(feature = "arbitrary")]
+const ATTEMPTS: usize = 8;
+
+/// Generates encodable operations, see the [module](self). Falls back to `nop` if the
+/// data runs out before a valid encoding is found.
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for Operation {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        while !u.is_empty() {
+            let class = u.choose_index(CLASS_COUNT)?;
+            for _ in 0..ATTEMPTS {
+                if let Some(operation) = from_bits(class, u.arbitrary()?) {
+                    return Ok(operation);
+                }
+            }
+        }
+        Ok(Operation::NOP)
+    }
+
+    fn size_hint(_depth: usize) -> (usize, Option<usize>) {
+        (0, None)
+    }
+}
+
+/// Strategy generating encodable operations, see the [module](self). Shrinks towards the
+/// first classes and operand bits of zero.
+///
+/// # Example
+/// ```
+/// # use armv6_m_instruction_parser::{encoder::encode, generate::operation};
+/// # use proptest::prelude::*;
+/// proptest!(|(operation in operation())| {
+///     prop_assert!(encode(&operation).is_ok());
+/// });
+/// ```
+#[cfg(feature = "proptest")]
+pub fn operation() -> impl proptest::strategy::Strategy<Value = Operation> {
+    use proptest::prelude::*;
+
+    (0..CLASS_COUNT, any::<u32>())
+        .prop_filter_map("invalid encoding", |(class, bits)| from_bits(class, bits))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn every_class() {
+        for (class, (fixed, _)) in CLASSES.iter().enumerate() {
+            let operations = (0..4096u32)
+                .filter_map(|bits| from_bits(class, bits.wrapping_mul(0x9e37_79b9)))
+                .count();
+            assert!(operations > 0, "class {class} {fixed:#x}");
+        }
+    }
+
+    #[cfg(feature = "arbitrary")]
+    #[test]
+    fn arbitrary_encodable() {
+        let data: Vec<u8> = (0..4096u32)
+            .map(|i| (i.wrapping_mul(0x9e37_79b9) >> 24) as u8)
+            .collect();
+        let mut u = arbitrary::Unstructured::new(&data);
+        while !u.is_empty() {
+            let operation: Operation = u.arbitrary().unwrap();
+            assert!(encode(&operation).is_ok(), "{operation:?}");
+        }
+    }
+}
@@ -0,0 +1,173 @@
+//! Recognition of instruction sequences forming one higher level operation, so that views of
+//! the code can show them collapsed.
+//!
+//! # Example
+//! ```
+//! # use armv6_m_instruction_parser::{control_flow::ControlFlowGraph, idioms::{idioms, Idiom}, program::Program, registers::Register};
+//! // movs r0, #0x12; lsls r0, r0, #8; adds r0, #0x34; bx lr
+//! let image = [0x12, 0x20, 0x00, 0x02, 0x34, 0x30, 0x70, 0x47];
+//! let program = Program::new(&image, 0);
+//! let cfg = ControlFlowGraph::new(&program, &[]);
+//! let found = idioms(&program, &image, &cfg);
+//! assert_eq!(found[0].range, 0x00..0x06);
+//! assert_eq!(
+//!     found[0].idiom,
+//!     Idiom::LoadConstant { register: Register::R0, value: 0x1234 }
+//! );
+//! ```
+
+use alloc::{vec, vec::Vec};
+use core::ops::Range;
+
+use crate::{
+    control_flow::ControlFlowGraph, instructons::Operation, literals::literal_loads,
+    program::Program, propagation::shift_immediate, registers::Register,
+};
+
+/// Higher level operation of an instruction sequence.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Idiom {
+    /// Load of a constant into a register, by a literal load or by `movs` followed by
+    /// `lsls`, `lsrs`, `adds`, `subs`, `mvns` and `rsbs` of the register onto itself.
+    LoadConstant { register: Register, value: u32 },
+}
+
+/// Instructions forming an [`Idiom`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IdiomMatch {
+    /// Addresses of the instructions.
+    pub range: Range<u32>,
+    pub idiom: Idiom,
+}
+
+/// Returns the idioms of `program`, disassembled from `image`, in address order.
+///
+/// Sequences are contiguous and within one block of `cfg`. Single `movs` are not reported as
+/// they already show the constant, literal loads are as they only show its address.
+pub fn idioms(program: &Program, image: &[u8], cfg: &ControlFlowGraph) -> Vec<IdiomMatch> {
+    let literals: Vec<(u32, u32)> = literal_loads(program, image)
+        .into_iter()
+        .filter_map(|load| Some((load.site, load.value?)))
+        .collect();
+    let mut found = vec![];
+    for block in cfg.blocks() {
+        // The constant being built, with the number of instructions building it.
+        let mut current: Option<(IdiomMatch, usize)> = None;
+        for (address, result) in program.range(block.clone()) {
+            let Ok(instruction) = result else {
+                found.extend(finish(current.take()));
+                continue;
+            };
+            let end = address.wrapping_add(instruction.size());
+            let extended = current.as_ref().and_then(|(constant, _)| {
+                let Idiom::LoadConstant { register, value } = constant.idiom;
+                Some(Idiom::LoadConstant {
+                    register,
+                    value: step(&instruction.operation, register, value)?,
+                })
+            });
+            if let (Some((constant, count)), Some(idiom)) = (&mut current, extended) {
+                constant.range.end = end;
+                constant.idiom = idiom;
+                *count += 1;
+                continue;
+            }
+            found.extend(finish(current.take()));
+            let start = match instruction.operation {
+                Operation::MOVImm { d, imm } => Some((d, imm, 1)),
+                // Counted twice, to be reported on its own.
+                Operation::LDRLiteral { t, .. } => literals
+                    .binary_search_by_key(&address, |(site, _)| *site)
+                    .ok()
+                    .map(|index| (t, literals[index].1, 2)),
+                _ => None,
+            };
+            current = start.map(|(register, value, count)| {
+                let idiom = Idiom::LoadConstant { register, value };
+                (
+                    IdiomMatch {
+                        range: address..end,
+                        idiom,
+                    },
+                    count,
+                )
+            });
+        }
+        found.extend(finish(current.take()));
+    }
+    found.sort_unstable_by_key(|idiom| idiom.range.start);
+    found
+}
+
+/// Returns the constant built, if it takes more than a `movs`.
+fn finish(current: Option<(IdiomMatch, usize)>) -> Option<IdiomMatch> {
+    current
+        .filter(|(_, count)| *count > 1)
+        .map(|(constant, _)| constant)
+}
+
+/// Returns `value` in `register` after `operation`, if it only changes it by a constant.
+fn step(operation: &Operation, register: Register, value: u32) -> Option<u32> {
+    match *operation {
+        Operation::LSLImm { m, d, .. } | Operation::LSRImm { m, d, .. }
+            if m == register && d == register =>
+        {
+            shift_immediate(operation, value)
+        }
+        Operation::ADDImm { imm, n, d } if n == register && d == register => {
+            Some(value.wrapping_add(imm))
+        }
+        Operation::SUBImm { imm, n, d } if n == register && d == register => {
+            Some(value.wrapping_sub(imm))
+        }
+        Operation::MVNReg { m, d } if m == register && d == register => Some(!value),
+        Operation::RSBImm { n, d } if n == register && d == register => Some(value.wrapping_neg()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::assembler::assemble;
+
+    #[test]
+    fn constants() {
+        let image = assemble(
+            "    movs r1, #1
+                 movs r0, #0xff
+                 mvns r0, r0
+                 lsls r0, r0, #4
+                 cmp r0, r1
+                 beq done
+                 ldr r2, value
+                 lsls r2, r2, #1
+             done:
+                 subs r0, #3
+                 movs r3, #5
+                 lsrs r3, r3, #32
+                 bx lr
+                 .align 2
+             value:
+                 .word 0x40000000",
+        )
+        .unwrap()
+        .relocate(0, |_| None)
+        .unwrap();
+        let program = Program::new(&image, 0);
+        let cfg = ControlFlowGraph::new(&program, &[]);
+        let constant = |register, value| Idiom::LoadConstant { register, value };
+        let found: Vec<_> = idioms(&program, &image, &cfg)
+            .into_iter()
+            .map(|found| (found.range, found.idiom))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (0x02..0x08, constant(Register::R0, 0xffff_f000)),
+                (0x0c..0x10, constant(Register::R2, 0x8000_0000)),
+                (0x12..0x16, constant(Register::R3, 0)),
+            ]
+        );
+    }
+}
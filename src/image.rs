@@ -0,0 +1,193 @@
+//! Images of memory built by the loaders, so analyses work on one representation
+//! regardless of the input format.
+//!
+//! # Example
+//! ```
+//! # use armv6_m_instruction_parser::image::MemoryImage;
+//! // Vector table with the reset handler at 0x0800_0008, then movs r0, #1; bx lr.
+//! let bin = [
+//!     0x00, 0x20, 0x00, 0x20, 0x09, 0x00, 0x00, 0x08, 0x01, 0x20, 0x70, 0x47,
+//! ];
+//! let mut image = MemoryImage::from_bin(&bin, 0x0800_0000);
+//! image.vector_table = Some(0x0800_0000);
+//! image.entry_points.push(0x0800_000a);
+//! assert_eq!(image.entries(2), [0x0800_0008, 0x0800_000a]);
+//! assert_eq!(image.read(0x0800_0008, 2), Some(&[0x01, 0x20][..]));
+//! ```
+
+use alloc::{vec, vec::Vec};
+
+use crate::{
+    instructons::Instruction, program::Program, vector_table::VectorTable, AddressedDisassembly,
+    Error, ThumbInstructions,
+};
+
+/// Contiguous bytes of a [`MemoryImage`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Segment {
+    pub address: u32,
+    pub data: Vec<u8>,
+}
+
+impl Segment {
+    /// Returns the instructions of the segment with their addresses.
+    pub fn instructions(&self) -> AddressedDisassembly<'_> {
+        self.data.thumb_instructions_at(self.address)
+    }
+
+    /// Returns the disassembly of the whole segment.
+    pub fn program(&self) -> Program {
+        Program::new(&self.data, self.address)
+    }
+
+    /// Returns whether `address` is in the segment.
+    pub fn contains(&self, address: u32) -> bool {
+        address.wrapping_sub(self.address) < self.data.len() as u32
+    }
+
+    /// Address after the segment, which can be past the 32 bit address space.
+    fn end(&self) -> u64 {
+        self.address as u64 + self.data.len() as u64
+    }
+}
+
+/// Bytes placed in memory, with where execution can start.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MemoryImage {
+    /// Segments in address order, neither overlapping nor adjacent.
+    segments: Vec<Segment>,
+    /// Addresses execution starts at besides the vector table, like the entry point of an
+    /// ELF file.
+    pub entry_points: Vec<u32>,
+    /// Address of the vector table, as set in `VTOR`.
+    pub vector_table: Option<u32>,
+}
+
+impl MemoryImage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the image of a raw binary placed at `base`.
+    pub fn from_bin(bytes: &[u8], base: u32) -> Self {
+        let mut image = Self::new();
+        image.insert(base, bytes);
+        image
+    }
+
+    /// Places `data` at `address`, replacing the bytes there. Bytes past the end of the
+    /// address space are dropped.
+    pub fn insert(&mut self, address: u32, data: &[u8]) {
+        let room = (1 << 32) - address as u64;
+        let data = &data[..(data.len() as u64).min(room) as usize];
+        if data.is_empty() {
+            return;
+        }
+        let end = address as u64 + data.len() as u64;
+        // Segments overlapping or adjacent to the data are merged with it.
+        let first = self
+            .segments
+            .partition_point(|segment| segment.end() < address as u64);
+        let last = self
+            .segments
+            .partition_point(|segment| segment.address as u64 <= end);
+        let merged: Vec<Segment> = self.segments.drain(first..last).collect();
+        let start = merged
+            .first()
+            .map_or(address, |segment| segment.address.min(address));
+        let stop = merged.last().map_or(end, |segment| segment.end().max(end));
+        let mut bytes = vec![0; (stop - start as u64) as usize];
+        for segment in &merged {
+            let offset = (segment.address - start) as usize;
+            bytes[offset..offset + segment.data.len()].copy_from_slice(&segment.data);
+        }
+        let offset = (address - start) as usize;
+        bytes[offset..offset + data.len()].copy_from_slice(data);
+        self.segments.insert(
+            first,
+            Segment {
+                address: start,
+                data: bytes,
+            },
+        );
+    }
+
+    /// Segments in address order.
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+
+    /// Returns the segment containing `address`.
+    pub fn segment(&self, address: u32) -> Option<&Segment> {
+        let index = self
+            .segments
+            .partition_point(|segment| segment.address <= address);
+        let segment = self.segments.get(index.checked_sub(1)?)?;
+        segment.contains(address).then_some(segment)
+    }
+
+    /// Returns the `len` bytes at `address`, `None` unless they are all in the image.
+    pub fn read(&self, address: u32, len: usize) -> Option<&[u8]> {
+        let segment = self.segment(address)?;
+        let offset = (address - segment.address) as usize;
+        segment.data.get(offset..offset + len)
+    }
+
+    /// Returns the instructions of all segments with their addresses.
+    pub fn instructions(&self) -> impl Iterator<Item = (u32, Result<Instruction, Error>)> + '_ {
+        self.segments.iter().flat_map(Segment::instructions)
+    }
+
+    /// Returns the first `entries` words of the vector table, see [`VectorTable::parse`].
+    pub fn vectors(&self, entries: usize) -> Option<VectorTable> {
+        let segment = self.segment(self.vector_table?)?;
+        let offset = (self.vector_table? - segment.address) as usize;
+        VectorTable::parse(&segment.data[offset..], entries).ok()
+    }
+
+    /// Returns the handlers of the first `entries` words of the vector table followed by
+    /// the other entry points, without duplicates.
+    pub fn entries(&self, entries: usize) -> Vec<u32> {
+        let mut addresses: Vec<u32> = self
+            .vectors(entries)
+            .map_or(vec![], |table| table.handlers().collect());
+        for &address in &self.entry_points {
+            if !addresses.contains(&address) {
+                addresses.push(address);
+            }
+        }
+        addresses
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn insert() {
+        let mut image = MemoryImage::from_bin(&[1, 2, 3, 4], 0x100);
+        image.insert(0x200, &[5, 6]);
+        image.insert(0x0fe, &[7, 8]);
+        image.insert(0x103, &[9, 10]);
+        assert_eq!(
+            image.segments(),
+            [
+                Segment {
+                    address: 0x0fe,
+                    data: vec![7, 8, 1, 2, 3, 9, 10],
+                },
+                Segment {
+                    address: 0x200,
+                    data: vec![5, 6],
+                },
+            ]
+        );
+        image.insert(0x105, &[0; 0xfb]);
+        assert_eq!(image.segments().len(), 1);
+        assert_eq!(image.read(0x1ff, 3), Some(&[0, 5, 6][..]));
+        assert_eq!(image.read(0x201, 2), None);
+        image.insert(0xffff_fffe, &[1, 2, 3]);
+        assert_eq!(image.read(0xffff_fffe, 2), Some(&[1, 2][..]));
+    }
+}
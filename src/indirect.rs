@@ -0,0 +1,153 @@
+//! Enumeration of the indirect branches of an image with the instructions computing their
+//! destinations, for control flow integrity audits.
+//!
+//! # Example
+//! ```
+//! # use armv6_m_instruction_parser::{indirect::{indirect_branches, IndirectKind}, program::Program};
+//! // ldr r3, [pc, #4]; adds r3, #1; bx r3; nop; .word 0x101
+//! let code = [
+//!     0x01, 0x4b, 0x01, 0x33, 0x18, 0x47, 0x00, 0xbf, 0x01, 0x01, 0x00, 0x00,
+//! ];
+//! let branches = indirect_branches(&Program::new(&code, 0));
+//! assert_eq!(branches[0].kind, IndirectKind::Bx);
+//! assert_eq!(branches[0].definitions, [0x02, 0x00]);
+//! assert!(branches[0].live_in.is_empty());
+//! ```
+
+use alloc::{vec, vec::Vec};
+
+use crate::{
+    control_flow::basic_blocks,
+    instructons::Operation,
+    program::Program,
+    registers::{Register, RegisterList},
+};
+
+/// Instruction branching to an address computed at run time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndirectKind {
+    /// `bx rX`, including returns with `bx lr`.
+    Bx,
+    /// `blx rX`.
+    Blx,
+    /// `pop {..., pc}`.
+    PopPc,
+    /// `mov pc, rX`.
+    MovPc,
+    /// `add pc, rX`.
+    AddPc,
+}
+
+/// Indirect branch found by [`indirect_branches`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndirectBranch {
+    /// Address of the branch.
+    pub site: u32,
+    pub kind: IndirectKind,
+    /// Register holding the destination, `pc` for `pop` as it is loaded from the stack.
+    pub register: Register,
+    /// Addresses of the instructions of the basic block the destination is computed with,
+    /// nearest to the branch first.
+    pub definitions: Vec<u32>,
+    /// Registers the destination depends on which are set before the basic block.
+    pub live_in: RegisterList,
+}
+
+/// Returns every indirect branch of `program` in address order.
+///
+/// The definitions follow the registers the destination is computed from back through the
+/// basic block of the branch, e.g. `adds r3, #1` and the `ldr r3, [pc, #4]` before it for
+/// `bx r3`. Registers read by the definitions are followed too, so a load adds its base
+/// register, while values loaded from memory are not followed. `pc` is never live in, as
+/// values computed from it are known.
+pub fn indirect_branches(program: &Program) -> Vec<IndirectBranch> {
+    let blocks = basic_blocks(program);
+    let mut branches = vec![];
+    for (site, result) in program {
+        let Ok(instruction) = result else {
+            continue;
+        };
+        let (kind, register) = match instruction.operation {
+            Operation::BX { m } => (IndirectKind::Bx, m),
+            Operation::BLXReg { m } => (IndirectKind::Blx, m),
+            Operation::POP { reg_list } if reg_list.contains(Register::PC) => {
+                (IndirectKind::PopPc, Register::PC)
+            }
+            Operation::MOVReg {
+                m, d: Register::PC, ..
+            } => (IndirectKind::MovPc, m),
+            Operation::ADDReg {
+                m, d: Register::PC, ..
+            } => (IndirectKind::AddPc, m),
+            _ => continue,
+        };
+
+        let start = blocks
+            .iter()
+            .find(|block| block.contains(&site))
+            .map_or(site, |block| block.start);
+        let mut live = RegisterList::new();
+        if kind != IndirectKind::PopPc {
+            live.insert(register);
+        }
+        let mut definitions = vec![];
+        for (address, result) in program.range(start..site).rev() {
+            let Ok(instruction) = result else {
+                break;
+            };
+            let written = instruction.operation.registers_written();
+            if live.bits() & written.bits() == 0 {
+                continue;
+            }
+            definitions.push(address);
+            live = RegisterList::from_bits(
+                live.bits() & !written.bits() | instruction.operation.registers_read().bits(),
+            );
+            live.remove(Register::PC);
+        }
+        branches.push(IndirectBranch {
+            site,
+            kind,
+            register,
+            definitions,
+            live_in: live,
+        });
+    }
+    branches
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn slices() {
+        // 0x00: push {r4, lr}; ldr r2, [r0, #4]; movs r1, #0; adds r3, r2, r1; blx r3
+        // 0x0a: mov pc, r4; pop {r4, pc}
+        let code = [
+            0x10, 0xb5, 0x42, 0x68, 0x00, 0x21, 0x53, 0x18, 0x98, 0x47, //
+            0xa7, 0x46, 0x10, 0xbd,
+        ];
+        let branches = indirect_branches(&Program::new(&code, 0));
+        let blx = &branches[0];
+        assert_eq!(
+            (blx.site, blx.kind, blx.register),
+            (0x08, IndirectKind::Blx, Register::R3)
+        );
+        assert_eq!(blx.definitions, [0x06, 0x04, 0x02]);
+        assert_eq!(blx.live_in, RegisterList::from([Register::R0]));
+
+        // Nothing in the block sets r4.
+        let mov = &branches[1];
+        assert_eq!((mov.site, mov.kind), (0x0a, IndirectKind::MovPc));
+        assert!(mov.definitions.is_empty());
+        assert_eq!(mov.live_in, RegisterList::from([Register::R4]));
+
+        let pop = &branches[2];
+        assert_eq!(
+            (pop.kind, pop.register),
+            (IndirectKind::PopPc, Register::PC)
+        );
+        assert!(pop.definitions.is_empty() && pop.live_in.is_empty());
+    }
+}
@@ -2,18 +2,20 @@
 
 use crate::{
     conditions::Condition,
-    registers::{Register, SpecialRegister},
+    registers::{Register, RegisterList, SpecialRegister},
 };
 
 /// Struct describing an instruction.
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Instruction {
     pub width: InstructionWidth,
     pub operation: Operation,
 }
 
 /// Enum describing the with of the corresponding binary representation of the instruction.
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum InstructionWidth {
     Bit32,
     Bit16,
@@ -29,10 +31,43 @@ impl Instruction {
     pub fn is_32bit(&self) -> bool {
         matches!(self.width, InstructionWidth::Bit32)
     }
+
+    /// Size of the instruction in bytes.
+    pub fn size(&self) -> u32 {
+        match self.width {
+            InstructionWidth::Bit16 => 2,
+            InstructionWidth::Bit32 => 4,
+        }
+    }
+}
+
+/// Instruction together with the address it was decoded at.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodedAt {
+    pub instruction: Instruction,
+    pub address: u32,
+}
+
+impl DecodedAt {
+    /// Size of the instruction in bytes.
+    pub fn size(&self) -> u32 {
+        self.instruction.size()
+    }
+
+    /// Address of the instruction following this one.
+    pub fn next_address(&self) -> u32 {
+        self.address.wrapping_add(self.size())
+    }
+
+    /// Address referred to by the instruction, see [`Operation::branch_target`].
+    pub fn branch_target(&self) -> BranchTarget {
+        self.instruction.operation.branch_target(self.address)
+    }
 }
 
 /// Describes operation i.e. what type of instruction it is.
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Operation {
     ADCReg {
         m: Register,
@@ -44,10 +79,13 @@ pub enum Operation {
         n: Register,
         d: Register,
     },
+    /// `adds` of low registers if `set_flags`, `add` of the destination and any register
+    /// otherwise.
     ADDReg {
         m: Register,
         n: Register,
         d: Register,
+        set_flags: bool,
     },
     ADDImmSP {
         d: Register,
@@ -125,7 +163,7 @@ pub enum Operation {
     },
     LDM {
         n: Register,
-        reg_list: Vec<Register>,
+        reg_list: RegisterList,
     },
     LDRImm {
         imm: u32,
@@ -220,10 +258,10 @@ pub enum Operation {
         dn: Register,
     },
     POP {
-        reg_list: Vec<Register>,
+        reg_list: RegisterList,
     },
     PUSH {
-        reg_list: Vec<Register>,
+        reg_list: RegisterList,
     },
     REV {
         m: Register,
@@ -252,7 +290,7 @@ pub enum Operation {
     SEV,
     STM {
         n: Register,
-        reg_list: Vec<Register>,
+        reg_list: RegisterList,
     },
     STRImm {
         imm: u32,
@@ -328,6 +366,374 @@ pub enum Operation {
     YIELD,
 }
 
+/// Address an instruction refers to, see [`Operation::branch_target`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BranchTarget {
+    /// Address known from the instruction and its address.
+    Direct(u32),
+    /// Branch to the address in a register.
+    Indirect(Register),
+    /// The instruction does not refer to an address.
+    None,
+}
+
+/// Memory access of a load or store, see [`Operation::memory_access`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryAccess {
+    /// Size of every transfer, every register of `ldm`, `stm`, `push` and `pop` is a word.
+    pub size: AccessSize,
+    pub direction: AccessDirection,
+    /// Loaded value is sign extended, only `ldrsb` and `ldrsh`.
+    pub signed: bool,
+    /// Register holding the base address, `pc` for literal loads.
+    pub base: Register,
+    pub offset: AccessOffset,
+    /// The base register is updated with the address after the transfers, or before them
+    /// for `push`.
+    pub writeback: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum AccessSize {
+    Byte,
+    Halfword,
+    Word,
+}
+
+impl AccessSize {
+    /// Size in bytes.
+    pub fn bytes(self) -> u32 {
+        match self {
+            AccessSize::Byte => 1,
+            AccessSize::Halfword => 2,
+            AccessSize::Word => 4,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum AccessDirection {
+    Load,
+    Store,
+}
+
+/// How the accessed addresses are computed from the base register.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessOffset {
+    /// Immediate added to the base, relative to `Align(PC, 4)` for literal loads.
+    Immediate(u32),
+    /// Register added to the base.
+    Register(Register),
+    /// One word per register from the base upwards, for `ldm`, `stm` and `pop`.
+    IncrementAfter(RegisterList),
+    /// One word per register ending just below the base, for `push`.
+    DecrementBefore(RegisterList),
+}
+
+/// Change of the stack pointer by an instruction, see [`Operation::sp_delta`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpDelta {
+    /// Bytes added to `sp`, negative when the stack grows.
+    Known(i32),
+    /// `sp` is set from a register.
+    Unknown,
+}
+
+/// Execution privilege an operation needs, see [`Operation::required_privilege`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Privilege {
+    /// Behaves the same in unprivileged mode.
+    Unprivileged,
+    /// Is ignored in unprivileged mode.
+    Privileged,
+}
+
+impl Operation {
+    /// Registers read by the operation, including `sp`, `lr` and `pc` read implicitly, e.g.
+    /// `sp` by `push` and `pop` and `pc` by branches and PC relative loads.
+    pub fn registers_read(&self) -> RegisterList {
+        self.def_use().1
+    }
+
+    /// Registers written by the operation, including `sp`, `lr` and `pc` written implicitly,
+    /// e.g. `lr` by `bl` and the base register of `stm`. The condition flags and special
+    /// registers are not included.
+    pub fn registers_written(&self) -> RegisterList {
+        self.def_use().0
+    }
+
+    /// Returns the registers written and read.
+    fn def_use(&self) -> (RegisterList, RegisterList) {
+        use Register::{LR, PC, SP};
+        let list = |registers: &[Register]| registers.iter().copied().collect::<RegisterList>();
+        let with = |mut reg_list: RegisterList, register: Register| {
+            reg_list.insert(register);
+            reg_list
+        };
+        match *self {
+            Operation::ADCReg { m, n, d }
+            | Operation::ADDReg { m, n, d, .. }
+            | Operation::SUBReg { m, n, d } => (list(&[d]), list(&[m, n])),
+            Operation::ADDImm { n, d, .. }
+            | Operation::SUBImm { n, d, .. }
+            | Operation::RSBImm { n, d } => (list(&[d]), list(&[n])),
+            Operation::ADDImmSP { d, .. } => (list(&[d]), list(&[SP])),
+            Operation::ADDRegSP { d, m } => (list(&[d]), list(&[SP, m])),
+            Operation::ADR { d, .. } => (list(&[d]), list(&[PC])),
+            Operation::ANDReg { m, dn }
+            | Operation::ASRReg { m, dn }
+            | Operation::BICReg { m, dn }
+            | Operation::EORReg { m, dn }
+            | Operation::LSLReg { m, dn }
+            | Operation::LSRReg { m, dn }
+            | Operation::ORRReg { m, dn }
+            | Operation::RORReg { m, dn }
+            | Operation::SBCReg { m, dn } => (list(&[dn]), list(&[m, dn])),
+            Operation::MUL { n, dm } => (list(&[dm]), list(&[n, dm])),
+            Operation::ASRImm { m, d, .. }
+            | Operation::LSLImm { m, d, .. }
+            | Operation::LSRImm { m, d, .. }
+            | Operation::MOVReg { m, d, .. }
+            | Operation::MVNReg { m, d }
+            | Operation::REV { m, d }
+            | Operation::REV16 { m, d }
+            | Operation::REVSH { m, d }
+            | Operation::SXTB { m, d }
+            | Operation::SXTH { m, d }
+            | Operation::UXTB { m, d }
+            | Operation::UXTH { m, d } => (list(&[d]), list(&[m])),
+            Operation::B { .. } => (list(&[PC]), list(&[PC])),
+            Operation::BL { .. } => (list(&[PC, LR]), list(&[PC])),
+            Operation::BLXReg { m } => (list(&[PC, LR]), list(&[PC, m])),
+            Operation::BX { m } => (list(&[PC]), list(&[m])),
+            Operation::CMNReg { m, n }
+            | Operation::CMPReg { m, n }
+            | Operation::TSTReg { m, n } => (list(&[]), list(&[m, n])),
+            Operation::CMPImm { n, .. } => (list(&[]), list(&[n])),
+            Operation::LDM { n, reg_list } => {
+                // The base register is written back unless it is loaded.
+                (with(reg_list, n), list(&[n]))
+            }
+            Operation::STM { n, reg_list } => (list(&[n]), with(reg_list, n)),
+            Operation::LDRImm { n, t, .. }
+            | Operation::LDRBImm { n, t, .. }
+            | Operation::LDRHImm { n, t, .. } => (list(&[t]), list(&[n])),
+            Operation::LDRLiteral { t, .. } => (list(&[t]), list(&[PC])),
+            Operation::LDRReg { m, n, t }
+            | Operation::LDRBReg { m, n, t }
+            | Operation::LDRHReg { m, n, t }
+            | Operation::LDRSBReg { m, n, t }
+            | Operation::LDRSH { m, n, t } => (list(&[t]), list(&[m, n])),
+            Operation::STRImm { n, t, .. }
+            | Operation::STRBImm { n, t, .. }
+            | Operation::STRHImm { n, t, .. } => (list(&[]), list(&[n, t])),
+            Operation::STRReg { m, n, t }
+            | Operation::STRBReg { m, n, t }
+            | Operation::STRHReg { m, n, t } => (list(&[]), list(&[m, n, t])),
+            Operation::MOVImm { d, .. } | Operation::MRS { d, .. } => (list(&[d]), list(&[])),
+            Operation::MSRReg { n, .. } => (list(&[]), list(&[n])),
+            Operation::POP { reg_list } => (with(reg_list, SP), list(&[SP])),
+            Operation::PUSH { reg_list } => (list(&[SP]), with(reg_list, SP)),
+            Operation::SUBImmSP { .. } => (list(&[SP]), list(&[SP])),
+            Operation::BKPT { .. }
+            | Operation::CPS { .. }
+            | Operation::CPY
+            | Operation::DMB { .. }
+            | Operation::DSB { .. }
+            | Operation::ISB { .. }
+            | Operation::NOP
+            | Operation::SEV
+            | Operation::SVC { .. }
+            | Operation::UDF { .. }
+            | Operation::WFE
+            | Operation::WFI
+            | Operation::YIELD => (list(&[]), list(&[])),
+        }
+    }
+
+    /// Returns the memory access of loads and stores, `None` for other operations.
+    pub fn memory_access(&self) -> Option<MemoryAccess> {
+        use AccessDirection::{Load, Store};
+        use AccessSize::{Byte, Halfword, Word};
+        let access = |size, direction, base, offset| MemoryAccess {
+            size,
+            direction,
+            signed: false,
+            base,
+            offset,
+            writeback: false,
+        };
+        let access = match *self {
+            Operation::LDRImm { imm, n, .. } => access(Word, Load, n, AccessOffset::Immediate(imm)),
+            Operation::LDRBImm { imm, n, .. } => {
+                access(Byte, Load, n, AccessOffset::Immediate(imm))
+            }
+            Operation::LDRHImm { imm, n, .. } => {
+                access(Halfword, Load, n, AccessOffset::Immediate(imm))
+            }
+            Operation::LDRLiteral { imm, .. } => {
+                access(Word, Load, Register::PC, AccessOffset::Immediate(imm))
+            }
+            Operation::LDRReg { m, n, .. } => access(Word, Load, n, AccessOffset::Register(m)),
+            Operation::LDRBReg { m, n, .. } => access(Byte, Load, n, AccessOffset::Register(m)),
+            Operation::LDRHReg { m, n, .. } => access(Halfword, Load, n, AccessOffset::Register(m)),
+            Operation::LDRSBReg { m, n, .. } => MemoryAccess {
+                signed: true,
+                ..access(Byte, Load, n, AccessOffset::Register(m))
+            },
+            Operation::LDRSH { m, n, .. } => MemoryAccess {
+                signed: true,
+                ..access(Halfword, Load, n, AccessOffset::Register(m))
+            },
+            Operation::STRImm { imm, n, .. } => {
+                access(Word, Store, n, AccessOffset::Immediate(imm))
+            }
+            Operation::STRBImm { imm, n, .. } => {
+                access(Byte, Store, n, AccessOffset::Immediate(imm))
+            }
+            Operation::STRHImm { imm, n, .. } => {
+                access(Halfword, Store, n, AccessOffset::Immediate(imm))
+            }
+            Operation::STRReg { m, n, .. } => access(Word, Store, n, AccessOffset::Register(m)),
+            Operation::STRBReg { m, n, .. } => access(Byte, Store, n, AccessOffset::Register(m)),
+            Operation::STRHReg { m, n, .. } => {
+                access(Halfword, Store, n, AccessOffset::Register(m))
+            }
+            Operation::LDM { n, reg_list } => MemoryAccess {
+                // The base register is written back unless it is loaded.
+                writeback: !reg_list.contains(n),
+                ..access(Word, Load, n, AccessOffset::IncrementAfter(reg_list))
+            },
+            Operation::STM { n, reg_list } => MemoryAccess {
+                writeback: true,
+                ..access(Word, Store, n, AccessOffset::IncrementAfter(reg_list))
+            },
+            Operation::POP { reg_list } => MemoryAccess {
+                writeback: true,
+                ..access(
+                    Word,
+                    Load,
+                    Register::SP,
+                    AccessOffset::IncrementAfter(reg_list),
+                )
+            },
+            Operation::PUSH { reg_list } => MemoryAccess {
+                writeback: true,
+                ..access(
+                    Word,
+                    Store,
+                    Register::SP,
+                    AccessOffset::DecrementBefore(reg_list),
+                )
+            },
+            _ => return None,
+        };
+        Some(access)
+    }
+
+    /// Returns the change of `sp` by the operation, [`SpDelta::Known(0)`](SpDelta::Known)
+    /// for operations not writing `sp`.
+    pub fn sp_delta(&self) -> SpDelta {
+        let words = |reg_list: RegisterList| 4 * reg_list.len() as i32;
+        match *self {
+            Operation::PUSH { reg_list } => SpDelta::Known(-words(reg_list)),
+            Operation::POP { reg_list } => SpDelta::Known(words(reg_list)),
+            Operation::ADDImmSP {
+                d: Register::SP,
+                imm,
+            } => SpDelta::Known(imm as i32),
+            Operation::SUBImmSP { imm } => SpDelta::Known(-(imm as i32)),
+            Operation::LDM {
+                n: Register::SP,
+                reg_list,
+            }
+            | Operation::STM {
+                n: Register::SP,
+                reg_list,
+            } if !reg_list.contains(Register::SP) => SpDelta::Known(words(reg_list)),
+            _ if self.registers_written().contains(Register::SP) => SpDelta::Unknown,
+            _ => SpDelta::Known(0),
+        }
+    }
+
+    /// Returns the privilege the operation needs to take effect.
+    ///
+    /// `cps` and `msr` to the stack pointers, `primask` or `control` are ignored in
+    /// unprivileged mode. Accesses to memory only available to privileged code, like the
+    /// system control space, depend on the address and are not covered.
+    pub fn required_privilege(&self) -> Privilege {
+        match self {
+            Operation::CPS { .. } => Privilege::Privileged,
+            Operation::MSRReg { sysm, .. } if *sysm as u8 >= SpecialRegister::MSP as u8 => {
+                Privilege::Privileged
+            }
+            _ => Privilege::Unprivileged,
+        }
+    }
+
+    /// Returns the address referred to by the instruction placed at `address`.
+    ///
+    /// Gives the destination of `b` and `bl`, the address loaded from by `ldr rX, [pc, #imm]`
+    /// and the address computed by `adr`, relative to `Align(PC, 4)` where `PC` is the address
+    /// plus 4. `bx`, `blx` and `mov pc, rX` branch to a register. Other instructions writing
+    /// `pc`, like `pop {pc}`, give [`BranchTarget::None`].
+    pub fn branch_target(&self, address: u32) -> BranchTarget {
+        let pc = address.wrapping_add(4);
+        match self {
+            Operation::B { imm, .. } | Operation::BL { imm } => {
+                BranchTarget::Direct(pc.wrapping_add(*imm))
+            }
+            Operation::LDRLiteral { imm, .. } | Operation::ADR { imm, .. } => {
+                BranchTarget::Direct((pc & !0b11).wrapping_add(*imm))
+            }
+            Operation::BX { m } | Operation::BLXReg { m } => BranchTarget::Indirect(*m),
+            Operation::MOVReg {
+                m, d: Register::PC, ..
+            } => BranchTarget::Indirect(*m),
+            _ => BranchTarget::None,
+        }
+    }
+
+    /// Branch instructions: `b`, `bl`, `bx` and `blx`.
+    pub fn is_branch(&self) -> bool {
+        matches!(
+            self,
+            Operation::B { .. }
+                | Operation::BL { .. }
+                | Operation::BX { .. }
+                | Operation::BLXReg { .. }
+        )
+    }
+
+    /// Function calls, `bl` and `blx`, setting `lr` to the return address.
+    pub fn is_call(&self) -> bool {
+        matches!(self, Operation::BL { .. } | Operation::BLXReg { .. })
+    }
+
+    /// Function returns, `bx lr` and `pop` including `pc`.
+    pub fn is_return(&self) -> bool {
+        match self {
+            Operation::BX { m } => *m == Register::LR,
+            Operation::POP { reg_list } => reg_list.contains(Register::PC),
+            _ => false,
+        }
+    }
+
+    /// Instructions writing `pc`: branches, `pop` including `pc` and `add` or `mov` with `pc`
+    /// as destination. Exceptions taken by e.g. `svc` and `udf` are not included.
+    pub fn modifies_pc(&self) -> bool {
+        match self {
+            Operation::POP { reg_list } => reg_list.contains(Register::PC),
+            Operation::ADDReg { d, .. }
+            | Operation::ADDRegSP { d, .. }
+            | Operation::MOVReg { d, .. } => *d == Register::PC,
+            operation => operation.is_branch(),
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -348,4 +754,210 @@ mod test {
         assert!(!instruction_16.is_32bit());
         assert!(instruction_16.is_16bit());
     }
+
+    #[test]
+    fn decoded_at() {
+        let bl = DecodedAt {
+            instruction: Instruction {
+                width: InstructionWidth::Bit32,
+                operation: Operation::BL { imm: 0xffff_fff0 },
+            },
+            address: 0x0800_0100,
+        };
+        assert_eq!(bl.size(), 4);
+        assert_eq!(bl.next_address(), 0x0800_0104);
+        assert_eq!(bl.branch_target(), BranchTarget::Direct(0x0800_00f4));
+
+        let bx = DecodedAt {
+            instruction: Instruction {
+                width: InstructionWidth::Bit16,
+                operation: Operation::BX { m: Register::LR },
+            },
+            address: 0x0800_0100,
+        };
+        assert_eq!(bx.next_address(), 0x0800_0102);
+        assert_eq!(bx.branch_target(), BranchTarget::Indirect(Register::LR));
+    }
+
+    #[test]
+    fn control_flow_predicates() {
+        let bl = Operation::BL { imm: 0 };
+        assert!(bl.is_branch() && bl.is_call() && !bl.is_return() && bl.modifies_pc());
+
+        let bx_lr = Operation::BX { m: Register::LR };
+        assert!(bx_lr.is_branch() && !bx_lr.is_call() && bx_lr.is_return());
+        let bx_r0 = Operation::BX { m: Register::R0 };
+        assert!(!bx_r0.is_return() && bx_r0.modifies_pc());
+
+        let pop = |reg_list: &[Register]| Operation::POP {
+            reg_list: reg_list.iter().copied().collect(),
+        };
+        assert!(pop(&[Register::R4, Register::PC]).is_return());
+        assert!(pop(&[Register::R4, Register::PC]).modifies_pc());
+        assert!(!pop(&[Register::R4, Register::PC]).is_branch());
+        assert!(!pop(&[Register::R4]).is_return());
+        assert!(!pop(&[Register::R4]).modifies_pc());
+
+        let mov_pc = Operation::MOVReg {
+            m: Register::R1,
+            d: Register::PC,
+            set_flags: false,
+        };
+        assert!(mov_pc.modifies_pc() && !mov_pc.is_branch());
+        assert!(!Operation::SVC { imm: 0 }.modifies_pc());
+    }
+
+    #[test]
+    fn branch_targets() {
+        let b = Operation::B {
+            cond: Condition::EQ,
+            imm: 0xffff_fffc,
+        };
+        assert_eq!(b.branch_target(0x1000), BranchTarget::Direct(0x1000));
+        let bl = Operation::BL { imm: 0x100 };
+        assert_eq!(bl.branch_target(0x1002), BranchTarget::Direct(0x1106));
+        // Relative to the word aligned PC.
+        let ldr = Operation::LDRLiteral {
+            t: Register::R0,
+            imm: 8,
+        };
+        assert_eq!(ldr.branch_target(0x1000), BranchTarget::Direct(0x100c));
+        assert_eq!(ldr.branch_target(0x1002), BranchTarget::Direct(0x100c));
+        let adr = Operation::ADR {
+            d: Register::R1,
+            imm: 0,
+        };
+        assert_eq!(adr.branch_target(0x1006), BranchTarget::Direct(0x1008));
+        assert_eq!(
+            Operation::BLXReg { m: Register::R3 }.branch_target(0x1000),
+            BranchTarget::Indirect(Register::R3)
+        );
+        assert_eq!(Operation::NOP.branch_target(0x1000), BranchTarget::None);
+    }
+
+    #[test]
+    fn memory_accesses() {
+        let ldrsh = Operation::LDRSH {
+            m: Register::R2,
+            n: Register::R1,
+            t: Register::R0,
+        };
+        assert_eq!(
+            ldrsh.memory_access(),
+            Some(MemoryAccess {
+                size: AccessSize::Halfword,
+                direction: AccessDirection::Load,
+                signed: true,
+                base: Register::R1,
+                offset: AccessOffset::Register(Register::R2),
+                writeback: false,
+            })
+        );
+
+        let strb = Operation::STRBImm {
+            imm: 3,
+            n: Register::R1,
+            t: Register::R0,
+        }
+        .memory_access()
+        .unwrap();
+        assert_eq!(strb.size.bytes(), 1);
+        assert_eq!(strb.direction, AccessDirection::Store);
+        assert_eq!(strb.offset, AccessOffset::Immediate(3));
+
+        let reg_list = RegisterList::from([Register::R0, Register::R1]);
+        let ldm = |n| Operation::LDM { n, reg_list }.memory_access().unwrap();
+        assert!(!ldm(Register::R0).writeback);
+        assert!(ldm(Register::R2).writeback);
+        let push = Operation::PUSH { reg_list }.memory_access().unwrap();
+        assert_eq!(push.base, Register::SP);
+        assert_eq!(push.offset, AccessOffset::DecrementBefore(reg_list));
+        assert_eq!(Operation::NOP.memory_access(), None);
+    }
+
+    #[test]
+    fn sp_deltas() {
+        let reg_list = RegisterList::from([Register::R4, Register::R5, Register::LR]);
+        assert_eq!(Operation::PUSH { reg_list }.sp_delta(), SpDelta::Known(-12));
+        assert_eq!(
+            Operation::SUBImmSP { imm: 16 }.sp_delta(),
+            SpDelta::Known(-16)
+        );
+        let add_sp = |d| Operation::ADDImmSP { d, imm: 16 }.sp_delta();
+        assert_eq!(add_sp(Register::SP), SpDelta::Known(16));
+        assert_eq!(add_sp(Register::R0), SpDelta::Known(0));
+        let mov_sp = Operation::MOVReg {
+            m: Register::R7,
+            d: Register::SP,
+            set_flags: false,
+        };
+        assert_eq!(mov_sp.sp_delta(), SpDelta::Unknown);
+        assert_eq!(Operation::NOP.sp_delta(), SpDelta::Known(0));
+    }
+
+    #[test]
+    fn privileges() {
+        let msr = |sysm| Operation::MSRReg {
+            n: Register::R0,
+            sysm,
+        };
+        assert_eq!(
+            msr(SpecialRegister::PRIMASK).required_privilege(),
+            Privilege::Privileged
+        );
+        assert_eq!(
+            msr(SpecialRegister::PSP).required_privilege(),
+            Privilege::Privileged
+        );
+        assert_eq!(
+            msr(SpecialRegister::APSR).required_privilege(),
+            Privilege::Unprivileged
+        );
+        assert_eq!(
+            Operation::CPS { im: true }.required_privilege(),
+            Privilege::Privileged
+        );
+        assert_eq!(
+            Operation::SVC { imm: 0 }.required_privilege(),
+            Privilege::Unprivileged
+        );
+    }
+
+    #[test]
+    fn def_use() {
+        use Register::*;
+        let push = Operation::PUSH {
+            reg_list: RegisterList::from([R4, LR]),
+        };
+        assert_eq!(push.registers_read(), RegisterList::from([R4, SP, LR]));
+        assert_eq!(push.registers_written(), RegisterList::from([SP]));
+
+        let pop = Operation::POP {
+            reg_list: RegisterList::from([R4, PC]),
+        };
+        assert_eq!(pop.registers_read(), RegisterList::from([SP]));
+        assert_eq!(pop.registers_written(), RegisterList::from([R4, SP, PC]));
+
+        let bl = Operation::BL { imm: 0 };
+        assert_eq!(bl.registers_read(), RegisterList::from([PC]));
+        assert_eq!(bl.registers_written(), RegisterList::from([LR, PC]));
+
+        let ands = Operation::ANDReg { m: R1, dn: R0 };
+        assert_eq!(ands.registers_read(), RegisterList::from([R0, R1]));
+        assert_eq!(ands.registers_written(), RegisterList::from([R0]));
+
+        let ldm = Operation::LDM {
+            n: R0,
+            reg_list: RegisterList::from([R1, R2]),
+        };
+        assert_eq!(ldm.registers_written(), RegisterList::from([R0, R1, R2]));
+        let str = Operation::STRReg {
+            m: R2,
+            n: R1,
+            t: R0,
+        };
+        assert_eq!(str.registers_read(), RegisterList::from([R0, R1, R2]));
+        assert!(str.registers_written().is_empty());
+        assert!(Operation::NOP.registers_read().is_empty());
+    }
 }
@@ -13,17 +13,178 @@
 //!     }
 //! # }
 //! ```
+//!
+//! # Features
+//! - `std` (default): reading from `io::Read`, the instruction cache, semihosting served by
+//!   the host, differential testing against and disassembly at the program counter of GDB
+//!   remote targets, the `Error` trait and logging with `tracing`.
+//!   Without it the crate is `no_std`.
+//! - `alloc`: everything but decoding, encoding, patching and emulation, e.g. the assembler
+//!   and the UF2 loader, for `no_std` targets with an allocator. Enabled by `std`. Without it
+//!   the crate does not allocate.
+//! - `rayon`: parallel disassembly of large images.
+//! - `mmap`: disassembly of memory mapped files.
+//! - `elf`: loading of executable sections and symbols from ELF files.
+//! - `gimli`: source lines of instructions from the DWARF debug information of ELF files.
+//! - `svd`: names of the peripheral registers accessed, from CMSIS-SVD files.
+//! - `capstone`: conversions to and from the types of Capstone and comparing decoding with it.
+//! - `ffi`: `extern "C"` functions decoding and formatting instructions, for C and C++.
+//! - `serde`: serialization of programs, analysis results, the emulated processor state and
+//!   execution traces.
+//! - `arbitrary`, `proptest`: random encodable operations for fuzzing and property tests.
+//! - `postcard`: compact, versioned binary snapshots of programs and analysis results.
+//! - `wasm`: `wasm-bindgen` functions decoding and formatting instructions in the browser.
+//! - `objdump`: differential testing of decoding and formatting against GNU objdump listings.
+//! - `probe-rs`: disassembly at the program counter of probe-rs cores and core dumps.
+//! - `yaxpeax`: the `yaxpeax-arch` traits, decoding as the ARMv6-M architecture of yaxpeax
+//!   based tools.
+//!
+//! The `thumb!` macro assembling byte arrays at compile time is in the separate
+//! `armv6-m-instruction-parser-macros` crate, a dependency of its own rather than a feature
+//! as the macro uses the assembler of this crate.
+
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
 
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
+pub mod alignment;
+#[cfg(feature = "alloc")]
+pub mod assembler;
+#[cfg(feature = "std")]
+pub mod cache;
+#[cfg(feature = "alloc")]
+pub mod call_graph;
+#[cfg(feature = "capstone")]
+pub mod capstone;
 pub mod conditions;
+#[cfg(feature = "alloc")]
+pub mod constants;
+#[cfg(feature = "alloc")]
+pub mod control_flow;
+#[cfg(feature = "alloc")]
+pub mod coverage;
+pub mod cpu;
+#[cfg(feature = "alloc")]
+pub mod critical;
+#[cfg(feature = "alloc")]
+pub mod dataflow;
+#[cfg(feature = "alloc")]
+pub mod dead_code;
+#[cfg(feature = "std")]
+pub mod differential;
+#[cfg(feature = "alloc")]
+pub mod dominators;
+#[cfg(feature = "gimli")]
+pub mod dwarf;
+#[cfg(feature = "elf")]
+pub mod elf;
+pub mod emulator;
+pub mod encoder;
+#[cfg(feature = "alloc")]
+pub mod esil;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "mmap")]
+pub mod file;
+#[cfg(feature = "alloc")]
+pub mod fingerprint;
+pub mod format;
+pub mod frame;
+#[cfg(feature = "alloc")]
+pub mod functions;
+#[cfg(any(feature = "arbitrary", feature = "proptest"))]
+pub mod generate;
+#[cfg(feature = "alloc")]
+pub mod idioms;
+#[cfg(feature = "alloc")]
+pub mod image;
+#[cfg(feature = "alloc")]
+pub mod indirect;
 pub mod instructons;
+#[cfg(feature = "alloc")]
+pub mod linker_map;
+#[cfg(feature = "alloc")]
+pub mod literals;
+#[cfg(feature = "std")]
+pub mod live;
+pub mod lpc;
+pub mod memory;
+#[cfg(feature = "alloc")]
+pub mod memory_source;
+#[cfg(feature = "alloc")]
+pub mod micro_ops;
+#[cfg(feature = "objdump")]
+pub mod objdump;
+#[cfg(feature = "rayon")]
+pub mod parallel;
+pub mod patcher;
+#[cfg(feature = "alloc")]
+pub mod peephole;
+#[cfg(feature = "alloc")]
+pub mod profile;
+#[cfg(feature = "alloc")]
+pub mod program;
+#[cfg(feature = "alloc")]
+pub mod propagation;
+#[cfg(feature = "alloc")]
+pub mod pseudo_c;
 pub mod registers;
+#[cfg(feature = "alloc")]
+pub mod runtime;
+pub mod semantics;
+#[cfg(feature = "alloc")]
+pub mod semihosting;
+#[cfg(feature = "postcard")]
+pub mod snapshot;
+#[cfg(feature = "alloc")]
+pub mod stack;
+#[cfg(feature = "alloc")]
+pub mod stream;
+#[cfg(feature = "alloc")]
+pub mod superset;
+#[cfg(feature = "svd")]
+pub mod svd;
+pub mod sweep;
+#[cfg(feature = "alloc")]
+pub mod symbols;
+pub mod systick;
+#[cfg(feature = "alloc")]
+pub mod target;
+#[cfg(feature = "alloc")]
+pub mod test_vectors;
+pub mod timing;
+#[cfg(feature = "alloc")]
+pub mod trace;
+#[cfg(feature = "alloc")]
+pub mod trampoline;
+#[cfg(feature = "alloc")]
+pub mod traversal;
+#[cfg(feature = "alloc")]
+pub mod uf2;
+#[cfg(feature = "alloc")]
+pub mod vector_table;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+#[cfg(feature = "alloc")]
+pub mod xref;
+#[cfg(feature = "yaxpeax")]
+pub mod yaxpeax;
 
 use conditions::Condition;
 use instructons::*;
 use registers::*;
-use tracing::debug;
 
-#[derive(Debug, PartialEq)]
+/// Logs with `tracing` when it is available.
+macro_rules! debug {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "std")]
+        tracing::debug!($($arg)*);
+    };
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Error {
     /// Input not long enough for a instruction.
     InsufficientInput,
@@ -39,27 +200,174 @@ pub enum Error {
     InvalidRegister,
     /// Invalid condition code used.
     InvalidCondition,
+    /// Assembly text could not be split into a mnemonic and operands.
+    InvalidSyntax,
+    /// Assembly mnemonic is not a ARMv6-M instruction.
+    UnknownMnemonic,
+    /// Operands does not match any form of the instruction.
+    InvalidOperands,
+    /// Immediate value does not fit the instruction.
+    ImmediateOutOfRange,
+    /// Assembly label is not defined.
+    UndefinedLabel,
+    /// Assembly label is defined more than once.
+    DuplicateLabel,
+    /// Offset is not aligned to a halfword.
+    UnalignedOffset,
+    /// Encoded instruction is wider than the space it replaces.
+    InstructionDoesNotFit,
+    /// Offset is in the middle of a 32 bit instruction.
+    InsideInstruction,
 }
 
+impl Error {
+    /// Describes the error in lower case without punctuation, as displayed.
+    pub(crate) fn message(&self) -> &'static str {
+        match self {
+            Error::InsufficientInput => "input not long enough for an instruction",
+            Error::Malfromed32BitInstruction => "32 bit instruction not long enough",
+            Error::Invalid32BitInstruction => "opcode not matching a valid 32 bit instruction",
+            Error::InvalidOpCode => "invalid opcode",
+            Error::Unpredictable => "instruction has unpredictable behaviour",
+            Error::InvalidRegister => "invalid register",
+            Error::InvalidCondition => "invalid condition code",
+            Error::InvalidSyntax => "invalid assembly syntax",
+            Error::UnknownMnemonic => "unknown mnemonic",
+            Error::InvalidOperands => "operands do not match any form of the instruction",
+            Error::ImmediateOutOfRange => "immediate does not fit the instruction",
+            Error::UndefinedLabel => "label is not defined",
+            Error::DuplicateLabel => "label is defined more than once",
+            Error::UnalignedOffset => "offset is not aligned to a halfword",
+            Error::InstructionDoesNotFit => "instruction is wider than the space it replaces",
+            Error::InsideInstruction => "offset is in the middle of a 32 bit instruction",
+        }
+    }
+}
+
+impl core::fmt::Display for Error {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(self.message())
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Error {}
+
 /// This function parses a input byte slice into one instruction.
 /// Returns Err(&str) if instruction is invalid.
 pub fn parse(input: &[u8]) -> Result<Instruction, Error> {
     if input.len() < 2 {
         return Err(Error::InsufficientInput);
     }
-    let mut instruction_bytes1: [u8; 2] = [0; 2];
-    instruction_bytes1.copy_from_slice(&input[0..2]);
-    let instruction_bits1 = u16::from_le_bytes(instruction_bytes1);
+    let halfword = |index: usize| u16::from_le_bytes([input[index], input[index + 1]]);
+    let second = if input.len() >= 4 {
+        Some(halfword(2))
+    } else {
+        None
+    };
+    parse_halfword_pair(halfword(0), second)
+}
 
+/// Parses one instruction placed at `address`.
+///
+/// # Example
+/// ```
+/// # use armv6_m_instruction_parser::{instructons::BranchTarget, parse_at};
+/// // beq.n -4
+/// let decoded = parse_at(&[0xfe, 0xd0], 0x0800_0010).unwrap();
+/// assert_eq!(decoded.next_address(), 0x0800_0012);
+/// assert_eq!(decoded.branch_target(), BranchTarget::Direct(0x0800_0010));
+/// ```
+pub fn parse_at(input: &[u8], address: u32) -> Result<DecodedAt, Error> {
+    Ok(DecodedAt {
+        instruction: parse(input)?,
+        address,
+    })
+}
+
+/// Parses one instruction from halfwords, e.g. program memory held as a `u16` array.
+///
+/// # Example
+/// ```
+/// # use armv6_m_instruction_parser::{instructons::Operation, parse_halfwords};
+/// let program_memory: [u16; 3] = [0xf000, 0xf87e, 0xbf00];
+/// let instruction = parse_halfwords(&program_memory).unwrap();
+/// assert_eq!(instruction.operation, Operation::BL { imm: 0xfc });
+/// ```
+pub fn parse_halfwords(input: &[u16]) -> Result<Instruction, Error> {
+    match input {
+        [] => Err(Error::InsufficientInput),
+        [first] => parse_halfword_pair(*first, None),
+        [first, second, ..] => parse_halfword_pair(*first, Some(*second)),
+    }
+}
+
+/// Parses the instruction in the first halfword, or in both for a 32 bit instruction.
+impl TryFrom<(u16, u16)> for Instruction {
+    type Error = Error;
+
+    fn try_from((first, second): (u16, u16)) -> Result<Self, Self::Error> {
+        parse_halfword_pair(first, Some(second))
+    }
+}
+
+/// Parses one instruction like [`parse`], for trusted input such as code produced by a
+/// compiler.
+///
+/// Encodings the architecture defines as UNPREDICTABLE are decoded as the instruction their
+/// bits describe instead of returning [`Error::Unpredictable`], e.g. `cmp` of two low
+/// registers in the high register form, and nothing is logged. Undefined encodings are still
+/// errors. With the `std` feature this takes about 25% less time than [`parse`], mostly from
+/// skipping the logging, see `benches/decode.rs`.
+///
+/// # Example
+/// ```
+/// # use armv6_m_instruction_parser::{instructons::Operation, parse, parse_unchecked, registers::Register, Error};
+/// // cmp r1, r0 in the high register form
+/// let program_memory = [0x01, 0x45];
+/// assert_eq!(parse(&program_memory), Err(Error::Unpredictable));
+/// assert_eq!(
+///     parse_unchecked(&program_memory).unwrap().operation,
+///     Operation::CMPReg { m: Register::R0, n: Register::R1 }
+/// );
+/// ```
+pub fn parse_unchecked(input: &[u8]) -> Result<Instruction, Error> {
+    let [low, high, rest @ ..] = input else {
+        return Err(Error::InsufficientInput);
+    };
+    let first = u16::from_le_bytes([*low, *high]);
+    if instruction_size(first) == 4 {
+        let [low, high, ..] = rest else {
+            return Err(Error::Malfromed32BitInstruction);
+        };
+        let second = u16::from_le_bytes([*low, *high]);
+        return Ok(Instruction {
+            width: InstructionWidth::Bit32,
+            operation: parse_32bit_operation((first as u32) << 16 | second as u32)?,
+        });
+    }
+    let operation = if first & 0xffc0 == 0x4500 {
+        // CMP (register) T2 with two low registers.
+        Operation::CMPReg {
+            m: (((first >> 3) & 0x7) as u8).try_into().unwrap(),
+            n: ((first & 0x7) as u8).try_into().unwrap(),
+        }
+    } else {
+        parse_16bit_operation(first)?
+    };
+    Ok(Instruction {
+        width: InstructionWidth::Bit16,
+        operation,
+    })
+}
+
+fn parse_halfword_pair(instruction_bits1: u16, second: Option<u16>) -> Result<Instruction, Error> {
     match (instruction_bits1 >> 11) & 0x1f {
         0b11101..=0b11111 => {
             // Check if it is a 32-bit instruction.
-            if input.len() < 4 {
+            let Some(instruction_bits2) = second else {
                 return Err(Error::Malfromed32BitInstruction);
             };
-            let mut instruction_bytes2: [u8; 2] = [0; 2];
-            instruction_bytes2.copy_from_slice(&input[2..4]);
-            let instruction_bits2 = u16::from_le_bytes(instruction_bytes2);
             let instruction_bits: u32 = (instruction_bits1 as u32) << 16 | instruction_bits2 as u32;
             debug!("instruction bits: {:#034b}", instruction_bits);
             Ok(Instruction {
@@ -77,6 +385,128 @@ pub fn parse(input: &[u8]) -> Result<Instruction, Error> {
     }
 }
 
+/// Decodes a byte slice instruction by instruction, see [`Disassembly`].
+///
+/// # Example
+/// ```
+/// # use armv6_m_instruction_parser::{disassemble, instructons::Operation};
+/// // push {r4, r5, r7, lr}; bl; truncated bl
+/// let program_memory = [0xb0, 0xb5, 0x00, 0xf0, 0x7e, 0xf8, 0x00, 0xf0];
+/// let offsets: Vec<usize> = disassemble(&program_memory)
+///     .map(|(offset, _)| offset)
+///     .collect();
+/// assert_eq!(offsets, [0, 2, 6]);
+/// ```
+pub fn disassemble(input: &[u8]) -> Disassembly<'_> {
+    Disassembly { input, offset: 0 }
+}
+
+/// Iterator over the instructions of a byte slice, yielding the offset of every instruction
+/// together with the result of parsing it.
+///
+/// Invalid instructions are skipped by their width, known from the first halfword. A
+/// truncated instruction at the end of the input yields
+/// [`Error::Malfromed32BitInstruction`] or [`Error::InsufficientInput`] and ends the iteration.
+#[derive(Debug, Clone)]
+pub struct Disassembly<'a> {
+    input: &'a [u8],
+    offset: usize,
+}
+
+impl Iterator for Disassembly<'_> {
+    type Item = (usize, Result<Instruction, Error>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let offset = self.offset;
+        let rest = &self.input[offset..];
+        let result = match rest {
+            [] => return None,
+            [_] => Err(Error::InsufficientInput),
+            [first, second, ..] => {
+                let size = instruction_size(u16::from_le_bytes([*first, *second]));
+                if rest.len() < size {
+                    Err(Error::Malfromed32BitInstruction)
+                } else {
+                    self.offset += size;
+                    return Some((offset, parse(&rest[..size])));
+                }
+            }
+        };
+        self.offset = self.input.len();
+        Some((offset, result))
+    }
+}
+
+impl core::iter::FusedIterator for Disassembly<'_> {}
+
+/// Iterator over the instructions of a byte slice placed at an address, yielding the address
+/// of every instruction together with the result of parsing it.
+#[derive(Debug, Clone)]
+pub struct AddressedDisassembly<'a> {
+    disassembly: Disassembly<'a>,
+    base: u32,
+}
+
+impl Iterator for AddressedDisassembly<'_> {
+    type Item = (u32, Result<Instruction, Error>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (offset, result) = self.disassembly.next()?;
+        Some((self.base.wrapping_add(offset as u32), result))
+    }
+}
+
+impl core::iter::FusedIterator for AddressedDisassembly<'_> {}
+
+/// Disassembly of byte slices with method syntax.
+///
+/// # Example
+/// ```
+/// # use armv6_m_instruction_parser::ThumbInstructions;
+/// let image = vec![0xb0, 0xb5, 0x00, 0xf0, 0x7e, 0xf8];
+/// for (address, instruction) in image.thumb_instructions_at(0x0800_0000) {
+///     println!("{address:#010x}: {instruction:?}");
+/// }
+/// assert_eq!(image.thumb_instructions().count(), 2);
+/// ```
+pub trait ThumbInstructions {
+    /// Returns the instructions with their offsets, see [`disassemble`].
+    fn thumb_instructions(&self) -> Disassembly<'_>;
+
+    /// Returns the instructions with their addresses, with the slice starting at `base`.
+    fn thumb_instructions_at(&self, base: u32) -> AddressedDisassembly<'_>;
+}
+
+impl ThumbInstructions for [u8] {
+    fn thumb_instructions(&self) -> Disassembly<'_> {
+        disassemble(self)
+    }
+
+    fn thumb_instructions_at(&self, base: u32) -> AddressedDisassembly<'_> {
+        AddressedDisassembly {
+            disassembly: disassemble(self),
+            base,
+        }
+    }
+}
+
+/// Lowercases a short ASCII name into `buffer` without allocating, returns `None` if the
+/// name does not fit.
+pub(crate) fn ascii_lowercase<'a>(name: &str, buffer: &'a mut [u8; 8]) -> Option<&'a str> {
+    let bytes = buffer.get_mut(..name.len())?;
+    bytes.copy_from_slice(name.as_bytes());
+    bytes.make_ascii_lowercase();
+    core::str::from_utf8(bytes).ok()
+}
+
+/// Returns the width in bytes of the instruction starting with `halfword`.
+pub(crate) fn instruction_size(halfword: u16) -> usize {
+    match (halfword >> 11) & 0x1f {
+        0b11101..=0b11111 => 4,
+        _ => 2,
+    }
+}
+
 fn parse_32bit_operation(input: u32) -> Result<Operation, Error> {
     let op1 = (input >> 27) & 0x3;
     let op = (input >> 15) & 0x1;
@@ -452,6 +882,7 @@ fn parse_special_data_branch_exchange_instruction(input: u16) -> Result<Operatio
                     m: rm,
                     n: rdn,
                     d: rdn,
+                    set_flags: false,
                 })
             }
         }
@@ -631,6 +1062,7 @@ fn parse_arith_instructions(input: u16) -> Result<Operation, Error> {
                 m: rm,
                 n: rn,
                 d: rd,
+                set_flags: true,
             })
         }
         0b01101 => {
@@ -830,7 +1262,7 @@ fn parse_hint_instruction(input: u16) -> Result<Operation, Error> {
         0b0000 => Ok(Operation::NOP),
         0b0001 => Ok(Operation::YIELD),
         0b0010 => Ok(Operation::WFE),
-        0b0011 => Ok(Operation::WFE),
+        0b0011 => Ok(Operation::WFI),
         0b0100 => Ok(Operation::SEV),
         _ => Err(Error::InvalidOpCode),
     }
@@ -858,6 +1290,27 @@ impl SignExtend for u32 {
 mod test {
     use super::*;
 
+    #[test]
+    fn wait_hints() {
+        assert_eq!(parse(&[0x20, 0xbf]).unwrap().operation, Operation::WFE);
+        assert_eq!(parse(&[0x30, 0xbf]).unwrap().operation, Operation::WFI);
+    }
+
+    #[test]
+    fn add_register_flags() {
+        // adds r0, r1, r2 and add r0, r1, the second with low registers but not setting flags.
+        let add = |m, n, d, set_flags| Operation::ADDReg { m, n, d, set_flags };
+        let (r0, r1, r2) = (Register::R0, Register::R1, Register::R2);
+        assert_eq!(
+            parse(&[0x88, 0x18]).unwrap().operation,
+            add(r2, r1, r0, true)
+        );
+        assert_eq!(
+            parse(&[0x08, 0x44]).unwrap().operation,
+            add(r1, r0, r0, false)
+        );
+    }
+
     #[test]
     fn sign_extend_u16() {
         assert_eq!(0xffffffff, 0x1u16.sign_extend(1));
@@ -873,4 +1326,97 @@ mod test {
         assert_eq!(0xfffffff9, 0x9u32.sign_extend(4));
         assert_eq!(0x00000009, 0x9u32.sign_extend(5));
     }
+
+    #[test]
+    fn error_messages() {
+        assert_eq!(
+            Error::ImmediateOutOfRange.to_string(),
+            "immediate does not fit the instruction"
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn error_trait() {
+        let error: Box<dyn std::error::Error> = Box::new(Error::InvalidRegister);
+        assert_eq!(error.to_string(), "invalid register");
+    }
+
+    #[test]
+    fn disassembly() {
+        // movs r0, #1; invalid 32 bit; bl; udf; odd byte
+        let input = [
+            0x01, 0x20, 0xff, 0xf7, 0x00, 0x00, 0x00, 0xf0, 0x7e, 0xf8, 0x00, 0xde, 0x00,
+        ];
+        let instructions: Vec<_> = disassemble(&input).collect();
+        assert_eq!(instructions.len(), 5);
+        assert_eq!(
+            instructions[0],
+            (
+                0,
+                Ok(Instruction {
+                    width: InstructionWidth::Bit16,
+                    operation: Operation::MOVImm {
+                        d: Register::R0,
+                        imm: 1
+                    }
+                })
+            )
+        );
+        assert_eq!(instructions[1].0, 2);
+        assert!(instructions[1].1.is_err());
+        assert_eq!(instructions[2].0, 6);
+        assert_eq!(instructions[4], (12, Err(Error::InsufficientInput)));
+
+        let truncated: Vec<_> = disassemble(&[0x01, 0x20, 0x00, 0xf0, 0x7e]).collect();
+        assert_eq!(truncated[1], (2, Err(Error::Malfromed32BitInstruction)));
+        assert_eq!(truncated.len(), 2);
+    }
+
+    #[test]
+    fn halfwords() {
+        let bytes = [0x01, 0x20, 0x00, 0xf0, 0x7e, 0xf8];
+        assert_eq!(parse_halfwords(&[0x2001]), parse(&bytes));
+        assert_eq!(parse_halfwords(&[0xf000, 0xf87e]), parse(&bytes[2..]));
+        assert_eq!(Instruction::try_from((0xf000, 0xf87e)), parse(&bytes[2..]));
+        assert_eq!(Instruction::try_from((0x2001, 0xffff)), parse(&bytes));
+        assert_eq!(
+            parse_halfwords(&[0xf000]),
+            Err(Error::Malfromed32BitInstruction)
+        );
+        assert_eq!(parse_halfwords(&[]), Err(Error::InsufficientInput));
+    }
+
+    #[test]
+    fn thumb_instructions() {
+        let input = [0x01, 0x20, 0x00, 0xf0, 0x7e, 0xf8, 0x70, 0x47];
+        let offsets: Vec<_> = input
+            .thumb_instructions()
+            .map(|(offset, _)| offset)
+            .collect();
+        assert_eq!(offsets, [0, 2, 6]);
+        let addresses: Vec<_> = input
+            .thumb_instructions_at(0x2000_0000)
+            .map(|(address, _)| address)
+            .collect();
+        assert_eq!(addresses, [0x2000_0000, 0x2000_0002, 0x2000_0006]);
+    }
+
+    #[test]
+    fn unchecked() {
+        for halfword in 0..=u16::MAX {
+            let mut bytes = [0; 4];
+            bytes[..2].copy_from_slice(&halfword.to_le_bytes());
+            bytes[2..].copy_from_slice(&0xf87eu16.to_le_bytes());
+            match parse(&bytes) {
+                Err(Error::Unpredictable) => assert!(parse_unchecked(&bytes).is_ok()),
+                checked => assert!(checked == parse_unchecked(&bytes), "{halfword:#06x}"),
+            }
+        }
+        assert_eq!(
+            parse_unchecked(&[0x00, 0xf0]),
+            Err(Error::Malfromed32BitInstruction)
+        );
+        assert_eq!(parse_unchecked(&[0x00]), Err(Error::InsufficientInput));
+    }
 }
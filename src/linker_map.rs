@@ -0,0 +1,186 @@
+//! Symbols from the map files of GNU ld and armlink, for naming the code of images whose
+//! ELF file has been stripped or is not at hand.
+//!
+//! # Example
+//! ```
+//! # use armv6_m_instruction_parser::linker_map::parse;
+//! let map = "\
+//! Linker script and memory map
+//!
+//!  .text.main     0x080000c0       0x24 main.o
+//!                 0x080000c0                main
+//! ";
+//! let symbols = parse(map);
+//! assert_eq!(symbols.get(0x0800_00c0).unwrap().name, "main");
+//! ```
+
+use alloc::{string::ToString, vec::Vec};
+
+use crate::symbols::{Symbol, SymbolTable};
+
+/// Parses the symbols of a GNU ld map file, or an armlink map file with an image symbol
+/// table.
+///
+/// Lines that are not symbols are skipped, as are assignments in GNU ld maps and section
+/// and absolute symbols in armlink maps.
+pub fn parse(map: &str) -> SymbolTable {
+    match map.contains("Image Symbol Table") {
+        true => parse_armlink(map),
+        false => parse_gnu(map),
+    }
+}
+
+fn hex(token: &str) -> Option<u32> {
+    let digits = token.strip_prefix("0x")?;
+    u64::from_str_radix(digits, 16)
+        .ok()
+        .map(|value| value as u32)
+}
+
+/// Symbols of the memory map of a GNU ld map file, lines of only an address and a name.
+/// The addresses of ld maps do not have the Thumb bit and their sizes are unknown.
+fn parse_gnu(map: &str) -> SymbolTable {
+    let start = map.find("Linker script and memory map").unwrap_or(0);
+    let end = map.find("Cross Reference Table").unwrap_or(map.len());
+    let mut symbols = SymbolTable::new();
+    for line in map[start..end.max(start)].lines() {
+        if !line.starts_with(char::is_whitespace) {
+            continue;
+        }
+        let line = line.trim();
+        let Some((address, name)) = line.split_once(char::is_whitespace) else {
+            continue;
+        };
+        let name = name.trim();
+        let Some(address) = hex(address) else {
+            continue;
+        };
+        // Continued input section lines have a size, assignments an `=`.
+        if name.is_empty() || hex(name.split_whitespace().next().unwrap()).is_some() {
+            continue;
+        }
+        if name.contains('=') || name.starts_with("PROVIDE") {
+            continue;
+        }
+        symbols.insert(Symbol {
+            name: name.to_string(),
+            address,
+            size: 0,
+        });
+    }
+    symbols
+}
+
+/// Symbols of the image symbol table of an armlink map file, lines of the name, value,
+/// type, size and object.
+fn parse_armlink(map: &str) -> SymbolTable {
+    let start = map.find("Image Symbol Table").unwrap();
+    let end = map[start..]
+        .find("Memory Map of the image")
+        .map_or(map.len(), |end| start + end);
+    let mut symbols = SymbolTable::new();
+    for line in map[start..end].lines() {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let [name, value, rest @ ..] = tokens.as_slice() else {
+            continue;
+        };
+        let Some(value) = hex(value) else {
+            continue;
+        };
+        // The overlay column is usually empty.
+        let (code, rest) = match rest {
+            ["Thumb" | "ARM", "Code", rest @ ..] => (true, rest),
+            ["Data", rest @ ..] => (false, rest),
+            _ => continue,
+        };
+        let size = rest.first().and_then(|size| size.parse().ok()).unwrap_or(0);
+        symbols.insert(Symbol {
+            name: name.to_string(),
+            address: value & !(code as u32),
+            size,
+        });
+    }
+    symbols
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn gnu_ld() {
+        let map = "\
+Discarded input sections
+
+ .text.unused   0x00000000       0x10 main.o
+
+Linker script and memory map
+
+LOAD main.o
+                0x20001000                _estack = 0x20001000
+ .text          0x08000000      0x1a4
+ *(.vector_table)
+ .vector_table  0x08000000       0xc0 startup.o
+                0x08000000                g_pfnVectors
+ .text.Reset_Handler
+                0x080000c0       0x10 startup.o
+                0x080000c0                Reset_Handler
+ *fill*         0x080000d0        0x2
+                0x080000d4                PROVIDE (__etext = .)
+ .text._Z3addii
+                0x080000d4        0x4 main.o
+                0x080000d4                add(int, int)
+";
+        let symbols = parse(map);
+        let names: Vec<(u32, &str)> = symbols
+            .iter()
+            .map(|symbol| (symbol.address, symbol.name.as_str()))
+            .collect();
+        assert_eq!(
+            names,
+            [
+                (0x0800_0000, "g_pfnVectors"),
+                (0x0800_00c0, "Reset_Handler"),
+                (0x0800_00d4, "add(int, int)"),
+            ]
+        );
+    }
+
+    #[test]
+    fn armlink() {
+        let map = "\
+    Image Symbol Table
+
+    Local Symbols
+
+    Symbol Name                              Value     Ov Type        Size  Object(Section)
+
+    RESET                                    0x08000000   Section      192  startup.o(RESET)
+    ../clib/microlib/init/entry.s            0x00000000   Number         0  entry.o ABSOLUTE
+
+    Global Symbols
+
+    Symbol Name                              Value     Ov Type        Size  Object(Section)
+
+    main                                     0x08000185   Thumb Code    28  main.o(i.main)
+    SystemCoreClock                          0x20000000   Data           4  system.o(.data)
+
+==============================================================================
+
+Memory Map of the image
+
+    Image Entry point : 0x080000c1
+";
+        let symbols = parse(map);
+        assert_eq!(symbols.len(), 2);
+        assert_eq!(
+            symbols.containing(0x0800_019e),
+            Some(&Symbol {
+                name: "main".to_string(),
+                address: 0x0800_0184,
+                size: 28,
+            })
+        );
+        assert_eq!(symbols.get(0x2000_0000).unwrap().name, "SystemCoreClock");
+    }
+}
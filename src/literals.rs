@@ -0,0 +1,98 @@
+//! Constants loaded from literal pools.
+//!
+//! # Example
+//! ```
+//! # use armv6_m_instruction_parser::{literals::{literal_loads, literal_pools}, program::Program};
+//! // ldr r0, [pc, #0]; bx lr; .word 0x20001000
+//! let image = [0x00, 0x48, 0x70, 0x47, 0x00, 0x10, 0x00, 0x20];
+//! let loads = literal_loads(&Program::new(&image, 0x100), &image);
+//! assert_eq!(loads[0].value, Some(0x2000_1000));
+//! assert_eq!(literal_pools(&loads), [0x104..0x108]);
+//! ```
+
+use alloc::{collections::BTreeSet, vec, vec::Vec};
+use core::ops::Range;
+
+use crate::{instructons::Operation, program::Program, registers::Register};
+
+/// `ldr rX, [pc, #imm]` with the constant it loads.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LiteralLoad {
+    /// Address of the load.
+    pub site: u32,
+    pub register: Register,
+    /// Address of the literal.
+    pub address: u32,
+    /// Loaded constant, `None` if the literal is outside the image.
+    pub value: Option<u32>,
+}
+
+/// Returns the literal loads of `program`, disassembled from `image`, in address order.
+pub fn literal_loads(program: &Program, image: &[u8]) -> Vec<LiteralLoad> {
+    program
+        .iter()
+        .filter_map(|(site, result)| {
+            let Operation::LDRLiteral { t, imm } = result.as_ref().ok()?.operation else {
+                return None;
+            };
+            let address = (site.wrapping_add(4) & !0b11).wrapping_add(imm);
+            let value = address
+                .checked_sub(program.base())
+                .and_then(|offset| image.get(offset as usize..offset as usize + 4))
+                .map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]]));
+            Some(LiteralLoad {
+                site,
+                register: t,
+                address,
+                value,
+            })
+        })
+        .collect()
+}
+
+/// Returns the literal pools of `loads`, runs of adjacent literals, in address order.
+///
+/// The instructions [`Program`] decodes in the pools are data, not code.
+pub fn literal_pools(loads: &[LiteralLoad]) -> Vec<Range<u32>> {
+    let literals: BTreeSet<u32> = loads.iter().map(|load| load.address).collect();
+    let mut pools: Vec<Range<u32>> = vec![];
+    for address in literals {
+        match pools.last_mut() {
+            Some(pool) if pool.end == address => pool.end = address.wrapping_add(4),
+            _ => pools.push(address..address.wrapping_add(4)),
+        }
+    }
+    pools
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn pools() {
+        // 0x00: ldr r0, [pc, #4]; ldr r1, [pc, #8]; ldr r2, [pc, #0]; bx lr
+        // 0x08: .word 1, 2; .word 0 not loaded
+        // 0x14: ldr r3, [pc, #0]; .word 3 outside the image
+        let image = [
+            0x01, 0x48, 0x02, 0x49, 0x00, 0x4a, 0x70, 0x47, 0x01, 0x00, 0x00, 0x00, 0x02, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x4b,
+        ];
+        let program = Program::new(&image, 0);
+        let loads = literal_loads(&program, &image);
+        let values: Vec<_> = loads
+            .iter()
+            .map(|load| (load.site, load.address, load.value))
+            .collect();
+        assert_eq!(
+            values,
+            [
+                (0x00, 0x08, Some(1)),
+                (0x02, 0x0c, Some(2)),
+                (0x04, 0x08, Some(1)),
+                (0x14, 0x18, None)
+            ]
+        );
+        assert_eq!(literal_pools(&loads), [0x08..0x10, 0x18..0x1c]);
+    }
+}
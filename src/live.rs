@@ -0,0 +1,217 @@
+//! Disassembly of the code of a halted target around its program counter, for debug tools
+//! showing the instructions a fault stopped at.
+//!
+//! Targets are reached over the GDB remote protocol, or with the `probe-rs` feature through
+//! the `Core` of a probe-rs session and read from probe-rs core dumps.
+//!
+//! # Example
+//! ```no_run
+//! # use armv6_m_instruction_parser::{differential::GdbRemote, live::disassemble_at_pc};
+//! // OpenOCD, `probe-rs gdb` or `qemu -s` with the target halted at the fault.
+//! let mut target = GdbRemote::connect("localhost:3333")?;
+//! let listing = disassemble_at_pc(&mut target, 16, 16)?;
+//! for (address, instruction) in listing.instructions() {
+//!     let marker = if address == listing.pc { "=>" } else { "  " };
+//!     println!("{marker} {address:#010x}: {instruction:?}");
+//! }
+//! # Ok::<(), std::io::Error>(())
+//! ```
+
+use std::io::{self, Read, Write};
+
+#[cfg(feature = "probe-rs")]
+use probe_rs::{Core, CoreDump, MemoryInterface};
+
+use crate::{
+    differential::GdbRemote, instruction_size, memory_source::MemorySource, AddressedDisassembly,
+    ThumbInstructions,
+};
+
+/// Halted processor whose memory and program counter can be read, like a debug probe
+/// session.
+///
+/// Implemented for [`GdbRemote`], which reaches probe-rs, OpenOCD and pyOCD through their
+/// GDB servers, and with the `probe-rs` feature for a probe-rs `Core` and `CoreDump`.
+pub trait LiveTarget: MemorySource {
+    /// Returns the address of the next instruction.
+    fn pc(&mut self) -> Result<u32, Self::Error>;
+}
+
+impl<S: Read + Write> LiveTarget for GdbRemote<S> {
+    fn pc(&mut self) -> io::Result<u32> {
+        self.read_register(15)
+    }
+}
+
+#[cfg(feature = "probe-rs")]
+impl MemorySource for Core<'_> {
+    type Error = probe_rs::Error;
+
+    fn fetch(&mut self, address: u32, buffer: &mut [u8]) -> Result<(), probe_rs::Error> {
+        self.read(address.into(), buffer)
+    }
+}
+
+/// The core must be halted for the program counter to be read.
+#[cfg(feature = "probe-rs")]
+impl LiveTarget for Core<'_> {
+    fn pc(&mut self) -> Result<u32, probe_rs::Error> {
+        let pc = self.program_counter();
+        self.read_core_reg(pc)
+    }
+}
+
+/// Reads fail outside the memory ranges dumped.
+#[cfg(feature = "probe-rs")]
+impl MemorySource for CoreDump {
+    type Error = probe_rs::Error;
+
+    fn fetch(&mut self, address: u32, buffer: &mut [u8]) -> Result<(), probe_rs::Error> {
+        self.read_8(address.into(), buffer)
+    }
+}
+
+#[cfg(feature = "probe-rs")]
+impl LiveTarget for CoreDump {
+    fn pc(&mut self) -> Result<u32, probe_rs::Error> {
+        let pc = self.registers().pc().map(|pc| pc.id());
+        match pc.and_then(|pc| self.registers.get(&pc)) {
+            Some(&value) => value.try_into(),
+            None => Err(probe_rs::Error::Other(
+                "program counter not in the core dump".into(),
+            )),
+        }
+    }
+}
+
+/// Code read around the program counter by [`disassemble_at_pc`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Listing {
+    /// Program counter of the target.
+    pub pc: u32,
+    /// Address of the first byte read.
+    pub address: u32,
+    pub bytes: Vec<u8>,
+}
+
+impl Listing {
+    /// Returns the instructions read, starting at the first instruction boundary that
+    /// decoding reaches the program counter from.
+    ///
+    /// Code before the program counter can not be decoded unambiguously, as the second
+    /// halfword of a 32 bit instruction may decode as a 16 bit instruction.
+    pub fn instructions(&self) -> AddressedDisassembly<'_> {
+        let offset = [0, 2]
+            .into_iter()
+            .find(|&offset| self.reaches_pc(offset))
+            .unwrap_or(0);
+        let bytes = self.bytes.get(offset..).unwrap_or_default();
+        bytes.thumb_instructions_at(self.address + offset as u32)
+    }
+
+    /// Returns `true` if decoding from `offset` has an instruction starting at the program
+    /// counter.
+    fn reaches_pc(&self, offset: usize) -> bool {
+        let pc = self.pc.wrapping_sub(self.address) as usize;
+        let mut start = offset;
+        while start < pc {
+            let Some(halfword) = self.bytes.get(start..start + 2) else {
+                return false;
+            };
+            start += instruction_size(u16::from_le_bytes([halfword[0], halfword[1]]));
+        }
+        start == pc
+    }
+}
+
+/// Reads the code from `before` bytes before the program counter of `target` to `after`
+/// bytes from it, halfword aligned, for showing the instructions it is stopped at.
+pub fn disassemble_at_pc<T: LiveTarget>(
+    target: &mut T,
+    before: u32,
+    after: u32,
+) -> Result<Listing, T::Error> {
+    let pc = target.pc()? & !1;
+    let address = pc.saturating_sub(before) & !1;
+    let end = pc.saturating_add(after).saturating_add(1) & !1;
+    let mut bytes = vec![0; (end - address) as usize];
+    target.fetch(address, &mut bytes)?;
+    Ok(Listing { pc, address, bytes })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Target halted in `bytes`, placed at `base`.
+    struct Halted {
+        base: u32,
+        bytes: Vec<u8>,
+        pc: u32,
+    }
+
+    impl MemorySource for Halted {
+        type Error = ();
+
+        fn fetch(&mut self, address: u32, buffer: &mut [u8]) -> Result<(), ()> {
+            let start = (address - self.base) as usize;
+            buffer.copy_from_slice(&self.bytes[start..start + buffer.len()]);
+            Ok(())
+        }
+    }
+
+    impl LiveTarget for Halted {
+        fn pc(&mut self) -> Result<u32, ()> {
+            Ok(self.pc)
+        }
+    }
+
+    #[test]
+    fn around_pc() {
+        // 0x100: movs r0, #1; bl 0x100; ldr r0, [r0]; bx lr
+        let mut target = Halted {
+            base: 0x100,
+            bytes: vec![0x01, 0x20, 0xff, 0xf7, 0xfc, 0xff, 0x00, 0x68, 0x70, 0x47],
+            pc: 0x106,
+        };
+        // Starting in the middle of the `bl` would decode its second halfword.
+        let listing = disassemble_at_pc(&mut target, 4, 3).unwrap();
+        assert_eq!(listing.address, 0x102);
+        assert_eq!(listing.bytes.len(), 8);
+        let addresses: Vec<u32> = listing.instructions().map(|(address, _)| address).collect();
+        assert_eq!(addresses, [0x102, 0x106, 0x108]);
+
+        let listing = disassemble_at_pc(&mut target, 2, 2).unwrap();
+        let addresses: Vec<u32> = listing.instructions().map(|(address, _)| address).collect();
+        assert_eq!(addresses, [0x106]);
+    }
+
+    #[cfg(feature = "probe-rs")]
+    #[test]
+    fn core_dump() {
+        use probe_rs::{CoreType, InstructionSet, RegisterValue};
+
+        let mut dump = CoreDump {
+            registers: Default::default(),
+            data: vec![(
+                0x100..0x10a,
+                vec![0x01, 0x20, 0xff, 0xf7, 0xfc, 0xff, 0x00, 0x68, 0x70, 0x47],
+            )],
+            instruction_set: InstructionSet::Thumb2,
+            supports_native_64bit_access: false,
+            core_type: CoreType::Armv6m,
+            fpu_support: false,
+            floating_point_register_count: None,
+        };
+        assert!(disassemble_at_pc(&mut dump, 4, 3).is_err());
+
+        // The Thumb bit is set in the program counter read.
+        let pc = dump.registers().pc().unwrap().id();
+        dump.registers.insert(pc, RegisterValue::U32(0x107));
+        let listing = disassemble_at_pc(&mut dump, 4, 3).unwrap();
+        assert_eq!(listing.pc, 0x106);
+        let addresses: Vec<u32> = listing.instructions().map(|(address, _)| address).collect();
+        assert_eq!(addresses, [0x102, 0x106, 0x108]);
+        assert!(disassemble_at_pc(&mut dump, 4, 8).is_err());
+    }
+}
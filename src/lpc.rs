@@ -0,0 +1,87 @@
+//! Vector table checksum of NXP LPC devices, whose boot ROM only starts the code in flash if
+//! the first eight words of the vector table sum to zero. The checksum is the reserved entry
+//! 7, so it changes with the stack pointer and the first six handlers.
+//!
+//! # Example
+//! ```
+//! # use armv6_m_instruction_parser::lpc::{fix_checksum, is_valid};
+//! let mut image = [0; 32];
+//! image[0..4].copy_from_slice(&0x1000_2000u32.to_le_bytes());
+//! image[4..8].copy_from_slice(&0x0000_00c1u32.to_le_bytes());
+//! assert!(!is_valid(&image));
+//! assert_eq!(fix_checksum(&mut image), Ok(0xefff_df3f));
+//! assert!(is_valid(&image));
+//! ```
+
+use crate::Error;
+
+/// Offset of the checksum in the image, entry 7 of the vector table.
+pub const CHECKSUM_OFFSET: usize = 0x1c;
+
+/// Returns the sum of the first `count` words of `image`.
+fn sum(image: &[u8], count: usize) -> Result<u32, Error> {
+    let words = image.get(..count * 4).ok_or(Error::InsufficientInput)?;
+    Ok(words
+        .chunks_exact(4)
+        .map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]]))
+        .fold(0, u32::wrapping_add))
+}
+
+/// Returns the checksum of the vector table at the start of `image`, the two's complement
+/// of the sum of entries 0 to 6. Returns [`Error::InsufficientInput`] if the image is
+/// shorter than the eight words.
+pub fn checksum(image: &[u8]) -> Result<u32, Error> {
+    if image.len() < CHECKSUM_OFFSET + 4 {
+        return Err(Error::InsufficientInput);
+    }
+    Ok(sum(image, 7)?.wrapping_neg())
+}
+
+/// Returns `true` if the vector table at the start of `image` has a valid checksum.
+pub fn is_valid(image: &[u8]) -> bool {
+    sum(image, 8) == Ok(0)
+}
+
+/// Writes the checksum of the vector table at the start of `image` to entry 7, returning
+/// it. Needed after changing the stack pointer or the first six handlers, or the device
+/// no longer boots.
+pub fn fix_checksum(image: &mut [u8]) -> Result<u32, Error> {
+    let checksum = checksum(image)?;
+    image[CHECKSUM_OFFSET..CHECKSUM_OFFSET + 4].copy_from_slice(&checksum.to_le_bytes());
+    Ok(checksum)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn checksum_words() {
+        // Stack pointer at the end of the SRAM of an LPC1114, reset, NMI and HardFault handlers.
+        let words = [
+            0x1000_2000u32,
+            0x0000_00f5,
+            0x0000_00ed,
+            0x0000_00ef,
+            0,
+            0,
+            0,
+            0xefff_dd2f,
+        ];
+        let mut image: [u8; 36] = [0xff; 36];
+        for (bytes, word) in image.chunks_exact_mut(4).zip(words) {
+            bytes.copy_from_slice(&word.to_le_bytes());
+        }
+        assert_eq!(checksum(&image), Ok(0xefff_dd2f));
+        assert!(is_valid(&image));
+
+        image[4] = 0xf9;
+        assert!(!is_valid(&image));
+        assert_eq!(fix_checksum(&mut image), Ok(0xefff_dd2b));
+        assert!(is_valid(&image));
+        assert_eq!(image[32..], [0xff; 4]);
+
+        assert_eq!(checksum(&image[..31]), Err(Error::InsufficientInput));
+        assert!(!is_valid(&image[..31]));
+    }
+}
@@ -0,0 +1,422 @@
+//! Memory the interpreter accesses, with RAM and flash regions combined into memory maps.
+//!
+//! # Example
+//! ```
+//! # use armv6_m_instruction_parser::memory::{BusError, Flash, Memory, Ram};
+//! let mut flash = Flash { base: 0x0800_0000, bytes: &[0x00, 0xbf, 0x70, 0x47] };
+//! assert_eq!(flash.read_halfword(0x0800_0002), Ok(0x4770));
+//! assert_eq!(flash.write_word(0x0800_0000, 0), Err(BusError::ReadOnly));
+//! let mut sram = [0; 0x100];
+//! let mut ram = Ram { base: 0x2000_0000, bytes: &mut sram };
+//! ram.write_word(0x2000_0010, 0x1234_5678).unwrap();
+//! assert_eq!(ram.read_byte(0x2000_0011), Ok(0x56));
+//! assert_eq!(ram.read_word(0x1000_0000), Err(BusError::Unmapped));
+//! ```
+
+use core::ops::Range;
+
+use crate::instructons::AccessSize;
+#[cfg(feature = "alloc")]
+use alloc::{boxed::Box, rc::Rc, vec, vec::Vec};
+
+/// Error of a [`Memory`] access, taken as a bus fault.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum BusError {
+    /// Nothing is mapped at the address.
+    Unmapped,
+    /// Write to memory that can only be read.
+    ReadOnly,
+    /// Read or instruction fetch the memory does not permit, see [`Permissions`].
+    Permission,
+}
+
+/// Accesses memory permits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Permissions {
+    pub read: bool,
+    pub write: bool,
+    /// Instructions can be fetched.
+    pub execute: bool,
+}
+
+impl Permissions {
+    pub const NONE: Self = Self::new(false, false, false);
+    pub const R: Self = Self::new(true, false, false);
+    pub const RW: Self = Self::new(true, true, false);
+    pub const RX: Self = Self::new(true, false, true);
+    pub const RWX: Self = Self::new(true, true, true);
+
+    pub const fn new(read: bool, write: bool, execute: bool) -> Self {
+        Self {
+            read,
+            write,
+            execute,
+        }
+    }
+}
+
+/// Returns `true` if `address` is in a region of the ARMv6-M memory map instructions cannot
+/// be fetched from: the peripheral, device and system regions.
+pub fn execute_never(address: u32) -> bool {
+    matches!(address >> 28, 0x4 | 0x5 | 0xa..=0xf)
+}
+
+/// Memory accessed in bytes, halfwords and words.
+///
+/// Accesses are aligned to their size, the interpreter faults on unaligned ones before
+/// accessing the memory.
+pub trait Memory {
+    /// Reads the little endian value of `size` at `address`, zero extended.
+    fn read(&mut self, address: u32, size: AccessSize) -> Result<u32, BusError>;
+
+    /// Writes the low bytes of `value` of `size` at `address`, little endian.
+    fn write(&mut self, address: u32, size: AccessSize, value: u32) -> Result<(), BusError>;
+
+    /// Returns the addresses of the memory, accesses outside them are bus errors.
+    fn range(&self) -> Range<u32>;
+
+    /// Returns the accesses permitted at `address`, all by default. Reads and writes are
+    /// checked by the memory, instruction fetches by the emulator.
+    fn permissions(&self, _address: u32) -> Permissions {
+        Permissions::RWX
+    }
+
+    fn read_byte(&mut self, address: u32) -> Result<u8, BusError> {
+        Ok(self.read(address, AccessSize::Byte)? as u8)
+    }
+
+    fn read_halfword(&mut self, address: u32) -> Result<u16, BusError> {
+        Ok(self.read(address, AccessSize::Halfword)? as u16)
+    }
+
+    fn read_word(&mut self, address: u32) -> Result<u32, BusError> {
+        self.read(address, AccessSize::Word)
+    }
+
+    fn write_byte(&mut self, address: u32, value: u8) -> Result<(), BusError> {
+        self.write(address, AccessSize::Byte, value as u32)
+    }
+
+    fn write_halfword(&mut self, address: u32, value: u16) -> Result<(), BusError> {
+        self.write(address, AccessSize::Halfword, value as u32)
+    }
+
+    fn write_word(&mut self, address: u32, value: u32) -> Result<(), BusError> {
+        self.write(address, AccessSize::Word, value)
+    }
+}
+
+/// Returns the range of `bytes` placed at `base` accessed by `size` at `address`.
+fn offsets(
+    base: u32,
+    bytes: &[u8],
+    address: u32,
+    size: AccessSize,
+) -> Result<Range<usize>, BusError> {
+    let start = address.checked_sub(base).ok_or(BusError::Unmapped)? as usize;
+    let end = start + size.bytes() as usize;
+    (end <= bytes.len())
+        .then_some(start..end)
+        .ok_or(BusError::Unmapped)
+}
+
+fn read(bytes: &[u8]) -> u32 {
+    let mut word = [0; 4];
+    word[..bytes.len()].copy_from_slice(bytes);
+    u32::from_le_bytes(word)
+}
+
+fn end(base: u32, bytes: &[u8]) -> u32 {
+    base.saturating_add(bytes.len() as u32)
+}
+
+/// RAM placed at `base`.
+#[derive(Debug)]
+pub struct Ram<'a> {
+    pub base: u32,
+    pub bytes: &'a mut [u8],
+}
+
+impl Memory for Ram<'_> {
+    fn read(&mut self, address: u32, size: AccessSize) -> Result<u32, BusError> {
+        Ok(read(
+            &self.bytes[offsets(self.base, self.bytes, address, size)?],
+        ))
+    }
+
+    fn write(&mut self, address: u32, size: AccessSize, value: u32) -> Result<(), BusError> {
+        let offsets = offsets(self.base, self.bytes, address, size)?;
+        let length = offsets.len();
+        self.bytes[offsets].copy_from_slice(&value.to_le_bytes()[..length]);
+        Ok(())
+    }
+
+    fn range(&self) -> Range<u32> {
+        self.base..end(self.base, self.bytes)
+    }
+}
+
+/// Flash placed at `base`, writes are bus errors.
+#[derive(Debug, Clone, Copy)]
+pub struct Flash<'a> {
+    pub base: u32,
+    pub bytes: &'a [u8],
+}
+
+impl Memory for Flash<'_> {
+    fn read(&mut self, address: u32, size: AccessSize) -> Result<u32, BusError> {
+        Ok(read(
+            &self.bytes[offsets(self.base, self.bytes, address, size)?],
+        ))
+    }
+
+    fn write(&mut self, address: u32, size: AccessSize, _value: u32) -> Result<(), BusError> {
+        offsets(self.base, self.bytes, address, size)?;
+        Err(BusError::ReadOnly)
+    }
+
+    fn range(&self) -> Range<u32> {
+        self.base..end(self.base, self.bytes)
+    }
+
+    fn permissions(&self, _address: u32) -> Permissions {
+        Permissions::RX
+    }
+}
+
+/// Memory with the accesses `permissions` permits, like RAM that cannot be executed.
+#[derive(Debug, Clone)]
+pub struct Protected<M> {
+    pub memory: M,
+    pub permissions: Permissions,
+}
+
+impl<M: Memory> Memory for Protected<M> {
+    fn read(&mut self, address: u32, size: AccessSize) -> Result<u32, BusError> {
+        if !self.permissions.read && self.range().contains(&address) {
+            return Err(BusError::Permission);
+        }
+        self.memory.read(address, size)
+    }
+
+    fn write(&mut self, address: u32, size: AccessSize, value: u32) -> Result<(), BusError> {
+        if !self.permissions.write && self.range().contains(&address) {
+            return Err(BusError::ReadOnly);
+        }
+        self.memory.write(address, size, value)
+    }
+
+    fn range(&self) -> Range<u32> {
+        self.memory.range()
+    }
+
+    fn permissions(&self, address: u32) -> Permissions {
+        let permissions = self.memory.permissions(address);
+        Permissions::new(
+            self.permissions.read && permissions.read,
+            self.permissions.write && permissions.write,
+            self.permissions.execute && permissions.execute,
+        )
+    }
+}
+
+/// Bytes of a page of [`CowRam`].
+#[cfg(feature = "alloc")]
+pub const PAGE_SIZE: usize = 1024;
+
+/// RAM placed at `base` whose clones share pages until written, for forking execution
+/// states cheaply.
+#[cfg(feature = "alloc")]
+#[derive(Debug, Clone)]
+pub struct CowRam {
+    base: u32,
+    size: u32,
+    pages: Vec<Rc<[u8; PAGE_SIZE]>>,
+}
+
+#[cfg(feature = "alloc")]
+impl CowRam {
+    /// Returns `size` bytes of zeroed RAM at `base`.
+    pub fn new(base: u32, size: u32) -> Self {
+        // Every page starts as the same zero page.
+        let zero = Rc::new([0; PAGE_SIZE]);
+        Self {
+            base,
+            size,
+            pages: vec![zero; (size as usize).div_ceil(PAGE_SIZE)],
+        }
+    }
+
+    /// Returns RAM at `base` holding `bytes`, like a loaded image.
+    pub fn from_bytes(base: u32, bytes: &[u8]) -> Self {
+        let mut ram = Self::new(base, bytes.len() as u32);
+        for (page, chunk) in ram.pages.iter_mut().zip(bytes.chunks(PAGE_SIZE)) {
+            Rc::make_mut(page)[..chunk.len()].copy_from_slice(chunk);
+        }
+        ram
+    }
+
+    /// Returns the number of pages not shared with `other`, a clone of the same RAM.
+    pub fn pages_copied(&self, other: &Self) -> usize {
+        self.pages
+            .iter()
+            .zip(&other.pages)
+            .filter(|(page, other)| !Rc::ptr_eq(page, other))
+            .count()
+    }
+
+    /// Returns the offsets of the bytes accessed by `size` at `address`.
+    fn offsets(&self, address: u32, size: AccessSize) -> Result<Range<usize>, BusError> {
+        let start = address.checked_sub(self.base).ok_or(BusError::Unmapped)?;
+        let end = start.checked_add(size.bytes()).ok_or(BusError::Unmapped)?;
+        (end <= self.size)
+            .then_some(start as usize..end as usize)
+            .ok_or(BusError::Unmapped)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl Memory for CowRam {
+    fn read(&mut self, address: u32, size: AccessSize) -> Result<u32, BusError> {
+        let offsets = self.offsets(address, size)?;
+        let mut word = [0; 4];
+        for (byte, offset) in word.iter_mut().zip(offsets) {
+            *byte = self.pages[offset / PAGE_SIZE][offset % PAGE_SIZE];
+        }
+        Ok(u32::from_le_bytes(word))
+    }
+
+    fn write(&mut self, address: u32, size: AccessSize, value: u32) -> Result<(), BusError> {
+        let offsets = self.offsets(address, size)?;
+        for (byte, offset) in value.to_le_bytes().into_iter().zip(offsets) {
+            Rc::make_mut(&mut self.pages[offset / PAGE_SIZE])[offset % PAGE_SIZE] = byte;
+        }
+        Ok(())
+    }
+
+    fn range(&self) -> Range<u32> {
+        self.base..self.base.saturating_add(self.size)
+    }
+}
+
+/// Memory made of regions, accesses outside every region are bus errors.
+///
+/// # Example
+/// ```
+/// # use armv6_m_instruction_parser::memory::{BusError, Flash, Memory, MemoryMap, Ram};
+/// let image = [0x00, 0xbf, 0x70, 0x47];
+/// let mut sram = [0; 0x100];
+/// let mut memory = MemoryMap::new();
+/// memory.insert(Flash { base: 0x0800_0000, bytes: &image });
+/// memory.insert(Ram { base: 0x2000_0000, bytes: &mut sram });
+/// assert_eq!(memory.read_halfword(0x0800_0002), Ok(0x4770));
+/// memory.write_word(0x2000_0010, 0x1234_5678).unwrap();
+/// assert_eq!(memory.read_word(0x1000_0000), Err(BusError::Unmapped));
+/// ```
+#[cfg(feature = "alloc")]
+#[derive(Default)]
+pub struct MemoryMap<'a> {
+    regions: Vec<Box<dyn Memory + 'a>>,
+}
+
+#[cfg(feature = "alloc")]
+impl<'a> MemoryMap<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `region`, where regions overlap the region inserted last is accessed, like a
+    /// peripheral placed over RAM.
+    pub fn insert(&mut self, region: impl Memory + 'a) {
+        self.regions.push(Box::new(region));
+    }
+
+    fn region_at(&self, address: u32) -> Option<&(dyn Memory + 'a)> {
+        self.regions
+            .iter()
+            .rev()
+            .find(|region| region.range().contains(&address))
+            .map(|region| region.as_ref())
+    }
+
+    fn region(
+        &mut self,
+        address: u32,
+        size: AccessSize,
+    ) -> Result<&mut (dyn Memory + 'a), BusError> {
+        let last = address
+            .checked_add(size.bytes() - 1)
+            .ok_or(BusError::Unmapped)?;
+        self.regions
+            .iter_mut()
+            .rev()
+            .find(|region| {
+                let range = region.range();
+                range.contains(&address) && range.contains(&last)
+            })
+            .map(|region| region.as_mut())
+            .ok_or(BusError::Unmapped)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl Memory for MemoryMap<'_> {
+    fn read(&mut self, address: u32, size: AccessSize) -> Result<u32, BusError> {
+        self.region(address, size)?.read(address, size)
+    }
+
+    fn write(&mut self, address: u32, size: AccessSize, value: u32) -> Result<(), BusError> {
+        self.region(address, size)?.write(address, size, value)
+    }
+
+    /// The addresses from the lowest to the highest mapped, with the gaps between regions.
+    fn range(&self) -> Range<u32> {
+        let start = self.regions.iter().map(|region| region.range().start).min();
+        let end = self.regions.iter().map(|region| region.range().end).max();
+        start.unwrap_or(0)..end.unwrap_or(0)
+    }
+
+    /// The permissions of the region at `address`, none in the gaps.
+    fn permissions(&self, address: u32) -> Permissions {
+        self.region_at(address)
+            .map_or(Permissions::NONE, |region| region.permissions(address))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn regions() {
+        let image = [0x01, 0x02, 0x03, 0x04, 0x05, 0x06];
+        let mut flash = Flash {
+            base: 0x100,
+            bytes: &image,
+        };
+        assert_eq!(flash.read_word(0x100), Ok(0x0403_0201));
+        assert_eq!(flash.read_halfword(0x104), Ok(0x0605));
+        assert_eq!(flash.read_word(0x104), Err(BusError::Unmapped));
+        assert_eq!(flash.write_byte(0x106, 0), Err(BusError::Unmapped));
+        assert_eq!(flash.write_byte(0x105, 0), Err(BusError::ReadOnly));
+        let mut bytes = [0; 8];
+        let mut ram = Ram {
+            base: 0x200,
+            bytes: &mut bytes,
+        };
+        ram.write_halfword(0x206, 0xbeef).unwrap();
+        ram.write_byte(0x200, 0xff).unwrap();
+        assert_eq!(ram.read_word(0x204), Ok(0xbeef_0000));
+        assert_eq!(ram.range(), 0x200..0x208);
+        assert_eq!(flash.permissions(0x100), Permissions::RX);
+        let mut protected = Protected {
+            memory: ram,
+            permissions: Permissions::R,
+        };
+        assert_eq!(protected.write_byte(0x200, 0), Err(BusError::ReadOnly));
+        assert_eq!(protected.write_byte(0x208, 0), Err(BusError::Unmapped));
+        assert_eq!(protected.permissions(0x200), Permissions::R);
+        assert_eq!(bytes, [0xff, 0, 0, 0, 0, 0, 0xef, 0xbe]);
+    }
+}
@@ -0,0 +1,172 @@
+//! Memory instructions are read from on demand, like a target behind a GDB server, for
+//! disassembling code without reading the whole image first.
+//!
+//! Sources are implemented for [`MemoryImage`] and, with the `std` feature, for the
+//! `GdbRemote` client of `qemu-system-arm -s` or OpenOCD.
+//!
+//! # Example
+//! ```
+//! # use armv6_m_instruction_parser::{image::MemoryImage, instructons::Operation, memory_source::MemorySource};
+//! // movs r0, #1; bx lr
+//! let mut image = MemoryImage::from_bin(&[0x01, 0x20, 0x70, 0x47], 0x100);
+//! let mut instructions = image.instructions_at(0x100);
+//! let (address, instruction) = instructions.next().unwrap().unwrap();
+//! assert_eq!(address, 0x100);
+//! assert!(matches!(instruction.unwrap().operation, Operation::MOVImm { imm: 1, .. }));
+//! assert_eq!(instructions.next().unwrap().unwrap().0, 0x102);
+//! // Reading past the image ends the instructions.
+//! assert!(instructions.next().unwrap().is_err());
+//! ```
+
+#[cfg(feature = "std")]
+use std::io::{self, Read, Write};
+
+use alloc::{vec, vec::Vec};
+
+#[cfg(feature = "std")]
+use crate::differential::GdbRemote;
+use crate::{
+    image::MemoryImage, instruction_size, instructons::Instruction, memory::BusError, parse, Error,
+};
+
+/// Bytes read at once by [`SourceInstructions`], aligned to their size.
+const CHUNK: u32 = 64;
+
+/// Memory bytes can be read from.
+pub trait MemorySource {
+    type Error;
+
+    /// Fills `buffer` with the bytes at `address`.
+    fn fetch(&mut self, address: u32, buffer: &mut [u8]) -> Result<(), Self::Error>;
+
+    /// Returns the instructions from `address` on, read as they are decoded.
+    fn instructions_at(&mut self, address: u32) -> SourceInstructions<'_, Self>
+    where
+        Self: Sized,
+    {
+        SourceInstructions {
+            source: self,
+            address: Some(address & !1),
+            chunk: Vec::new(),
+            chunk_address: 0,
+        }
+    }
+}
+
+impl MemorySource for MemoryImage {
+    type Error = BusError;
+
+    fn fetch(&mut self, address: u32, buffer: &mut [u8]) -> Result<(), BusError> {
+        let bytes = self.read(address, buffer.len()).ok_or(BusError::Unmapped)?;
+        buffer.copy_from_slice(bytes);
+        Ok(())
+    }
+}
+
+/// Memory of the target read with `m` packets of at most 256 bytes, which all servers
+/// accept.
+#[cfg(feature = "std")]
+impl<S: Read + Write> MemorySource for GdbRemote<S> {
+    type Error = io::Error;
+
+    fn fetch(&mut self, address: u32, buffer: &mut [u8]) -> io::Result<()> {
+        for (index, part) in buffer.chunks_mut(256).enumerate() {
+            let start = address.wrapping_add(index as u32 * 256);
+            let bytes = self.read_memory(start, part.len() as u32)?;
+            if bytes.len() != part.len() {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "short memory read",
+                ));
+            }
+            part.copy_from_slice(&bytes);
+        }
+        Ok(())
+    }
+}
+
+/// Iterator over the instructions of a [`MemorySource`], see
+/// [`MemorySource::instructions_at`].
+///
+/// Memory is read in aligned chunks, falling back to single instructions where a chunk can
+/// not be read, like at the end of flash. Ends after the first read failing or at the end
+/// of the address space.
+#[derive(Debug)]
+pub struct SourceInstructions<'a, S> {
+    source: &'a mut S,
+    /// Address of the next instruction, `None` once done.
+    address: Option<u32>,
+    /// Bytes last read and their address.
+    chunk: Vec<u8>,
+    chunk_address: u32,
+}
+
+impl<S: MemorySource> SourceInstructions<'_, S> {
+    /// Returns the halfword at `address`, reading its chunk if not read yet.
+    fn halfword(&mut self, address: u32) -> Result<u16, S::Error> {
+        let offset = address.wrapping_sub(self.chunk_address) as usize;
+        if let Some(bytes) = self.chunk.get(offset..offset + 2) {
+            return Ok(u16::from_le_bytes([bytes[0], bytes[1]]));
+        }
+        let start = address & !(CHUNK - 1);
+        let mut chunk = vec![0; (CHUNK as u64).min((1 << 32) - start as u64) as usize];
+        let (start, chunk) = match self.source.fetch(start, &mut chunk) {
+            Ok(()) => (start, chunk),
+            Err(_) => {
+                let mut halfword = vec![0; 2];
+                self.source.fetch(address, &mut halfword)?;
+                (address, halfword)
+            }
+        };
+        self.chunk = chunk;
+        self.chunk_address = start;
+        self.halfword(address)
+    }
+}
+
+impl<S: MemorySource> Iterator for SourceInstructions<'_, S> {
+    type Item = Result<(u32, Result<Instruction, Error>), S::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let address = self.address.take()?;
+        let first = match self.halfword(address) {
+            Ok(first) => first,
+            Err(error) => return Some(Err(error)),
+        };
+        let size = instruction_size(first);
+        let mut bytes = [0; 4];
+        bytes[..2].copy_from_slice(&first.to_le_bytes());
+        if size == 4 {
+            let second = match self.halfword(address.wrapping_add(2)) {
+                Ok(second) => second,
+                Err(error) => return Some(Err(error)),
+            };
+            bytes[2..].copy_from_slice(&second.to_le_bytes());
+        }
+        self.address = address.checked_add(size as u32);
+        Some(Ok((address, parse(&bytes[..size]))))
+    }
+}
+
+impl<S: MemorySource> core::iter::FusedIterator for SourceInstructions<'_, S> {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn on_demand() {
+        // bl across the chunk boundary at 0x40, movs r0, #1 at the end of the image.
+        let mut image = MemoryImage::new();
+        image.insert(0x3e, &[0xff, 0xf7, 0xdf, 0xff, 0x01, 0x20]);
+        let listing: Vec<_> = image.instructions_at(0x3e).collect();
+        assert_eq!(listing.len(), 3);
+        let (address, instruction) = listing[0].clone().unwrap();
+        assert_eq!((address, instruction.unwrap().size()), (0x3e, 4));
+        assert_eq!(listing[1].clone().unwrap().0, 0x42);
+        assert_eq!(listing[2], Err(BusError::Unmapped));
+
+        let mut image = MemoryImage::from_bin(&[0x70, 0x47], 0xffff_fffe);
+        assert_eq!(image.instructions_at(0xffff_fffe).count(), 1);
+    }
+}
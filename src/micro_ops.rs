@@ -0,0 +1,517 @@
+//! Micro-op IR, a small RISC-like intermediate form the operations are lowered to, with far
+//! fewer cases than [`Operation`](crate::instructons::Operation) for JITs, lifters and
+//! analyzers.
+//!
+//! # Example
+//! ```
+//! # use armv6_m_instruction_parser::{instructons::{AccessSize, DecodedAt}, micro_ops::{lower, MicroOp, Operand}, parse, registers::Register, semantics::BinaryOperation};
+//! // ldr r0, [r1, #4]
+//! let instruction = parse(&[0x48, 0x68]).unwrap();
+//! let micro_ops = lower(&DecodedAt { instruction, address: 0x100 });
+//! assert_eq!(
+//!     micro_ops,
+//!     [
+//!         MicroOp::BinOp {
+//!             destination: 0,
+//!             operation: BinaryOperation::Add,
+//!             x: Operand::Register(Register::R1),
+//!             y: Operand::Constant(4),
+//!         },
+//!         MicroOp::Load { destination: 1, address: Operand::Temporary(0), size: AccessSize::Word },
+//!         MicroOp::Write { register: Register::R0, value: Operand::Temporary(1) },
+//!     ]
+//! );
+//! ```
+
+use alloc::vec::Vec;
+
+use crate::{
+    conditions::Condition,
+    instructons::{AccessSize, DecodedAt},
+    registers::{Register, SpecialRegister},
+    semantics::{describe, BinaryOperation, Effects, Event, Flag, Shift},
+};
+
+/// Temporary, a value computed by a micro-op, numbered from 0 in a [`Lowering`].
+pub type Temporary = u32;
+
+/// Input of a micro-op, booleans are 0 or 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operand {
+    /// Register before the instruction, never `pc`.
+    Register(Register),
+    Temporary(Temporary),
+    Constant(u32),
+    Flag(Flag),
+    /// 1 if the condition holds for the flags.
+    Condition(Condition),
+}
+
+/// Kind of a [`MicroOp::Branch`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BranchKind {
+    /// Branch keeping the Thumb state, `b`, `bl` and writes to `pc`.
+    Direct,
+    /// Branch with the Thumb state from bit 0 of the target, or an exception return, `bx`
+    /// and `pop {pc}`.
+    Exchange,
+    /// `blx`, like `Exchange` but never an exception return.
+    ExchangeLink,
+}
+
+/// Micro-op, one step of an operation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MicroOp {
+    /// Loads `size` bytes zero extended.
+    Load {
+        destination: Temporary,
+        address: Operand,
+        size: AccessSize,
+    },
+    /// Stores the low `size` bytes of `value`.
+    Store {
+        address: Operand,
+        size: AccessSize,
+        value: Operand,
+    },
+    BinOp {
+        destination: Temporary,
+        operation: BinaryOperation,
+        x: Operand,
+        y: Operand,
+    },
+    /// `x + y + carry` with the carry out and the signed overflow.
+    AddWithCarry {
+        destination: Temporary,
+        carry_out: Temporary,
+        overflow: Temporary,
+        x: Operand,
+        y: Operand,
+        carry: Operand,
+    },
+    /// Shift with the carry out, `carry` when `amount` is 0.
+    Shift {
+        destination: Temporary,
+        carry_out: Temporary,
+        shift: Shift,
+        value: Operand,
+        amount: Operand,
+        carry: Operand,
+    },
+    /// Sets N and Z from `result`, and C and V when given.
+    SetFlags {
+        result: Operand,
+        carry: Option<Operand>,
+        overflow: Option<Operand>,
+    },
+    ReadSpecial {
+        destination: Temporary,
+        register: SpecialRegister,
+    },
+    WriteSpecial {
+        register: SpecialRegister,
+        value: Operand,
+    },
+    /// Writes a register other than `pc`.
+    Write {
+        register: Register,
+        value: Operand,
+    },
+    /// Branches to `target` if `condition` is 1, always without one.
+    Branch {
+        condition: Option<Operand>,
+        target: Operand,
+        kind: BranchKind,
+    },
+    Event(Event),
+}
+
+/// Lowering of instructions to micro-ops, numbering the temporaries across them.
+///
+/// The micro-ops of an instruction read the registers before it: its register writes
+/// follow its other micro-ops, and its branch comes last.
+#[derive(Debug, Clone, Default)]
+pub struct Lowering {
+    micro_ops: Vec<MicroOp>,
+    temporaries: Temporary,
+    /// Register writes and branch of the instruction lowered.
+    deferred: Vec<MicroOp>,
+}
+
+impl Lowering {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends the micro-ops of `decoded`.
+    pub fn lower(&mut self, decoded: &DecodedAt) {
+        describe(decoded, self);
+        self.micro_ops.append(&mut self.deferred);
+    }
+
+    pub fn micro_ops(&self) -> &[MicroOp] {
+        &self.micro_ops
+    }
+
+    pub fn into_micro_ops(self) -> Vec<MicroOp> {
+        self.micro_ops
+    }
+
+    /// Number of temporaries used.
+    pub fn temporaries(&self) -> Temporary {
+        self.temporaries
+    }
+
+    fn temporary(&mut self) -> Temporary {
+        self.temporaries += 1;
+        self.temporaries - 1
+    }
+}
+
+/// Returns the micro-ops of `decoded`.
+pub fn lower(decoded: &DecodedAt) -> Vec<MicroOp> {
+    let mut lowering = Lowering::new();
+    lowering.lower(decoded);
+    lowering.into_micro_ops()
+}
+
+impl Effects for Lowering {
+    type Value = Operand;
+    type Bool = Operand;
+
+    fn constant(&mut self, value: u32) -> Operand {
+        Operand::Constant(value)
+    }
+
+    fn boolean(&mut self, value: bool) -> Operand {
+        Operand::Constant(value as u32)
+    }
+
+    fn read_register(&mut self, register: Register) -> Operand {
+        Operand::Register(register)
+    }
+
+    fn write_register(&mut self, register: Register, value: Operand) {
+        self.deferred.push(match register {
+            Register::PC => MicroOp::Branch {
+                condition: None,
+                target: value,
+                kind: BranchKind::Direct,
+            },
+            register => MicroOp::Write { register, value },
+        });
+    }
+
+    fn read_memory(&mut self, address: Operand, size: AccessSize) -> Operand {
+        let destination = self.temporary();
+        self.micro_ops.push(MicroOp::Load {
+            destination,
+            address,
+            size,
+        });
+        Operand::Temporary(destination)
+    }
+
+    fn write_memory(&mut self, address: Operand, size: AccessSize, value: Operand) {
+        self.micro_ops.push(MicroOp::Store {
+            address,
+            size,
+            value,
+        });
+    }
+
+    fn read_flag(&mut self, flag: Flag) -> Operand {
+        Operand::Flag(flag)
+    }
+
+    fn set_flags(&mut self, result: Operand, carry: Option<Operand>, overflow: Option<Operand>) {
+        self.micro_ops.push(MicroOp::SetFlags {
+            result,
+            carry,
+            overflow,
+        });
+    }
+
+    fn condition(&mut self, condition: Condition) -> Operand {
+        Operand::Condition(condition)
+    }
+
+    fn binary(&mut self, operation: BinaryOperation, x: Operand, y: Operand) -> Operand {
+        let destination = self.temporary();
+        self.micro_ops.push(MicroOp::BinOp {
+            destination,
+            operation,
+            x,
+            y,
+        });
+        Operand::Temporary(destination)
+    }
+
+    fn not(&mut self, value: Operand) -> Operand {
+        self.binary(BinaryOperation::Xor, value, Operand::Constant(u32::MAX))
+    }
+
+    fn extend(&mut self, value: Operand, bits: u32, signed: bool) -> Operand {
+        if !signed {
+            let mask = Operand::Constant(u32::MAX >> (32 - bits));
+            return self.binary(BinaryOperation::And, value, mask);
+        }
+        let shift = Operand::Constant(32 - bits);
+        let high = self.binary(BinaryOperation::Lsl, value, shift);
+        self.binary(BinaryOperation::Asr, high, shift)
+    }
+
+    fn add_with_carry(
+        &mut self,
+        x: Operand,
+        y: Operand,
+        carry: Operand,
+    ) -> (Operand, Operand, Operand) {
+        let (destination, carry_out, overflow) =
+            (self.temporary(), self.temporary(), self.temporary());
+        self.micro_ops.push(MicroOp::AddWithCarry {
+            destination,
+            carry_out,
+            overflow,
+            x,
+            y,
+            carry,
+        });
+        (
+            Operand::Temporary(destination),
+            Operand::Temporary(carry_out),
+            Operand::Temporary(overflow),
+        )
+    }
+
+    fn shift(
+        &mut self,
+        shift: Shift,
+        value: Operand,
+        amount: Operand,
+        carry: Operand,
+    ) -> (Operand, Operand) {
+        let (destination, carry_out) = (self.temporary(), self.temporary());
+        self.micro_ops.push(MicroOp::Shift {
+            destination,
+            carry_out,
+            shift,
+            value,
+            amount,
+            carry,
+        });
+        (
+            Operand::Temporary(destination),
+            Operand::Temporary(carry_out),
+        )
+    }
+
+    fn branch_if(&mut self, condition: Operand, target: Operand) {
+        self.deferred.push(MicroOp::Branch {
+            condition: Some(condition),
+            target,
+            kind: BranchKind::Direct,
+        });
+    }
+
+    fn branch_exchange(&mut self, target: Operand, link: bool) {
+        let kind = match link {
+            true => BranchKind::ExchangeLink,
+            false => BranchKind::Exchange,
+        };
+        self.deferred.push(MicroOp::Branch {
+            condition: None,
+            target,
+            kind,
+        });
+    }
+
+    fn read_special(&mut self, register: SpecialRegister) -> Operand {
+        let destination = self.temporary();
+        self.micro_ops.push(MicroOp::ReadSpecial {
+            destination,
+            register,
+        });
+        Operand::Temporary(destination)
+    }
+
+    fn write_special(&mut self, register: SpecialRegister, value: Operand) {
+        self.micro_ops
+            .push(MicroOp::WriteSpecial { register, value });
+    }
+
+    fn event(&mut self, event: Event) {
+        self.micro_ops.push(MicroOp::Event(event));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parse;
+
+    fn lower_at(bytes: &[u8], address: u32) -> Vec<MicroOp> {
+        let instruction = parse(bytes).unwrap();
+        lower(&DecodedAt {
+            instruction,
+            address,
+        })
+    }
+
+    #[test]
+    fn lowering() {
+        use Operand::{Constant, Register as R, Temporary as T};
+
+        // adds r0, #1
+        assert_eq!(
+            lower_at(&[0x01, 0x30], 0),
+            [
+                MicroOp::AddWithCarry {
+                    destination: 0,
+                    carry_out: 1,
+                    overflow: 2,
+                    x: R(Register::R0),
+                    y: Constant(1),
+                    carry: Constant(0),
+                },
+                MicroOp::SetFlags {
+                    result: T(0),
+                    carry: Some(T(1)),
+                    overflow: Some(T(2)),
+                },
+                MicroOp::Write {
+                    register: Register::R0,
+                    value: T(0),
+                },
+            ]
+        );
+        // ldmia r0!, {r0, r1} loads from the base before the writes, without write back.
+        let micro_ops = lower_at(&[0x03, 0xc8], 0);
+        assert!(matches!(
+            micro_ops[..3],
+            [
+                MicroOp::Load { .. },
+                MicroOp::BinOp { .. },
+                MicroOp::Load { .. }
+            ]
+        ));
+        assert_eq!(
+            micro_ops[3..],
+            [
+                MicroOp::Write {
+                    register: Register::R0,
+                    value: T(0),
+                },
+                MicroOp::Write {
+                    register: Register::R1,
+                    value: T(2),
+                },
+            ]
+        );
+        // pop {pc} writes sp before branching.
+        let micro_ops = lower_at(&[0x00, 0xbd], 0);
+        assert_eq!(
+            micro_ops.last(),
+            Some(&MicroOp::Branch {
+                condition: None,
+                target: T(0),
+                kind: BranchKind::Exchange,
+            })
+        );
+        assert!(matches!(
+            micro_ops[micro_ops.len() - 2],
+            MicroOp::Write {
+                register: Register::SP,
+                ..
+            }
+        ));
+        // beq to 0x10 at 0x08.
+        assert_eq!(
+            lower_at(&[0x02, 0xd0], 0x08),
+            [MicroOp::Branch {
+                condition: Some(Operand::Condition(Condition::EQ)),
+                target: Constant(0x10),
+                kind: BranchKind::Direct,
+            }]
+        );
+    }
+
+    #[test]
+    fn edge_cases() {
+        use Operand::{Constant, Flag as F, Register as R, Temporary as T};
+
+        // Immediate right shifts by 32 are encoded with 0.
+        for (bytes, shift) in [([0x08, 0x08], Shift::Lsr), ([0x08, 0x10], Shift::Asr)] {
+            assert!(matches!(
+                lower_at(&bytes, 0)[0],
+                MicroOp::Shift { shift: s, amount: Constant(32), .. } if s == shift
+            ));
+        }
+        // lsls r0, r1 shifts by the bottom byte of r1.
+        assert_eq!(
+            lower_at(&[0x88, 0x40], 0)[..2],
+            [
+                MicroOp::BinOp {
+                    destination: 0,
+                    operation: BinaryOperation::And,
+                    x: R(Register::R1),
+                    y: Constant(0xff),
+                },
+                MicroOp::Shift {
+                    destination: 1,
+                    carry_out: 2,
+                    shift: Shift::Lsl,
+                    value: R(Register::R0),
+                    amount: T(0),
+                    carry: F(Flag::C),
+                },
+            ]
+        );
+        // sbcs r0, r1 adds the inverted r1 with the carry, rsbs r0, r1, #0 with 1.
+        let sbcs = lower_at(&[0x88, 0x41], 0);
+        assert!(matches!(
+            sbcs[1],
+            MicroOp::AddWithCarry {
+                x: R(Register::R0),
+                y: T(0),
+                carry: F(Flag::C),
+                ..
+            }
+        ));
+        let rsbs = lower_at(&[0x48, 0x42], 0);
+        assert!(matches!(
+            rsbs[1],
+            MicroOp::AddWithCarry {
+                x: T(0),
+                y: Constant(0),
+                carry: Constant(1),
+                ..
+            }
+        ));
+        // add pc, r0 at 0x40 branches to pc plus r0 without setting flags, mov pc, r1 to r1.
+        assert_eq!(
+            lower_at(&[0x87, 0x44], 0x40)[1..],
+            [MicroOp::Branch {
+                condition: None,
+                target: T(0),
+                kind: BranchKind::Direct,
+            }]
+        );
+        assert!(matches!(
+            lower_at(&[0x87, 0x44], 0x40)[0],
+            MicroOp::AddWithCarry {
+                x: Constant(0x44),
+                y: R(Register::R0),
+                ..
+            }
+        ));
+        assert_eq!(
+            lower_at(&[0x8f, 0x46], 0x40),
+            [MicroOp::Branch {
+                condition: None,
+                target: R(Register::R1),
+                kind: BranchKind::Direct,
+            }]
+        );
+    }
+}
@@ -0,0 +1,396 @@
+//! Differential testing of decoding and formatting against GNU objdump, running
+//! `arm-none-eabi-objdump` on a binary, parsing its listing and comparing every instruction
+//! with the [formatted](crate::format) text of this crate.
+//!
+//! Texts are compared after [`normalize`], so differences in spelling like `ldmia` and
+//! `ldm`, `#0x10` and `#16` or branch suffixes like `.n` are not reported. Listings of
+//! `llvm-objdump` are parsed too.
+//!
+//! # Example
+//! ```no_run
+//! # use armv6_m_instruction_parser::objdump::Objdump;
+//! let mismatches = Objdump::new().compare_file("firmware.elf")?;
+//! for mismatch in mismatches {
+//!     println!("{mismatch:?}");
+//! }
+//! # Ok::<(), std::io::Error>(())
+//! ```
+
+use std::{
+    ffi::OsString,
+    io,
+    path::Path,
+    process::{Command, Output},
+};
+
+use crate::{parse_at, Error};
+
+/// Instruction of an objdump listing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ListingLine {
+    pub address: u32,
+    /// Bytes of the instruction in memory order.
+    pub bytes: Vec<u8>,
+    /// Mnemonic and operands as printed, without comments.
+    pub text: String,
+}
+
+/// Difference between objdump and this crate found by [`compare`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Mismatch {
+    /// Instruction objdump disassembles but this crate does not decode.
+    Rejected {
+        address: u32,
+        objdump: String,
+        error: Error,
+    },
+    /// Instruction this crate decodes but objdump marks as undefined.
+    Accepted { address: u32, text: String },
+    /// Instruction formatted differently, with both texts as printed.
+    Text {
+        address: u32,
+        objdump: String,
+        text: String,
+    },
+}
+
+/// Runner of an objdump executable.
+#[derive(Debug, Clone)]
+pub struct Objdump {
+    program: OsString,
+}
+
+impl Default for Objdump {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Objdump {
+    /// Runs `arm-none-eabi-objdump` from the `PATH`.
+    pub fn new() -> Self {
+        Self::with_program("arm-none-eabi-objdump")
+    }
+
+    /// Runs `program`, another build of GNU objdump or `llvm-objdump`.
+    pub fn with_program(program: impl Into<OsString>) -> Self {
+        Objdump {
+            program: program.into(),
+        }
+    }
+
+    /// Disassembles the executable sections of the ELF file at `path`.
+    pub fn disassemble_elf(&self, path: impl AsRef<Path>) -> io::Result<Vec<ListingLine>> {
+        self.run(&["-d".as_ref(), path.as_ref().as_os_str()])
+    }
+
+    /// Disassembles the raw binary at `path` placed at `base` as Thumb code, with the
+    /// options of GNU objdump.
+    pub fn disassemble_binary(
+        &self,
+        path: impl AsRef<Path>,
+        base: u32,
+    ) -> io::Result<Vec<ListingLine>> {
+        let adjust = format!("--adjust-vma={base:#x}");
+        let args = [
+            "-D",
+            "-b",
+            "binary",
+            "-m",
+            "arm",
+            "-M",
+            "force-thumb",
+            &adjust,
+        ];
+        let mut args: Vec<_> = args.iter().map(|arg| arg.as_ref()).collect();
+        args.push(path.as_ref().as_os_str());
+        self.run(&args)
+    }
+
+    /// Disassembles the ELF file at `path` and compares it with this crate, see
+    /// [`compare`].
+    pub fn compare_file(&self, path: impl AsRef<Path>) -> io::Result<Vec<Mismatch>> {
+        Ok(compare(&self.disassemble_elf(path)?))
+    }
+
+    fn run(&self, args: &[&std::ffi::OsStr]) -> io::Result<Vec<ListingLine>> {
+        let Output {
+            status,
+            stdout,
+            stderr,
+        } = Command::new(&self.program).args(args).output()?;
+        if !status.success() {
+            return Err(io::Error::other(
+                String::from_utf8_lossy(&stderr).into_owned(),
+            ));
+        }
+        Ok(parse_listing(&String::from_utf8_lossy(&stdout)))
+    }
+}
+
+/// Parses the instruction lines of an objdump listing, fields separated by tabs after the
+/// address, with the bytes as halfwords and words printed by GNU objdump or as the bytes
+/// printed by `llvm-objdump`. Other lines and lines without the bytes of the instruction are
+/// skipped.
+pub fn parse_listing(listing: &str) -> Vec<ListingLine> {
+    listing.lines().filter_map(parse_line).collect()
+}
+
+fn parse_line(line: &str) -> Option<ListingLine> {
+    let (address, rest) = line.split_once(':')?;
+    let address = u32::from_str_radix(address.trim(), 16).ok()?;
+    let mut fields = rest.trim_start().split('\t');
+    let mut bytes = Vec::new();
+    for token in fields.next()?.split_whitespace() {
+        let value = u32::from_str_radix(token, 16).ok()?;
+        // GNU objdump prints halfwords and words, llvm-objdump bytes.
+        match token.len() {
+            2 => bytes.push(value as u8),
+            4 => bytes.extend((value as u16).to_le_bytes()),
+            8 => bytes.extend(value.to_le_bytes()),
+            _ => return None,
+        }
+    }
+    let mnemonic = fields.next()?.trim();
+    let operands = fields.next().unwrap_or_default();
+    let operands = operands.split([';', '@']).next().unwrap_or_default().trim();
+    if bytes.is_empty() || mnemonic.is_empty() {
+        return None;
+    }
+    let text = match operands.is_empty() {
+        true => mnemonic.to_string(),
+        false => format!("{mnemonic} {operands}"),
+    };
+    Some(ListingLine {
+        address,
+        bytes,
+        text,
+    })
+}
+
+/// Decodes and formats the instructions of `listing` and compares them with the text of
+/// objdump. Data directives like `.word` are skipped.
+pub fn compare(listing: &[ListingLine]) -> Vec<Mismatch> {
+    let mut mismatches = Vec::new();
+    for line in listing {
+        let undefined = line.text.contains("UNDEFINED")
+            || line.text.starts_with(".inst")
+            || line.text == "<unknown>";
+        if line.text.starts_with('.') && !undefined {
+            continue;
+        }
+        match parse_at(&line.bytes, line.address) {
+            Ok(decoded) if undefined => mismatches.push(Mismatch::Accepted {
+                address: line.address,
+                text: decoded.to_string(),
+            }),
+            Ok(decoded) => {
+                let text = decoded.to_string();
+                if normalize(&text) != normalize(&line.text) {
+                    mismatches.push(Mismatch::Text {
+                        address: line.address,
+                        objdump: line.text.clone(),
+                        text,
+                    });
+                }
+            }
+            Err(_) if undefined => {}
+            Err(error) => mismatches.push(Mismatch::Rejected {
+                address: line.address,
+                objdump: line.text.clone(),
+                error,
+            }),
+        }
+    }
+    mismatches
+}
+
+/// Returns `text` of an instruction in a canonical spelling, for comparing the texts of
+/// different disassemblers:
+/// - lowercase, without symbols like `<main+0x4>` and width suffixes like `.n`,
+/// - numbers in decimal without `#`, branch targets printed without `0x` read as hex,
+/// - `r9` to `r12` for `sb`, `sl`, `fp` and `ip`,
+/// - aliases replaced, like `ldm` for `ldmia`, `bcc` for `blo`, `add rX, pc` for `adr` and
+///   `udf #254` for `trap`,
+/// - no zero offsets, like `[r0]` for `[r0, #0]`,
+/// - two operands for operations written with three but the same register twice, like
+///   `adds r0, r0, r1`.
+pub fn normalize(text: &str) -> String {
+    let text = text.to_lowercase();
+    let (mnemonic, operands) = text.split_once(char::is_whitespace).unwrap_or((&text, ""));
+    let mnemonic = mnemonic
+        .trim_end_matches(".n")
+        .trim_end_matches(".w")
+        .replace("ldmia", "ldm")
+        .replace("stmia", "stm")
+        .replace("blo", "bcc")
+        .replace("bhs", "bcs");
+    let branch = mnemonic.starts_with('b') && !mnemonic.starts_with("bi") && mnemonic != "bkpt";
+
+    let mut operands: Vec<String> = split_operands(operands)
+        .into_iter()
+        .map(|operand| normalize_operand(&operand, branch))
+        .collect();
+    if let Some(last) = operands.last_mut() {
+        if let Some(base) = last.strip_suffix(", 0]") {
+            *last = format!("{base}]");
+        }
+    }
+    let load_store = mnemonic.starts_with("ldr") || mnemonic.starts_with("str");
+    let mut mnemonic = mnemonic;
+    match (mnemonic.as_str(), operands.as_slice()) {
+        ("adr", [_, _]) => operands.insert(1, "pc".into()),
+        ("rsbs", [_, _, zero]) if zero == "0" => {
+            mnemonic = "negs".into();
+            operands.pop();
+        }
+        ("muls", [d, _, m]) if d == m => {
+            operands.pop();
+        }
+        ("add", [d, sp, m]) if sp == "sp" && d == m => {
+            operands.pop();
+        }
+        ("trap", []) => operands.push("254".into()),
+        ("__brkdiv0", []) => operands.push("249".into()),
+        ("mov", [d, m]) if d == "r8" && m == "r8" => {
+            mnemonic = "nop".into();
+            operands.clear();
+        }
+        (_, [d, n, _]) if d == n && !load_store => {
+            operands.remove(1);
+        }
+        _ => {}
+    }
+    match mnemonic.as_str() {
+        "adr" => mnemonic = "add".into(),
+        "trap" | "__brkdiv0" => mnemonic = "udf".into(),
+        _ => {}
+    }
+    match operands.is_empty() {
+        true => mnemonic,
+        false => format!("{mnemonic} {}", operands.join(", ")),
+    }
+}
+
+/// Splits operands at commas outside brackets and braces, keeping memory operands whole.
+fn split_operands(operands: &str) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut depth = 0;
+    let mut current = String::new();
+    for character in operands.chars() {
+        match character {
+            '[' | '{' => depth += 1,
+            ']' | '}' => depth -= 1,
+            ',' if depth == 0 => {
+                parts.push(current.trim().to_string());
+                current.clear();
+                continue;
+            }
+            _ => {}
+        }
+        current.push(character);
+    }
+    if !current.trim().is_empty() {
+        parts.push(current.trim().to_string());
+    }
+    parts
+}
+
+/// Normalizes the registers and numbers of one operand, a memory operand normalized as a
+/// whole.
+fn normalize_operand(operand: &str, branch: bool) -> String {
+    let operand = operand.split('<').next().unwrap_or_default();
+    let mut normalized = String::new();
+    let mut token = String::new();
+    for character in operand.chars().chain([' ']) {
+        if character.is_ascii_alphanumeric() || character == '#' || character == '-' {
+            token.push(character);
+        } else {
+            normalized.push_str(&normalize_token(&token, branch));
+            normalized.push(character);
+            token.clear();
+        }
+    }
+    normalized.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn normalize_token(token: &str, branch: bool) -> String {
+    let number = token.trim_start_matches('#');
+    let (negative, digits) = match number.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, number),
+    };
+    let value = match digits.strip_prefix("0x") {
+        Some(hex) => i64::from_str_radix(hex, 16).ok(),
+        None if branch && !token.starts_with('#') => i64::from_str_radix(digits, 16).ok(),
+        None => digits.parse::<i64>().ok(),
+    };
+    match (value, token) {
+        (Some(value), _) if !digits.is_empty() => {
+            let value = if negative { -value } else { value };
+            value.to_string()
+        }
+        (_, "sb") => "r9".into(),
+        (_, "sl") => "r10".into(),
+        (_, "fp") => "r11".into(),
+        (_, "ip") => "r12".into(),
+        _ => token.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn gnu_and_llvm_listings() {
+        let gnu = "
+00000000 <f>:
+   0:	b580      	push	{r7, lr}
+   2:	4602      	mov	r2, r0
+   4:	c908      	ldmia	r1!, {r3}
+   6:	6802      	ldr	r2, [r0, #0]
+   8:	d0fa      	beq.n	0 <f>
+   a:	f000 f801 	bl	10 <g>
+   e:	46c0      	nop			; (mov r8, r8)
+  10:	bf00      	nop
+  12:	b100      	cbz	r0, 16 <f+0x16>
+  14:	4348      	muls	r0, r1
+  16:	811c9dc5 	.word	0x811c9dc5
+";
+        let listing = parse_listing(gnu);
+        assert_eq!(listing.len(), 11);
+        assert_eq!(listing[5].bytes, [0x00, 0xf0, 0x01, 0xf8]);
+        assert_eq!(listing[5].text, "bl 10 <g>");
+        let mismatches = compare(&listing);
+        assert_eq!(
+            mismatches,
+            [Mismatch::Rejected {
+                address: 0x12,
+                objdump: "cbz r0, 16 <f+0x16>".into(),
+                error: Error::InvalidOpCode,
+            }]
+        );
+
+        let llvm = "
+       0: ef f3 10 81  \tmrs\tr1, primask
+       4: 52 1c        \tadds\tr2, r2, #1
+       6: 4b 43        \tmuls\tr3, r1, r3
+       8: 02 a0        \tadr\tr0, #8
+       a: fb d1        \tbne\t0x4 <critical+0x4>   @ imm = #-10
+       c: 48 42        \trsbs\tr0, r1, #0
+       e: 00 29        \tcmp\tr1, #1
+      10: fe d3        \tblo\t0x10 <critical+0x10>   @ imm = #-4
+      12: ed           \t<unknown>
+";
+        let mismatches = compare(&parse_listing(llvm));
+        assert_eq!(
+            mismatches,
+            [Mismatch::Text {
+                address: 0xe,
+                objdump: "cmp r1, #1".into(),
+                text: "cmp r1, #0".into(),
+            }]
+        );
+    }
+}
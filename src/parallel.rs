@@ -0,0 +1,108 @@
+//! Parallel disassembly of large images.
+//!
+//! The image is split in chunks decoded in parallel. A chunk can start in the middle of a 32
+//! bit instruction, so when the chunks are merged in order a chunk that did not start on an
+//! instruction boundary is decoded again from the end of the previous chunk until it lines
+//! up with the parallel decoding, which happens within a few instructions.
+
+use rayon::prelude::*;
+
+use crate::{instructons::Instruction, Disassembly, Error};
+
+/// Size of the chunks decoded in parallel by [`par_disassemble`].
+pub const DEFAULT_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Instructions decoded from one chunk.
+struct Chunk {
+    instructions: Vec<(usize, Result<Instruction, Error>)>,
+    /// Offset after the last instruction.
+    end: usize,
+}
+
+/// Decodes `input` in parallel, returning the same instructions and offsets as
+/// [`crate::disassemble`].
+///
+/// # Example
+/// ```
+/// # use armv6_m_instruction_parser::{disassemble, parallel::par_disassemble};
+/// let image: Vec<u8> = [0x00, 0xf0, 0x7e, 0xf8, 0x01, 0x20].repeat(1000);
+/// let sequential: Vec<_> = disassemble(&image).collect();
+/// assert_eq!(par_disassemble(&image, 1000), sequential);
+/// ```
+pub fn par_disassemble(
+    input: &[u8],
+    chunk_size: usize,
+) -> Vec<(usize, Result<Instruction, Error>)> {
+    // Chunks start on halfwords.
+    let chunk_size = (chunk_size.max(2) + 1) & !1;
+    let chunks: Vec<Chunk> = (0..input.len())
+        .step_by(chunk_size)
+        .collect::<Vec<_>>()
+        .into_par_iter()
+        .map(|start| decode_chunk(input, start, (start + chunk_size).min(input.len())))
+        .collect();
+
+    let mut instructions = Vec::with_capacity(input.len() / 2);
+    let mut offset = 0;
+    for chunk in chunks {
+        let mut decoded = chunk.instructions.into_iter().peekable();
+        loop {
+            match decoded.peek() {
+                Some((next, _)) if *next < offset => {
+                    decoded.next();
+                }
+                Some((next, _)) if *next == offset => {
+                    instructions.extend(decoded);
+                    offset = chunk.end;
+                    break;
+                }
+                _ if offset >= chunk.end => break,
+                _ => {
+                    // Not in sync with the chunk, decode sequentially.
+                    let mut disassembly = Disassembly { input, offset };
+                    instructions.extend(disassembly.next());
+                    offset = disassembly.offset;
+                }
+            }
+        }
+    }
+    instructions
+}
+
+/// Decodes the instructions starting from `start` to `end`, the last one can end after `end`.
+fn decode_chunk(input: &[u8], start: usize, end: usize) -> Chunk {
+    let mut disassembly = Disassembly {
+        input,
+        offset: start,
+    };
+    let mut instructions = vec![];
+    while disassembly.offset < end {
+        instructions.extend(disassembly.next());
+    }
+    Chunk {
+        instructions,
+        end: disassembly.offset,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::disassemble;
+
+    #[test]
+    fn chunk_seams() {
+        // Chunks of 6 bytes split every other bl, and end with a truncated bl.
+        let mut image = [0x00, 0xf0, 0x7e, 0xf8, 0x01, 0x20, 0x00, 0xf0, 0x7e, 0xf8].repeat(50);
+        image.extend([0x00, 0xf0]);
+        let sequential: Vec<_> = disassemble(&image).collect();
+        for chunk_size in [1, 2, 4, 6, 7, 100, 10_000] {
+            assert_eq!(
+                par_disassemble(&image, chunk_size),
+                sequential,
+                "{chunk_size}"
+            );
+        }
+        assert!(par_disassemble(&[], 4).is_empty());
+    }
+}
@@ -0,0 +1,337 @@
+//! Patching of instructions in a binary.
+//!
+//! # Example
+//! ```
+//! # use armv6_m_instruction_parser::{instructons::Operation, patcher::Patcher, registers::Register};
+//! // movs r0, #1; bl 0x100
+//! let mut code = [0x01, 0x20, 0x00, 0xf0, 0x7e, 0xf8];
+//! let mut patcher = Patcher::new(&mut code);
+//! patcher
+//!     .replace(2, &Operation::MOVImm { d: Register::R1, imm: 2 })
+//!     .unwrap();
+//! // movs r0, #1; movs r1, #2; nop
+//! assert_eq!(code, [0x01, 0x20, 0x02, 0x21, 0x00, 0xbf]);
+//! ```
+
+use core::ops::Range;
+
+use crate::{
+    encoder::{encode, Encoding},
+    instruction_size,
+    instructons::Operation,
+    lpc, parse, Error,
+};
+
+/// Encoding of `nop`.
+const NOP: [u8; 2] = [0x00, 0xbf];
+
+/// Patches instructions in a mutable byte buffer holding thumb code.
+///
+/// All offsets are relative to the start of the buffer and must be halfword aligned.
+/// Operations are encoded as is, so PC relative immediates must be computed for the
+/// offset they are written to.
+#[derive(Debug)]
+pub struct Patcher<'a> {
+    bytes: &'a mut [u8],
+}
+
+impl<'a> Patcher<'a> {
+    pub fn new(bytes: &'a mut [u8]) -> Self {
+        Self { bytes }
+    }
+
+    /// Returns the patched bytes.
+    pub fn bytes(&self) -> &[u8] {
+        self.bytes
+    }
+
+    /// Returns the width in bytes of the instruction at `offset`, decided by its first
+    /// halfword only, so the instruction does not need to be valid.
+    pub fn instruction_size(&self, offset: usize) -> Result<usize, Error> {
+        let halfword = self.range(offset..offset + 2)?;
+        let size = instruction_size(u16::from_le_bytes([halfword[0], halfword[1]]));
+        self.range(offset..offset + size)?;
+        Ok(size)
+    }
+
+    /// Replaces all instructions in `range` with `nop`s.
+    pub fn nop_out(&mut self, range: Range<usize>) -> Result<(), Error> {
+        if !range.len().is_multiple_of(2) {
+            return Err(Error::UnalignedOffset);
+        }
+        for nop in self.range_mut(range)?.chunks_mut(2) {
+            nop.copy_from_slice(&NOP);
+        }
+        Ok(())
+    }
+
+    /// Replaces the instruction at `offset` with `operation`. A 16 bit operation replacing a
+    /// 32 bit instruction is followed by a `nop`. Returns [`Error::InstructionDoesNotFit`]
+    /// if a 32 bit operation would replace a 16 bit instruction.
+    pub fn replace(&mut self, offset: usize, operation: &Operation) -> Result<(), Error> {
+        let size = self.instruction_size(offset)?;
+        self.write(offset..offset + size, core::slice::from_ref(operation))
+    }
+
+    /// Writes `operations` to `range`, filling the rest of the range with `nop`s. Returns
+    /// [`Error::InstructionDoesNotFit`] if the operations need more space than the range.
+    pub fn write(&mut self, range: Range<usize>, operations: &[Operation]) -> Result<(), Error> {
+        let mut size = 0;
+        for operation in operations {
+            size += encode(operation)?.size();
+        }
+        if size > range.len() {
+            return Err(Error::InstructionDoesNotFit);
+        }
+        self.range(range.clone())?;
+        self.nop_out(range.start + size..range.end)?;
+
+        let mut offset = range.start;
+        for operation in operations {
+            let encoding = encode(operation)?;
+            let bytes = encoding.as_bytes();
+            self.bytes[offset..offset + bytes.len()].copy_from_slice(bytes);
+            offset += bytes.len();
+        }
+        Ok(())
+    }
+
+    /// Changes the destination of the `b` or `bl` at `offset` to `destination`, with `base`
+    /// being the address of the start of the buffer. See [`retarget`]. Returns
+    /// [`Error::ImmediateOutOfRange`] if `offset` is beyond the 32 bit address space.
+    pub fn retarget(&mut self, offset: usize, base: u32, destination: u32) -> Result<(), Error> {
+        let size = self.instruction_size(offset)?;
+        let instruction = parse(self.range(offset..offset + size)?)?;
+        let relative = u32::try_from(offset).map_err(|_| Error::ImmediateOutOfRange)?;
+        let address = base.wrapping_add(relative);
+        let encoding = retarget(&instruction.operation, address, destination)?;
+        self.bytes[offset..offset + size].copy_from_slice(encoding.as_bytes());
+        Ok(())
+    }
+
+    /// Writes the vector table checksum NXP LPC boot ROMs check, for buffers starting with
+    /// the vector table. See [`lpc::fix_checksum`].
+    pub fn fix_lpc_checksum(&mut self) -> Result<u32, Error> {
+        lpc::fix_checksum(self.bytes)
+    }
+
+    /// Returns an error if `offset` is not the start of an instruction, decoding from the
+    /// start of the buffer.
+    pub fn check_boundary(&self, offset: usize) -> Result<(), Error> {
+        let mut start = 0;
+        while start < offset {
+            start += self.instruction_size(start)?;
+        }
+        match start == offset {
+            true => Ok(()),
+            false => Err(Error::InsideInstruction),
+        }
+    }
+
+    fn range(&self, range: Range<usize>) -> Result<&[u8], Error> {
+        if !range.start.is_multiple_of(2) {
+            return Err(Error::UnalignedOffset);
+        }
+        self.bytes.get(range).ok_or(Error::InsufficientInput)
+    }
+
+    fn range_mut(&mut self, range: Range<usize>) -> Result<&mut [u8], Error> {
+        self.range(range.clone())?;
+        Ok(&mut self.bytes[range])
+    }
+}
+
+/// Bytes of an instruction replaced by a breakpoint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OriginalBytes {
+    offset: usize,
+    bytes: [u8; 2],
+}
+
+impl OriginalBytes {
+    /// Offset of the breakpoint.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Restores the instruction replaced by the breakpoint in `buffer`.
+    pub fn restore(&self, buffer: &mut [u8]) -> Result<(), Error> {
+        buffer
+            .get_mut(self.offset..self.offset + 2)
+            .ok_or(Error::InsufficientInput)?
+            .copy_from_slice(&self.bytes);
+        Ok(())
+    }
+}
+
+/// Replaces the instruction at `offset` in `buffer` with `bkpt #0`, returning the replaced
+/// bytes. Of a 32 bit instruction only the first halfword is replaced.
+///
+/// The buffer is decoded from the start to find the instruction boundaries, returning
+/// [`Error::InsideInstruction`] if `offset` is in the middle of a 32 bit instruction.
+///
+/// # Example
+/// ```
+/// # use armv6_m_instruction_parser::patcher::insert_bkpt;
+/// // movs r0, #1; bx lr
+/// let mut code = [0x01, 0x20, 0x70, 0x47];
+/// let original = insert_bkpt(&mut code, 2).unwrap();
+/// assert_eq!(code, [0x01, 0x20, 0x00, 0xbe]);
+/// original.restore(&mut code).unwrap();
+/// assert_eq!(code, [0x01, 0x20, 0x70, 0x47]);
+/// ```
+pub fn insert_bkpt(buffer: &mut [u8], offset: usize) -> Result<OriginalBytes, Error> {
+    let patcher = Patcher::new(buffer);
+    patcher.check_boundary(offset)?;
+    patcher.instruction_size(offset)?;
+    let original = OriginalBytes {
+        offset,
+        bytes: [buffer[offset], buffer[offset + 1]],
+    };
+    let bkpt = encode(&Operation::BKPT { imm: 0 })?;
+    buffer[offset..offset + 2].copy_from_slice(bkpt.as_bytes());
+    Ok(original)
+}
+
+/// Returns the encoding of the branch `operation` at `address` changed to branch to
+/// `destination`, keeping the condition of conditional branches. The thumb bit of
+/// `destination` is ignored. Returns [`Error::ImmediateOutOfRange`] if the destination is out
+/// of range of the branch and [`Error::InvalidOperands`] if the operation is not a `b` or `bl`.
+pub fn retarget(operation: &Operation, address: u32, destination: u32) -> Result<Encoding, Error> {
+    let imm = (destination & !1).wrapping_sub(address.wrapping_add(4));
+    match operation {
+        Operation::B { cond, .. } => encode(&Operation::B { cond: *cond, imm }),
+        Operation::BL { .. } => encode(&Operation::BL { imm }),
+        _ => Err(Error::InvalidOperands),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{conditions::Condition, parse, registers::Register};
+
+    #[test]
+    fn patching() {
+        // movs r0, #1; bl; bx lr
+        let mut code = [0x01, 0x20, 0x00, 0xf0, 0x7e, 0xf8, 0x70, 0x47];
+        let mut patcher = Patcher::new(&mut code);
+        assert_eq!(patcher.instruction_size(2), Ok(4));
+        patcher.nop_out(2..6).unwrap();
+        assert_eq!(patcher.bytes()[2..6], [0x00, 0xbf, 0x00, 0xbf]);
+
+        patcher
+            .write(
+                0..6,
+                &[
+                    Operation::MOVImm {
+                        d: Register::R0,
+                        imm: 2,
+                    },
+                    Operation::B {
+                        cond: Condition::EQ,
+                        imm: 0,
+                    },
+                ],
+            )
+            .unwrap();
+        assert_eq!(code, [0x02, 0x20, 0x00, 0xd0, 0x00, 0xbf, 0x70, 0x47]);
+
+        let mut patcher = Patcher::new(&mut code);
+        patcher
+            .replace(6, &Operation::BX { m: Register::R1 })
+            .unwrap();
+        assert_eq!(
+            parse(&patcher.bytes()[6..]).unwrap().operation,
+            Operation::BX { m: Register::R1 }
+        );
+    }
+
+    #[test]
+    fn retargeting() {
+        // bl at 0x0800_0100 to 0x0800_0200
+        let bl = Operation::BL { imm: 0xfc };
+        let encoding = retarget(&bl, 0x0800_0100, 0x0840_0001).unwrap();
+        assert_eq!(
+            parse(encoding.as_bytes()).unwrap().operation,
+            Operation::BL { imm: 0x3f_fefc }
+        );
+        let encoding = retarget(&bl, 0x0800_0100, 0x07c0_0104).unwrap();
+        assert_eq!(
+            parse(encoding.as_bytes()).unwrap().operation,
+            Operation::BL { imm: 0xffc0_0000 }
+        );
+        assert_eq!(
+            retarget(&bl, 0x0800_0100, 0x0a00_0000),
+            Err(Error::ImmediateOutOfRange)
+        );
+
+        let beq = Operation::B {
+            cond: Condition::EQ,
+            imm: 0,
+        };
+        let encoding = retarget(&beq, 0x100, 0x80).unwrap();
+        assert_eq!(encoding.as_bytes(), [0xbe, 0xd0]);
+        assert_eq!(
+            retarget(&beq, 0x100, 0x400),
+            Err(Error::ImmediateOutOfRange)
+        );
+        assert_eq!(
+            retarget(&Operation::NOP, 0x100, 0x80),
+            Err(Error::InvalidOperands)
+        );
+
+        // movs r0, #1; bl +0x100
+        let mut code = [0x01, 0x20, 0x00, 0xf0, 0x7e, 0xf8];
+        Patcher::new(&mut code)
+            .retarget(2, 0x2000_0000, 0x2000_0008)
+            .unwrap();
+        assert_eq!(
+            parse(&code[2..]).unwrap().operation,
+            Operation::BL { imm: 2 }
+        );
+
+        // Buffer mapped at the top of the address space, the b at its end wrapping to 0.
+        let mut code = [0x00, 0xbf, 0x00, 0xbf, 0xfe, 0xe7];
+        Patcher::new(&mut code)
+            .retarget(4, 0xffff_fffc, 0x10)
+            .unwrap();
+        assert_eq!(
+            parse(&code[4..]).unwrap().operation,
+            Operation::B {
+                cond: Condition::None,
+                imm: 0xc
+            }
+        );
+    }
+
+    #[test]
+    fn breakpoints() {
+        // bl; movs r0, #1
+        let mut code = [0x00, 0xf0, 0x7e, 0xf8, 0x01, 0x20];
+        assert_eq!(insert_bkpt(&mut code, 2), Err(Error::InsideInstruction));
+        assert_eq!(insert_bkpt(&mut code, 6), Err(Error::InsufficientInput));
+        let original = insert_bkpt(&mut code, 0).unwrap();
+        assert_eq!(original.offset(), 0);
+        assert_eq!(parse(&code).unwrap().operation, Operation::BKPT { imm: 0 });
+        original.restore(&mut code).unwrap();
+        assert_eq!(code, [0x00, 0xf0, 0x7e, 0xf8, 0x01, 0x20]);
+    }
+
+    #[test]
+    fn patching_errors() {
+        let mut code = [0x01, 0x20, 0x00, 0xf0, 0x7e, 0xf8];
+        let mut patcher = Patcher::new(&mut code);
+        assert_eq!(
+            patcher.replace(0, &Operation::DSB { option: 0xf }),
+            Err(Error::InstructionDoesNotFit)
+        );
+        assert_eq!(patcher.nop_out(1..3), Err(Error::UnalignedOffset));
+        assert_eq!(patcher.nop_out(4..8), Err(Error::InsufficientInput));
+        assert_eq!(
+            patcher.replace(6, &Operation::NOP),
+            Err(Error::InsufficientInput)
+        );
+        assert_eq!(code, [0x01, 0x20, 0x00, 0xf0, 0x7e, 0xf8]);
+    }
+}
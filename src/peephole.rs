@@ -0,0 +1,219 @@
+//! Peephole optimization of generated instruction sequences, for patches where every byte
+//! counts.
+//!
+//! # Example
+//! ```
+//! # use armv6_m_instruction_parser::{instructons::Operation, peephole::optimize, registers::{Register, RegisterList}};
+//! let push = |register| Operation::PUSH { reg_list: RegisterList::from([register]) };
+//! let sequence = [
+//!     push(Register::LR),
+//!     push(Register::R4),
+//!     Operation::MOVReg { m: Register::R4, d: Register::R4, set_flags: false },
+//! ];
+//! assert_eq!(
+//!     optimize(&sequence),
+//!     [Operation::PUSH { reg_list: RegisterList::from([Register::R4, Register::LR]) }]
+//! );
+//! ```
+
+use alloc::vec::Vec;
+
+use crate::{
+    instructons::Operation,
+    registers::{Register, RegisterList},
+};
+
+/// Largest immediate of `add sp, #imm` and `sub sp, #imm`.
+const MAX_SP_ADJUSTMENT: u32 = 508;
+
+/// Returns `operations` rewritten into an equivalent sequence of at most as many operations.
+///
+/// The rewrites are:
+/// - `mov rX, rX` is removed, and so is `mov rA, rB` right after `mov rB, rA`.
+/// - Consecutive `push` and `pop` are merged when the merged instruction transfers the
+///   registers to the same stack slots.
+/// - `push` followed by `pop` of the same registers, other than `pc`, is removed.
+/// - Consecutive `add sp` and `sub sp` are merged, or removed when they cancel out.
+///
+/// Instructions setting the flags are never removed. The sequence must run straight through,
+/// no branch may target its middle.
+pub fn optimize(operations: &[Operation]) -> Vec<Operation> {
+    let mut optimized: Vec<Operation> = Vec::with_capacity(operations.len());
+    for operation in operations {
+        if let Operation::MOVReg {
+            m,
+            d,
+            set_flags: false,
+        } = *operation
+        {
+            if m == d && d != Register::PC {
+                continue;
+            }
+        }
+        let Some(previous) = optimized.last() else {
+            optimized.push(operation.clone());
+            continue;
+        };
+        match combine(previous, operation) {
+            Some(Combined::Both(combined)) => {
+                optimized.pop();
+                optimized.push(combined);
+            }
+            Some(Combined::Neither) => {
+                optimized.pop();
+            }
+            Some(Combined::First) => {}
+            None => optimized.push(operation.clone()),
+        }
+    }
+    optimized
+}
+
+/// Result of combining two consecutive operations.
+enum Combined {
+    /// One operation doing both.
+    Both(Operation),
+    /// The first operation alone is equivalent.
+    First,
+    /// The operations cancel out.
+    Neither,
+}
+
+/// Returns how `first` and `second` can be combined, `None` if they cannot.
+fn combine(first: &Operation, second: &Operation) -> Option<Combined> {
+    match (first, second) {
+        (
+            &Operation::MOVReg {
+                m: first_m,
+                d: first_d,
+                set_flags: false,
+            },
+            &Operation::MOVReg {
+                m,
+                d,
+                set_flags: false,
+            },
+        ) if m == first_d && d == first_m && m != Register::PC && d != Register::PC => {
+            Some(Combined::First)
+        }
+        (&Operation::PUSH { reg_list: first }, &Operation::PUSH { reg_list: second }) => {
+            // The second push stores below the first, so its registers must be lower.
+            ordered(second, first).then(|| {
+                Combined::Both(Operation::PUSH {
+                    reg_list: union(first, second),
+                })
+            })
+        }
+        (&Operation::POP { reg_list: first }, &Operation::POP { reg_list: second }) => {
+            (ordered(first, second) && !first.contains(Register::PC)).then(|| {
+                Combined::Both(Operation::POP {
+                    reg_list: union(first, second),
+                })
+            })
+        }
+        (&Operation::PUSH { reg_list: pushed }, &Operation::POP { reg_list: popped }) => {
+            (pushed == popped && !popped.contains(Register::PC)).then_some(Combined::Neither)
+        }
+        _ => combine_sp(sp_adjustment(first)?, sp_adjustment(second)?),
+    }
+}
+
+/// Returns the operation adjusting `sp` by the sum of `first` and `second`.
+fn combine_sp(first: i32, second: i32) -> Option<Combined> {
+    let total = first + second;
+    let imm = total.unsigned_abs();
+    if imm > MAX_SP_ADJUSTMENT {
+        return None;
+    }
+    Some(match total {
+        0 => Combined::Neither,
+        1.. => Combined::Both(Operation::ADDImmSP {
+            d: Register::SP,
+            imm,
+        }),
+        _ => Combined::Both(Operation::SUBImmSP { imm }),
+    })
+}
+
+/// Returns the bytes `operation` adds to `sp` if it is `add sp, #imm` or `sub sp, #imm`.
+fn sp_adjustment(operation: &Operation) -> Option<i32> {
+    match *operation {
+        Operation::ADDImmSP {
+            d: Register::SP,
+            imm,
+        } => Some(imm as i32),
+        Operation::SUBImmSP { imm } => Some(-(imm as i32)),
+        _ => None,
+    }
+}
+
+/// Returns `true` if every register of `low` is below every register of `high`.
+fn ordered(low: RegisterList, high: RegisterList) -> bool {
+    match (low.iter().last(), high.iter().next()) {
+        (Some(low), Some(high)) => (low as u8) < (high as u8),
+        _ => true,
+    }
+}
+
+fn union(first: RegisterList, second: RegisterList) -> RegisterList {
+    RegisterList::from_bits(first.bits() | second.bits())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn rewrites() {
+        let list = |registers: &[Register]| registers.iter().copied().collect::<RegisterList>();
+        let mov = |m, d| Operation::MOVReg {
+            m,
+            d,
+            set_flags: false,
+        };
+        let sequence = [
+            Operation::SUBImmSP { imm: 8 },
+            Operation::SUBImmSP { imm: 16 },
+            Operation::PUSH {
+                reg_list: list(&[Register::R2]),
+            },
+            Operation::POP {
+                reg_list: list(&[Register::R2]),
+            },
+            Operation::ADDImmSP {
+                d: Register::SP,
+                imm: 24,
+            },
+            mov(Register::R1, Register::R8),
+            mov(Register::R8, Register::R1),
+            Operation::POP {
+                reg_list: list(&[Register::R4]),
+            },
+            Operation::POP {
+                reg_list: list(&[Register::R5, Register::PC]),
+            },
+            // Not merged, the registers would be loaded from other slots.
+            Operation::PUSH {
+                reg_list: list(&[Register::R1]),
+            },
+            Operation::PUSH {
+                reg_list: list(&[Register::R2]),
+            },
+        ];
+        assert_eq!(
+            optimize(&sequence),
+            [
+                mov(Register::R1, Register::R8),
+                Operation::POP {
+                    reg_list: list(&[Register::R4, Register::R5, Register::PC]),
+                },
+                Operation::PUSH {
+                    reg_list: list(&[Register::R1]),
+                },
+                Operation::PUSH {
+                    reg_list: list(&[Register::R2]),
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,183 @@
+//! Profiles from sampled program counter values, like those of the DWT PC sampling.
+//!
+//! # Example
+//! ```
+//! # use armv6_m_instruction_parser::{functions::functions, profile::{Category, Profile}, program::Program};
+//! // push {r4, lr}; bl 0x08; pop {r4, pc}
+//! // 0x08: wfi; bx lr
+//! let image = [0x10, 0xb5, 0x00, 0xf0, 0x01, 0xf8, 0x10, 0xbd, 0x30, 0xbf, 0x70, 0x47];
+//! let program = Program::new(&image, 0);
+//! let profile = Profile::new(&program, &functions(&program), [0x08, 0x08, 0x02, 0x100]);
+//! assert_eq!(profile.functions[&0x08], 2);
+//! assert_eq!(profile.categories[&Category::System], 2);
+//! assert_eq!(profile.outside, 1);
+//! ```
+
+use alloc::collections::BTreeMap;
+
+use crate::{
+    functions::FunctionRange,
+    instructons::{AccessDirection, Operation},
+    program::Program,
+};
+
+/// Kind of instruction a sample hit, see [`category`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Category {
+    /// Arithmetic, logic, moves, shifts, compares and extends.
+    DataProcessing,
+    Load,
+    Store,
+    /// Branches and returns.
+    Branch,
+    Call,
+    /// Barriers, hints like `wfi`, and special register and exception instructions.
+    System,
+    /// Halfwords not decoding to an instruction.
+    Undefined,
+}
+
+/// Returns the category of `operation`.
+///
+/// Calls and instructions writing `pc`, like `pop {..., pc}`, are counted as such rather than
+/// as loads.
+pub fn category(operation: &Operation) -> Category {
+    if operation.is_call() {
+        return Category::Call;
+    }
+    if operation.modifies_pc() {
+        return Category::Branch;
+    }
+    if let Some(access) = operation.memory_access() {
+        return match access.direction {
+            AccessDirection::Load => Category::Load,
+            AccessDirection::Store => Category::Store,
+        };
+    }
+    match operation {
+        Operation::BKPT { .. }
+        | Operation::CPS { .. }
+        | Operation::DMB { .. }
+        | Operation::DSB { .. }
+        | Operation::ISB { .. }
+        | Operation::MRS { .. }
+        | Operation::MSRReg { .. }
+        | Operation::NOP
+        | Operation::SEV
+        | Operation::SVC { .. }
+        | Operation::WFE
+        | Operation::WFI
+        | Operation::YIELD => Category::System,
+        Operation::UDF { .. } => Category::Undefined,
+        _ => Category::DataProcessing,
+    }
+}
+
+/// Hit counts of program counter samples.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Profile {
+    /// Number of samples added.
+    pub samples: u64,
+    /// Samples outside the program, e.g. in code running from RAM or the boot ROM.
+    pub outside: u64,
+    /// Samples in the program, by start of the function containing them.
+    pub functions: BTreeMap<u32, u64>,
+    /// Samples in the program outside every function.
+    pub unattributed: u64,
+    /// Samples in the program, by category of the instruction.
+    pub categories: BTreeMap<Category, u64>,
+}
+
+impl Profile {
+    /// Returns the profile of the `samples` of code in `program` with `functions`, in
+    /// address order as returned by [`functions`](crate::functions::functions).
+    pub fn new(
+        program: &Program,
+        functions: &[FunctionRange],
+        samples: impl IntoIterator<Item = u32>,
+    ) -> Self {
+        let mut profile = Self::default();
+        for sample in samples {
+            profile.add(program, functions, sample);
+        }
+        profile
+    }
+
+    /// Counts the sample `pc`, the Thumb bit is ignored.
+    ///
+    /// Samples in the second halfword of a 32 bit instruction are counted for that
+    /// instruction.
+    pub fn add(&mut self, program: &Program, functions: &[FunctionRange], pc: u32) {
+        self.samples += 1;
+        let pc = pc & !1;
+        let containing = program
+            .range(pc.saturating_sub(2)..=pc)
+            .find(|(address, result)| {
+                let size = result.as_ref().map_or(2, |instruction| instruction.size());
+                pc < address.wrapping_add(size)
+            });
+        let category = match containing {
+            Some((_, Ok(instruction))) => category(&instruction.operation),
+            Some((_, Err(_))) => Category::Undefined,
+            None => {
+                self.outside += 1;
+                return;
+            }
+        };
+        *self.categories.entry(category).or_default() += 1;
+
+        let index = functions.partition_point(|function| function.start <= pc);
+        match index.checked_sub(1).map(|index| &functions[index]) {
+            Some(function) if pc < function.end => {
+                *self.functions.entry(function.start).or_default() += 1
+            }
+            _ => self.unattributed += 1,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn hit_counts() {
+        // 0x00: push {r4, lr}; bl 0x0a; pop {r4, pc}; udf #0
+        // 0x0a: ldr r0, [r1]; adds r0, #1; bx lr
+        let image = [
+            0x10, 0xb5, 0x00, 0xf0, 0x02, 0xf8, 0x10, 0xbd, 0x00, 0xde, //
+            0x08, 0x68, 0x01, 0x30, 0x70, 0x47,
+        ];
+        let program = Program::new(&image, 0x100);
+        let functions = [
+            FunctionRange {
+                start: 0x100,
+                end: 0x108,
+                called: false,
+                saves_lr: true,
+            },
+            FunctionRange {
+                start: 0x10a,
+                end: 0x110,
+                called: true,
+                saves_lr: false,
+            },
+        ];
+        let samples = [0x105, 0x10a, 0x10c, 0x10c, 0x108, 0x106, 0x80];
+        let profile = Profile::new(&program, &functions, samples);
+        assert_eq!(profile.samples, 7);
+        assert_eq!(profile.outside, 1);
+        assert_eq!(profile.unattributed, 1);
+        assert_eq!(profile.functions, BTreeMap::from([(0x100, 2), (0x10a, 3)]));
+        assert_eq!(
+            profile.categories,
+            BTreeMap::from([
+                (Category::DataProcessing, 2),
+                (Category::Load, 1),
+                (Category::Branch, 1),
+                (Category::Call, 1),
+                (Category::Undefined, 1),
+            ])
+        );
+    }
+}
@@ -0,0 +1,187 @@
+//! Whole image disassembly held in memory, for analysis passes over all instructions.
+//!
+//! # Example
+//! ```
+//! # use armv6_m_instruction_parser::{instructons::Operation, program::Program};
+//! // push {r7, lr}; bl; pop {r7, pc}
+//! let image = [0x80, 0xb5, 0x00, 0xf0, 0x7e, 0xf8, 0x80, 0xbd];
+//! let program = Program::new(&image, 0x0800_0000);
+//! assert_eq!(program.len(), 3);
+//! assert_eq!(
+//!     program.at(0x0800_0002).map(|instruction| &instruction.operation),
+//!     Some(&Operation::BL { imm: 0xfc })
+//! );
+//! assert_eq!(program.at(0x0800_0004), None);
+//! let calls = program.range(0x0800_0002..).count();
+//! assert_eq!(calls, 2);
+//! ```
+
+use alloc::vec::Vec;
+use core::{
+    ops::{Bound, RangeBounds},
+    slice,
+};
+
+use crate::{disassemble, instructons::Instruction, Error};
+
+/// Decoded instructions of an image, stored contiguously in address order.
+///
+/// The addresses and the decoded instructions are kept in separate arrays, so passes looking
+/// only at the instructions or searching addresses touch as little memory as possible.
+/// Instructions that failed to decode are kept with their error, so the program covers the
+/// whole image.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Program {
+    base: u32,
+    addresses: Vec<u32>,
+    instructions: Vec<Result<Instruction, Error>>,
+}
+
+impl Program {
+    /// Disassembles `image` placed at `base`, see [`disassemble`].
+    pub fn new(image: &[u8], base: u32) -> Self {
+        let (addresses, instructions) = disassemble(image)
+            .map(|(offset, result)| (base.wrapping_add(offset as u32), result))
+            .unzip();
+        Self {
+            base,
+            addresses,
+            instructions,
+        }
+    }
+
+    /// Address of the start of the image.
+    pub fn base(&self) -> u32 {
+        self.base
+    }
+
+    /// Number of instructions, including the ones that failed to decode.
+    pub fn len(&self) -> usize {
+        self.instructions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.instructions.is_empty()
+    }
+
+    /// Returns the instruction starting at `address`, `None` if no instruction starts there
+    /// or it failed to decode.
+    pub fn at(&self, address: u32) -> Option<&Instruction> {
+        self.instructions[self.index_of(address)?].as_ref().ok()
+    }
+
+    /// Returns the position of the instruction starting at `address`.
+    pub fn index_of(&self, address: u32) -> Option<usize> {
+        self.addresses.binary_search(&address).ok()
+    }
+
+    /// Returns the address and instruction at position `index`.
+    pub fn get(&self, index: usize) -> Option<(u32, &Result<Instruction, Error>)> {
+        Some((*self.addresses.get(index)?, self.instructions.get(index)?))
+    }
+
+    /// Decoded instructions in address order, without their addresses.
+    pub fn instructions(&self) -> &[Result<Instruction, Error>] {
+        &self.instructions
+    }
+
+    /// Returns the instructions with their addresses.
+    pub fn iter(&self) -> Iter<'_> {
+        Iter {
+            addresses: self.addresses.iter(),
+            instructions: self.instructions.iter(),
+        }
+    }
+
+    /// Returns the instructions starting in `range` of addresses.
+    pub fn range(&self, range: impl RangeBounds<u32>) -> Iter<'_> {
+        let start = match range.start_bound() {
+            Bound::Included(start) => self.addresses.partition_point(|address| address < start),
+            Bound::Excluded(start) => self.addresses.partition_point(|address| address <= start),
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(end) => self.addresses.partition_point(|address| address <= end),
+            Bound::Excluded(end) => self.addresses.partition_point(|address| address < end),
+            Bound::Unbounded => self.addresses.len(),
+        };
+        let end = end.max(start);
+        Iter {
+            addresses: self.addresses[start..end].iter(),
+            instructions: self.instructions[start..end].iter(),
+        }
+    }
+}
+
+impl<'a> IntoIterator for &'a Program {
+    type Item = (u32, &'a Result<Instruction, Error>);
+    type IntoIter = Iter<'a>;
+
+    fn into_iter(self) -> Iter<'a> {
+        self.iter()
+    }
+}
+
+/// Iterator over instructions of a [`Program`] with their addresses.
+#[derive(Debug, Clone)]
+pub struct Iter<'a> {
+    addresses: slice::Iter<'a, u32>,
+    instructions: slice::Iter<'a, Result<Instruction, Error>>,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = (u32, &'a Result<Instruction, Error>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        Some((*self.addresses.next()?, self.instructions.next()?))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.addresses.size_hint()
+    }
+}
+
+impl DoubleEndedIterator for Iter<'_> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        Some((*self.addresses.next_back()?, self.instructions.next_back()?))
+    }
+}
+
+impl ExactSizeIterator for Iter<'_> {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::instructons::Operation;
+
+    #[test]
+    fn lookup() {
+        // movs r0, #1; invalid 32 bit; bl; bx lr
+        let image = [
+            0x01, 0x20, 0xff, 0xf7, 0x00, 0x00, 0x00, 0xf0, 0x7e, 0xf8, 0x70, 0x47,
+        ];
+        let program = Program::new(&image, 0x100);
+        let addresses: Vec<_> = program.iter().map(|(address, _)| address).collect();
+        assert_eq!(addresses, [0x100, 0x102, 0x106, 0x10a]);
+        assert_eq!(program.index_of(0x106), Some(2));
+        assert_eq!(program.index_of(0x108), None);
+        assert_eq!(program.at(0x102), None);
+        assert!(program.get(1).unwrap().1.is_err());
+        assert_eq!(
+            program.at(0x10a).map(|instruction| &instruction.operation),
+            Some(&Operation::BX {
+                m: crate::registers::Register::LR
+            })
+        );
+
+        let in_range = |range: Iter<'_>| range.map(|(address, _)| address).collect::<Vec<_>>();
+        assert_eq!(in_range(program.range(0x101..0x10a)), [0x102, 0x106]);
+        assert_eq!(
+            in_range(program.range(0x102..=0x10a)),
+            [0x102, 0x106, 0x10a]
+        );
+        assert_eq!(in_range(program.range(..0x100)), []);
+        assert_eq!(program.range(0x106..).next_back().unwrap().0, 0x10a);
+    }
+}
@@ -0,0 +1,282 @@
+//! Constant propagation through the registers of a function, resolving indirect branches and
+//! memory accesses to addresses.
+//!
+//! # Example
+//! ```
+//! # use armv6_m_instruction_parser::{control_flow::ControlFlowGraph, functions::FunctionRange, program::Program, propagation::propagate, registers::Register};
+//! // 0x00: movs r3, #1; lsls r3, r3, #4; adds r3, #1; ldr r2, [pc, #4]; str r3, [r2, #4]
+//! // 0x0a: bx r3; .word 0x40000000
+//! let image = [
+//!     0x01, 0x23, 0x1b, 0x01, 0x01, 0x33, 0x01, 0x4a, 0x53, 0x60, 0x18, 0x47, 0x00, 0x00,
+//!     0x00, 0x40,
+//! ];
+//! let program = Program::new(&image, 0);
+//! let cfg = ControlFlowGraph::new(&program, &[]);
+//! let function = FunctionRange { start: 0, end: 0x0c, called: false, saves_lr: false };
+//! let values = propagate(&program, &image, &cfg, &function);
+//! assert_eq!(values.before(0x0a).unwrap().get(Register::R3), Some(0x11));
+//! assert_eq!(values.memory_address(&program, 0x08), Some(0x4000_0004));
+//! assert_eq!(values.branch_target(&program, 0x0a), Some(0x10));
+//! ```
+
+use crate::{
+    control_flow::ControlFlowGraph,
+    dataflow::{solve, Analysis, Solution},
+    functions::FunctionRange,
+    instructons::{AccessOffset, Instruction, Operation},
+    program::Program,
+    registers::Register,
+};
+
+/// Values of the registers known at an instruction, `None` for unknown values.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RegisterValues([Option<u32>; 16]);
+
+impl RegisterValues {
+    /// Returns the value of `register`, `pc` is never known.
+    pub fn get(&self, register: Register) -> Option<u32> {
+        self.0[register as usize]
+    }
+
+    pub fn set(&mut self, register: Register, value: Option<u32>) {
+        if register != Register::PC {
+            self.0[register as usize] = value;
+        }
+    }
+
+    /// Forgets the values differing in `other`, returning `true` if any value was forgotten.
+    fn meet(&mut self, other: &RegisterValues) -> bool {
+        let mut changed = false;
+        for (value, other) in self.0.iter_mut().zip(other.0) {
+            if value.is_some() && *value != other {
+                *value = None;
+                changed = true;
+            }
+        }
+        changed
+    }
+}
+
+/// Register values known before the instructions of a function, from [`propagate`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Propagation {
+    solution: Solution<RegisterValues>,
+}
+
+impl Propagation {
+    /// Returns the values before the instruction at `address`, `None` if the instruction is
+    /// not reached from the start of the function.
+    pub fn before(&self, address: u32) -> Option<&RegisterValues> {
+        self.solution.before(address)
+    }
+
+    /// Returns the destination of the `bx`, `blx`, `mov pc` or `add pc` at `address`, if
+    /// the register it jumps to is known.
+    pub fn branch_target(&self, program: &Program, address: u32) -> Option<u32> {
+        let values = self.before(address)?;
+        let target = match program.at(address)?.operation {
+            Operation::BX { m }
+            | Operation::BLXReg { m }
+            | Operation::MOVReg {
+                m, d: Register::PC, ..
+            } => values.get(m)?,
+            Operation::ADDReg {
+                m,
+                n: Register::PC,
+                d: Register::PC,
+                ..
+            } => address.wrapping_add(4).wrapping_add(values.get(m)?),
+            _ => return None,
+        };
+        Some(target & !1)
+    }
+
+    /// Returns the address of the first transfer of the load or store at `address`, if the
+    /// registers it is computed from are known.
+    pub fn memory_address(&self, program: &Program, address: u32) -> Option<u32> {
+        let values = self.before(address)?;
+        let access = program.at(address)?.operation.memory_access()?;
+        let base = match access.base {
+            Register::PC => address.wrapping_add(4) & !0b11,
+            base => values.get(base)?,
+        };
+        Some(match access.offset {
+            AccessOffset::Immediate(imm) => base.wrapping_add(imm),
+            AccessOffset::Register(m) => base.wrapping_add(values.get(m)?),
+            AccessOffset::IncrementAfter(_) => base,
+            AccessOffset::DecrementBefore(reg_list) => base.wrapping_sub(4 * reg_list.len() as u32),
+        })
+    }
+}
+
+/// Propagates constants through the registers of `function` in `program`, disassembled from
+/// `image`, along the blocks of `cfg`.
+///
+/// Values are set by `movs`, `adr` and literal loads and computed through moves, arithmetic,
+/// shifts and logical operations. Where paths meet, only the values equal on all of them are
+/// kept. Register values are unknown at the start of the function and memory is not tracked,
+/// calls forget the registers the AAPCS lets the called function change.
+pub fn propagate(
+    program: &Program,
+    image: &[u8],
+    cfg: &ControlFlowGraph,
+    function: &FunctionRange,
+) -> Propagation {
+    Propagation {
+        solution: solve(&Constants { program, image }, program, cfg, function),
+    }
+}
+
+/// Constant propagation as a dataflow analysis.
+struct Constants<'a> {
+    program: &'a Program,
+    image: &'a [u8],
+}
+
+impl Analysis for Constants<'_> {
+    type State = RegisterValues;
+
+    fn entry(&self) -> RegisterValues {
+        RegisterValues::default()
+    }
+
+    fn join(&self, state: &mut RegisterValues, other: &RegisterValues) -> bool {
+        state.meet(other)
+    }
+
+    fn transfer(&self, state: &mut RegisterValues, address: u32, instruction: &Instruction) {
+        step(
+            state,
+            &instruction.operation,
+            address,
+            self.program,
+            self.image,
+        );
+    }
+}
+
+/// Updates `values` with the effect of `operation` at `address`.
+fn step(
+    values: &mut RegisterValues,
+    operation: &Operation,
+    address: u32,
+    program: &Program,
+    image: &[u8],
+) {
+    let pc = address.wrapping_add(4);
+    let get = |register: Register| match register {
+        Register::PC => Some(pc),
+        register => values.get(register),
+    };
+    let binary = |m: Register, n: Register, f: fn(u32, u32) -> u32| Some(f(get(n)?, get(m)?));
+    let result = match *operation {
+        Operation::MOVImm { d, imm } => Some((d, Some(imm))),
+        Operation::MOVReg { m, d, .. } => Some((d, get(m))),
+        Operation::MVNReg { m, d } => Some((d, get(m).map(|value| !value))),
+        Operation::RSBImm { n, d } => Some((d, get(n).map(u32::wrapping_neg))),
+        Operation::ADDImm { imm, n, d } => Some((d, get(n).map(|value| value.wrapping_add(imm)))),
+        Operation::SUBImm { imm, n, d } => Some((d, get(n).map(|value| value.wrapping_sub(imm)))),
+        Operation::ADDReg { m, n, d, .. } => Some((d, binary(m, n, u32::wrapping_add))),
+        Operation::SUBReg { m, n, d } => Some((d, binary(m, n, u32::wrapping_sub))),
+        Operation::MUL { n, dm } => Some((dm, binary(n, dm, u32::wrapping_mul))),
+        Operation::ANDReg { m, dn } => Some((dn, binary(m, dn, |n, m| n & m))),
+        Operation::ORRReg { m, dn } => Some((dn, binary(m, dn, |n, m| n | m))),
+        Operation::EORReg { m, dn } => Some((dn, binary(m, dn, |n, m| n ^ m))),
+        Operation::BICReg { m, dn } => Some((dn, binary(m, dn, |n, m| n & !m))),
+        Operation::LSLImm { m, d, .. }
+        | Operation::LSRImm { m, d, .. }
+        | Operation::ASRImm { m, d, .. } => Some((
+            d,
+            get(m).and_then(|value| shift_immediate(operation, value)),
+        )),
+        Operation::ADR { d, imm } => Some((d, Some((pc & !0b11).wrapping_add(imm)))),
+        Operation::LDRLiteral { t, imm } => {
+            let literal = (pc & !0b11).wrapping_add(imm);
+            let value = literal
+                .checked_sub(program.base())
+                .and_then(|offset| image.get(offset as usize..offset as usize + 4))
+                .map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]]));
+            Some((t, value))
+        }
+        _ => None,
+    };
+
+    for register in operation.registers_written() {
+        values.set(register, None);
+    }
+    if operation.is_call() {
+        use Register::*;
+        for register in [R0, R1, R2, R3, R12] {
+            values.set(register, None);
+        }
+    }
+    if let Some((register, value)) = result {
+        values.set(register, value);
+    }
+}
+
+/// Returns `value` shifted by `operation`, if it is a shift by an immediate, the immediate 0
+/// of right shifts meaning 32.
+pub(crate) fn shift_immediate(operation: &Operation, value: u32) -> Option<u32> {
+    match *operation {
+        Operation::LSLImm { imm, .. } => Some(value.checked_shl(imm).unwrap_or(0)),
+        Operation::LSRImm { imm: 0, .. } => Some(0),
+        Operation::LSRImm { imm, .. } => Some(value >> imm),
+        Operation::ASRImm { imm: 0, .. } => Some(((value as i32) >> 31) as u32),
+        Operation::ASRImm { imm, .. } => Some(((value as i32) >> imm) as u32),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn merges_and_calls() {
+        // 0x00: movs r0, #1; movs r4, #7; cmp r1, #0; beq.n 0x0a; movs r0, #2
+        // 0x0a: bl 0x10; bx lr
+        // 0x10: bx lr
+        let image = [
+            0x01, 0x20, 0x07, 0x24, 0x00, 0x29, 0x00, 0xd0, 0x02, 0x20, //
+            0x00, 0xf0, 0x01, 0xf8, 0x70, 0x47, 0x70, 0x47,
+        ];
+        let program = Program::new(&image, 0);
+        let cfg = ControlFlowGraph::new(&program, &[]);
+        let function = FunctionRange {
+            start: 0,
+            end: 0x10,
+            called: false,
+            saves_lr: false,
+        };
+        let values = propagate(&program, &image, &cfg, &function);
+        assert_eq!(values.before(0x08).unwrap().get(Register::R0), Some(1));
+        let merged = values.before(0x0a).unwrap();
+        assert_eq!(merged.get(Register::R0), None);
+        assert_eq!(merged.get(Register::R4), Some(7));
+        let after_call = values.before(0x0e).unwrap();
+        assert_eq!(after_call.get(Register::R4), Some(7));
+        assert_eq!(after_call.get(Register::LR), None);
+        assert_eq!(values.branch_target(&program, 0x0e), None);
+        assert!(values.before(0x10).is_none());
+    }
+
+    #[test]
+    fn shifts_by_32() {
+        // movs r0, #1; lsls r0, r0, #31; lsrs r1, r0, #32; asrs r2, r0, #32; bx lr
+        let image = [0x01, 0x20, 0xc0, 0x07, 0x01, 0x08, 0x02, 0x10, 0x70, 0x47];
+        let program = Program::new(&image, 0);
+        let cfg = ControlFlowGraph::new(&program, &[]);
+        let function = FunctionRange {
+            start: 0,
+            end: 0x0a,
+            called: false,
+            saves_lr: false,
+        };
+        let values = propagate(&program, &image, &cfg, &function);
+        let before = values.before(0x08).unwrap();
+        assert_eq!(before.get(Register::R0), Some(0x8000_0000));
+        assert_eq!(before.get(Register::R1), Some(0));
+        assert_eq!(before.get(Register::R2), Some(0xffff_ffff));
+    }
+}
@@ -0,0 +1,624 @@
+//! Pseudo-C view of basic blocks, the micro-ops of their instructions rendered as C
+//! statements with the known register values folded in, for quick triage rather than full
+//! decompilation.
+//!
+//! Shifts by a register are rendered as calls, `lsl(r0, r1 & 0xff)`, with the results of the
+//! instructions for the amounts of 32 or more that C leaves undefined.
+//!
+//! # Example
+//! ```
+//! # use armv6_m_instruction_parser::{control_flow::ControlFlowGraph, functions::FunctionRange, program::Program, propagation::propagate, pseudo_c::render_function};
+//! // movs r0, #5; loop: subs r0, #1; bne loop; bx lr
+//! let image = [0x05, 0x20, 0x01, 0x38, 0xfd, 0xd1, 0x70, 0x47];
+//! let program = Program::new(&image, 0);
+//! let cfg = ControlFlowGraph::new(&program, &[]);
+//! let function = FunctionRange { start: 0, end: 8, called: false, saves_lr: false };
+//! let propagation = propagate(&program, &image, &cfg, &function);
+//! assert_eq!(
+//!     render_function(&program, &cfg, &function, &propagation),
+//!     "L_0:\n    r0 = 5;\nL_2:\n    r0 = r0 - 1;\n    if (r0 != 0) goto L_2;\nL_6:\n    return;\n"
+//! );
+//! ```
+
+use alloc::{boxed::Box, format, string::String, vec::Vec};
+use core::{
+    fmt::{self, Display, Formatter},
+    ops::Range,
+};
+
+use crate::{
+    conditions::Condition,
+    control_flow::ControlFlowGraph,
+    functions::FunctionRange,
+    instructons::{AccessSize, DecodedAt, Operation},
+    micro_ops::{BranchKind, Lowering, MicroOp, Operand},
+    program::Program,
+    propagation::{Propagation, RegisterValues},
+    registers::{Register, SpecialRegister},
+    semantics::{BinaryOperation, Event, Flag, Shift},
+};
+
+/// Expression of a value computed in a block.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Expression {
+    Constant(u32),
+    Register(Register),
+    Flag(Flag),
+    Special(SpecialRegister),
+    /// Value held in a local, for register writes read by a later write of the same
+    /// instruction.
+    Local(u32),
+    Load(AccessSize, Box<Expression>),
+    Binary(BinaryOperation, Box<Expression>, Box<Expression>),
+    /// Rotation, or shift by an amount not known, rendered as a call for the amounts of 32
+    /// or more.
+    Shift(Shift, Box<Expression>, Box<Expression>),
+    Not(Box<Expression>),
+    /// The low bits of the value, sign extended.
+    SignExtend(u32, Box<Expression>),
+}
+
+impl Expression {
+    /// Returns the expression of `x operation y`, constants folded.
+    fn binary(operation: BinaryOperation, x: Expression, y: Expression) -> Expression {
+        use BinaryOperation::*;
+        use Expression::Constant;
+
+        match (operation, x, y) {
+            (operation, Constant(x), Constant(y)) => Constant(match operation {
+                Add => x.wrapping_add(y),
+                Sub => x.wrapping_sub(y),
+                Mul => x.wrapping_mul(y),
+                And => x & y,
+                Or => x | y,
+                Xor => x ^ y,
+                Lsl => x.checked_shl(y & 0xff).unwrap_or(0),
+                Lsr => x.checked_shr(y & 0xff).unwrap_or(0),
+                Asr => ((x as i32) >> (y & 0xff).min(31)) as u32,
+            }),
+            (Add | Sub | Or | Xor | Lsl | Lsr | Asr, x, Constant(0)) => x,
+            (Lsl | Lsr, _, Constant(32..)) => Constant(0),
+            (Asr, x, Constant(32..)) => Expression::binary(Asr, x, Constant(31)),
+            // Offsets from offsets, like the addresses of `push`.
+            (Add | Sub, Expression::Binary(inner @ (Add | Sub), x, offset), Constant(y)) => {
+                let Constant(offset) = *offset else {
+                    return Expression::Binary(
+                        operation,
+                        Box::new(Expression::Binary(inner, x, offset)),
+                        Box::new(Constant(y)),
+                    );
+                };
+                let signed = |operation, value: u32| match operation {
+                    Sub => value.wrapping_neg(),
+                    _ => value,
+                };
+                let total = signed(inner, offset).wrapping_add(signed(operation, y));
+                match total as i32 {
+                    0 => *x,
+                    total if total < 0 => {
+                        Expression::binary(Sub, *x, Constant(total.unsigned_abs()))
+                    }
+                    _ => Expression::binary(Add, *x, Constant(total)),
+                }
+            }
+            (Xor, x, Constant(u32::MAX)) => Expression::not(x),
+            // The sign extension of the lowering, a shift left and back.
+            (Asr, Expression::Binary(Lsl, x, shift), Constant(y)) if *shift == Constant(y) => {
+                Expression::SignExtend(32 - y, x)
+            }
+            (operation @ (Lsl | Lsr | Asr), x, y) if !matches!(y, Constant(_)) => {
+                let shift = match operation {
+                    Lsl => Shift::Lsl,
+                    Lsr => Shift::Lsr,
+                    _ => Shift::Asr,
+                };
+                Expression::Shift(shift, Box::new(x), Box::new(y))
+            }
+            (operation, x, y) => Expression::Binary(operation, Box::new(x), Box::new(y)),
+        }
+    }
+
+    fn not(x: Expression) -> Expression {
+        match x {
+            Expression::Constant(x) => Expression::Constant(!x),
+            Expression::Not(x) => *x,
+            x => Expression::Not(Box::new(x)),
+        }
+    }
+
+    /// Returns `true` if the expression reads `register`.
+    fn reads(&self, register: Register) -> bool {
+        match self {
+            Expression::Register(read) => *read == register,
+            Expression::Load(_, x) | Expression::Not(x) | Expression::SignExtend(_, x) => {
+                x.reads(register)
+            }
+            Expression::Binary(_, x, y) | Expression::Shift(_, x, y) => {
+                x.reads(register) || y.reads(register)
+            }
+            _ => false,
+        }
+    }
+
+    /// Formats the expression as an operand of an operator, in parentheses if compound.
+    fn operand(&self) -> String {
+        match self {
+            Expression::Binary(..) => format!("({self})"),
+            expression => format!("{expression}"),
+        }
+    }
+}
+
+impl Display for Expression {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Expression::Constant(value) if *value < 10 => write!(f, "{value}"),
+            Expression::Constant(value) => write!(f, "{value:#x}"),
+            Expression::Register(register) => write!(f, "{}", name(*register)),
+            Expression::Flag(flag) => f.write_str(match flag {
+                Flag::N => "negative",
+                Flag::Z => "zero",
+                Flag::C => "carry",
+                Flag::V => "overflow",
+            }),
+            Expression::Special(register) => {
+                write!(f, "{}", format!("{register:?}").to_lowercase())
+            }
+            Expression::Local(index) => write!(f, "tmp{index}"),
+            Expression::Load(size, address) => {
+                write!(f, "*({} *){}", unsigned(*size), address.operand())
+            }
+            Expression::Binary(BinaryOperation::Asr, x, y) => {
+                write!(f, "(int32_t){} >> {}", x.operand(), y.operand())
+            }
+            Expression::Binary(operation, x, y) => {
+                let operator = match operation {
+                    BinaryOperation::Add => "+",
+                    BinaryOperation::Sub => "-",
+                    BinaryOperation::Mul => "*",
+                    BinaryOperation::And => "&",
+                    BinaryOperation::Or => "|",
+                    BinaryOperation::Xor => "^",
+                    BinaryOperation::Lsl => "<<",
+                    BinaryOperation::Lsr | BinaryOperation::Asr => ">>",
+                };
+                write!(f, "{} {operator} {}", x.operand(), y.operand())
+            }
+            Expression::Shift(shift, x, y) => {
+                let function = match shift {
+                    Shift::Lsl => "lsl",
+                    Shift::Lsr => "lsr",
+                    Shift::Asr => "asr",
+                    Shift::Ror => "ror",
+                };
+                write!(f, "{function}({x}, {y})")
+            }
+            Expression::Not(x) => write!(f, "~{}", x.operand()),
+            Expression::SignExtend(bits, x) => write!(f, "(int{bits}_t){}", x.operand()),
+        }
+    }
+}
+
+/// What the flags were last set from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Flags {
+    Unknown,
+    /// N and Z from a result.
+    Result(Expression),
+    /// All flags from `x - y`, with its result.
+    Compare(Expression, Expression, Expression),
+}
+
+/// Rendering of the micro-ops of a block as statements.
+struct Renderer {
+    lowering: Lowering,
+    /// Expressions of the temporaries of the lowering.
+    temporaries: Vec<Expression>,
+    /// Subtractions by the temporary of their carry out.
+    subtractions: Vec<(u32, Expression, Expression)>,
+    values: RegisterValues,
+    flags: Flags,
+    locals: u32,
+    lines: Vec<String>,
+}
+
+impl Renderer {
+    fn operand(&self, operand: Operand) -> Expression {
+        match operand {
+            Operand::Register(register) => match self.values.get(register) {
+                Some(value) => Expression::Constant(value),
+                None => Expression::Register(register),
+            },
+            Operand::Temporary(temporary) => self.temporaries[temporary as usize].clone(),
+            Operand::Constant(value) => Expression::Constant(value),
+            Operand::Flag(flag) => Expression::Flag(flag),
+            // Conditions are only branched on, see `condition`.
+            Operand::Condition(_) => Expression::Constant(1),
+        }
+    }
+
+    fn define(&mut self, temporary: u32, expression: Expression) {
+        let index = temporary as usize;
+        if self.temporaries.len() <= index {
+            self.temporaries.resize(index + 1, Expression::Constant(0));
+        }
+        self.temporaries[index] = expression;
+    }
+
+    fn line(&mut self, line: String) {
+        self.lines.push(format!("    {line}"));
+    }
+
+    /// Renders the instruction `decoded`.
+    fn instruction(&mut self, decoded: &DecodedAt) {
+        let start = self.lowering.micro_ops().len();
+        self.lowering.lower(decoded);
+        let micro_ops = self.lowering.micro_ops()[start..].to_vec();
+        let mut writes = Vec::new();
+        let mut branch = None;
+        for micro_op in micro_ops {
+            match micro_op {
+                MicroOp::Write { register, value } => writes.push((register, self.operand(value))),
+                MicroOp::Branch {
+                    condition,
+                    target,
+                    kind,
+                } => branch = Some((condition, self.operand(target), kind)),
+                micro_op => self.compute(micro_op),
+            }
+        }
+        let operation = &decoded.instruction.operation;
+        let call = matches!(operation, Operation::BL { .. } | Operation::BLXReg { .. });
+        if call {
+            writes.retain(|(register, _)| *register != Register::LR);
+        }
+        let returns = match &branch {
+            Some((_, Expression::Register(Register::LR), BranchKind::Exchange)) => true,
+            Some(_) => matches!(operation, Operation::POP { .. }),
+            None => false,
+        };
+        let target = branch.as_ref().filter(|_| !returns && !call);
+        self.write(writes, target.map(|(_, target, _)| target.clone()));
+        let Some((condition, target, _)) = branch else {
+            return;
+        };
+        let statement = if call {
+            self.values = RegisterValues::default();
+            self.flags = Flags::Unknown;
+            match target {
+                Expression::Constant(target) => format!("sub_{:x}();", target & !1),
+                target => format!("(*{})();", target.operand()),
+            }
+        } else if returns {
+            "return;".into()
+        } else {
+            match target {
+                Expression::Constant(target) => format!("goto L_{:x};", target & !1),
+                target => format!("goto *{};", target.operand()),
+            }
+        };
+        match condition {
+            Some(Operand::Condition(condition)) => {
+                let condition = self.condition(condition);
+                self.line(format!("if ({condition}) {statement}"))
+            }
+            _ => self.line(statement),
+        }
+    }
+
+    /// Computes the temporaries of a micro-op other than a write or branch, rendering the
+    /// stores, special register writes and events.
+    fn compute(&mut self, micro_op: MicroOp) {
+        match micro_op {
+            MicroOp::Load {
+                destination,
+                address,
+                size,
+            } => {
+                let address = self.operand(address);
+                self.define(destination, Expression::Load(size, Box::new(address)));
+            }
+            MicroOp::Store {
+                address,
+                size,
+                value,
+            } => {
+                let (address, value) = (self.operand(address), self.operand(value));
+                self.line(format!(
+                    "*({} *){} = {value};",
+                    unsigned(size),
+                    address.operand()
+                ));
+            }
+            MicroOp::BinOp {
+                destination,
+                operation,
+                x,
+                y,
+            } => {
+                let (x, y) = (self.operand(x), self.operand(y));
+                self.define(destination, Expression::binary(operation, x, y));
+            }
+            MicroOp::AddWithCarry {
+                destination,
+                carry_out,
+                x,
+                y,
+                carry,
+                ..
+            } => {
+                let (x, y, carry) = (self.operand(x), self.operand(y), self.operand(carry));
+                // Subtractions are added with the inverted subtrahend and a carry of 1.
+                let subtraction = match (x, y, carry) {
+                    (Expression::Not(x), Expression::Constant(0), Expression::Constant(1)) => {
+                        Ok((Expression::Constant(0), *x))
+                    }
+                    (x, Expression::Not(y), Expression::Constant(1)) => Ok((x, *y)),
+                    (x, Expression::Constant(y), Expression::Constant(1)) => {
+                        Ok((x, Expression::Constant(!y)))
+                    }
+                    (x, y, carry) => Err((x, y, carry)),
+                };
+                let result = match subtraction {
+                    Ok((x, y)) => {
+                        self.subtractions.push((carry_out, x.clone(), y.clone()));
+                        Expression::binary(BinaryOperation::Sub, x, y)
+                    }
+                    Err((x, y, carry)) => {
+                        let sum = Expression::binary(BinaryOperation::Add, x, y);
+                        Expression::binary(BinaryOperation::Add, sum, carry)
+                    }
+                };
+                self.define(destination, result);
+            }
+            MicroOp::Shift {
+                destination,
+                shift,
+                value,
+                amount,
+                ..
+            } => {
+                let (value, amount) = (self.operand(value), self.operand(amount));
+                let result = match shift {
+                    Shift::Lsl => Expression::binary(BinaryOperation::Lsl, value, amount),
+                    Shift::Lsr => Expression::binary(BinaryOperation::Lsr, value, amount),
+                    Shift::Asr => Expression::binary(BinaryOperation::Asr, value, amount),
+                    Shift::Ror => Expression::Shift(shift, Box::new(value), Box::new(amount)),
+                };
+                self.define(destination, result);
+            }
+            MicroOp::SetFlags { result, carry, .. } => {
+                let result = self.operand(result);
+                let subtraction = self
+                    .subtractions
+                    .iter()
+                    .find(|(carry_out, ..)| carry == Some(Operand::Temporary(*carry_out)));
+                self.flags = match subtraction {
+                    Some((_, x, y)) => Flags::Compare(x.clone(), y.clone(), result),
+                    None => Flags::Result(result),
+                };
+            }
+            MicroOp::ReadSpecial {
+                destination,
+                register,
+            } => self.define(destination, Expression::Special(register)),
+            MicroOp::WriteSpecial { register, value } => {
+                let value = self.operand(value);
+                self.line(format!("{} = {value};", Expression::Special(register)));
+            }
+            MicroOp::Event(event) => self.line(match event {
+                Event::Breakpoint(imm) => format!("__bkpt({imm:#x});"),
+                Event::SupervisorCall(imm) => format!("__svc({imm:#x});"),
+                Event::Undefined => "__udf();".into(),
+                Event::WaitForEvent => "__wfe();".into(),
+                Event::WaitForInterrupt => "__wfi();".into(),
+                Event::SendEvent => "__sev();".into(),
+                Event::Barrier => "__barrier();".into(),
+                Event::ChangeInterrupts { disable: true } => "__disable_irq();".into(),
+                Event::ChangeInterrupts { disable: false } => "__enable_irq();".into(),
+            }),
+            MicroOp::Write { .. } | MicroOp::Branch { .. } => {}
+        }
+    }
+
+    /// Renders the register writes of an instruction, through locals where a later write or
+    /// the branch `target` reads a register written before.
+    fn write(&mut self, writes: Vec<(Register, Expression)>, target: Option<Expression>) {
+        let mut deferred = Vec::new();
+        for (index, (register, value)) in writes.iter().enumerate() {
+            let read_later = writes[index + 1..]
+                .iter()
+                .map(|(_, value)| value)
+                .chain(&target)
+                .any(|value| value.reads(*register));
+            if read_later {
+                let local = Expression::Local(self.locals);
+                self.locals += 1;
+                self.line(format!("{local} = {value};"));
+                deferred.push((*register, local));
+            } else {
+                self.assign(*register, value.clone());
+            }
+        }
+        for (register, local) in deferred {
+            self.assign(register, local);
+        }
+    }
+
+    fn assign(&mut self, register: Register, value: Expression) {
+        self.line(format!("{} = {value};", name(register)));
+        let written = Expression::Register(register);
+        // The flags are kept in terms of the register holding their result.
+        self.flags = match core::mem::replace(&mut self.flags, Flags::Unknown) {
+            Flags::Result(result) if result == value => Flags::Result(written),
+            Flags::Compare(x, y, result) if result == value => {
+                match x.reads(register) || y.reads(register) {
+                    true => Flags::Result(written),
+                    false => Flags::Compare(x, y, written),
+                }
+            }
+            Flags::Result(result) if !result.reads(register) => Flags::Result(result),
+            Flags::Compare(x, y, result) if !x.reads(register) && !y.reads(register) => {
+                match result.reads(register) {
+                    true => Flags::Compare(
+                        x.clone(),
+                        y.clone(),
+                        Expression::binary(BinaryOperation::Sub, x, y),
+                    ),
+                    false => Flags::Compare(x, y, result),
+                }
+            }
+            _ => Flags::Unknown,
+        };
+        let value = match value {
+            Expression::Constant(value) => Some(value),
+            _ => None,
+        };
+        self.values.set(register, value);
+    }
+
+    /// Returns the C of `condition` on the flags, its name if they are unknown.
+    fn condition(&self, condition: Condition) -> String {
+        use Condition::*;
+
+        let signed = |x: &Expression| format!("(int32_t){}", x.operand());
+        match (&self.flags, condition) {
+            (Flags::Compare(x, y, _), EQ) => format!("{x} == {y}"),
+            (Flags::Compare(x, y, _), NE) => format!("{x} != {y}"),
+            (Flags::Compare(x, y, _), CS) => format!("{x} >= {y}"),
+            (Flags::Compare(x, y, _), CC) => format!("{x} < {y}"),
+            (Flags::Compare(x, y, _), HI) => format!("{x} > {y}"),
+            (Flags::Compare(x, y, _), LS) => format!("{x} <= {y}"),
+            (Flags::Compare(x, y, _), GE) => format!("{} >= {}", signed(x), signed(y)),
+            (Flags::Compare(x, y, _), LT) => format!("{} < {}", signed(x), signed(y)),
+            (Flags::Compare(x, y, _), GT) => format!("{} > {}", signed(x), signed(y)),
+            (Flags::Compare(x, y, _), LE) => format!("{} <= {}", signed(x), signed(y)),
+            (Flags::Result(result) | Flags::Compare(_, _, result), condition) => match condition {
+                EQ => format!("{result} == 0"),
+                NE => format!("{result} != 0"),
+                MI => format!("{} < 0", signed(result)),
+                PL => format!("{} >= 0", signed(result)),
+                condition => format!("{condition:?}").to_lowercase(),
+            },
+            (Flags::Unknown, condition) => format!("{condition:?}").to_lowercase(),
+        }
+    }
+}
+
+/// Returns the pseudo-C of the basic block `block` of `program`, with the register values
+/// known at its start `values`, e.g. from [`propagate`](crate::propagation::propagate).
+///
+/// Blocks start with the label `L_<address>`. Instructions that failed to decode end the
+/// block with `__undecoded();`.
+pub fn render_block(
+    program: &Program,
+    block: Range<u32>,
+    values: Option<&RegisterValues>,
+) -> String {
+    let mut renderer = Renderer {
+        lowering: Lowering::new(),
+        temporaries: Vec::new(),
+        subtractions: Vec::new(),
+        values: values.copied().unwrap_or_default(),
+        flags: Flags::Unknown,
+        locals: 0,
+        lines: Vec::from([format!("L_{:x}:", block.start)]),
+    };
+    for (address, result) in program.range(block) {
+        let Ok(instruction) = result else {
+            renderer.line("__undecoded();".into());
+            break;
+        };
+        renderer.instruction(&DecodedAt {
+            instruction: instruction.clone(),
+            address,
+        });
+    }
+    let mut text = renderer.lines.join("\n");
+    text.push('\n');
+    text
+}
+
+/// Returns the pseudo-C of the blocks of `cfg` in `function`, in address order, with the
+/// register values of `propagation`.
+pub fn render_function(
+    program: &Program,
+    cfg: &ControlFlowGraph,
+    function: &FunctionRange,
+    propagation: &Propagation,
+) -> String {
+    cfg.blocks()
+        .iter()
+        .filter(|block| function.start <= block.start && block.start < function.end)
+        .map(|block| render_block(program, block.clone(), propagation.before(block.start)))
+        .collect()
+}
+
+fn name(register: Register) -> &'static str {
+    [
+        "r0", "r1", "r2", "r3", "r4", "r5", "r6", "r7", "r8", "r9", "r10", "r11", "r12", "sp",
+        "lr", "pc",
+    ][register as usize]
+}
+
+fn unsigned(size: AccessSize) -> &'static str {
+    match size {
+        AccessSize::Byte => "uint8_t",
+        AccessSize::Halfword => "uint16_t",
+        AccessSize::Word => "uint32_t",
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn block() {
+        // ldr r2, [pc, #12]; ldr r1, [r2, #4]; cmp r1, #3; bhi skip; str r3, [r2, #4]; bl 0x20
+        // skip: movs r0, r0; .word 0x40000000
+        let image = [
+            0x03, 0x4a, 0x51, 0x68, 0x03, 0x29, 0x02, 0xd8, 0x53, 0x60, 0x00, 0xf0, 0x09, 0xf8,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x40,
+        ];
+        let program = Program::new(&image, 0);
+        assert_eq!(
+            render_block(&program, 0..0x08, None),
+            "L_0:\n    r2 = *(uint32_t *)0x10;\n    r1 = *(uint32_t *)(r2 + 4);\n    \
+             if (r1 > 3) goto L_e;\n"
+        );
+        let mut values = RegisterValues::default();
+        values.set(Register::R2, Some(0x4000_0000));
+        assert_eq!(
+            render_block(&program, 0x08..0x0e, Some(&values)),
+            "L_8:\n    *(uint32_t *)0x40000004 = r3;\n    sub_20();\n"
+        );
+    }
+
+    #[test]
+    fn shifts() {
+        // lsrs r0, r0, #32; asrs r1, r1, #32; lsls r2, r3; lsrs r2, r3; asrs r2, r3;
+        // rors r2, r3
+        let image = [
+            0x00, 0x08, 0x09, 0x10, 0x9a, 0x40, 0xda, 0x40, 0x1a, 0x41, 0xda, 0x41,
+        ];
+        let program = Program::new(&image, 0);
+        assert_eq!(
+            render_block(&program, 0..0x0c, None),
+            "L_0:\n    r0 = 0;\n    r1 = (int32_t)r1 >> 0x1f;\n    r2 = lsl(r2, r3 & 0xff);\n    \
+             r2 = lsr(r2, r3 & 0xff);\n    r2 = asr(r2, r3 & 0xff);\n    r2 = ror(r2, r3 & 0xff);\n"
+        );
+        // Shifts by 33, the bottom byte of r3.
+        let mut values = RegisterValues::default();
+        values.set(Register::R2, Some(0x8000_0000));
+        values.set(Register::R3, Some(0x121));
+        assert_eq!(
+            render_block(&program, 0x04..0x06, Some(&values)),
+            "L_4:\n    r2 = 0;\n"
+        );
+        assert_eq!(
+            render_block(&program, 0x08..0x0a, Some(&values)),
+            "L_8:\n    r2 = 0xffffffff;\n"
+        );
+    }
+}
@@ -1,7 +1,10 @@
-use crate::Error;
+use core::str::FromStr;
+
+use crate::{ascii_lowercase, Error};
 
 /// Normal register type.
-#[derive(Debug, PartialEq, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(u8)]
 pub enum Register {
     R0 = 0,
@@ -48,8 +51,43 @@ impl TryFrom<u8> for Register {
     }
 }
 
+impl FromStr for Register {
+    type Err = Error;
+
+    /// Parses a register name such as `r3`, `sp` or `lr`, ignoring case.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut buffer = [0; 8];
+        match ascii_lowercase(s, &mut buffer).ok_or(Error::InvalidRegister)? {
+            "sp" => Ok(Register::SP),
+            "lr" => Ok(Register::LR),
+            "pc" => Ok(Register::PC),
+            "ip" => Ok(Register::R12),
+            name => match name.strip_prefix('r') {
+                Some(number) if !number.starts_with('+') => number
+                    .parse::<u8>()
+                    .map_err(|_| Error::InvalidRegister)?
+                    .try_into(),
+                _ => Err(Error::InvalidRegister),
+            },
+        }
+    }
+}
+
+impl core::fmt::Display for Register {
+    /// Writes the name of the register, like `r3` or `sp`.
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Register::SP => f.write_str("sp"),
+            Register::LR => f.write_str("lr"),
+            Register::PC => f.write_str("pc"),
+            register => write!(f, "r{}", *register as u8),
+        }
+    }
+}
+
 /// Special register type.
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(u8)]
 pub enum SpecialRegister {
     APSR = 0,
@@ -86,15 +124,169 @@ impl TryFrom<u8> for SpecialRegister {
     }
 }
 
-/// Creates a register list from a bit array.
-pub fn register_list_from_bit_array(bit_array: u16) -> Vec<Register> {
-    let mut ret = vec![];
-    for i in 0..16 {
-        if (bit_array >> i) & 0b1 == 0b1 {
-            ret.push(i.try_into().unwrap())
+impl FromStr for SpecialRegister {
+    type Err = Error;
+
+    /// Parses a special register name such as `primask`, ignoring case.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut buffer = [0; 8];
+        match ascii_lowercase(s, &mut buffer).ok_or(Error::InvalidRegister)? {
+            "apsr" => Ok(SpecialRegister::APSR),
+            "iapsr" => Ok(SpecialRegister::IAPSR),
+            "eapsr" => Ok(SpecialRegister::EAPSR),
+            "xpsr" => Ok(SpecialRegister::XPSR),
+            "ipsr" => Ok(SpecialRegister::IPSR),
+            "epsr" => Ok(SpecialRegister::EPSR),
+            "iepsr" => Ok(SpecialRegister::IEPSR),
+            "msp" => Ok(SpecialRegister::MSP),
+            "psp" => Ok(SpecialRegister::PSP),
+            "primask" => Ok(SpecialRegister::PRIMASK),
+            "control" => Ok(SpecialRegister::CONTROL),
+            _ => Err(Error::InvalidRegister),
+        }
+    }
+}
+
+impl core::fmt::Display for SpecialRegister {
+    /// Writes the name of the register in upper case, like `PRIMASK`.
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(match self {
+            SpecialRegister::APSR => "APSR",
+            SpecialRegister::IAPSR => "IAPSR",
+            SpecialRegister::EAPSR => "EAPSR",
+            SpecialRegister::XPSR => "XPSR",
+            SpecialRegister::IPSR => "IPSR",
+            SpecialRegister::EPSR => "EPSR",
+            SpecialRegister::IEPSR => "IEPSR",
+            SpecialRegister::MSP => "MSP",
+            SpecialRegister::PSP => "PSP",
+            SpecialRegister::PRIMASK => "PRIMASK",
+            SpecialRegister::CONTROL => "CONTROL",
+        })
+    }
+}
+
+/// Set of registers, as held by `push`, `pop`, `ldm` and `stm`, stored as a bit array with
+/// bit n set for register n.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RegisterList(u16);
+
+impl RegisterList {
+    /// Returns an empty register list.
+    pub const fn new() -> Self {
+        Self(0)
+    }
+
+    /// Creates a register list from a bit array.
+    pub const fn from_bits(bits: u16) -> Self {
+        Self(bits)
+    }
+
+    /// Returns the bit array of the list.
+    pub const fn bits(self) -> u16 {
+        self.0
+    }
+
+    pub const fn contains(self, register: Register) -> bool {
+        self.0 & 1 << register as u16 != 0
+    }
+
+    pub fn insert(&mut self, register: Register) {
+        self.0 |= 1 << register as u16;
+    }
+
+    pub fn remove(&mut self, register: Register) {
+        self.0 &= !(1 << register as u16);
+    }
+
+    /// Number of registers in the list.
+    pub const fn len(self) -> usize {
+        self.0.count_ones() as usize
+    }
+
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Returns the registers in ascending order.
+    pub fn iter(self) -> RegisterListIter {
+        RegisterListIter(self.0)
+    }
+}
+
+impl core::fmt::Debug for RegisterList {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl core::fmt::Display for RegisterList {
+    /// Writes the registers as in assembly, like `{r4, r5, lr}`.
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("{")?;
+        for (index, register) in self.iter().enumerate() {
+            if index > 0 {
+                f.write_str(", ")?;
+            }
+            write!(f, "{register}")?;
+        }
+        f.write_str("}")
+    }
+}
+
+impl FromIterator<Register> for RegisterList {
+    fn from_iter<T: IntoIterator<Item = Register>>(iter: T) -> Self {
+        let mut list = Self::new();
+        for register in iter {
+            list.insert(register);
         }
+        list
+    }
+}
+
+impl<const N: usize> From<[Register; N]> for RegisterList {
+    fn from(registers: [Register; N]) -> Self {
+        registers.into_iter().collect()
+    }
+}
+
+impl IntoIterator for RegisterList {
+    type Item = Register;
+    type IntoIter = RegisterListIter;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
     }
-    ret
+}
+
+/// Iterator over the registers of a [`RegisterList`] in ascending order.
+#[derive(Debug, Clone)]
+pub struct RegisterListIter(u16);
+
+impl Iterator for RegisterListIter {
+    type Item = Register;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.0 == 0 {
+            return None;
+        }
+        let register = self.0.trailing_zeros() as u8;
+        self.0 &= self.0 - 1;
+        register.try_into().ok()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.0.count_ones() as usize;
+        (len, Some(len))
+    }
+}
+
+impl ExactSizeIterator for RegisterListIter {}
+
+/// Creates a register list from a bit array.
+pub fn register_list_from_bit_array(bit_array: u16) -> RegisterList {
+    RegisterList::from_bits(bit_array)
 }
 
 #[cfg(test)]
@@ -125,24 +317,54 @@ mod tests {
         )
     }
 
+    #[test]
+    fn register_from_str() {
+        assert_eq!("r0".parse(), Ok(Register::R0));
+        assert_eq!("R12".parse(), Ok(Register::R12));
+        assert_eq!("r13".parse(), Ok(Register::SP));
+        assert_eq!("sp".parse(), Ok(Register::SP));
+        assert_eq!("LR".parse(), Ok(Register::LR));
+        assert_eq!("pc".parse(), Ok(Register::PC));
+        assert_eq!("r16".parse::<Register>(), Err(Error::InvalidRegister));
+        assert_eq!("x1".parse::<Register>(), Err(Error::InvalidRegister));
+        assert_eq!(
+            "PRIMASK".parse::<SpecialRegister>(),
+            Ok(SpecialRegister::PRIMASK)
+        );
+    }
+
     #[test]
     fn register_list() {
-        assert_eq!(register_list_from_bit_array(0), vec![]);
-        assert_eq!(register_list_from_bit_array(0b1), vec![Register::R0]);
         assert_eq!(
-            register_list_from_bit_array(0b111),
+            register_list_from_bit_array(0).iter().collect::<Vec<_>>(),
+            vec![]
+        );
+        assert_eq!(
+            register_list_from_bit_array(0b1).iter().collect::<Vec<_>>(),
+            vec![Register::R0]
+        );
+        assert_eq!(
+            register_list_from_bit_array(0b111)
+                .iter()
+                .collect::<Vec<_>>(),
             vec![Register::R0, Register::R1, Register::R2]
         );
         assert_eq!(
-            register_list_from_bit_array(0b1000000000000000),
+            register_list_from_bit_array(0b1000000000000000)
+                .iter()
+                .collect::<Vec<_>>(),
             vec![Register::PC]
         );
         assert_eq!(
-            register_list_from_bit_array(0b1110000000000000),
+            register_list_from_bit_array(0b1110000000000000)
+                .iter()
+                .collect::<Vec<_>>(),
             vec![Register::SP, Register::LR, Register::PC]
         );
         assert_eq!(
-            register_list_from_bit_array(0xffff),
+            register_list_from_bit_array(0xffff)
+                .iter()
+                .collect::<Vec<_>>(),
             vec![
                 Register::R0,
                 Register::R1,
@@ -163,4 +385,17 @@ mod tests {
             ]
         );
     }
+
+    #[test]
+    fn register_list_set() {
+        let mut list = RegisterList::from([Register::R4, Register::LR]);
+        assert_eq!(list.bits(), 0x4010);
+        assert_eq!(list.len(), 2);
+        assert!(list.contains(Register::LR));
+        list.insert(Register::R0);
+        list.remove(Register::LR);
+        assert!(!list.contains(Register::LR));
+        assert_eq!(format!("{list:?}"), "[R0, R4]");
+        assert!(RegisterList::new().is_empty());
+    }
 }
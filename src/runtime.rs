@@ -0,0 +1,255 @@
+//! Recognition of the compiler runtime helpers ARMv6-M code calls, like `__aeabi_uidiv` for
+//! every division as the architecture has no divide instruction.
+//!
+//! # Example
+//! ```
+//! # use armv6_m_instruction_parser::{assembler::assemble, functions::functions, program::Program, runtime::{annotate_calls, helpers, RuntimeHelper}};
+//! let assembly = assemble(
+//!     "    push {r4, lr}
+//!          bl copy
+//!          pop {r4, pc}
+//!      copy:
+//!          movs r3, #0
+//!      loop:
+//!          ldrb r4, [r1, r3]
+//!          strb r4, [r0, r3]
+//!          adds r3, #1
+//!          cmp r3, r2
+//!          bne loop
+//!          bx lr",
+//! )
+//! .unwrap();
+//! let image = assembly.relocate(0, |_| None).unwrap();
+//! let program = Program::new(&image, 0);
+//! let helpers = helpers(&program, &functions(&program));
+//! assert_eq!(annotate_calls(&program, &helpers), [(0x02, RuntimeHelper::Memcpy)]);
+//! assert_eq!(RuntimeHelper::Memcpy.name(), "__aeabi_memcpy");
+//! ```
+
+use alloc::{collections::BTreeMap, vec::Vec};
+
+use crate::{
+    functions::FunctionRange,
+    instructons::{AccessDirection, BranchTarget, Operation},
+    program::Program,
+    registers::RegisterList,
+};
+
+/// Steps of the shift and subtract loop needed to take a function for a division.
+const DIVISION_STEPS: usize = 4;
+
+/// Compiler runtime helper recognized by [`helpers`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RuntimeHelper {
+    UnsignedDivide,
+    SignedDivide,
+    /// Unsigned division also returning the remainder in `r1`.
+    UnsignedDivMod,
+    /// Signed division also returning the remainder in `r1`.
+    SignedDivMod,
+    Memcpy,
+    Memset,
+}
+
+impl RuntimeHelper {
+    /// Returns the name of the helper in the run-time ABI for the Arm architecture.
+    pub fn name(self) -> &'static str {
+        match self {
+            RuntimeHelper::UnsignedDivide => "__aeabi_uidiv",
+            RuntimeHelper::SignedDivide => "__aeabi_idiv",
+            RuntimeHelper::UnsignedDivMod => "__aeabi_uidivmod",
+            RuntimeHelper::SignedDivMod => "__aeabi_idivmod",
+            RuntimeHelper::Memcpy => "__aeabi_memcpy",
+            RuntimeHelper::Memset => "__aeabi_memset",
+        }
+    }
+}
+
+/// Returns the runtime helpers among `functions` of `program`, by start address.
+///
+/// Divisions are recognized by the shift, compare and subtract steps of the libgcc and
+/// compiler-rt implementations, signed if they also negate. Functions calling a division and
+/// multiplying the quotient back are the variants returning the remainder. Leaf functions
+/// looping over a load and a store of the same register copy memory, ones looping over a
+/// store without loads fill it.
+pub fn helpers(program: &Program, functions: &[FunctionRange]) -> BTreeMap<u32, RuntimeHelper> {
+    let mut helpers: BTreeMap<u32, RuntimeHelper> = functions
+        .iter()
+        .filter_map(|function| Some((function.start, leaf_helper(program, function)?)))
+        .collect();
+    for function in functions {
+        let mut division = None;
+        let mut multiplies = false;
+        for (address, result) in program.range(function.start..function.end) {
+            let Ok(instruction) = result else {
+                continue;
+            };
+            match (
+                &instruction.operation,
+                instruction.operation.branch_target(address),
+            ) {
+                (Operation::BL { .. }, BranchTarget::Direct(target)) => {
+                    division = division.or(match helpers.get(&target) {
+                        Some(RuntimeHelper::UnsignedDivide) => Some(RuntimeHelper::UnsignedDivMod),
+                        Some(RuntimeHelper::SignedDivide) => Some(RuntimeHelper::SignedDivMod),
+                        _ => None,
+                    });
+                }
+                (Operation::MUL { .. }, _) => multiplies |= division.is_some(),
+                _ => {}
+            }
+        }
+        if let (Some(helper), true) = (division, multiplies) {
+            helpers.entry(function.start).or_insert(helper);
+        }
+    }
+    helpers
+}
+
+/// Returns the calls of `program` to the `helpers`, e.g. from [`helpers`], as call sites with
+/// the called helper.
+pub fn annotate_calls(
+    program: &Program,
+    helpers: &BTreeMap<u32, RuntimeHelper>,
+) -> Vec<(u32, RuntimeHelper)> {
+    program
+        .iter()
+        .filter_map(|(site, result)| {
+            let operation = &result.as_ref().ok()?.operation;
+            match (operation, operation.branch_target(site)) {
+                (Operation::BL { .. }, BranchTarget::Direct(target)) => {
+                    Some((site, *helpers.get(&target)?))
+                }
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// Recognizes the helpers not calling other functions.
+fn leaf_helper(program: &Program, function: &FunctionRange) -> Option<RuntimeHelper> {
+    let mut carries = 0;
+    let mut shifts = 0;
+    let mut subtractions = 0;
+    let mut negates = false;
+    let mut loops = false;
+    let mut loaded = RegisterList::new();
+    let mut copies = false;
+    let mut stores = false;
+    for (address, result) in program.range(function.start..function.end) {
+        let operation = &result.as_ref().ok()?.operation;
+        if operation.is_call() {
+            return None;
+        }
+        match *operation {
+            Operation::ADCReg { m, d, .. } if m == d => carries += 1,
+            Operation::LSRImm { .. } => shifts += 1,
+            Operation::SUBReg { .. } => subtractions += 1,
+            Operation::RSBImm { .. } => negates = true,
+            Operation::B { .. } => {
+                loops |= matches!(
+                    operation.branch_target(address),
+                    BranchTarget::Direct(target) if target <= address && target >= function.start
+                );
+            }
+            _ => {}
+        }
+        let Some(access) = operation.memory_access() else {
+            continue;
+        };
+        let transferred = match *operation {
+            Operation::LDRImm { t, .. }
+            | Operation::LDRReg { t, .. }
+            | Operation::LDRBImm { t, .. }
+            | Operation::LDRBReg { t, .. }
+            | Operation::LDRHImm { t, .. }
+            | Operation::LDRHReg { t, .. }
+            | Operation::STRImm { t, .. }
+            | Operation::STRReg { t, .. }
+            | Operation::STRBImm { t, .. }
+            | Operation::STRBReg { t, .. }
+            | Operation::STRHImm { t, .. }
+            | Operation::STRHReg { t, .. } => t,
+            _ => continue,
+        };
+        match access.direction {
+            AccessDirection::Load => loaded.insert(transferred),
+            AccessDirection::Store => {
+                stores = true;
+                copies |= loaded.contains(transferred);
+            }
+        }
+    }
+
+    if carries >= DIVISION_STEPS && shifts >= DIVISION_STEPS && subtractions >= DIVISION_STEPS {
+        return Some(match negates {
+            true => RuntimeHelper::SignedDivide,
+            false => RuntimeHelper::UnsignedDivide,
+        });
+    }
+    match (loops, copies, stores, loaded.is_empty()) {
+        (true, true, _, _) => Some(RuntimeHelper::Memcpy),
+        (true, false, true, true) => Some(RuntimeHelper::Memset),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{assembler::assemble, functions::functions};
+    use alloc::{format, string::String};
+
+    #[test]
+    fn divisions() {
+        let mut source = String::from(
+            "    push {r4, lr}
+                 bl divmod
+                 bl fill
+                 pop {r4, pc}
+             divmod:
+                 push {r0, r1, lr}
+                 bl udiv
+                 pop {r1, r2, r3}
+                 muls r2, r0
+                 subs r1, r1, r2
+                 bx r3
+             fill:
+                 movs r3, #0
+             again:
+                 strb r1, [r0, r3]
+                 adds r3, #1
+                 cmp r3, r2
+                 bne again
+                 bx lr
+             udiv:
+                 movs r2, #0",
+        );
+        for shift in [12, 8, 4, 1] {
+            source += &format!(
+                "
+                 lsrs r3, r0, #{shift}
+                 cmp r3, r1
+                 bcc skip{shift}
+                 lsls r3, r1, #{shift}
+                 subs r0, r0, r3
+             skip{shift}:
+                 adcs r2, r2"
+            );
+        }
+        source += "
+                 mov r0, r2
+                 bx lr";
+        let image = assemble(&source).unwrap().relocate(0, |_| None).unwrap();
+        let program = Program::new(&image, 0);
+        let helpers = helpers(&program, &functions(&program));
+        let calls: Vec<_> = annotate_calls(&program, &helpers)
+            .into_iter()
+            .map(|(_, helper)| helper.name())
+            .collect();
+        assert_eq!(
+            calls,
+            ["__aeabi_uidivmod", "__aeabi_memset", "__aeabi_uidiv"]
+        );
+    }
+}
@@ -0,0 +1,876 @@
+//! Semantics of the operations as side effects over the values of an engine, for symbolic
+//! executors to take the semantics from the decoder instead of re-encoding the architecture
+//! manual.
+//!
+//! # Example
+//! ```
+//! # use armv6_m_instruction_parser::{conditions::Condition, instructons::{AccessSize, DecodedAt}, parse, registers::{Register, SpecialRegister}, semantics::*};
+//! /// Engine printing the effects with values as expressions.
+//! struct Printer(Vec<String>);
+//!
+//! impl Effects for Printer {
+//!     type Value = String;
+//!     type Bool = String;
+//!     fn constant(&mut self, value: u32) -> String { format!("{value:#x}") }
+//!     fn boolean(&mut self, value: bool) -> String { value.to_string() }
+//!     fn read_register(&mut self, register: Register) -> String { format!("{register:?}") }
+//!     fn write_register(&mut self, register: Register, value: String) { self.0.push(format!("{register:?} = {value}")) }
+//!     fn read_memory(&mut self, address: String, size: AccessSize) -> String { format!("{size:?}[{address}]") }
+//!     fn write_memory(&mut self, address: String, size: AccessSize, value: String) { self.0.push(format!("{size:?}[{address}] = {value}")) }
+//!     fn read_flag(&mut self, flag: Flag) -> String { format!("{flag:?}") }
+//!     fn set_flags(&mut self, result: String, carry: Option<String>, overflow: Option<String>) { self.0.push(format!("flags({result}, {carry:?}, {overflow:?})")) }
+//!     fn condition(&mut self, condition: Condition) -> String { format!("{condition:?}") }
+//!     fn binary(&mut self, operation: BinaryOperation, x: String, y: String) -> String { format!("{operation:?}({x}, {y})") }
+//!     fn not(&mut self, value: String) -> String { format!("!{value}") }
+//!     fn extend(&mut self, value: String, bits: u32, signed: bool) -> String { format!("extend{bits}({value}, {signed})") }
+//!     fn add_with_carry(&mut self, x: String, y: String, carry: String) -> (String, String, String) {
+//!         let sum = format!("adc({x}, {y}, {carry})");
+//!         (sum.clone(), format!("carry({sum})"), format!("overflow({sum})"))
+//!     }
+//!     fn shift(&mut self, shift: Shift, value: String, amount: String, carry: String) -> (String, String) {
+//!         (format!("{shift:?}({value}, {amount})"), format!("carry_out({carry})"))
+//!     }
+//!     fn branch_if(&mut self, condition: String, target: String) { self.0.push(format!("if {condition} goto {target}")) }
+//!     fn branch_exchange(&mut self, target: String, _link: bool) { self.0.push(format!("bx {target}")) }
+//!     fn read_special(&mut self, register: SpecialRegister) -> String { format!("{register:?}") }
+//!     fn write_special(&mut self, register: SpecialRegister, value: String) { self.0.push(format!("{register:?} = {value}")) }
+//!     fn event(&mut self, event: Event) { self.0.push(format!("{event:?}")) }
+//! }
+//!
+//! // ldr r0, [r1, #4]
+//! let instruction = parse(&[0x48, 0x68]).unwrap();
+//! let mut printer = Printer(Vec::new());
+//! describe(&DecodedAt { instruction, address: 0x100 }, &mut printer);
+//! assert_eq!(printer.0, ["R0 = Word[Add(R1, 0x4)]"]);
+//! ```
+
+use crate::{
+    conditions::Condition,
+    instructons::{AccessSize, DecodedAt, Operation},
+    registers::{Register, SpecialRegister},
+};
+
+/// Flag of the APSR.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Flag {
+    N,
+    Z,
+    C,
+    V,
+}
+
+/// Operation on two values, without flags, shifting by the low byte of the amount like
+/// the shifts of the registers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BinaryOperation {
+    /// Wrapping addition.
+    Add,
+    /// Wrapping subtraction.
+    Sub,
+    /// Wrapping multiplication, the low 32 bits of the product.
+    Mul,
+    And,
+    Or,
+    Xor,
+    Lsl,
+    Lsr,
+    Asr,
+}
+
+/// Shift with carry out, see [`Effects::shift`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Shift {
+    Lsl,
+    Lsr,
+    Asr,
+    Ror,
+}
+
+/// Effect on the processor other than registers, memory and flags.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Event {
+    /// `bkpt`, with its immediate.
+    Breakpoint(u32),
+    /// `svc`, with its immediate.
+    SupervisorCall(u32),
+    /// An undefined instruction, taking HardFault.
+    Undefined,
+    WaitForEvent,
+    WaitForInterrupt,
+    SendEvent,
+    /// `dmb`, `dsb` or `isb`.
+    Barrier,
+    /// `cpsid` or `cpsie`, setting PRIMASK to `disable` when privileged.
+    ChangeInterrupts {
+        disable: bool,
+    },
+}
+
+/// Values and side effects of an engine, symbolic or concrete, that [`describe`] expresses
+/// the operations with.
+///
+/// `pc` is never read, [`describe`] reads it as the address of the instruction plus 4.
+pub trait Effects {
+    /// Value of a register or memory.
+    type Value: Clone;
+    /// Value of a flag or condition.
+    type Bool: Clone;
+
+    fn constant(&mut self, value: u32) -> Self::Value;
+
+    fn boolean(&mut self, value: bool) -> Self::Bool;
+
+    /// Reads a register, the active stack pointer for `sp`.
+    fn read_register(&mut self, register: Register) -> Self::Value;
+
+    /// Writes a register, a branch without changing the Thumb state for `pc`.
+    fn write_register(&mut self, register: Register, value: Self::Value);
+
+    /// Loads `size` bytes zero extended, faulting if `address` is not aligned.
+    fn read_memory(&mut self, address: Self::Value, size: AccessSize) -> Self::Value;
+
+    /// Stores the low `size` bytes of `value`, faulting if `address` is not aligned.
+    fn write_memory(&mut self, address: Self::Value, size: AccessSize, value: Self::Value);
+
+    fn read_flag(&mut self, flag: Flag) -> Self::Bool;
+
+    /// Sets N and Z from `result`, and C and V when given.
+    fn set_flags(
+        &mut self,
+        result: Self::Value,
+        carry: Option<Self::Bool>,
+        overflow: Option<Self::Bool>,
+    );
+
+    /// Returns `true` if `condition` holds for the flags.
+    fn condition(&mut self, condition: Condition) -> Self::Bool;
+
+    fn binary(&mut self, operation: BinaryOperation, x: Self::Value, y: Self::Value)
+        -> Self::Value;
+
+    /// Bitwise not.
+    fn not(&mut self, value: Self::Value) -> Self::Value;
+
+    /// Extends the low `bits` of `value`, with its sign if `signed`.
+    fn extend(&mut self, value: Self::Value, bits: u32, signed: bool) -> Self::Value;
+
+    /// Returns `x + y + carry` with the carry out and the signed overflow, `AddWithCarry` of
+    /// the architecture.
+    fn add_with_carry(
+        &mut self,
+        x: Self::Value,
+        y: Self::Value,
+        carry: Self::Bool,
+    ) -> (Self::Value, Self::Bool, Self::Bool);
+
+    /// Returns `value` shifted by `amount`, 0 to 255, with the carry out, `carry` when
+    /// `amount` is 0, `Shift_C` of the architecture.
+    fn shift(
+        &mut self,
+        shift: Shift,
+        value: Self::Value,
+        amount: Self::Value,
+        carry: Self::Bool,
+    ) -> (Self::Value, Self::Bool);
+
+    /// Branches to `target` if `condition` holds.
+    fn branch_if(&mut self, condition: Self::Bool, target: Self::Value);
+
+    /// Branches to `target` with the Thumb state from its bit 0, an exception return for
+    /// an `EXC_RETURN` value in Handler mode unless `link`, a `blx`.
+    fn branch_exchange(&mut self, target: Self::Value, link: bool);
+
+    fn read_special(&mut self, register: SpecialRegister) -> Self::Value;
+
+    fn write_special(&mut self, register: SpecialRegister, value: Self::Value);
+
+    fn event(&mut self, event: Event);
+}
+
+/// Expresses the semantics of `decoded` as effects on `effects`, in the order the
+/// instruction has them.
+pub fn describe<E: Effects>(decoded: &DecodedAt, effects: &mut E) {
+    use AccessSize::{Byte, Halfword, Word};
+    use BinaryOperation::{Add, And, Lsl, Lsr, Or, Sub};
+
+    let e = effects;
+    let pc = decoded.address.wrapping_add(4);
+    let read = |e: &mut E, register: Register| match register {
+        Register::PC => e.constant(pc),
+        register => e.read_register(register),
+    };
+    // Register plus immediate or register, the address of loads and stores.
+    let offset = |e: &mut E, n: Register, imm: u32| {
+        let (base, offset) = (read(e, n), e.constant(imm));
+        e.binary(Add, base, offset)
+    };
+    let indexed = |e: &mut E, n: Register, m: Register| {
+        let (base, offset) = (read(e, n), read(e, m));
+        e.binary(Add, base, offset)
+    };
+    let write_nz = |e: &mut E, d: Register, result: E::Value| {
+        e.write_register(d, result.clone());
+        e.set_flags(result, None, None);
+    };
+    let write_nzcv = |e: &mut E, d: Register, (result, carry, overflow)| {
+        e.write_register(d, Clone::clone(&result));
+        e.set_flags(result, Some(carry), Some(overflow));
+    };
+    let write_shifted = |e: &mut E, d: Register, value, shift, amount| {
+        let carry = e.read_flag(Flag::C);
+        let (result, carry) = e.shift(shift, value, amount, carry);
+        e.write_register(d, Clone::clone(&result));
+        e.set_flags(result, Some(carry), None);
+    };
+    // Shifts by the bottom byte of `m`.
+    let shift_register = |e: &mut E, dn: Register, m: Register, shift| {
+        let (value, amount, mask) = (read(e, dn), read(e, m), e.constant(0xff));
+        let amount = e.binary(And, amount, mask);
+        write_shifted(e, dn, value, shift, amount);
+    };
+    // Shifts by an immediate, 0 meaning 32 for right shifts.
+    let shift_immediate = |e: &mut E, d: Register, m: Register, shift, imm: u32| {
+        let amount = match (shift, imm) {
+            (Shift::Lsr | Shift::Asr, 0) => 32,
+            _ => imm,
+        };
+        let (value, amount) = (read(e, m), e.constant(amount));
+        write_shifted(e, d, value, shift, amount);
+    };
+    let logical = |e: &mut E, dn: Register, m: Register, operation, invert: bool| {
+        let (x, mut y) = (read(e, dn), read(e, m));
+        if invert {
+            y = e.not(y);
+        }
+        let result = e.binary(operation, x, y);
+        write_nz(e, dn, result);
+    };
+    let load = |e: &mut E, t: Register, address, size, signed: bool| {
+        let mut value = e.read_memory(address, size);
+        if signed {
+            value = e.extend(value, 8 * size.bytes(), true);
+        }
+        e.write_register(t, value);
+    };
+    let store = |e: &mut E, t: Register, address, size| {
+        let value = read(e, t);
+        e.write_memory(address, size, value);
+    };
+    // Address `index` words after `base`.
+    let word = |e: &mut E, base: &E::Value, index: u32| {
+        if index == 0 {
+            return base.clone();
+        }
+        let offset = e.constant(4 * index);
+        e.binary(Add, base.clone(), offset)
+    };
+    let link = decoded.next_address() | 1;
+
+    match decoded.instruction.operation {
+        Operation::ADCReg { m, n, d } => {
+            let (x, y, carry) = (read(e, n), read(e, m), e.read_flag(Flag::C));
+            let sum = e.add_with_carry(x, y, carry);
+            write_nzcv(e, d, sum);
+        }
+        Operation::ADDImm { imm, n, d } => {
+            let (x, y, carry) = (read(e, n), e.constant(imm), e.boolean(false));
+            let sum = e.add_with_carry(x, y, carry);
+            write_nzcv(e, d, sum);
+        }
+        Operation::ADDReg { m, n, d, set_flags } => {
+            let (x, y, carry) = (read(e, n), read(e, m), e.boolean(false));
+            let sum = e.add_with_carry(x, y, carry);
+            if set_flags {
+                write_nzcv(e, d, sum);
+            } else {
+                e.write_register(d, sum.0);
+            }
+        }
+        Operation::ADDImmSP { d, imm } => {
+            let sum = offset(e, Register::SP, imm);
+            e.write_register(d, sum);
+        }
+        Operation::ADDRegSP { d, m } => {
+            let sum = indexed(e, Register::SP, m);
+            e.write_register(d, sum);
+        }
+        Operation::ADR { d, imm } => {
+            let address = e.constant((pc & !0b11).wrapping_add(imm));
+            e.write_register(d, address);
+        }
+        Operation::ANDReg { m, dn } => logical(e, dn, m, And, false),
+        Operation::ASRImm { imm, m, d } => shift_immediate(e, d, m, Shift::Asr, imm),
+        Operation::ASRReg { m, dn } => shift_register(e, dn, m, Shift::Asr),
+        Operation::B { cond, imm } => {
+            let target = e.constant(pc.wrapping_add(imm));
+            if cond == Condition::None {
+                e.write_register(Register::PC, target);
+            } else {
+                let condition = e.condition(cond);
+                e.branch_if(condition, target);
+            }
+        }
+        Operation::BICReg { m, dn } => logical(e, dn, m, And, true),
+        Operation::BKPT { imm } => e.event(Event::Breakpoint(imm)),
+        Operation::BL { imm } => {
+            let (link, target) = (e.constant(link), e.constant(pc.wrapping_add(imm)));
+            e.write_register(Register::LR, link);
+            e.write_register(Register::PC, target);
+        }
+        Operation::BLXReg { m } => {
+            let (target, link) = (read(e, m), e.constant(link));
+            e.write_register(Register::LR, link);
+            e.branch_exchange(target, true);
+        }
+        Operation::BX { m } => {
+            let target = read(e, m);
+            e.branch_exchange(target, false);
+        }
+        Operation::CMNReg { m, n } => {
+            let (x, y, carry) = (read(e, n), read(e, m), e.boolean(false));
+            let (result, carry, overflow) = e.add_with_carry(x, y, carry);
+            e.set_flags(result, Some(carry), Some(overflow));
+        }
+        Operation::CMPImm { n, imm } => {
+            let (x, y, carry) = (read(e, n), e.constant(!imm), e.boolean(true));
+            let (result, carry, overflow) = e.add_with_carry(x, y, carry);
+            e.set_flags(result, Some(carry), Some(overflow));
+        }
+        Operation::CMPReg { m, n } => {
+            let (x, y, carry) = (read(e, n), read(e, m), e.boolean(true));
+            let y = e.not(y);
+            let (result, carry, overflow) = e.add_with_carry(x, y, carry);
+            e.set_flags(result, Some(carry), Some(overflow));
+        }
+        Operation::CPS { im } => e.event(Event::ChangeInterrupts { disable: im }),
+        Operation::CPY | Operation::UDF { .. } => e.event(Event::Undefined),
+        Operation::DMB { .. } | Operation::DSB { .. } | Operation::ISB { .. } => {
+            e.event(Event::Barrier)
+        }
+        Operation::NOP | Operation::YIELD => {}
+        Operation::SEV => e.event(Event::SendEvent),
+        Operation::SVC { imm } => e.event(Event::SupervisorCall(imm)),
+        Operation::WFE => e.event(Event::WaitForEvent),
+        Operation::WFI => e.event(Event::WaitForInterrupt),
+        Operation::EORReg { m, dn } => logical(e, dn, m, BinaryOperation::Xor, false),
+        Operation::LDM { n, reg_list } => {
+            let base = read(e, n);
+            for (index, register) in (0..).zip(reg_list) {
+                let address = word(e, &base, index);
+                load(e, register, address, Word, false);
+            }
+            if !reg_list.contains(n) {
+                let end = word(e, &base, reg_list.len() as u32);
+                e.write_register(n, end);
+            }
+        }
+        Operation::LDRImm { imm, n, t } => {
+            let address = offset(e, n, imm);
+            load(e, t, address, Word, false);
+        }
+        Operation::LDRLiteral { t, imm } => {
+            let address = e.constant((pc & !0b11).wrapping_add(imm));
+            load(e, t, address, Word, false);
+        }
+        Operation::LDRReg { m, n, t } => {
+            let address = indexed(e, n, m);
+            load(e, t, address, Word, false);
+        }
+        Operation::LDRBImm { imm, n, t } => {
+            let address = offset(e, n, imm);
+            load(e, t, address, Byte, false);
+        }
+        Operation::LDRBReg { m, n, t } => {
+            let address = indexed(e, n, m);
+            load(e, t, address, Byte, false);
+        }
+        Operation::LDRHImm { imm, n, t } => {
+            let address = offset(e, n, imm);
+            load(e, t, address, Halfword, false);
+        }
+        Operation::LDRHReg { m, n, t } => {
+            let address = indexed(e, n, m);
+            load(e, t, address, Halfword, false);
+        }
+        Operation::LDRSBReg { m, n, t } => {
+            let address = indexed(e, n, m);
+            load(e, t, address, Byte, true);
+        }
+        Operation::LDRSH { m, n, t } => {
+            let address = indexed(e, n, m);
+            load(e, t, address, Halfword, true);
+        }
+        Operation::LSLImm { imm, m, d } => shift_immediate(e, d, m, Shift::Lsl, imm),
+        Operation::LSLReg { m, dn } => shift_register(e, dn, m, Shift::Lsl),
+        Operation::LSRImm { imm, m, d } => shift_immediate(e, d, m, Shift::Lsr, imm),
+        Operation::LSRReg { m, dn } => shift_register(e, dn, m, Shift::Lsr),
+        Operation::MOVImm { d, imm } => {
+            let value = e.constant(imm);
+            write_nz(e, d, value);
+        }
+        Operation::MOVReg { m, d, set_flags } => {
+            let value = read(e, m);
+            match set_flags {
+                true => write_nz(e, d, value),
+                false => e.write_register(d, value),
+            }
+        }
+        Operation::MRS { d, sysm } => {
+            let value = e.read_special(sysm);
+            e.write_register(d, value);
+        }
+        Operation::MSRReg { n, sysm } => {
+            let value = read(e, n);
+            e.write_special(sysm, value);
+        }
+        Operation::MUL { n, dm } => {
+            let (x, y) = (read(e, n), read(e, dm));
+            let product = e.binary(BinaryOperation::Mul, x, y);
+            write_nz(e, dm, product);
+        }
+        Operation::MVNReg { m, d } => {
+            let value = read(e, m);
+            let value = e.not(value);
+            write_nz(e, d, value);
+        }
+        Operation::ORRReg { m, dn } => logical(e, dn, m, Or, false),
+        Operation::POP { reg_list } => {
+            let base = read(e, Register::SP);
+            let mut target = None;
+            for (index, register) in (0..).zip(reg_list) {
+                let address = word(e, &base, index);
+                match register {
+                    Register::PC => target = Some(e.read_memory(address, Word)),
+                    register => load(e, register, address, Word, false),
+                }
+            }
+            let end = word(e, &base, reg_list.len() as u32);
+            e.write_register(Register::SP, end);
+            if let Some(target) = target {
+                e.branch_exchange(target, false);
+            }
+        }
+        Operation::PUSH { reg_list } => {
+            let (sp, size) = (read(e, Register::SP), e.constant(4 * reg_list.len() as u32));
+            let start = e.binary(Sub, sp, size);
+            for (index, register) in (0..).zip(reg_list) {
+                let address = word(e, &start, index);
+                store(e, register, address, Word);
+            }
+            e.write_register(Register::SP, start);
+        }
+        Operation::REV { m, d } => {
+            // The bytes of the halves swapped, then the halves.
+            let value = read(e, m);
+            let swapped = swap_halfword_bytes(e, value);
+            let (sixteen, high, low) = (e.constant(16), swapped.clone(), swapped);
+            let high = e.binary(Lsl, high, sixteen.clone());
+            let low = e.binary(Lsr, low, sixteen);
+            let value = e.binary(Or, high, low);
+            e.write_register(d, value);
+        }
+        Operation::REV16 { m, d } => {
+            let value = read(e, m);
+            let value = swap_halfword_bytes(e, value);
+            e.write_register(d, value);
+        }
+        Operation::REVSH { m, d } => {
+            let value = read(e, m);
+            let value = swap_halfword_bytes(e, value);
+            let value = e.extend(value, 16, true);
+            e.write_register(d, value);
+        }
+        Operation::RORReg { m, dn } => shift_register(e, dn, m, Shift::Ror),
+        Operation::RSBImm { n, d } => {
+            let (x, zero, carry) = (read(e, n), e.constant(0), e.boolean(true));
+            let x = e.not(x);
+            let difference = e.add_with_carry(x, zero, carry);
+            write_nzcv(e, d, difference);
+        }
+        Operation::SBCReg { m, dn } => {
+            let (x, y, carry) = (read(e, dn), read(e, m), e.read_flag(Flag::C));
+            let y = e.not(y);
+            let difference = e.add_with_carry(x, y, carry);
+            write_nzcv(e, dn, difference);
+        }
+        Operation::STM { n, reg_list } => {
+            let base = read(e, n);
+            for (index, register) in (0..).zip(reg_list) {
+                let address = word(e, &base, index);
+                store(e, register, address, Word);
+            }
+            let end = word(e, &base, reg_list.len() as u32);
+            e.write_register(n, end);
+        }
+        Operation::STRImm { imm, n, t } => {
+            let address = offset(e, n, imm);
+            store(e, t, address, Word);
+        }
+        Operation::STRReg { m, n, t } => {
+            let address = indexed(e, n, m);
+            store(e, t, address, Word);
+        }
+        Operation::STRBImm { imm, n, t } => {
+            let address = offset(e, n, imm);
+            store(e, t, address, Byte);
+        }
+        Operation::STRBReg { m, n, t } => {
+            let address = indexed(e, n, m);
+            store(e, t, address, Byte);
+        }
+        Operation::STRHImm { imm, n, t } => {
+            let address = offset(e, n, imm);
+            store(e, t, address, Halfword);
+        }
+        Operation::STRHReg { m, n, t } => {
+            let address = indexed(e, n, m);
+            store(e, t, address, Halfword);
+        }
+        Operation::SUBImm { imm, n, d } => {
+            let (x, y, carry) = (read(e, n), e.constant(!imm), e.boolean(true));
+            let difference = e.add_with_carry(x, y, carry);
+            write_nzcv(e, d, difference);
+        }
+        Operation::SUBReg { m, n, d } => {
+            let (x, y, carry) = (read(e, n), read(e, m), e.boolean(true));
+            let y = e.not(y);
+            let difference = e.add_with_carry(x, y, carry);
+            write_nzcv(e, d, difference);
+        }
+        Operation::SUBImmSP { imm } => {
+            let (sp, imm) = (read(e, Register::SP), e.constant(imm));
+            let difference = e.binary(Sub, sp, imm);
+            e.write_register(Register::SP, difference);
+        }
+        Operation::SXTB { m, d } | Operation::SXTH { m, d } => {
+            let bits = match decoded.instruction.operation {
+                Operation::SXTB { .. } => 8,
+                _ => 16,
+            };
+            let value = read(e, m);
+            let value = e.extend(value, bits, true);
+            e.write_register(d, value);
+        }
+        Operation::TSTReg { m, n } => {
+            let (x, y) = (read(e, n), read(e, m));
+            let result = e.binary(And, x, y);
+            e.set_flags(result, None, None);
+        }
+        Operation::UXTB { m, d } | Operation::UXTH { m, d } => {
+            let bits = match decoded.instruction.operation {
+                Operation::UXTB { .. } => 8,
+                _ => 16,
+            };
+            let value = read(e, m);
+            let value = e.extend(value, bits, false);
+            e.write_register(d, value);
+        }
+    }
+}
+
+/// Returns `value` with the bytes of each halfword swapped, `rev16`.
+fn swap_halfword_bytes<E: Effects>(e: &mut E, value: E::Value) -> E::Value {
+    use BinaryOperation::{And, Lsl, Lsr, Or};
+
+    let (eight, high_mask, low_mask) = (
+        e.constant(8),
+        e.constant(0xff00_ff00),
+        e.constant(0x00ff_00ff),
+    );
+    let high = e.binary(Lsl, value.clone(), eight.clone());
+    let high = e.binary(And, high, high_mask);
+    let low = e.binary(Lsr, value, eight);
+    let low = e.binary(And, low, low_mask);
+    e.binary(Or, high, low)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        cpu::CpuState,
+        emulator::{Emulator, Stop},
+        memory::{Memory, Ram},
+        parse,
+    };
+
+    /// Engine over concrete values, executing like the emulator without exceptions.
+    struct Concrete<'a> {
+        state: CpuState,
+        memory: Ram<'a>,
+        /// Address of the next instruction.
+        next: u32,
+        stop: Option<Stop>,
+    }
+
+    impl Effects for Concrete<'_> {
+        type Value = u32;
+        type Bool = bool;
+
+        fn constant(&mut self, value: u32) -> u32 {
+            value
+        }
+
+        fn boolean(&mut self, value: bool) -> bool {
+            value
+        }
+
+        fn read_register(&mut self, register: Register) -> u32 {
+            assert_ne!(register, Register::PC);
+            self.state.get(register)
+        }
+
+        fn write_register(&mut self, register: Register, value: u32) {
+            match register {
+                Register::PC => self.next = value,
+                register => self.state.set(register, value),
+            }
+        }
+
+        fn read_memory(&mut self, address: u32, size: AccessSize) -> u32 {
+            self.memory.read(address, size).unwrap()
+        }
+
+        fn write_memory(&mut self, address: u32, size: AccessSize, value: u32) {
+            self.memory.write(address, size, value).unwrap()
+        }
+
+        fn read_flag(&mut self, flag: Flag) -> bool {
+            let apsr = self.state.apsr;
+            match flag {
+                Flag::N => apsr.n,
+                Flag::Z => apsr.z,
+                Flag::C => apsr.c,
+                Flag::V => apsr.v,
+            }
+        }
+
+        fn set_flags(&mut self, result: u32, carry: Option<bool>, overflow: Option<bool>) {
+            let apsr = &mut self.state.apsr;
+            apsr.n = result >> 31 == 1;
+            apsr.z = result == 0;
+            apsr.c = carry.unwrap_or(apsr.c);
+            apsr.v = overflow.unwrap_or(apsr.v);
+        }
+
+        fn condition(&mut self, condition: Condition) -> bool {
+            self.state.apsr.holds(condition)
+        }
+
+        fn binary(&mut self, operation: BinaryOperation, x: u32, y: u32) -> u32 {
+            match operation {
+                BinaryOperation::Add => x.wrapping_add(y),
+                BinaryOperation::Sub => x.wrapping_sub(y),
+                BinaryOperation::Mul => x.wrapping_mul(y),
+                BinaryOperation::And => x & y,
+                BinaryOperation::Or => x | y,
+                BinaryOperation::Xor => x ^ y,
+                BinaryOperation::Lsl => x.checked_shl(y & 0xff).unwrap_or(0),
+                BinaryOperation::Lsr => x.checked_shr(y & 0xff).unwrap_or(0),
+                BinaryOperation::Asr => ((x as i32) >> (y & 0xff).min(31)) as u32,
+            }
+        }
+
+        fn not(&mut self, value: u32) -> u32 {
+            !value
+        }
+
+        fn extend(&mut self, value: u32, bits: u32, signed: bool) -> u32 {
+            let shift = 32 - bits;
+            match signed {
+                true => ((value << shift) as i32 >> shift) as u32,
+                false => value << shift >> shift,
+            }
+        }
+
+        fn add_with_carry(&mut self, x: u32, y: u32, carry: bool) -> (u32, bool, bool) {
+            let unsigned = x as u64 + y as u64 + carry as u64;
+            let signed = x as i32 as i64 + y as i32 as i64 + carry as i64;
+            let result = unsigned as u32;
+            (
+                result,
+                result as u64 != unsigned,
+                result as i32 as i64 != signed,
+            )
+        }
+
+        fn shift(&mut self, shift: Shift, value: u32, amount: u32, carry: bool) -> (u32, bool) {
+            let bit = |n: u32| value >> n & 1 == 1;
+            match (shift, amount) {
+                (_, 0) => (value, carry),
+                (Shift::Lsl, 1..=32) => (value.checked_shl(amount).unwrap_or(0), bit(32 - amount)),
+                (Shift::Lsr, 1..=32) => (value.checked_shr(amount).unwrap_or(0), bit(amount - 1)),
+                (Shift::Lsl | Shift::Lsr, _) => (0, false),
+                (Shift::Asr, _) => {
+                    let amount = amount.min(32);
+                    (((value as i32) >> amount.min(31)) as u32, bit(amount - 1))
+                }
+                (Shift::Ror, _) => {
+                    let result = value.rotate_right(amount % 32);
+                    (result, result >> 31 == 1)
+                }
+            }
+        }
+
+        fn branch_if(&mut self, condition: bool, target: u32) {
+            if condition {
+                self.next = target;
+            }
+        }
+
+        fn branch_exchange(&mut self, target: u32, _link: bool) {
+            self.next = target & !1;
+        }
+
+        fn read_special(&mut self, register: SpecialRegister) -> u32 {
+            self.state.read_special(register)
+        }
+
+        fn write_special(&mut self, register: SpecialRegister, value: u32) {
+            self.state.write_special(register, value)
+        }
+
+        fn event(&mut self, event: Event) {
+            if let Event::Breakpoint(imm) = event {
+                self.stop = Some(Stop::Breakpoint(imm));
+            }
+        }
+    }
+
+    #[test]
+    fn matches_emulator() {
+        let code: [u16; 24] = [
+            0xb530, // push {r4, r5, lr}
+            0x200a, // movs r0, #10
+            0x4241, // rsbs r1, r0
+            0x1049, // asrs r1, r1, #1
+            0x0fcb, // lsrs r3, r1, #31
+            0x4159, // adcs r1, r3
+            0x2403, // movs r4, #3
+            0x41e1, // rors r1, r4
+            0xba0a, // rev r2, r1
+            0xbaca, // revsh r2, r1
+            0x4348, // muls r0, r1
+            0xb243, // sxtb r3, r0
+            0x4298, // cmp r0, r3
+            0xd800, // bhi +0
+            0x4199, // sbcs r1, r3
+            0x466d, // mov r5, sp
+            0x3d10, // subs r5, #16
+            0xc50c, // stmia r5!, {r2, r3}
+            0x3d08, // subs r5, #8
+            0xcd03, // ldmia r5!, {r0, r1}
+            0x5fac, // ldrsh r4, [r5, r6]
+            0xbd30, // pop {r4, r5, pc}
+            0xbe00, // bkpt 0
+            0,
+        ];
+        let mut bytes = [0; 0x200];
+        for (index, halfword) in code.iter().enumerate() {
+            bytes[2 * index..2 * index + 2].copy_from_slice(&halfword.to_le_bytes());
+        }
+        let mut copy = bytes;
+        let mut state = CpuState::new(0x200, 0);
+        state.set(Register::LR, 0x2d);
+        let mut emulator = Emulator::new(
+            state.clone(),
+            Ram {
+                base: 0,
+                bytes: &mut bytes,
+            },
+        );
+        let mut concrete = Concrete {
+            state,
+            memory: Ram {
+                base: 0,
+                bytes: &mut copy,
+            },
+            next: 0,
+            stop: None,
+        };
+        while concrete.stop.is_none() {
+            let address = concrete.state.get(Register::PC);
+            let instruction = parse(&concrete.memory.bytes[address as usize..]).unwrap();
+            let decoded = DecodedAt {
+                instruction,
+                address,
+            };
+            concrete.next = decoded.next_address();
+            describe(&decoded, &mut concrete);
+            concrete.state.set(Register::PC, concrete.next);
+            if concrete.stop.is_none() {
+                assert_eq!(emulator.step(), Ok(()));
+                assert_eq!(emulator.state, concrete.state, "at {address:#x}");
+            }
+        }
+        assert_eq!(emulator.step(), Err(Stop::Breakpoint(0)));
+        assert_eq!(emulator.memory.bytes[..], concrete.memory.bytes[..]);
+    }
+
+    #[test]
+    fn edge_cases_match_emulator() {
+        // Instruction, r0, r1 and carry before.
+        let cases: [(u16, u32, u32, bool); 18] = [
+            (0x0808, 7, 0x8000_0001, false),  // lsrs r0, r1, #32
+            (0x1008, 7, 0x8000_0000, false),  // asrs r0, r1, #32
+            (0x1008, 7, 0x7fff_ffff, true),   // asrs r0, r1, #32
+            (0x4088, 1, 32, false),           // lsls r0, r1
+            (0x4088, 1, 33, true),            // lsls r0, r1
+            (0x4088, 5, 0x100, true),         // lsls r0, r1
+            (0x40c8, 0x8000_0000, 32, false), // lsrs r0, r1
+            (0x40c8, 0x8000_0000, 255, true), // lsrs r0, r1
+            (0x4108, 0x8000_0000, 40, false), // asrs r0, r1
+            (0x41c8, 0x8000_0001, 32, false), // rors r0, r1
+            (0x4148, 0x7fff_ffff, 0, true),   // adcs r0, r1
+            (0x4188, 0x8000_0000, 0, false),  // sbcs r0, r1
+            (0x4248, 7, 0x8000_0000, false),  // rsbs r0, r1, #0
+            (0xc803, 0x80, 0, false),         // ldm r0, {r0, r1}
+            (0xc905, 0, 0x80, false),         // ldm r1!, {r0, r2}
+            (0xc003, 0x90, 5, false),         // stm r0!, {r0, r1}
+            (0x4487, 0x11, 0, false),         // add pc, r0
+            (0x468f, 0, 0x81, false),         // mov pc, r1
+        ];
+        for (halfword, r0, r1, carry) in cases {
+            let mut bytes = [0; 0x100];
+            bytes[0x40..0x42].copy_from_slice(&halfword.to_le_bytes());
+            bytes[0x80..0x88].copy_from_slice(&[1, 0, 0, 0, 2, 0, 0, 0]);
+            let mut copy = bytes;
+            let mut state = CpuState::new(0x100, 0x40);
+            state.set(Register::R0, r0);
+            state.set(Register::R1, r1);
+            state.apsr.c = carry;
+            let mut emulator = Emulator::new(
+                state.clone(),
+                Ram {
+                    base: 0,
+                    bytes: &mut bytes,
+                },
+            );
+            let mut concrete = Concrete {
+                state,
+                memory: Ram {
+                    base: 0,
+                    bytes: &mut copy,
+                },
+                next: 0x42,
+                stop: None,
+            };
+            let instruction = parse(&halfword.to_le_bytes()).unwrap();
+            describe(
+                &DecodedAt {
+                    instruction,
+                    address: 0x40,
+                },
+                &mut concrete,
+            );
+            concrete.state.set(Register::PC, concrete.next);
+            assert_eq!(emulator.step(), Ok(()));
+            assert_eq!(emulator.state, concrete.state, "{halfword:#06x}");
+            assert_eq!(
+                emulator.memory.bytes[..],
+                concrete.memory.bytes[..],
+                "{halfword:#06x}"
+            );
+        }
+    }
+}
@@ -0,0 +1,744 @@
+//! Detection of semihosting calls, requests to a debugger attached to the target like
+//! writing to its console or exiting the program, and with the `std` feature a [`Host`]
+//! serving them to emulated firmware.
+//!
+//! # Example
+//! ```
+//! # use armv6_m_instruction_parser::{control_flow::ControlFlowGraph, functions::FunctionRange, program::Program, propagation::propagate, semihosting::{semihosting_calls, Trap}};
+//! // movs r0, #0x18; bkpt 0xab; bx lr
+//! let image = [0x18, 0x20, 0xab, 0xbe, 0x70, 0x47];
+//! let program = Program::new(&image, 0);
+//! let cfg = ControlFlowGraph::new(&program, &[]);
+//! let function = FunctionRange { start: 0, end: 0x06, called: false, saves_lr: false };
+//! let values = propagate(&program, &image, &cfg, &function);
+//! let calls = semihosting_calls(&program, Some(&values));
+//! assert_eq!((calls[0].site, calls[0].trap), (0x02, Trap::Bkpt));
+//! assert_eq!(calls[0].operation_name(), Some("SYS_EXIT"));
+//! ```
+
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Read, Seek, SeekFrom, Stdin, Stdout, Write},
+    time::{Instant, SystemTime},
+};
+
+#[cfg(feature = "std")]
+use crate::{
+    cpu::CpuState,
+    emulator::{Emulator, Fault, Hooks, Stop},
+    memory::Memory,
+};
+use crate::{
+    instructons::Operation, program::Program, propagation::Propagation, registers::Register,
+};
+
+/// Immediate of the `bkpt` and `svc` instructions requesting semihosting in Thumb state.
+const SEMIHOSTING_IMMEDIATE: u32 = 0xab;
+
+/// Operation numbers of the semihosting specification, requested in `r0`.
+pub const SYS_OPEN: u32 = 0x01;
+pub const SYS_CLOSE: u32 = 0x02;
+pub const SYS_WRITEC: u32 = 0x03;
+pub const SYS_WRITE0: u32 = 0x04;
+pub const SYS_WRITE: u32 = 0x05;
+pub const SYS_READ: u32 = 0x06;
+pub const SYS_READC: u32 = 0x07;
+pub const SYS_ISERROR: u32 = 0x08;
+pub const SYS_ISTTY: u32 = 0x09;
+pub const SYS_SEEK: u32 = 0x0a;
+pub const SYS_FLEN: u32 = 0x0c;
+pub const SYS_TMPNAM: u32 = 0x0d;
+pub const SYS_REMOVE: u32 = 0x0e;
+pub const SYS_RENAME: u32 = 0x0f;
+pub const SYS_CLOCK: u32 = 0x10;
+pub const SYS_TIME: u32 = 0x11;
+pub const SYS_SYSTEM: u32 = 0x12;
+pub const SYS_ERRNO: u32 = 0x13;
+pub const SYS_GET_CMDLINE: u32 = 0x15;
+pub const SYS_HEAPINFO: u32 = 0x16;
+pub const SYS_EXIT: u32 = 0x18;
+pub const SYS_EXIT_EXTENDED: u32 = 0x20;
+pub const SYS_ELAPSED: u32 = 0x30;
+pub const SYS_TICKFREQ: u32 = 0x31;
+
+/// Instruction a semihosting call is made with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Trap {
+    /// `bkpt 0xab`, the only form on ARMv6-M and ARMv7-M.
+    Bkpt,
+    /// `svc 0xab`, used before `bkpt` was, and by code handling it in its `SVCall` handler.
+    Svc,
+}
+
+/// Semihosting call found by [`semihosting_calls`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SemihostingCall {
+    pub site: u32,
+    pub trap: Trap,
+    /// Operation number requested in `r0`, if known from constant propagation.
+    pub operation: Option<u32>,
+}
+
+impl SemihostingCall {
+    /// Returns the name of the requested operation in the semihosting specification.
+    pub fn operation_name(&self) -> Option<&'static str> {
+        Some(match self.operation? {
+            SYS_OPEN => "SYS_OPEN",
+            SYS_CLOSE => "SYS_CLOSE",
+            SYS_WRITEC => "SYS_WRITEC",
+            SYS_WRITE0 => "SYS_WRITE0",
+            SYS_WRITE => "SYS_WRITE",
+            SYS_READ => "SYS_READ",
+            SYS_READC => "SYS_READC",
+            SYS_ISERROR => "SYS_ISERROR",
+            SYS_ISTTY => "SYS_ISTTY",
+            SYS_SEEK => "SYS_SEEK",
+            SYS_FLEN => "SYS_FLEN",
+            SYS_TMPNAM => "SYS_TMPNAM",
+            SYS_REMOVE => "SYS_REMOVE",
+            SYS_RENAME => "SYS_RENAME",
+            SYS_CLOCK => "SYS_CLOCK",
+            SYS_TIME => "SYS_TIME",
+            SYS_SYSTEM => "SYS_SYSTEM",
+            SYS_ERRNO => "SYS_ERRNO",
+            SYS_GET_CMDLINE => "SYS_GET_CMDLINE",
+            SYS_HEAPINFO => "SYS_HEAPINFO",
+            SYS_EXIT => "SYS_EXIT",
+            SYS_EXIT_EXTENDED => "SYS_EXIT_EXTENDED",
+            SYS_ELAPSED => "SYS_ELAPSED",
+            SYS_TICKFREQ => "SYS_TICKFREQ",
+            _ => return None,
+        })
+    }
+}
+
+/// Returns how `operation` requests semihosting, `None` if it does not.
+pub fn semihosting_trap(operation: &Operation) -> Option<Trap> {
+    match *operation {
+        Operation::BKPT { imm } if imm == SEMIHOSTING_IMMEDIATE => Some(Trap::Bkpt),
+        Operation::SVC { imm } if imm == SEMIHOSTING_IMMEDIATE => Some(Trap::Svc),
+        _ => None,
+    }
+}
+
+/// Returns the semihosting calls of `program` in address order, with the operation numbers
+/// known from `values`, the propagation through the function making the calls.
+pub fn semihosting_calls(program: &Program, values: Option<&Propagation>) -> Vec<SemihostingCall> {
+    program
+        .iter()
+        .filter_map(|(site, result)| {
+            let trap = semihosting_trap(&result.as_ref().ok()?.operation)?;
+            let operation = values
+                .and_then(|values| values.before(site))
+                .and_then(|before| before.get(Register::R0));
+            Some(SemihostingCall {
+                site,
+                trap,
+                operation,
+            })
+        })
+        .collect()
+}
+
+/// Reason of `SYS_EXIT` for the application exiting, `ADP_Stopped_ApplicationExit`.
+pub const APPLICATION_EXIT: u32 = 0x20026;
+
+/// Exit requested with `SYS_EXIT` or `SYS_EXIT_EXTENDED`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Exit {
+    /// Reason, [`APPLICATION_EXIT`] or another `ADP_Stopped_*` value.
+    pub reason: u32,
+    /// Exit code of `SYS_EXIT_EXTENDED`, 0 for `SYS_EXIT`.
+    pub subcode: u32,
+}
+
+impl Exit {
+    /// Returns `true` if the application exited with code 0.
+    pub fn is_success(&self) -> bool {
+        self.reason == APPLICATION_EXIT && self.subcode == 0
+    }
+}
+
+/// `errno` values returned by `SYS_ERRNO` for errors without an OS error.
+#[cfg(feature = "std")]
+const EIO: u32 = 5;
+#[cfg(feature = "std")]
+const EBADF: u32 = 9;
+#[cfg(feature = "std")]
+const ENOSYS: u32 = 38;
+
+/// Bytes `SYS_READ` and `SYS_WRITE` move between the host and the guest at once, bounding
+/// the buffers of the host whatever length the guest asks for.
+#[cfg(feature = "std")]
+const TRANSFER: u32 = 0x1000;
+
+/// File opened by `SYS_OPEN`.
+#[cfg(feature = "std")]
+#[derive(Debug)]
+enum Handle {
+    /// `:tt` opened for reading, the console input.
+    Input,
+    /// `:tt` opened for writing, the console output.
+    Output,
+    /// `:tt` opened for appending, the standard error of the host.
+    Error,
+    File(File),
+}
+
+/// Host serving the semihosting calls of emulated firmware from the console `input` and
+/// `output` and the host file system, like a debugger does.
+///
+/// Calls failing on the host return -1 with the error for `SYS_ERRNO`. `SYS_SYSTEM`,
+/// `SYS_TMPNAM`, `SYS_ELAPSED` and `SYS_TICKFREQ` are not supported and fail with `ENOSYS`.
+///
+/// # Example
+/// ```
+/// # use armv6_m_instruction_parser::{cpu::CpuState, emulator::Emulator, memory::Ram, semihosting::Host};
+/// // movs r0, #4; movs r1, #0x14; bkpt 0xab; movs r0, #0x18; ldr r1, [pc, #4]; bkpt 0xab
+/// let code = [0x04, 0x20, 0x14, 0x21, 0xab, 0xbe, 0x18, 0x20, 0x01, 0x49, 0xab, 0xbe, 0, 0, 0, 0];
+/// // Exit reason ADP_Stopped_ApplicationExit and the string SYS_WRITE0 writes.
+/// let data = [0x26, 0x00, 0x02, 0x00, b'h', b'i', b'\n', 0];
+/// let mut image = [&code[..], &data].concat();
+/// let memory = Ram { base: 0, bytes: &mut image };
+/// let mut emulator = Emulator::new(CpuState::new(0x2000_0000, 0), memory);
+/// let mut host = Host::with_console(&[][..], Vec::new());
+/// assert!(host.run(&mut emulator).unwrap().is_success());
+/// assert_eq!(host.output, b"hi\n");
+/// ```
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub struct Host<I = Stdin, O = Stdout> {
+    pub input: I,
+    pub output: O,
+    /// Command line returned by `SYS_GET_CMDLINE`.
+    pub command_line: String,
+    /// Files by handle, closed ones `None`.
+    files: Vec<Option<Handle>>,
+    errno: u32,
+    start: Instant,
+}
+
+#[cfg(feature = "std")]
+impl Host {
+    /// Returns a host with the standard input and output of the process as console.
+    pub fn new() -> Self {
+        Self::with_console(io::stdin(), io::stdout())
+    }
+}
+
+#[cfg(feature = "std")]
+impl Default for Host {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "std")]
+impl<I: Read, O: Write> Host<I, O> {
+    /// Returns a host with `input` and `output` as console, like a buffer capturing the
+    /// output in tests.
+    pub fn with_console(input: I, output: O) -> Self {
+        Self {
+            input,
+            output,
+            command_line: String::new(),
+            files: Vec::new(),
+            errno: 0,
+            start: Instant::now(),
+        }
+    }
+
+    /// Runs `emulator` serving the semihosting calls it stops at, until the firmware exits
+    /// or the emulator stops otherwise.
+    ///
+    /// A call accessing memory that is not mapped faults like a bus error of the `bkpt`.
+    pub fn run<M: Memory, H: Hooks>(
+        &mut self,
+        emulator: &mut Emulator<M, H>,
+    ) -> Result<Exit, Stop> {
+        loop {
+            match emulator.run() {
+                Stop::Breakpoint(SEMIHOSTING_IMMEDIATE) => {
+                    match self.call(&mut emulator.state, &mut emulator.memory) {
+                        Ok(Some(exit)) => return Ok(exit),
+                        Ok(None) => {}
+                        Err(fault) => emulator.exceptions.handle(
+                            &mut emulator.state,
+                            &mut emulator.memory,
+                            Stop::HardFault(fault),
+                        )?,
+                    }
+                }
+                stop => return Err(stop),
+            }
+        }
+    }
+
+    /// Serves the call of the `bkpt 0xab` at the `pc` of `state`, the operation in `r0` with
+    /// its parameter in `r1`, returning the result in `r0` and continuing after the `bkpt`.
+    ///
+    /// Returns the exit requested, with `state` unchanged, or the fault of an access to
+    /// `memory`.
+    pub fn call(
+        &mut self,
+        state: &mut CpuState,
+        memory: &mut impl Memory,
+    ) -> Result<Option<Exit>, Fault> {
+        let parameter = state.get(Register::R1);
+        let result = match state.get(Register::R0) {
+            SYS_EXIT => {
+                return Ok(Some(Exit {
+                    reason: parameter,
+                    subcode: 0,
+                }))
+            }
+            SYS_EXIT_EXTENDED => {
+                let [reason, subcode] = words(memory, parameter)?;
+                return Ok(Some(Exit { reason, subcode }));
+            }
+            operation => self.operation(operation, parameter, memory)?,
+        };
+        state.set(Register::R0, result);
+        state.set(Register::PC, state.get(Register::PC).wrapping_add(2));
+        Ok(None)
+    }
+
+    /// Performs `operation` with the `parameter` in `r1`, returning the result for `r0`.
+    fn operation(
+        &mut self,
+        operation: u32,
+        parameter: u32,
+        memory: &mut impl Memory,
+    ) -> Result<u32, Fault> {
+        Ok(match operation {
+            SYS_OPEN => {
+                let [name, mode, length] = words(memory, parameter)?;
+                let name = string(memory, name, length)?;
+                let result = self.open(&name, mode);
+                self.result(result)
+            }
+            SYS_CLOSE => {
+                let [handle] = words(memory, parameter)?;
+                match self.files.get_mut(handle as usize).and_then(Option::take) {
+                    Some(_) => 0,
+                    None => self.fail(EBADF),
+                }
+            }
+            SYS_WRITEC => {
+                let byte = read_byte(memory, parameter)?;
+                let result = self.output.write_all(&[byte]).map(|_| 0);
+                self.result(result)
+            }
+            SYS_WRITE0 => {
+                let mut bytes = Vec::new();
+                for address in parameter.. {
+                    match read_byte(memory, address)? {
+                        0 => break,
+                        byte => bytes.push(byte),
+                    }
+                }
+                let result = self.output.write_all(&bytes).map(|_| 0);
+                self.result(result)
+            }
+            SYS_WRITE => {
+                let [handle, buffer, length] = words(memory, parameter)?;
+                let mut written = 0;
+                loop {
+                    let part = (length - written).min(TRANSFER);
+                    let bytes = bytes(memory, buffer.wrapping_add(written), part)?;
+                    if let Err(error) = self.write(handle, &bytes) {
+                        self.result(Err(error));
+                        break length - written;
+                    }
+                    written += part;
+                    if written == length {
+                        break 0;
+                    }
+                }
+            }
+            SYS_READ => {
+                let [handle, buffer, length] = words(memory, parameter)?;
+                let mut chunk = vec![0; length.min(TRANSFER) as usize];
+                let mut read = 0;
+                loop {
+                    let part = (length - read).min(TRANSFER) as usize;
+                    let count = match self.read(handle, &mut chunk[..part]) {
+                        Ok(count) => count,
+                        Err(error) => {
+                            self.result(Err(error));
+                            break length - read;
+                        }
+                    };
+                    for (offset, byte) in (read..).zip(&chunk[..count]) {
+                        write_byte(memory, buffer.wrapping_add(offset), *byte)?;
+                    }
+                    read += count as u32;
+                    if count < part || read == length {
+                        break length - read;
+                    }
+                }
+            }
+            SYS_READC => {
+                let mut byte = [0];
+                let result = self.input.read_exact(&mut byte).map(|_| byte[0] as u32);
+                self.result(result)
+            }
+            SYS_ISERROR => {
+                let [status] = words(memory, parameter)?;
+                ((status as i32) < 0) as u32
+            }
+            SYS_ISTTY => {
+                let [handle] = words(memory, parameter)?;
+                match self.files.get(handle as usize) {
+                    Some(Some(Handle::File(_))) => 0,
+                    Some(Some(_)) => 1,
+                    _ => self.fail(EBADF),
+                }
+            }
+            SYS_SEEK => {
+                let [handle, position] = words(memory, parameter)?;
+                let result = self
+                    .file(handle)
+                    .and_then(|file| file.seek(SeekFrom::Start(position as u64)))
+                    .map(|_| 0);
+                self.result(result)
+            }
+            SYS_FLEN => {
+                let [handle] = words(memory, parameter)?;
+                let result = self
+                    .file(handle)
+                    .and_then(|file| file.metadata())
+                    .map(|metadata| metadata.len() as u32);
+                self.result(result)
+            }
+            SYS_REMOVE => {
+                let [name, length] = words(memory, parameter)?;
+                let name = string(memory, name, length)?;
+                let result = std::fs::remove_file(name).map(|_| 0);
+                self.result(result)
+            }
+            SYS_RENAME => {
+                let [from, from_length, to, to_length] = words(memory, parameter)?;
+                let from = string(memory, from, from_length)?;
+                let to = string(memory, to, to_length)?;
+                let result = std::fs::rename(from, to).map(|_| 0);
+                self.result(result)
+            }
+            SYS_CLOCK => (self.start.elapsed().as_millis() / 10) as u32,
+            SYS_TIME => SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map_or(0, |time| time.as_secs() as u32),
+            SYS_ERRNO => self.errno,
+            SYS_GET_CMDLINE => {
+                let [buffer, length] = words(memory, parameter)?;
+                let command_line = self.command_line.as_bytes();
+                if command_line.len() >= length as usize {
+                    return Ok(u32::MAX);
+                }
+                for (address, byte) in (buffer..).zip(command_line.iter().chain([&0])) {
+                    write_byte(memory, address, *byte)?;
+                }
+                write_word(memory, parameter + 4, command_line.len() as u32)?;
+                0
+            }
+            SYS_HEAPINFO => {
+                // The heap and stack are unknown to the host, left to the firmware.
+                let [block] = words(memory, parameter)?;
+                for offset in (0..16).step_by(4) {
+                    write_word(memory, block.wrapping_add(offset), 0)?;
+                }
+                0
+            }
+            _ => self.fail(ENOSYS),
+        })
+    }
+
+    /// Opens `name` with the `fopen` mode numbered `mode`, returning the handle.
+    fn open(&mut self, name: &str, mode: u32) -> io::Result<u32> {
+        let handle = match (name, mode) {
+            (":tt", 0..=3) => Handle::Input,
+            (":tt", 4..=7) => Handle::Output,
+            (":tt", 8..=11) => Handle::Error,
+            (_, 0..=11) => {
+                let mut options = OpenOptions::new();
+                // r, r+, w, w+, a and a+, each also binary.
+                match mode / 2 {
+                    0 => options.read(true),
+                    1 => options.read(true).write(true),
+                    2 => options.write(true).create(true).truncate(true),
+                    3 => options.read(true).write(true).create(true).truncate(true),
+                    4 => options.append(true).create(true),
+                    _ => options.read(true).append(true).create(true),
+                };
+                Handle::File(options.open(name)?)
+            }
+            _ => return Err(io::ErrorKind::InvalidInput.into()),
+        };
+        let free = self.files.iter().position(Option::is_none);
+        let handle = match free {
+            Some(free) => {
+                self.files[free] = Some(handle);
+                free
+            }
+            None => {
+                self.files.push(Some(handle));
+                self.files.len() - 1
+            }
+        };
+        Ok(handle as u32)
+    }
+
+    /// Writes `bytes` to the file `handle`, returning the number of bytes written.
+    fn write(&mut self, handle: u32, bytes: &[u8]) -> io::Result<u32> {
+        match self.files.get_mut(handle as usize) {
+            Some(Some(Handle::Output)) => self.output.write_all(bytes)?,
+            Some(Some(Handle::Error)) => io::stderr().write_all(bytes)?,
+            Some(Some(Handle::File(file))) => file.write_all(bytes)?,
+            _ => return Err(bad_handle()),
+        }
+        Ok(bytes.len() as u32)
+    }
+
+    /// Reads to `bytes` from the file `handle`, returning the number of bytes read.
+    fn read(&mut self, handle: u32, bytes: &mut [u8]) -> io::Result<usize> {
+        match self.files.get_mut(handle as usize) {
+            Some(Some(Handle::Input)) => self.input.read(bytes),
+            Some(Some(Handle::File(file))) => {
+                let mut read = 0;
+                while read < bytes.len() {
+                    match file.read(&mut bytes[read..])? {
+                        0 => break,
+                        length => read += length,
+                    }
+                }
+                Ok(read)
+            }
+            _ => Err(bad_handle()),
+        }
+    }
+
+    /// Returns the host file `handle`, the console is not a file.
+    fn file(&mut self, handle: u32) -> io::Result<&mut File> {
+        match self.files.get_mut(handle as usize) {
+            Some(Some(Handle::File(file))) => Ok(file),
+            _ => Err(bad_handle()),
+        }
+    }
+
+    /// Returns the value of `result`, or -1 recording the error.
+    fn result(&mut self, result: io::Result<u32>) -> u32 {
+        match result {
+            Ok(value) => value,
+            Err(error) => {
+                let errno = error.raw_os_error().map_or(EIO, |errno| errno as u32);
+                self.fail(errno)
+            }
+        }
+    }
+
+    /// Records `errno` and returns -1.
+    fn fail(&mut self, errno: u32) -> u32 {
+        self.errno = errno;
+        u32::MAX
+    }
+}
+
+#[cfg(feature = "std")]
+fn bad_handle() -> io::Error {
+    io::Error::from_raw_os_error(EBADF as i32)
+}
+
+#[cfg(feature = "std")]
+fn bus_fault(address: u32) -> impl Fn(crate::memory::BusError) -> Fault {
+    move |error| Fault::Bus { address, error }
+}
+
+/// Reads the `N` words of the parameter block at `address`.
+#[cfg(feature = "std")]
+fn words<const N: usize>(memory: &mut impl Memory, address: u32) -> Result<[u32; N], Fault> {
+    let mut words = [0; N];
+    for (address, word) in (address..).step_by(4).zip(&mut words) {
+        *word = memory.read_word(address).map_err(bus_fault(address))?;
+    }
+    Ok(words)
+}
+
+#[cfg(feature = "std")]
+fn read_byte(memory: &mut impl Memory, address: u32) -> Result<u8, Fault> {
+    memory.read_byte(address).map_err(bus_fault(address))
+}
+
+#[cfg(feature = "std")]
+fn write_byte(memory: &mut impl Memory, address: u32, value: u8) -> Result<(), Fault> {
+    memory
+        .write_byte(address, value)
+        .map_err(bus_fault(address))
+}
+
+#[cfg(feature = "std")]
+fn write_word(memory: &mut impl Memory, address: u32, value: u32) -> Result<(), Fault> {
+    memory
+        .write_word(address, value)
+        .map_err(bus_fault(address))
+}
+
+/// Reads the `length` bytes at `address`.
+#[cfg(feature = "std")]
+fn bytes(memory: &mut impl Memory, address: u32, length: u32) -> Result<Vec<u8>, Fault> {
+    (0..length)
+        .map(|offset| read_byte(memory, address.wrapping_add(offset)))
+        .collect()
+}
+
+/// Reads the file name of `length` bytes at `address`, invalid UTF-8 replaced.
+#[cfg(feature = "std")]
+fn string(memory: &mut impl Memory, address: u32, length: u32) -> Result<String, Fault> {
+    Ok(String::from_utf8_lossy(&bytes(memory, address, length)?).into_owned())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[cfg(feature = "std")]
+    use crate::{instructons::AccessSize, memory::BusError};
+    #[cfg(feature = "std")]
+    use core::ops::Range;
+
+    #[test]
+    fn traps() {
+        // bkpt 0xab; bkpt 0x00; svc 0xab; svc 0x01
+        let image = [0xab, 0xbe, 0x00, 0xbe, 0xab, 0xdf, 0x01, 0xdf];
+        let program = Program::new(&image, 0);
+        let calls = semihosting_calls(&program, None);
+        assert_eq!(
+            calls,
+            [
+                SemihostingCall {
+                    site: 0x00,
+                    trap: Trap::Bkpt,
+                    operation: None
+                },
+                SemihostingCall {
+                    site: 0x04,
+                    trap: Trap::Svc,
+                    operation: None
+                },
+            ]
+        );
+        assert_eq!(calls[0].operation_name(), None);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn host_files() {
+        use crate::memory::Ram;
+
+        let path = std::env::temp_dir().join("semihosting-host-files.txt");
+        let name = path.to_str().unwrap().as_bytes();
+        let mut bytes = [0; 0x200];
+        bytes[0x100..][..name.len()].copy_from_slice(name);
+        bytes[0x40..0x45].copy_from_slice(b"hello");
+        let mut memory = Ram {
+            base: 0x2000_0000,
+            bytes: &mut bytes,
+        };
+        let mut state = CpuState::new(0x2000_0200, 0);
+        let mut host = Host::with_console(&b"x"[..], Vec::new());
+        // Calls `operation` with the parameter block `words` at 0x2000_0000.
+        let mut call = |operation: u32, words: &[u32]| {
+            for (address, word) in (0x2000_0000..).step_by(4).zip(words) {
+                memory.write_word(address, *word).unwrap();
+            }
+            state.set(Register::R0, operation);
+            state.set(Register::R1, 0x2000_0000);
+            assert_eq!(host.call(&mut state, &mut memory), Ok(None));
+            state.get(Register::R0)
+        };
+        let length = name.len() as u32;
+        let handle = call(SYS_OPEN, &[0x2000_0100, 4, length]);
+        assert_eq!(call(SYS_WRITE, &[handle, 0x2000_0040, 5]), 0);
+        assert_eq!(call(SYS_FLEN, &[handle]), 5);
+        assert_eq!(call(SYS_ISTTY, &[handle]), 0);
+        assert_eq!(call(SYS_CLOSE, &[handle]), 0);
+        // The handle of the closed file is reused.
+        assert_eq!(call(SYS_OPEN, &[0x2000_0100, 0, length]), handle);
+        assert_eq!(call(SYS_SEEK, &[handle, 1]), 0);
+        assert_eq!(call(SYS_READ, &[handle, 0x2000_0080, 8]), 4);
+        assert_eq!(call(SYS_CLOSE, &[handle]), 0);
+        assert_eq!(call(SYS_READ, &[handle, 0x2000_0080, 8]), 8);
+        assert_eq!(call(SYS_ERRNO, &[]), EBADF);
+        assert_eq!(call(SYS_REMOVE, &[0x2000_0100, length]), 0);
+        assert_eq!(call(SYS_READC, &[]), b'x' as u32);
+        assert_eq!(call(SYS_SYSTEM, &[]), u32::MAX);
+        assert_eq!(call(SYS_ERRNO, &[]), ENOSYS);
+        assert_eq!(&bytes[0x80..0x84], b"ello");
+        assert_eq!(state.get(Register::PC), 30);
+    }
+
+    /// RAM of 16 KiB repeated over the address space.
+    #[cfg(feature = "std")]
+    struct Mirrored([u8; 0x4000]);
+
+    #[cfg(feature = "std")]
+    impl Memory for Mirrored {
+        fn read(&mut self, address: u32, size: AccessSize) -> Result<u32, BusError> {
+            let mut word = [0; 4];
+            for (offset, byte) in (0..size.bytes()).zip(&mut word) {
+                *byte = self.0[(address.wrapping_add(offset) & 0x3fff) as usize];
+            }
+            Ok(u32::from_le_bytes(word))
+        }
+
+        fn write(&mut self, address: u32, size: AccessSize, value: u32) -> Result<(), BusError> {
+            for (offset, byte) in (0..size.bytes()).zip(value.to_le_bytes()) {
+                self.0[(address.wrapping_add(offset) & 0x3fff) as usize] = byte;
+            }
+            Ok(())
+        }
+
+        fn range(&self) -> Range<u32> {
+            0..u32::MAX
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn console_transfers() {
+        let input: Vec<u8> = (0..0x1800).map(|index| index as u8).collect();
+        let mut memory = Mirrored([0; 0x4000]);
+        memory.0[0x3ffe..].copy_from_slice(b"ab");
+        memory.0[..2].copy_from_slice(b"cd");
+        memory.0[0x200..0x203].copy_from_slice(b":tt");
+        let mut state = CpuState::new(0x100, 0);
+        let mut host = Host::with_console(&input[..], Vec::new());
+        // Calls `operation` with the parameter block `words` at 0x100.
+        let mut call = |operation: u32, words: &[u32]| {
+            for (address, word) in (0x100..).step_by(4).zip(words) {
+                memory.write_word(address, *word).unwrap();
+            }
+            state.set(Register::R0, operation);
+            state.set(Register::R1, 0x100);
+            let result = host
+                .call(&mut state, &mut memory)
+                .map(|_| state.get(Register::R0));
+            (result, memory.read_word(0x2ffc).unwrap())
+        };
+        let (stdin, stdout) = (
+            call(SYS_OPEN, &[0x200, 0, 3]).0,
+            call(SYS_OPEN, &[0x200, 4, 3]).0,
+        );
+        let (stdin, stdout) = (stdin.unwrap(), stdout.unwrap());
+        // A read asking for more than the console has stops at its end, read in chunks.
+        assert_eq!(
+            call(SYS_READ, &[stdin, 0x1800, 0xffff_ff00]),
+            (Ok(0xffff_e700), 0xfffe_fdfc)
+        );
+        // A write wrapping at the end of the address space, and one of many chunks.
+        assert_eq!(call(SYS_WRITE, &[stdout, 0xffff_fffe, 4]).0, Ok(0));
+        assert_eq!(call(SYS_WRITE, &[stdout, 0x1800, 0x1800]).0, Ok(0));
+        assert_eq!(host.output[..4], *b"abcd");
+        assert_eq!(host.output[4..], input);
+    }
+}
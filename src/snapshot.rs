@@ -0,0 +1,153 @@
+//! Compact binary snapshots of programs and analysis results, encoded with `postcard`, for
+//! caching the analysis of large images on disk.
+//!
+//! Snapshots start with a header holding [`FORMAT_VERSION`], so snapshots written by a
+//! version of the crate encoding the types differently are rejected instead of misread.
+//!
+//! # Example
+//! ```
+//! # use armv6_m_instruction_parser::{program::Program, snapshot, xref::Xrefs};
+//! // push {r7, lr}; bl; pop {r7, pc}
+//! let program = Program::new(&[0x80, 0xb5, 0x00, 0xf0, 0x7e, 0xf8, 0x80, 0xbd], 0x1000);
+//! let xrefs = Xrefs::new(&program);
+//! let bytes = snapshot::to_bytes(&(&program, &xrefs))?;
+//! let (loaded, _): (Program, Xrefs) = snapshot::from_bytes(&bytes)?;
+//! assert_eq!(loaded, program);
+//! # Ok::<(), snapshot::SnapshotError>(())
+//! ```
+
+use alloc::vec::Vec;
+use core::fmt::{self, Display};
+#[cfg(feature = "std")]
+use std::{fs, io, path::Path};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+/// Version of the encoding of the types of the crate, increased when a serialized type
+/// changes.
+pub const FORMAT_VERSION: u32 = 1;
+
+/// First bytes of a snapshot.
+const MAGIC: [u8; 4] = *b"A6MS";
+
+/// Start of a snapshot.
+#[derive(Serialize, Deserialize)]
+struct Header {
+    magic: [u8; 4],
+    version: u32,
+}
+
+/// Error from writing or reading a snapshot.
+#[derive(Debug)]
+pub enum SnapshotError {
+    /// The bytes are not a snapshot.
+    NotASnapshot,
+    /// The snapshot was written with another [`FORMAT_VERSION`], given.
+    Version(u32),
+    /// The snapshot could not be encoded or decoded, e.g. as it is truncated.
+    Postcard(postcard::Error),
+    /// The snapshot file could not be written or read.
+    #[cfg(feature = "std")]
+    Io(io::Error),
+}
+
+impl Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotError::NotASnapshot => f.write_str("not a snapshot"),
+            SnapshotError::Version(version) => write!(
+                f,
+                "snapshot has format version {version}, expected {FORMAT_VERSION}"
+            ),
+            SnapshotError::Postcard(error) => write!(f, "invalid snapshot: {error}"),
+            #[cfg(feature = "std")]
+            SnapshotError::Io(error) => write!(f, "snapshot file: {error}"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for SnapshotError {}
+
+impl From<postcard::Error> for SnapshotError {
+    fn from(error: postcard::Error) -> Self {
+        SnapshotError::Postcard(error)
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<io::Error> for SnapshotError {
+    fn from(error: io::Error) -> Self {
+        SnapshotError::Io(error)
+    }
+}
+
+/// Encodes `value`, like a [`Program`](crate::program::Program) or a tuple of it and its
+/// analysis results, into a snapshot.
+pub fn to_bytes<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>, SnapshotError> {
+    let header = Header {
+        magic: MAGIC,
+        version: FORMAT_VERSION,
+    };
+    let bytes = postcard::to_allocvec(&header)?;
+    Ok(postcard::to_extend(value, bytes)?)
+}
+
+/// Decodes a snapshot written by [`to_bytes`].
+pub fn from_bytes<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, SnapshotError> {
+    let (header, rest) = match postcard::take_from_bytes::<Header>(bytes) {
+        Ok((header, rest)) if header.magic == MAGIC => (header, rest),
+        _ => return Err(SnapshotError::NotASnapshot),
+    };
+    if header.version != FORMAT_VERSION {
+        return Err(SnapshotError::Version(header.version));
+    }
+    Ok(postcard::from_bytes(rest)?)
+}
+
+/// Writes a snapshot of `value` to the file at `path`.
+#[cfg(feature = "std")]
+pub fn save<T: Serialize + ?Sized>(path: impl AsRef<Path>, value: &T) -> Result<(), SnapshotError> {
+    Ok(fs::write(path, to_bytes(value)?)?)
+}
+
+/// Reads the snapshot in the file at `path`.
+#[cfg(feature = "std")]
+pub fn load<T: DeserializeOwned>(path: impl AsRef<Path>) -> Result<T, SnapshotError> {
+    from_bytes(&fs::read(path)?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{call_graph::CallGraph, functions::functions, program::Program};
+
+    #[test]
+    fn versioned() {
+        // push {r7, lr}; bl 0x1008; pop {r7, pc}; cbz r0, which ARMv6-M lacks; bx lr
+        let image = [
+            0x80, 0xb5, 0x00, 0xf0, 0x02, 0xf8, 0x80, 0xbd, 0x00, 0xb1, 0x70, 0x47,
+        ];
+        let program = Program::new(&image, 0x1000);
+        let graph = CallGraph::new(&program, &functions(&program));
+        let mut bytes = to_bytes(&(&program, &graph)).unwrap();
+        assert_eq!(
+            from_bytes::<(Program, CallGraph)>(&bytes).unwrap(),
+            (program, graph)
+        );
+
+        assert!(matches!(
+            from_bytes::<(Program, CallGraph)>(&bytes[..bytes.len() - 1]),
+            Err(SnapshotError::Postcard(_))
+        ));
+        bytes[4] = FORMAT_VERSION as u8 + 1;
+        assert!(matches!(
+            from_bytes::<Program>(&bytes),
+            Err(SnapshotError::Version(2))
+        ));
+        assert!(matches!(
+            from_bytes::<Program>(b"{}"),
+            Err(SnapshotError::NotASnapshot)
+        ));
+    }
+}
@@ -0,0 +1,275 @@
+//! Static stack usage of the functions of an image, like `-fstack-usage` of GCC but derived
+//! from the code.
+//!
+//! # Example
+//! ```
+//! # use armv6_m_instruction_parser::{call_graph::CallGraph, control_flow::ControlFlowGraph, functions::functions, program::Program, stack::stack_usage};
+//! // 0x00: push {r4, lr}; bl 0x08; pop {r4, pc}
+//! // 0x08: push {r7, lr}; sub sp, #8; add sp, #8; pop {r7, pc}
+//! let code = [
+//!     0x10, 0xb5, 0x00, 0xf0, 0x01, 0xf8, 0x10, 0xbd, 0x80, 0xb5, 0x82, 0xb0, 0x02, 0xb0,
+//!     0x80, 0xbd,
+//! ];
+//! let program = Program::new(&code, 0);
+//! let cfg = ControlFlowGraph::new(&program, &[]);
+//! let graph = CallGraph::new(&program, &functions(&program));
+//! let usage = stack_usage(&program, &cfg, &graph);
+//! assert_eq!((usage[0].local, usage[0].total), (8, 24));
+//! assert_eq!((usage[1].local, usage[1].total), (16, 16));
+//! assert!(usage[0].bounded);
+//! ```
+
+use alloc::{collections::BTreeMap, format, string::String, vec, vec::Vec};
+use core::fmt::Write;
+
+use crate::{
+    call_graph::{CallGraph, CallTarget},
+    control_flow::ControlFlowGraph,
+    functions::FunctionRange,
+    instructons::SpDelta,
+    program::Program,
+};
+
+/// Stack usage of a function found by [`stack_usage`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StackUsage {
+    /// Start of the function.
+    pub function: u32,
+    /// Most bytes pushed or allocated by the function itself.
+    pub local: u32,
+    /// `sp` is set from a register or changes by different amounts on paths meeting in the
+    /// function, so `local` is only the usage up to there.
+    pub dynamic: bool,
+    /// Most bytes used by the function and the functions it calls directly or through them.
+    pub total: u32,
+    /// The function calls itself directly or through other functions. The total counts the
+    /// functions of the cycle once.
+    pub recursive: bool,
+    /// The total is an upper bound, as no function in it is dynamic, recursive or makes
+    /// indirect calls.
+    pub bounded: bool,
+}
+
+/// Usage of a function without its calls.
+struct Local {
+    max: u32,
+    dynamic: bool,
+    indirect: bool,
+    /// Bytes used at each direct call and the called address.
+    calls: Vec<(u32, u32)>,
+}
+
+/// Returns the stack usage of the functions of `graph` in address order.
+///
+/// The usage within a function follows the changes of `sp` along the blocks of `cfg` from the
+/// start of the function. Calls add the total of the called function to the usage at the
+/// call, calls to addresses that are not functions of the graph add nothing.
+pub fn stack_usage(
+    program: &Program,
+    cfg: &ControlFlowGraph,
+    graph: &CallGraph,
+) -> Vec<StackUsage> {
+    let functions = graph.functions();
+    let locals: Vec<Local> = functions
+        .iter()
+        .map(|function| local(program, cfg, graph, function))
+        .collect();
+    let mut search = Search {
+        functions,
+        locals: &locals,
+        states: vec![State::New; functions.len()],
+        stack: vec![],
+        recursive: vec![false; functions.len()],
+    };
+    for index in 0..functions.len() {
+        search.visit(index);
+    }
+    functions
+        .iter()
+        .zip(&locals)
+        .zip(&search.states)
+        .enumerate()
+        .map(|(index, ((function, local), state))| {
+            let State::Done { total, bounded } = *state else {
+                unreachable!("all functions are visited")
+            };
+            StackUsage {
+                function: function.start,
+                local: local.max,
+                dynamic: local.dynamic,
+                total,
+                recursive: search.recursive[index],
+                bounded,
+            }
+        })
+        .collect()
+}
+
+/// Returns the usage in the format of `-fstack-usage`, a line per function with its address,
+/// local usage and `static` or `dynamic`, followed by the total, separated by tabs. Totals
+/// that are not bounded end with `+`.
+///
+/// ```text
+/// 0x00000000    8    static    24
+/// ```
+pub fn report(usages: &[StackUsage]) -> String {
+    let mut report = String::new();
+    for usage in usages {
+        let qualifier = if usage.dynamic { "dynamic" } else { "static" };
+        let total = match usage.bounded {
+            true => format!("{}", usage.total),
+            false => format!("{}+", usage.total),
+        };
+        let _ = writeln!(
+            report,
+            "{:#010x}\t{}\t{qualifier}\t{total}",
+            usage.function, usage.local
+        );
+    }
+    report
+}
+
+fn local(
+    program: &Program,
+    cfg: &ControlFlowGraph,
+    graph: &CallGraph,
+    function: &FunctionRange,
+) -> Local {
+    let mut usage = Local {
+        max: 0,
+        dynamic: false,
+        indirect: false,
+        calls: vec![],
+    };
+    // Bytes used at the entry of the blocks reached.
+    let mut entries = BTreeMap::from([(function.start, 0)]);
+    let mut depths = BTreeMap::new();
+    let mut work = vec![(function.start, 0i32)];
+    while let Some((entry, mut depth)) = work.pop() {
+        let Some(block) = cfg.block_at(entry).map(|index| &cfg.blocks()[index]) else {
+            continue;
+        };
+        for (address, result) in program.range(entry..block.end) {
+            let Ok(instruction) = result else {
+                break;
+            };
+            if instruction.operation.is_call() {
+                depths.insert(address, depth);
+            }
+            match instruction.operation.sp_delta() {
+                SpDelta::Known(delta) => depth -= delta,
+                SpDelta::Unknown => usage.dynamic = true,
+            }
+            usage.max = usage.max.max(depth.max(0) as u32);
+        }
+        for &successor in cfg.successors(block.start) {
+            if !(function.start..function.end).contains(&successor) {
+                continue;
+            }
+            match entries.get(&successor) {
+                Some(&known) => usage.dynamic |= known != depth,
+                None => {
+                    entries.insert(successor, depth);
+                    work.push((successor, depth));
+                }
+            }
+        }
+    }
+
+    for call in graph.calls() {
+        let Some(&depth) = depths.get(&call.site) else {
+            continue;
+        };
+        match call.target {
+            CallTarget::Direct(target) => usage.calls.push((depth.max(0) as u32, target)),
+            CallTarget::Indirect(_) => usage.indirect = true,
+        }
+    }
+    usage
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    New,
+    Active,
+    Done { total: u32, bounded: bool },
+}
+
+/// Depth first search of the call graph computing the totals.
+struct Search<'a> {
+    functions: &'a [FunctionRange],
+    locals: &'a [Local],
+    states: Vec<State>,
+    /// Functions being visited, callers first.
+    stack: Vec<usize>,
+    recursive: Vec<bool>,
+}
+
+impl Search<'_> {
+    fn visit(&mut self, index: usize) {
+        if self.states[index] != State::New {
+            return;
+        }
+        self.states[index] = State::Active;
+        self.stack.push(index);
+        let local = &self.locals[index];
+        let mut total = local.max;
+        let mut bounded = !local.dynamic && !local.indirect;
+        for &(depth, target) in &local.calls {
+            let Ok(callee) = self
+                .functions
+                .binary_search_by_key(&target, |function| function.start)
+            else {
+                continue;
+            };
+            self.visit(callee);
+            match self.states[callee] {
+                State::Done {
+                    total: callee_total,
+                    bounded: callee_bounded,
+                } => {
+                    total = total.max(depth + callee_total);
+                    bounded &= callee_bounded;
+                }
+                State::Active => {
+                    let cycle = self.stack.iter().rposition(|&f| f == callee).unwrap_or(0);
+                    for &function in &self.stack[cycle..] {
+                        self.recursive[function] = true;
+                    }
+                }
+                State::New => unreachable!("visited above"),
+            }
+        }
+        bounded &= !self.recursive[index];
+        self.stack.pop();
+        self.states[index] = State::Done { total, bounded };
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::functions::functions;
+
+    #[test]
+    fn call_chains() {
+        // 0x00: push {r4, lr}; cmp r0, #0; beq.n 0x0a; bl 0x00; pop {r4, pc}
+        // 0x0c: push {r7, lr}; mov sp, r7; blx r3; pop {r7, pc}
+        let code = [
+            0x10, 0xb5, 0x00, 0x28, 0x01, 0xd0, 0xff, 0xf7, 0xfb, 0xff, 0x10, 0xbd, //
+            0x80, 0xb5, 0xbd, 0x46, 0x98, 0x47, 0x80, 0xbd,
+        ];
+        let program = Program::new(&code, 0);
+        let cfg = ControlFlowGraph::new(&program, &[]);
+        let graph = CallGraph::new(&program, &functions(&program));
+        let usage = stack_usage(&program, &cfg, &graph);
+        assert_eq!(usage.len(), 2);
+        assert!(usage[0].recursive && !usage[0].bounded && !usage[0].dynamic);
+        assert_eq!((usage[0].local, usage[0].total), (8, 8));
+        assert!(usage[1].dynamic && !usage[1].recursive && !usage[1].bounded);
+        assert_eq!(
+            report(&usage),
+            "0x00000000\t8\tstatic\t8+\n0x0000000c\t8\tdynamic\t8+\n"
+        );
+    }
+}
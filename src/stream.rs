@@ -0,0 +1,293 @@
+//! Push style decoding of instructions from input arriving in pieces, e.g. from ring buffers
+//! or debug links.
+//!
+//! # Example
+//! ```
+//! # use armv6_m_instruction_parser::{instructons::Operation, stream::{Decoded, StreamDecoder}};
+//! let mut decoder = StreamDecoder::new();
+//! // The first halfword of a bl.
+//! decoder.feed(&[0x00, 0xf0]);
+//! assert_eq!(decoder.decode(), Decoded::NeedMoreBytes(2));
+//! decoder.feed(&[0x7e, 0xf8]);
+//! let Decoded::Instruction { offset, result } = decoder.decode() else {
+//!     panic!()
+//! };
+//! assert_eq!(offset, 0);
+//! assert_eq!(result.unwrap().operation, Operation::BL { imm: 0xfc });
+//! ```
+
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::io::{self, Read};
+
+use crate::{instruction_size, instructons::Instruction, parse, Error};
+
+/// Result of [`StreamDecoder::decode`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Decoded {
+    /// An instruction, or the error from parsing it, at `offset` bytes from the start of the
+    /// stream.
+    Instruction {
+        offset: u64,
+        result: Result<Instruction, Error>,
+    },
+    /// The next instruction needs this many more bytes.
+    NeedMoreBytes(usize),
+}
+
+/// Decoder buffering input until whole instructions are available.
+///
+/// Invalid instructions are skipped by their width, known from the first halfword, so the
+/// decoder stays in sync with the instruction boundaries.
+#[derive(Debug, Clone, Default)]
+pub struct StreamDecoder {
+    buffer: Vec<u8>,
+    /// Start of the bytes in `buffer` not yet decoded.
+    start: usize,
+    /// Offset in the stream of the first byte in `buffer`.
+    offset: u64,
+}
+
+impl StreamDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds bytes to the end of the input.
+    pub fn feed(&mut self, bytes: &[u8]) {
+        if self.start > 0 {
+            self.buffer.drain(..self.start);
+            self.offset += self.start as u64;
+            self.start = 0;
+        }
+        self.buffer.extend_from_slice(bytes);
+    }
+
+    /// Decodes the next instruction, or returns how many more bytes are needed for it.
+    pub fn decode(&mut self) -> Decoded {
+        let input = &self.buffer[self.start..];
+        let size = match input {
+            [] => return Decoded::NeedMoreBytes(2),
+            [_] => return Decoded::NeedMoreBytes(1),
+            [first, second, ..] => instruction_size(u16::from_le_bytes([*first, *second])),
+        };
+        if input.len() < size {
+            return Decoded::NeedMoreBytes(size - input.len());
+        }
+        let decoded = Decoded::Instruction {
+            offset: self.offset(),
+            result: parse(&input[..size]),
+        };
+        self.start += size;
+        decoded
+    }
+
+    /// Offset in the stream of the next instruction.
+    pub fn offset(&self) -> u64 {
+        self.offset + self.start as u64
+    }
+
+    /// Bytes fed but not yet decoded.
+    pub fn pending(&self) -> &[u8] {
+        &self.buffer[self.start..]
+    }
+}
+
+impl Iterator for StreamDecoder {
+    type Item = (u64, Result<Instruction, Error>);
+
+    /// Returns the next instruction, or `None` until more bytes are fed.
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.decode() {
+            Decoded::Instruction { offset, result } => Some((offset, result)),
+            Decoded::NeedMoreBytes(_) => None,
+        }
+    }
+}
+
+/// Error from [`InstructionReader`].
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub enum ReadError {
+    /// Reading from the underlying reader failed.
+    Io(io::Error),
+    /// The input ended in the middle of an instruction.
+    UnexpectedEof,
+    /// The instruction could not be parsed.
+    Parse(Error),
+}
+
+#[cfg(feature = "std")]
+impl core::fmt::Display for ReadError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            ReadError::Io(error) => write!(f, "reading failed: {error}"),
+            ReadError::UnexpectedEof => f.write_str("input ended in the middle of an instruction"),
+            ReadError::Parse(error) => error.fmt(f),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ReadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ReadError::Io(error) => Some(error),
+            ReadError::UnexpectedEof => None,
+            ReadError::Parse(error) => Some(error),
+        }
+    }
+}
+
+/// Iterator decoding instructions read from a [`Read`], yielding the offset of every
+/// instruction together with the result of parsing it.
+///
+/// The iteration ends at the end of the input, or after a [`ReadError::Io`] or
+/// [`ReadError::UnexpectedEof`]. Wrap unbuffered readers in a [`std::io::BufReader`], as every
+/// halfword is a separate read.
+///
+/// # Example
+/// ```
+/// # use armv6_m_instruction_parser::{instructons::Operation, stream::InstructionReader};
+/// let input: &[u8] = &[0x00, 0xbf, 0x70, 0x47];
+/// let operations: Vec<Operation> = InstructionReader::new(input)
+///     .map(|(_, result)| result.unwrap().operation)
+///     .collect();
+/// assert_eq!(operations.len(), 2);
+/// ```
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub struct InstructionReader<R> {
+    reader: R,
+    offset: u64,
+    done: bool,
+}
+
+#[cfg(feature = "std")]
+impl<R: Read> InstructionReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            offset: 0,
+            done: false,
+        }
+    }
+
+    /// Returns the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    /// Reads until `buffer` is full or the input ends, returning the bytes read.
+    fn read_full(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        let mut read = 0;
+        while read < buffer.len() {
+            match self.reader.read(&mut buffer[read..]) {
+                Ok(0) => break,
+                Ok(n) => read += n,
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => {}
+                Err(error) => return Err(error),
+            }
+        }
+        Ok(read)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<R: Read> Iterator for InstructionReader<R> {
+    type Item = (u64, Result<Instruction, ReadError>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let offset = self.offset;
+        let mut bytes = [0; 4];
+        let result = self.read_full(&mut bytes[..2]).and_then(|read| {
+            if read < 2 {
+                return Ok(read);
+            }
+            let size = instruction_size(u16::from_le_bytes([bytes[0], bytes[1]]));
+            Ok(2 + self.read_full(&mut bytes[2..size])?)
+        });
+        let read = match result {
+            Ok(0) => {
+                self.done = true;
+                return None;
+            }
+            Ok(read) => read,
+            Err(error) => {
+                self.done = true;
+                return Some((offset, Err(ReadError::Io(error))));
+            }
+        };
+        if read < 2 || read < instruction_size(u16::from_le_bytes([bytes[0], bytes[1]])) {
+            self.done = true;
+            return Some((offset, Err(ReadError::UnexpectedEof)));
+        }
+        self.offset += read as u64;
+        Some((offset, parse(&bytes[..read]).map_err(ReadError::Parse)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{disassemble, instructons::Operation};
+
+    #[test]
+    fn byte_by_byte() {
+        // movs r0, #1; bl; bx lr
+        let input = [0x01, 0x20, 0x00, 0xf0, 0x7e, 0xf8, 0x70, 0x47];
+        let mut decoder = StreamDecoder::new();
+        let mut decoded = vec![];
+        for byte in input {
+            decoder.feed(&[byte]);
+            decoded.extend(&mut decoder);
+        }
+        assert!(decoder.pending().is_empty());
+        assert_eq!(decoder.offset(), 8);
+        let expected: Vec<_> = disassemble(&input)
+            .map(|(offset, result)| (offset as u64, result))
+            .collect();
+        assert_eq!(decoded, expected);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn reader() {
+        let input: &[u8] = &[0x01, 0x20, 0xff, 0xf7, 0x00, 0x00, 0x00, 0xf0, 0x7e];
+        let decoded: Vec<_> = InstructionReader::new(input).collect();
+        assert_eq!(decoded.len(), 3);
+        assert_eq!(decoded[0].0, 0);
+        assert!(decoded[0].1.is_ok());
+        assert!(matches!(decoded[1], (2, Err(ReadError::Parse(_)))));
+        assert!(matches!(decoded[2], (6, Err(ReadError::UnexpectedEof))));
+
+        let odd: &[u8] = &[0x01];
+        let decoded: Vec<_> = InstructionReader::new(odd).collect();
+        assert!(matches!(decoded[..], [(0, Err(ReadError::UnexpectedEof))]));
+    }
+
+    #[test]
+    fn need_more_bytes() {
+        let mut decoder = StreamDecoder::new();
+        assert_eq!(decoder.decode(), Decoded::NeedMoreBytes(2));
+        decoder.feed(&[0x00]);
+        assert_eq!(decoder.decode(), Decoded::NeedMoreBytes(1));
+        decoder.feed(&[0xf0, 0x7e]);
+        assert_eq!(decoder.decode(), Decoded::NeedMoreBytes(1));
+        decoder.feed(&[0xf8, 0x00, 0xbf]);
+        assert_eq!(
+            decoder.next().map(|(_, result)| result.unwrap().operation),
+            Some(Operation::BL { imm: 0xfc })
+        );
+        assert_eq!(
+            decoder.decode(),
+            Decoded::Instruction {
+                offset: 4,
+                result: parse(&[0x00, 0xbf])
+            }
+        );
+    }
+}
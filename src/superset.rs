@@ -0,0 +1,229 @@
+//! Superset disassembly, decoding at every halfword of an image to find the likely code when
+//! the entry points or the alignment of the instructions are not known, like in corrupted or
+//! partially dumped images.
+//!
+//! # Example
+//! ```
+//! # use armv6_m_instruction_parser::superset::Superset;
+//! // 0x00: .word 0xffffffff
+//! // 0x04: push {r4, lr}; movs r0, #1; pop {r4, pc}
+//! let image = [0xff, 0xff, 0xff, 0xff, 0x10, 0xb5, 0x01, 0x20, 0x10, 0xbd];
+//! let superset = Superset::new(&image, 0);
+//! assert_eq!(superset.confidence(0x00), 0);
+//! assert_eq!(superset.code_regions(8), [0x04..0x0a]);
+//! ```
+
+use alloc::{vec, vec::Vec};
+use core::ops::Range;
+
+use crate::{
+    functions::ends_flow,
+    instructons::{BranchTarget, Instruction, Operation},
+    parse,
+    registers::Register,
+};
+
+/// Longest run of instructions counted towards the confidence.
+const MAX_RUN: u32 = 16;
+
+/// Confidence added for every call to an instruction.
+const CALL_WEIGHT: u32 = 8;
+
+/// Confidence added for every branch to an instruction.
+const BRANCH_WEIGHT: u32 = 4;
+
+/// Confidence added for a `push {..., lr}`, the usual start of a function.
+const PROLOGUE_WEIGHT: u32 = 8;
+
+/// Instructions decoded at every halfword of an image, with how plausible it is that each
+/// of them is code.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Superset {
+    base: u32,
+    /// Instruction decoded at every halfword, `None` if it failed to decode or is `udf`.
+    decoded: Vec<Option<Instruction>>,
+    /// Confidence of every halfword, 0 if its instruction is not plausible.
+    confidence: Vec<u32>,
+}
+
+impl Superset {
+    /// Decodes `image` placed at `base` at every halfword and scores the instructions.
+    ///
+    /// An instruction is plausible if it decodes, its direct branches target plausible
+    /// instructions in the image, and the instructions it falls through to are plausible up
+    /// to the next unconditional branch or return. Calls may target code outside the image.
+    /// The confidence of a plausible instruction grows with the number of instructions it
+    /// falls through, calls and branches to it from plausible instructions, and with it
+    /// being a `push {..., lr}`.
+    pub fn new(image: &[u8], base: u32) -> Self {
+        let decoded: Vec<Option<Instruction>> = (0..image.len() / 2)
+            .map(|index| {
+                parse(&image[index * 2..])
+                    .ok()
+                    .filter(|instruction| !matches!(instruction.operation, Operation::UDF { .. }))
+            })
+            .collect();
+        let mut superset = Self {
+            base,
+            confidence: vec![0; decoded.len()],
+            decoded,
+        };
+
+        // Plausibility only ever goes from true to false, so this terminates.
+        let mut plausible: Vec<bool> = superset.decoded.iter().map(Option::is_some).collect();
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for index in (0..plausible.len()).rev() {
+                if plausible[index] && !superset.is_plausible(index, &plausible) {
+                    plausible[index] = false;
+                    changed = true;
+                }
+            }
+        }
+
+        // Runs are counted backwards, from the instructions falling through to.
+        let mut runs = vec![0; plausible.len()];
+        for index in (0..plausible.len()).rev() {
+            let Some(instruction) = superset.decoded[index]
+                .as_ref()
+                .filter(|_| plausible[index])
+            else {
+                continue;
+            };
+            runs[index] = 1 + match ends_flow(&instruction.operation) {
+                true => 0,
+                false => runs[index + instruction.size() as usize / 2],
+            };
+        }
+        for index in 0..plausible.len() {
+            if !plausible[index] {
+                continue;
+            }
+            let operation = &superset.decoded[index].as_ref().unwrap().operation;
+            superset.confidence[index] += runs[index].min(MAX_RUN);
+            if matches!(operation, Operation::PUSH { reg_list } if reg_list.contains(Register::LR))
+            {
+                superset.confidence[index] += PROLOGUE_WEIGHT;
+            }
+            let Some(target) = superset.target(index) else {
+                continue;
+            };
+            if plausible[target] {
+                superset.confidence[target] += match operation.is_call() {
+                    true => CALL_WEIGHT,
+                    false => BRANCH_WEIGHT,
+                };
+            }
+        }
+        superset
+    }
+
+    /// Returns the instruction decoded at `address`, whether plausible or not.
+    pub fn at(&self, address: u32) -> Option<&Instruction> {
+        self.decoded.get(self.index(address)?)?.as_ref()
+    }
+
+    /// Returns the confidence that `address` starts an instruction, 0 if it is not plausible.
+    pub fn confidence(&self, address: u32) -> u32 {
+        self.index(address)
+            .map_or(0, |index| self.confidence[index])
+    }
+
+    /// Returns the likely code of the image, in address order.
+    ///
+    /// A region starts at a plausible instruction with at least `min_confidence` and
+    /// continues through the instructions it falls through to while they are plausible.
+    pub fn code_regions(&self, min_confidence: u32) -> Vec<Range<u32>> {
+        let mut regions: Vec<Range<u32>> = vec![];
+        let mut index = 0;
+        let mut in_region = false;
+        while index < self.decoded.len() {
+            let confidence = self.confidence[index];
+            let address = self.base.wrapping_add(index as u32 * 2);
+            if confidence == 0 || (!in_region && confidence < min_confidence) {
+                in_region = false;
+                index += 1;
+                continue;
+            }
+            let size = self.decoded[index].as_ref().map_or(2, Instruction::size);
+            match regions.last_mut() {
+                Some(region) if in_region => region.end = address.wrapping_add(size),
+                _ => regions.push(address..address.wrapping_add(size)),
+            }
+            in_region = true;
+            index += size as usize / 2;
+        }
+        regions
+    }
+
+    /// Returns the halfword index of `address`.
+    fn index(&self, address: u32) -> Option<usize> {
+        let offset = address.checked_sub(self.base)?;
+        let index = (offset / 2) as usize;
+        (offset % 2 == 0 && index < self.decoded.len()).then_some(index)
+    }
+
+    /// Returns the halfword index of the direct branch target of the instruction at `index`,
+    /// if in the image.
+    fn target(&self, index: usize) -> Option<usize> {
+        let operation = &self.decoded[index].as_ref()?.operation;
+        let address = self.base.wrapping_add(index as u32 * 2);
+        match (operation, operation.branch_target(address)) {
+            (Operation::B { .. } | Operation::BL { .. }, BranchTarget::Direct(target)) => {
+                self.index(target)
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns `true` if the instruction at `index` is plausible given `plausible`.
+    fn is_plausible(&self, index: usize, plausible: &[bool]) -> bool {
+        let Some(instruction) = &self.decoded[index] else {
+            return false;
+        };
+        let address = self.base.wrapping_add(index as u32 * 2);
+        let falls_through = !ends_flow(&instruction.operation);
+        let next = index + instruction.size() as usize / 2;
+        if falls_through && !plausible.get(next).copied().unwrap_or(false) {
+            return false;
+        }
+        match (
+            &instruction.operation,
+            instruction.operation.branch_target(address),
+        ) {
+            (Operation::B { .. } | Operation::BL { .. }, BranchTarget::Direct(_)) => {
+                match self.target(index) {
+                    Some(target) => plausible[target],
+                    None => instruction.operation.is_call(),
+                }
+            }
+            _ => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn plausible_code() {
+        // 0x00: push {r4, lr}; bl 0x10; pop {r4, pc}
+        // 0x08: .word 0; .word 0xffffffff
+        // 0x10: cmp r0, #0; beq.n 0x16; movs r0, #1
+        // 0x16: bx lr
+        let image = [
+            0x10, 0xb5, 0x00, 0xf0, 0x05, 0xf8, 0x10, 0xbd, //
+            0x00, 0x00, 0x00, 0x00, 0xff, 0xff, 0xff, 0xff, //
+            0x00, 0x28, 0x00, 0xd0, 0x01, 0x20, 0x70, 0x47,
+        ];
+        let superset = Superset::new(&image, 0x100);
+        // The zeros decode, but fall through into the undefined word.
+        assert!(superset.at(0x108).is_some());
+        assert_eq!(superset.confidence(0x108), 0);
+        assert_eq!(superset.confidence(0x110), 4 + CALL_WEIGHT);
+        assert_eq!(superset.confidence(0x116), 1 + BRANCH_WEIGHT);
+        assert_eq!(superset.code_regions(8), [0x100..0x108, 0x110..0x118]);
+    }
+}
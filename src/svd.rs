@@ -0,0 +1,526 @@
+//! Peripherals of a device from its CMSIS-SVD file, naming the registers loads and stores
+//! access, like `str r1, [r0] ; GPIOA->ODR`.
+//!
+//! # Example
+//! ```
+//! # use armv6_m_instruction_parser::{control_flow::ControlFlowGraph, functions::FunctionRange, program::Program, propagation::propagate, svd::{peripheral_accesses, Device}};
+//! let svd = "<device><name>STM32F0</name><peripherals><peripheral>
+//!     <name>GPIOA</name><baseAddress>0x48000000</baseAddress>
+//!     <registers><register><name>ODR</name><addressOffset>0x14</addressOffset></register></registers>
+//! </peripheral></peripherals></device>";
+//! let device = Device::parse(svd).unwrap();
+//! // ldr r0, [pc, #4]; str r1, [r0, #0x14]; bx lr; .word 0x48000000
+//! let image = [0x01, 0x48, 0x41, 0x61, 0x70, 0x47, 0x00, 0x00, 0x00, 0x00, 0x00, 0x48];
+//! let program = Program::new(&image, 0);
+//! let cfg = ControlFlowGraph::new(&program, &[]);
+//! let function = FunctionRange { start: 0, end: 6, called: false, saves_lr: false };
+//! let propagation = propagate(&program, &image, &cfg, &function);
+//! let accesses = peripheral_accesses(&device, &program, &propagation, &function);
+//! assert_eq!(accesses[0].address, 0x02);
+//! assert_eq!(accesses[0].to_string(), "GPIOA->ODR");
+//! ```
+
+use alloc::{
+    collections::BTreeMap,
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+use core::fmt::{self, Display};
+
+use roxmltree::{Document, Node};
+
+use crate::{
+    functions::FunctionRange, instructons::AccessDirection, program::Program,
+    propagation::Propagation,
+};
+
+/// Error from parsing an SVD file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SvdError {
+    /// The file is not valid XML.
+    Xml(roxmltree::Error),
+    /// An element is missing or invalid, with the name of the element.
+    Invalid(String),
+}
+
+impl Display for SvdError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SvdError::Xml(error) => write!(f, "invalid XML: {error}"),
+            SvdError::Invalid(element) => write!(f, "missing or invalid <{element}>"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for SvdError {}
+
+/// Bit field of a [`PeripheralRegister`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Field {
+    pub name: String,
+    /// Lowest bit of the field.
+    pub offset: u32,
+    /// Number of bits.
+    pub width: u32,
+}
+
+/// Memory mapped register of a [`Peripheral`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PeripheralRegister {
+    /// Name, with the cluster names before it separated by `.`.
+    pub name: String,
+    pub address: u32,
+    /// Size in bytes.
+    pub size: u32,
+    pub fields: Vec<Field>,
+}
+
+impl PeripheralRegister {
+    /// Returns whether `address` is in the register.
+    pub fn contains(&self, address: u32) -> bool {
+        address.wrapping_sub(self.address) < self.size
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Peripheral {
+    pub name: String,
+    pub base: u32,
+    /// Registers in the order of the SVD file, with clusters and arrays expanded.
+    pub registers: Vec<PeripheralRegister>,
+}
+
+/// Device described by an SVD file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Device {
+    pub name: String,
+    pub peripherals: Vec<Peripheral>,
+}
+
+impl Device {
+    /// Parses the SVD file `svd`.
+    ///
+    /// Peripherals derived from another take its registers, and clusters and `dim` arrays of
+    /// registers are expanded with `%s` replaced by the index.
+    pub fn parse(svd: &str) -> Result<Self, SvdError> {
+        let document = Document::parse(svd).map_err(SvdError::Xml)?;
+        let device = document.root_element();
+        let size = match text(device, "size") {
+            Some(size) => number(size, "size")? as u32,
+            None => 32,
+        };
+        let mut peripherals: Vec<Peripheral> = Vec::new();
+        let mut by_name: BTreeMap<&str, Node> = BTreeMap::new();
+        let nodes = child(device, "peripherals")
+            .into_iter()
+            .flat_map(|peripherals| elements(peripherals, "peripheral"));
+        for node in nodes {
+            let name = text(node, "name").ok_or_else(|| invalid("name"))?;
+            by_name.insert(name, node);
+            let base = number(
+                text(node, "baseAddress").ok_or_else(|| invalid("baseAddress"))?,
+                "baseAddress",
+            )? as u32;
+            let size = match text(node, "size") {
+                Some(size) => number(size, "size")? as u32,
+                None => size,
+            };
+            let registers = match child(node, "registers") {
+                Some(registers) => Some(registers),
+                None => node
+                    .attribute("derivedFrom")
+                    .and_then(|from| by_name.get(from))
+                    .and_then(|from| child(*from, "registers")),
+            };
+            let mut peripheral = Peripheral {
+                name: name.to_string(),
+                base,
+                registers: vec![],
+            };
+            if let Some(registers) = registers {
+                parse_registers(registers, base, "", size, &mut peripheral.registers)?;
+            }
+            peripherals.push(peripheral);
+        }
+        Ok(Device {
+            name: text(device, "name").unwrap_or_default().to_string(),
+            peripherals,
+        })
+    }
+
+    /// Returns the register at `address` with its peripheral.
+    pub fn register(&self, address: u32) -> Option<(&Peripheral, &PeripheralRegister)> {
+        self.peripherals.iter().find_map(|peripheral| {
+            let register = peripheral
+                .registers
+                .iter()
+                .find(|register| register.contains(address))?;
+            Some((peripheral, register))
+        })
+    }
+}
+
+/// Appends the registers and clusters in `node` at `base` to `registers`.
+fn parse_registers(
+    node: Node,
+    base: u32,
+    prefix: &str,
+    size: u32,
+    registers: &mut Vec<PeripheralRegister>,
+) -> Result<(), SvdError> {
+    for node in node.children().filter(Node::is_element) {
+        let tag = node.tag_name().name();
+        if tag != "register" && tag != "cluster" {
+            continue;
+        }
+        let name = text(node, "name").ok_or_else(|| invalid("name"))?;
+        let offset = number(
+            text(node, "addressOffset").ok_or_else(|| invalid("addressOffset"))?,
+            "addressOffset",
+        )? as u32;
+        let size = match text(node, "size") {
+            Some(size) => number(size, "size")? as u32,
+            None => size,
+        };
+        for (index, increment) in dimensions(node)? {
+            let name = format!("{prefix}{}", name.replace("%s", &index));
+            let address = base.wrapping_add(offset).wrapping_add(increment);
+            if tag == "cluster" {
+                parse_registers(node, address, &format!("{name}."), size, registers)?;
+                continue;
+            }
+            let mut fields = Vec::new();
+            let nodes = child(node, "fields")
+                .into_iter()
+                .flat_map(|fields| elements(fields, "field"));
+            for field in nodes {
+                fields.push(parse_field(field)?);
+            }
+            registers.push(PeripheralRegister {
+                name,
+                address,
+                size: size / 8,
+                fields,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Returns the index names and address increments of the elements of a `dim` array, a
+/// single element without an index for elements not an array.
+fn dimensions(node: Node) -> Result<Vec<(String, u32)>, SvdError> {
+    let Some(dim) = text(node, "dim") else {
+        return Ok(vec![(String::new(), 0)]);
+    };
+    let count = number(dim, "dim")? as u32;
+    let increment = number(
+        text(node, "dimIncrement").ok_or_else(|| invalid("dimIncrement"))?,
+        "dimIncrement",
+    )? as u32;
+    let indices: Vec<String> = match text(node, "dimIndex") {
+        Some(indices) => match indices.split_once('-') {
+            Some((first, last)) => match (first.parse::<u32>(), last.parse::<u32>()) {
+                (Ok(first), Ok(last)) => (first..=last).map(|index| format!("{index}")).collect(),
+                _ => (first.bytes().next().unwrap_or(b'A')..=last.bytes().next().unwrap_or(b'A'))
+                    .map(|index| (index as char).to_string())
+                    .collect(),
+            },
+            None => indices
+                .split(',')
+                .map(|index| index.trim().to_string())
+                .collect(),
+        },
+        None => (0..count).map(|index| format!("{index}")).collect(),
+    };
+    Ok(indices
+        .into_iter()
+        .take(count as usize)
+        .zip(0..)
+        .map(|(index, element)| (index, element * increment))
+        .collect())
+}
+
+fn parse_field(node: Node) -> Result<Field, SvdError> {
+    let name = text(node, "name")
+        .ok_or_else(|| invalid("name"))?
+        .to_string();
+    let bits = |name: &'static str| text(node, name).map(|value| number(value, name));
+    let (offset, width) = if let Some(range) = text(node, "bitRange") {
+        let (msb, lsb) = range
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .split_once(':')
+            .ok_or_else(|| invalid("bitRange"))?;
+        let lsb = lsb.parse::<u32>().map_err(|_| invalid("bitRange"))?;
+        let msb = msb.parse::<u32>().map_err(|_| invalid("bitRange"))?;
+        (lsb, msb.saturating_sub(lsb) + 1)
+    } else if let (Some(lsb), Some(msb)) = (bits("lsb"), bits("msb")) {
+        let (lsb, msb) = (lsb? as u32, msb? as u32);
+        (lsb, msb.saturating_sub(lsb) + 1)
+    } else {
+        let offset = bits("bitOffset").ok_or_else(|| invalid("bitOffset"))??;
+        let width = bits("bitWidth").unwrap_or(Ok(1))?;
+        (offset as u32, width as u32)
+    };
+    Ok(Field {
+        name,
+        offset,
+        width,
+    })
+}
+
+fn elements<'a, 'input>(
+    node: Node<'a, 'input>,
+    name: &'static str,
+) -> impl Iterator<Item = Node<'a, 'input>> {
+    node.children()
+        .filter(move |child| child.tag_name().name() == name)
+}
+
+fn child<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Option<Node<'a, 'input>> {
+    node.children()
+        .find(|child| child.tag_name().name() == name)
+}
+
+fn text<'a>(node: Node<'a, '_>, name: &str) -> Option<&'a str> {
+    Some(child(node, name)?.text()?.trim())
+}
+
+/// Parses an SVD number, decimal, hexadecimal with `0x` or binary with `#`.
+fn number(text: &str, element: &'static str) -> Result<u64, SvdError> {
+    let result = if let Some(hex) = text.strip_prefix("0x").or(text.strip_prefix("0X")) {
+        u64::from_str_radix(hex, 16)
+    } else if let Some(binary) = text.strip_prefix('#') {
+        u64::from_str_radix(binary, 2)
+    } else {
+        text.parse()
+    };
+    result.map_err(|_| invalid(element))
+}
+
+fn invalid(element: &'static str) -> SvdError {
+    SvdError::Invalid(element.to_string())
+}
+
+/// Load or store of a peripheral register, from [`peripheral_accesses`].
+///
+/// Displays as `GPIOA->ODR`, with the fields accessed for accesses to part of the register
+/// like `GPIOA->ODR.ODR8`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PeripheralAccess<'a> {
+    /// Address of the instruction.
+    pub address: u32,
+    pub direction: AccessDirection,
+    pub peripheral: &'a Peripheral,
+    pub register: &'a PeripheralRegister,
+    /// Fields accessed, empty for accesses to the whole register.
+    pub fields: Vec<&'a Field>,
+}
+
+impl Display for PeripheralAccess<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}->{}", self.peripheral.name, self.register.name)?;
+        match self.fields.as_slice() {
+            [] => Ok(()),
+            [field] => write!(f, ".{}", field.name),
+            fields => {
+                let names: Vec<&str> = fields.iter().map(|field| field.name.as_str()).collect();
+                write!(f, ".{{{}}}", names.join(", "))
+            }
+        }
+    }
+}
+
+/// Returns the loads and stores of `function` whose address is known from `propagation` and
+/// is in a register of `device`, in address order.
+pub fn peripheral_accesses<'a>(
+    device: &'a Device,
+    program: &Program,
+    propagation: &Propagation,
+    function: &FunctionRange,
+) -> Vec<PeripheralAccess<'a>> {
+    let mut accesses = Vec::new();
+    for (address, result) in program.range(function.start..function.end) {
+        let Ok(instruction) = result else {
+            continue;
+        };
+        let Some(access) = instruction.operation.memory_access() else {
+            continue;
+        };
+        let Some(target) = propagation.memory_address(program, address) else {
+            continue;
+        };
+        let Some((peripheral, register)) = device.register(target) else {
+            continue;
+        };
+        let bytes = access.size.bytes();
+        let fields = match bytes < register.size {
+            true => {
+                let low = (target - register.address) * 8;
+                let high = low + bytes * 8;
+                register
+                    .fields
+                    .iter()
+                    .filter(|field| field.offset < high && field.offset + field.width > low)
+                    .collect()
+            }
+            false => vec![],
+        };
+        accesses.push(PeripheralAccess {
+            address,
+            direction: access.direction,
+            peripheral,
+            register,
+            fields,
+        });
+    }
+    accesses
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let svd = r#"<?xml version="1.0" encoding="utf-8"?>
+<device schemaVersion="1.3">
+  <name>RP2040</name>
+  <size>32</size>
+  <peripherals>
+    <peripheral>
+      <name>DMA</name>
+      <baseAddress>0x50000000</baseAddress>
+      <registers>
+        <cluster>
+          <dim>2</dim>
+          <dimIncrement>0x40</dimIncrement>
+          <name>CH%s</name>
+          <addressOffset>0x0</addressOffset>
+          <register>
+            <name>READ_ADDR</name>
+            <addressOffset>0x0</addressOffset>
+          </register>
+        </cluster>
+        <register>
+          <dim>2</dim>
+          <dimIncrement>2</dimIncrement>
+          <dimIndex>A,B</dimIndex>
+          <name>CTRL_%s</name>
+          <addressOffset>0x400</addressOffset>
+          <size>16</size>
+          <fields>
+            <field><name>EN</name><bitOffset>0</bitOffset><bitWidth>1</bitWidth></field>
+            <field><name>MODE</name><bitRange>[15:8]</bitRange></field>
+          </fields>
+        </register>
+      </registers>
+    </peripheral>
+    <peripheral derivedFrom="DMA">
+      <name>DMA1</name>
+      <baseAddress>0x50100000</baseAddress>
+    </peripheral>
+  </peripherals>
+</device>"#;
+        let device = Device::parse(svd).unwrap();
+        assert_eq!(device.name, "RP2040");
+        let registers: Vec<(&str, u32, u32)> = device.peripherals[0]
+            .registers
+            .iter()
+            .map(|register| (register.name.as_str(), register.address, register.size))
+            .collect();
+        assert_eq!(
+            registers,
+            [
+                ("CH0.READ_ADDR", 0x5000_0000, 4),
+                ("CH1.READ_ADDR", 0x5000_0040, 4),
+                ("CTRL_A", 0x5000_0400, 2),
+                ("CTRL_B", 0x5000_0402, 2),
+            ]
+        );
+        assert_eq!(
+            device.peripherals[0].registers[3].fields[1],
+            Field {
+                name: "MODE".to_string(),
+                offset: 8,
+                width: 8,
+            }
+        );
+        let (peripheral, register) = device.register(0x5010_0043).unwrap();
+        assert_eq!(
+            (peripheral.name.as_str(), register.name.as_str()),
+            ("DMA1", "CH1.READ_ADDR")
+        );
+        assert_eq!(device.register(0x5000_0404), None);
+
+        let access = PeripheralAccess {
+            address: 0,
+            direction: AccessDirection::Store,
+            peripheral: &device.peripherals[0],
+            register: &device.peripherals[0].registers[2],
+            fields: device.peripherals[0].registers[2].fields.iter().collect(),
+        };
+        assert_eq!(access.to_string(), "DMA->CTRL_A.{EN, MODE}");
+        assert_eq!(
+            Device::parse("<device><peripherals><peripheral/></peripherals></device>"),
+            Err(SvdError::Invalid("name".to_string()))
+        );
+    }
+
+    #[test]
+    fn accesses() {
+        use crate::{control_flow::ControlFlowGraph, propagation::propagate};
+
+        let svd = "<device><name>STM32F0</name><peripherals><peripheral>
+            <name>GPIOA</name><baseAddress>0x48000000</baseAddress>
+            <registers><register><name>ODR</name><addressOffset>0x14</addressOffset><fields>
+                <field><name>ODR0</name><bitRange>[0:0]</bitRange></field>
+                <field><name>ODR8</name><lsb>8</lsb><msb>8</msb></field>
+                <field><name>MODE</name><bitOffset>9</bitOffset><bitWidth>8</bitWidth></field>
+            </fields></register></registers>
+        </peripheral></peripherals></device>";
+        let device = Device::parse(svd).unwrap();
+        // ldr r0, [pc, #8]; strb r1, [r0, #0x15]; ldrh r2, [r0, #0x14]; str r1, [r0, #0x14]
+        // ldr r3, [r0, #0x18]; bx lr; .word 0x48000000
+        let image = [
+            0x02, 0x48, 0x41, 0x75, 0x82, 0x8a, 0x41, 0x61, 0x83, 0x69, 0x70, 0x47, 0x00, 0x00,
+            0x00, 0x48,
+        ];
+        let program = Program::new(&image, 0);
+        let cfg = ControlFlowGraph::new(&program, &[]);
+        let function = FunctionRange {
+            start: 0,
+            end: 0x0c,
+            called: false,
+            saves_lr: false,
+        };
+        let propagation = propagate(&program, &image, &cfg, &function);
+        let accesses: Vec<(u32, AccessDirection, String)> =
+            peripheral_accesses(&device, &program, &propagation, &function)
+                .iter()
+                .map(|access| (access.address, access.direction, access.to_string()))
+                .collect();
+        // The byte store is to bits 8 to 15, the halfword load to bits 0 to 15, and the load
+        // past the register is not to a register.
+        assert_eq!(
+            accesses,
+            [
+                (
+                    0x02,
+                    AccessDirection::Store,
+                    "GPIOA->ODR.{ODR8, MODE}".to_string()
+                ),
+                (
+                    0x04,
+                    AccessDirection::Load,
+                    "GPIOA->ODR.{ODR0, ODR8, MODE}".to_string()
+                ),
+                (0x06, AccessDirection::Store, "GPIOA->ODR".to_string()),
+            ]
+        );
+    }
+}
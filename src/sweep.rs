@@ -0,0 +1,160 @@
+//! Linear sweep disassembly of sections mixing code and data, such as literal pools and jump
+//! tables between functions.
+//!
+//! # Example
+//! ```
+//! # use armv6_m_instruction_parser::sweep::{sweep, Item};
+//! // ldr r0, [pc, #0]; bx lr; .word 0xffff_ffff; .hword 0xffff
+//! let section = [0x00, 0x48, 0x70, 0x47, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff];
+//! let items: Vec<_> = sweep(&section).collect();
+//! assert_eq!(items.len(), 4);
+//! assert!(matches!(items[1], (2, Item::Instruction(_))));
+//! assert_eq!(items[2], (4, Item::Literal(0xffff_ffff)));
+//! assert_eq!(items[3], (8, Item::Data(0xffff)));
+//! ```
+
+use crate::{
+    instruction_size,
+    instructons::{Instruction, Operation},
+    parse,
+};
+
+/// Words tracked for literal loads, more than the 1 KiB reach of `ldr rX, [pc, #imm]`.
+const LITERAL_WINDOW: usize = 512;
+
+/// Decoded instruction or data in a [`Sweep`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Item {
+    Instruction(Instruction),
+    /// Word loaded by an earlier `ldr rX, [pc, #imm]` of the sweep.
+    Literal(u32),
+    /// Halfword that does not start a valid instruction.
+    Data(u16),
+    /// Odd byte at the end of the input.
+    Byte(u8),
+}
+
+/// Decodes a byte slice from start to end, see [`Sweep`].
+pub fn sweep(input: &[u8]) -> Sweep<'_> {
+    Sweep {
+        input,
+        offset: 0,
+        literals: [0; LITERAL_WINDOW / 64],
+    }
+}
+
+/// Iterator over the instructions and data of a byte slice, yielding the offset of every
+/// item.
+///
+/// Unlike [`crate::Disassembly`] a halfword that does not decode, or a 32 bit instruction
+/// truncated by the end of the input, is yielded as [`Item::Data`] and decoding continues at
+/// the next halfword, so the items cover every byte of the input. Data that happens to decode
+/// is yielded as instructions, and decoding can get out of step with the code for a few
+/// instructions after data.
+///
+/// Words loaded by the literal loads decoded before them are yielded as [`Item::Literal`]
+/// instead of being decoded, so literal pools after the code using them are not mistaken for
+/// instructions. The input is assumed to start on a word boundary.
+#[derive(Debug, Clone)]
+pub struct Sweep<'a> {
+    input: &'a [u8],
+    offset: usize,
+    /// Words ahead loaded as literals, bit `word % LITERAL_WINDOW`.
+    literals: [u64; LITERAL_WINDOW / 64],
+}
+
+impl Sweep<'_> {
+    fn literal_bit(&mut self, offset: usize) -> (&mut u64, u64) {
+        let word = offset / 4 % LITERAL_WINDOW;
+        (&mut self.literals[word / 64], 1 << (word % 64))
+    }
+
+    /// Takes the mark of the literal starting at `offset`.
+    fn take_literal(&mut self, offset: usize) -> bool {
+        let (bits, bit) = self.literal_bit(offset);
+        let marked = *bits & bit != 0;
+        *bits &= !bit;
+        marked
+    }
+}
+
+impl Iterator for Sweep<'_> {
+    type Item = (usize, Item);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let offset = self.offset;
+        let rest = &self.input[offset..];
+        if offset.is_multiple_of(4) && self.take_literal(offset) {
+            if let Some(word) = rest.get(..4) {
+                self.offset += 4;
+                let value = u32::from_le_bytes([word[0], word[1], word[2], word[3]]);
+                return Some((offset, Item::Literal(value)));
+            }
+        }
+        let item = match rest {
+            [] => return None,
+            [byte] => {
+                self.offset += 1;
+                Item::Byte(*byte)
+            }
+            [first, second, ..] => {
+                let halfword = u16::from_le_bytes([*first, *second]);
+                let size = instruction_size(halfword);
+                match rest.get(..size).map(parse) {
+                    Some(Ok(instruction)) => {
+                        self.offset += size;
+                        if let Operation::LDRLiteral { imm, .. } = instruction.operation {
+                            let literal = ((offset + 4) & !0b11) + imm as usize;
+                            let (bits, bit) = self.literal_bit(literal);
+                            *bits |= bit;
+                        }
+                        Item::Instruction(instruction)
+                    }
+                    _ => {
+                        self.offset += 2;
+                        Item::Data(halfword)
+                    }
+                }
+            }
+        };
+        Some((offset, item))
+    }
+}
+
+impl core::iter::FusedIterator for Sweep<'_> {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn resynchronization() {
+        // movs r0, #1; invalid 32 bit; udf; truncated bl; odd byte
+        let input = [
+            0x01, 0x20, 0xff, 0xf7, 0x00, 0x00, 0x00, 0xde, 0x00, 0xf0, 0x7e,
+        ];
+        let items: Vec<_> = sweep(&input).collect();
+        let offsets: Vec<_> = items.iter().map(|(offset, _)| *offset).collect();
+        assert_eq!(offsets, [0, 2, 4, 6, 8, 10]);
+        assert_eq!(items[1].1, Item::Data(0xf7ff));
+        // The second halfword of the invalid instruction decodes on its own.
+        assert!(matches!(items[2].1, Item::Instruction(_)));
+        assert_eq!(items[4].1, Item::Data(0xf000));
+        assert_eq!(items[5].1, Item::Byte(0x7e));
+    }
+
+    #[test]
+    fn literal_pools() {
+        // ldr r0, [pc, #4]; ldr r1, [pc, #8]; bx lr; nop; .word 0xf000f000, 0x4770; .word 0
+        let input = [
+            0x01, 0x48, 0x02, 0x49, 0x70, 0x47, 0x00, 0xbf, 0x00, 0xf0, 0x00, 0xf0, 0x70, 0x47,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        ];
+        let items: Vec<_> = sweep(&input).collect();
+        assert_eq!(items[4], (8, Item::Literal(0xf000_f000)));
+        assert_eq!(items[5], (12, Item::Literal(0x4770)));
+        // Words not loaded are still decoded.
+        assert!(matches!(items[6], (16, Item::Instruction(_))));
+        assert_eq!(items.len(), 8);
+    }
+}
@@ -0,0 +1,137 @@
+//! Symbol tables naming the addresses of an image, from ELF files or linker map files, for
+//! labeling output like the call graph.
+//!
+//! # Example
+//! ```
+//! # use armv6_m_instruction_parser::symbols::{Symbol, SymbolTable};
+//! let mut symbols = SymbolTable::new();
+//! symbols.insert(Symbol { name: "main".into(), address: 0x0800_00c0, size: 0x24 });
+//! assert_eq!(symbols.label(0x0800_00c0), "main");
+//! assert_eq!(symbols.label(0x0800_00c4), "main+0x4");
+//! assert_eq!(symbols.label(0x0800_00e4), "0x080000e4");
+//! ```
+
+use alloc::{collections::BTreeMap, format, string::String};
+
+/// Named address.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Symbol {
+    pub name: String,
+    /// Address of the symbol, without the Thumb bit for functions.
+    pub address: u32,
+    /// Size in bytes, 0 when unknown.
+    pub size: u32,
+}
+
+/// Symbols by address.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SymbolTable {
+    symbols: BTreeMap<u32, Symbol>,
+}
+
+impl SymbolTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `symbol`, returning false if there already is a symbol at its address, which is
+    /// kept.
+    pub fn insert(&mut self, symbol: Symbol) -> bool {
+        if self.symbols.contains_key(&symbol.address) {
+            return false;
+        }
+        self.symbols.insert(symbol.address, symbol);
+        true
+    }
+
+    pub fn len(&self) -> usize {
+        self.symbols.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.symbols.is_empty()
+    }
+
+    /// Returns the symbols in address order.
+    pub fn iter(&self) -> impl Iterator<Item = &Symbol> {
+        self.symbols.values()
+    }
+
+    /// Returns the symbol at `address`.
+    pub fn get(&self, address: u32) -> Option<&Symbol> {
+        self.symbols.get(&address)
+    }
+
+    /// Returns the symbol named `name`.
+    pub fn by_name(&self, name: &str) -> Option<&Symbol> {
+        self.symbols.values().find(|symbol| symbol.name == name)
+    }
+
+    /// Returns the symbol containing `address`, the closest one before it for symbols
+    /// without a size.
+    pub fn containing(&self, address: u32) -> Option<&Symbol> {
+        let (_, symbol) = self.symbols.range(..=address).next_back()?;
+        match symbol.size {
+            0 => Some(symbol),
+            size => (address - symbol.address < size).then_some(symbol),
+        }
+    }
+
+    /// Returns `address` as the name of the symbol containing it, with the offset into the
+    /// symbol if not 0, or in hexadecimal outside all symbols.
+    pub fn label(&self, address: u32) -> String {
+        match self.containing(address) {
+            Some(symbol) if symbol.address == address => symbol.name.clone(),
+            Some(symbol) => format!("{}+{:#x}", symbol.name, address - symbol.address),
+            None => format!("{address:#010x}"),
+        }
+    }
+}
+
+impl Extend<Symbol> for SymbolTable {
+    fn extend<I: IntoIterator<Item = Symbol>>(&mut self, symbols: I) {
+        for symbol in symbols {
+            self.insert(symbol);
+        }
+    }
+}
+
+impl FromIterator<Symbol> for SymbolTable {
+    fn from_iter<I: IntoIterator<Item = Symbol>>(symbols: I) -> Self {
+        let mut table = Self::new();
+        table.extend(symbols);
+        table
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use alloc::vec::Vec;
+
+    #[test]
+    fn lookup() {
+        let symbol = |name: &str, address, size| Symbol {
+            name: name.into(),
+            address,
+            size,
+        };
+        let symbols: SymbolTable = [
+            symbol("reset", 0x100, 0x10),
+            symbol("alias", 0x100, 0),
+            symbol("loop", 0x120, 0),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(symbols.len(), 2);
+        assert_eq!(symbols.label(0x10e), "reset+0xe");
+        assert_eq!(symbols.label(0x110), "0x00000110");
+        assert_eq!(symbols.label(0x200), "loop+0xe0");
+        assert_eq!(
+            symbols.by_name("loop").map(|symbol| symbol.address),
+            Some(0x120)
+        );
+        let names: Vec<&str> = symbols.iter().map(|symbol| symbol.name.as_str()).collect();
+        assert_eq!(names, ["reset", "loop"]);
+    }
+}
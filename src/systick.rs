@@ -0,0 +1,198 @@
+//! SysTick, the system timer of ARMv6-M, counting down the cycles of an emulated processor
+//! and raising the SysTick exception, for firmware driven by a periodic tick.
+//!
+//! # Example
+//! ```
+//! # use armv6_m_instruction_parser::{emulator::Exceptions, memory::Memory, systick::{SysTick, CSR, CVR, RVR}};
+//! let mut sys_tick = SysTick::new();
+//! let mut exceptions = Exceptions::new(0);
+//! sys_tick.write_word(RVR, 99).unwrap();
+//! sys_tick.write_word(CVR, 0).unwrap();
+//! // Enabled with the interrupt, counting the processor clock.
+//! sys_tick.write_word(CSR, 0b111).unwrap();
+//! sys_tick.tick(100, &mut exceptions);
+//! assert_eq!(sys_tick.read_word(CVR), Ok(0));
+//! assert!(exceptions.is_pending(15));
+//! // Reading COUNTFLAG clears it.
+//! assert_eq!(sys_tick.read_word(CSR), Ok(0x0001_0007));
+//! assert_eq!(sys_tick.read_word(CSR), Ok(0x0000_0007));
+//! ```
+
+use core::ops::Range;
+
+use crate::{
+    emulator::Exceptions,
+    instructons::AccessSize,
+    memory::{BusError, Memory},
+};
+
+/// Address of the control and status register, SYST_CSR.
+pub const CSR: u32 = 0xe000_e010;
+/// Address of the reload value register, SYST_RVR.
+pub const RVR: u32 = 0xe000_e014;
+/// Address of the current value register, SYST_CVR.
+pub const CVR: u32 = 0xe000_e018;
+/// Address of the calibration value register, SYST_CALIB.
+pub const CALIB: u32 = 0xe000_e01c;
+/// Exception number of SysTick.
+pub const EXCEPTION: u32 = 15;
+
+/// Bits of the counter.
+const MASK: u32 = 0x00ff_ffff;
+/// Bits of SYST_CSR.
+const ENABLE: u32 = 1;
+const TICKINT: u32 = 1 << 1;
+const CLKSOURCE: u32 = 1 << 2;
+
+/// SysTick timer, its registers accessed as [`Memory`] and its counter advanced by
+/// [`tick`](Self::tick).
+///
+/// The counter always counts the processor clock, SYST_CSR.CLKSOURCE reads as 1 and
+/// SYST_CALIB reports no reference clock. Registers are accessed as words, other accesses
+/// are bus errors.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SysTick {
+    pub enabled: bool,
+    /// Reaching 0 makes SysTick pending.
+    pub interrupt: bool,
+    /// Set when the counter reaches 0, cleared by reading SYST_CSR or writing SYST_CVR.
+    pub count_flag: bool,
+    pub reload: u32,
+    pub current: u32,
+}
+
+impl SysTick {
+    /// Returns the timer out of reset, disabled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Advances the counter by `cycles` of the processor clock, making SysTick pending in
+    /// `exceptions` when it reaches 0 with the interrupt enabled.
+    ///
+    /// The counter reloads on the cycle after reaching 0, and stops at 0 when the reload
+    /// value is 0.
+    pub fn tick(&mut self, cycles: u32, exceptions: &mut Exceptions) {
+        if !self.enabled {
+            return;
+        }
+        let mut remaining = cycles;
+        while remaining > 0 {
+            if self.current == 0 {
+                if self.reload == 0 {
+                    return;
+                }
+                self.current = self.reload;
+                remaining -= 1;
+                continue;
+            }
+            let count = remaining.min(self.current);
+            self.current -= count;
+            remaining -= count;
+            if self.current == 0 {
+                self.count_flag = true;
+                if self.interrupt {
+                    exceptions.set_pending(EXCEPTION);
+                }
+            }
+        }
+    }
+
+    /// Returns the cycles until the counter next reaches 0, `None` if it does not, like a
+    /// sleeping processor waiting for the tick.
+    pub fn cycles_to_wrap(&self) -> Option<u32> {
+        match (self.enabled, self.current, self.reload) {
+            (false, _, _) | (true, 0, 0) => None,
+            (true, 0, reload) => Some(reload + 1),
+            (true, current, _) => Some(current),
+        }
+    }
+}
+
+impl Memory for SysTick {
+    fn read(&mut self, address: u32, size: AccessSize) -> Result<u32, BusError> {
+        if size != AccessSize::Word {
+            return Err(BusError::Unmapped);
+        }
+        Ok(match address {
+            CSR => {
+                let bits = (self.count_flag as u32) << 16
+                    | CLKSOURCE
+                    | (self.interrupt as u32) << 1
+                    | self.enabled as u32;
+                self.count_flag = false;
+                bits
+            }
+            RVR => self.reload,
+            CVR => self.current,
+            // NOREF, no reference clock, and SKEW, no exact 10ms calibration.
+            CALIB => 0xc000_0000,
+            _ => return Err(BusError::Unmapped),
+        })
+    }
+
+    fn write(&mut self, address: u32, size: AccessSize, value: u32) -> Result<(), BusError> {
+        if size != AccessSize::Word {
+            return Err(BusError::Unmapped);
+        }
+        match address {
+            CSR => {
+                self.enabled = value & ENABLE != 0;
+                self.interrupt = value & TICKINT != 0;
+            }
+            RVR => self.reload = value & MASK,
+            // Any write clears the counter.
+            CVR => {
+                self.current = 0;
+                self.count_flag = false;
+            }
+            CALIB => {}
+            _ => return Err(BusError::Unmapped),
+        }
+        Ok(())
+    }
+
+    fn range(&self) -> Range<u32> {
+        CSR..CALIB + 4
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{cpu::CpuState, emulator::Emulator, memory::Ram, registers::Register};
+
+    #[test]
+    fn periodic_interrupt() {
+        let mut bytes = [0; 0x100];
+        let code: [(usize, u16); 8] = [
+            (15 * 4, 0x51), // SysTick vector
+            (0x40, 0x2063), // movs r0, #99
+            (0x42, 0x6048), // str r0, [r1, #4]
+            (0x44, 0x2007), // movs r0, #7
+            (0x46, 0x6008), // str r0, [r1]
+            (0x48, 0xe7fe), // b .
+            (0x50, 0x3401), // adds r4, #1
+            (0x52, 0x4770), // bx lr
+        ];
+        for (address, halfword) in code {
+            bytes[address..address + 2].copy_from_slice(&halfword.to_le_bytes());
+        }
+        let memory = Ram {
+            base: 0,
+            bytes: &mut bytes,
+        };
+        let mut state = CpuState::new(0x100, 0x40);
+        state.set(Register::R1, CSR);
+        let mut emulator = Emulator::new(state, memory);
+        // Enabled after 6 cycles, the 100 cycle period passes 10 times in the next 1000
+        // cycles, leaving the handler time to run.
+        while emulator.clock.cycles < 6 + 1000 + 40 {
+            emulator.step().unwrap();
+        }
+        assert_eq!(emulator.state.get(Register::R4), 10);
+        assert!(emulator.sys_tick.enabled && emulator.sys_tick.interrupt);
+        assert_eq!(emulator.sys_tick.reload, 99);
+    }
+}
@@ -0,0 +1,394 @@
+//! Memory maps of targets, classifying addresses as flash, RAM, peripherals or system
+//! registers for analyses, like finding handlers outside the code or code run from RAM.
+//!
+//! Unlike [`memory::MemoryMap`](crate::memory::MemoryMap), which holds the memory the
+//! interpreter accesses, these maps only describe where the memories of a device are.
+//!
+//! # Example
+//! ```
+//! # use armv6_m_instruction_parser::{target::{MemoryMap, RegionKind, VectorProblem}, vector_table::{Exception, VectorTable}};
+//! let map = MemoryMap::stm32f030x8();
+//! assert_eq!(map.classify(0x0800_0140), Some(RegionKind::Flash));
+//! assert_eq!(map.classify(0x4800_0014), Some(RegionKind::Peripheral));
+//! assert_eq!(map.region(0x2000_0000).unwrap().name, "SRAM");
+//!
+//! // Initial stack pointer at the end of RAM, NMI handler in the GPIO registers.
+//! let image = [
+//!     0x00, 0x20, 0x00, 0x20, 0x41, 0x01, 0x00, 0x08, 0x01, 0x00, 0x00, 0x48,
+//! ];
+//! let table = VectorTable::parse(&image, 3).unwrap();
+//! assert_eq!(
+//!     map.check_vectors(&table),
+//!     [VectorProblem::Handler { exception: Exception::Nmi, handler: 0x4800_0000 }]
+//! );
+//! ```
+
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
+
+use crate::{
+    functions::FunctionRange,
+    instructons::AccessDirection,
+    program::Program,
+    propagation::Propagation,
+    vector_table::{Exception, VectorTable},
+    xref::{Reference, ReferenceKind, Xrefs},
+};
+
+/// What is mapped in a [`Region`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RegionKind {
+    /// Flash, ROM and other memory code is stored in.
+    Flash,
+    Ram,
+    /// Registers of the peripherals of the device.
+    Peripheral,
+    /// Registers of the processor, like the system control space.
+    System,
+}
+
+impl RegionKind {
+    /// Returns `true` for memory instructions can be fetched from.
+    pub fn executable(self) -> bool {
+        matches!(self, RegionKind::Flash | RegionKind::Ram)
+    }
+}
+
+/// Range of addresses of a [`MemoryMap`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Region {
+    pub name: String,
+    pub base: u32,
+    /// Size in bytes.
+    pub size: u32,
+    pub kind: RegionKind,
+}
+
+impl Region {
+    pub fn new(name: &str, base: u32, size: u32, kind: RegionKind) -> Self {
+        Self {
+            name: name.to_string(),
+            base,
+            size,
+            kind,
+        }
+    }
+
+    /// Returns `true` if `address` is in the region.
+    pub fn contains(&self, address: u32) -> bool {
+        address.wrapping_sub(self.base) < self.size
+    }
+
+    /// Returns the address after the region.
+    pub fn end(&self) -> u64 {
+        self.base as u64 + self.size as u64
+    }
+}
+
+/// Regions of the address space of a target, see the [module](self) documentation.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MemoryMap {
+    regions: Vec<Region>,
+}
+
+/// Private peripheral bus of the processor, the same on all ARMv6-M devices.
+fn private_peripheral_bus() -> Region {
+    Region::new("PPB", 0xe000_0000, 0x0010_0000, RegionKind::System)
+}
+
+impl MemoryMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `region`. Addresses in several regions belong to the one added first.
+    pub fn insert(&mut self, region: Region) {
+        self.regions.push(region);
+    }
+
+    /// Returns the regions in the order they were added.
+    pub fn regions(&self) -> &[Region] {
+        &self.regions
+    }
+
+    /// Returns the region containing `address`.
+    pub fn region(&self, address: u32) -> Option<&Region> {
+        self.regions.iter().find(|region| region.contains(address))
+    }
+
+    /// Returns the kind of the region containing `address`, `None` for unmapped addresses.
+    pub fn classify(&self, address: u32) -> Option<RegionKind> {
+        Some(self.region(address)?.kind)
+    }
+
+    /// Returns `true` if `address` is in flash or RAM.
+    pub fn executable(&self, address: u32) -> bool {
+        self.classify(address).is_some_and(RegionKind::executable)
+    }
+
+    /// Default memory map of the ARMv6-M architecture, for devices without a preset: code,
+    /// SRAM, peripheral, external RAM, external device and system regions.
+    pub fn armv6m() -> Self {
+        Self {
+            regions: Vec::from([
+                Region::new("Code", 0x0000_0000, 0x2000_0000, RegionKind::Flash),
+                Region::new("SRAM", 0x2000_0000, 0x2000_0000, RegionKind::Ram),
+                Region::new(
+                    "Peripheral",
+                    0x4000_0000,
+                    0x2000_0000,
+                    RegionKind::Peripheral,
+                ),
+                Region::new("External RAM", 0x6000_0000, 0x4000_0000, RegionKind::Ram),
+                Region::new(
+                    "External device",
+                    0xa000_0000,
+                    0x4000_0000,
+                    RegionKind::Peripheral,
+                ),
+                private_peripheral_bus(),
+                Region::new(
+                    "Vendor system",
+                    0xe010_0000,
+                    0x1ff0_0000,
+                    RegionKind::System,
+                ),
+            ]),
+        }
+    }
+
+    /// Raspberry Pi RP2040: boot ROM, 16 MiB of XIP flash and 264 KiB of SRAM.
+    pub fn rp2040() -> Self {
+        Self {
+            regions: Vec::from([
+                Region::new("ROM", 0x0000_0000, 0x4000, RegionKind::Flash),
+                Region::new("XIP", 0x1000_0000, 0x0100_0000, RegionKind::Flash),
+                Region::new("SRAM", 0x2000_0000, 0x0004_2000, RegionKind::Ram),
+                Region::new("APB", 0x4000_0000, 0x0007_0000, RegionKind::Peripheral),
+                Region::new("AHB", 0x5000_0000, 0x0040_0000, RegionKind::Peripheral),
+                Region::new("SIO", 0xd000_0000, 0x0000_0180, RegionKind::Peripheral),
+                private_peripheral_bus(),
+            ]),
+        }
+    }
+
+    /// ST STM32F030x8: 64 KiB of flash, also mapped at 0 when booting from it, the system
+    /// memory with the bootloader and 8 KiB of SRAM.
+    pub fn stm32f030x8() -> Self {
+        Self {
+            regions: Vec::from([
+                Region::new("Boot", 0x0000_0000, 0x0001_0000, RegionKind::Flash),
+                Region::new("Flash", 0x0800_0000, 0x0001_0000, RegionKind::Flash),
+                Region::new("System memory", 0x1fff_ec00, 0x0000_0c00, RegionKind::Flash),
+                Region::new("Option bytes", 0x1fff_f800, 0x0000_0010, RegionKind::Flash),
+                Region::new("SRAM", 0x2000_0000, 0x2000, RegionKind::Ram),
+                Region::new("APB", 0x4000_0000, 0x0001_8000, RegionKind::Peripheral),
+                Region::new("AHB1", 0x4002_0000, 0x0000_3400, RegionKind::Peripheral),
+                Region::new("AHB2", 0x4800_0000, 0x0000_1800, RegionKind::Peripheral),
+                private_peripheral_bus(),
+            ]),
+        }
+    }
+
+    /// Nordic nRF51822-QFAA: 256 KiB of flash, the FICR and UICR and 16 KiB of RAM.
+    pub fn nrf51822() -> Self {
+        Self {
+            regions: Vec::from([
+                Region::new("Flash", 0x0000_0000, 0x0004_0000, RegionKind::Flash),
+                Region::new("FICR", 0x1000_0000, 0x0400, RegionKind::Flash),
+                Region::new("UICR", 0x1000_1000, 0x0400, RegionKind::Flash),
+                Region::new("RAM", 0x2000_0000, 0x4000, RegionKind::Ram),
+                Region::new("APB", 0x4000_0000, 0x0008_0000, RegionKind::Peripheral),
+                Region::new("GPIO", 0x5000_0000, 0x1000, RegionKind::Peripheral),
+                private_peripheral_bus(),
+            ]),
+        }
+    }
+
+    /// Microchip ATSAMD21G18: 256 KiB of flash and 32 KiB of SRAM.
+    pub fn samd21g18() -> Self {
+        Self {
+            regions: Vec::from([
+                Region::new("Flash", 0x0000_0000, 0x0004_0000, RegionKind::Flash),
+                Region::new("SRAM", 0x2000_0000, 0x8000, RegionKind::Ram),
+                Region::new("APB", 0x4000_0000, 0x0300_0000, RegionKind::Peripheral),
+                Region::new("IOBUS", 0x6000_0000, 0x0200, RegionKind::Peripheral),
+                private_peripheral_bus(),
+            ]),
+        }
+    }
+
+    /// NXP LPC1114/302: 32 KiB of flash, 8 KiB of SRAM at `0x1000_0000` and the boot ROM.
+    pub fn lpc1114() -> Self {
+        Self {
+            regions: Vec::from([
+                Region::new("Flash", 0x0000_0000, 0x8000, RegionKind::Flash),
+                Region::new("SRAM", 0x1000_0000, 0x2000, RegionKind::Ram),
+                Region::new("Boot ROM", 0x1fff_0000, 0x4000, RegionKind::Flash),
+                Region::new("APB", 0x4000_0000, 0x0008_0000, RegionKind::Peripheral),
+                Region::new("AHB", 0x5000_0000, 0x0020_0000, RegionKind::Peripheral),
+                private_peripheral_bus(),
+            ]),
+        }
+    }
+
+    /// Returns the problems of `table` that would fault at reset or when taking an
+    /// exception: an initial stack pointer outside RAM, the end of a RAM region included,
+    /// and handlers outside flash and RAM.
+    pub fn check_vectors(&self, table: &VectorTable) -> Vec<VectorProblem> {
+        let mut problems = Vec::new();
+        let sp = table.initial_sp;
+        let in_ram = self.regions.iter().any(|region| {
+            region.kind == RegionKind::Ram
+                && sp as u64 > region.base as u64
+                && sp as u64 <= region.end()
+        });
+        if !in_ram {
+            problems.push(VectorProblem::StackPointer(sp));
+        }
+        for entry in &table.entries {
+            if let Some(handler) = entry.handler {
+                if !self.executable(handler) {
+                    problems.push(VectorProblem::Handler {
+                        exception: entry.exception,
+                        handler,
+                    });
+                }
+            }
+        }
+        problems
+    }
+
+    /// Returns the branches and calls of `xrefs` to RAM, code copied to RAM before it runs
+    /// or branches to data.
+    pub fn ram_execution<'a>(&self, xrefs: &'a Xrefs) -> Vec<&'a Reference> {
+        xrefs
+            .references()
+            .iter()
+            .filter(|reference| {
+                matches!(reference.kind, ReferenceKind::Branch | ReferenceKind::Call)
+            })
+            .filter(|reference| self.classify(reference.to) == Some(RegionKind::Ram))
+            .collect()
+    }
+}
+
+/// Problem of a vector table found by [`MemoryMap::check_vectors`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VectorProblem {
+    /// Initial stack pointer outside RAM.
+    StackPointer(u32),
+    /// Handler outside flash and RAM.
+    Handler { exception: Exception, handler: u32 },
+}
+
+/// Load or store to an address of a [`MemoryMap`], see [`accesses`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RegionAccess<'a> {
+    /// Address of the instruction.
+    pub address: u32,
+    /// Address accessed.
+    pub target: u32,
+    pub direction: AccessDirection,
+    /// Region accessed, `None` for unmapped addresses.
+    pub region: Option<&'a Region>,
+}
+
+/// Returns the loads and stores of `function` whose address is known from `propagation`,
+/// with the region of `map` they access, in address order.
+pub fn accesses<'a>(
+    map: &'a MemoryMap,
+    program: &Program,
+    propagation: &Propagation,
+    function: &FunctionRange,
+) -> Vec<RegionAccess<'a>> {
+    let mut accesses = Vec::new();
+    for (address, result) in program.range(function.start..function.end) {
+        let Ok(instruction) = result else {
+            continue;
+        };
+        let Some(access) = instruction.operation.memory_access() else {
+            continue;
+        };
+        let Some(target) = propagation.memory_address(program, address) else {
+            continue;
+        };
+        accesses.push(RegionAccess {
+            address,
+            target,
+            direction: access.direction,
+            region: map.region(target),
+        });
+    }
+    accesses
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{control_flow::ControlFlowGraph, propagation::propagate};
+
+    #[test]
+    fn classify() {
+        let map = MemoryMap::lpc1114();
+        assert_eq!(map.classify(0x1000_1fff), Some(RegionKind::Ram));
+        assert_eq!(map.classify(0x1000_2000), None);
+        assert_eq!(map.classify(0xe000_ed08), Some(RegionKind::System));
+        assert!(MemoryMap::armv6m().executable(0x6000_0000));
+        assert!(!MemoryMap::armv6m().executable(0xa000_0000));
+
+        // Stack pointer past the end of SRAM, reset handler in SRAM.
+        let image = [0x04, 0x20, 0x00, 0x10, 0x01, 0x00, 0x00, 0x10];
+        let table = VectorTable::parse(&image, 2).unwrap();
+        assert_eq!(
+            map.check_vectors(&table),
+            [VectorProblem::StackPointer(0x1000_2004)]
+        );
+
+        let mut map = MemoryMap::new();
+        map.insert(Region::new("Flash", 0, 0x1000, RegionKind::Flash));
+        map.insert(Region::new("RAM", 0x1000, 0x1000, RegionKind::Ram));
+        map.insert(Region::new(
+            "GPIO",
+            0x5000_0000,
+            0x1000,
+            RegionKind::Peripheral,
+        ));
+        // 0x00: ldr r0, [pc, #4]; str r1, [r0]; bl 0x1000; .word 0x50000000
+        let image = [
+            0x01, 0x48, 0x01, 0x60, 0x00, 0xf0, 0xfc, 0xff, 0x00, 0x00, 0x00, 0x50,
+        ];
+        let program = Program::new(&image, 0);
+        let calls: Vec<u32> = map
+            .ram_execution(&Xrefs::new(&program))
+            .iter()
+            .map(|reference| reference.from)
+            .collect();
+        assert_eq!(calls, [0x04]);
+
+        let cfg = ControlFlowGraph::new(&program, &[]);
+        let function = FunctionRange {
+            start: 0,
+            end: 8,
+            called: false,
+            saves_lr: false,
+        };
+        let propagation = propagate(&program, &image, &cfg, &function);
+        let regions: Vec<(u32, u32, &str)> = accesses(&map, &program, &propagation, &function)
+            .iter()
+            .map(|access| {
+                (
+                    access.address,
+                    access.target,
+                    access.region.unwrap().name.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            regions,
+            [(0x00, 0x08, "Flash"), (0x02, 0x5000_0000, "GPIO")]
+        );
+    }
+}
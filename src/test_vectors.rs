@@ -0,0 +1,465 @@
+//! Golden test vectors: compiled ARMv6-M functions with the instructions they decode to,
+//! for catching decoding regressions on real code rather than synthetic bit patterns.
+//!
+//! The bundled vectors are functions compiled by rustc for `thumbv6m-none-eabi` at
+//! `opt-level = "s"`, with the expected instructions as disassembled by `llvm-objdump`.
+//! Vectors from other toolchains are added with [`Corpus::register`].
+//!
+//! # Example
+//! ```
+//! # use armv6_m_instruction_parser::test_vectors::{Corpus, TestVector};
+//! let mut corpus = Corpus::builtin();
+//! // movs r0, #1; bx lr
+//! corpus.register(TestVector::new("one", 0x100, &[0x01, 0x20, 0x70, 0x47], &["movs r0, #1", "bx lr"]));
+//! assert_eq!(corpus.check(), []);
+//! ```
+
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
+
+use crate::{instructons::Operation, Error, ThumbInstructions};
+
+/// Bundled vectors: name, code placed at 0 and the expected instructions. Calls are not
+/// relocated, so `bl` branches to itself.
+const BUILTIN: &[(&str, &[u8], &[&str])] = &[
+    (
+        "checksum",
+        &[
+            0x80, 0xb5, 0x00, 0xaf, 0x00, 0x29, 0x07, 0xd0, 0x02, 0x46, 0x00, 0x20, 0x13, 0x78,
+            0xc0, 0x18, 0x52, 0x1c, 0x49, 0x1e, 0xfa, 0xd1, 0x80, 0xbd, 0x00, 0x20, 0x80, 0xbd,
+        ],
+        &[
+            "push {r7, lr}",
+            "add r7, sp, #0",
+            "cmp r1, #0",
+            "beq #14",
+            "mov r2, r0",
+            "movs r0, #0",
+            "ldrb r3, [r2]",
+            "adds r0, r0, r3",
+            "adds r2, r2, #1",
+            "subs r1, r1, #1",
+            "bne #-12",
+            "pop {r7, pc}",
+            "movs r0, #0",
+            "pop {r7, pc}",
+        ],
+    ),
+    (
+        "clamp",
+        &[
+            0x80, 0xb5, 0x00, 0xaf, 0x90, 0x42, 0x03, 0x46, 0x00, 0xdb, 0x13, 0x46, 0x88, 0x42,
+            0x00, 0xdb, 0x19, 0x46, 0x08, 0x46, 0x80, 0xbd,
+        ],
+        &[
+            "push {r7, lr}",
+            "add r7, sp, #0",
+            "cmp r0, r2",
+            "mov r3, r0",
+            "blt #0",
+            "mov r3, r2",
+            "cmp r0, r1",
+            "blt #0",
+            "mov r1, r3",
+            "mov r0, r1",
+            "pop {r7, pc}",
+        ],
+    ),
+    (
+        "popcount",
+        &[
+            0x80, 0xb5, 0x00, 0xaf, 0x00, 0x28, 0x08, 0xd0, 0x01, 0x46, 0x00, 0x20, 0x0a, 0x46,
+            0x49, 0x1e, 0x11, 0x40, 0x40, 0x1c, 0x00, 0x29, 0xf9, 0xd1, 0x80, 0xbd, 0x00, 0x20,
+            0x80, 0xbd,
+        ],
+        &[
+            "push {r7, lr}",
+            "add r7, sp, #0",
+            "cmp r0, #0",
+            "beq #16",
+            "mov r1, r0",
+            "movs r0, #0",
+            "mov r2, r1",
+            "subs r1, r1, #1",
+            "ands r1, r2",
+            "adds r0, r0, #1",
+            "cmp r1, #0",
+            "bne #-14",
+            "pop {r7, pc}",
+            "movs r0, #0",
+            "pop {r7, pc}",
+        ],
+    ),
+    (
+        "scale",
+        &[
+            0x80, 0xb5, 0x00, 0xaf, 0x07, 0x22, 0x0a, 0x40, 0xc0, 0x00, 0x10, 0x41, 0x80, 0xbd,
+        ],
+        &[
+            "push {r7, lr}",
+            "add r7, sp, #0",
+            "movs r2, #7",
+            "ands r2, r1",
+            "lsls r0, r0, #3",
+            "asrs r0, r2",
+            "pop {r7, pc}",
+        ],
+    ),
+    (
+        "weigh",
+        &[
+            0x80, 0xb5, 0x00, 0xaf, 0x07, 0x22, 0x82, 0x56, 0x03, 0x68, 0x4b, 0x43, 0x81, 0x88,
+            0x59, 0x18, 0x80, 0x79, 0x08, 0x18, 0x80, 0x18, 0x80, 0xbd,
+        ],
+        &[
+            "push {r7, lr}",
+            "add r7, sp, #0",
+            "movs r2, #7",
+            "ldrsb r2, [r0, r2]",
+            "ldr r3, [r0]",
+            "muls r3, r1, r3",
+            "ldrh r1, [r0, #4]",
+            "adds r1, r3, r1",
+            "ldrb r0, [r0, #6]",
+            "adds r0, r1, r0",
+            "adds r0, r0, r2",
+            "pop {r7, pc}",
+        ],
+    ),
+    (
+        "swap16",
+        &[0x80, 0xb5, 0x00, 0xaf, 0x40, 0xba, 0x80, 0xbd],
+        &[
+            "push {r7, lr}",
+            "add r7, sp, #0",
+            "rev16 r0, r0",
+            "pop {r7, pc}",
+        ],
+    ),
+    (
+        "set_bits",
+        &[
+            0x80, 0xb5, 0x00, 0xaf, 0x02, 0x68, 0x0a, 0x43, 0x02, 0x60, 0x80, 0xbd,
+        ],
+        &[
+            "push {r7, lr}",
+            "add r7, sp, #0",
+            "ldr r2, [r0]",
+            "orrs r2, r1",
+            "str r2, [r0]",
+            "pop {r7, pc}",
+        ],
+    ),
+    (
+        "copy_words",
+        &[
+            0x80, 0xb5, 0x00, 0xaf, 0x00, 0x2a, 0x03, 0xd0, 0x08, 0xc9, 0x08, 0xc0, 0x52, 0x1e,
+            0xfb, 0xd1, 0x80, 0xbd,
+        ],
+        &[
+            "push {r7, lr}",
+            "add r7, sp, #0",
+            "cmp r2, #0",
+            "beq #6",
+            "ldm r1!, {r3}",
+            "stm r0!, {r3}",
+            "subs r2, r2, #1",
+            "bne #-10",
+            "pop {r7, pc}",
+        ],
+    ),
+    (
+        "call_twice",
+        &[
+            0x80, 0xb5, 0x00, 0xaf, 0xff, 0xf7, 0xfe, 0xff, 0x55, 0x21, 0x48, 0x40, 0xff, 0xf7,
+            0xfe, 0xff, 0x40, 0x1c, 0x80, 0xbd,
+        ],
+        &[
+            "push {r7, lr}",
+            "add r7, sp, #0",
+            "bl #-4",
+            "movs r1, #85",
+            "eors r0, r1",
+            "bl #-4",
+            "adds r0, r0, #1",
+            "pop {r7, pc}",
+        ],
+    ),
+    (
+        "div10",
+        &[
+            0x80, 0xb5, 0x00, 0xaf, 0x0a, 0x21, 0xff, 0xf7, 0xfe, 0xff, 0x80, 0xbd,
+        ],
+        &[
+            "push {r7, lr}",
+            "add r7, sp, #0",
+            "movs r1, #10",
+            "bl #-4",
+            "pop {r7, pc}",
+        ],
+    ),
+    (
+        "hash_locals",
+        &[
+            0xf0, 0xb5, 0x03, 0xaf, 0x8f, 0xb0, 0x04, 0x90, 0x00, 0x26, 0x08, 0x96, 0x07, 0x96,
+            0x06, 0x96, 0x05, 0x96, 0x05, 0xa8, 0x03, 0x90, 0x04, 0x21, 0xff, 0xf7, 0xfe, 0xff,
+            0x09, 0xa8, 0x08, 0x30, 0x02, 0x90, 0x03, 0x99, 0x3c, 0xc9, 0x3c, 0xc0, 0x02, 0x99,
+            0x09, 0x4b,
+        ],
+        &[
+            "push {r4, r5, r6, r7, lr}",
+            "add r7, sp, #12",
+            "sub sp, #60",
+            "str r0, [sp, #16]",
+            "movs r6, #0",
+            "str r6, [sp, #32]",
+            "str r6, [sp, #28]",
+            "str r6, [sp, #24]",
+            "str r6, [sp, #20]",
+            "add r0, sp, #20",
+            "str r0, [sp, #12]",
+            "movs r1, #4",
+            "bl #-4",
+            "add r0, sp, #36",
+            "adds r0, #8",
+            "str r0, [sp, #8]",
+            "ldr r1, [sp, #12]",
+            "ldm r1!, {r2, r3, r4, r5}",
+            "stm r0!, {r2, r3, r4, r5}",
+            "ldr r1, [sp, #8]",
+            "ldr r3, [pc, #36]",
+        ],
+    ),
+    (
+        "critical",
+        &[
+            0x80, 0xb5, 0x00, 0xaf, 0xef, 0xf3, 0x10, 0x81, 0x72, 0xb6, 0xbf, 0xf3, 0x5f, 0x8f,
+            0x02, 0x68, 0x52, 0x1c, 0x02, 0x60, 0xbf, 0xf3, 0x5f, 0x8f, 0xc8, 0x07, 0x00, 0xd1,
+            0x62, 0xb6, 0x80, 0xbd,
+        ],
+        &[
+            "push {r7, lr}",
+            "add r7, sp, #0",
+            "mrs r1, primask",
+            "cpsid i",
+            "dmb sy",
+            "ldr r2, [r0]",
+            "adds r2, r2, #1",
+            "str r2, [r0]",
+            "dmb sy",
+            "lsls r0, r1, #31",
+            "bne #0",
+            "cpsie i",
+            "pop {r7, pc}",
+        ],
+    ),
+    (
+        "sleep",
+        &[
+            0x80, 0xb5, 0x00, 0xaf, 0xbf, 0xf3, 0x4f, 0x8f, 0x30, 0xbf, 0xbf, 0xf3, 0x6f, 0x8f,
+            0x80, 0xbd,
+        ],
+        &[
+            "push {r7, lr}",
+            "add r7, sp, #0",
+            "dsb sy",
+            "wfi",
+            "isb sy",
+            "pop {r7, pc}",
+        ],
+    ),
+    (
+        "syscall",
+        &[0x80, 0xb5, 0x00, 0xaf, 0x01, 0xdf, 0x80, 0xbd],
+        &["push {r7, lr}", "add r7, sp, #0", "svc #1", "pop {r7, pc}"],
+    ),
+];
+
+/// Code with the instructions it is expected to decode to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TestVector {
+    pub name: String,
+    /// Address of the first byte.
+    pub address: u32,
+    pub bytes: Vec<u8>,
+    /// Expected instructions in the syntax of the [assembler](crate::assembler), with branch
+    /// targets as offsets like `bne #-12`.
+    pub expected: Vec<String>,
+}
+
+/// Difference between a [`TestVector`] and how its code decodes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Mismatch {
+    /// The instruction decoded to another operation.
+    Operation {
+        address: u32,
+        expected: Operation,
+        found: Operation,
+    },
+    /// The instruction failed to decode.
+    Decode {
+        address: u32,
+        expected: Operation,
+        error: Error,
+    },
+    /// An expected instruction is not accepted by the assembler, with its position.
+    Expected {
+        index: usize,
+        text: String,
+        error: Error,
+    },
+    /// The code decoded to a different number of instructions.
+    Count { expected: usize, found: usize },
+}
+
+impl TestVector {
+    pub fn new(name: &str, address: u32, bytes: &[u8], expected: &[&str]) -> Self {
+        TestVector {
+            name: name.to_string(),
+            address,
+            bytes: bytes.to_vec(),
+            expected: expected.iter().map(|text| text.to_string()).collect(),
+        }
+    }
+
+    /// Decodes the code and compares every instruction with the expected one.
+    pub fn check(&self) -> Vec<Mismatch> {
+        let mut mismatches = Vec::new();
+        let decoded: Vec<_> = self.bytes.thumb_instructions_at(self.address).collect();
+        if decoded.len() != self.expected.len() {
+            mismatches.push(Mismatch::Count {
+                expected: self.expected.len(),
+                found: decoded.len(),
+            });
+        }
+        for (index, ((address, result), text)) in
+            decoded.into_iter().zip(&self.expected).enumerate()
+        {
+            let expected = match text.parse::<Operation>() {
+                Ok(expected) => expected,
+                Err(error) => {
+                    mismatches.push(Mismatch::Expected {
+                        index,
+                        text: text.clone(),
+                        error,
+                    });
+                    continue;
+                }
+            };
+            match result {
+                Ok(instruction) if instruction.operation == expected => {}
+                Ok(instruction) => mismatches.push(Mismatch::Operation {
+                    address,
+                    expected,
+                    found: instruction.operation,
+                }),
+                Err(error) => mismatches.push(Mismatch::Decode {
+                    address,
+                    expected,
+                    error,
+                }),
+            }
+        }
+        mismatches
+    }
+}
+
+/// Set of test vectors, the bundled ones and those registered.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Corpus {
+    vectors: Vec<TestVector>,
+}
+
+impl Corpus {
+    /// Returns an empty corpus.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the corpus of the vectors bundled with the crate.
+    pub fn builtin() -> Self {
+        let vectors = BUILTIN
+            .iter()
+            .map(|(name, bytes, expected)| TestVector::new(name, 0, bytes, expected))
+            .collect();
+        Corpus { vectors }
+    }
+
+    /// Adds `vector` to the corpus.
+    pub fn register(&mut self, vector: TestVector) {
+        self.vectors.push(vector);
+    }
+
+    pub fn vectors(&self) -> &[TestVector] {
+        &self.vectors
+    }
+
+    /// Checks all vectors, returning the name of every vector with a mismatch together with
+    /// the mismatch.
+    pub fn check(&self) -> Vec<(&str, Mismatch)> {
+        self.vectors
+            .iter()
+            .flat_map(|vector| {
+                vector
+                    .check()
+                    .into_iter()
+                    .map(|mismatch| (vector.name.as_str(), mismatch))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::registers::Register;
+
+    #[test]
+    fn builtin_and_registered() {
+        let mut corpus = Corpus::builtin();
+        assert_eq!(corpus.check(), []);
+
+        // ldr r0, [r1] expected as ldr r0, [r2]; truncated bl
+        corpus.register(TestVector::new(
+            "broken",
+            0x100,
+            &[0x08, 0x68, 0x00, 0xf0],
+            &["ldr r0, [r2]", "bl #0", "nop"],
+        ));
+        let operation = |n| Operation::LDRImm {
+            imm: 0,
+            n,
+            t: Register::R0,
+        };
+        assert_eq!(
+            corpus.check(),
+            [
+                (
+                    "broken",
+                    Mismatch::Count {
+                        expected: 3,
+                        found: 2
+                    }
+                ),
+                (
+                    "broken",
+                    Mismatch::Operation {
+                        address: 0x100,
+                        expected: operation(Register::R2),
+                        found: operation(Register::R1),
+                    }
+                ),
+                (
+                    "broken",
+                    Mismatch::Decode {
+                        address: 0x102,
+                        expected: Operation::BL { imm: 0 },
+                        error: Error::Malfromed32BitInstruction,
+                    }
+                ),
+            ]
+        );
+    }
+}
@@ -0,0 +1,371 @@
+//! Cycle counts of instructions on the Cortex-M0 and Cortex-M0+, from the timings published
+//! in their technical reference manuals.
+//!
+//! [`cycles`] assumes zero wait state memory and no stalls from the bus or interrupts,
+//! [`wcet`] adds wait states of the memory the code runs from, and a [`Clock`] counts the
+//! cycles of emulated execution.
+//!
+//! # Example
+//! ```
+//! # use armv6_m_instruction_parser::{conditions::Condition, instructons::Operation, timing::{cycles, CoreModel, CycleCount}};
+//! let bne = Operation::B { cond: Condition::NE, imm: 0xffff_fffc };
+//! assert_eq!(
+//!     cycles(&bne, CoreModel::CortexM0Plus),
+//!     CycleCount::Conditional { not_taken: 1, taken: 2 }
+//! );
+//! ```
+
+use core::ops::Range;
+
+use crate::{
+    conditions::Condition,
+    instructons::{DecodedAt, Instruction, Operation},
+    registers::Register,
+};
+
+/// Processor the cycle counts are for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CoreModel {
+    CortexM0,
+    CortexM0Plus,
+}
+
+/// Cycles taken by an instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CycleCount {
+    /// Always takes this many cycles.
+    Fixed(u32),
+    /// Conditional branch, taking more cycles when the branch is taken.
+    Conditional { not_taken: u32, taken: u32 },
+    /// Depends on the implementation, `muls` takes 1 cycle with the fast multiplier and 32
+    /// with the small one.
+    Range { min: u32, max: u32 },
+}
+
+impl CycleCount {
+    /// Fewest cycles the instruction can take.
+    pub fn min(self) -> u32 {
+        match self {
+            CycleCount::Fixed(cycles) => cycles,
+            CycleCount::Conditional { not_taken, taken } => not_taken.min(taken),
+            CycleCount::Range { min, .. } => min,
+        }
+    }
+
+    /// Most cycles the instruction can take.
+    pub fn max(self) -> u32 {
+        match self {
+            CycleCount::Fixed(cycles) => cycles,
+            CycleCount::Conditional { not_taken, taken } => not_taken.max(taken),
+            CycleCount::Range { max, .. } => max,
+        }
+    }
+}
+
+/// Returns the cycles `operation` takes on `model`.
+///
+/// Branches include the pipeline refill. `svc`, `bkpt` and `udf` give the latency of the
+/// exception entry, as the exception is taken instead of executing the next instruction.
+/// `wfi` and `wfe` give the cycles before the processor sleeps.
+pub fn cycles(operation: &Operation, model: CoreModel) -> CycleCount {
+    // Cycles to refill the pipeline after a branch, which is 1 shorter on the M0+.
+    let branch = match model {
+        CoreModel::CortexM0 => 3,
+        CoreModel::CortexM0Plus => 2,
+    };
+    let fixed = match operation {
+        Operation::B {
+            cond: Condition::None,
+            ..
+        } => branch,
+        Operation::B { .. } => {
+            return CycleCount::Conditional {
+                not_taken: 1,
+                taken: branch,
+            }
+        }
+        Operation::BL { .. } => branch + 1,
+        Operation::BX { .. } | Operation::BLXReg { .. } => branch,
+        Operation::ADDReg {
+            d: Register::PC, ..
+        }
+        | Operation::ADDRegSP {
+            d: Register::PC, ..
+        }
+        | Operation::MOVReg {
+            d: Register::PC, ..
+        } => branch,
+        Operation::MUL { .. } => return CycleCount::Range { min: 1, max: 32 },
+        Operation::LDRImm { .. }
+        | Operation::LDRLiteral { .. }
+        | Operation::LDRReg { .. }
+        | Operation::LDRBImm { .. }
+        | Operation::LDRBReg { .. }
+        | Operation::LDRHImm { .. }
+        | Operation::LDRHReg { .. }
+        | Operation::LDRSBReg { .. }
+        | Operation::LDRSH { .. }
+        | Operation::STRImm { .. }
+        | Operation::STRReg { .. }
+        | Operation::STRBImm { .. }
+        | Operation::STRBReg { .. }
+        | Operation::STRHImm { .. }
+        | Operation::STRHReg { .. } => 2,
+        Operation::POP { reg_list } if reg_list.contains(Register::PC) => {
+            // Pop and return, a cycle per low register and the refill after loading pc.
+            1 + (reg_list.len() as u32 - 1) + branch
+        }
+        Operation::LDM { reg_list, .. }
+        | Operation::STM { reg_list, .. }
+        | Operation::PUSH { reg_list }
+        | Operation::POP { reg_list } => 1 + reg_list.len() as u32,
+        Operation::MRS { .. }
+        | Operation::MSRReg { .. }
+        | Operation::DMB { .. }
+        | Operation::DSB { .. }
+        | Operation::ISB { .. } => branch + 1,
+        Operation::SVC { .. } | Operation::BKPT { .. } | Operation::UDF { .. } => {
+            exception_latency(model)
+        }
+        Operation::WFE | Operation::WFI => 2,
+        _ => 1,
+    };
+    CycleCount::Fixed(fixed)
+}
+
+/// Returns the worst case cycles to execute `path`, instructions in the order they execute,
+/// e.g. a basic block, from memory with `wait_states`.
+///
+/// A conditional branch is taken when the next instruction of the path is not the one
+/// following it, the last instruction counts as taken. Every 32 bit word of code fetched and
+/// every literal load adds the wait states, so the estimate is an upper bound for code in
+/// flash with prefetching.
+///
+/// # Example
+/// ```
+/// # use armv6_m_instruction_parser::{parse_at, timing::{wcet, CoreModel}};
+/// // push {r4, lr}; movs r0, #1; pop {r4, pc}
+/// let code = [0x10, 0xb5, 0x01, 0x20, 0x10, 0xbd];
+/// let path: Vec<_> = (0..code.len())
+///     .step_by(2)
+///     .map(|offset| parse_at(&code[offset..], 0x100 + offset as u32).unwrap())
+///     .collect();
+/// assert_eq!(wcet(&path, CoreModel::CortexM0, 0), 3 + 1 + 5);
+/// // Two words of code fetched with one wait state each.
+/// assert_eq!(wcet(&path, CoreModel::CortexM0, 1), 3 + 1 + 5 + 2);
+/// ```
+pub fn wcet(path: &[DecodedAt], model: CoreModel, wait_states: u32) -> u32 {
+    let mut total = 0;
+    let mut fetched = None;
+    for (index, decoded) in path.iter().enumerate() {
+        let sequential = path
+            .get(index + 1)
+            .is_some_and(|next| next.address == decoded.next_address());
+        total += match cycles(&decoded.instruction.operation, model) {
+            CycleCount::Conditional { not_taken, .. } if sequential => not_taken,
+            count => count.max(),
+        };
+        for word in [decoded.address, decoded.next_address() - 1].map(|address| address >> 2) {
+            if fetched != Some(word) {
+                fetched = Some(word);
+                total += wait_states;
+            }
+        }
+        if matches!(decoded.instruction.operation, Operation::LDRLiteral { .. }) {
+            total += wait_states;
+        }
+        if !sequential {
+            // The pipeline is refilled from the destination.
+            fetched = None;
+        }
+    }
+    total
+}
+
+/// Cycles of exception entry, from the exception becoming pending to the first instruction of
+/// the handler.
+pub fn exception_latency(model: CoreModel) -> u32 {
+    match model {
+        CoreModel::CortexM0 => 16,
+        CoreModel::CortexM0Plus => 15,
+    }
+}
+
+/// Processor clock of an [`Emulator`](crate::emulator::Emulator), counting the cycles of the
+/// instructions executed and exceptions taken, the cycles SysTick counts with the processor
+/// clock as source.
+///
+/// Code fetched and data accessed in `wait_state_memory` take `wait_states` more cycles per
+/// access, as in [`wcet`], with a sequential fetch of the same word free.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Clock {
+    pub model: CoreModel,
+    /// Memory with wait states, like flash.
+    pub wait_state_memory: Range<u32>,
+    pub wait_states: u32,
+    /// `muls` takes 1 cycle, and 32 with the small multiplier.
+    pub fast_multiplier: bool,
+    /// Cycles elapsed.
+    pub cycles: u64,
+    /// Word of code fetched last, `None` after the pipeline is refilled.
+    fetched: Option<u32>,
+}
+
+impl Clock {
+    /// Returns a clock at 0 for `model` with zero wait state memory and the fast multiplier.
+    pub fn new(model: CoreModel) -> Self {
+        Self {
+            model,
+            wait_state_memory: 0..0,
+            wait_states: 0,
+            fast_multiplier: true,
+            cycles: 0,
+            fetched: None,
+        }
+    }
+
+    /// Counts `instruction` at `address` executed with the instruction at `next` following
+    /// and `accesses` of data in the memory with wait states, returning its cycles.
+    pub fn execute(
+        &mut self,
+        address: u32,
+        instruction: &Instruction,
+        next: u32,
+        accesses: u32,
+    ) -> u32 {
+        let sequential = next == address.wrapping_add(instruction.size());
+        let mut total = match cycles(&instruction.operation, self.model) {
+            CycleCount::Conditional { not_taken, .. } if sequential => not_taken,
+            CycleCount::Range { min, .. } if self.fast_multiplier => min,
+            count => count.max(),
+        };
+        let last = address.wrapping_add(instruction.size() - 1);
+        for address in [address, last] {
+            if self.wait_state_memory.contains(&address) && self.fetched != Some(address >> 2) {
+                total += self.wait_states;
+            }
+            self.fetched = Some(address >> 2);
+        }
+        total += accesses * self.wait_states;
+        if !sequential {
+            self.fetched = None;
+        }
+        self.cycles += total as u64;
+        total
+    }
+
+    /// Counts the entry of an exception, returning its cycles.
+    pub fn exception_entry(&mut self) -> u32 {
+        let latency = exception_latency(self.model);
+        self.fetched = None;
+        self.cycles += latency as u64;
+        latency
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::registers::RegisterList;
+
+    #[test]
+    fn published_timings() {
+        use CoreModel::*;
+        let pop = Operation::POP {
+            reg_list: RegisterList::from([Register::R4, Register::R5, Register::PC]),
+        };
+        let push = Operation::PUSH {
+            reg_list: RegisterList::from([Register::R4, Register::R5, Register::LR]),
+        };
+        let expected = [
+            (Operation::NOP, CortexM0, 1),
+            (Operation::BL { imm: 0 }, CortexM0, 4),
+            (Operation::BL { imm: 0 }, CortexM0Plus, 3),
+            (Operation::BX { m: Register::LR }, CortexM0, 3),
+            (pop.clone(), CortexM0, 6),
+            (pop, CortexM0Plus, 5),
+            (push, CortexM0Plus, 4),
+            (Operation::DSB { option: 0xf }, CortexM0, 4),
+        ];
+        for (operation, model, count) in expected {
+            assert_eq!(
+                cycles(&operation, model),
+                CycleCount::Fixed(count),
+                "{operation:?}"
+            );
+        }
+
+        let b = Operation::B {
+            cond: Condition::EQ,
+            imm: 0,
+        };
+        assert_eq!(cycles(&b, CortexM0).min(), 1);
+        assert_eq!(cycles(&b, CortexM0).max(), 3);
+        let muls = Operation::MUL {
+            n: Register::R0,
+            dm: Register::R1,
+        };
+        assert_eq!(cycles(&muls, CortexM0Plus).max(), 32);
+    }
+
+    #[test]
+    fn paths() {
+        // cmp r0, #0; beq.n +2; movs r0, #1; bx lr
+        let code = [0x00, 0x28, 0x00, 0xd0, 0x01, 0x20, 0x70, 0x47];
+        let decoded: Vec<_> = (0..code.len())
+            .step_by(2)
+            .map(|offset| crate::parse_at(&code[offset..], 0x1000 + offset as u32).unwrap())
+            .collect();
+        let fall_through = wcet(&decoded, CoreModel::CortexM0Plus, 0);
+        assert_eq!(fall_through, 1 + 1 + 1 + 2);
+        let taken = [decoded[0].clone(), decoded[1].clone(), decoded[3].clone()];
+        assert_eq!(wcet(&taken, CoreModel::CortexM0Plus, 0), 1 + 2 + 2);
+        // The words at 0x1000 and 0x1004.
+        assert_eq!(wcet(&taken, CoreModel::CortexM0Plus, 2), 5 + 2 * 2);
+        // b.n to itself refetches the word.
+        let branch = crate::parse_at(&[0xfe, 0xe7], 0x1000).unwrap();
+        let spin = [branch.clone(), branch];
+        assert_eq!(wcet(&spin, CoreModel::CortexM0, 1), 2 * (3 + 1));
+        assert_eq!(wcet(&decoded[..2], CoreModel::CortexM0, 0), 1 + 3);
+        assert_eq!(wcet(&[], CoreModel::CortexM0, 3), 0);
+    }
+
+    #[test]
+    fn clock() {
+        use crate::{
+            cpu::CpuState,
+            emulator::{Emulator, Stop},
+            memory::Ram,
+        };
+
+        let mut bytes = [0; 0x100];
+        let code: [(usize, u16); 7] = [
+            (0x00, 0x2002), // movs r0, #2
+            (0x02, 0x3801), // loop: subs r0, #1
+            (0x04, 0xd1fd), // bne loop
+            (0x06, 0x6801), // ldr r1, [r0]
+            (0x08, 0xbe00), // bkpt 0
+            (15 * 4, 0x41), // SysTick vector
+            (0x40, 0xbf00), // nop
+        ];
+        for (address, halfword) in code {
+            bytes[address..address + 2].copy_from_slice(&halfword.to_le_bytes());
+        }
+        let memory = Ram {
+            base: 0,
+            bytes: &mut bytes,
+        };
+        let mut emulator = Emulator::new(CpuState::new(0x100, 0), memory);
+        emulator.clock.wait_state_memory = 0..0x40;
+        emulator.clock.wait_states = 1;
+        assert_eq!(emulator.run(), Stop::Breakpoint(0));
+        // Words of code refetched after the taken branch, and the load from the slow memory.
+        assert_eq!(
+            emulator.clock.cycles,
+            (1 + 1) + 1 + (3 + 1) + (1 + 1) + (1 + 1) + (2 + 1)
+        );
+        emulator.exceptions.set_pending(15);
+        emulator.step().unwrap();
+        assert_eq!(emulator.clock.cycles, 14 + 16 + 1);
+    }
+}
@@ -0,0 +1,236 @@
+//! Execution traces of emulated runs, a record of every instruction with the registers and
+//! memory it changed, kept in a ring buffer and serializable with the `serde` feature to
+//! replay and diff failing runs.
+//!
+//! # Example
+//! ```
+//! # use armv6_m_instruction_parser::{cpu::CpuState, emulator::Emulator, memory::Ram, registers::Register, trace::Trace};
+//! // movs r0, #7; str r0, [r1]; bkpt 0
+//! let mut bytes = [0x07, 0x20, 0x08, 0x60, 0x00, 0xbe, 0, 0, 0, 0, 0, 0];
+//! let memory = Ram { base: 0, bytes: &mut bytes };
+//! let mut state = CpuState::new(0x2000_0000, 0);
+//! state.set(Register::R1, 8);
+//! let mut emulator = Emulator::new(state, memory).with_hooks(Trace::new(16));
+//! emulator.run();
+//! let records: Vec<_> = emulator.hooks.records().collect();
+//! assert_eq!(records.len(), 3);
+//! assert_eq!((records[0].address, records[0].encoding), (0x00, 0x2007));
+//! assert_eq!(records[0].writes, [(Register::R0, 7)]);
+//! assert_eq!(records[1].accesses[0].value, 7);
+//! ```
+
+use alloc::{collections::VecDeque, vec::Vec};
+
+use crate::{
+    cpu::{Apsr, CpuState},
+    emulator::{Exceptions, Hooks, Stop},
+    instructons::{AccessDirection, AccessSize, Instruction},
+    registers::Register,
+};
+
+/// Registers compared before and after every instruction, all but `pc`.
+const REGISTERS: [Register; 15] = [
+    Register::R0,
+    Register::R1,
+    Register::R2,
+    Register::R3,
+    Register::R4,
+    Register::R5,
+    Register::R6,
+    Register::R7,
+    Register::R8,
+    Register::R9,
+    Register::R10,
+    Register::R11,
+    Register::R12,
+    Register::SP,
+    Register::LR,
+];
+
+/// Load or store of a traced instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Access {
+    pub address: u32,
+    pub size: AccessSize,
+    pub direction: AccessDirection,
+    /// Value loaded or stored.
+    pub value: u32,
+}
+
+/// Instruction executed in a [`Trace`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Record {
+    pub address: u32,
+    /// Encoding, the first halfword in the high bits for 32-bit instructions.
+    pub encoding: u32,
+    pub instruction: Instruction,
+    /// Registers other than `pc` the instruction changed, with their values after it.
+    pub writes: Vec<(Register, u32)>,
+    pub accesses: Vec<Access>,
+    /// Flags after the instruction.
+    pub apsr: Apsr,
+    /// Result of the instruction, the stop of an instruction faulting or trapping.
+    pub result: Result<(), Stop>,
+}
+
+/// Records of the last instructions executed, as the [`Hooks`] of an emulator.
+///
+/// Instructions that fail to decode are not recorded, nor the accesses of exception entry
+/// and return.
+#[derive(Debug, Clone)]
+pub struct Trace {
+    records: VecDeque<Record>,
+    capacity: usize,
+    /// Address and encoding of the instruction fetched.
+    fetched: (u32, u32),
+    /// Registers before the instruction executing.
+    registers: [u32; 15],
+    accesses: Vec<Access>,
+}
+
+impl Trace {
+    /// Returns an empty trace keeping the last `capacity` records.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            records: VecDeque::with_capacity(capacity),
+            capacity,
+            fetched: (0, 0),
+            registers: [0; 15],
+            accesses: Vec::new(),
+        }
+    }
+
+    /// Returns the records from the oldest kept.
+    pub fn records(&self) -> impl Iterator<Item = &Record> + '_ {
+        self.records.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.records.clear();
+    }
+
+    /// Returns the index of the first record differing from `other`, or where one trace
+    /// ends before the other, `None` if the traces are the same.
+    pub fn divergence(&self, other: &Trace) -> Option<usize> {
+        let index = self
+            .records
+            .iter()
+            .zip(&other.records)
+            .position(|(record, other)| record != other);
+        match index {
+            None if self.len() == other.len() => None,
+            None => Some(self.len().min(other.len())),
+            index => index,
+        }
+    }
+}
+
+impl Hooks for Trace {
+    fn fetch(&mut self, address: u32, encoding: u32) {
+        self.fetched = (address, encoding);
+    }
+
+    fn before(&mut self, state: &CpuState, _instruction: &Instruction) {
+        self.registers = REGISTERS.map(|register| state.get(register));
+        self.accesses.clear();
+    }
+
+    fn after(
+        &mut self,
+        _address: u32,
+        instruction: &Instruction,
+        result: Result<(), Stop>,
+        state: &CpuState,
+        _exceptions: &mut Exceptions,
+    ) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.records.len() == self.capacity {
+            self.records.pop_front();
+        }
+        let writes = REGISTERS
+            .iter()
+            .zip(self.registers)
+            .map(|(register, before)| (*register, before, state.get(*register)))
+            .filter(|(_, before, after)| before != after)
+            .map(|(register, _, after)| (register, after))
+            .collect();
+        let (address, encoding) = self.fetched;
+        self.records.push_back(Record {
+            address,
+            encoding,
+            instruction: instruction.clone(),
+            writes,
+            accesses: core::mem::take(&mut self.accesses),
+            apsr: state.apsr,
+            result,
+        });
+    }
+
+    fn memory_access(
+        &mut self,
+        address: u32,
+        size: AccessSize,
+        direction: AccessDirection,
+        value: u32,
+    ) {
+        self.accesses.push(Access {
+            address,
+            size,
+            direction,
+            value,
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        emulator::Emulator,
+        memory::{CowRam, Memory},
+    };
+
+    #[test]
+    fn ring_buffer() {
+        // movs r0, #3; loop: subs r0, #1; bne loop; bkpt 0
+        let bytes = [0x03, 0x20, 0x01, 0x38, 0xfd, 0xd1, 0x00, 0xbe];
+        let memory = CowRam::from_bytes(0, &bytes);
+        let mut emulator =
+            Emulator::new(CpuState::new(0x2000_0000, 0), memory).with_hooks(Trace::new(4));
+        let start = emulator.snapshot();
+        assert_eq!(emulator.run(), Stop::Breakpoint(0));
+        // The first 4 of the 8 records were dropped.
+        let trace = &emulator.hooks;
+        let addresses: Vec<_> = trace.records().map(|record| record.address).collect();
+        assert_eq!(addresses, [0x04, 0x02, 0x04, 0x06]);
+        let last = trace.records().last().unwrap();
+        assert_eq!(last.result, Err(Stop::Breakpoint(0)));
+        assert!(last.writes.is_empty());
+        assert!(trace.records().nth(1).unwrap().apsr.z);
+
+        // Replaying with another count diverges at the first instruction.
+        let mut replay = |count: u8| {
+            emulator.restore(&start);
+            emulator.hooks = Trace::new(16);
+            emulator.memory.write_byte(0, count).unwrap();
+            assert_eq!(emulator.run(), Stop::Breakpoint(0));
+            emulator.hooks.clone()
+        };
+        let (three, two) = (replay(3), replay(2));
+        assert_eq!((three.len(), two.len()), (8, 6));
+        assert_eq!(three.divergence(&two), Some(0));
+        assert_eq!(three.divergence(&three), None);
+    }
+}
@@ -0,0 +1,113 @@
+//! Generation of trampolines, jumping to any address from a `b` or `bl` in range of the
+//! trampoline.
+//!
+//! # Example
+//! ```
+//! # use armv6_m_instruction_parser::{registers::Register, trampoline::trampoline};
+//! // ldr r3, [pc, #0]; bx r3; .word 0x0800_1235
+//! let trampoline = trampoline(0x0800_1234, Some(Register::R3)).unwrap();
+//! assert_eq!(
+//!     trampoline.bytes,
+//!     [0x00, 0x4b, 0x18, 0x47, 0x35, 0x12, 0x00, 0x08]
+//! );
+//! assert_eq!(trampoline.alignment, 4);
+//! assert_eq!(trampoline.literal_offset, 4);
+//! ```
+
+use alloc::{vec, vec::Vec};
+
+use crate::{
+    encoder::encode,
+    instructons::Operation,
+    registers::{Register, RegisterList},
+    Error,
+};
+
+/// Code jumping to a destination held in a literal.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Trampoline {
+    /// The instructions followed by the literal.
+    pub bytes: Vec<u8>,
+    /// Alignment in bytes required for the start of the trampoline, for the literal to be
+    /// word aligned.
+    pub alignment: u32,
+    /// Offset of the literal holding the destination with the thumb bit set.
+    pub literal_offset: usize,
+}
+
+/// Returns a trampoline jumping to `destination`.
+///
+/// With a `scratch` register the trampoline loads the destination into it and jumps with
+/// `bx`, the scratch register must be a low register. Without one the destination is written
+/// over a pushed register and jumped to with `pop {r0, pc}`, leaving all registers intact.
+/// Neither modifies the condition flags or `lr`, so trampolines can be placed between a `bl`
+/// and a function.
+pub fn trampoline(destination: u32, scratch: Option<Register>) -> Result<Trampoline, Error> {
+    let operations = match scratch {
+        Some(t) => vec![Operation::LDRLiteral { t, imm: 0 }, Operation::BX { m: t }],
+        None => vec![
+            Operation::PUSH {
+                reg_list: RegisterList::from([Register::R0, Register::R1]),
+            },
+            Operation::LDRLiteral {
+                t: Register::R0,
+                imm: 4,
+            },
+            Operation::STRImm {
+                imm: 4,
+                n: Register::SP,
+                t: Register::R0,
+            },
+            Operation::POP {
+                reg_list: RegisterList::from([Register::R0, Register::PC]),
+            },
+        ],
+    };
+
+    let mut bytes = vec![];
+    for operation in &operations {
+        bytes.extend_from_slice(encode(operation)?.as_bytes());
+    }
+    let literal_offset = bytes.len();
+    bytes.extend_from_slice(&(destination | 1).to_le_bytes());
+    Ok(Trampoline {
+        bytes,
+        alignment: 4,
+        literal_offset,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parse;
+
+    #[test]
+    fn register_preserving_trampoline() {
+        let trampoline = trampoline(0x2000_0100, None).unwrap();
+        assert_eq!(trampoline.literal_offset, 8);
+        assert_eq!(trampoline.bytes[8..], [0x01, 0x01, 0x00, 0x20]);
+        // The literal is 4 bytes from Align(PC, 4) of the `ldr` at offset 2.
+        assert_eq!(
+            parse(&trampoline.bytes[2..4]).unwrap().operation,
+            Operation::LDRLiteral {
+                t: Register::R0,
+                imm: 4
+            }
+        );
+        assert_eq!(
+            parse(&trampoline.bytes[6..8]).unwrap().operation,
+            Operation::POP {
+                reg_list: RegisterList::from([Register::R0, Register::PC])
+            }
+        );
+    }
+
+    #[test]
+    fn trampoline_errors() {
+        assert_eq!(
+            trampoline(0x2000_0100, Some(Register::R12)),
+            Err(Error::InvalidRegister)
+        );
+    }
+}
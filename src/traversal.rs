@@ -0,0 +1,227 @@
+//! Recursive traversal disassembly, decoding only what is reached by following the control
+//! flow from entry points, so literal pools and other data between functions are never
+//! decoded as code.
+//!
+//! # Example
+//! ```
+//! # use armv6_m_instruction_parser::traversal::{traverse, Content};
+//! // 0x00: ldr r0, [pc, #0]; bx lr; .word 0xffffffff
+//! // 0x08: movs r0, #1
+//! let image = [0x00, 0x48, 0x70, 0x47, 0xff, 0xff, 0xff, 0xff, 0x01, 0x20];
+//! let traversal = traverse(&image, 0, &[0x00]);
+//! assert_eq!(
+//!     traversal.code_map(),
+//!     [
+//!         (0x00..0x04, Content::Code),
+//!         (0x04..0x08, Content::Literal),
+//!         (0x08..0x0a, Content::Unknown),
+//!     ]
+//! );
+//! ```
+
+use alloc::{
+    collections::{BTreeMap, BTreeSet},
+    vec,
+    vec::Vec,
+};
+use core::ops::Range;
+
+use crate::{
+    functions::{ends_flow, is_padding},
+    instructons::{BranchTarget, Instruction, Operation},
+    parse,
+};
+
+/// What a byte of the image was found to be by a [`Traversal`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Content {
+    /// Part of an instruction reached from an entry point.
+    Code,
+    /// Part of a word loaded by a literal load reached from an entry point.
+    Literal,
+    /// Padding instruction not reached, like the `nop` aligning the next function.
+    Padding,
+    /// Not reached.
+    Unknown,
+}
+
+/// Instructions and literals reached from entry points, see [`traverse`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Traversal {
+    base: u32,
+    size: u32,
+    instructions: BTreeMap<u32, Instruction>,
+    /// Addresses of the words loaded by literal loads.
+    literals: BTreeSet<u32>,
+    /// Addresses reached where no instruction could be decoded.
+    errors: BTreeSet<u32>,
+    /// Addresses of the halfwords not reached holding padding.
+    padding: BTreeSet<u32>,
+}
+
+/// Disassembles `image` placed at `base` by following the control flow from `entries`.
+///
+/// Fall through, direct branches and calls are followed, addresses reached are decoded once.
+/// Paths end at unconditional branches, returns and indirect branches, whose targets are not
+/// known, and at addresses outside the image, in a known literal or not decoding. Literals
+/// found after the code decoded over them do not undo that code.
+pub fn traverse(image: &[u8], base: u32, entries: &[u32]) -> Traversal {
+    let mut traversal = Traversal {
+        base,
+        size: image.len() as u32,
+        instructions: BTreeMap::new(),
+        literals: BTreeSet::new(),
+        errors: BTreeSet::new(),
+        padding: BTreeSet::new(),
+    };
+    let mut work: Vec<u32> = entries.iter().rev().map(|entry| entry & !1).collect();
+    while let Some(address) = work.pop() {
+        if traversal.instructions.contains_key(&address)
+            || traversal.errors.contains(&address)
+            || traversal.literal_at(address).is_some()
+        {
+            continue;
+        }
+        let Some(offset) = address
+            .checked_sub(base)
+            .filter(|offset| *offset < traversal.size)
+        else {
+            continue;
+        };
+        let Ok(instruction) = parse(&image[offset as usize..]) else {
+            traversal.errors.insert(address);
+            continue;
+        };
+        let operation = &instruction.operation;
+        match (operation, operation.branch_target(address)) {
+            (Operation::LDRLiteral { .. }, BranchTarget::Direct(literal)) => {
+                traversal.literals.insert(literal);
+            }
+            (Operation::B { .. } | Operation::BL { .. }, BranchTarget::Direct(target)) => {
+                work.push(target);
+            }
+            _ => {}
+        }
+        if !ends_flow(operation) {
+            work.push(address.wrapping_add(instruction.size()));
+        }
+        traversal.instructions.insert(address, instruction);
+    }
+
+    let unreached: Vec<u32> = traversal
+        .code_map()
+        .into_iter()
+        .filter(|(_, content)| *content == Content::Unknown)
+        .flat_map(|(range, _)| range.step_by(2))
+        .collect();
+    for address in unreached {
+        let offset = address.wrapping_sub(base) as usize;
+        if parse(&image[offset..])
+            .is_ok_and(|instruction| instruction.is_16bit() && is_padding(&instruction.operation))
+        {
+            traversal.padding.insert(address);
+        }
+    }
+    traversal
+}
+
+impl Traversal {
+    /// Returns the instruction reached at `address`.
+    pub fn at(&self, address: u32) -> Option<&Instruction> {
+        self.instructions.get(&address)
+    }
+
+    /// Returns the instructions reached, in address order.
+    pub fn instructions(&self) -> impl Iterator<Item = (u32, &Instruction)> {
+        self.instructions
+            .iter()
+            .map(|(address, instruction)| (*address, instruction))
+    }
+
+    /// Returns the addresses of the literals loaded by the instructions reached.
+    pub fn literals(&self) -> impl Iterator<Item = u32> + '_ {
+        self.literals.iter().copied()
+    }
+
+    /// Returns the addresses reached that did not decode, like data reached by a branch
+    /// computed wrong or a corrupted image.
+    pub fn errors(&self) -> impl Iterator<Item = u32> + '_ {
+        self.errors.iter().copied()
+    }
+
+    /// Returns the content of the whole image, as runs of the same content in address
+    /// order. Bytes of instructions take precedence over literals overlapping them.
+    pub fn code_map(&self) -> Vec<(Range<u32>, Content)> {
+        let mut map: Vec<(Range<u32>, Content)> = vec![];
+        let end = self.base.wrapping_add(self.size);
+        let mut address = self.base;
+        while address != end {
+            let (content, size) = match (self.at(address), self.literal_at(address)) {
+                (Some(instruction), _) => (Content::Code, instruction.size()),
+                (None, Some(literal)) => (Content::Literal, literal + 4 - address),
+                (None, None) if self.padding.contains(&address) => (Content::Padding, 2),
+                (None, None) => (Content::Unknown, 2),
+            };
+            let next = address.wrapping_add(size.min(end.wrapping_sub(address)));
+            match map.last_mut() {
+                Some((range, last)) if *last == content => range.end = next,
+                _ => map.push((address..next, content)),
+            }
+            address = next;
+        }
+        map
+    }
+
+    /// Returns the content of every halfword of the image, in address order, the last one
+    /// for an odd byte at the end.
+    pub fn classification(&self) -> Vec<Content> {
+        self.code_map()
+            .into_iter()
+            .flat_map(|(range, content)| range.step_by(2).map(move |_| content))
+            .collect()
+    }
+
+    /// Returns the address of the literal containing `address`.
+    fn literal_at(&self, address: u32) -> Option<u32> {
+        self.literals
+            .range(address.saturating_sub(3)..=address)
+            .next_back()
+            .copied()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn follows_branches() {
+        // 0x00: push {r4, lr}; bl 0x0c; pop {r4, pc}; nop
+        // 0x0a: .hword 0xffff
+        // 0x0c: ldr r0, [pc, #4]; cmp r0, #0; bne.n 0x18; bx lr
+        // 0x14: .word 0x12345678
+        // 0x18: bx lr
+        let image = [
+            0x10, 0xb5, 0x00, 0xf0, 0x03, 0xf8, 0x10, 0xbd, 0x00, 0xbf, 0xff, 0xff, //
+            0x01, 0x48, 0x00, 0x28, 0x02, 0xd1, 0x70, 0x47, 0x78, 0x56, 0x34, 0x12, //
+            0x70, 0x47,
+        ];
+        let traversal = traverse(&image, 0x100, &[0x101]);
+        assert_eq!(traversal.literals().collect::<Vec<_>>(), [0x114]);
+        assert!(traversal.errors().next().is_none());
+        assert_eq!(
+            traversal.code_map(),
+            [
+                (0x100..0x108, Content::Code),
+                (0x108..0x10a, Content::Padding),
+                (0x10a..0x10c, Content::Unknown),
+                (0x10c..0x114, Content::Code),
+                (0x114..0x118, Content::Literal),
+                (0x118..0x11a, Content::Code),
+            ]
+        );
+        let classification = traversal.classification();
+        assert_eq!(classification.len(), 13);
+        assert_eq!(classification[5], Content::Unknown);
+    }
+}
@@ -0,0 +1,167 @@
+//! Loading of UF2 files, as used by the RP2040 and many USB bootloaders, reassembling the
+//! 512 byte blocks into the address ranges they are written to.
+//!
+//! # Example
+//! ```no_run
+//! # use armv6_m_instruction_parser::uf2::{parse, RP2040};
+//! let file = std::fs::read("firmware.uf2").unwrap();
+//! let images = parse(&file).unwrap();
+//! let image = images.iter().find(|image| image.family == Some(RP2040)).unwrap();
+//! for (address, instruction) in image.image.instructions() {
+//!     println!("{address:#010x}: {instruction:?}");
+//! }
+//! ```
+
+use alloc::vec::Vec;
+
+use crate::image::MemoryImage;
+
+/// Family ID of the RP2040.
+pub const RP2040: u32 = 0xe48b_ff56;
+/// Family ID of the Arm secure image of the RP2350.
+pub const RP2350_ARM_S: u32 = 0xe48b_ff59;
+/// Family ID of the SAMD21.
+pub const SAMD21: u32 = 0x68ed_2b88;
+/// Family ID of the STM32F0.
+pub const STM32F0: u32 = 0x6478_24b6;
+/// Family ID of the nRF51.
+pub const NRF51: u32 = 0x1b57_745f;
+
+const BLOCK_SIZE: usize = 512;
+const MAGIC_START: [u32; 2] = [0x0a32_4655, 0x9e5d_5157];
+const MAGIC_END: u32 = 0x0ab1_6f30;
+const PAYLOAD_MAX: u32 = 476;
+
+const NOT_MAIN_FLASH: u32 = 0x0000_0001;
+const FILE_CONTAINER: u32 = 0x0000_1000;
+const FAMILY_ID_PRESENT: u32 = 0x0000_2000;
+
+/// Error from parsing a UF2 file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Uf2Error {
+    /// The file is not a whole number of blocks.
+    Truncated,
+    /// The block has the wrong magic numbers.
+    InvalidMagic { block: usize },
+    /// The block has more payload than fits in it.
+    InvalidPayloadSize { block: usize },
+}
+
+impl core::fmt::Display for Uf2Error {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Uf2Error::Truncated => f.write_str("file is not a whole number of UF2 blocks"),
+            Uf2Error::InvalidMagic { block } => write!(f, "block {block} is not a UF2 block"),
+            Uf2Error::InvalidPayloadSize { block } => {
+                write!(f, "block {block} has an invalid payload size")
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Uf2Error {}
+
+/// Blocks of a UF2 file for one family.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Uf2Image {
+    /// Family ID of the blocks, `None` for blocks without one.
+    pub family: Option<u32>,
+    /// Payloads of the blocks, a block written twice keeps the last payload.
+    pub image: MemoryImage,
+}
+
+/// Parses the UF2 file in `data` into one image per family, in the order the families
+/// first appear.
+///
+/// Blocks marked as not for the main flash and file container blocks are skipped.
+pub fn parse(data: &[u8]) -> Result<Vec<Uf2Image>, Uf2Error> {
+    if !data.len().is_multiple_of(BLOCK_SIZE) {
+        return Err(Uf2Error::Truncated);
+    }
+    let mut images: Vec<Uf2Image> = Vec::new();
+    for (index, block) in data.chunks_exact(BLOCK_SIZE).enumerate() {
+        let word =
+            |offset: usize| u32::from_le_bytes(block[offset..offset + 4].try_into().unwrap());
+        if [word(0), word(4)] != MAGIC_START || word(BLOCK_SIZE - 4) != MAGIC_END {
+            return Err(Uf2Error::InvalidMagic { block: index });
+        }
+        let (flags, address, size) = (word(8), word(12), word(16));
+        if size > PAYLOAD_MAX {
+            return Err(Uf2Error::InvalidPayloadSize { block: index });
+        }
+        if flags & (NOT_MAIN_FLASH | FILE_CONTAINER) != 0 {
+            continue;
+        }
+        let family = (flags & FAMILY_ID_PRESENT != 0).then(|| word(28));
+        let position = match images.iter().position(|image| image.family == family) {
+            Some(position) => position,
+            None => {
+                images.push(Uf2Image {
+                    family,
+                    image: MemoryImage::new(),
+                });
+                images.len() - 1
+            }
+        };
+        images[position]
+            .image
+            .insert(address, &block[32..32 + size as usize]);
+    }
+    Ok(images)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn block(flags: u32, address: u32, payload: &[u8], family: u32) -> Vec<u8> {
+        let mut block = Vec::new();
+        for word in [
+            MAGIC_START[0],
+            MAGIC_START[1],
+            flags,
+            address,
+            payload.len() as u32,
+            0,
+            1,
+            family,
+        ] {
+            block.extend(word.to_le_bytes());
+        }
+        block.extend(payload);
+        block.resize(BLOCK_SIZE - 4, 0);
+        block.extend(MAGIC_END.to_le_bytes());
+        block
+    }
+
+    #[test]
+    fn families_and_segments() {
+        let mut file = block(FAMILY_ID_PRESENT, 0x1000_0100, &[0x70, 0x47], RP2040);
+        file.extend(block(FAMILY_ID_PRESENT, 0x1000_0000, &[0x01; 256], RP2040));
+        file.extend(block(0, 0x0800_0000, &[0x00, 0xbf], 0));
+        file.extend(block(NOT_MAIN_FLASH, 0x2000_0000, &[0xff; 4], 0));
+        file.extend(block(FAMILY_ID_PRESENT, 0x1000_1000, &[0xc0, 0x46], RP2040));
+        let images = parse(&file).unwrap();
+        assert_eq!(images.len(), 2);
+        assert_eq!(images[0].family, Some(RP2040));
+        let segments: Vec<(u32, usize)> = images[0]
+            .image
+            .segments()
+            .iter()
+            .map(|segment| (segment.address, segment.data.len()))
+            .collect();
+        assert_eq!(segments, [(0x1000_0000, 0x102), (0x1000_1000, 2)]);
+        assert_eq!(
+            images[1],
+            Uf2Image {
+                family: None,
+                image: MemoryImage::from_bin(&[0x00, 0xbf], 0x0800_0000),
+            }
+        );
+
+        assert_eq!(parse(&file[..100]), Err(Uf2Error::Truncated));
+        file[512] ^= 1;
+        assert_eq!(parse(&file), Err(Uf2Error::InvalidMagic { block: 1 }));
+    }
+}
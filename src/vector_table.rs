@@ -0,0 +1,293 @@
+//! Parsing of the ARMv6-M vector table at the start of an image, holding the initial stack
+//! pointer and the addresses of the exception handlers.
+//!
+//! # Example
+//! ```
+//! # use armv6_m_instruction_parser::vector_table::{Exception, VectorTable};
+//! let image = [
+//!     0x00, 0x20, 0x00, 0x20, 0x41, 0x01, 0x00, 0x08, 0x45, 0x01, 0x00, 0x08,
+//! ];
+//! let table = VectorTable::parse(&image, 3).unwrap();
+//! assert_eq!(table.initial_sp, 0x2000_2000);
+//! assert_eq!(table.reset(), Some(0x0800_0140));
+//! assert_eq!(table.entries[1].exception, Exception::Nmi);
+//! assert_eq!(table.entries[1].handler, Some(0x0800_0144));
+//! ```
+
+use alloc::{collections::BTreeMap, format, string::String, vec::Vec};
+
+use crate::Error;
+
+/// Exception a vector table entry is for, by exception number.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Exception {
+    Reset,
+    Nmi,
+    HardFault,
+    SvCall,
+    PendSv,
+    SysTick,
+    /// Exception number reserved on ARMv6-M.
+    Reserved(u8),
+    /// External interrupt, numbered from 0.
+    Irq(u16),
+}
+
+impl Exception {
+    /// Returns the exception with `number`, the index of its entry in the vector table.
+    /// `None` for 0, the initial stack pointer.
+    pub fn from_number(number: u32) -> Option<Self> {
+        Some(match number {
+            0 => return None,
+            1 => Exception::Reset,
+            2 => Exception::Nmi,
+            3 => Exception::HardFault,
+            11 => Exception::SvCall,
+            14 => Exception::PendSv,
+            15 => Exception::SysTick,
+            4..=15 => Exception::Reserved(number as u8),
+            _ => Exception::Irq(u16::try_from(number - 16).ok()?),
+        })
+    }
+
+    /// Returns the exception number.
+    pub fn number(self) -> u32 {
+        match self {
+            Exception::Reset => 1,
+            Exception::Nmi => 2,
+            Exception::HardFault => 3,
+            Exception::SvCall => 11,
+            Exception::PendSv => 14,
+            Exception::SysTick => 15,
+            Exception::Reserved(number) => number as u32,
+            Exception::Irq(irq) => irq as u32 + 16,
+        }
+    }
+}
+
+/// Names as in the CMSIS device headers, `IRQ` with the interrupt number for interrupts.
+impl core::fmt::Display for Exception {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Exception::Reset => f.write_str("Reset"),
+            Exception::Nmi => f.write_str("NMI"),
+            Exception::HardFault => f.write_str("HardFault"),
+            Exception::SvCall => f.write_str("SVC"),
+            Exception::PendSv => f.write_str("PendSV"),
+            Exception::SysTick => f.write_str("SysTick"),
+            Exception::Reserved(number) => write!(f, "Reserved{number}"),
+            Exception::Irq(irq) => write!(f, "IRQ{irq}"),
+        }
+    }
+}
+
+/// Entry of a [`VectorTable`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VectorEntry {
+    pub exception: Exception,
+    /// Word in the table.
+    pub value: u32,
+    /// Address of the handler, `value` without the Thumb bit. `None` if the Thumb bit is not
+    /// set, like for reserved entries left zero, as taking the exception would fault.
+    pub handler: Option<u32>,
+}
+
+/// Handler function of one or more exceptions, see [`VectorTable::handler_functions`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Handler {
+    pub address: u32,
+    /// Exceptions handled, in exception number order.
+    pub exceptions: Vec<Exception>,
+}
+
+impl Handler {
+    /// Returns the name of the handler, like `SysTick_Handler`, or `Default_Handler` for a
+    /// handler of several exceptions.
+    pub fn name(&self) -> String {
+        match self.exceptions.as_slice() {
+            [exception] => format!("{exception}_Handler"),
+            _ => String::from("Default_Handler"),
+        }
+    }
+}
+
+/// Vector table parsed by [`VectorTable::parse`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VectorTable {
+    pub initial_sp: u32,
+    /// Entries after the initial stack pointer, in exception number order.
+    pub entries: Vec<VectorEntry>,
+}
+
+impl VectorTable {
+    /// Parses the first `entries` words of the vector table at the start of `image`,
+    /// including the initial stack pointer. The table is cut short at the end of the image.
+    ///
+    /// The size of the table depends on the number of interrupts of the device, 16 words
+    /// for the system exceptions and one for every interrupt, at most 48 on ARMv6-M.
+    /// Returns [`Error::InsufficientInput`] if the image does not hold the initial stack
+    /// pointer and the reset vector.
+    pub fn parse(image: &[u8], entries: usize) -> Result<Self, Error> {
+        let mut words = image
+            .chunks_exact(4)
+            .take(entries)
+            .map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]]));
+        let initial_sp = words.next().ok_or(Error::InsufficientInput)?;
+        let entries: Vec<VectorEntry> = words
+            .zip(1..)
+            .filter_map(|(value, number)| {
+                Some(VectorEntry {
+                    exception: Exception::from_number(number)?,
+                    value,
+                    handler: (value & 1 == 1).then_some(value & !1),
+                })
+            })
+            .collect();
+        if entries.is_empty() {
+            return Err(Error::InsufficientInput);
+        }
+        Ok(Self {
+            initial_sp,
+            entries,
+        })
+    }
+
+    /// Returns the entry for `exception`.
+    pub fn entry(&self, exception: Exception) -> Option<&VectorEntry> {
+        self.entries.get(exception.number() as usize - 1)
+    }
+
+    /// Returns the address of the reset handler.
+    pub fn reset(&self) -> Option<u32> {
+        self.entries[0].handler
+    }
+
+    /// Returns the addresses of the handlers, in exception number order, the natural entry
+    /// points of an image.
+    pub fn handlers(&self) -> impl Iterator<Item = u32> + '_ {
+        self.entries.iter().filter_map(|entry| entry.handler)
+    }
+
+    /// Returns the handler functions with the exceptions they handle, in address order.
+    pub fn handler_functions(&self) -> Vec<Handler> {
+        let mut handlers: BTreeMap<u32, Vec<Exception>> = BTreeMap::new();
+        for entry in &self.entries {
+            if let Some(address) = entry.handler {
+                handlers.entry(address).or_default().push(entry.exception);
+            }
+        }
+        handlers
+            .into_iter()
+            .map(|(address, exceptions)| Handler {
+                address,
+                exceptions,
+            })
+            .collect()
+    }
+
+    /// Returns the handler shared by the most exceptions, if more than one, like the
+    /// `Default_Handler` unused interrupts are left to. Ties go to the lowest address.
+    pub fn default_handler(&self) -> Option<u32> {
+        let mut default: Option<Handler> = None;
+        for handler in self.handler_functions() {
+            let most = default
+                .as_ref()
+                .map_or(1, |default| default.exceptions.len());
+            if handler.exceptions.len() > most {
+                default = Some(handler);
+            }
+        }
+        Some(default?.address)
+    }
+
+    /// Returns the exceptions without a handler, not counting reserved entries.
+    pub fn unpopulated(&self) -> Vec<Exception> {
+        self.entries
+            .iter()
+            .filter(|entry| entry.handler.is_none())
+            .map(|entry| entry.exception)
+            .filter(|exception| !matches!(exception, Exception::Reserved(_)))
+            .collect()
+    }
+
+    /// Returns the exceptions handled by the [`default_handler`](Self::default_handler).
+    pub fn aliased(&self) -> Vec<Exception> {
+        let Some(default) = self.default_handler() else {
+            return Vec::new();
+        };
+        self.entries
+            .iter()
+            .filter(|entry| entry.handler == Some(default))
+            .map(|entry| entry.exception)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn exceptions() {
+        let mut image = alloc::vec![0; 17 * 4 + 2];
+        image[4..8].copy_from_slice(&0x0000_00c1u32.to_le_bytes());
+        image[60..64].copy_from_slice(&0x0000_00c5u32.to_le_bytes());
+        image[64..68].copy_from_slice(&0x0000_00c8u32.to_le_bytes());
+        let table = VectorTable::parse(&image, 48).unwrap();
+        assert_eq!(table.entries.len(), 16);
+        assert_eq!(table.handlers().collect::<Vec<_>>(), [0xc0, 0xc4]);
+        let irq = table.entry(Exception::Irq(0)).unwrap();
+        assert_eq!((irq.value, irq.handler), (0xc8, None));
+        assert_eq!(table.entries[3].exception, Exception::Reserved(4));
+        assert!(table.entry(Exception::Irq(1)).is_none());
+        for number in 1..64 {
+            assert_eq!(Exception::from_number(number).unwrap().number(), number);
+        }
+        assert_eq!(
+            VectorTable::parse(&image[..6], 48),
+            Err(Error::InsufficientInput)
+        );
+    }
+
+    #[test]
+    fn handlers() {
+        let mut image = alloc::vec![0; 20 * 4];
+        let mut vector = |number: usize, value: u32| {
+            image[number * 4..number * 4 + 4].copy_from_slice(&value.to_le_bytes())
+        };
+        vector(1, 0x101);
+        for number in [2, 3, 11, 14, 16, 18] {
+            vector(number, 0x121);
+        }
+        vector(15, 0x111);
+        vector(17, 0x131);
+        let table = VectorTable::parse(&image, 20).unwrap();
+        let names: Vec<_> = table
+            .handler_functions()
+            .iter()
+            .map(|handler| (handler.address, handler.name()))
+            .collect();
+        assert_eq!(
+            names,
+            [
+                (0x100, String::from("Reset_Handler")),
+                (0x110, String::from("SysTick_Handler")),
+                (0x120, String::from("Default_Handler")),
+                (0x130, String::from("IRQ1_Handler")),
+            ]
+        );
+        assert_eq!(table.default_handler(), Some(0x120));
+        assert_eq!(
+            table.aliased(),
+            [
+                Exception::Nmi,
+                Exception::HardFault,
+                Exception::SvCall,
+                Exception::PendSv,
+                Exception::Irq(0),
+                Exception::Irq(2),
+            ]
+        );
+        assert_eq!(table.unpopulated(), [Exception::Irq(3)]);
+    }
+}
@@ -0,0 +1,127 @@
+//! WebAssembly bindings decoding and formatting instructions, for disassembling firmware in
+//! the browser.
+//!
+//! Build with `wasm-pack build --target web -- --features wasm` and call from JavaScript:
+//! ```js
+//! import init, { decode, format } from "./pkg/armv6_m_instruction_parser.js";
+//! await init();
+//! const code = new Uint8Array([0x01, 0x20, 0x70, 0x47]);
+//! for (const { address, text } of decode(code, 0x1000)) {
+//!     console.log(address.toString(16), text);
+//! }
+//! ```
+//!
+//! # Example
+//! ```
+//! # use armv6_m_instruction_parser::wasm::listing;
+//! // movs r0, #1; cbz r0, which ARMv6-M lacks; bx lr
+//! let decoded = listing(&[0x01, 0x20, 0x00, 0xb1, 0x70, 0x47], 0x1000);
+//! assert_eq!(decoded[0].text.as_deref(), Some("movs r0, #1"));
+//! assert_eq!(decoded[1].error.as_deref(), Some("invalid opcode"));
+//! assert_eq!(decoded[2].address, 0x1004);
+//! ```
+
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
+
+use serde::Serialize;
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    instruction_size,
+    instructons::{BranchTarget, DecodedAt, Operation},
+    parse_at, ThumbInstructions,
+};
+
+/// Instruction as returned to JavaScript by [`decode`], a plain object with camel case
+/// fields.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Decoded {
+    pub address: u32,
+    /// Bytes of the instruction, or of the halfwords skipped if it is invalid.
+    pub bytes: Vec<u8>,
+    /// Assembly text, see [`format`](crate::format).
+    pub text: Option<String>,
+    /// Operation with its operands, like `{ "ADDImm": { "imm": 4, "n": "R1", "d": "R0" } }`.
+    pub operation: Option<Operation>,
+    /// Why the bytes could not be decoded.
+    pub error: Option<String>,
+    /// Address branched to or loaded from, see [`Operation::branch_target`].
+    pub target: Option<u32>,
+}
+
+/// Decodes `code` placed at `address`, see [`decode`].
+pub fn listing(code: &[u8], address: u32) -> Vec<Decoded> {
+    code.thumb_instructions_at(address)
+        .map(|(here, result)| {
+            let offset = here.wrapping_sub(address) as usize;
+            let rest = &code[offset..];
+            let size = match rest {
+                [first, second, ..] => instruction_size(u16::from_le_bytes([*first, *second])),
+                _ => rest.len(),
+            };
+            let bytes = rest[..size.min(rest.len())].to_vec();
+            match result {
+                Ok(instruction) => {
+                    let target = match instruction.operation.branch_target(here) {
+                        BranchTarget::Direct(target) => Some(target),
+                        _ => None,
+                    };
+                    let decoded = DecodedAt {
+                        instruction,
+                        address: here,
+                    };
+                    Decoded {
+                        address: here,
+                        bytes,
+                        text: Some(decoded.to_string()),
+                        operation: Some(decoded.instruction.operation),
+                        error: None,
+                        target,
+                    }
+                }
+                Err(error) => Decoded {
+                    address: here,
+                    bytes,
+                    text: None,
+                    operation: None,
+                    error: Some(error.to_string()),
+                    target: None,
+                },
+            }
+        })
+        .collect()
+}
+
+/// Decodes the Thumb code in `code` placed at `address` into an array of plain objects,
+/// one per instruction or invalid encoding, see [`Decoded`].
+#[wasm_bindgen]
+pub fn decode(code: &[u8], address: u32) -> Result<JsValue, JsError> {
+    let serializer = serde_wasm_bindgen::Serializer::json_compatible();
+    Ok(listing(code, address).serialize(&serializer)?)
+}
+
+/// Returns the assembly text of the instruction at the start of `code` placed at `address`.
+#[wasm_bindgen]
+pub fn format(code: &[u8], address: u32) -> Result<String, JsError> {
+    Ok(parse_at(code, address)?.to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn listing_with_errors() {
+        // b 0x1000; bl truncated
+        let decoded = listing(&[0xfe, 0xe7, 0x00, 0xf0], 0x1000);
+        assert_eq!(decoded.len(), 2);
+        assert_eq!(decoded[0].text.as_deref(), Some("b 0x1000"));
+        assert_eq!(decoded[0].target, Some(0x1000));
+        assert_eq!(decoded[1].bytes, [0x00, 0xf0]);
+        assert!(decoded[1].operation.is_none() && decoded[1].error.is_some());
+    }
+}
@@ -0,0 +1,160 @@
+//! Cross references between the instructions of an image and the addresses they refer to.
+//!
+//! # Example
+//! ```
+//! # use armv6_m_instruction_parser::{program::Program, xref::{ReferenceKind, Xrefs}};
+//! // 0x00: push {r4, lr}; bl 0x0a; ldr r0, [pc, #4]; pop {r4, pc}
+//! // 0x0a: bx lr; .word 0x12345678
+//! let code = [
+//!     0x10, 0xb5, 0x00, 0xf0, 0x02, 0xf8, 0x01, 0x48, 0x10, 0xbd, 0x70, 0x47, 0x78, 0x56,
+//!     0x34, 0x12,
+//! ];
+//! let xrefs = Xrefs::new(&Program::new(&code, 0));
+//! let calls: Vec<_> = xrefs.refs_to(0x0a).map(|reference| reference.from).collect();
+//! assert_eq!(calls, [0x02]);
+//! assert_eq!(xrefs.refs_from(0x06).next().unwrap().kind, ReferenceKind::Literal);
+//! ```
+
+use alloc::vec::Vec;
+
+use crate::{
+    instructons::{BranchTarget, Operation},
+    program::Program,
+};
+
+/// How an instruction refers to an address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ReferenceKind {
+    /// `b` to the address.
+    Branch,
+    /// `bl` to the address.
+    Call,
+    /// `ldr` from the literal at the address.
+    Literal,
+    /// `adr` computing the address.
+    Address,
+}
+
+/// Reference from an instruction to an address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Reference {
+    /// Address of the referring instruction.
+    pub from: u32,
+    /// Referred address.
+    pub to: u32,
+    pub kind: ReferenceKind,
+}
+
+/// Index of the references of the instructions of a program, by referring and referred
+/// address.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Xrefs {
+    by_from: Vec<Reference>,
+    by_to: Vec<Reference>,
+}
+
+impl Xrefs {
+    /// Indexes the branches, calls, literal loads and `adr` of `program`.
+    ///
+    /// Referred addresses are included whether or not they are in the program, so calls to
+    /// other images and literals past its end are found too.
+    pub fn new(program: &Program) -> Self {
+        let by_from: Vec<Reference> = program
+            .iter()
+            .filter_map(|(from, result)| {
+                let operation = &result.as_ref().ok()?.operation;
+                let kind = match operation {
+                    Operation::B { .. } => ReferenceKind::Branch,
+                    Operation::BL { .. } => ReferenceKind::Call,
+                    Operation::LDRLiteral { .. } => ReferenceKind::Literal,
+                    Operation::ADR { .. } => ReferenceKind::Address,
+                    _ => return None,
+                };
+                match operation.branch_target(from) {
+                    BranchTarget::Direct(to) => Some(Reference { from, to, kind }),
+                    _ => None,
+                }
+            })
+            .collect();
+        let mut by_to = by_from.clone();
+        by_to.sort_by_key(|reference| (reference.to, reference.from));
+        Self { by_from, by_to }
+    }
+
+    /// Returns the references to `address`, in order of the referring instructions.
+    pub fn refs_to(&self, address: u32) -> impl Iterator<Item = &Reference> {
+        let start = self
+            .by_to
+            .partition_point(|reference| reference.to < address);
+        self.by_to[start..]
+            .iter()
+            .take_while(move |reference| reference.to == address)
+    }
+
+    /// Returns the references made by the instruction at `address`.
+    pub fn refs_from(&self, address: u32) -> impl Iterator<Item = &Reference> {
+        let start = self
+            .by_from
+            .partition_point(|reference| reference.from < address);
+        self.by_from[start..]
+            .iter()
+            .take_while(move |reference| reference.from == address)
+    }
+
+    /// All references in order of the referring instructions.
+    pub fn references(&self) -> &[Reference] {
+        &self.by_from
+    }
+
+    /// Returns the number of references.
+    pub fn len(&self) -> usize {
+        self.by_from.len()
+    }
+
+    /// Returns `true` if the program makes no references.
+    pub fn is_empty(&self) -> bool {
+        self.by_from.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn index() {
+        // 0x00: adr r0, 0x08; beq.n 0x08; b.n 0x08; nop
+        // 0x08: ldr r1, [pc, #0]; bx lr; .word 0
+        let code = [
+            0x01, 0xa0, 0x01, 0xd0, 0x00, 0xe0, 0x00, 0xbf, 0x00, 0x49, 0x70, 0x47, 0x00, 0x00,
+            0x00, 0x00,
+        ];
+        let xrefs = Xrefs::new(&Program::new(&code, 0));
+        assert_eq!(xrefs.len(), 4);
+        let to: Vec<_> = xrefs
+            .refs_to(0x08)
+            .map(|reference| (reference.from, reference.kind))
+            .collect();
+        assert_eq!(
+            to,
+            [
+                (0x00, ReferenceKind::Address),
+                (0x02, ReferenceKind::Branch),
+                (0x04, ReferenceKind::Branch)
+            ]
+        );
+        assert_eq!(
+            xrefs.refs_from(0x08).collect::<Vec<_>>(),
+            [&Reference {
+                from: 0x08,
+                to: 0x0c,
+                kind: ReferenceKind::Literal
+            }]
+        );
+        assert_eq!(xrefs.refs_from(0x06).count(), 0);
+        assert_eq!(xrefs.refs_to(0x0a).count(), 0);
+    }
+}
@@ -0,0 +1,164 @@
+//! The traits of `yaxpeax-arch` for the instructions of this crate, so tools built on yaxpeax
+//! decode ARMv6-M through [`ARMv6M`] like the architectures of `yaxpeax-arm` and `yaxpeax-x86`.
+//!
+//! Instructions are read in bytes like in `yaxpeax-arm`, so instruction lengths and reader
+//! offsets are both in bytes.
+//!
+//! # Example
+//! ```
+//! # use armv6_m_instruction_parser::{instructons::Operation, yaxpeax::ARMv6M};
+//! use yaxpeax_arch::{Arch, Decoder, LengthedInstruction, U8Reader};
+//!
+//! // movs r0, #1; bl 0x1100
+//! let code = [0x01, 0x20, 0x00, 0xf0, 0x7e, 0xf8];
+//! let decoder = <ARMv6M as Arch>::Decoder::default();
+//! let mut reader = U8Reader::new(&code);
+//! let first = decoder.decode(&mut reader).unwrap();
+//! let second = decoder.decode(&mut reader).unwrap();
+//! assert_eq!(first.len().to_const(), 2);
+//! assert_eq!(second.operation, Operation::BL { imm: 0xfc });
+//! ```
+
+use yaxpeax_arch::{
+    AddressDiff, Arch, DecodeError, Decoder, LengthedInstruction, ReadError, Reader,
+};
+
+use crate::{
+    instruction_size,
+    instructons::{Instruction, Operation},
+    parse_halfword_pair,
+    registers::Register,
+    Error,
+};
+
+/// ARMv6-M Thumb as a yaxpeax architecture, addressed by `u32` and read in bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ARMv6M;
+
+impl Arch for ARMv6M {
+    type Word = u8;
+    type Address = u32;
+    type Instruction = Instruction;
+    type DecodeError = Error;
+    type Decoder = InstDecoder;
+    /// Operations hold their operands as fields, registers are the ones with a type of their
+    /// own.
+    type Operand = Register;
+}
+
+/// Decoder of [`ARMv6M`], decoding like [`parse`](crate::parse).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct InstDecoder;
+
+impl Decoder<ARMv6M> for InstDecoder {
+    fn decode_into<T: Reader<u32, u8>>(
+        &self,
+        instruction: &mut Instruction,
+        words: &mut T,
+    ) -> Result<(), Error> {
+        let first = halfword(words).map_err(|_| Error::InsufficientInput)?;
+        let second = if instruction_size(first) == 4 {
+            Some(halfword(words).map_err(|_| Error::Malfromed32BitInstruction)?)
+        } else {
+            None
+        };
+        *instruction = parse_halfword_pair(first, second)?;
+        Ok(())
+    }
+}
+
+/// Reads a little endian halfword.
+fn halfword<T: Reader<u32, u8>>(words: &mut T) -> Result<u16, ReadError> {
+    let mut bytes = [0; 2];
+    words.next_n(&mut bytes)?;
+    Ok(u16::from_le_bytes(bytes))
+}
+
+/// `nop`, which yaxpeax decoders decode into.
+impl Default for Instruction {
+    fn default() -> Self {
+        Instruction {
+            width: crate::instructons::InstructionWidth::Bit16,
+            operation: Operation::NOP,
+        }
+    }
+}
+
+impl yaxpeax_arch::Instruction for Instruction {
+    /// Always true, as encodings with UNPREDICTABLE behaviour are not decoded.
+    fn well_defined(&self) -> bool {
+        true
+    }
+}
+
+impl LengthedInstruction for Instruction {
+    type Unit = AddressDiff<u32>;
+
+    fn len(&self) -> Self::Unit {
+        AddressDiff::from_const(self.size())
+    }
+
+    fn min_size() -> Self::Unit {
+        AddressDiff::from_const(2)
+    }
+}
+
+impl DecodeError for Error {
+    fn data_exhausted(&self) -> bool {
+        matches!(
+            self,
+            Error::InsufficientInput | Error::Malfromed32BitInstruction
+        )
+    }
+
+    fn bad_opcode(&self) -> bool {
+        matches!(
+            self,
+            Error::InvalidOpCode | Error::Invalid32BitInstruction | Error::Unpredictable
+        )
+    }
+
+    fn bad_operand(&self) -> bool {
+        matches!(self, Error::InvalidRegister | Error::InvalidCondition)
+    }
+
+    fn description(&self) -> &'static str {
+        self.message()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use yaxpeax_arch::{Arch, Decoder, LengthedInstruction, Reader, U8Reader};
+
+    use super::*;
+
+    #[test]
+    fn decode_through_yaxpeax() {
+        let decoder = <ARMv6M as Arch>::Decoder::default();
+
+        // bx lr; bl 0x1100; the first halfword of a bl
+        let code = [0x70, 0x47, 0x00, 0xf0, 0x7e, 0xf8, 0x00, 0xf0];
+        let mut reader = U8Reader::new(&code);
+        let decoded = decoder.decode(&mut reader).unwrap();
+        assert_eq!(decoded.operation, Operation::BX { m: Register::LR });
+        assert_eq!(decoded.len().to_const(), 2);
+        let decoded = decoder.decode(&mut reader).unwrap();
+        assert_eq!(decoded.operation, Operation::BL { imm: 0xfc });
+        assert_eq!(decoded.len().to_const(), 4);
+        assert_eq!(Reader::<u32, u8>::total_offset(&mut reader), 6);
+        let error = decoder.decode(&mut reader).unwrap_err();
+        assert_eq!(error, Error::Malfromed32BitInstruction);
+        assert!(error.data_exhausted());
+        assert!(decoder.decode(&mut reader).unwrap_err().data_exhausted());
+
+        // ldr.w r0, [r1] is an ARMv7-M instruction.
+        let mut instruction = Instruction::default();
+        let mut reader = U8Reader::new(&[0xd1, 0xf8, 0x00, 0x00]);
+        let error = decoder
+            .decode_into(&mut instruction, &mut reader)
+            .unwrap_err();
+        assert!(error.bad_opcode() && !error.data_exhausted());
+        assert_eq!(error.description(), error.to_string());
+    }
+}